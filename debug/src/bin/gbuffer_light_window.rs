@@ -1,7 +1,10 @@
 //! 后端无关示例：仅依赖 render-api + LumeliteWindowBackend，宿主不直接调用 wgpu。
 //! Run: cargo run -p debug --bin gbuffer_light_window
+//! Drag with the left mouse button to orbit, scroll to zoom, `[` / `]` to change the FOV, `O` to toggle
+//! orthographic. `--fov <degrees>`, `--near <n>`, `--far <f>` and `--ortho` set the initial projection.
 
 use std::collections::HashMap;
+use debug::camera::OrbitCamera;
 use render_api::{ExtractedMeshes, ExtractedView, RenderBackendWindow};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use winit::application::ApplicationHandler;
@@ -9,92 +12,12 @@ use winit::event::WindowEvent;
 use winit::event_loop::ActiveEventLoop;
 use winit::window::WindowId;
 
-/// Build orthographic projection (column-major, WebGPU NDC z in [0,1]).
-/// View space: -Z forward. Maps -near -> NDC 0, -far -> NDC 1.
-fn ortho_projection(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> [f32; 16] {
-    let sx = 2.0 / (right - left);
-    let sy = 2.0 / (top - bottom);
-    let sz = -1.0 / (far - near);  // -near->0, -far->1
-    let tx = -(right + left) / (right - left);
-    let ty = -(top + bottom) / (top - bottom);
-    let tz = -near / (far - near);
-    [
-        sx, 0.0, 0.0, 0.0,
-        0.0, sy, 0.0, 0.0,
-        0.0, 0.0, sz, 0.0,
-        tx, ty, tz, 1.0,
-    ]
-}
-
-/// Build perspective projection matrix (column-major, WebGPU NDC z in [0,1]).
-/// View space: -Z forward, maps -near->NDC 0, -far->NDC 1.
-#[allow(dead_code)]
-fn perspective_projection(fov_y_rad: f32, aspect: f32, near: f32, far: f32) -> [f32; 16] {
-    let t = (fov_y_rad / 2.0).tan();
-    let sy = 1.0 / t;
-    let sx = sy / aspect;
-    let a = far / (near - far);
-    let b = (near * far) / (near - far);
-    [
-        sx, 0.0, 0.0, 0.0,
-        0.0, sy, 0.0, 0.0,
-        0.0, 0.0, a, -1.0,
-        0.0, 0.0, b, 0.0,
-    ]
-}
-
-/// Build look-at view matrix (column-major). Camera at eye looking at center.
-fn look_at(eye: [f32; 3], center: [f32; 3], up: [f32; 3]) -> [f32; 16] {
-    let f = [
-        center[0] - eye[0],
-        center[1] - eye[1],
-        center[2] - eye[2],
-    ];
-    let len_f = (f[0] * f[0] + f[1] * f[1] + f[2] * f[2]).sqrt();
-    let f = [f[0] / len_f, f[1] / len_f, f[2] / len_f];
-    let s = [
-        f[1] * up[2] - f[2] * up[1],
-        f[2] * up[0] - f[0] * up[2],
-        f[0] * up[1] - f[1] * up[0],
-    ];
-    let len_s = (s[0] * s[0] + s[1] * s[1] + s[2] * s[2]).sqrt();
-    let s = [s[0] / len_s, s[1] / len_s, s[2] / len_s];
-    let u = [
-        s[1] * f[2] - s[2] * f[1],
-        s[2] * f[0] - s[0] * f[2],
-        s[0] * f[1] - s[1] * f[0],
-    ];
-    let tx = -(s[0] * eye[0] + s[1] * eye[1] + s[2] * eye[2]);
-    let ty = -(u[0] * eye[0] + u[1] * eye[1] + u[2] * eye[2]);
-    let tz = f[0] * eye[0] + f[1] * eye[1] + f[2] * eye[2];
-    // Column-major: col0=(s,0), col1=(u,0), col2=(-f,0), col3=(tx,ty,tz,1)
-    [
-        s[0], u[0], -f[0], 0.0,
-        s[1], u[1], -f[1], 0.0,
-        s[2], u[2], -f[2], 0.0,
-        tx, ty, tz, 1.0,
-    ]
-}
-
-/// Multiply two 4x4 column-major matrices: C = A * B.
-fn mat4_mul(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
-    let mut c = [0.0f32; 16];
-    for col in 0..4 {
-        for row in 0..4 {
-            c[col * 4 + row] = a[row] * b[col * 4 + 0]
-                + a[4 + row] * b[col * 4 + 1]
-                + a[8 + row] * b[col * 4 + 2]
-                + a[12 + row] * b[col * 4 + 3];
-        }
-    }
-    c
-}
-
 struct App {
     window: Option<winit::window::Window>,
     /// 后端：通过 render-api 的 RenderBackendWindow 渲染，不持有任何 wgpu 类型
     backend: Option<Box<dyn RenderBackendWindow>>,
     size: (u32, u32),
+    camera: OrbitCamera,
     identity: [f32; 16],
     vertex_data: Vec<u8>,
     index_data: Vec<u8>,
@@ -116,20 +39,12 @@ impl App {
             window: None,
             backend: None,
             size: (800, 600),
+            camera: OrbitCamera::new([0.0, 0.0, 2.0], [0.0, 0.0, 0.0]),
             identity,
             vertex_data,
             index_data,
         }
     }
-
-    fn build_view_projection(&self) -> [f32; 16] {
-        let (w, h) = self.size;
-        let aspect = if h > 0 { w as f32 / h as f32 } else { 1.0 };
-        // Use ortho to isolate projection issues (triangle at z=0, view z=-2)
-        let proj = ortho_projection(-aspect, aspect, -1.0, 1.0, 0.1, 100.0);
-        let view = look_at([0.0, 0.0, 2.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
-        mat4_mul(&proj, &view)
-    }
 }
 
 impl ApplicationHandler for App {
@@ -155,6 +70,11 @@ impl ApplicationHandler for App {
         _id: WindowId,
         event: WindowEvent,
     ) {
+        if self.camera.handle_event(&event) {
+            if let Some(ref w) = self.window {
+                w.request_redraw();
+            }
+        }
         match event {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
@@ -198,12 +118,15 @@ impl ApplicationHandler for App {
                 });
                 let extracted = ExtractedMeshes { meshes };
                 let view = ExtractedView {
-                    view_proj: self.build_view_projection(),
+                    view_proj: self.camera.view_proj(self.size.0, self.size.1),
+                    camera_pos: self.camera.eye(),
                     viewport_size: self.size,
                     directional_light: Some(([0.3, -0.8, 0.5], [1.0, 1.0, 1.0])),
                     point_lights: Vec::new(),
                     spot_lights: Vec::new(),
                     sky_light: None,
+                    tone_mapping: None,
+                    exposure: None,
                 };
                 let backend = match &mut self.backend {
                     Some(b) => b,
@@ -221,6 +144,7 @@ impl ApplicationHandler for App {
 fn main() -> Result<(), String> {
     let event_loop = winit::event_loop::EventLoop::new().map_err(|e| e.to_string())?;
     let mut app = App::new();
+    app.camera.apply_args(&std::env::args().collect::<Vec<_>>())?;
     event_loop.run_app(&mut app).map_err(|e| e.to_string())?;
     Ok(())
 }
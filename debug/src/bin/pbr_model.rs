@@ -1,12 +1,19 @@
 //! PBR model viewer: load OBJ + PBR textures, render with Lumelite (prepare + render_frame_to_window).
+//! Drag with the left mouse button to orbit, scroll to zoom.
 //! Run from repo root: cargo run -p debug --bin pbr_model
 //! Resources: 模型/green-vintage-metal-chair-with-books-and-flowers.obj and .../textures/
+//! Pass `--no-dedup` to upload non-indexed vertex data (one vertex per index), `--grid` to draw a
+//! ground grid under the model, `--hdr` to present to an HDR swapchain when the display offers one,
+//! `--screenshot <path>` to render one 800x600 frame headless (no window) and save it as a PNG.
+//! `--fov <degrees>`, `--near <n>`, `--far <f>` and `--ortho` set the projection (the camera frames the
+//! model by default); `[` / `]` change the FOV and `O` toggles orthographic at runtime.
 
 use std::collections::HashMap;
 use std::path::Path;
 
+use debug::camera::OrbitCamera;
 use render_api::{
-    ExtractedMeshes, ExtractedView, ExtractedPbrMaterial, PbrTextureData, RenderBackendWindow,
+    ExtractedMeshes, ExtractedView, ExtractedPbrMaterial, PbrTextureData, RenderBackend, RenderBackendWindow,
 };
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use winit::application::ApplicationHandler;
@@ -29,7 +36,7 @@ fn find_texture(dir: &Path, pattern: &str) -> Option<std::path::PathBuf> {
     let entries = std::fs::read_dir(dir).ok()?;
     for e in entries.flatten() {
         let p = e.path();
-        if p.file_name().and_then(|n| n.to_str()).map_or(false, |n| n.contains(pattern)) {
+        if p.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.contains(pattern)) {
             return Some(p);
         }
     }
@@ -56,30 +63,84 @@ fn load_pbr_material(texture_dir: &Path) -> Result<ExtractedPbrMaterial, String>
         normal,
         metallic_roughness,
         ao,
+        double_sided: false,
+        emissive_factor: [0.0; 3],
     })
 }
 
-fn load_obj_mesh(obj_path: &Path) -> Result<(Vec<u8>, Vec<u8>), String> {
+/// One OBJ model (`o`/`g` group) as vertex/index data, in the file's coordinates, so the parts of a
+/// multi-object OBJ keep their relative positions with an identity transform.
+struct ObjPart {
+    vertex_data: Vec<u8>,
+    index_data: Vec<u8>,
+    vertex_format: render_api::VertexFormat,
+    /// Min and max corner of the part's positions.
+    bounds: ([f32; 3], [f32; 3]),
+}
+
+fn load_obj_parts(obj_path: &Path, indexed: bool) -> Result<Vec<ObjPart>, String> {
     let (models, _) = tobj::load_obj(obj_path, &tobj::GPU_LOAD_OPTIONS)
         .map_err(|e| format!("load_obj: {:?}", e))?;
-    let mesh = models.into_iter().next().ok_or("No mesh in OBJ")?.mesh;
-    let positions: Vec<f32> = mesh.positions.iter().map(|&x| x as f32).collect();
+    let parts = obj_models_to_parts(&models, indexed);
+    if parts.is_empty() {
+        return Err("No mesh in OBJ".to_string());
+    }
+    Ok(parts)
+}
+
+/// Every model with geometry, in file order.
+fn obj_models_to_parts(models: &[tobj::Model], indexed: bool) -> Vec<ObjPart> {
+    models
+        .iter()
+        .filter(|m| !m.mesh.indices.is_empty() && !m.mesh.positions.is_empty())
+        .map(|m| {
+            let (vertex_data, index_data) = obj_mesh_to_vertex_data(&m.mesh, indexed);
+            let vertex_format = if m.mesh.vertex_color.is_empty() {
+                render_api::VertexFormat::PositionNormalTangentUv
+            } else {
+                render_api::VertexFormat::PositionNormalColorUv
+            };
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for p in m.mesh.positions.chunks_exact(3) {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(p[axis]);
+                    max[axis] = max[axis].max(p[axis]);
+                }
+            }
+            ObjPart { vertex_data, index_data, vertex_format, bounds: (min, max) }
+        })
+        .collect()
+}
+
+/// Build 48-byte (position+normal+tangent+uv) vertices and u32 indices from an OBJ mesh, with the
+/// tangents OBJ lacks from [`render_api::generate_tangents`], or (position+normal+color+uv) ones when
+/// the OBJ has `v x y z r g b` vertex colors (read as sRGB); those have no room for tangents.
+/// When `indexed`, identical vertices are merged and shared via the index buffer; otherwise
+/// every index gets its own vertex (non-indexed fallback).
+fn obj_mesh_to_vertex_data(mesh: &tobj::Mesh, indexed: bool) -> (Vec<u8>, Vec<u8>) {
+    let positions = &mesh.positions;
     let normals: Vec<f32> = if mesh.normals.is_empty() {
-        (0..positions.len()).map(|_| 0.0f32).collect()
+        vec![0.0f32; positions.len()]
     } else {
-        mesh.normals.iter().map(|&x| x as f32).collect()
+        mesh.normals.clone()
     };
     let texcoords: Vec<f32> = if mesh.texcoords.is_empty() {
-        (0..(positions.len() / 3 * 2)).map(|_| 0.0f32).collect()
+        vec![0.0f32; positions.len() / 3 * 2]
     } else {
-        mesh.texcoords.iter().map(|&x| x as f32).collect()
+        mesh.texcoords.clone()
     };
-    let indices = mesh.indices;
+    let indices = &mesh.indices;
     let n_pos = positions.len() / 3;
     let n_norm = normals.len() / 3;
     let n_tex = texcoords.len() / 2;
+    let colored = !mesh.vertex_color.is_empty();
+    let n_color = mesh.vertex_color.len() / 3;
 
-    let mut vertex_data = Vec::with_capacity(indices.len() * 32);
+    let mut vertices: Vec<[f32; 12]> = Vec::with_capacity(indices.len());
+    let mut new_indices: Vec<u32> = Vec::with_capacity(indices.len());
+    // Key on the bit patterns so identical attribute tuples hash equal (f32 is not Hash).
+    let mut dedup: HashMap<[u32; 12], u32> = HashMap::new();
     for (i, &idx) in indices.iter().enumerate() {
         let pi = (idx as usize).min(n_pos.saturating_sub(1)) * 3;
         let ni = if mesh.normal_indices.is_empty() {
@@ -94,122 +155,124 @@ fn load_obj_mesh(obj_path: &Path) -> Result<(Vec<u8>, Vec<u8>), String> {
             let ti_idx = mesh.texcoord_indices.get(i).copied().unwrap_or(0) as usize;
             ti_idx.min(n_tex.saturating_sub(1)) * 2
         };
-        vertex_data.extend_from_slice(bytemuck::cast_slice(&[
+        // With tobj's single index, colors share the position index.
+        let color = if colored {
+            let ci = (idx as usize).min(n_color.saturating_sub(1)) * 3;
+            render_api::srgb_to_linear_rgb([mesh.vertex_color[ci], mesh.vertex_color[ci + 1], mesh.vertex_color[ci + 2]])
+        } else {
+            [1.0; 3]
+        };
+        let vertex = [
             positions[pi],
             positions[pi + 1],
             positions[pi + 2],
             normals[ni],
             normals[ni + 1],
             normals[ni + 2],
+            color[0],
+            color[1],
+            color[2],
+            1.0,
             texcoords[ti],
             texcoords[ti + 1],
-        ]));
-    }
-    let new_indices: Vec<u32> = (0..indices.len() as u32).collect();
-    let index_data = bytemuck::cast_slice(new_indices.as_slice()).to_vec();
-    Ok((vertex_data, index_data))
-}
-
-fn ortho_projection(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> [f32; 16] {
-    let sx = 2.0 / (right - left);
-    let sy = 2.0 / (top - bottom);
-    let sz = -1.0 / (far - near);
-    let tx = -(right + left) / (right - left);
-    let ty = -(top + bottom) / (top - bottom);
-    let tz = -near / (far - near);
-    [
-        sx, 0.0, 0.0, 0.0, 0.0, sy, 0.0, 0.0, 0.0, 0.0, sz, 0.0, tx, ty, tz, 1.0,
-    ]
-}
-
-fn look_at(eye: [f32; 3], center: [f32; 3], up: [f32; 3]) -> [f32; 16] {
-    let f = [
-        center[0] - eye[0],
-        center[1] - eye[1],
-        center[2] - eye[2],
-    ];
-    let len_f = (f[0] * f[0] + f[1] * f[1] + f[2] * f[2]).sqrt();
-    let f = [f[0] / len_f, f[1] / len_f, f[2] / len_f];
-    let s = [
-        f[1] * up[2] - f[2] * up[1],
-        f[2] * up[0] - f[0] * up[2],
-        f[0] * up[1] - f[1] * up[0],
-    ];
-    let len_s = (s[0] * s[0] + s[1] * s[1] + s[2] * s[2]).sqrt();
-    let s = [s[0] / len_s, s[1] / len_s, s[2] / len_s];
-    let u = [
-        s[1] * f[2] - s[2] * f[1],
-        s[2] * f[0] - s[0] * f[2],
-        s[0] * f[1] - s[1] * f[0],
-    ];
-    let tx = -(s[0] * eye[0] + s[1] * eye[1] + s[2] * eye[2]);
-    let ty = -(u[0] * eye[0] + u[1] * eye[1] + u[2] * eye[2]);
-    let tz = f[0] * eye[0] + f[1] * eye[1] + f[2] * eye[2];
-    [
-        s[0], u[0], -f[0], 0.0, s[1], u[1], -f[1], 0.0, s[2], u[2], -f[2], 0.0, tx, ty, tz, 1.0,
-    ]
-}
-
-fn mat4_mul(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
-    let mut c = [0.0f32; 16];
-    for col in 0..4 {
-        for row in 0..4 {
-            c[col * 4 + row] = a[row] * b[col * 4 + 0]
-                + a[4 + row] * b[col * 4 + 1]
-                + a[8 + row] * b[col * 4 + 2]
-                + a[12 + row] * b[col * 4 + 3];
+        ];
+        if !indexed {
+            new_indices.push(vertices.len() as u32);
+            vertices.push(vertex);
+            continue;
         }
+        let key = vertex.map(f32::to_bits);
+        let index = *dedup.entry(key).or_insert_with(|| {
+            vertices.push(vertex);
+            (vertices.len() - 1) as u32
+        });
+        new_indices.push(index);
     }
-    c
+    let vertex_data = if colored {
+        bytemuck::cast_slice(vertices.as_slice()).to_vec()
+    } else {
+        let positions: Vec<[f32; 3]> = vertices.iter().map(|v| [v[0], v[1], v[2]]).collect();
+        let normals: Vec<[f32; 3]> = vertices.iter().map(|v| [v[3], v[4], v[5]]).collect();
+        let uvs: Vec<[f32; 2]> = vertices.iter().map(|v| [v[10], v[11]]).collect();
+        let tangents = render_api::generate_tangents(&positions, &normals, &uvs, &new_indices);
+        // The tangent takes the color slots.
+        vertices
+            .iter()
+            .zip(&tangents)
+            .flat_map(|(v, t)| v[..6].iter().chain(t).chain(&v[10..]))
+            .flat_map(|f| f.to_ne_bytes())
+            .collect()
+    };
+    let index_data = bytemuck::cast_slice(new_indices.as_slice()).to_vec();
+    (vertex_data, index_data)
 }
 
 struct App {
     window: Option<winit::window::Window>,
     backend: Option<Box<dyn RenderBackendWindow>>,
+    backend_options: lumelite_bridge::LumeliteBackendOptions,
+    camera: OrbitCamera,
     size: (u32, u32),
     extracted_meshes: ExtractedMeshes,
 }
 
 impl App {
-    fn new(obj_path: &Path, texture_dir: &Path) -> Result<Self, String> {
-        let (vertex_data, index_data) = load_obj_mesh(obj_path)?;
+    fn new(
+        obj_path: &Path,
+        texture_dir: &Path,
+        indexed: bool,
+        backend_options: lumelite_bridge::LumeliteBackendOptions,
+    ) -> Result<Self, String> {
+        let parts = load_obj_parts(obj_path, indexed)?;
+        // One texture set per model directory, shared by all parts.
         let material = load_pbr_material(texture_dir).ok();
         let identity: [f32; 16] = [
             1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
         ];
         let mut meshes = HashMap::new();
-        meshes.insert(
-            1u64,
-            render_api::ExtractedMesh {
-                entity_id: 1,
-                vertex_data,
-                index_data,
-                transform: identity,
-                visible: true,
-                vertex_format: render_api::VertexFormat::PositionNormalUv,
-                material,
-            },
-        );
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for (i, part) in parts.into_iter().enumerate() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(part.bounds.0[axis]);
+                max[axis] = max[axis].max(part.bounds.1[axis]);
+            }
+            let entity_id = i as u64 + 1;
+            meshes.insert(
+                entity_id,
+                render_api::ExtractedMesh {
+                    entity_id,
+                    vertex_data: part.vertex_data,
+                    index_data: part.index_data,
+                    transform: identity,
+                    visible: true,
+                    vertex_format: part.vertex_format,
+                    material: material.clone(),
+                    lods: Vec::new(),
+                    shader_variant: render_api::ShaderVariant::Pbr,
+                    bounds_override: None,
+                    geometry_revision: 0,
+                },
+            );
+        }
         let extracted_meshes = ExtractedMeshes { meshes };
         Ok(Self {
             window: None,
             backend: None,
+            backend_options,
+            camera: {
+                let mut camera = OrbitCamera::new([2.0, 1.5, 2.0], [0.0, 0.0, 0.0]);
+                camera.fit_bounds(min, max);
+                camera
+            },
             size: (800, 600),
             extracted_meshes,
         })
     }
 
-    fn build_view_projection(&self) -> [f32; 16] {
-        let (w, h) = self.size;
-        let aspect = if h > 0 { w as f32 / h as f32 } else { 1.0 };
-        let proj = ortho_projection(-aspect, aspect, -1.0, 1.0, 0.1, 100.0);
-        let view = look_at([2.0, 1.5, 2.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
-        mat4_mul(&proj, &view)
-    }
-
     /// 构建带合理光照的 ExtractedView：主平行光模拟太阳 + 点光模拟背景/环境光。
     fn build_view(&self) -> ExtractedView {
-        let view_proj = self.build_view_projection();
+        let view_proj = self.camera.view_proj(self.size.0, self.size.1);
         let viewport_size = self.size;
 
         // 主平行光：模拟太阳，从右上前方照向场景，方向为光照射方向（指向场景）
@@ -224,32 +287,67 @@ impl App {
                 color: [0.28, 0.32, 0.38],
                 radius: 18.0,
                 falloff_exponent: 2.0,
+                ..Default::default()
             },
             render_api::PointLight {
                 position: [-2.5, 1.0, 2.0],
                 color: [0.22, 0.25, 0.3],
                 radius: 14.0,
                 falloff_exponent: 2.0,
+                ..Default::default()
             },
             render_api::PointLight {
                 position: [2.0, 0.5, -1.5],
                 color: [0.18, 0.2, 0.24],
                 radius: 12.0,
                 falloff_exponent: 2.0,
+                ..Default::default()
             },
         ];
 
         ExtractedView {
             view_proj,
+            camera_pos: self.camera.eye(),
             viewport_size,
             directional_light,
             point_lights,
             spot_lights: Vec::new(),
             sky_light: None,
+            tone_mapping: None,
+            exposure: None,
         }
     }
 }
 
+impl App {
+    /// Render one frame without a window on a surface-less adapter and write it to `path` as a PNG.
+    fn save_screenshot(&self, path: &Path) -> Result<(), String> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .ok_or("No adapter")?;
+        let options = &self.backend_options;
+        options.validate(adapter.features(), &adapter.limits())?;
+        let descriptor = wgpu::DeviceDescriptor {
+            required_features: options.required_features,
+            required_limits: options.required_limits.clone(),
+            ..Default::default()
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&descriptor, None)).map_err(|e| e.to_string())?;
+        // PNG stores sRGB-encoded bytes, which an Srgb target writes directly.
+        let config = lumelite_renderer::LumeliteConfig {
+            swapchain_format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            hdr_output: false,
+            ..options.config.clone()
+        };
+        let mut plugin = lumelite_bridge::LumelitePlugin::new_with_config(device, queue, config)?;
+        plugin.prepare(&self.extracted_meshes);
+        let view = self.build_view();
+        let pixels = plugin.render_frame_to_rgba8(&view)?;
+        let (width, height) = view.viewport_size;
+        image::save_buffer(path, &pixels, width, height, image::ExtendedColorType::Rgba8).map_err(|e| e.to_string())
+    }
+}
+
 fn normalize(v: [f32; 3]) -> [f32; 3] {
     let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
     if len > 1e-6 {
@@ -282,6 +380,11 @@ impl ApplicationHandler for App {
         _id: WindowId,
         event: WindowEvent,
     ) {
+        if self.camera.handle_event(&event) {
+            if let Some(ref w) = self.window {
+                w.request_redraw();
+            }
+        }
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::Resized(physical) => {
@@ -300,10 +403,10 @@ impl ApplicationHandler for App {
                     (phys.width.max(1), phys.height.max(1))
                 };
                 if self.backend.is_none() {
-                    match lumelite_bridge::LumeliteWindowBackend::from_window(window) {
+                    match lumelite_bridge::LumeliteWindowBackend::from_window_with_options(window, &self.backend_options) {
                         Ok(backend) => self.backend = Some(backend),
                         Err(e) => {
-                            eprintln!("LumeliteWindowBackend::from_window failed: {}", e);
+                            eprintln!("LumeliteWindowBackend::from_window_with_options failed: {}", e);
                             return;
                         }
                     }
@@ -332,8 +435,119 @@ fn main() -> Result<(), String> {
     if !obj_path.exists() {
         return Err(format!("OBJ not found: {}", obj_path.display()));
     }
+    // --no-dedup keeps the old one-vertex-per-index layout (for comparing against the indexed path).
+    let indexed = !std::env::args().any(|a| a == "--no-dedup");
+    let mut backend_options = lumelite_bridge::LumeliteBackendOptions::default();
+    backend_options.config.grid = std::env::args().any(|a| a == "--grid").then(lumelite_renderer::GridConfig::default);
+    backend_options.config.hdr_output = std::env::args().any(|a| a == "--hdr");
+    let mut app = App::new(&obj_path, &texture_dir, indexed, backend_options)?;
+    let args: Vec<String> = std::env::args().collect();
+    app.camera.apply_args(&args)?;
+    if let Some(i) = args.iter().position(|a| a == "--screenshot") {
+        let path = args.get(i + 1).ok_or("--screenshot needs an output path")?;
+        app.save_screenshot(Path::new(path))?;
+        println!("Saved {}", path);
+        return Ok(());
+    }
     let event_loop = winit::event_loop::EventLoop::new().map_err(|e| e.to_string())?;
-    let mut app = App::new(&obj_path, &texture_dir)?;
     event_loop.run_app(&mut app).map_err(|e| e.to_string())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_OBJ: &str = "\
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 -1
+vn 0 0 1
+vn -1 0 0
+vn 1 0 0
+vn 0 -1 0
+vn 0 1 0
+f 1/1/1 4/4/1 3/3/1 2/2/1
+f 5/1/2 6/2/2 7/3/2 8/4/2
+f 1/1/3 5/2/3 8/3/3 4/4/3
+f 2/1/4 3/4/4 7/3/4 6/2/4
+f 1/1/5 2/2/5 6/3/5 5/4/5
+f 4/1/6 8/4/6 7/3/6 3/2/6
+";
+
+    fn cube_mesh() -> tobj::Mesh {
+        let mut reader = std::io::BufReader::new(CUBE_OBJ.as_bytes());
+        let (models, _) = tobj::load_obj_buf(&mut reader, &tobj::GPU_LOAD_OPTIONS, |_| {
+            Err(tobj::LoadError::OpenFileFailed)
+        })
+        .expect("parse cube obj");
+        models.into_iter().next().expect("cube model").mesh
+    }
+
+    #[test]
+    fn cube_is_deduplicated() {
+        let (vertex_data, index_data) = obj_mesh_to_vertex_data(&cube_mesh(), true);
+        assert_eq!(vertex_data.len() / 48, 24);
+        assert_eq!(index_data.len() / 4, 36);
+    }
+
+    #[test]
+    fn obj_meshes_get_unit_tangents_along_u() {
+        let (vertex_data, _) = obj_mesh_to_vertex_data(&cube_mesh(), true);
+        for vertex in bytemuck::cast_slice::<u8, [f32; 12]>(&vertex_data) {
+            let (normal, tangent) = (&vertex[3..6], &vertex[6..10]);
+            let dot: f32 = normal.iter().zip(tangent).map(|(n, t)| n * t).sum();
+            let len: f32 = tangent[..3].iter().map(|t| t * t).sum::<f32>().sqrt();
+            assert!(dot.abs() < 1e-5 && (len - 1.0).abs() < 1e-5, "{:?}", vertex);
+            assert_eq!(tangent[3].abs(), 1.0);
+        }
+        let parts = obj_models_to_parts(&[tobj::Model { mesh: cube_mesh(), name: String::new() }], true);
+        assert_eq!(parts[0].vertex_format, render_api::VertexFormat::PositionNormalTangentUv);
+    }
+
+    #[test]
+    fn every_obj_object_becomes_a_part_in_place() {
+        let obj = "o left\nv -3 0 0\nv -2 0 0\nv -3 1 0\nf 1 2 3\no right\nv 2 0 0\nv 3 0 0\nv 3 1 1\nf 4 5 6\n";
+        let mut reader = std::io::BufReader::new(obj.as_bytes());
+        let (models, _) =
+            tobj::load_obj_buf(&mut reader, &tobj::GPU_LOAD_OPTIONS, |_| Err(tobj::LoadError::OpenFileFailed)).unwrap();
+        let parts = obj_models_to_parts(&models, true);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].bounds, ([-3.0, 0.0, 0.0], [-2.0, 1.0, 0.0]));
+        assert_eq!(parts[1].bounds, ([2.0, 0.0, 0.0], [3.0, 1.0, 1.0]));
+        let first_x: Vec<f32> = parts.iter().map(|p| bytemuck::cast_slice::<u8, f32>(&p.vertex_data)[0]).collect();
+        assert_eq!(first_x, vec![-3.0, 2.0]);
+    }
+
+    #[test]
+    fn obj_vertex_colors_become_a_color_format() {
+        let obj = "v 0 0 0 1 0.5 0\nv 1 0 0 1 0.5 0\nv 0 1 0 0 0 1\nf 1 2 3\n";
+        let mut reader = std::io::BufReader::new(obj.as_bytes());
+        let (models, _) =
+            tobj::load_obj_buf(&mut reader, &tobj::GPU_LOAD_OPTIONS, |_| Err(tobj::LoadError::OpenFileFailed)).unwrap();
+        let parts = obj_models_to_parts(&models, true);
+        assert_eq!(parts[0].vertex_format, render_api::VertexFormat::PositionNormalColorUv);
+        let floats: &[f32] = bytemuck::cast_slice(&parts[0].vertex_data);
+        assert_eq!(floats.len(), 3 * 12);
+        assert_eq!(floats[6], 1.0);
+        assert!((floats[7] - render_api::srgb_to_linear(0.5)).abs() < 1e-6);
+        assert_eq!(&floats[24 + 6..24 + 10], &[0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn cube_unindexed_fallback() {
+        let (vertex_data, index_data) = obj_mesh_to_vertex_data(&cube_mesh(), false);
+        assert_eq!(vertex_data.len() / 48, 36);
+        assert_eq!(index_data.len() / 4, 36);
+    }
+}
@@ -32,11 +32,14 @@ fn main() -> Result<(), String> {
     let extracted = ExtractedMeshes { meshes };
     let view = ExtractedView {
         view_proj: identity,
+        camera_pos: [0.0, 0.0, 0.0],
         viewport_size: (800, 600),
         directional_light: None,
         point_lights: Vec::new(),
         spot_lights: Vec::new(),
         sky_light: None,
+        tone_mapping: None,
+        exposure: None,
     };
 
     backend.prepare(&extracted);
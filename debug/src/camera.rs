@@ -0,0 +1,316 @@
+//! Camera math shared by the window examples (column-major matrices, WebGPU NDC z in [0, 1], view
+//! space -Z forward) and an orbit camera driven by winit mouse input.
+
+use render_api::mat4_mul;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::Key;
+
+/// Build orthographic projection. Maps -near -> NDC 0, -far -> NDC 1.
+pub fn ortho_projection(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> [f32; 16] {
+    let sx = 2.0 / (right - left);
+    let sy = 2.0 / (top - bottom);
+    let sz = -1.0 / (far - near);
+    let tx = -(right + left) / (right - left);
+    let ty = -(top + bottom) / (top - bottom);
+    let tz = -near / (far - near);
+    [
+        sx, 0.0, 0.0, 0.0,
+        0.0, sy, 0.0, 0.0,
+        0.0, 0.0, sz, 0.0,
+        tx, ty, tz, 1.0,
+    ]
+}
+
+/// Build perspective projection. Maps -near -> NDC 0, -far -> NDC 1.
+pub fn perspective_projection(fov_y_rad: f32, aspect: f32, near: f32, far: f32) -> [f32; 16] {
+    let t = (fov_y_rad / 2.0).tan();
+    let sy = 1.0 / t;
+    let sx = sy / aspect;
+    let a = far / (near - far);
+    let b = (near * far) / (near - far);
+    [
+        sx, 0.0, 0.0, 0.0,
+        0.0, sy, 0.0, 0.0,
+        0.0, 0.0, a, -1.0,
+        0.0, 0.0, b, 0.0,
+    ]
+}
+
+/// Build look-at view matrix. Camera at eye looking at center.
+pub fn look_at(eye: [f32; 3], center: [f32; 3], up: [f32; 3]) -> [f32; 16] {
+    let f = [
+        center[0] - eye[0],
+        center[1] - eye[1],
+        center[2] - eye[2],
+    ];
+    let len_f = (f[0] * f[0] + f[1] * f[1] + f[2] * f[2]).sqrt();
+    let f = [f[0] / len_f, f[1] / len_f, f[2] / len_f];
+    let s = [
+        f[1] * up[2] - f[2] * up[1],
+        f[2] * up[0] - f[0] * up[2],
+        f[0] * up[1] - f[1] * up[0],
+    ];
+    let len_s = (s[0] * s[0] + s[1] * s[1] + s[2] * s[2]).sqrt();
+    let s = [s[0] / len_s, s[1] / len_s, s[2] / len_s];
+    let u = [
+        s[1] * f[2] - s[2] * f[1],
+        s[2] * f[0] - s[0] * f[2],
+        s[0] * f[1] - s[1] * f[0],
+    ];
+    let tx = -(s[0] * eye[0] + s[1] * eye[1] + s[2] * eye[2]);
+    let ty = -(u[0] * eye[0] + u[1] * eye[1] + u[2] * eye[2]);
+    let tz = f[0] * eye[0] + f[1] * eye[1] + f[2] * eye[2];
+    // Column-major: col0=(s,0), col1=(u,0), col2=(-f,0), col3=(tx,ty,tz,1)
+    [
+        s[0], u[0], -f[0], 0.0,
+        s[1], u[1], -f[1], 0.0,
+        s[2], u[2], -f[2], 0.0,
+        tx, ty, tz, 1.0,
+    ]
+}
+
+/// Keeps the camera off the poles, where the look-at up vector would be parallel to the view.
+const MAX_PITCH: f32 = 1.5;
+
+/// FOV range reachable with the `[` / `]` keys, in degrees.
+const FOV_RANGE_DEG: (f32, f32) = (5.0, 150.0);
+
+/// Camera orbiting `target`: left-drag rotates, the wheel zooms, `[` / `]` narrow / widen the field of
+/// view and `O` toggles orthographic projection. Feed it every window event with
+/// [`Self::handle_event`] and request a redraw when that returns true.
+#[derive(Clone, Debug)]
+pub struct OrbitCamera {
+    pub target: [f32; 3],
+    pub distance: f32,
+    /// Rotation around +Y in radians; 0 looks from +Z toward -Z.
+    pub yaw: f32,
+    /// Elevation in radians, positive above the target; clamped to (-1.5, 1.5).
+    pub pitch: f32,
+    pub fov_y: f32,
+    /// Orthographic instead of perspective; the view height is what `fov_y` covers at `distance`, so
+    /// toggling keeps the target framed.
+    pub orthographic: bool,
+    pub near: f32,
+    pub far: f32,
+    /// Radians per pixel of drag.
+    pub rotate_speed: f32,
+    /// Fraction of the distance per wheel line.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    dragging: bool,
+    cursor: Option<(f64, f64)>,
+}
+
+impl OrbitCamera {
+    /// Camera at `eye` looking at `target`.
+    pub fn new(eye: [f32; 3], target: [f32; 3]) -> Self {
+        let d = [eye[0] - target[0], eye[1] - target[1], eye[2] - target[2]];
+        let distance = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt().max(1e-3);
+        Self {
+            target,
+            distance,
+            yaw: d[0].atan2(d[2]),
+            pitch: (d[1] / distance).clamp(-1.0, 1.0).asin().clamp(-MAX_PITCH, MAX_PITCH),
+            fov_y: 45f32.to_radians(),
+            orthographic: false,
+            near: 0.05,
+            far: 100.0,
+            rotate_speed: 0.01,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            dragging: false,
+            cursor: None,
+        }
+    }
+
+    pub fn eye(&self) -> [f32; 3] {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        [
+            self.target[0] + self.distance * cos_pitch * sin_yaw,
+            self.target[1] + self.distance * sin_pitch,
+            self.target[2] + self.distance * cos_pitch * cos_yaw,
+        ]
+    }
+
+    /// Aim at the center of the box `min`..`max` from the current direction, far enough back that its
+    /// bounding sphere fills the vertical field of view.
+    pub fn fit_bounds(&mut self, min: [f32; 3], max: [f32; 3]) {
+        let half = [(max[0] - min[0]) / 2.0, (max[1] - min[1]) / 2.0, (max[2] - min[2]) / 2.0];
+        let radius = (half[0] * half[0] + half[1] * half[1] + half[2] * half[2]).sqrt().max(1e-3);
+        self.target = [min[0] + half[0], min[1] + half[1], min[2] + half[2]];
+        self.distance = (radius / (self.fov_y / 2.0).sin()).max(self.min_distance);
+        self.near = self.near.min(radius * 0.01);
+        self.far = self.far.max(self.distance + radius * 2.0);
+    }
+
+    /// Orbit by a drag of (`dx`, `dy`) pixels; dragging right turns the view right, down raises the camera.
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * self.rotate_speed;
+        self.pitch = (self.pitch + dy * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Zoom by `lines` wheel lines; positive moves toward the target.
+    pub fn zoom(&mut self, lines: f32) {
+        self.distance = (self.distance * (1.0 - self.zoom_speed).powf(lines)).max(self.min_distance);
+    }
+
+    /// Override the projection from command-line `args`: `--fov <degrees>`, `--near <n>`, `--far <f>`
+    /// and `--ortho`. Other arguments are ignored.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        let value = |name: &str| -> Result<Option<f32>, String> {
+            let Some(i) = args.iter().position(|a| a == name) else {
+                return Ok(None);
+            };
+            let arg = args.get(i + 1).ok_or_else(|| format!("{} needs a value", name))?;
+            arg.parse().map(Some).map_err(|e| format!("{} {}: {}", name, arg, e))
+        };
+        if let Some(fov) = value("--fov")? {
+            if !(FOV_RANGE_DEG.0..=FOV_RANGE_DEG.1).contains(&fov) {
+                return Err(format!("--fov {} outside {:?} degrees", fov, FOV_RANGE_DEG));
+            }
+            self.fov_y = fov.to_radians();
+        }
+        let near = value("--near")?.unwrap_or(self.near);
+        let far = value("--far")?.unwrap_or(self.far);
+        if !(near > 0.0 && far > near) {
+            return Err(format!("need 0 < near < far, got near {} far {}", near, far));
+        }
+        (self.near, self.far) = (near, far);
+        self.orthographic |= args.iter().any(|a| a == "--ortho");
+        Ok(())
+    }
+
+    /// Update from a window event; returns true when the camera moved.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.dragging = *state == ElementState::Pressed;
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let previous = self.cursor.replace((position.x, position.y));
+                match previous {
+                    Some((x, y)) if self.dragging => {
+                        self.rotate((position.x - x) as f32, (position.y - y) as f32);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // Trackpads report pixels; roughly 20 per line.
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / 20.0,
+                };
+                self.zoom(lines);
+                lines != 0.0
+            }
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                let step = 5f32.to_radians();
+                let (min, max) = (FOV_RANGE_DEG.0.to_radians(), FOV_RANGE_DEG.1.to_radians());
+                match event.logical_key.as_ref() {
+                    Key::Character("[") => self.fov_y = (self.fov_y - step).max(min),
+                    Key::Character("]") => self.fov_y = (self.fov_y + step).min(max),
+                    Key::Character("o") | Key::Character("O") => self.orthographic = !self.orthographic,
+                    _ => return false,
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn view(&self) -> [f32; 16] {
+        look_at(self.eye(), self.target, [0.0, 1.0, 0.0])
+    }
+
+    pub fn projection(&self, aspect: f32) -> [f32; 16] {
+        if self.orthographic {
+            let half_height = self.distance * (self.fov_y / 2.0).tan();
+            let half_width = half_height * aspect;
+            ortho_projection(-half_width, half_width, -half_height, half_height, self.near, self.far)
+        } else {
+            perspective_projection(self.fov_y, aspect, self.near, self.far)
+        }
+    }
+
+    /// `view_proj` for `ExtractedView` at the window's `width` x `height`.
+    pub fn view_proj(&self, width: u32, height: u32) -> [f32; 16] {
+        let aspect = if height > 0 { width as f32 / height as f32 } else { 1.0 };
+        mat4_mul(&self.projection(aspect), &self.view())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(m: &[f32; 16], p: [f32; 3]) -> [f32; 3] {
+        let c = |r: usize| m[r] * p[0] + m[4 + r] * p[1] + m[8 + r] * p[2] + m[12 + r];
+        let w = c(3);
+        [c(0) / w, c(1) / w, c(2) / w]
+    }
+
+    #[test]
+    fn orbit_keeps_the_target_centered() {
+        let mut camera = OrbitCamera::new([2.0, 1.5, 2.0], [0.0, 0.5, 0.0]);
+        let eye = camera.eye();
+        assert!(eye.iter().zip([2.0, 1.5, 2.0]).all(|(a, b)| (a - b).abs() < 1e-5), "{:?}", eye);
+        for (dx, dy) in [(0.0, 0.0), (120.0, -40.0), (-300.0, 500.0)] {
+            camera.rotate(dx, dy);
+            let ndc = transform(&camera.view_proj(800, 600), camera.target);
+            assert!(ndc[0].abs() < 1e-4 && ndc[1].abs() < 1e-4 && ndc[2] > 0.0 && ndc[2] < 1.0, "{:?}", ndc);
+        }
+        assert!(camera.pitch <= MAX_PITCH);
+    }
+
+    #[test]
+    fn fit_bounds_keeps_the_box_in_view() {
+        let mut camera = OrbitCamera::new([2.0, 1.5, 2.0], [0.0; 3]);
+        let (min, max) = ([-3.0, 0.0, -1.0], [5.0, 2.0, 1.0]);
+        camera.fit_bounds(min, max);
+        assert_eq!(camera.target, [1.0, 1.0, 0.0]);
+        let view_proj = camera.view_proj(600, 600);
+        for corner in 0..8 {
+            let p = [0, 1, 2].map(|axis| if corner >> axis & 1 == 0 { min[axis] } else { max[axis] });
+            let ndc = transform(&view_proj, p);
+            assert!(ndc.iter().all(|c| c.abs() <= 1.0) && ndc[2] >= 0.0, "{:?} -> {:?}", p, ndc);
+        }
+    }
+
+    #[test]
+    fn args_override_the_projection() {
+        let mut camera = OrbitCamera::new([0.0, 0.0, 4.0], [0.0; 3]);
+        let args = |s: &str| s.split_whitespace().map(String::from).collect::<Vec<_>>();
+        camera.apply_args(&args("pbr_model --grid --fov 30 --near 0.5 --far 20 --ortho")).unwrap();
+        assert!((camera.fov_y - 30f32.to_radians()).abs() < 1e-6);
+        assert_eq!((camera.near, camera.far), (0.5, 20.0));
+        assert!(camera.orthographic);
+        // The target stays centered and the frustum spans 2 * distance * tan(fov / 2) vertically.
+        let view_proj = camera.view_proj(600, 600);
+        let edge = 4.0 * 15f32.to_radians().tan();
+        let ndc = transform(&view_proj, [0.0, edge, 0.0]);
+        assert!(ndc[0].abs() < 1e-5 && (ndc[1] - 1.0).abs() < 1e-5 && ndc[2] > 0.0 && ndc[2] < 1.0, "{:?}", ndc);
+
+        assert!(camera.apply_args(&args("--near 30")).is_err());
+        assert!(camera.apply_args(&args("--fov wide")).is_err());
+        assert!(camera.apply_args(&args("--fov")).is_err());
+    }
+
+    #[test]
+    fn zoom_scales_distance_and_stops_at_the_minimum() {
+        let mut camera = OrbitCamera::new([0.0, 0.0, 4.0], [0.0; 3]);
+        camera.zoom(1.0);
+        assert!((camera.distance - 3.6).abs() < 1e-5);
+        camera.zoom(-1.0);
+        assert!((camera.distance - 4.0).abs() < 1e-5);
+        camera.zoom(1000.0);
+        assert_eq!(camera.distance, camera.min_distance);
+    }
+}
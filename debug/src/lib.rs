@@ -0,0 +1,3 @@
+//! Helpers shared by the debug/example binaries.
+
+pub mod camera;
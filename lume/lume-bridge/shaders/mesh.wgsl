@@ -0,0 +1,36 @@
+// Direct mesh path: position + normal, one directional light with a constant ambient term.
+// Vertex input is the 24-byte position/normal prefix of render_api's vertex layouts. See src/mesh_pass.rs.
+
+struct MeshUniform {
+    view_proj: mat4x4<f32>,
+    model: mat4x4<f32>,
+    // xyz = direction the light travels (world space), w unused.
+    light_dir: vec4<f32>,
+    // rgb = light color, a = ambient intensity.
+    light_color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> u: MeshUniform;
+
+struct VertexOutput {
+    @builtin(position) clip: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+}
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(1) normal: vec3<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip = u.view_proj * u.model * vec4<f32>(position, 1.0);
+    // Upper 3x3 of the model matrix; correct for rotations and uniform scale.
+    out.normal = (u.model * vec4<f32>(normal, 0.0)).xyz;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(in.normal);
+    let l = normalize(-u.light_dir.xyz);
+    let diffuse = max(dot(n, l), 0.0);
+    let color = u.light_color.rgb * (diffuse + u.light_color.a);
+    return vec4<f32>(color, 1.0);
+}
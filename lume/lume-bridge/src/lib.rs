@@ -1,8 +1,18 @@
 //! Lume Bridge: MercuryEngine integration layer.
 //! Uses render_api types and RenderBackend; Lume RHI (Vulkan) and Lume Renderer.
 
+mod mesh_pass;
 mod plugin;
+mod window;
+
+#[cfg(test)]
+mod test_support;
 
 pub use plugin::LumePlugin;
 pub use lume_renderer::Renderer;
-pub use render_api::{ExtractedMesh, ExtractedMeshes, ExtractedView};
+/// Extract types are render-api's own, not copies: data a host extracts once drives either backend.
+pub use render_api::{
+    ExtractedMesh, ExtractedMeshes, ExtractedPbrMaterial, ExtractedView, MeshLod, PbrTextureData, PointLight,
+    RenderBackend, RenderBackendWindow, ShaderVariant, SkyLight, Sphere, SpotLight, SwapchainInfo, ToneMapping,
+    VertexFormat,
+};
@@ -0,0 +1,315 @@
+//! Direct mesh path: draws `ExtractedMeshes` straight into the swapchain image with one directional
+//! light, as a separate pass. Stands in for the deferred pipeline until it is ported to Lume, so hosts
+//! get the same picture from `RenderBackendWindow` on either backend; this is the desktop default, and
+//! `lume_renderer::deferred`'s merged GBuffer and lighting subpasses are for tiled GPUs.
+
+use lume_rhi::{
+    Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, ColorTargetState, CompareOp, CullMode,
+    DepthStencilState, DescriptorPool, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    DescriptorType, Device, DeviceExt, GraphicsPipeline, GraphicsPipelineDescriptor, IndexFormat, LoadOp, PrimitiveTopology,
+    RasterizationState, RenderPass, ShaderStage, ShaderStages, StoreOp, TextureFormat, VertexAttribute,
+    VertexBinding, VertexFormat, VertexInputDescriptor, VertexInputRate,
+};
+use lume_renderer::compile_wgsl;
+use render_api::{ExtractedMesh, ExtractedMeshes, ExtractedView};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Depth format of the window target; the pipeline is built against it.
+pub(crate) const DEPTH_FORMAT: TextureFormat = TextureFormat::D32Float;
+
+/// Position (12) + normal (12): the prefix shared by every `render_api::VertexFormat`.
+const VERTEX_STRIDE: usize = 24;
+
+/// Matches lumelite's fallback when the view has no directional light.
+const DEFAULT_DIRECTIONAL_LIGHT: ([f32; 3], [f32; 3]) = ([0.3, -0.8, 0.5], [1.0, 1.0, 1.0]);
+
+const AMBIENT: f32 = 0.03;
+
+/// Layout of `MeshUniform` in shaders/mesh.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MeshUniform {
+    view_proj: [f32; 16],
+    model: [f32; 16],
+    light_dir: [f32; 4],
+    light_color: [f32; 4],
+}
+
+const UNIFORM_SIZE: u64 = std::mem::size_of::<MeshUniform>() as u64;
+
+/// Uniform buffer and its descriptor set for one swapchain image.
+struct UniformSlot {
+    buffer: Box<dyn Buffer>,
+    set: Box<dyn DescriptorSet>,
+    _pool: Box<dyn DescriptorPool>,
+}
+
+struct GpuMesh {
+    vertex_buffer: Box<dyn Buffer>,
+    index_buffer: Box<dyn Buffer>,
+    index_count: u32,
+    /// Hash of the extracted vertex and index bytes; a change triggers a re-upload.
+    content_hash: u64,
+    /// `ExtractedMesh::geometry_revision` the hash was taken at; the bytes are only rehashed when it moves.
+    geometry_revision: u64,
+    transform: [f32; 16],
+    visible: bool,
+    /// One uniform slot per swapchain image, so a frame in flight never sees the next frame's matrices.
+    slots: Vec<UniformSlot>,
+}
+
+pub(crate) struct MeshPass {
+    layout_bindings: Vec<DescriptorSetLayoutBinding>,
+    set_layout: Box<dyn DescriptorSetLayout>,
+    /// Built for the swapchain's color format on first use; rebuilt if the format changes.
+    pipeline: Option<(TextureFormat, Box<dyn GraphicsPipeline>)>,
+    meshes: HashMap<u64, GpuMesh>,
+}
+
+impl MeshPass {
+    pub(crate) fn new(device: &dyn Device) -> Result<Self, String> {
+        let layout_bindings = vec![DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: DescriptorType::UniformBuffer,
+            count: 1,
+            stages: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+        }];
+        let set_layout = device.create_descriptor_set_layout(&layout_bindings)?;
+        Ok(Self {
+            layout_bindings,
+            set_layout,
+            pipeline: None,
+            meshes: HashMap::new(),
+        })
+    }
+
+    /// Upload new or changed meshes and drop the ones no longer extracted. Only meshes whose
+    /// `geometry_revision` moved, or is 0 (untracked), are hashed. Buffers are host-visible and earlier frames may still read
+    /// them, so the device is idled before any are replaced.
+    pub(crate) fn prepare(&mut self, device: &dyn Device, extracted: &ExtractedMeshes) -> Result<(), String> {
+        // Hashes of new meshes and of changed geometry that needs new buffers.
+        let mut hashes: HashMap<u64, u64> = HashMap::new();
+        for (&id, mesh) in &extracted.meshes {
+            match self.meshes.get_mut(&id) {
+                Some(gpu) if mesh.geometry_revision != 0 && gpu.geometry_revision == mesh.geometry_revision => {}
+                Some(gpu) => match content_hash(mesh) {
+                    hash if hash == gpu.content_hash => gpu.geometry_revision = mesh.geometry_revision,
+                    hash => {
+                        hashes.insert(id, hash);
+                    }
+                },
+                None => {
+                    hashes.insert(id, content_hash(mesh));
+                }
+            }
+        }
+        let stale = |id: &u64| !extracted.meshes.contains_key(id) || hashes.contains_key(id);
+        if self.meshes.keys().any(stale) {
+            device.wait_idle()?;
+            self.meshes.retain(|id, _| !stale(id));
+        }
+        for (&id, mesh) in &extracted.meshes {
+            match self.meshes.get_mut(&id) {
+                Some(gpu) => {
+                    gpu.transform = mesh.transform;
+                    gpu.visible = mesh.visible;
+                }
+                None => {
+                    if let Some(gpu) = upload_mesh(device, mesh, hashes[&id])? {
+                        self.meshes.insert(id, gpu);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensure the pipeline matches `color_format`.
+    pub(crate) fn ensure_pipeline(&mut self, device: &dyn Device, color_format: TextureFormat) -> Result<(), String> {
+        if matches!(self.pipeline, Some((format, _)) if format == color_format) {
+            return Ok(());
+        }
+        let pipeline = create_pipeline(device, color_format, &self.layout_bindings)?;
+        self.pipeline = Some((color_format, pipeline));
+        Ok(())
+    }
+
+    /// Record draws for all visible meshes into `pass`. `image_index` selects the uniform slot;
+    /// the caller must have waited for that image's previous frame.
+    pub(crate) fn record(
+        &mut self,
+        device: &dyn Device,
+        pass: &mut dyn RenderPass,
+        view: &ExtractedView,
+        image_index: u32,
+    ) -> Result<(), String> {
+        let Some((_, pipeline)) = self.pipeline.as_ref() else {
+            return Err("MeshPass::record called before ensure_pipeline".to_string());
+        };
+        let (direction, color) = view.directional_light.unwrap_or(DEFAULT_DIRECTIONAL_LIGHT);
+        pass.set_pipeline(pipeline.as_ref());
+        for gpu in self.meshes.values_mut() {
+            if !gpu.visible || gpu.index_count == 0 {
+                continue;
+            }
+            while gpu.slots.len() <= image_index as usize {
+                gpu.slots.push(create_uniform_slot(device, self.set_layout.as_ref())?);
+            }
+            let slot = &gpu.slots[image_index as usize];
+            let uniform = MeshUniform {
+                view_proj: view.view_proj,
+                model: gpu.transform,
+                light_dir: [direction[0], direction[1], direction[2], 0.0],
+                light_color: [color[0], color[1], color[2], AMBIENT],
+            };
+            device.write_buffer_typed(slot.buffer.as_ref(), 0, &uniform)?;
+            pass.bind_descriptor_set(0, slot.set.as_ref());
+            pass.set_vertex_buffer(0, gpu.vertex_buffer.as_ref(), 0);
+            pass.set_index_buffer(gpu.index_buffer.as_ref(), 0, IndexFormat::Uint32);
+            pass.draw_indexed(gpu.index_count, 1, 0, 0, 0);
+        }
+        Ok(())
+    }
+}
+
+/// Strip each vertex to its position/normal prefix.
+fn position_normal_data(mesh: &ExtractedMesh) -> Vec<u8> {
+    mesh.vertex_data
+        .chunks_exact(mesh.vertex_format.stride())
+        .flat_map(|vertex| &vertex[..VERTEX_STRIDE])
+        .copied()
+        .collect()
+}
+
+fn content_hash(mesh: &ExtractedMesh) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    mesh.vertex_format.stride().hash(&mut hasher);
+    mesh.vertex_data.hash(&mut hasher);
+    mesh.index_data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns None for meshes with no geometry.
+fn upload_mesh(device: &dyn Device, mesh: &ExtractedMesh, content_hash: u64) -> Result<Option<GpuMesh>, String> {
+    let vertices = position_normal_data(mesh);
+    let index_count = (mesh.index_data.len() / 4) as u32;
+    if vertices.is_empty() || index_count == 0 {
+        return Ok(None);
+    }
+    let vertex_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("lume_mesh_vertices"),
+        size: vertices.len() as u64,
+        usage: BufferUsage::VERTEX,
+        memory: BufferMemoryPreference::HostVisible,
+    })?;
+    device.write_buffer(vertex_buffer.as_ref(), 0, &vertices)?;
+    let index_bytes = &mesh.index_data[..index_count as usize * 4];
+    let index_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("lume_mesh_indices"),
+        size: index_bytes.len() as u64,
+        usage: BufferUsage::INDEX,
+        memory: BufferMemoryPreference::HostVisible,
+    })?;
+    device.write_buffer(index_buffer.as_ref(), 0, index_bytes)?;
+    Ok(Some(GpuMesh {
+        vertex_buffer,
+        index_buffer,
+        index_count,
+        content_hash,
+        geometry_revision: mesh.geometry_revision,
+        transform: mesh.transform,
+        visible: mesh.visible,
+        slots: Vec::new(),
+    }))
+}
+
+fn create_uniform_slot(device: &dyn Device, layout: &dyn DescriptorSetLayout) -> Result<UniformSlot, String> {
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("lume_mesh_uniform"),
+        size: UNIFORM_SIZE,
+        usage: BufferUsage::UNIFORM,
+        memory: BufferMemoryPreference::HostVisible,
+    })?;
+    let pool = device.create_descriptor_pool(1)?;
+    let mut set = pool.allocate_set(layout)?;
+    set.write_buffer(0, buffer.as_ref(), 0, UNIFORM_SIZE)?;
+    Ok(UniformSlot { buffer, set, _pool: pool })
+}
+
+fn create_pipeline(
+    device: &dyn Device,
+    color_format: TextureFormat,
+    layout_bindings: &[DescriptorSetLayoutBinding],
+) -> Result<Box<dyn GraphicsPipeline>, String> {
+    let source = include_str!("../shaders/mesh.wgsl");
+    device.create_graphics_pipeline(&GraphicsPipelineDescriptor {
+        label: Some("lume_mesh"),
+        vertex_shader: ShaderStage {
+            source: compile_wgsl(source, ShaderStages::VERTEX, "vs_main")?,
+            entry_point: "vs_main".to_string(),
+        },
+        fragment_shader: Some(ShaderStage {
+            source: compile_wgsl(source, ShaderStages::FRAGMENT, "fs_main")?,
+            entry_point: "fs_main".to_string(),
+        }),
+        vertex_input: VertexInputDescriptor {
+            attributes: vec![
+                VertexAttribute { location: 0, binding: 0, format: VertexFormat::Float32x3, offset: 0 },
+                VertexAttribute { location: 1, binding: 0, format: VertexFormat::Float32x3, offset: 12 },
+            ],
+            bindings: vec![VertexBinding {
+                binding: 0,
+                stride: VERTEX_STRIDE as u32,
+                input_rate: VertexInputRate::Vertex,
+            }],
+        },
+        primitive_topology: PrimitiveTopology::TriangleList,
+        primitive_restart: false,
+        // Host meshes come with either winding; no culling keeps both visible.
+        rasterization: RasterizationState { cull_mode: CullMode::None, ..Default::default() },
+        color_targets: vec![ColorTargetState {
+            format: color_format,
+            blend: None,
+            load_op: Some(LoadOp::Clear),
+            store_op: Some(StoreOp::Store),
+        }],
+        depth_stencil: Some(DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: CompareOp::Less,
+            depth_load_op: Some(LoadOp::Clear),
+            depth_store_op: Some(StoreOp::DontCare),
+        }),
+        layout_bindings: layout_bindings.to_vec(),
+        subpass: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render_api::VertexFormat as MeshVertexFormat;
+
+    #[test]
+    fn strips_vertices_to_position_normal() {
+        let vertex: Vec<u8> = (0..48u8).collect();
+        let mesh = ExtractedMesh {
+            vertex_data: [vertex.clone(), vertex].concat(),
+            vertex_format: MeshVertexFormat::PositionNormalTangentUv,
+            ..Default::default()
+        };
+        let out = position_normal_data(&mesh);
+        assert_eq!(out.len(), 2 * VERTEX_STRIDE);
+        assert_eq!(&out[..VERTEX_STRIDE], &out[VERTEX_STRIDE..]);
+        assert_eq!(out[23], 23);
+    }
+
+    #[test]
+    fn mesh_shader_compiles_to_spirv() {
+        let source = include_str!("../shaders/mesh.wgsl");
+        assert!(!compile_wgsl(source, ShaderStages::VERTEX, "vs_main").unwrap().is_empty());
+        assert!(!compile_wgsl(source, ShaderStages::FRAGMENT, "fs_main").unwrap().is_empty());
+    }
+}
@@ -1,14 +1,23 @@
-//! Lume plugin: implements render_api::RenderBackend for the host.
+//! Lume plugin: implements render_api::RenderBackend and RenderBackendWindow for the host.
 
 use lume_rhi::Device;
 use lume_renderer::Renderer;
-use render_api::{ExtractedMeshes, ExtractedView, RenderBackend};
+use render_api::{
+    log, ExtractedMeshes, ExtractedView, LogLevel, RawDisplayHandle, RawWindowHandle, RenderBackend, RenderBackendWindow,
+};
 use std::sync::Arc;
 
+use crate::mesh_pass::MeshPass;
+use crate::window::WindowTarget;
+
 /// Plugin state: holds the Lume renderer and device for submission.
 pub struct LumePlugin {
     device: Arc<dyn Device>,
     renderer: Renderer,
+    /// Direct mesh path drawn into the window; created on the first `prepare`.
+    mesh_pass: Option<MeshPass>,
+    /// Swapchain and frame sync; created on the first `render_frame_to_window`.
+    window: Option<WindowTarget>,
 }
 
 impl LumePlugin {
@@ -17,12 +26,19 @@ impl LumePlugin {
         Self {
             device: Arc::clone(&device),
             renderer: Renderer::new(device),
+            mesh_pass: None,
+            window: None,
         }
     }
 
-    /// Prepare phase: upload extracted meshes to GPU buffers and register with the render graph.
-    pub fn prepare(&mut self, _extracted: &ExtractedMeshes) {
-        // TODO: Create/update RHI buffers from extracted mesh data and add to graph resources.
+    /// Prepare phase: upload extracted meshes to GPU buffers. Meshes are cached by `entity_id` and
+    /// re-uploaded when their vertex or index data changes (see `ExtractedMesh::geometry_revision`).
+    pub fn prepare(&mut self, extracted: &ExtractedMeshes) -> Result<(), String> {
+        if self.mesh_pass.is_none() {
+            self.mesh_pass = Some(MeshPass::new(self.device.as_ref())?);
+        }
+        let mesh_pass = self.mesh_pass.as_mut().expect("mesh pass created above");
+        mesh_pass.prepare(self.device.as_ref(), extracted)
     }
 
     /// Render one frame; returns command buffers (caller may submit via device). Used internally by RenderBackend.
@@ -32,11 +48,60 @@ impl LumePlugin {
     ) -> Result<Vec<Box<dyn lume_rhi::CommandBuffer>>, String> {
         self.renderer.render_frame()
     }
+
+    /// Render one frame and present it. Mirrors lumelite's `render_frame_to_window`: the swapchain is
+    /// (re)created from `view.viewport_size`, acquire/present and frame pacing are handled here.
+    /// Prepared meshes are drawn with `view.view_proj` and its directional light.
+    /// The surface is owned by the device, so it must have been created with this window
+    /// (`DeviceCreateParams { surface: Some(window), .. }`); any other window is an error. The display
+    /// handle is not needed, as the surface already has it.
+    pub fn render_frame_to_window(
+        &mut self,
+        view: &ExtractedView,
+        raw_window_handle: RawWindowHandle,
+        _raw_display_handle: RawDisplayHandle,
+    ) -> Result<(), String> {
+        if !self.device.surface_matches(raw_window_handle) {
+            return Err(
+                "render_frame_to_window: the device's surface was not created for this window \
+                 (DeviceCreateParams::surface)"
+                    .to_string(),
+            );
+        }
+        let (width, height) = view.viewport_size;
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let target = WindowTarget::ensure(self.window.take(), self.device.as_ref(), (width, height))?;
+        let target = self.window.insert(target);
+        let device = self.device.as_ref();
+        let mut mesh_pass = self.mesh_pass.as_mut();
+        if let Some(mesh_pass) = mesh_pass.as_deref_mut() {
+            mesh_pass.ensure_pipeline(device, target.format())?;
+        }
+        let command_buffers = self.renderer.render_frame()?;
+        target.present_frame(device, command_buffers, |pass, image_index| match mesh_pass {
+            Some(mesh_pass) => mesh_pass.record(device, pass, view, image_index),
+            None => Ok(()),
+        })
+    }
+}
+
+impl Drop for LumePlugin {
+    fn drop(&mut self) {
+        // Swapchain images and in-flight command buffers must outlive the GPU work using them.
+        if self.window.is_some() {
+            let _ = self.device.wait_idle();
+        }
+    }
 }
 
 impl RenderBackend for LumePlugin {
     fn prepare(&mut self, extracted: &ExtractedMeshes) {
-        LumePlugin::prepare(self, extracted);
+        // The trait has no error channel; a failed upload leaves the previous meshes in place.
+        if let Err(e) = LumePlugin::prepare(self, extracted) {
+            log(LogLevel::Error, "lume", format_args!("prepare: {}", e));
+        }
     }
 
     fn render_frame(&mut self, _view: &ExtractedView) -> Result<(), String> {
@@ -45,3 +110,39 @@ impl RenderBackend for LumePlugin {
         Ok(())
     }
 }
+
+impl RenderBackendWindow for LumePlugin {
+    fn render_frame_to_window(
+        &mut self,
+        view: &ExtractedView,
+        raw_window_handle: RawWindowHandle,
+        raw_display_handle: RawDisplayHandle,
+    ) -> Result<(), String> {
+        LumePlugin::render_frame_to_window(self, view, raw_window_handle, raw_display_handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeDevice;
+    use raw_window_handle::{RawDisplayHandle, Win32WindowHandle, WindowsDisplayHandle};
+    use std::num::NonZeroIsize;
+
+    fn win32(hwnd: isize) -> RawWindowHandle {
+        RawWindowHandle::Win32(Win32WindowHandle::new(NonZeroIsize::new(hwnd).unwrap()))
+    }
+
+    #[test]
+    fn presents_only_to_the_device_surface_window() {
+        let device = Arc::new(FakeDevice { hwnd: Some(7), ..Default::default() });
+        let mut plugin = LumePlugin::new(device.clone());
+        let view = ExtractedView { viewport_size: (4, 4), ..Default::default() };
+        let display = RawDisplayHandle::Windows(WindowsDisplayHandle::new());
+        let err = plugin.render_frame_to_window(&view, win32(8), display).unwrap_err();
+        assert!(err.contains("not created for this window"), "{}", err);
+        assert!(device.presented.lock().unwrap().is_empty());
+        plugin.render_frame_to_window(&view, win32(7), display).unwrap();
+        assert_eq!(*device.presented.lock().unwrap(), [0]);
+    }
+}
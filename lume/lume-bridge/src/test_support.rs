@@ -0,0 +1,308 @@
+//! A fake device that tracks semaphore and fence state, so presenting can be tested without a
+//! window or GPU. Only what the window path uses is implemented; the rest returns an error.
+
+use lume_rhi::{
+    Buffer, BufferDescriptor, ClearColor, CommandBuffer, CommandEncoder, ComputePass, ComputePipeline,
+    ComputePipelineDescriptor, DescriptorPool, DescriptorPoolDescriptor, DescriptorSet, DescriptorSetLayout,
+    DescriptorSetLayoutBinding, Device, Fence, FilterMode, GraphicsPipeline, GraphicsPipelineDescriptor, ImageLayout,
+    IndexFormat, Queue, Rect2D, RenderPass, RenderPassDescriptor, ResourceId, Sampler, SamplerDescriptor, Semaphore,
+    Swapchain, SwapchainAcquire, SwapchainFrame, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureRegion, TextureSubresourceRange,
+};
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct FakeSemaphore {
+    signaled: AtomicBool,
+}
+
+impl Semaphore for FakeSemaphore {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub(crate) fn signaled(semaphore: &dyn Semaphore) -> &AtomicBool {
+    &semaphore.as_any().downcast_ref::<FakeSemaphore>().expect("fake semaphore").signaled
+}
+
+/// Consume a signaled semaphore, as a queue or present wait does.
+fn wait(semaphore: &dyn Semaphore) -> Result<(), String> {
+    match signaled(semaphore).swap(false, Ordering::SeqCst) {
+        true => Ok(()),
+        false => Err("waited on an unsignaled semaphore".to_string()),
+    }
+}
+
+#[derive(Debug)]
+struct FakeFence {
+    signaled: AtomicBool,
+}
+
+impl Fence for FakeFence {
+    fn wait(&self, _timeout_ns: u64) -> Result<(), String> {
+        match self.signaled.load(Ordering::SeqCst) {
+            true => Ok(()),
+            false => Err("fence timeout".to_string()),
+        }
+    }
+    fn reset(&self) -> Result<(), String> {
+        self.signaled.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct FakeTexture {
+    format: TextureFormat,
+    size: (u32, u32, u32),
+}
+
+impl Texture for FakeTexture {
+    fn id(&self) -> ResourceId {
+        0
+    }
+    fn format(&self) -> TextureFormat {
+        self.format
+    }
+    fn size(&self) -> (u32, u32, u32) {
+        self.size
+    }
+    fn dimension(&self) -> TextureDimension {
+        TextureDimension::D2
+    }
+    fn mip_level_count(&self) -> u32 {
+        1
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct FakeCommandBuffer;
+
+impl CommandBuffer for FakeCommandBuffer {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug)]
+struct FakeRenderPass;
+
+impl RenderPass for FakeRenderPass {
+    fn set_pipeline(&mut self, _pipeline: &dyn GraphicsPipeline) {}
+    fn bind_descriptor_set(&mut self, _set_index: u32, _set: &dyn DescriptorSet) {}
+    fn set_vertex_buffer(&mut self, _index: u32, _buffer: &dyn Buffer, _offset: u64) {}
+    fn set_index_buffer(&mut self, _buffer: &dyn Buffer, _offset: u64, _index_format: IndexFormat) {}
+    fn draw(&mut self, _vertex_count: u32, _instance_count: u32, _first_vertex: u32, _first_instance: u32) {}
+    fn draw_indexed(&mut self, _: u32, _: u32, _: u32, _: i32, _: u32) {}
+    fn draw_indexed_indirect(&mut self, _buffer: &dyn Buffer, _offset: u64, _draw_count: u32, _stride: u32) {}
+    fn clear_region(&mut self, _rect: Rect2D, _color: Option<ClearColor>, _depth: Option<f32>) -> Result<(), String> {
+        Ok(())
+    }
+    fn next_subpass(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+    fn end(self: Box<Self>) {}
+}
+
+/// Result of every fake method the window path does not use, so a test that reaches one gets an error
+/// it can check instead of a panic.
+fn unsupported<T>() -> Result<T, String> {
+    Err("not supported by FakeDevice".to_string())
+}
+
+#[derive(Debug)]
+struct FakeComputePass;
+
+impl ComputePass for FakeComputePass {
+    fn set_pipeline(&mut self, _pipeline: &dyn ComputePipeline) {}
+    fn bind_descriptor_set(&mut self, _set_index: u32, _set: &dyn DescriptorSet) {}
+    fn dispatch(&mut self, _x: u32, _y: u32, _z: u32) -> Result<(), String> {
+        unsupported()
+    }
+    fn dispatch_indirect(&mut self, _buffer: &dyn Buffer, _offset: u64) {}
+}
+
+#[derive(Debug)]
+struct FakeEncoder;
+
+impl CommandEncoder for FakeEncoder {
+    fn begin_compute_pass(&mut self) -> Box<dyn ComputePass> {
+        Box::new(FakeComputePass)
+    }
+    fn begin_render_pass<'a>(&mut self, _desc: RenderPassDescriptor<'a>) -> Result<Box<dyn RenderPass>, String> {
+        Ok(Box::new(FakeRenderPass))
+    }
+    fn copy_buffer_to_buffer(&mut self, _: &dyn Buffer, _: u64, _: &dyn Buffer, _: u64, _: u64) {}
+    fn copy_buffer_to_texture(&mut self, _: &dyn Buffer, _: u64, _: &dyn Texture, _: u32, _: (u32, u32, u32), _: (u32, u32, u32)) {}
+    fn copy_texture_to_buffer(&mut self, _: &dyn Texture, _: u32, _: (u32, u32, u32), _: (u32, u32, u32), _: &dyn Buffer, _: u64) {}
+    fn blit_texture(&mut self, _: &dyn Texture, _: TextureRegion, _: &dyn Texture, _: TextureRegion, _: FilterMode) {}
+    fn pipeline_barrier_texture_range(&mut self, _: &dyn Texture, _: ImageLayout, _: ImageLayout, _: TextureSubresourceRange) {}
+    fn pipeline_barrier_buffer(&mut self, _: &dyn Buffer, _: u64, _: u64) {}
+    fn pipeline_barrier_buffer_after_read(&mut self, _: &dyn Buffer, _: u64, _: u64) {}
+    fn pipeline_barrier_memory(&mut self) {}
+    fn pipeline_barrier_buffer_host_read(&mut self, _: &dyn Buffer, _: u64, _: u64) {}
+    fn pipeline_barrier_buffer_indirect_read(&mut self, _: &dyn Buffer, _: u64, _: u64) {}
+    fn finish(self: Box<Self>) -> Result<Box<dyn CommandBuffer>, String> {
+        Ok(Box::new(FakeCommandBuffer))
+    }
+}
+
+#[derive(Debug)]
+struct FakeQueue;
+
+impl Queue for FakeQueue {
+    fn submit(
+        &self,
+        _command_buffers: &[&dyn CommandBuffer],
+        wait_semaphores: &[&dyn Semaphore],
+        signal_semaphores: &[&dyn Semaphore],
+        signal_fence: Option<&dyn Fence>,
+    ) -> Result<(), String> {
+        wait_semaphores.iter().try_for_each(|s| wait(*s))?;
+        for semaphore in signal_semaphores {
+            signaled(*semaphore).store(true, Ordering::SeqCst);
+        }
+        if let Some(fence) = signal_fence {
+            fence.as_any().downcast_ref::<FakeFence>().expect("fake fence").signaled.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+/// Two images handed out in turn; acquiring into a signaled semaphore is an error, as in Vulkan.
+#[derive(Debug)]
+struct FakeSwapchain {
+    images: Vec<FakeTexture>,
+    next: u32,
+    presented: Arc<Mutex<Vec<u32>>>,
+}
+
+impl Swapchain for FakeSwapchain {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn acquire_next_image_timeout(
+        &mut self,
+        wait_semaphore: Option<&dyn Semaphore>,
+        _timeout_ns: u64,
+    ) -> Result<SwapchainAcquire<'_>, String> {
+        let semaphore = signaled(wait_semaphore.expect("acquire semaphore"));
+        if semaphore.swap(true, Ordering::SeqCst) {
+            return Err("acquire semaphore is already signaled".to_string());
+        }
+        let image_index = self.next;
+        self.next = (self.next + 1) % self.image_count();
+        Ok(SwapchainAcquire::Frame(SwapchainFrame {
+            image_index,
+            texture: &self.images[image_index as usize],
+            layout: ImageLayout::Undefined,
+        }))
+    }
+    fn present(&self, image_index: u32, wait_semaphore: Option<&dyn Semaphore>) -> Result<(), String> {
+        wait(wait_semaphore.expect("render semaphore"))?;
+        self.presented.lock().unwrap().push(image_index);
+        Ok(())
+    }
+    fn image_layout(&self, _image_index: u32) -> ImageLayout {
+        ImageLayout::Undefined
+    }
+    fn set_image_layout(&self, _image_index: u32, _layout: ImageLayout) {}
+    fn extent(&self) -> (u32, u32) {
+        (self.images[0].size.0, self.images[0].size.1)
+    }
+    fn image_count(&self) -> u32 {
+        self.images.len() as u32
+    }
+    fn format(&self) -> TextureFormat {
+        TextureFormat::Bgra8Unorm
+    }
+}
+
+/// Presents to the Win32 window `hwnd` when set; records presented image indices.
+#[derive(Debug, Default)]
+pub(crate) struct FakeDevice {
+    pub(crate) hwnd: Option<isize>,
+    pub(crate) presented: Arc<Mutex<Vec<u32>>>,
+}
+
+impl Device for FakeDevice {
+    fn create_buffer(&self, _desc: &BufferDescriptor) -> Result<Box<dyn Buffer>, String> {
+        unsupported()
+    }
+    fn create_texture(&self, desc: &TextureDescriptor) -> Result<Box<dyn Texture>, String> {
+        Ok(Box::new(FakeTexture { format: desc.format, size: desc.size }))
+    }
+    fn create_sampler(&self, _desc: &SamplerDescriptor) -> Result<Box<dyn Sampler>, String> {
+        unsupported()
+    }
+    fn create_compute_pipeline(&self, _desc: &ComputePipelineDescriptor) -> Result<Box<dyn ComputePipeline>, String> {
+        unsupported()
+    }
+    fn create_compute_pipeline_uncached(
+        &self,
+        _desc: &ComputePipelineDescriptor,
+    ) -> Result<Box<dyn ComputePipeline>, String> {
+        unsupported()
+    }
+    fn create_graphics_pipeline(&self, _desc: &GraphicsPipelineDescriptor) -> Result<Box<dyn GraphicsPipeline>, String> {
+        unsupported()
+    }
+    fn create_descriptor_set_layout(
+        &self,
+        _bindings: &[DescriptorSetLayoutBinding],
+    ) -> Result<Box<dyn DescriptorSetLayout>, String> {
+        unsupported()
+    }
+    fn create_descriptor_pool(&self, _max_sets: u32) -> Result<Box<dyn DescriptorPool>, String> {
+        unsupported()
+    }
+    fn create_descriptor_pool_with_descriptor(
+        &self,
+        _desc: &DescriptorPoolDescriptor,
+    ) -> Result<Box<dyn DescriptorPool>, String> {
+        unsupported()
+    }
+    fn create_command_encoder(&self) -> Result<Box<dyn CommandEncoder>, String> {
+        Ok(Box::new(FakeEncoder))
+    }
+    fn submit(&self, _command_buffers: Vec<Box<dyn CommandBuffer>>) -> Result<(), String> {
+        unsupported()
+    }
+    fn queue(&self) -> Result<Box<dyn Queue>, String> {
+        Ok(Box::new(FakeQueue))
+    }
+    fn write_buffer(&self, _buffer: &dyn Buffer, _offset: u64, _data: &[u8]) -> Result<(), String> {
+        unsupported()
+    }
+    fn read_buffer(&self, _buffer: &dyn Buffer, _offset: u64, _out: &mut [u8]) -> Result<(), String> {
+        unsupported()
+    }
+    fn upload_to_buffer(&self, _buffer: &dyn Buffer, _offset: u64, _data: &[u8]) -> Result<(), String> {
+        unsupported()
+    }
+    fn wait_idle(&self) -> Result<(), String> {
+        Ok(())
+    }
+    fn create_fence(&self, signaled: bool) -> Result<Box<dyn Fence>, String> {
+        Ok(Box::new(FakeFence { signaled: AtomicBool::new(signaled) }))
+    }
+    fn create_semaphore(&self) -> Result<Box<dyn Semaphore>, String> {
+        Ok(Box::new(FakeSemaphore::default()))
+    }
+    fn surface_matches(&self, window: raw_window_handle::RawWindowHandle) -> bool {
+        matches!(window, raw_window_handle::RawWindowHandle::Win32(win) if Some(win.hwnd.get()) == self.hwnd)
+    }
+    fn create_swapchain(&self, extent: (u32, u32), _old: Option<&dyn Swapchain>) -> Result<Box<dyn Swapchain>, String> {
+        let image = || FakeTexture { format: TextureFormat::Bgra8Unorm, size: (extent.0, extent.1, 1) };
+        Ok(Box::new(FakeSwapchain { images: vec![image(), image()], next: 0, presented: Arc::clone(&self.presented) }))
+    }
+}
@@ -0,0 +1,211 @@
+//! Window presentation for LumePlugin: owns the swapchain, depth buffer and per-image sync, records
+//! the final pass into the acquired image and presents. The surface itself belongs to the device
+//! (`lume_rhi::create_device` with `surface: Some(window)`).
+
+use lume_rhi::{
+    ClearColor, ColorAttachment, CommandBuffer, DepthStencilAttachment, Device, Fence, ImageLayout, LoadOp,
+    RenderPass, RenderPassDescriptor, Semaphore, StoreOp, Swapchain, SwapchainAcquire, SwapchainFrame, Texture,
+    TextureDescriptor, TextureFormat, TextureUsage,
+};
+
+use crate::mesh_pass::DEPTH_FORMAT;
+
+/// How long to wait for an image's previous frame before giving up (10 s).
+const FENCE_TIMEOUT_NS: u64 = 10_000_000_000;
+
+/// Background of the presented image.
+const CLEAR_COLOR: ClearColor = ClearColor { r: 0.0, g: 0.0, b: 0.0, a: 1.0 };
+
+pub(crate) struct WindowTarget {
+    swapchain: Box<dyn Swapchain>,
+    /// Shared by all swapchain images; cleared at the start of every frame.
+    depth: Box<dyn Texture>,
+    sem_acquire: Box<dyn Semaphore>,
+    sem_render: Box<dyn Semaphore>,
+    /// One fence per swapchain image; waited before the image is reused.
+    frame_fences: Vec<Box<dyn Fence>>,
+    /// Command buffers submitted for each image, kept alive until its fence is waited on
+    /// (freeing them earlier causes DEVICE_LOST).
+    pending_command_buffers: Vec<Vec<Box<dyn CommandBuffer>>>,
+}
+
+impl WindowTarget {
+    fn new(device: &dyn Device, extent: (u32, u32), old: Option<&dyn Swapchain>) -> Result<Self, String> {
+        let swapchain = device.create_swapchain(extent, old)?;
+        let n = swapchain.image_count() as usize;
+        let depth = device.create_texture(&TextureDescriptor {
+            label: Some("lume_window_depth"),
+            size: (extent.0, extent.1, 1),
+            format: DEPTH_FORMAT,
+            usage: TextureUsage::RENDER_ATTACHMENT,
+            // Cleared and discarded within the present pass.
+            transient: true,
+            ..Default::default()
+        })?;
+        // Fences start signaled so the first wait on each image passes immediately.
+        let frame_fences = (0..n).map(|_| device.create_fence(true)).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            swapchain,
+            depth,
+            sem_acquire: device.create_semaphore()?,
+            sem_render: device.create_semaphore()?,
+            frame_fences,
+            pending_command_buffers: (0..n).map(|_| Vec::new()).collect(),
+        })
+    }
+
+    /// Returns a target for `extent`, creating or resizing the swapchain as needed.
+    pub(crate) fn ensure(existing: Option<Self>, device: &dyn Device, extent: (u32, u32)) -> Result<Self, String> {
+        match existing {
+            Some(t) if t.swapchain.extent() == extent => Ok(t),
+            Some(t) => {
+                device.wait_idle()?;
+                Self::new(device, extent, Some(t.swapchain.as_ref()))
+            }
+            None => Self::new(device, extent, None),
+        }
+    }
+
+    pub(crate) fn format(&self) -> TextureFormat {
+        self.swapchain.format()
+    }
+
+    /// Acquire an image, submit `command_buffers` followed by the present pass into it, and present.
+    /// When no image is available within the acquire timeout the frame is skipped.
+    /// `record` draws into the present pass once the image's previous frame has completed; it gets
+    /// the image index so per-image resources can be reused safely.
+    /// Once an image is acquired every path waits on the acquire semaphore, so a failed frame never
+    /// leaves it signaled for the next acquire: when encoding fails the image is still presented,
+    /// untouched, and the error returned.
+    pub(crate) fn present_frame(
+        &mut self,
+        device: &dyn Device,
+        mut command_buffers: Vec<Box<dyn CommandBuffer>>,
+        record: impl FnOnce(&mut dyn RenderPass, u32) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let frame = match self.swapchain.acquire_next_image(Some(self.sem_acquire.as_ref()))? {
+            SwapchainAcquire::Frame(frame) => frame,
+            // The compositor is stalled: drop this frame rather than block the event loop.
+            SwapchainAcquire::WouldBlock => return Ok(()),
+        };
+        let image_index = frame.image_index as usize;
+        if let Err(e) = self.frame_fences[image_index].wait(FENCE_TIMEOUT_NS) {
+            // The fence is still in use, so nothing can be submitted with it.
+            self.consume_acquire(device);
+            return Err(e);
+        }
+        self.pending_command_buffers[image_index].clear();
+
+        let encoded = encode_frame(device, &frame, self.depth.as_ref(), record)
+            .map_err(|e| (e, encode_present_transition(device, &frame)));
+        match encoded {
+            Ok(frame_commands) => {
+                command_buffers.push(frame_commands);
+                self.submit_and_present(device, image_index, command_buffers)
+            }
+            Err((e, transition)) => {
+                // `command_buffers` are dropped unsubmitted; the image goes back with nothing drawn.
+                let released = match transition {
+                    Ok(cmd) => self.submit_and_present(device, image_index, vec![cmd]),
+                    Err(release) => {
+                        self.consume_acquire(device);
+                        Err(release)
+                    }
+                };
+                match released {
+                    Ok(()) => Err(e),
+                    Err(release) => Err(format!("{} (and releasing the image failed: {})", e, release)),
+                }
+            }
+        }
+    }
+
+    /// Submit `command_buffers` waiting on the acquire semaphore and present image `image_index`.
+    fn submit_and_present(
+        &mut self,
+        device: &dyn Device,
+        image_index: usize,
+        command_buffers: Vec<Box<dyn CommandBuffer>>,
+    ) -> Result<(), String> {
+        let fence = &self.frame_fences[image_index];
+        let cmd_refs: Vec<&dyn CommandBuffer> = command_buffers.iter().map(|c| c.as_ref()).collect();
+        let submitted = device.queue().and_then(|queue| {
+            // Reset only once submission is certain, so an early error leaves the fence signaled.
+            fence.reset()?;
+            queue.submit(&cmd_refs, &[self.sem_acquire.as_ref()], &[self.sem_render.as_ref()], Some(fence.as_ref()))
+        });
+        if let Err(e) = submitted {
+            self.consume_acquire(device);
+            return Err(e);
+        }
+        self.pending_command_buffers[image_index] = command_buffers;
+        self.swapchain.present(image_index as u32, Some(self.sem_render.as_ref()))
+    }
+
+    /// Wait on the acquire semaphore with an empty batch, so the next acquire gets it unsignaled.
+    /// Best effort: it only runs on paths that already return an error.
+    fn consume_acquire(&self, device: &dyn Device) {
+        let _ = device.queue().and_then(|queue| queue.submit(&[], &[self.sem_acquire.as_ref()], &[], None));
+    }
+}
+
+/// The present pass into the acquired image with `depth` cleared, ending in PresentSrc.
+fn encode_frame(
+    device: &dyn Device,
+    frame: &SwapchainFrame,
+    depth: &dyn Texture,
+    record: impl FnOnce(&mut dyn RenderPass, u32) -> Result<(), String>,
+) -> Result<Box<dyn CommandBuffer>, String> {
+    let mut encoder = device.create_command_encoder()?;
+    encoder.pipeline_barrier_texture(frame.texture, frame.layout, ImageLayout::ColorAttachment);
+    let mut pass = encoder.begin_render_pass(RenderPassDescriptor {
+        label: Some("lume_present"),
+        color_attachments: vec![ColorAttachment {
+            texture: frame.texture,
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store,
+            clear_value: Some(CLEAR_COLOR),
+            initial_layout: Some(ImageLayout::ColorAttachment),
+        }],
+        depth_stencil_attachment: Some(DepthStencilAttachment {
+            texture: depth,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::DontCare,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::DontCare,
+            clear_depth: 1.0,
+        }),
+        subpasses: Vec::new(),
+    })?;
+    let recorded = record(pass.as_mut(), frame.image_index);
+    pass.end();
+    recorded?;
+    encoder.pipeline_barrier_texture(frame.texture, ImageLayout::ColorAttachment, ImageLayout::PresentSrc);
+    encoder.finish()
+}
+
+/// Only the transition of the acquired image to PresentSrc, to hand it back after a failed frame.
+fn encode_present_transition(device: &dyn Device, frame: &SwapchainFrame) -> Result<Box<dyn CommandBuffer>, String> {
+    let mut encoder = device.create_command_encoder()?;
+    encoder.pipeline_barrier_texture(frame.texture, frame.layout, ImageLayout::PresentSrc);
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{signaled, FakeCommandBuffer, FakeDevice};
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn failed_frame_leaves_the_acquire_semaphore_unsignaled() {
+        let device = FakeDevice::default();
+        let mut target = WindowTarget::ensure(None, &device, (4, 4)).unwrap();
+        let failed = target.present_frame(&device, vec![Box::new(FakeCommandBuffer)], |_, _| Err("injected".to_string()));
+        assert_eq!(failed, Err("injected".to_string()));
+        assert!(!signaled(target.sem_acquire.as_ref()).load(Ordering::SeqCst));
+        target.present_frame(&device, Vec::new(), |_, _| Ok(())).unwrap();
+        // The failed frame's image is handed back too, so acquires keep cycling through the images.
+        assert_eq!(*device.presented.lock().unwrap(), [0, 1]);
+    }
+}
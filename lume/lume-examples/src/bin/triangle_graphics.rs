@@ -20,6 +20,7 @@ fn main() {
         usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::COPY_SRC,
         dimension: TextureDimension::D2,
         mip_level_count: 1,
+        transient: false,
     }).expect("create_texture");
 
     let vertex_buffer = device.create_buffer(&lume_rhi::BufferDescriptor {
@@ -54,6 +55,7 @@ fn main() {
             }],
         },
         primitive_topology: PrimitiveTopology::TriangleList,
+        primitive_restart: false,
         rasterization: Default::default(),
         color_targets: vec![ColorTargetState {
             format: TextureFormat::Rgba8Unorm,
@@ -63,6 +65,7 @@ fn main() {
         }],
         depth_stencil: None,
         layout_bindings: vec![],
+        subpass: None,
     };
 
     let pipeline = device.create_graphics_pipeline(&pipeline_desc).expect("create_graphics_pipeline");
@@ -83,6 +86,7 @@ fn main() {
             initial_layout: None,
         }],
         depth_stencil_attachment: None,
+        subpasses: Vec::new(),
     }).expect("begin_render_pass");
 
     pass.set_pipeline(pipeline.as_ref());
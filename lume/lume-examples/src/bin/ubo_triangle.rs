@@ -2,11 +2,11 @@
 //! bind_descriptor_set in RenderPass, and DescriptorSet::write_buffer with UniformBuffer type.
 
 use lume_rhi::{
-    BufferUsage, ColorAttachment, ColorTargetState, DescriptorSetLayoutBinding, DescriptorType,
+    BufferUsage, ColorAttachment, ColorTargetState, DescriptorSetLayoutBinding, DescriptorType, DeviceExt,
     GraphicsPipelineDescriptor, LoadOp, PrimitiveTopology, RenderPassDescriptor,
     ShaderStage, ShaderStages, StoreOp, TextureDescriptor, TextureDimension, TextureFormat,
     TextureUsage, VertexAttribute, VertexBinding, VertexInputDescriptor, VertexInputRate,
-    VertexFormat,
+    VertexFormat, reflect,
 };
 
 fn main() {
@@ -20,6 +20,7 @@ fn main() {
         usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::COPY_SRC,
         dimension: TextureDimension::D2,
         mip_level_count: 1,
+        transient: false,
     }).expect("create_texture");
 
     let vertex_buffer = device.create_buffer(&lume_rhi::BufferDescriptor {
@@ -30,7 +31,7 @@ fn main() {
     }).expect("create_buffer");
     let vertices: [f32; 9] = [0.0, 0.6, 0.0, -0.6, -0.6, 0.0, 0.6, -0.6, 0.0];
     device
-        .write_buffer(vertex_buffer.as_ref(), 0, bytemuck::bytes_of(&vertices))
+        .write_buffer_slice(vertex_buffer.as_ref(), 0, &vertices)
         .expect("write vertices");
 
     // UBO: vec4 color. Use 256 bytes to satisfy minUniformBufferOffsetAlignment.
@@ -43,7 +44,7 @@ fn main() {
     }).expect("create_buffer ubo");
     let color_data: [f32; 4] = [0.2, 0.8, 0.2, 1.0]; // green
     device
-        .write_buffer(uniform_buffer.as_ref(), 0, bytemuck::bytes_of(&color_data))
+        .write_buffer_typed(uniform_buffer.as_ref(), 0, &color_data)
         .expect("write ubo");
 
     let layout_bindings = vec![DescriptorSetLayoutBinding {
@@ -77,6 +78,7 @@ fn main() {
             }],
         },
         primitive_topology: PrimitiveTopology::TriangleList,
+        primitive_restart: false,
         rasterization: Default::default(),
         color_targets: vec![ColorTargetState {
             format: TextureFormat::Rgba8Unorm,
@@ -86,7 +88,12 @@ fn main() {
         }],
         depth_stencil: None,
         layout_bindings: layout_bindings.clone(),
+        subpass: None,
     };
+    // The hand-written layout must agree with the bindings the WGSL declares.
+    let reflected = reflect::reflect_pipeline_bindings(&[&pipeline_desc.vertex_shader.source, &fragment_spirv()])
+        .expect("reflect bindings");
+    reflect::validate_bindings(&layout_bindings, &reflected).expect("layout_bindings match the shaders");
 
     let pipeline = device.create_graphics_pipeline(&pipeline_desc).expect("create_graphics_pipeline");
     let layout = device.create_descriptor_set_layout(&layout_bindings).expect("create_descriptor_set_layout");
@@ -111,6 +118,7 @@ fn main() {
             initial_layout: None,
         }],
         depth_stencil_attachment: None,
+        subpasses: Vec::new(),
     }).expect("begin_render_pass");
 
     pass.set_pipeline(pipeline.as_ref());
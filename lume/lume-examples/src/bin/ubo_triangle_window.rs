@@ -4,8 +4,8 @@
 #[cfg(feature = "window")]
 use lume_rhi::{
     BufferUsage, ColorAttachment, ColorTargetState, DescriptorSetLayoutBinding, DescriptorType,
-    Device, GraphicsPipelineDescriptor, ImageLayout, LoadOp, PrimitiveTopology,
-    RenderPassDescriptor, ShaderStage, ShaderStages, Swapchain,
+    Device, DeviceExt, GraphicsPipelineDescriptor, ImageLayout, LoadOp, PrimitiveTopology,
+    RenderPassDescriptor, ShaderStage, ShaderStages, Swapchain, SwapchainAcquire,
     VertexAttribute, VertexBinding, VertexInputDescriptor, VertexInputRate, VertexFormat,
 };
 
@@ -16,6 +16,8 @@ use winit::event::WindowEvent;
 #[cfg(feature = "window")]
 use winit::event_loop::{ActiveEventLoop, EventLoop};
 #[cfg(feature = "window")]
+use render_api::{log, LogLevel};
+#[cfg(feature = "window")]
 use std::time::Duration;
 #[cfg(feature = "window")]
 use winit::window::{Window, WindowId};
@@ -25,7 +27,6 @@ struct App {
     window: Option<Window>,
     device: Option<std::sync::Arc<dyn Device>>,
     swapchain: Option<Box<dyn Swapchain>>,
-    swapchain_image_layouts: Option<Vec<ImageLayout>>,
     pipeline: Option<Box<dyn lume_rhi::GraphicsPipeline>>,
     vertex_buffer: Option<Box<dyn lume_rhi::Buffer>>,
     uniform_buffer: Option<Box<dyn lume_rhi::Buffer>>,
@@ -49,7 +50,6 @@ impl App {
             window: None,
             device: None,
             swapchain: None,
-            swapchain_image_layouts: None,
             pipeline: None,
             vertex_buffer: None,
             uniform_buffer: None,
@@ -73,8 +73,8 @@ impl App {
         let sem_acquire = self.sem_acquire.as_ref().unwrap();
         let sem_render = self.sem_render.as_ref().unwrap();
         let frame = match swapchain.acquire_next_image(Some(sem_acquire.as_ref())) {
-            Ok(f) => f,
-            Err(_) => return,
+            Ok(SwapchainAcquire::Frame(f)) => f,
+            Ok(SwapchainAcquire::WouldBlock) | Err(_) => return,
         };
         const FENCE_TIMEOUT_NS: u64 = 10_000_000_000; // 10 s
         let image_index = frame.image_index;
@@ -86,10 +86,8 @@ impl App {
         if let Some(ref mut pending) = self.pending_command_buffers {
             let _ = pending.get_mut(image_index as usize).and_then(|s| s.take());
         }
-        let layouts = self.swapchain_image_layouts.as_mut().unwrap();
-        let old_layout = layouts[image_index as usize];
         let mut encoder = device.create_command_encoder().expect("create_command_encoder");
-        encoder.pipeline_barrier_texture(frame.texture, old_layout, ImageLayout::ColorAttachment);
+        encoder.pipeline_barrier_texture(frame.texture, frame.layout, ImageLayout::ColorAttachment);
         {
             let mut pass = encoder.begin_render_pass(RenderPassDescriptor {
                 label: Some("main_pass"),
@@ -106,6 +104,7 @@ impl App {
                     initial_layout: Some(ImageLayout::ColorAttachment),
                 }],
                 depth_stencil_attachment: None,
+                subpasses: Vec::new(),
             }).expect("begin_render_pass");
             pass.set_pipeline(self.pipeline.as_ref().unwrap().as_ref());
             pass.bind_descriptor_set(0, self.descriptor_set.as_ref().unwrap().as_ref());
@@ -114,7 +113,6 @@ impl App {
             pass.end();
         }
         encoder.pipeline_barrier_texture(frame.texture, ImageLayout::ColorAttachment, ImageLayout::PresentSrc);
-        layouts[image_index as usize] = ImageLayout::PresentSrc;
         drop(frame);
         let cmd = encoder.finish().expect("finish");
         if let Err(e) = device
@@ -127,13 +125,13 @@ impl App {
                 Some(fence.as_ref()),
             )
         {
-            eprintln!("queue submit failed: {} (will retry next frame)", e);
+            log(LogLevel::Warn, "ubo_triangle_window", format_args!("queue submit failed: {} (will retry next frame)", e));
             // Re-skip a few frames and retry; avoids giving up on transient DEVICE_LOST / timing races.
             self.skip_next_render = 4;
             return;
         }
         if let Err(e) = swapchain.present(image_index, Some(sem_render.as_ref())) {
-            eprintln!("present failed: {}", e);
+            log(LogLevel::Warn, "ubo_triangle_window", format_args!("present failed: {}", e));
             return;
         }
         // Keep cmd alive until we wait on this image's fence again (freeing now causes DEVICE_LOST).
@@ -176,7 +174,7 @@ impl App {
         }).expect("create_buffer vertices");
         let vertices: [f32; 9] = [0.0, 0.6, 0.0, -0.6, -0.6, 0.0, 0.6, -0.6, 0.0];
         device
-            .write_buffer(vertex_buffer.as_ref(), 0, bytemuck::bytes_of(&vertices))
+            .write_buffer_slice(vertex_buffer.as_ref(), 0, &vertices)
             .expect("write vertices");
 
         const UBO_SIZE: u64 = 256;
@@ -188,7 +186,7 @@ impl App {
         }).expect("create_buffer ubo");
         let color_data: [f32; 4] = [0.2, 0.8, 0.2, 1.0];
         device
-            .write_buffer(uniform_buffer.as_ref(), 0, bytemuck::bytes_of(&color_data))
+            .write_buffer_typed(uniform_buffer.as_ref(), 0, &color_data)
             .expect("write ubo");
 
         let layout_bindings = vec![DescriptorSetLayoutBinding {
@@ -222,6 +220,7 @@ impl App {
                 }],
             },
             primitive_topology: PrimitiveTopology::TriangleList,
+            primitive_restart: false,
             rasterization: Default::default(),
             color_targets: vec![ColorTargetState {
                 format: swapchain_format,
@@ -231,6 +230,7 @@ impl App {
             }],
             depth_stencil: None,
             layout_bindings: layout_bindings.clone(),
+            subpass: None,
         };
 
         let pipeline = device.create_graphics_pipeline(&pipeline_desc).expect("create_graphics_pipeline");
@@ -254,7 +254,6 @@ impl App {
         std::thread::sleep(Duration::from_millis(80));
         self.device = Some(device);
         self.swapchain = Some(swapchain);
-        self.swapchain_image_layouts = Some(vec![ImageLayout::Undefined; n]);
         self.pipeline = Some(pipeline);
         self.vertex_buffer = Some(vertex_buffer);
         self.uniform_buffer = Some(uniform_buffer);
@@ -302,7 +301,6 @@ impl ApplicationHandler for App {
                 self.vertex_buffer = None;
                 self.pipeline = None;
                 self.swapchain = None;
-                self.swapchain_image_layouts = None;
                 self.device = None;
                 event_loop.exit();
             }
@@ -323,7 +321,6 @@ impl ApplicationHandler for App {
                         );
                         self.pending_command_buffers = Some((0..n).map(|_| None).collect());
                         self.swapchain = Some(new_swapchain);
-                        self.swapchain_image_layouts = Some(vec![ImageLayout::Undefined; n]);
                     }
                 } else {
                     // Defer init to RedrawRequested to avoid 0xC000041d (create surface outside Resized callback).
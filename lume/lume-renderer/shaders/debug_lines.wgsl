@@ -0,0 +1,25 @@
+// Debug lines (e.g. the virtual geometry culling overlay): a line list of world-space endpoints with
+// per-vertex colors, drawn over the scene. See `DebugLinePass` in src/virtual_geom/debug_overlay.rs.
+
+struct LineUniform {
+    // Column-major.
+    view_proj: mat4x4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> line_uniform: LineUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex fn vs_main(@location(0) position: vec3<f32>, @location(1) color: vec4<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = line_uniform.view_proj * vec4<f32>(position, 1.0);
+    out.color = color;
+    return out;
+}
+
+@fragment fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
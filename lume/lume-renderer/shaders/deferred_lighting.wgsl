@@ -0,0 +1,36 @@
+// Deferred lighting subpass: one directional light with a constant ambient term, shading the GBuffer
+// written by the first subpass. See `DeferredLightingPass` in src/deferred/mod.rs.
+
+struct LightUniform {
+    // xyz = direction the light travels (world space), w unused.
+    light_dir: vec4<f32>,
+    // rgb = light color, a = ambient intensity.
+    light_color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> light: LightUniform;
+// Input attachments 0 and 1 of the lighting subpass (compile_wgsl_with_input_attachments); loads at
+// ORIGIN read the current pixel.
+@group(0) @binding(1) var gbuffer_normal: texture_2d<f32>;
+@group(0) @binding(2) var gbuffer_depth: texture_2d<f32>;
+
+const ORIGIN = vec2<i32>(0, 0);
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the viewport.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_lighting() -> @location(0) vec4<f32> {
+    // Nothing was drawn here: keep the cleared background.
+    if textureLoad(gbuffer_depth, ORIGIN, 0).x >= 1.0 {
+        discard;
+    }
+    let n = normalize(textureLoad(gbuffer_normal, ORIGIN, 0).xyz);
+    let l = normalize(-light.light_dir.xyz);
+    let diffuse = max(dot(n, l), 0.0);
+    return vec4<f32>(light.light_color.rgb * (diffuse + light.light_color.a), 1.0);
+}
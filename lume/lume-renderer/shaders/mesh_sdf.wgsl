@@ -0,0 +1,77 @@
+// Mesh SDF: one thread per voxel, brute force over all triangles. Same grid and math as the CPU
+// generator in lume-tools/src/sdf.rs: nearest-triangle distance, sign from the winding number.
+
+struct SdfParams {
+    origin: vec3<f32>,
+    triangle_count: u32,
+    voxel_size: vec3<f32>,
+    _pad0: u32,
+    resolution: vec3<u32>,
+    _pad1: u32,
+}
+
+// Three vec4 per triangle (xyz = vertex, w unused).
+@group(0) @binding(0) var<storage, read> triangles: array<vec4<f32>>;
+@group(0) @binding(1) var<uniform> params: SdfParams;
+@group(0) @binding(2) var sdf: texture_storage_3d<r32float, write>;
+
+const PI: f32 = 3.14159265358979;
+
+// Closest point on triangle abc to p (Ericson, Real-Time Collision Detection 5.1.5).
+fn closest_point_on_triangle(p: vec3<f32>, a: vec3<f32>, b: vec3<f32>, c: vec3<f32>) -> vec3<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = dot(ab, ap);
+    let d2 = dot(ac, ap);
+    if d1 <= 0.0 && d2 <= 0.0 { return a; }
+    let bp = p - b;
+    let d3 = dot(ab, bp);
+    let d4 = dot(ac, bp);
+    if d3 >= 0.0 && d4 <= d3 { return b; }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 { return a + ab * (d1 / (d1 - d3)); }
+    let cp = p - c;
+    let d5 = dot(ab, cp);
+    let d6 = dot(ac, cp);
+    if d6 >= 0.0 && d5 <= d6 { return c; }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 { return a + ac * (d2 / (d2 - d6)); }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    return a + ab * (vb * denom) + ac * (vc * denom);
+}
+
+// Signed solid angle of triangle abc seen from p (Van Oosterom & Strackee).
+fn triangle_solid_angle(p: vec3<f32>, a_in: vec3<f32>, b_in: vec3<f32>, c_in: vec3<f32>) -> f32 {
+    let a = a_in - p;
+    let b = b_in - p;
+    let c = c_in - p;
+    let la = length(a);
+    let lb = length(b);
+    let lc = length(c);
+    let det = dot(a, cross(b, c));
+    let denom = la * lb * lc + dot(a, b) * lc + dot(a, c) * lb + dot(b, c) * la;
+    return 2.0 * atan2(det, denom);
+}
+
+@compute @workgroup_size(4, 4, 4) fn cs_main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    if any(gid >= params.resolution) { return; }
+    let p = params.origin + (vec3<f32>(gid) + 0.5) * params.voxel_size;
+    var dist_sq = 3.4e38;
+    var solid_angle = 0.0;
+    for (var t = 0u; t < params.triangle_count; t++) {
+        let a = triangles[t * 3u].xyz;
+        let b = triangles[t * 3u + 1u].xyz;
+        let c = triangles[t * 3u + 2u].xyz;
+        let d = p - closest_point_on_triangle(p, a, b, c);
+        dist_sq = min(dist_sq, dot(d, d));
+        solid_angle += triangle_solid_angle(p, a, b, c);
+    }
+    var dist = sqrt(dist_sq);
+    if solid_angle / (4.0 * PI) > 0.5 { dist = -dist; }
+    textureStore(sdf, gid, vec4<f32>(dist, 0.0, 0.0, 0.0));
+}
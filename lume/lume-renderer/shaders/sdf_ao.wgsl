@@ -0,0 +1,86 @@
+// SDF ambient occlusion in the lighting subpass: reconstructs each GBuffer pixel's world position from
+// depth and adds the ambient light times its AO factor, sampled from the global SDF. Same math as the
+// CPU reference `sdf_ambient_occlusion` in src/gi/sdf_ao.rs; see `SdfAoPass` there.
+
+struct AoParams {
+    // Column-major inverse of the view-proj the GBuffer was drawn with.
+    inv_view_proj: mat4x4<f32>,
+    // Global SDF grid (`GlobalSdf::grid` and `resolution`).
+    origin: vec3<f32>,
+    radius: f32,
+    voxel_size: vec3<f32>,
+    strength: f32,
+    resolution: vec3<u32>,
+    steps: u32,
+    // Render target size in pixels.
+    viewport: vec2<f32>,
+    _pad: vec2<f32>,
+    // rgb = ambient light to occlude, w unused.
+    ambient: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> params: AoParams;
+@group(0) @binding(1) var<storage, read> global_sdf: array<f32>;
+// Input attachments 0 and 1 of the lighting subpass (compile_wgsl_with_input_attachments); loads at ORIGIN
+// read the current pixel.
+@group(0) @binding(2) var gbuffer_normal: texture_2d<f32>;
+@group(0) @binding(3) var gbuffer_depth: texture_2d<f32>;
+
+const ORIGIN = vec2<i32>(0, 0);
+const TILT: f32 = 0.70710678;
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the viewport.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// `GlobalSdf::sample`: nearest voxel, clamped into the grid.
+fn sample_sdf(p: vec3<f32>) -> f32 {
+    let res = params.resolution;
+    let v = clamp(floor((p - params.origin) / params.voxel_size), vec3<f32>(0.0), vec3<f32>(res) - vec3<f32>(1.0));
+    let i = vec3<u32>(v);
+    return global_sdf[i.x + i.y * res.x + i.z * res.x * res.y];
+}
+
+@fragment
+fn fs_ao(@builtin(position) frag: vec4<f32>) -> @location(0) vec4<f32> {
+    let depth = textureLoad(gbuffer_depth, ORIGIN, 0).x;
+    // Nothing was drawn here: keep the cleared background.
+    if depth >= 1.0 {
+        discard;
+    }
+    if params.steps == 0u || params.radius <= 0.0 {
+        return vec4<f32>(params.ambient.rgb, 0.0);
+    }
+    let uv = frag.xy / params.viewport;
+    let world = params.inv_view_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = world.xyz / world.w;
+    let n = normalize(textureLoad(gbuffer_normal, ORIGIN, 0).xyz);
+    // The normal plus four directions tilted 45 degrees towards the tangents.
+    var up = vec3<f32>(1.0, 0.0, 0.0);
+    if abs(n.y) < 0.9 {
+        up = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let t = normalize(cross(up, n));
+    let b = cross(n, t);
+    var directions = array<vec3<f32>, 5>(n, (n + t) * TILT, (n - t) * TILT, (n + b) * TILT, (n - b) * TILT);
+    var occlusion = 0.0;
+    var total_weight = 0.0;
+    for (var d = 0u; d < 5u; d++) {
+        let dir = directions[d];
+        let facing = dot(dir, n);
+        var weight = 1.0;
+        for (var step = 1u; step <= params.steps; step++) {
+            let h = params.radius * f32(step) / f32(params.steps);
+            let sample = sample_sdf(position + dir * h);
+            occlusion += weight * clamp((h * facing - sample) / h, 0.0, 1.0);
+            total_weight += weight;
+            weight *= 0.5;
+        }
+    }
+    let ao = clamp(1.0 - params.strength * occlusion / total_weight, 0.0, 1.0);
+    // Blended additively onto the lit color.
+    return vec4<f32>(params.ambient.rgb * ao, 0.0);
+}
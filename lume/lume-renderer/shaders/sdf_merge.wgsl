@@ -0,0 +1,65 @@
+// Global SDF merge: one thread per voxel of a region takes the minimum over the placed mesh SDFs whose
+// reach (bounds grown by the truncation) contains the voxel center. Same math as the CPU reference
+// `GlobalSdf::update_region` in src/gi/global_sdf.rs.
+
+struct MergeParams {
+    origin: vec3<f32>,
+    truncation: f32,
+    voxel_size: vec3<f32>,
+    mesh_count: u32,
+    resolution: vec3<u32>,
+    _pad0: u32,
+    // First voxel and voxel count per axis of the region.
+    region_min: vec3<u32>,
+    _pad1: u32,
+    region_size: vec3<u32>,
+    _pad2: u32,
+}
+
+struct MeshInput {
+    origin: vec3<f32>,
+    // First voxel of the mesh in `mesh_data`.
+    data_offset: u32,
+    voxel_size: vec3<f32>,
+    _pad0: u32,
+    resolution: vec3<u32>,
+    _pad1: u32,
+    reach_min: vec3<f32>,
+    _pad2: u32,
+    reach_max: vec3<f32>,
+    _pad3: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> global_sdf: array<f32>;
+@group(0) @binding(1) var<storage, read> meshes: array<MeshInput>;
+@group(0) @binding(2) var<storage, read> mesh_data: array<f32>;
+@group(0) @binding(3) var<uniform> params: MergeParams;
+
+// `MeshSdf::sample`: nearest voxel clamped into the grid, plus the distance to the grid bounds.
+fn sample_mesh(mesh: MeshInput, p: vec3<f32>) -> f32 {
+    let res = mesh.resolution;
+    let v = clamp(floor((p - mesh.origin) / mesh.voxel_size), vec3<f32>(0.0), vec3<f32>(res) - vec3<f32>(1.0));
+    let i = vec3<u32>(v);
+    let bounds_max = mesh.origin + vec3<f32>(res) * mesh.voxel_size;
+    let outside = max(max(mesh.origin - p, p - bounds_max), vec3<f32>(0.0));
+    return mesh_data[mesh.data_offset + i.x + i.y * res.x + i.z * res.x * res.y] + length(outside);
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id >= params.region_size) {
+        return;
+    }
+    let voxel = params.region_min + id;
+    let center = params.origin + (vec3<f32>(voxel) + vec3<f32>(0.5)) * params.voxel_size;
+    var distance = params.truncation;
+    for (var m = 0u; m < params.mesh_count; m++) {
+        let mesh = meshes[m];
+        if any(center < mesh.reach_min) || any(center > mesh.reach_max) {
+            continue;
+        }
+        distance = min(distance, sample_mesh(mesh, center));
+    }
+    let res = params.resolution;
+    global_sdf[voxel.x + voxel.y * res.x + voxel.z * res.x * res.y] = distance;
+}
@@ -0,0 +1,55 @@
+// Virtual geometry cluster culling: one thread per cluster tests its bounding sphere against the view
+// frustum and writes the cluster's indirect draw (instance count 0 when culled), so
+// draw_indexed_indirect consumes the result without a CPU round trip. Same test as the CPU path
+// (`sphere_visible` in src/virtual_geom/gpu_cull.rs).
+
+struct ClusterInput {
+    // xyz center, w radius.
+    sphere: vec4<f32>,
+    index_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    _pad: u32,
+}
+
+struct CullParams {
+    // Normalized frustum planes (left, right, bottom, top, near, far); inside when dot(xyz, p) + w >= 0.
+    planes: array<vec4<f32>, 6>,
+    cluster_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// Layout of VkDrawIndexedIndirectCommand.
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<storage, read> clusters: array<ClusterInput>;
+@group(0) @binding(1) var<uniform> params: CullParams;
+@group(0) @binding(2) var<storage, read_write> draws: array<DrawIndexedIndirect>;
+// CullingStats: total, visible, frustum-culled, cone-culled.
+@group(0) @binding(3) var<storage, read_write> stats: array<atomic<u32>, 4>;
+
+@compute @workgroup_size(64) fn cs_main(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let i = gid.x;
+    if i >= params.cluster_count {
+        return;
+    }
+    let c = clusters[i];
+    var visible = true;
+    for (var p = 0u; p < 6u; p++) {
+        let plane = params.planes[p];
+        if dot(plane.xyz, c.sphere.xyz) + plane.w < -c.sphere.w {
+            visible = false;
+        }
+    }
+    atomicAdd(&stats[0], 1u);
+    atomicAdd(&stats[select(2u, 1u, visible)], 1u);
+    draws[i] = DrawIndexedIndirect(c.index_count, select(0u, 1u, visible), c.first_index, c.vertex_offset, 0u);
+}
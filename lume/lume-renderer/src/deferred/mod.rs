@@ -0,0 +1,284 @@
+//! Deferred shading in one render pass of two subpasses: the GBuffer subpass writes world normals and
+//! depth, and the lighting subpass reads both back at the same pixel as input attachments and writes
+//! the lit color (`SdfAoPass` may add occluded ambient there). The GBuffer never leaves the pass, so
+//! its textures are transient and tiled GPUs keep them on chip. Desktop hosts keep drawing in separate
+//! passes (lume-bridge's mesh path).
+//!
+//! Attachments are ordered `[normal, lit]` then depth; see [`deferred_render_pass`]. Geometry
+//! pipelines are created for [`gbuffer_subpass`] and write the normal at `location = 0`.
+
+use lume_rhi::{
+    Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, ColorAttachment, ColorTargetState,
+    DepthStencilAttachment, DescriptorPool, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    DescriptorType, Device, GraphicsPipeline, GraphicsPipelineDescriptor, LoadOp, PipelineSubpass, PrimitiveTopology,
+    CullMode, RasterizationState, RenderPass, RenderPassDescriptor, ShaderStage, ShaderStages, StoreOp, SubpassDescription,
+    Texture, TextureDescriptor, TextureFormat, TextureUsage, VertexInputDescriptor,
+};
+
+use crate::shader::{compile_wgsl, compile_wgsl_with_input_attachments};
+
+/// GBuffer normal: world-space normal in xyz.
+pub const GBUFFER_NORMAL_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const GBUFFER_DEPTH_FORMAT: TextureFormat = TextureFormat::D32Float;
+
+/// Framebuffer indices of the deferred pass's attachments.
+pub const NORMAL_ATTACHMENT: u32 = 0;
+pub const LIT_ATTACHMENT: u32 = 1;
+pub const DEPTH_ATTACHMENT: u32 = 2;
+
+/// Subpasses of the deferred pass, in recording order.
+pub const GBUFFER_SUBPASS: u32 = 0;
+pub const LIGHTING_SUBPASS: u32 = 1;
+
+/// Bytes of `LightUniform` in shaders/deferred_lighting.wgsl.
+const LIGHT_UNIFORM_SIZE: u64 = 32;
+
+/// (binding, input attachment index) of the lighting shader's GBuffer reads.
+const LIGHTING_INPUTS: &[(u32, u32)] = &[(1, 0), (2, 1)];
+
+/// The two subpasses: geometry into normal and depth, then lighting from both into the lit target.
+pub fn deferred_subpasses() -> Vec<SubpassDescription> {
+    vec![
+        SubpassDescription { color_attachments: vec![NORMAL_ATTACHMENT], input_attachments: vec![], depth_stencil: true },
+        SubpassDescription {
+            color_attachments: vec![LIT_ATTACHMENT],
+            input_attachments: vec![NORMAL_ATTACHMENT, DEPTH_ATTACHMENT],
+            depth_stencil: false,
+        },
+    ]
+}
+
+/// Subpass `index` of the deferred pass with a `lit_format` target, for `GraphicsPipelineDescriptor::subpass`.
+pub(crate) fn deferred_subpass(lit_format: TextureFormat, index: u32) -> PipelineSubpass {
+    PipelineSubpass {
+        color_formats: vec![GBUFFER_NORMAL_FORMAT, lit_format],
+        depth_format: Some(GBUFFER_DEPTH_FORMAT),
+        subpasses: deferred_subpasses(),
+        index,
+    }
+}
+
+/// Where geometry pipelines draw: the GBuffer subpass, whose only color target is
+/// [`GBUFFER_NORMAL_FORMAT`] and which depth-tests against [`GBUFFER_DEPTH_FORMAT`].
+pub fn gbuffer_subpass(lit_format: TextureFormat) -> PipelineSubpass {
+    deferred_subpass(lit_format, GBUFFER_SUBPASS)
+}
+
+/// The deferred pass writing into `lit`. Record geometry, call `next_subpass`, then
+/// [`DeferredLightingPass::record`] and optionally `SdfAoPass`.
+pub fn deferred_render_pass<'a>(lit: ColorAttachment<'a>, gbuffer: &'a GBuffer) -> RenderPassDescriptor<'a> {
+    RenderPassDescriptor {
+        label: Some("lume_deferred"),
+        color_attachments: vec![
+            ColorAttachment {
+                texture: gbuffer.normal.as_ref(),
+                load_op: LoadOp::Clear,
+                store_op: StoreOp::DontCare,
+                clear_value: None,
+                initial_layout: None,
+            },
+            lit,
+        ],
+        depth_stencil_attachment: Some(DepthStencilAttachment {
+            texture: gbuffer.depth.as_ref(),
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::DontCare,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::DontCare,
+            clear_depth: 1.0,
+        }),
+        subpasses: deferred_subpasses(),
+    }
+}
+
+/// Transient GBuffer attachments of one render size.
+pub struct GBuffer {
+    pub normal: Box<dyn Texture>,
+    pub depth: Box<dyn Texture>,
+}
+
+impl GBuffer {
+    pub fn new(device: &dyn Device, size: (u32, u32)) -> Result<Self, String> {
+        let texture = |label, format| {
+            device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size: (size.0, size.1, 1),
+                format,
+                usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::INPUT_ATTACHMENT,
+                transient: true,
+                ..Default::default()
+            })
+        };
+        Ok(Self {
+            normal: texture("lume_gbuffer_normal", GBUFFER_NORMAL_FORMAT)?,
+            depth: texture("lume_gbuffer_depth", GBUFFER_DEPTH_FORMAT)?,
+        })
+    }
+}
+
+/// Directional light of the lighting subpass.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction the light travels, world space.
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    /// Constant ambient term added to the diffuse factor.
+    pub ambient: f32,
+}
+
+fn light_uniform_bytes(light: &DirectionalLight) -> Vec<u8> {
+    let [dx, dy, dz] = light.direction;
+    let [r, g, b] = light.color;
+    [dx, dy, dz, 0.0, r, g, b, light.ambient].iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+/// Light uniform and descriptor set of one frame in flight.
+struct LightingSlot {
+    uniform: Box<dyn Buffer>,
+    set: Box<dyn DescriptorSet>,
+    _pool: Box<dyn DescriptorPool>,
+}
+
+/// Fullscreen lighting subpass: shades every covered GBuffer pixel with one [`DirectionalLight`] and
+/// leaves the lit target's clear color where nothing was drawn.
+pub struct DeferredLightingPass {
+    pipeline: Box<dyn GraphicsPipeline>,
+    set_layout: Box<dyn DescriptorSetLayout>,
+    slots: Vec<LightingSlot>,
+}
+
+impl DeferredLightingPass {
+    /// `lit_format` must match the lit attachment of the render pass passed to [`Self::record`].
+    pub fn new(device: &dyn Device, lit_format: TextureFormat) -> Result<Self, String> {
+        let input = |binding| DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: DescriptorType::InputAttachment,
+            count: 1,
+            stages: ShaderStages::FRAGMENT,
+        };
+        let layout_bindings = vec![
+            DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: DescriptorType::UniformBuffer,
+                count: 1,
+                stages: ShaderStages::FRAGMENT,
+            },
+            input(1),
+            input(2),
+        ];
+        let source = include_str!("../../shaders/deferred_lighting.wgsl");
+        let pipeline = device.create_graphics_pipeline(&GraphicsPipelineDescriptor {
+            label: Some("lume_deferred_lighting"),
+            vertex_shader: ShaderStage {
+                source: compile_wgsl(source, ShaderStages::VERTEX, "vs_fullscreen")?,
+                entry_point: "vs_fullscreen".to_string(),
+            },
+            fragment_shader: Some(ShaderStage {
+                // Bindings 1 and 2 are the lighting subpass's inputs 0 (normal) and 1 (depth).
+                source: compile_wgsl_with_input_attachments(source, "fs_lighting", LIGHTING_INPUTS)?,
+                entry_point: "fs_lighting".to_string(),
+            }),
+            vertex_input: VertexInputDescriptor::default(),
+            primitive_topology: PrimitiveTopology::TriangleList,
+            primitive_restart: false,
+            rasterization: RasterizationState { cull_mode: CullMode::None, ..Default::default() },
+            color_targets: vec![ColorTargetState { format: lit_format, blend: None, load_op: None, store_op: None }],
+            depth_stencil: None,
+            layout_bindings: layout_bindings.clone(),
+            subpass: Some(deferred_subpass(lit_format, LIGHTING_SUBPASS)),
+        })?;
+        let set_layout = device.create_descriptor_set_layout(&layout_bindings)?;
+        Ok(Self { pipeline, set_layout, slots: Vec::new() })
+    }
+
+    /// Record the lighting subpass into `pass`, which must be in its last subpass over `gbuffer`.
+    /// `slot` selects the uniform and set to write, one per frame in flight (e.g. the swapchain image
+    /// index); the caller must have waited for that slot's previous frame.
+    pub fn record(
+        &mut self,
+        device: &dyn Device,
+        pass: &mut dyn RenderPass,
+        gbuffer: &GBuffer,
+        light: &DirectionalLight,
+        slot: usize,
+    ) -> Result<(), String> {
+        while self.slots.len() <= slot {
+            self.slots.push(create_lighting_slot(device, self.set_layout.as_ref())?);
+        }
+        let lighting_slot = &mut self.slots[slot];
+        device.write_buffer(lighting_slot.uniform.as_ref(), 0, &light_uniform_bytes(light))?;
+        // Rewritten every frame: the GBuffer is recreated when the target resizes.
+        lighting_slot.set.write_input_attachment(1, gbuffer.normal.as_ref())?;
+        lighting_slot.set.write_input_attachment(2, gbuffer.depth.as_ref())?;
+        pass.set_pipeline(self.pipeline.as_ref());
+        pass.bind_descriptor_set(0, lighting_slot.set.as_ref());
+        pass.draw(3, 1, 0, 0);
+        Ok(())
+    }
+}
+
+fn create_lighting_slot(device: &dyn Device, layout: &dyn DescriptorSetLayout) -> Result<LightingSlot, String> {
+    let uniform = device.create_buffer(&BufferDescriptor {
+        label: Some("lume_deferred_light_uniform"),
+        size: LIGHT_UNIFORM_SIZE,
+        usage: BufferUsage::UNIFORM,
+        memory: BufferMemoryPreference::HostVisible,
+    })?;
+    let pool = device.create_descriptor_pool(1)?;
+    let mut set = pool.allocate_set(layout)?;
+    set.write_buffer(0, uniform.as_ref(), 0, LIGHT_UNIFORM_SIZE)?;
+    Ok(LightingSlot { uniform, set, _pool: pool })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lume_rhi::test_support::test_device;
+
+    #[test]
+    fn lighting_reads_the_gbuffer_as_input_attachments() {
+        lume_rhi::validate_subpasses(&deferred_subpasses(), 2, true).unwrap();
+        let light = DirectionalLight { direction: [0.0, -1.0, 0.0], color: [1.0, 0.5, 0.25], ambient: 0.03 };
+        let bytes = light_uniform_bytes(&light);
+        assert_eq!(bytes.len() as u64, LIGHT_UNIFORM_SIZE);
+        assert_eq!(&bytes[28..32], &0.03f32.to_le_bytes());
+        let source = include_str!("../../shaders/deferred_lighting.wgsl");
+        let fragment = compile_wgsl_with_input_attachments(source, "fs_lighting", LIGHTING_INPUTS).unwrap();
+        let types: Vec<_> = lume_rhi::reflect::reflect_bindings(&fragment).unwrap().iter().map(|b| b.descriptor_type).collect();
+        let input = DescriptorType::InputAttachment;
+        assert_eq!(types, [DescriptorType::UniformBuffer, input, input]);
+        assert!(!compile_wgsl(source, ShaderStages::VERTEX, "vs_fullscreen").unwrap().is_empty());
+    }
+
+    /// The deferred pass records with its GBuffer subpass left empty. Skipped without a Vulkan device.
+    #[test]
+    fn deferred_pass_records_an_empty_gbuffer() {
+        let Some(device) = test_device("deferred_pass_records_an_empty_gbuffer") else { return };
+        let format = TextureFormat::Rgba8Unorm;
+        let gbuffer = GBuffer::new(device.as_ref(), (16, 16)).unwrap();
+        let lit = device
+            .create_texture(&TextureDescriptor {
+                size: (16, 16, 1),
+                format,
+                usage: TextureUsage::RENDER_ATTACHMENT,
+                ..Default::default()
+            })
+            .unwrap();
+        let mut lighting = DeferredLightingPass::new(device.as_ref(), format).unwrap();
+        let light = DirectionalLight { direction: [0.0, -1.0, 0.0], color: [1.0, 1.0, 1.0], ambient: 0.03 };
+        let lit_attachment = ColorAttachment {
+            texture: lit.as_ref(),
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store,
+            clear_value: None,
+            initial_layout: None,
+        };
+        let mut encoder = device.create_command_encoder().unwrap();
+        let mut pass = encoder.begin_render_pass(deferred_render_pass(lit_attachment, &gbuffer)).unwrap();
+        pass.next_subpass().unwrap();
+        lighting.record(device.as_ref(), pass.as_mut(), &gbuffer, &light, 0).unwrap();
+        pass.end();
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+    }
+}
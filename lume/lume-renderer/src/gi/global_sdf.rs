@@ -0,0 +1,360 @@
+//! Global SDF: the scene distance field merged from placed mesh SDFs (minimum of their distances),
+//! with incremental updates of the regions dynamic objects moved through.
+//!
+//! Distances are truncated at [`GlobalSdf::truncation`], so a mesh only affects voxels within that
+//! distance of its bounds. Re-merging the voxels of a moved mesh's swept bounds, grown by the
+//! truncation, therefore gives exactly the result of a full merge. The merge runs in a compute pass
+//! over each dirty region ([`GlobalSdf::encode_update_dirty`], see `SdfMergePass`); the CPU merge
+//! ([`GlobalSdf::update_region`]) is its reference and keeps [`GlobalSdf::data`] for CPU sampling.
+
+use lume_rhi::{Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, CommandEncoder, Device};
+
+use super::sdf_merge::{SdfMergeDispatch, SdfMergePass};
+use super::{sdf_ambient_occlusion, SdfAoConfig, SdfGrid};
+
+/// Axis-aligned world-space box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: std::array::from_fn(|c| self.min[c].min(other.min[c])),
+            max: std::array::from_fn(|c| self.max[c].max(other.max[c])),
+        }
+    }
+
+    /// Grown by `amount` on every side.
+    pub fn expand(self, amount: f32) -> Aabb {
+        Aabb { min: self.min.map(|v| v - amount), max: self.max.map(|v| v + amount) }
+    }
+
+    pub fn contains(&self, p: [f32; 3]) -> bool {
+        (0..3).all(|c| p[c] >= self.min[c] && p[c] <= self.max[c])
+    }
+
+    /// Distance from `p` to the box (0 inside).
+    pub fn distance(&self, p: [f32; 3]) -> f32 {
+        let d: [f32; 3] = std::array::from_fn(|c| (self.min[c] - p[c]).max(p[c] - self.max[c]).max(0.0));
+        (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt()
+    }
+}
+
+/// World-space box covered by a `resolution` grid of voxels.
+fn grid_bounds(resolution: (u32, u32, u32), grid: SdfGrid) -> Aabb {
+    let (rx, ry, rz) = resolution;
+    let extent = [rx, ry, rz];
+    Aabb {
+        min: grid.origin,
+        max: std::array::from_fn(|c| grid.origin[c] + extent[c] as f32 * grid.voxel_size[c]),
+    }
+}
+
+/// Data index of the voxel containing `p`, clamped into the grid.
+fn nearest_voxel(resolution: (u32, u32, u32), grid: SdfGrid, p: [f32; 3]) -> usize {
+    let (rx, ry, rz) = resolution;
+    let res = [rx, ry, rz];
+    let voxel: [usize; 3] = std::array::from_fn(|c| {
+        let v = ((p[c] - grid.origin[c]) / grid.voxel_size[c]).floor();
+        v.clamp(0.0, res[c].saturating_sub(1) as f32) as usize
+    });
+    voxel[0] + voxel[1] * rx as usize + voxel[2] * rx as usize * ry as usize
+}
+
+/// Low-resolution SDF of one mesh placed in the scene. Used for ray marching.
+#[derive(Clone, Debug)]
+pub struct MeshSdf {
+    resolution: (u32, u32, u32),
+    grid: SdfGrid,
+    data: Vec<f32>,
+}
+
+impl MeshSdf {
+    /// `grid` is the world placement of the voxels: the baked grid (e.g. `lume_tools::MeshSdfOutput::origin`
+    /// / `voxel_size`) translated to the object's position. `data` holds the signed distances, x fastest,
+    /// then y, then z (the `lume_tools::MeshSdfOutput::data` layout), one per voxel of `resolution`.
+    pub fn new(resolution: (u32, u32, u32), grid: SdfGrid, data: Vec<f32>) -> Result<Self, String> {
+        let (rx, ry, rz) = resolution;
+        if rx == 0 || ry == 0 || rz == 0 {
+            return Err(format!("mesh sdf: empty resolution {:?}", resolution));
+        }
+        let voxels = rx as usize * ry as usize * rz as usize;
+        if data.len() != voxels {
+            return Err(format!("mesh sdf: {} distances for a {:?} grid ({} voxels)", data.len(), resolution, voxels));
+        }
+        Ok(Self { resolution, grid, data })
+    }
+
+    pub fn resolution(&self) -> (u32, u32, u32) {
+        self.resolution
+    }
+
+    pub fn grid(&self) -> SdfGrid {
+        self.grid
+    }
+
+    /// Move the object: only the grid moves, the distances stay.
+    pub fn set_grid(&mut self, grid: SdfGrid) {
+        self.grid = grid;
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// World-space box covered by the voxels.
+    pub fn bounds(&self) -> Aabb {
+        grid_bounds(self.resolution, self.grid)
+    }
+
+    /// Distance at a world position: the nearest voxel to `p` clamped into the grid, plus the distance
+    /// from `p` to the grid bounds when it lies outside.
+    pub fn sample(&self, p: [f32; 3]) -> f32 {
+        self.data[nearest_voxel(self.resolution, self.grid, p)] + self.bounds().distance(p)
+    }
+}
+
+/// Combined scene SDF built from multiple MeshSdf at runtime.
+pub struct GlobalSdf {
+    resolution: (u32, u32, u32),
+    grid: SdfGrid,
+    truncation: f32,
+    /// Same layout as [`MeshSdf::data`]; starts fully at `truncation` (empty scene).
+    data: Vec<f32>,
+    /// Regions to re-merge at the next [`Self::update_dirty`].
+    dirty: Vec<Aabb>,
+}
+
+impl GlobalSdf {
+    /// Unit voxels centered on the world origin, truncated at 4 voxels.
+    pub fn new(resolution: (u32, u32, u32)) -> Result<Self, String> {
+        let (rx, ry, rz) = resolution;
+        let origin = [rx, ry, rz].map(|r| -(r as f32) * 0.5);
+        Self::with_grid(resolution, SdfGrid { origin, voxel_size: [1.0; 3] }, 4.0)
+    }
+
+    /// Errors when the voxel count of `resolution` overflows `usize`.
+    pub fn with_grid(
+        resolution: (u32, u32, u32),
+        grid: SdfGrid,
+        truncation: f32,
+    ) -> Result<Self, String> {
+        let (rx, ry, rz) = resolution;
+        let voxels = (rx as usize)
+            .checked_mul(ry as usize)
+            .and_then(|n| n.checked_mul(rz as usize))
+            .ok_or_else(|| format!("global sdf: {:?} voxels overflow usize", resolution))?;
+        Ok(Self {
+            resolution,
+            grid,
+            truncation,
+            data: vec![truncation; voxels],
+            dirty: Vec::new(),
+        })
+    }
+
+    pub fn resolution(&self) -> (u32, u32, u32) {
+        self.resolution
+    }
+
+    pub fn grid(&self) -> SdfGrid {
+        self.grid
+    }
+
+    /// Largest stored distance; also how far a mesh's influence reaches beyond its bounds.
+    pub fn truncation(&self) -> f32 {
+        self.truncation
+    }
+
+    /// Voxel distances, x fastest, then y, then z (for upload to a 3D R32Float texture).
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// World-space box covered by the voxels.
+    pub fn bounds(&self) -> Aabb {
+        grid_bounds(self.resolution, self.grid)
+    }
+
+    /// Distance at a world position (nearest voxel, clamped into the grid).
+    pub fn sample(&self, p: [f32; 3]) -> f32 {
+        self.data[nearest_voxel(self.resolution, self.grid, p)]
+    }
+
+    /// AO factor at a surface point from this field (see [`sdf_ambient_occlusion`]). Keep
+    /// `config.radius` below the truncation, since farther occluders are clamped away.
+    pub fn ambient_occlusion(&self, position: [f32; 3], normal: [f32; 3], config: &SdfAoConfig) -> f32 {
+        sdf_ambient_occlusion(|p| self.sample(p), position, normal, config)
+    }
+
+    /// Rebuild every voxel from `mesh_sdfs` (the scene's complete set) and drop pending dirty regions.
+    pub fn merge_mesh_sdfs(&mut self, mesh_sdfs: &[MeshSdf]) {
+        self.dirty.clear();
+        self.update_region(self.bounds(), mesh_sdfs);
+    }
+
+    /// First and last voxel (inclusive) whose centers (origin + (i + 0.5) * size) lie in `region`;
+    /// `None` when there are none.
+    pub(super) fn region_voxels(&self, region: Aabb) -> Option<([usize; 3], [usize; 3])> {
+        let (rx, ry, rz) = self.resolution;
+        let res = [rx, ry, rz];
+        let mut lo = [0usize; 3];
+        let mut hi = [0usize; 3];
+        for c in 0..3 {
+            let to_index = |v: f32| (v - self.grid.origin[c]) / self.grid.voxel_size[c] - 0.5;
+            let first = to_index(region.min[c]).ceil().max(0.0);
+            let last = to_index(region.max[c]).floor().min(res[c] as f32 - 1.0);
+            if res[c] == 0 || first > last {
+                return None;
+            }
+            lo[c] = first as usize;
+            hi[c] = last as usize;
+        }
+        Some((lo, hi))
+    }
+
+    /// Re-merge only the voxels whose centers lie in `region` from `mesh_sdfs` (the scene's complete
+    /// set, at their current placement) on the CPU; returns how many voxels were rewritten. The CPU
+    /// reference of `SdfMergePass`.
+    pub fn update_region(&mut self, region: Aabb, mesh_sdfs: &[MeshSdf]) -> usize {
+        let (rx, ry, _) = self.resolution;
+        let Some((lo, hi)) = self.region_voxels(region) else {
+            return 0;
+        };
+        let reach: Vec<(Aabb, &MeshSdf)> =
+            mesh_sdfs.iter().map(|m| (m.bounds().expand(self.truncation), m)).collect();
+        let mut written = 0;
+        for z in lo[2]..=hi[2] {
+            for y in lo[1]..=hi[1] {
+                for x in lo[0]..=hi[0] {
+                    let center: [f32; 3] = std::array::from_fn(|c| {
+                        self.grid.origin[c] + ([x, y, z][c] as f32 + 0.5) * self.grid.voxel_size[c]
+                    });
+                    let distance = reach
+                        .iter()
+                        .filter(|(bounds, _)| bounds.contains(center))
+                        .map(|(_, mesh)| mesh.sample(center))
+                        .fold(self.truncation, f32::min);
+                    self.data[x + y * rx as usize + z * rx as usize * ry as usize] = distance;
+                    written += 1;
+                }
+            }
+        }
+        written
+    }
+
+    /// Record that a mesh moved from `before` to `after` (its [`MeshSdf::bounds`]); the swept region,
+    /// grown by the truncation, is re-merged at the next [`Self::update_dirty`]. Also use it for meshes
+    /// added (`before == after`) or removed.
+    pub fn mark_moved(&mut self, before: Aabb, after: Aabb) {
+        self.dirty.push(before.union(after).expand(self.truncation));
+    }
+
+    /// Re-merge every region recorded by [`Self::mark_moved`] since the last update on the CPU; returns
+    /// how many voxels were rewritten (voxels in overlapping regions count once per region).
+    pub fn update_dirty(&mut self, mesh_sdfs: &[MeshSdf]) -> usize {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty.into_iter().map(|region| self.update_region(region, mesh_sdfs)).sum()
+    }
+
+    /// Storage buffer holding the current voxels ([`Self::data`] layout), for
+    /// [`Self::encode_update_dirty`] and shaders that sample the field.
+    pub fn create_voxel_buffer(&self, device: &dyn Device) -> Result<Box<dyn Buffer>, String> {
+        let bytes: Vec<u8> = self.data.iter().flat_map(|d| d.to_le_bytes()).collect();
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("lume_global_sdf"),
+            size: bytes.len() as u64,
+            usage: BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+            memory: BufferMemoryPreference::HostVisible,
+        })?;
+        device.write_buffer(buffer.as_ref(), 0, &bytes)?;
+        Ok(buffer)
+    }
+
+    /// GPU [`Self::update_dirty`]: record one merge dispatch per region recorded by [`Self::mark_moved`]
+    /// into `voxels` (from [`Self::create_voxel_buffer`]) and clear them. [`Self::data`] is not
+    /// updated. `None` when nothing is dirty; otherwise keep the dispatch alive until the command
+    /// buffer has completed.
+    pub fn encode_update_dirty(
+        &mut self,
+        encoder: &mut dyn CommandEncoder,
+        device: &dyn Device,
+        pass: &SdfMergePass,
+        mesh_sdfs: &[MeshSdf],
+        voxels: &dyn Buffer,
+    ) -> Result<Option<SdfMergeDispatch>, String> {
+        let regions: Vec<_> = self.dirty.iter().filter_map(|&region| self.region_voxels(region)).collect();
+        let dispatch = pass.encode(encoder, device, self, &regions, mesh_sdfs, voxels)?;
+        self.dirty.clear();
+        Ok(dispatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube_at(x: f32) -> MeshSdf {
+        #[rustfmt::skip]
+        let positions = [
+            -0.5, -0.5, -0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5, 0.5, -0.5,
+            -0.5, -0.5, 0.5, 0.5, -0.5, 0.5, 0.5, 0.5, 0.5, -0.5, 0.5, 0.5,
+        ];
+        let indices = [
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, 3, 6, 2, 3, 7, 6, 0, 4, 7, 0, 7, 3, 1, 2, 6, 1, 6, 5,
+        ];
+        let baked = lume_tools::generate_mesh_sdf(&positions, &indices, 8).unwrap();
+        let grid = SdfGrid { origin: [baked.origin[0] + x, baked.origin[1], baked.origin[2]], voxel_size: baked.voxel_size };
+        MeshSdf::new(baked.resolution, grid, baked.data).unwrap()
+    }
+
+    #[test]
+    fn mesh_sdf_rejects_mismatched_data() {
+        let grid = SdfGrid { origin: [0.0; 3], voxel_size: [1.0; 3] };
+        assert!(MeshSdf::new((2, 2, 2), grid, vec![0.0; 7]).is_err());
+        assert!(MeshSdf::new((0, 2, 2), grid, Vec::new()).is_err());
+        assert_eq!(MeshSdf::new((2, 2, 2), grid, vec![0.5; 8]).unwrap().sample([1.0; 3]), 0.5);
+    }
+
+    #[test]
+    fn oversized_resolution_is_an_error() {
+        let grid = SdfGrid { origin: [0.0; 3], voxel_size: [1.0; 3] };
+        assert!(GlobalSdf::with_grid((u32::MAX, u32::MAX, u32::MAX), grid, 1.0).is_err());
+        assert_eq!(GlobalSdf::with_grid((2, 3, 4), grid, 1.0).unwrap().data.len(), 24);
+    }
+
+    #[test]
+    fn moving_one_mesh_only_rewrites_its_swept_region() {
+        let grid = SdfGrid { origin: [-4.0; 3], voxel_size: [0.25; 3] };
+        let mut global = GlobalSdf::with_grid((32, 32, 32), grid, 0.5).unwrap();
+        let mut meshes = vec![cube_at(-2.0), cube_at(2.0)];
+        global.merge_mesh_sdfs(&meshes);
+        assert!(global.sample([-2.0, 0.0, 0.0]) < 0.0 && global.sample([2.0, 0.0, 0.0]) < 0.0);
+        assert_eq!(global.sample([0.0, 0.0, 0.0]), 0.5);
+        let before = global.data().to_vec();
+
+        let old_bounds = meshes[1].bounds();
+        meshes[1] = cube_at(1.5);
+        global.mark_moved(old_bounds, meshes[1].bounds());
+        let written = global.update_dirty(&meshes);
+        assert!(written > 0 && written < global.data().len() / 4, "{}", written);
+
+        let mut full = GlobalSdf::with_grid((32, 32, 32), grid, 0.5).unwrap();
+        full.merge_mesh_sdfs(&meshes);
+        assert_eq!(global.data(), full.data());
+        // Voxels outside the swept bounds (grown by the truncation) keep their values.
+        let swept = old_bounds.union(meshes[1].bounds()).expand(0.5);
+        for (i, (&now, &then)) in global.data().iter().zip(&before).enumerate() {
+            let voxel = [i % 32, i / 32 % 32, i / (32 * 32)];
+            let center = voxel.map(|v| -4.0 + (v as f32 + 0.5) * 0.25);
+            if !swept.contains(center) {
+                assert_eq!(now, then, "voxel {:?}", voxel);
+            }
+        }
+        assert!(global.sample([1.5, 0.0, 0.0]) < 0.0);
+        assert!(global.sample([2.25, 0.0, 0.0]) > 0.0);
+    }
+}
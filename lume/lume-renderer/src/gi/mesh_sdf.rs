@@ -0,0 +1,246 @@
+//! GPU mesh SDF generation: one compute thread per voxel writes the signed distance to the nearest
+//! triangle into a 3D R32Float storage texture. Matches `lume_tools::generate_mesh_sdf` (same grid,
+//! distance and winding-number sign), so meshes can be (re)baked at runtime for dynamic scenes.
+
+use lume_rhi::{
+    Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, CommandEncoder, ComputePipeline,
+    ComputePipelineDescriptor, DescriptorPool, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    DescriptorType, Device, ImageLayout, ShaderStages, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsage,
+};
+
+use crate::shader::compile_wgsl;
+
+/// Must match `@workgroup_size` in shaders/mesh_sdf.wgsl.
+const WORKGROUP_SIZE: u32 = 4;
+
+/// Layout of `SdfParams` in shaders/mesh_sdf.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SdfParams {
+    origin: [f32; 3],
+    triangle_count: u32,
+    voxel_size: [f32; 3],
+    _pad0: u32,
+    resolution: [u32; 3],
+    _pad1: u32,
+}
+
+impl SdfParams {
+    fn to_bytes(self) -> Vec<u8> {
+        let words: [u32; 12] = [
+            self.origin[0].to_bits(),
+            self.origin[1].to_bits(),
+            self.origin[2].to_bits(),
+            self.triangle_count,
+            self.voxel_size[0].to_bits(),
+            self.voxel_size[1].to_bits(),
+            self.voxel_size[2].to_bits(),
+            self._pad0,
+            self.resolution[0],
+            self.resolution[1],
+            self.resolution[2],
+            self._pad1,
+        ];
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+}
+
+/// World-space placement of an SDF grid: voxel (i, j, k) is centered at `origin + (ijk + 0.5) * voxel_size`.
+/// Use `lume_tools::mesh_sdf_grid` to get the same grid as the CPU generator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SdfGrid {
+    pub origin: [f32; 3],
+    pub voxel_size: [f32; 3],
+}
+
+/// Buffers and descriptor set used by one [`MeshSdfGenerator::encode`]; keep alive until the
+/// command buffer has completed.
+pub struct MeshSdfDispatch {
+    _triangles: Box<dyn Buffer>,
+    _params: Box<dyn Buffer>,
+    _set: Box<dyn DescriptorSet>,
+    _pool: Box<dyn DescriptorPool>,
+}
+
+pub struct MeshSdfGenerator {
+    pipeline: Box<dyn ComputePipeline>,
+    set_layout: Box<dyn DescriptorSetLayout>,
+}
+
+impl MeshSdfGenerator {
+    pub fn new(device: &dyn Device) -> Result<Self, String> {
+        let layout_bindings = vec![
+            DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: DescriptorType::StorageBuffer,
+                count: 1,
+                stages: ShaderStages::COMPUTE,
+            },
+            DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: DescriptorType::UniformBuffer,
+                count: 1,
+                stages: ShaderStages::COMPUTE,
+            },
+            DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: DescriptorType::StorageImage,
+                count: 1,
+                stages: ShaderStages::COMPUTE,
+            },
+        ];
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("lume_mesh_sdf"),
+            shader_source: compile_wgsl(include_str!("../../shaders/mesh_sdf.wgsl"), ShaderStages::COMPUTE, "cs_main")?,
+            entry_point: "cs_main".to_string(),
+            layout_bindings: layout_bindings.clone(),
+        })?;
+        let set_layout = device.create_descriptor_set_layout(&layout_bindings)?;
+        Ok(Self { pipeline, set_layout })
+    }
+
+    /// 3D R32Float texture of `resolution`³ voxels usable as the generator's output and for sampling.
+    pub fn create_output_texture(device: &dyn Device, resolution: u32) -> Result<Box<dyn Texture>, String> {
+        device.create_texture(&TextureDescriptor {
+            label: Some("lume_mesh_sdf"),
+            size: (resolution, resolution, resolution),
+            format: TextureFormat::R32Float,
+            usage: TextureUsage::STORAGE_BINDING | TextureUsage::TEXTURE_BINDING | TextureUsage::COPY_SRC,
+            dimension: TextureDimension::D3,
+            mip_level_count: 1,
+            transient: false,
+        })
+    }
+
+    /// Record the SDF bake of a mesh (positions as xyz triples + triangle indices) into `output`, which
+    /// must come from [`Self::create_output_texture`]. Every voxel is overwritten, so `output` is
+    /// transitioned from Undefined; it is left in [`ImageLayout::ShaderReadOnly`].
+    pub fn encode(
+        &self,
+        encoder: &mut dyn CommandEncoder,
+        device: &dyn Device,
+        positions: &[f32],
+        indices: &[u32],
+        grid: SdfGrid,
+        output: &dyn Texture,
+    ) -> Result<MeshSdfDispatch, String> {
+        let (rx, ry, rz) = output.size();
+        let mut triangle_data = Vec::with_capacity(indices.len() / 3 * 48);
+        for &i in &indices[..indices.len() / 3 * 3] {
+            let p = positions
+                .get(i as usize * 3..i as usize * 3 + 3)
+                .ok_or_else(|| format!("mesh sdf: index {} out of range", i))?;
+            for v in [p[0], p[1], p[2], 0.0] {
+                triangle_data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        if triangle_data.is_empty() {
+            return Err("mesh sdf: mesh has no triangles".to_string());
+        }
+        let triangles = device.create_buffer(&BufferDescriptor {
+            label: Some("lume_mesh_sdf_triangles"),
+            size: triangle_data.len() as u64,
+            usage: BufferUsage::STORAGE,
+            memory: BufferMemoryPreference::HostVisible,
+        })?;
+        device.write_buffer(triangles.as_ref(), 0, &triangle_data)?;
+        let params_data = SdfParams {
+            origin: grid.origin,
+            triangle_count: (indices.len() / 3) as u32,
+            voxel_size: grid.voxel_size,
+            _pad0: 0,
+            resolution: [rx, ry, rz],
+            _pad1: 0,
+        }
+        .to_bytes();
+        let params = device.create_buffer(&BufferDescriptor {
+            label: Some("lume_mesh_sdf_params"),
+            size: params_data.len() as u64,
+            usage: BufferUsage::UNIFORM,
+            memory: BufferMemoryPreference::HostVisible,
+        })?;
+        device.write_buffer(params.as_ref(), 0, &params_data)?;
+
+        let pool = device.create_descriptor_pool(1)?;
+        let mut set = pool.allocate_set(self.set_layout.as_ref())?;
+        set.write_buffer(0, triangles.as_ref(), 0, triangle_data.len() as u64)?;
+        set.write_buffer(1, params.as_ref(), 0, params_data.len() as u64)?;
+        set.write_texture(2, output)?;
+
+        encoder.pipeline_barrier_texture(output, ImageLayout::Undefined, ImageLayout::General);
+        {
+            let mut pass = encoder.begin_compute_pass();
+            pass.set_pipeline(self.pipeline.as_ref());
+            pass.bind_descriptor_set(0, set.as_ref());
+            pass.dispatch(rx.div_ceil(WORKGROUP_SIZE), ry.div_ceil(WORKGROUP_SIZE), rz.div_ceil(WORKGROUP_SIZE))?;
+        }
+        encoder.pipeline_barrier_texture(output, ImageLayout::General, ImageLayout::ShaderReadOnly);
+        Ok(MeshSdfDispatch {
+            _triangles: triangles,
+            _params: params,
+            _set: set,
+            _pool: pool,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lume_rhi::test_support::test_device;
+
+    #[test]
+    fn params_match_wgsl_layout() {
+        let bytes = SdfParams {
+            origin: [1.0, 2.0, 3.0],
+            triangle_count: 7,
+            voxel_size: [0.5; 3],
+            _pad0: 0,
+            resolution: [8, 8, 8],
+            _pad1: 0,
+        }
+        .to_bytes();
+        assert_eq!(bytes.len(), 48);
+        assert_eq!(&bytes[12..16], &7u32.to_le_bytes());
+        assert_eq!(&bytes[32..36], &8u32.to_le_bytes());
+    }
+
+    #[test]
+    fn mesh_sdf_shader_compiles_to_spirv() {
+        let spirv = compile_wgsl(include_str!("../../shaders/mesh_sdf.wgsl"), ShaderStages::COMPUTE, "cs_main").unwrap();
+        assert!(!spirv.is_empty());
+    }
+
+    /// Bakes a cube on the GPU and compares with the CPU generator. Skipped without a Vulkan device.
+    #[test]
+    fn gpu_sdf_matches_cpu() {
+        let Some(device) = test_device("gpu_sdf_matches_cpu") else { return };
+        let positions = [
+            -0.5, -0.5, -0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5, 0.5, -0.5, //
+            -0.5, -0.5, 0.5, 0.5, -0.5, 0.5, 0.5, 0.5, 0.5, -0.5, 0.5, 0.5,
+        ];
+        let indices = [
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, 3, 6, 2, 3, 7, 6, 0, 4, 7, 0, 7, 3, 1, 2, 6, 1, 6, 5,
+        ];
+        let res = 8u32;
+        let cpu = lume_tools::generate_mesh_sdf(&positions, &indices, res).unwrap();
+
+        let generator = MeshSdfGenerator::new(device.as_ref()).unwrap();
+        let output = MeshSdfGenerator::create_output_texture(device.as_ref(), res).unwrap();
+        let grid = SdfGrid { origin: cpu.origin, voxel_size: cpu.voxel_size };
+        let mut encoder = device.create_command_encoder().unwrap();
+        let _dispatch = generator
+            .encode(encoder.as_mut(), device.as_ref(), &positions, &indices, grid, output.as_ref())
+            .unwrap();
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+
+        let bytes = device.read_texture(output.as_ref(), 0, ImageLayout::ShaderReadOnly).unwrap();
+        assert_eq!(bytes.len(), (res * res * res * 4) as usize);
+        for (i, (chunk, &expected)) in bytes.chunks_exact(4).zip(&cpu.data).enumerate() {
+            let got = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            assert!((got - expected).abs() < 1e-3, "voxel {}: gpu {} vs cpu {}", i, got, expected);
+        }
+    }
+}
@@ -1,31 +1,18 @@
 //! Global Illumination: Lumen-like SDF ray marching, surface cache, and temporal accumulation.
 //! Implementation uses only Lume RHI (Vulkan / Metal).
 
-use lume_rhi::{Device, Texture};
+use lume_rhi::{Buffer, CommandEncoder, Device, Texture};
 use std::sync::Arc;
 
-/// Low-resolution SDF for one mesh or the combined scene. Used for ray marching.
-pub struct MeshSdf {
-    /// Resolution (e.g. 64^3). Data format and layout TBD (3D texture or buffer).
-    pub resolution: (u32, u32, u32),
-}
-
-/// Combined scene SDF built from multiple MeshSdf at runtime.
-pub struct GlobalSdf {
-    #[allow(dead_code)]
-    resolution: (u32, u32, u32),
-}
+mod global_sdf;
+mod mesh_sdf;
+mod sdf_ao;
+mod sdf_merge;
 
-impl GlobalSdf {
-    pub fn new(resolution: (u32, u32, u32)) -> Self {
-        Self { resolution }
-    }
-
-    /// Merge mesh SDFs into the global SDF (TODO: GPU pass).
-    pub fn merge_mesh_sdfs(&mut self, _mesh_sdfs: &[MeshSdf]) {
-        // TODO: compute pass to combine SDFs
-    }
-}
+pub use global_sdf::{Aabb, GlobalSdf, MeshSdf};
+pub use sdf_ao::{sdf_ambient_occlusion, SdfAoConfig, SdfAoFrame, SdfAoPass};
+pub use mesh_sdf::{MeshSdfDispatch, MeshSdfGenerator, SdfGrid};
+pub use sdf_merge::{SdfMergeDispatch, SdfMergePass};
 
 /// Surface properties (BaseColor, Normal, Emissive) cached in an atlas for hit lookup.
 pub struct SurfaceCache {
@@ -53,9 +40,10 @@ pub enum TraceRange {
 
 /// One frame of GI: trace rays (1 spp), then temporal accumulate.
 pub struct GiSystem {
-    #[allow(dead_code)]
     device: Arc<dyn Device>,
     global_sdf: GlobalSdf,
+    /// Merge pass and GPU voxels of `global_sdf`, created by the first [`Self::encode_sdf_update`].
+    global_sdf_gpu: Option<(SdfMergePass, Box<dyn Buffer>)>,
     surface_cache: SurfaceCache,
     /// Previous frame's radiance for temporal accumulation (TODO: texture/buffer).
     _temporal_history: Option<Box<dyn Texture>>,
@@ -65,7 +53,8 @@ impl GiSystem {
     pub fn new(device: Arc<dyn Device>) -> Self {
         Self {
             device: device.clone(),
-            global_sdf: GlobalSdf::new((64, 64, 64)),
+            global_sdf: GlobalSdf::new((64, 64, 64)).expect("64^3 voxels fit in usize"),
+            global_sdf_gpu: None,
             surface_cache: SurfaceCache::new(&device),
             _temporal_history: None,
         }
@@ -83,6 +72,28 @@ impl GiSystem {
         Ok(())
     }
 
+    /// Re-merge the global SDF's dirty regions ([`GlobalSdf::mark_moved`]) on the GPU; see
+    /// [`GlobalSdf::encode_update_dirty`].
+    pub fn encode_sdf_update(
+        &mut self,
+        encoder: &mut dyn CommandEncoder,
+        mesh_sdfs: &[MeshSdf],
+    ) -> Result<Option<SdfMergeDispatch>, String> {
+        if self.global_sdf_gpu.is_none() {
+            let pass = SdfMergePass::new(self.device.as_ref())?;
+            let voxels = self.global_sdf.create_voxel_buffer(self.device.as_ref())?;
+            self.global_sdf_gpu = Some((pass, voxels));
+        }
+        let (pass, voxels) = self.global_sdf_gpu.as_ref().expect("created above");
+        self.global_sdf.encode_update_dirty(encoder, self.device.as_ref(), pass, mesh_sdfs, voxels.as_ref())
+    }
+
+    /// GPU voxels of the global SDF ([`GlobalSdf::data`] layout); `None` before the first
+    /// [`Self::encode_sdf_update`].
+    pub fn global_sdf_buffer(&self) -> Option<&dyn Buffer> {
+        self.global_sdf_gpu.as_ref().map(|(_, voxels)| voxels.as_ref())
+    }
+
     pub fn global_sdf_mut(&mut self) -> &mut GlobalSdf {
         &mut self.global_sdf
     }
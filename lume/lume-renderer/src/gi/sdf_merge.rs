@@ -0,0 +1,274 @@
+//! GPU global SDF merge: one compute thread per voxel of a dirty region takes the minimum over the
+//! placed mesh SDFs reaching it, like the CPU reference `GlobalSdf::update_region`. Driven by
+//! `GlobalSdf::encode_update_dirty`, one dispatch per region.
+
+use lume_rhi::{
+    Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, CommandEncoder, ComputePipeline,
+    ComputePipelineDescriptor, DescriptorPool, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    DescriptorType, Device, ShaderStages,
+};
+
+use super::global_sdf::{GlobalSdf, MeshSdf};
+use crate::shader::compile_wgsl;
+
+/// Must match `@workgroup_size` in shaders/sdf_merge.wgsl.
+const WORKGROUP_SIZE: u32 = 4;
+
+/// Bytes of `MergeParams` in shaders/sdf_merge.wgsl.
+const PARAMS_SIZE: usize = 80;
+
+/// Bytes of `MeshInput` in shaders/sdf_merge.wgsl.
+const MESH_INPUT_SIZE: usize = 80;
+
+fn params_bytes(global: &GlobalSdf, mesh_count: u32, first: [usize; 3], last: [usize; 3]) -> Vec<u8> {
+    let grid = global.grid();
+    let (rx, ry, rz) = global.resolution();
+    let f = |v: f32| v.to_bits();
+    let words = [
+        f(grid.origin[0]),
+        f(grid.origin[1]),
+        f(grid.origin[2]),
+        f(global.truncation()),
+        f(grid.voxel_size[0]),
+        f(grid.voxel_size[1]),
+        f(grid.voxel_size[2]),
+        mesh_count,
+        rx,
+        ry,
+        rz,
+        0,
+        first[0] as u32,
+        first[1] as u32,
+        first[2] as u32,
+        0,
+        (last[0] - first[0] + 1) as u32,
+        (last[1] - first[1] + 1) as u32,
+        (last[2] - first[2] + 1) as u32,
+        0,
+    ];
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+fn mesh_input_bytes(mesh: &MeshSdf, data_offset: u32, truncation: f32) -> [u8; MESH_INPUT_SIZE] {
+    let grid = mesh.grid();
+    let (rx, ry, rz) = mesh.resolution();
+    let reach = mesh.bounds().expand(truncation);
+    let f = |v: f32| v.to_bits();
+    let words = [
+        f(grid.origin[0]),
+        f(grid.origin[1]),
+        f(grid.origin[2]),
+        data_offset,
+        f(grid.voxel_size[0]),
+        f(grid.voxel_size[1]),
+        f(grid.voxel_size[2]),
+        0,
+        rx,
+        ry,
+        rz,
+        0,
+        f(reach.min[0]),
+        f(reach.min[1]),
+        f(reach.min[2]),
+        0,
+        f(reach.max[0]),
+        f(reach.max[1]),
+        f(reach.max[2]),
+        0,
+    ];
+    let mut out = [0u8; MESH_INPUT_SIZE];
+    for (chunk, w) in out.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&w.to_le_bytes());
+    }
+    out
+}
+
+/// Buffers and descriptor sets used by one `GlobalSdf::encode_update_dirty`; keep alive until the
+/// command buffer has completed.
+pub struct SdfMergeDispatch {
+    _meshes: Box<dyn Buffer>,
+    _mesh_data: Box<dyn Buffer>,
+    _params: Vec<Box<dyn Buffer>>,
+    _sets: Vec<Box<dyn DescriptorSet>>,
+    _pool: Box<dyn DescriptorPool>,
+}
+
+pub struct SdfMergePass {
+    pipeline: Box<dyn ComputePipeline>,
+    set_layout: Box<dyn DescriptorSetLayout>,
+}
+
+impl SdfMergePass {
+    pub fn new(device: &dyn Device) -> Result<Self, String> {
+        let binding = |binding: u32, descriptor_type: DescriptorType| DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type,
+            count: 1,
+            stages: ShaderStages::COMPUTE,
+        };
+        let layout_bindings = vec![
+            binding(0, DescriptorType::StorageBuffer),
+            binding(1, DescriptorType::StorageBuffer),
+            binding(2, DescriptorType::StorageBuffer),
+            binding(3, DescriptorType::UniformBuffer),
+        ];
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("lume_sdf_merge"),
+            shader_source: compile_wgsl(include_str!("../../shaders/sdf_merge.wgsl"), ShaderStages::COMPUTE, "cs_main")?,
+            entry_point: "cs_main".to_string(),
+            layout_bindings: layout_bindings.clone(),
+        })?;
+        let set_layout = device.create_descriptor_set_layout(&layout_bindings)?;
+        Ok(Self { pipeline, set_layout })
+    }
+
+    /// Record the merge of `mesh_sdfs` into the voxel ranges `regions` (first and last voxel, from
+    /// `GlobalSdf::region_voxels`) of `voxels`. Earlier reads of `voxels` finish first and the writes
+    /// are made visible to later shader reads. `None` when there are no regions.
+    pub(super) fn encode(
+        &self,
+        encoder: &mut dyn CommandEncoder,
+        device: &dyn Device,
+        global: &GlobalSdf,
+        regions: &[([usize; 3], [usize; 3])],
+        mesh_sdfs: &[MeshSdf],
+        voxels: &dyn Buffer,
+    ) -> Result<Option<SdfMergeDispatch>, String> {
+        if regions.is_empty() {
+            return Ok(None);
+        }
+        if voxels.size() < global.data().len() as u64 * 4 {
+            return Err(format!("sdf merge: voxel buffer of {} bytes is too small", voxels.size()));
+        }
+        let mut mesh_inputs = Vec::with_capacity(mesh_sdfs.len().max(1) * MESH_INPUT_SIZE);
+        let mut mesh_data: Vec<u8> = Vec::new();
+        for mesh in mesh_sdfs {
+            let data_offset = (mesh_data.len() / 4) as u32;
+            mesh_inputs.extend_from_slice(&mesh_input_bytes(mesh, data_offset, global.truncation()));
+            mesh_data.extend(mesh.data().iter().flat_map(|d| d.to_le_bytes()));
+        }
+        // Storage buffers cannot be empty; a scene without meshes merges to the truncation everywhere.
+        mesh_inputs.resize(mesh_inputs.len().max(MESH_INPUT_SIZE), 0);
+        mesh_data.resize(mesh_data.len().max(4), 0);
+        let storage = |label, data: &[u8]| -> Result<Box<dyn Buffer>, String> {
+            let buffer = device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: data.len() as u64,
+                usage: BufferUsage::STORAGE,
+                memory: BufferMemoryPreference::HostVisible,
+            })?;
+            device.write_buffer(buffer.as_ref(), 0, data)?;
+            Ok(buffer)
+        };
+        let meshes = storage("lume_sdf_merge_meshes", &mesh_inputs)?;
+        let mesh_data_buf = storage("lume_sdf_merge_mesh_data", &mesh_data)?;
+
+        let pool = device.create_descriptor_pool(regions.len() as u32)?;
+        let mut params = Vec::with_capacity(regions.len());
+        let mut sets = Vec::with_capacity(regions.len());
+        encoder.pipeline_barrier_buffer_after_read(voxels, 0, 0);
+        {
+            let mut pass = encoder.begin_compute_pass();
+            pass.set_pipeline(self.pipeline.as_ref());
+            for &(first, last) in regions {
+                let params_data = params_bytes(global, mesh_sdfs.len() as u32, first, last);
+                let params_buf = device.create_buffer(&BufferDescriptor {
+                    label: Some("lume_sdf_merge_params"),
+                    size: PARAMS_SIZE as u64,
+                    usage: BufferUsage::UNIFORM,
+                    memory: BufferMemoryPreference::HostVisible,
+                })?;
+                device.write_buffer(params_buf.as_ref(), 0, &params_data)?;
+                let mut set = pool.allocate_set(self.set_layout.as_ref())?;
+                set.write_buffer(0, voxels, 0, voxels.size())?;
+                set.write_buffer(1, meshes.as_ref(), 0, mesh_inputs.len() as u64)?;
+                set.write_buffer(2, mesh_data_buf.as_ref(), 0, mesh_data.len() as u64)?;
+                set.write_buffer(3, params_buf.as_ref(), 0, PARAMS_SIZE as u64)?;
+                pass.bind_descriptor_set(0, set.as_ref());
+                let count: [u32; 3] = std::array::from_fn(|c| (last[c] - first[c] + 1) as u32);
+                pass.dispatch(
+                    count[0].div_ceil(WORKGROUP_SIZE),
+                    count[1].div_ceil(WORKGROUP_SIZE),
+                    count[2].div_ceil(WORKGROUP_SIZE),
+                )?;
+                params.push(params_buf);
+                sets.push(set);
+            }
+        }
+        encoder.pipeline_barrier_buffer(voxels, 0, voxels.size());
+        Ok(Some(SdfMergeDispatch {
+            _meshes: meshes,
+            _mesh_data: mesh_data_buf,
+            _params: params,
+            _sets: sets,
+            _pool: pool,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gi::{Aabb, SdfGrid};
+    use lume_rhi::test_support::test_device;
+
+    #[test]
+    fn merge_inputs_match_wgsl_layout() {
+        let grid = SdfGrid { origin: [-1.0; 3], voxel_size: [0.5; 3] };
+        let global = GlobalSdf::with_grid((8, 8, 8), grid, 0.5).unwrap();
+        let params = params_bytes(&global, 3, [1, 2, 3], [4, 4, 4]);
+        assert_eq!(params.len(), PARAMS_SIZE);
+        assert_eq!(&params[28..32], &3u32.to_le_bytes());
+        assert_eq!(&params[64..68], &4u32.to_le_bytes());
+        let mesh = MeshSdf::new((2, 2, 2), grid, vec![0.0; 8]).unwrap();
+        let input = mesh_input_bytes(&mesh, 16, 0.5);
+        assert_eq!(&input[12..16], &16u32.to_le_bytes());
+        // Reach: bounds [-1, 0] grown by the truncation.
+        assert_eq!(&input[48..52], &(-1.5f32).to_le_bytes());
+        assert_eq!(&input[64..68], &0.5f32.to_le_bytes());
+        let spirv = compile_wgsl(include_str!("../../shaders/sdf_merge.wgsl"), ShaderStages::COMPUTE, "cs_main").unwrap();
+        assert!(!spirv.is_empty());
+    }
+
+    /// Moves one cube and re-merges its swept region on the GPU; the voxel buffer must match the CPU
+    /// merge. Skipped without a Vulkan device.
+    #[test]
+    fn gpu_merge_matches_cpu() {
+        let Some(device) = test_device("gpu_merge_matches_cpu") else { return };
+        let cube = |x: f32| {
+            let grid = SdfGrid { origin: [x - 0.5, -0.5, -0.5], voxel_size: [0.25; 3] };
+            let data = (0..64)
+                .map(|i| {
+                    let c = [i % 4, i / 4 % 4, i / 16].map(|v| (v as f32 + 0.5) * 0.25 - 0.5);
+                    c.iter().map(|v| v.abs()).fold(0.0, f32::max) - 0.5
+                })
+                .collect();
+            MeshSdf::new((4, 4, 4), grid, data).unwrap()
+        };
+        let grid = SdfGrid { origin: [-4.0; 3], voxel_size: [0.25; 3] };
+        let mut gpu = GlobalSdf::with_grid((32, 32, 32), grid, 0.5).unwrap();
+        let mut meshes = vec![cube(-2.0), cube(2.0)];
+        gpu.merge_mesh_sdfs(&meshes);
+        let voxels = gpu.create_voxel_buffer(device.as_ref()).unwrap();
+
+        let old_bounds: Aabb = meshes[1].bounds();
+        meshes[1] = cube(1.5);
+        gpu.mark_moved(old_bounds, meshes[1].bounds());
+        let pass = SdfMergePass::new(device.as_ref()).unwrap();
+        let mut encoder = device.create_command_encoder().unwrap();
+        let dispatch = gpu.encode_update_dirty(encoder.as_mut(), device.as_ref(), &pass, &meshes, voxels.as_ref()).unwrap();
+        assert!(dispatch.is_some());
+        encoder.pipeline_barrier_buffer_host_read(voxels.as_ref(), 0, 0);
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+
+        let mut cpu = GlobalSdf::with_grid((32, 32, 32), grid, 0.5).unwrap();
+        cpu.merge_mesh_sdfs(&meshes);
+        let mut bytes = vec![0u8; cpu.data().len() * 4];
+        device.read_buffer(voxels.as_ref(), 0, &mut bytes).unwrap();
+        for (i, (chunk, &expected)) in bytes.chunks_exact(4).zip(cpu.data()).enumerate() {
+            let got = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            assert!((got - expected).abs() < 1e-5, "voxel {}: gpu {} vs cpu {}", i, got, expected);
+        }
+    }
+}
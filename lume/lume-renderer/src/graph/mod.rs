@@ -1,17 +1,29 @@
 //! Lume Render Graph: task dependency ordering and resource lifecycle.
 //!
 //! Nodes run in topological order. **Buffer** barriers are inserted automatically when a resource
-//! is written by one node and read or written by a later node (`pipeline_barrier_buffer`).
+//! is written by one node and read or written by a later node (`pipeline_barrier_buffer`), and when
+//! it is read by one node and written by a later node (`pipeline_barrier_buffer_after_read`).
 //!
 //! **Texture** barriers are optional: when a node declares a [`TextureBarrierHint`] for a texture
 //! resource, the graph will insert `pipeline_barrier_texture` before that node if a previous node
 //! wrote to the texture, transitioning from the tracked layout to `need_layout`. If no hint is
 //! given for a texture, nodes must perform layout transitions themselves (dependency ordering
 //! is still enforced).
+//!
+//! Accesses the graph cannot see (a resource reached outside `add_node`'s declarations, e.g. through a
+//! descriptor set or memory aliasing) get no automatic barrier. Order such nodes with
+//! [`RenderGraph::add_barrier_edge`], which also records a full memory barrier; prefer declaring the
+//! resource, whose barrier is much narrower. Plain [`RenderGraph::add_edge`] only orders the nodes.
+//!
+//! Each node only sees the resources it declared, and [`RenderGraph::validate`] reports usage the
+//! barriers cannot cover, such as unordered conflicting accesses. `execute` runs it in debug builds;
+//! release builds only check for its errors, which are cheap, and skip the hazard search. Either way
+//! the checks run once per change to the graph, not once per frame.
 
 use lume_rhi::{CommandBuffer, Device, ImageLayout};
+use render_api::{log, log_enabled, DotAccess, GraphDot, LogLevel};
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 /// Identifier for a node in the graph.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -39,6 +51,13 @@ impl ResourceUsage {
     pub fn is_read(&self) -> bool {
         matches!(self, ResourceUsage::Read | ResourceUsage::ReadWrite)
     }
+    fn dot_access(&self) -> DotAccess {
+        match self {
+            ResourceUsage::Read => DotAccess::Read,
+            ResourceUsage::Write => DotAccess::Write,
+            ResourceUsage::ReadWrite => DotAccess::ReadWrite,
+        }
+    }
 }
 
 /// Optional hint for texture resources so the graph can insert `pipeline_barrier_texture` automatically.
@@ -55,7 +74,9 @@ pub struct TextureBarrierHint {
 
 /// A single node in the render graph. Executes and returns command buffers.
 pub trait RenderGraphNode: Send + Sync {
-    /// Run the node; may record commands and return command buffers.
+    /// Run the node; may record commands and return command buffers. `resources` only contains the
+    /// resources declared for this node in [`RenderGraph::add_node`], so an undeclared access finds
+    /// nothing instead of racing without a barrier.
     fn execute(
         &self,
         device: &Arc<dyn Device>,
@@ -70,28 +91,20 @@ pub enum ResourceHandle {
 }
 
 /// Builds and executes the render graph.
+#[derive(Default)]
 pub struct RenderGraph {
     nodes: Vec<Box<dyn RenderGraphNode>>,
     /// Per-node resource usage for automatic barrier insertion. Third element is optional texture barrier hint.
     node_resource_usage: Vec<Vec<(ResourceId, ResourceUsage, Option<TextureBarrierHint>)>>,
     /// Edges: (from, to) means from runs before to.
     edges: Vec<(NodeId, NodeId)>,
+    /// Edges from [`Self::add_barrier_edge`]; also in `edges`.
+    barrier_edges: Vec<(NodeId, NodeId)>,
     resources: HashMap<ResourceId, ResourceHandle>,
     next_node_id: usize,
     next_resource_id: usize,
-}
-
-impl Default for RenderGraph {
-    fn default() -> Self {
-        Self {
-            nodes: Vec::new(),
-            node_resource_usage: Vec::new(),
-            edges: Vec::new(),
-            resources: HashMap::new(),
-            next_node_id: 0,
-            next_resource_id: 0,
-        }
-    }
+    /// Outcome of [`Self::execute_checks`] since the graph last changed; every `add_*` clears it.
+    checked: OnceLock<Result<(), String>>,
 }
 
 impl RenderGraph {
@@ -111,12 +124,23 @@ impl RenderGraph {
         self.next_node_id += 1;
         self.nodes.push(node);
         self.node_resource_usage.push(resource_usage);
+        self.checked.take();
         id
     }
 
     /// Add a dependency: `before` runs before `after`.
     pub fn add_edge(&mut self, before: NodeId, after: NodeId) {
         self.edges.push((before, after));
+        self.checked.take();
+    }
+
+    /// Add a dependency with a full memory barrier (`pipeline_barrier_memory`) before `after`, for
+    /// hazards through resources the nodes do not declare. Declared resources get their barriers
+    /// automatically and do not need this.
+    pub fn add_barrier_edge(&mut self, before: NodeId, after: NodeId) {
+        self.edges.push((before, after));
+        self.barrier_edges.push((before, after));
+        self.checked.take();
     }
 
     /// Register a resource for use by nodes.
@@ -124,6 +148,7 @@ impl RenderGraph {
         let id = ResourceId(self.next_resource_id);
         self.next_resource_id += 1;
         self.resources.insert(id, handle);
+        self.checked.take();
         id
     }
 
@@ -155,50 +180,236 @@ impl RenderGraph {
         Ok(order)
     }
 
-    /// Execute the graph in dependency order; returns all command buffers from all nodes.
-    /// Inserts `pipeline_barrier_buffer` between nodes when a buffer was written by a previous node
-    /// and is read or written by the current node. For texture resources with a [`TextureBarrierHint`],
-    /// inserts `pipeline_barrier_texture` from the tracked layout to `need_layout` when a previous
-    /// node wrote the texture.
-    pub fn execute(&self, device: &Arc<dyn Device>) -> Result<Vec<Box<dyn CommandBuffer>>, String> {
-        let order = self.topological_order()?;
-        let mut all_cmds = Vec::new();
+    /// Check declared resource usage; `execute` runs this in debug builds and logs the warnings once
+    /// per change to the graph (release builds only check for the errors, see [`Self::check_declarations`]).
+    /// Errors: a node declares a resource that was never registered, an edge names an unknown node, or
+    /// the edges form a cycle. Warnings are hazards the automatic barriers cannot fix: two nodes
+    /// accessing a resource (at least one writing) with no edge path between them, so their order and
+    /// the barrier are arbitrary; and a texture written by an earlier node but used without a
+    /// [`TextureBarrierHint`], so the node must transition it itself.
+    pub fn validate(&self) -> Result<Vec<String>, String> {
+        self.topological_order()?;
+        self.check_declarations()?;
+        let n = self.nodes.len();
+        let reach = self.reachability();
+        let mut warnings = self.unordered_accesses(&reach);
+        for (b, usage) in self.node_resource_usage.iter().enumerate() {
+            for (rid, _, hint) in usage {
+                if hint.is_some() || !matches!(self.resources.get(rid), Some(ResourceHandle::Texture(_))) {
+                    continue;
+                }
+                let writer = (0..n).find(|&a| reach[a][b] && self.writes(a, *rid));
+                if let Some(a) = writer {
+                    warnings.push(format!(
+                        "texture {} is written by node {} and used by node {} without a TextureBarrierHint; \
+                         node {} must transition it itself",
+                        rid.0, a, b, b
+                    ));
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// The errors of [`Self::validate`] other than cycles, in time linear in the edges and declarations:
+    /// an edge naming an unknown node, or a node declaring an unregistered resource.
+    fn check_declarations(&self) -> Result<(), String> {
+        let n = self.nodes.len();
+        for &(NodeId(a), NodeId(b)) in &self.edges {
+            if a >= n || b >= n {
+                return Err(format!("render graph: edge {} -> {} names an unknown node", a, b));
+            }
+        }
+        for (i, usage) in self.node_resource_usage.iter().enumerate() {
+            if let Some((rid, _, _)) = usage.iter().find(|(rid, _, _)| !self.resources.contains_key(rid)) {
+                return Err(format!("render graph: node {} declares unregistered resource {}", i, rid.0));
+            }
+        }
+        Ok(())
+    }
+
+    /// Result of [`Self::execute_checks`], run only when the graph changed since the last call.
+    fn checks(&self) -> Result<(), String> {
+        self.checked.get_or_init(|| self.execute_checks()).clone()
+    }
+
+    /// What `execute` checks before its first run after a change: [`Self::validate`], logging its
+    /// warnings, in debug builds, as the hazard search is quadratic in the nodes; only
+    /// [`Self::check_declarations`] otherwise.
+    fn execute_checks(&self) -> Result<(), String> {
+        if !cfg!(debug_assertions) {
+            return self.check_declarations();
+        }
+        for warning in self.validate()? {
+            log(LogLevel::Warn, "lume::graph", format_args!("{}", warning));
+        }
+        Ok(())
+    }
+
+    /// `reach[a][b]`: node `a` runs before node `b` through a path of edges. Assumes valid node ids.
+    fn reachability(&self) -> Vec<Vec<bool>> {
+        let n = self.nodes.len();
+        let mut out_edges: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for &(NodeId(a), NodeId(b)) in &self.edges {
+            out_edges[a].push(b);
+        }
+        let mut reach = vec![vec![false; n]; n];
+        for (start, row) in reach.iter_mut().enumerate() {
+            let mut stack = out_edges[start].clone();
+            while let Some(v) = stack.pop() {
+                if !row[v] {
+                    row[v] = true;
+                    stack.extend_from_slice(&out_edges[v]);
+                }
+            }
+        }
+        reach
+    }
+
+    fn writes(&self, node: usize, rid: ResourceId) -> bool {
+        self.node_resource_usage[node].iter().any(|(r, ru, _)| *r == rid && ru.is_write())
+    }
+
+    /// Warnings for pairs of nodes accessing the same resource, at least one writing, with no order between them.
+    fn unordered_accesses(&self, reach: &[Vec<bool>]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (a, usage_a) in self.node_resource_usage.iter().enumerate() {
+            for (b, usage_b) in self.node_resource_usage.iter().enumerate().skip(a + 1) {
+                if reach[a][b] || reach[b][a] {
+                    continue;
+                }
+                for (rid, ru_a, _) in usage_a {
+                    let conflict = usage_b.iter().any(|(r, ru_b, _)| r == rid && (ru_a.is_write() || ru_b.is_write()));
+                    if conflict {
+                        warnings.push(format!(
+                            "nodes {} and {} both access resource {} (at least one writes) but no edge orders them",
+                            a, b, rid.0
+                        ));
+                    }
+                }
+            }
+        }
+        warnings
+    }
+
+    /// Graphviz (DOT) description of the graph for debugging, e.g. `dot -Tpng graph.dot -o graph.png`
+    /// (format in [`render_api::GraphDot`]). Texture barrier hints label their edge with the needed
+    /// layout; orange dependencies come from [`Self::add_barrier_edge`].
+    pub fn to_dot(&self) -> String {
+        let mut dot = GraphDot::new(self.nodes.len());
+        for (rid, handle) in &self.resources {
+            let kind = match handle {
+                ResourceHandle::Buffer(_) => "buffer",
+                ResourceHandle::Texture(_) => "texture",
+            };
+            dot.resource(rid.0, kind);
+        }
+        for (i, usage) in self.node_resource_usage.iter().enumerate() {
+            for (rid, ru, hint) in usage {
+                let label = hint.as_ref().map(|h| format!("{:?}", h.need_layout));
+                dot.access(i, rid.0, ru.dot_access(), label.as_deref());
+            }
+        }
+        for &(NodeId(a), NodeId(b)) in &self.edges {
+            dot.edge(a, b, self.barrier_edges.contains(&(NodeId(a), NodeId(b))));
+        }
+        dot.finish()
+    }
+
+    /// Barriers to record before each node of `order`, tracking writes, pending reads and texture
+    /// layouts through the whole order.
+    fn plan_barriers(&self, order: &[usize]) -> Vec<NodeBarriers> {
         let mut resources_written: HashSet<ResourceId> = HashSet::new();
+        // Buffers read since their last write: a later writer must wait for those reads.
+        let mut pending_reads: HashSet<ResourceId> = HashSet::new();
         let mut texture_layout: HashMap<ResourceId, ImageLayout> = HashMap::new();
-        for index in order {
-            let usage = self
-                .node_resource_usage
-                .get(index)
-                .map(|u| u.as_slice())
-                .unwrap_or(&[]);
-            let mut need_buffer_barrier: Vec<ResourceId> = Vec::new();
-            let mut need_texture_barriers: Vec<(ResourceId, ImageLayout, ImageLayout)> = Vec::new();
+        let mut plan = Vec::with_capacity(order.len());
+        for &index in order {
+            let usage = self.node_resource_usage.get(index).map(|u| u.as_slice()).unwrap_or(&[]);
+            let mut barriers = NodeBarriers {
+                memory: self.barrier_edges.iter().any(|&(_, NodeId(after))| after == index),
+                ..Default::default()
+            };
             for (rid, ru, hint_opt) in usage {
                 if !ru.is_read() && !ru.is_write() {
                     continue;
                 }
-                if resources_written.contains(rid) {
-                    if let Some(ResourceHandle::Buffer(_)) = self.resources.get(rid) {
-                        need_buffer_barrier.push(*rid);
-                    } else if let Some(ResourceHandle::Texture(_)) = self.resources.get(rid) {
+                match self.resources.get(rid) {
+                    Some(ResourceHandle::Buffer(_)) => {
+                        if resources_written.contains(rid) {
+                            barriers.after_write.push(*rid);
+                        }
+                        if ru.is_write() && pending_reads.contains(rid) {
+                            barriers.after_read.push(*rid);
+                        }
+                    }
+                    Some(ResourceHandle::Texture(_)) if resources_written.contains(rid) => {
                         if let Some(ref hint) = hint_opt {
                             let old = texture_layout.get(rid).copied().unwrap_or(ImageLayout::Undefined);
                             if old != hint.need_layout {
-                                need_texture_barriers.push((*rid, old, hint.need_layout));
+                                barriers.textures.push((*rid, old, hint.need_layout));
                             }
                         }
                     }
+                    _ => {}
                 }
             }
-            if !need_buffer_barrier.is_empty() || !need_texture_barriers.is_empty() {
+            for (rid, ru, hint_opt) in usage {
+                if ru.is_write() {
+                    resources_written.insert(*rid);
+                    pending_reads.remove(rid);
+                    if let Some(ResourceHandle::Texture(_)) = self.resources.get(rid) {
+                        if let Some(ref hint) = hint_opt {
+                            let new_layout = hint.after_pass_layout.unwrap_or(hint.need_layout);
+                            texture_layout.insert(*rid, new_layout);
+                        }
+                    }
+                } else if let Some(ResourceHandle::Texture(_)) = self.resources.get(rid) {
+                    if let Some(ref hint) = hint_opt {
+                        texture_layout.insert(*rid, hint.need_layout);
+                    }
+                }
+                if ru.is_read() {
+                    pending_reads.insert(*rid);
+                }
+            }
+            plan.push(barriers);
+        }
+        plan
+    }
+
+    /// Execute the graph in dependency order; returns all command buffers from all nodes.
+    /// Inserts `pipeline_barrier_buffer` between nodes when a buffer was written by a previous node
+    /// and is read or written by the current node, and `pipeline_barrier_buffer_after_read` when a
+    /// buffer read by a previous node is written by the current one. For texture resources with a
+    /// [`TextureBarrierHint`], inserts `pipeline_barrier_texture` from the tracked layout to
+    /// `need_layout` when a previous node wrote the texture. Nodes after an
+    /// [`Self::add_barrier_edge`] get `pipeline_barrier_memory` first.
+    pub fn execute(&self, device: &Arc<dyn Device>) -> Result<Vec<Box<dyn CommandBuffer>>, String> {
+        self.checks()?;
+        let order = self.topological_order()?;
+        let plan = self.plan_barriers(&order);
+        // CPU time each node takes to record, e.g. to spot a pass rebuilding its resources every frame.
+        let timed = log_enabled(LogLevel::Debug, "lume::graph");
+        let mut all_cmds = Vec::new();
+        for (index, barriers) in order.into_iter().zip(plan) {
+            if !barriers.is_empty() {
                 let mut encoder = device.create_command_encoder()?;
-                for rid in need_buffer_barrier {
+                if barriers.memory {
+                    encoder.pipeline_barrier_memory();
+                }
+                for rid in barriers.after_write {
                     if let Some(ResourceHandle::Buffer(ref b)) = self.resources.get(&rid) {
                         let size = b.size();
                         encoder.pipeline_barrier_buffer(b.as_ref(), 0, size);
                     }
                 }
-                for (rid, old_layout, new_layout) in need_texture_barriers {
+                for rid in barriers.after_read {
+                    if let Some(ResourceHandle::Buffer(ref b)) = self.resources.get(&rid) {
+                        encoder.pipeline_barrier_buffer_after_read(b.as_ref(), 0, b.size());
+                    }
+                }
+                for (rid, old_layout, new_layout) in barriers.textures {
                     if let Some(ResourceHandle::Texture(ref t)) = self.resources.get(&rid) {
                         encoder.pipeline_barrier_texture(t.as_ref(), old_layout, new_layout);
                     }
@@ -207,29 +418,160 @@ impl RenderGraph {
                 all_cmds.push(barrier_cmd);
             }
             let node = &self.nodes[index];
-            let resource_refs: HashMap<ResourceId, &ResourceHandle> = self
-                .resources
+            let usage = self.node_resource_usage.get(index).map(|u| u.as_slice()).unwrap_or(&[]);
+            let resource_refs: HashMap<ResourceId, &ResourceHandle> = usage
                 .iter()
-                .map(|(k, v)| (*k, v))
+                .filter_map(|(rid, _, _)| self.resources.get(rid).map(|h| (*rid, h)))
                 .collect();
+            let started = timed.then(std::time::Instant::now);
             let cmds = node.execute(device, &resource_refs);
-            all_cmds.extend(cmds);
-            for (rid, ru, hint_opt) in usage {
-                if ru.is_write() {
-                    resources_written.insert(*rid);
-                    if let Some(ResourceHandle::Texture(_)) = self.resources.get(rid) {
-                        if let Some(ref hint) = hint_opt {
-                            let new_layout = hint.after_pass_layout.unwrap_or(hint.need_layout);
-                            texture_layout.insert(*rid, new_layout);
-                        }
-                    }
-                } else if let Some(ResourceHandle::Texture(_)) = self.resources.get(rid) {
-                    if let Some(ref hint) = hint_opt {
-                        texture_layout.insert(*rid, hint.need_layout);
-                    }
-                }
+            if let Some(started) = started {
+                log(LogLevel::Debug, "lume::graph", format_args!("node {} recorded in {:.2?} (CPU)", index, started.elapsed()));
             }
+            all_cmds.extend(cmds);
         }
         Ok(all_cmds)
     }
 }
+
+/// Barriers `RenderGraph::execute` records before one node.
+#[derive(Debug, Default, PartialEq)]
+struct NodeBarriers {
+    /// Full memory barrier from an [`RenderGraph::add_barrier_edge`] into this node.
+    memory: bool,
+    /// Buffers written by an earlier node (read-after-write, write-after-write).
+    after_write: Vec<ResourceId>,
+    /// Buffers read by an earlier node and written by this one (write-after-read).
+    after_read: Vec<ResourceId>,
+    /// Texture transitions (resource, old layout, new layout) from [`TextureBarrierHint`]s.
+    textures: Vec<(ResourceId, ImageLayout, ImageLayout)>,
+}
+
+impl NodeBarriers {
+    fn is_empty(&self) -> bool {
+        !self.memory && self.after_write.is_empty() && self.after_read.is_empty() && self.textures.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EmptyNode;
+
+    impl RenderGraphNode for EmptyNode {
+        fn execute(
+            &self,
+            _device: &Arc<dyn Device>,
+            _resources: &HashMap<ResourceId, &ResourceHandle>,
+        ) -> Vec<Box<dyn CommandBuffer>> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn dot_dump_lists_nodes_resource_usage_and_edges() {
+        let mut graph = RenderGraph::new();
+        let hint = TextureBarrierHint { need_layout: ImageLayout::ShaderReadOnly, after_pass_layout: None };
+        let a = graph.add_node(Box::new(EmptyNode), vec![(ResourceId(0), ResourceUsage::Write, None)]);
+        let b = graph.add_node(
+            Box::new(EmptyNode),
+            vec![(ResourceId(0), ResourceUsage::Read, Some(hint)), (ResourceId(1), ResourceUsage::ReadWrite, None)],
+        );
+        graph.add_edge(a, b);
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph render_graph {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(dot.contains("n0 [shape=box, label=\"node 0\"];"));
+        assert!(dot.contains("r1 [shape=ellipse, label=\"resource 1\\nunregistered\"];"));
+        assert!(dot.contains("n0 -> r0 [color=red];"));
+        assert!(dot.contains("r0 -> n1 [color=blue, label=\"ShaderReadOnly\"];"));
+        assert!(dot.contains("n1 -> r1 [color=purple, dir=both];"));
+        assert!(dot.contains("n0 -> n1 [style=bold];"));
+    }
+
+    #[test]
+    fn validate_reports_unregistered_resources_and_unordered_writes() {
+        let mut graph = RenderGraph::new();
+        let a = graph.add_node(Box::new(EmptyNode), vec![(ResourceId(0), ResourceUsage::Write, None)]);
+        let b = graph.add_node(Box::new(EmptyNode), vec![(ResourceId(0), ResourceUsage::Read, None)]);
+        let c = graph.add_node(Box::new(EmptyNode), vec![(ResourceId(0), ResourceUsage::Read, None)]);
+        let err = graph.validate().unwrap_err();
+        assert!(err.contains("unregistered resource 0"), "{}", err);
+
+        // Without edges the writer races both readers; the two readers may run in any order.
+        assert_eq!(graph.unordered_accesses(&graph.reachability()).len(), 2);
+        graph.add_edge(a, b);
+        graph.add_edge(b, c);
+        assert!(graph.unordered_accesses(&graph.reachability()).is_empty());
+        graph.add_edge(c, a);
+        assert!(graph.validate().unwrap_err().contains("cycle"));
+    }
+
+    #[test]
+    fn checks_rerun_only_after_the_graph_changes() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(Box::new(EmptyNode), vec![(ResourceId(0), ResourceUsage::Write, None)]);
+        assert!(graph.checks().unwrap_err().contains("unregistered resource 0"));
+        assert!(graph.checked.get().is_some());
+        graph.add_resource(ResourceHandle::Buffer(Box::new(StubBuffer)));
+        assert!(graph.checked.get().is_none());
+        assert_eq!(graph.checks(), Ok(()));
+        let b = graph.add_node(Box::new(EmptyNode), Vec::new());
+        graph.checks().unwrap();
+        graph.add_edge(b, NodeId(5));
+        assert!(graph.checks().unwrap_err().contains("unknown node"));
+    }
+
+    #[derive(Debug)]
+    struct StubBuffer;
+
+    impl lume_rhi::Buffer for StubBuffer {
+        fn id(&self) -> lume_rhi::ResourceId {
+            0
+        }
+        fn size(&self) -> u64 {
+            256
+        }
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn write_after_read_gets_a_barrier() {
+        let mut graph = RenderGraph::new();
+        let buf = graph.add_resource(ResourceHandle::Buffer(Box::new(StubBuffer)));
+        let read = graph.add_node(Box::new(EmptyNode), vec![(buf, ResourceUsage::Read, None)]);
+        let write = graph.add_node(Box::new(EmptyNode), vec![(buf, ResourceUsage::Write, None)]);
+        let read_again = graph.add_node(Box::new(EmptyNode), vec![(buf, ResourceUsage::Read, None)]);
+        let write_again = graph.add_node(Box::new(EmptyNode), vec![(buf, ResourceUsage::Write, None)]);
+        graph.add_edge(read, write);
+        graph.add_edge(write, read_again);
+        graph.add_edge(read_again, write_again);
+        let order = graph.topological_order().unwrap();
+        let plan = graph.plan_barriers(&order);
+        assert_eq!(plan[0], NodeBarriers::default());
+        assert_eq!(plan[1], NodeBarriers { after_read: vec![buf], ..Default::default() });
+        assert_eq!(plan[2], NodeBarriers { after_write: vec![buf], ..Default::default() });
+        assert_eq!(plan[3], NodeBarriers { after_write: vec![buf], after_read: vec![buf], ..Default::default() });
+    }
+
+    #[test]
+    fn barrier_edge_orders_nodes_with_a_memory_barrier() {
+        let mut graph = RenderGraph::new();
+        let a = graph.add_node(Box::new(EmptyNode), Vec::new());
+        let b = graph.add_node(Box::new(EmptyNode), Vec::new());
+        let c = graph.add_node(Box::new(EmptyNode), Vec::new());
+        graph.add_barrier_edge(a, b);
+        graph.add_edge(b, c);
+        let order = graph.topological_order().unwrap();
+        assert_eq!(order, [0, 1, 2]);
+        let plan = graph.plan_barriers(&order);
+        assert_eq!(plan.iter().map(|p| p.memory).collect::<Vec<_>>(), [false, true, false]);
+        assert!(plan[2].is_empty());
+        let dot = graph.to_dot();
+        assert!(dot.contains("n0 -> n1 [style=bold, color=orange, label=\"memory barrier\"];"));
+        assert!(dot.contains("n1 -> n2 [style=bold];"));
+    }
+}
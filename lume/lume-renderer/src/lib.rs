@@ -4,14 +4,17 @@
 use lume_rhi::{CommandBuffer, Device};
 use std::sync::Arc;
 
+pub mod deferred;
 pub mod gi;
 pub mod graph;
+pub mod shader;
 pub mod virtual_geom;
 
 pub use graph::{
     NodeId, RenderGraph, RenderGraphNode, ResourceHandle, ResourceId as GraphResourceId,
     TextureBarrierHint,
 };
+pub use shader::{compile_wgsl, compile_wgsl_with_input_attachments};
 
 pub struct Renderer {
     device: Arc<dyn Device>,
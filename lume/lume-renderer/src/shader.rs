@@ -0,0 +1,206 @@
+//! WGSL shader compilation for the Lume RHI, which consumes SPIR-V.
+
+use lume_rhi::ShaderStages;
+use std::collections::{HashMap, HashSet};
+
+/// Compile one WGSL entry point to SPIR-V bytes (little-endian words). `stage` must be exactly one of
+/// VERTEX, FRAGMENT or COMPUTE. naga's default options flip Y, so WGSL clip space maps onto Vulkan unchanged.
+pub fn compile_wgsl(source: &str, stage: ShaderStages, entry_point: &str) -> Result<Vec<u8>, String> {
+    let shader_stage = if stage == ShaderStages::VERTEX {
+        naga::ShaderStage::Vertex
+    } else if stage == ShaderStages::FRAGMENT {
+        naga::ShaderStage::Fragment
+    } else if stage == ShaderStages::COMPUTE {
+        naga::ShaderStage::Compute
+    } else {
+        return Err(format!("compile_wgsl {}: expected a single shader stage, got {:?}", entry_point, stage));
+    };
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| format!("parse {}: {}", entry_point, e))?;
+    let info = naga::valid::Validator::new(naga::valid::ValidationFlags::default(), naga::valid::Capabilities::default())
+        .validate(&module)
+        .map_err(|e| format!("validate {}: {}", entry_point, e))?;
+    let pipeline_options = naga::back::spv::PipelineOptions {
+        shader_stage,
+        entry_point: entry_point.to_string(),
+    };
+    let words = naga::back::spv::write_vec(&module, &info, &naga::back::spv::Options::default(), Some(&pipeline_options))
+        .map_err(|e| format!("spirv {}: {}", entry_point, e))?;
+    Ok(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
+
+const HEADER_WORDS: usize = 5;
+const OP_CAPABILITY: u32 = 17;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_VARIABLE: u32 = 59;
+const OP_LOAD: u32 = 61;
+const OP_DECORATE: u32 = 71;
+const OP_IMAGE_FETCH: u32 = 95;
+const OP_IMAGE_READ: u32 = 98;
+
+const DIM_2D: u32 = 1;
+const DIM_SUBPASS_DATA: u32 = 6;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_INPUT_ATTACHMENT_INDEX: u32 = 43;
+const CAPABILITY_INPUT_ATTACHMENT: u32 = 40;
+
+/// Compile a WGSL fragment entry point that reads input attachments, which WGSL cannot declare.
+/// Each `(binding, index)` of `input_attachments` names a `texture_2d<f32>` of group 0 that becomes
+/// the subpass input `input_attachment_index = index`; read it with `textureLoad(t, vec2<i32>(0, 0), 0)`,
+/// which turns into `subpassLoad` at the current pixel. naga gives every `texture_2d<f32>` one SPIR-V
+/// type, so the module must not declare other textures of that type.
+pub fn compile_wgsl_with_input_attachments(
+    source: &str,
+    entry_point: &str,
+    input_attachments: &[(u32, u32)],
+) -> Result<Vec<u8>, String> {
+    let bytes = compile_wgsl(source, ShaderStages::FRAGMENT, entry_point)?;
+    let words: Vec<u32> = bytes.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+    let words = to_input_attachments(&words, input_attachments).map_err(|e| format!("{}: {}", entry_point, e))?;
+    Ok(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
+
+/// Instructions of a SPIR-V module after its header.
+fn instructions(words: &[u32]) -> Result<Vec<&[u32]>, String> {
+    let mut out = Vec::new();
+    let mut i = HEADER_WORDS;
+    while i < words.len() {
+        let count = (words[i] >> 16) as usize;
+        if count == 0 || i + count > words.len() {
+            return Err(format!("malformed SPIR-V instruction at word {}", i));
+        }
+        out.push(&words[i..i + count]);
+        i += count;
+    }
+    Ok(out)
+}
+
+/// Rewrite the sampled 2D images bound at `input_attachments` as `SubpassData` images and their
+/// `OpImageFetch`es as `OpImageRead`s, and declare the capability and attachment indices.
+fn to_input_attachments(words: &[u32], input_attachments: &[(u32, u32)]) -> Result<Vec<u32>, String> {
+    let instructions = instructions(words)?;
+    let (mut set, mut binding) = (HashMap::new(), HashMap::new());
+    let (mut pointee, mut variables, mut images) = (HashMap::new(), HashMap::new(), HashMap::new());
+    for inst in &instructions {
+        match inst[0] & 0xffff {
+            OP_DECORATE if inst.len() >= 4 && inst[2] == DECORATION_DESCRIPTOR_SET => {
+                set.insert(inst[1], inst[3]);
+            }
+            OP_DECORATE if inst.len() >= 4 && inst[2] == DECORATION_BINDING => {
+                binding.insert(inst[1], inst[3]);
+            }
+            OP_TYPE_POINTER if inst.len() >= 4 => {
+                pointee.insert(inst[1], inst[3]);
+            }
+            OP_VARIABLE if inst.len() >= 4 => {
+                variables.insert(inst[2], inst[1]);
+            }
+            OP_TYPE_IMAGE if inst.len() >= 9 => {
+                images.insert(inst[1], *inst);
+            }
+            _ => {}
+        }
+    }
+    let image_of = |var: &u32| variables.get(var).and_then(|ptr| pointee.get(ptr)).copied();
+    // Variable -> attachment index, and the image types they use.
+    let mut attachments = HashMap::new();
+    let mut converted = HashSet::new();
+    for &(b, index) in input_attachments {
+        let var = binding
+            .iter()
+            .find(|&(var, &vb)| vb == b && set.get(var) == Some(&0))
+            .map(|(&var, _)| var)
+            .ok_or_else(|| format!("no resource at group 0 binding {}", b))?;
+        let image = image_of(&var).filter(|image| {
+            // Sampled type, Dim, Depth, Arrayed, MS, Sampled: a plain non-depth sampled 2D image.
+            images.get(image).is_some_and(|inst| inst[3..8] == [DIM_2D, 0, 0, 0, 1])
+        });
+        let image = image.ok_or_else(|| format!("binding {} is not a texture_2d<f32>", b))?;
+        attachments.insert(var, index);
+        converted.insert(image);
+    }
+    for (var, b) in &binding {
+        if !attachments.contains_key(var) && image_of(var).is_some_and(|image| converted.contains(&image)) {
+            return Err(format!("binding {} shares the SPIR-V type of the input attachments", b));
+        }
+    }
+    let mut loaded = HashSet::new();
+    for inst in &instructions {
+        if inst[0] & 0xffff == OP_LOAD && inst.len() >= 4 && converted.contains(&inst[1]) {
+            loaded.insert(inst[2]);
+        }
+    }
+
+    let mut out = words[..HEADER_WORDS].to_vec();
+    let mut capability_added = false;
+    for inst in instructions {
+        let opcode = inst[0] & 0xffff;
+        if opcode == OP_CAPABILITY && !capability_added {
+            out.extend([(2 << 16) | OP_CAPABILITY, CAPABILITY_INPUT_ATTACHMENT]);
+            capability_added = true;
+        }
+        match opcode {
+            OP_TYPE_IMAGE if converted.contains(&inst[1]) => {
+                let mut image = inst.to_vec();
+                image[3] = DIM_SUBPASS_DATA;
+                // Sampled = 2: read without a sampler.
+                image[7] = 2;
+                out.extend(image);
+            }
+            // Result type, result, image and coordinate; the Lod operand is dropped.
+            OP_IMAGE_FETCH if inst.len() >= 5 && loaded.contains(&inst[3]) => {
+                out.extend([(5 << 16) | OP_IMAGE_READ, inst[1], inst[2], inst[3], inst[4]]);
+            }
+            _ => out.extend_from_slice(inst),
+        }
+        if opcode == OP_DECORATE && inst.len() >= 4 && inst[2] == DECORATION_BINDING {
+            if let Some(&index) = attachments.get(&inst[1]) {
+                out.extend([(4 << 16) | OP_DECORATE, inst[1], DECORATION_INPUT_ATTACHMENT_INDEX, index]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lume_rhi::DescriptorType;
+
+    const SOURCE: &str = "
+const ORIGIN = vec2<i32>(0, 0);
+@group(0) @binding(0) var<uniform> scale: vec4<f32>;
+@group(0) @binding(1) var albedo: texture_2d<f32>;
+@group(0) @binding(2) var normal: texture_2d<f32>;
+@fragment fn fs_main() -> @location(0) vec4<f32> {
+    return scale * textureLoad(albedo, ORIGIN, 0) + textureLoad(normal, ORIGIN, 0);
+}
+";
+
+    #[test]
+    fn textures_become_input_attachments() {
+        let spirv = compile_wgsl_with_input_attachments(SOURCE, "fs_main", &[(1, 0), (2, 1)]).unwrap();
+        let types: Vec<_> = lume_rhi::reflect::reflect_bindings(&spirv).unwrap().iter().map(|b| b.descriptor_type).collect();
+        assert_eq!(types, [DescriptorType::UniformBuffer, DescriptorType::InputAttachment, DescriptorType::InputAttachment]);
+        let words: Vec<u32> = spirv.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+        let instructions = instructions(&words).unwrap();
+        assert!(instructions.contains(&&[(2 << 16) | OP_CAPABILITY, CAPABILITY_INPUT_ATTACHMENT][..]));
+        let indices: Vec<u32> = instructions
+            .iter()
+            .filter(|inst| inst[0] & 0xffff == OP_DECORATE && inst[2] == DECORATION_INPUT_ATTACHMENT_INDEX)
+            .map(|inst| inst[3])
+            .collect();
+        assert_eq!(indices, [0, 1]);
+        let reads = instructions.iter().filter(|inst| inst[0] & 0xffff == OP_IMAGE_READ).count();
+        assert_eq!(reads, 2);
+        assert!(!instructions.iter().any(|inst| inst[0] & 0xffff == OP_IMAGE_FETCH));
+    }
+
+    #[test]
+    fn input_attachments_must_be_the_only_sampled_textures() {
+        assert!(compile_wgsl_with_input_attachments(SOURCE, "fs_main", &[(1, 0)]).unwrap_err().contains("binding 2"));
+        assert!(compile_wgsl_with_input_attachments(SOURCE, "fs_main", &[(0, 0)]).is_err());
+        assert!(compile_wgsl_with_input_attachments(SOURCE, "fs_main", &[(3, 0)]).is_err());
+    }
+}
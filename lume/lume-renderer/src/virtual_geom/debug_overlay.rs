@@ -0,0 +1,277 @@
+//! Culling debug overlay: wireframe cluster bounding spheres colored by culling result, emitted as
+//! line-list vertices and drawn over the scene by [`DebugLinePass`].
+
+use lume_rhi::{
+    BlendComponent, BlendFactor, BlendOp, BlendState, Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage,
+    ColorTargetState, CompareOp, DepthStencilState, DescriptorPool, DescriptorSet, DescriptorSetLayout,
+    DescriptorSetLayoutBinding, DescriptorType, Device, GraphicsPipeline, GraphicsPipelineDescriptor, LoadOp,
+    PrimitiveTopology, RasterizationState, RenderPass, ShaderStage, ShaderStages, StoreOp, TextureFormat,
+    VertexAttribute, VertexBinding, VertexFormat, VertexInputDescriptor, VertexInputRate,
+};
+
+use crate::shader::compile_wgsl;
+
+/// Culling result of one cluster, recorded by `prepare_culling_pass` while the overlay is enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClusterVisibility {
+    Visible,
+    FrustumCulled,
+    ConeCulled,
+}
+
+impl ClusterVisibility {
+    /// Overlay color: green visible, red frustum-culled, yellow cone-culled.
+    pub fn color(self) -> [f32; 4] {
+        match self {
+            ClusterVisibility::Visible => [0.0, 1.0, 0.0, 1.0],
+            ClusterVisibility::FrustumCulled => [1.0, 0.0, 0.0, 1.0],
+            ClusterVisibility::ConeCulled => [1.0, 1.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// One endpoint of a debug line; consecutive pairs form a segment (`PrimitiveTopology::LineList`).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugLineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Bytes of one [`DebugLineVertex`] in the vertex buffer (position, then color).
+pub const DEBUG_LINE_VERTEX_SIZE: usize = std::mem::size_of::<DebugLineVertex>();
+
+/// Bytes of `LineUniform` in shaders/debug_lines.wgsl.
+const LINE_UNIFORM_SIZE: u64 = 64;
+
+fn line_vertex_bytes(lines: &[DebugLineVertex]) -> Vec<u8> {
+    lines.iter().flat_map(|v| v.position.iter().chain(&v.color)).flat_map(|f| f.to_le_bytes()).collect()
+}
+
+/// Segments per circle of a sphere wireframe.
+pub const SPHERE_SEGMENTS: usize = 16;
+
+/// Append a sphere wireframe (three axis-aligned great circles, `3 * SPHERE_SEGMENTS` segments).
+pub fn push_sphere_wireframe(out: &mut Vec<DebugLineVertex>, sphere: [f32; 4], color: [f32; 4]) {
+    let [cx, cy, cz, r] = sphere;
+    let point = |axis: usize, i: usize| {
+        let angle = i as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+        let (s, c) = (angle.sin() * r, angle.cos() * r);
+        let offset = match axis {
+            0 => [c, s, 0.0],
+            1 => [c, 0.0, s],
+            _ => [0.0, c, s],
+        };
+        DebugLineVertex { position: [cx + offset[0], cy + offset[1], cz + offset[2]], color }
+    };
+    for axis in 0..3 {
+        for i in 0..SPHERE_SEGMENTS {
+            out.push(point(axis, i));
+            out.push(point(axis, i + 1));
+        }
+    }
+}
+
+/// View-proj uniform and vertex buffer of one frame in flight.
+struct LineSlot {
+    uniform: Box<dyn Buffer>,
+    /// Grown to the next power of two of the frame's vertex bytes.
+    vertices: Option<Box<dyn Buffer>>,
+    set: Box<dyn DescriptorSet>,
+    _pool: Box<dyn DescriptorPool>,
+}
+
+/// Line-list pass drawing [`DebugLineVertex`] lines (e.g. `VirtualGeometryManager::debug_overlay_lines`)
+/// inside the host's render pass, after the scene. The pipeline loads and stores the color target
+/// (alpha blended) and, with a depth format, depth-tests LessOrEqual against the scene without writing.
+pub struct DebugLinePass {
+    pipeline: Box<dyn GraphicsPipeline>,
+    set_layout: Box<dyn DescriptorSetLayout>,
+    slots: Vec<LineSlot>,
+}
+
+impl DebugLinePass {
+    /// `color_format` and `depth_format` must match the attachments of the render pass passed to
+    /// [`Self::record`].
+    pub fn new(device: &dyn Device, color_format: TextureFormat, depth_format: Option<TextureFormat>) -> Result<Self, String> {
+        let layout_bindings = vec![DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: DescriptorType::UniformBuffer,
+            count: 1,
+            stages: ShaderStages::VERTEX,
+        }];
+        let source = include_str!("../../shaders/debug_lines.wgsl");
+        let blend = BlendComponent {
+            src_factor: BlendFactor::SrcAlpha,
+            dst_factor: BlendFactor::OneMinusSrcAlpha,
+            operation: BlendOp::Add,
+        };
+        let pipeline = device.create_graphics_pipeline(&GraphicsPipelineDescriptor {
+            label: Some("lume_debug_lines"),
+            vertex_shader: ShaderStage {
+                source: compile_wgsl(source, ShaderStages::VERTEX, "vs_main")?,
+                entry_point: "vs_main".to_string(),
+            },
+            fragment_shader: Some(ShaderStage {
+                source: compile_wgsl(source, ShaderStages::FRAGMENT, "fs_main")?,
+                entry_point: "fs_main".to_string(),
+            }),
+            vertex_input: VertexInputDescriptor {
+                attributes: vec![
+                    VertexAttribute { location: 0, binding: 0, format: VertexFormat::Float32x3, offset: 0 },
+                    VertexAttribute { location: 1, binding: 0, format: VertexFormat::Float32x4, offset: 12 },
+                ],
+                bindings: vec![VertexBinding {
+                    binding: 0,
+                    stride: DEBUG_LINE_VERTEX_SIZE as u32,
+                    input_rate: VertexInputRate::Vertex,
+                }],
+            },
+            primitive_topology: PrimitiveTopology::LineList,
+            primitive_restart: false,
+            rasterization: RasterizationState::default(),
+            color_targets: vec![ColorTargetState {
+                format: color_format,
+                blend: Some(BlendState { color: blend, alpha: blend }),
+                load_op: Some(LoadOp::Load),
+                store_op: Some(StoreOp::Store),
+            }],
+            depth_stencil: depth_format.map(|format| DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: CompareOp::LessOrEqual,
+                depth_load_op: Some(LoadOp::Load),
+                depth_store_op: Some(StoreOp::Store),
+            }),
+            layout_bindings: layout_bindings.clone(),
+            subpass: None,
+        })?;
+        let set_layout = device.create_descriptor_set_layout(&layout_bindings)?;
+        Ok(Self { pipeline, set_layout, slots: Vec::new() })
+    }
+
+    /// Record `lines` (consecutive pairs form a segment) with the column-major `view_proj` into `pass`.
+    /// `slot` selects the buffers to write, one per frame in flight (e.g. the swapchain image index);
+    /// the caller must have waited for that slot's previous frame.
+    pub fn record(
+        &mut self,
+        device: &dyn Device,
+        pass: &mut dyn RenderPass,
+        view_proj: &[[f32; 4]; 4],
+        lines: &[DebugLineVertex],
+        slot: usize,
+    ) -> Result<(), String> {
+        if !lines.len().is_multiple_of(2) {
+            return Err(format!("DebugLinePass::record: {} vertices do not form whole segments", lines.len()));
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+        while self.slots.len() <= slot {
+            self.slots.push(create_line_slot(device, self.set_layout.as_ref())?);
+        }
+        let line_slot = &mut self.slots[slot];
+        let bytes = line_vertex_bytes(lines);
+        if line_slot.vertices.as_ref().is_none_or(|b| b.size() < bytes.len() as u64) {
+            line_slot.vertices = Some(device.create_buffer(&BufferDescriptor {
+                label: Some("lume_debug_line_vertices"),
+                size: (bytes.len() as u64).next_power_of_two(),
+                usage: BufferUsage::VERTEX,
+                memory: BufferMemoryPreference::HostVisible,
+            })?);
+        }
+        let vertices = line_slot.vertices.as_ref().expect("vertex buffer created above");
+        device.write_buffer(vertices.as_ref(), 0, &bytes)?;
+        let matrix: Vec<u8> = view_proj.iter().flatten().flat_map(|f| f.to_le_bytes()).collect();
+        device.write_buffer(line_slot.uniform.as_ref(), 0, &matrix)?;
+        pass.set_pipeline(self.pipeline.as_ref());
+        pass.bind_descriptor_set(0, line_slot.set.as_ref());
+        pass.set_vertex_buffer(0, vertices.as_ref(), 0);
+        pass.draw(lines.len() as u32, 1, 0, 0);
+        Ok(())
+    }
+}
+
+fn create_line_slot(device: &dyn Device, layout: &dyn DescriptorSetLayout) -> Result<LineSlot, String> {
+    let uniform = device.create_buffer(&BufferDescriptor {
+        label: Some("lume_debug_line_uniform"),
+        size: LINE_UNIFORM_SIZE,
+        usage: BufferUsage::UNIFORM,
+        memory: BufferMemoryPreference::HostVisible,
+    })?;
+    let pool = device.create_descriptor_pool(1)?;
+    let mut set = pool.allocate_set(layout)?;
+    set.write_buffer(0, uniform.as_ref(), 0, LINE_UNIFORM_SIZE)?;
+    Ok(LineSlot { uniform, vertices: None, set, _pool: pool })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lume_rhi::test_support::test_device;
+
+    #[test]
+    fn sphere_wireframe_lies_on_the_sphere() {
+        let mut lines = Vec::new();
+        push_sphere_wireframe(&mut lines, [1.0, 2.0, 3.0, 0.5], ClusterVisibility::Visible.color());
+        assert_eq!(lines.len(), 3 * SPHERE_SEGMENTS * 2);
+        for v in &lines {
+            let d = [v.position[0] - 1.0, v.position[1] - 2.0, v.position[2] - 3.0];
+            assert!(((d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() - 0.5).abs() < 1e-5);
+            assert_eq!(v.color, [0.0, 1.0, 0.0, 1.0]);
+        }
+        // Each circle closes on its first point.
+        assert!((lines[2 * SPHERE_SEGMENTS - 1].position[0] - lines[0].position[0]).abs() < 1e-5);
+    }
+
+    #[test]
+    fn line_vertices_match_the_shader_layout() {
+        assert_eq!(DEBUG_LINE_VERTEX_SIZE, 28);
+        let vertex = DebugLineVertex { position: [1.0, 2.0, 3.0], color: [0.5, 0.25, 0.0, 1.0] };
+        let bytes = line_vertex_bytes(&[vertex]);
+        assert_eq!(bytes.len(), DEBUG_LINE_VERTEX_SIZE);
+        assert_eq!(&bytes[12..16], &0.5f32.to_le_bytes());
+        let source = include_str!("../../shaders/debug_lines.wgsl");
+        assert!(!compile_wgsl(source, ShaderStages::VERTEX, "vs_main").unwrap().is_empty());
+        assert!(!compile_wgsl(source, ShaderStages::FRAGMENT, "fs_main").unwrap().is_empty());
+    }
+
+    /// The overlay records into a host render pass and rejects a dangling endpoint. Skipped without a
+    /// Vulkan device.
+    #[test]
+    fn debug_lines_record_into_a_render_pass() {
+        let Some(device) = test_device("debug_lines_record_into_a_render_pass") else { return };
+        let mut lines = Vec::new();
+        push_sphere_wireframe(&mut lines, [0.0, 0.0, 0.5, 0.25], ClusterVisibility::Visible.color());
+        let mut debug_pass = DebugLinePass::new(device.as_ref(), TextureFormat::Rgba8Unorm, None).unwrap();
+        let target = device
+            .create_texture(&lume_rhi::TextureDescriptor {
+                size: (16, 16, 1),
+                format: TextureFormat::Rgba8Unorm,
+                usage: lume_rhi::TextureUsage::RENDER_ATTACHMENT,
+                ..Default::default()
+            })
+            .unwrap();
+        let identity = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        let mut encoder = device.create_command_encoder().unwrap();
+        let mut pass = encoder
+            .begin_render_pass(lume_rhi::RenderPassDescriptor {
+                label: Some("debug_lines"),
+                color_attachments: vec![lume_rhi::ColorAttachment {
+                    texture: target.as_ref(),
+                    load_op: LoadOp::Clear,
+                    store_op: StoreOp::Store,
+                    clear_value: None,
+                    initial_layout: None,
+                }],
+                depth_stencil_attachment: None,
+                subpasses: Vec::new(),
+            })
+            .unwrap();
+        assert!(debug_pass.record(device.as_ref(), pass.as_mut(), &identity, &lines[..3], 0).is_err());
+        debug_pass.record(device.as_ref(), pass.as_mut(), &identity, &lines, 0).unwrap();
+        pass.end();
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+//! GPU cluster culling: cluster bounding spheres in a storage buffer, tested against the frustum by a
+//! compute shader that writes one indirect draw per cluster (instance count 0 when culled) and
+//! accumulates [`super::CullingStats`]. Driven by `VirtualGeometryManager` once GPU culling is enabled.
+
+use lume_rhi::{
+    Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, CommandEncoder, ComputePipeline,
+    ComputePipelineDescriptor, DescriptorPool, DescriptorSet, DescriptorSetLayout, DescriptorSetLayoutBinding,
+    DescriptorType, Device, ShaderStages,
+};
+
+use super::{Cluster, DrawIndexedIndirectCommand};
+use crate::shader::compile_wgsl;
+
+/// Must match `@workgroup_size` in shaders/vg_cull.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Bytes of `ClusterInput` in shaders/vg_cull.wgsl.
+const CLUSTER_INPUT_SIZE: usize = 32;
+
+/// Bytes of `CullParams` in shaders/vg_cull.wgsl: six planes, the cluster count and padding.
+const PARAMS_SIZE: usize = 112;
+
+/// Normalized frustum planes (left, right, bottom, top, near, far) of a column-major view-projection
+/// (`view_proj[column][row]`) with a [0, 1] depth range [Gribb & Hartmann 2001]. A point is inside when
+/// `dot(xyz, p) + w >= 0` for every plane, and that value is its distance to the plane.
+pub fn frustum_planes(view_proj: &[[f32; 4]; 4]) -> [[f32; 4]; 6] {
+    let row = |r: usize| [view_proj[0][r], view_proj[1][r], view_proj[2][r], view_proj[3][r]];
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let add = |a: [f32; 4], b: [f32; 4]| [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]];
+    let sub = |a: [f32; 4], b: [f32; 4]| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]];
+    [add(r3, r0), sub(r3, r0), add(r3, r1), sub(r3, r1), r2, sub(r3, r2)].map(|p| {
+        let len = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+        if len > 0.0 {
+            p.map(|v| v / len)
+        } else {
+            p
+        }
+    })
+}
+
+/// Whether a bounding sphere (xyz center, w radius) is at least partly inside `planes`.
+pub fn sphere_visible(planes: &[[f32; 4]; 6], sphere: [f32; 4]) -> bool {
+    planes.iter().all(|p| p[0] * sphere[0] + p[1] * sphere[1] + p[2] * sphere[2] + p[3] >= -sphere[3])
+}
+
+fn params_bytes(planes: &[[f32; 4]; 6], cluster_count: u32) -> Vec<u8> {
+    let mut out: Vec<u8> = planes.iter().flatten().flat_map(|v| v.to_le_bytes()).collect();
+    for word in [cluster_count, 0, 0, 0] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn cluster_bytes(cluster: &Cluster) -> [u8; CLUSTER_INPUT_SIZE] {
+    let s = cluster.bounding_sphere;
+    let words = [
+        s[0].to_bits(),
+        s[1].to_bits(),
+        s[2].to_bits(),
+        s[3].to_bits(),
+        cluster.triangle_count * 3,
+        cluster.index_offset,
+        cluster.vertex_offset,
+        0,
+    ];
+    let mut out = [0u8; CLUSTER_INPUT_SIZE];
+    for (chunk, w) in out.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&w.to_le_bytes());
+    }
+    out
+}
+
+/// Buffers and descriptor set of one set of clusters; rebuilt when meshes are uploaded.
+pub(super) struct GpuCullBuffers {
+    /// `DrawIndexedIndirectCommand` per cluster, written by the compute pass (`COPY_SRC` for readback).
+    pub(super) indirect: Box<dyn Buffer>,
+    pub(super) cluster_count: u32,
+    /// Host-visible copy of `indirect` for the debug overlay; set while the overlay is enabled.
+    pub(super) overlay_readback: Option<Box<dyn Buffer>>,
+    params: Box<dyn Buffer>,
+    _clusters: Box<dyn Buffer>,
+    set: Box<dyn DescriptorSet>,
+    _pool: Box<dyn DescriptorPool>,
+}
+
+pub(super) struct GpuClusterCuller {
+    pipeline: Box<dyn ComputePipeline>,
+    set_layout: Box<dyn DescriptorSetLayout>,
+}
+
+impl GpuClusterCuller {
+    pub(super) fn new(device: &dyn Device) -> Result<Self, String> {
+        let binding = |binding: u32, descriptor_type: DescriptorType| DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type,
+            count: 1,
+            stages: ShaderStages::COMPUTE,
+        };
+        let layout_bindings = vec![
+            binding(0, DescriptorType::StorageBuffer),
+            binding(1, DescriptorType::UniformBuffer),
+            binding(2, DescriptorType::StorageBuffer),
+            binding(3, DescriptorType::StorageBuffer),
+        ];
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("lume_vg_cull"),
+            shader_source: compile_wgsl(include_str!("../../shaders/vg_cull.wgsl"), ShaderStages::COMPUTE, "cs_main")?,
+            entry_point: "cs_main".to_string(),
+            layout_bindings: layout_bindings.clone(),
+        })?;
+        let set_layout = device.create_descriptor_set_layout(&layout_bindings)?;
+        Ok(Self { pipeline, set_layout })
+    }
+
+    /// Upload `clusters` and create the indirect buffer the compute pass writes, bound with `stats`.
+    /// `None` when there are no clusters.
+    pub(super) fn create_buffers<'a>(
+        &self,
+        device: &dyn Device,
+        clusters: impl Iterator<Item = &'a Cluster>,
+        stats: &dyn Buffer,
+    ) -> Result<Option<GpuCullBuffers>, String> {
+        let cluster_data: Vec<u8> = clusters.flat_map(cluster_bytes).collect();
+        let cluster_count = (cluster_data.len() / CLUSTER_INPUT_SIZE) as u32;
+        if cluster_count == 0 {
+            return Ok(None);
+        }
+        let clusters = device.create_buffer(&BufferDescriptor {
+            label: Some("vg_cull_clusters"),
+            size: cluster_data.len() as u64,
+            usage: BufferUsage::STORAGE,
+            memory: BufferMemoryPreference::HostVisible,
+        })?;
+        device.write_buffer(clusters.as_ref(), 0, &cluster_data)?;
+        let params = device.create_buffer(&BufferDescriptor {
+            label: Some("vg_cull_params"),
+            size: PARAMS_SIZE as u64,
+            usage: BufferUsage::UNIFORM,
+            memory: BufferMemoryPreference::HostVisible,
+        })?;
+        let indirect_size = cluster_count as u64 * std::mem::size_of::<DrawIndexedIndirectCommand>() as u64;
+        let indirect = device.create_buffer(&BufferDescriptor {
+            label: Some("vg_indirect"),
+            size: indirect_size,
+            usage: BufferUsage::INDIRECT | BufferUsage::STORAGE | BufferUsage::COPY_SRC,
+            memory: BufferMemoryPreference::DeviceLocal,
+        })?;
+        let pool = device.create_descriptor_pool(1)?;
+        let mut set = pool.allocate_set(self.set_layout.as_ref())?;
+        set.write_buffer(0, clusters.as_ref(), 0, cluster_data.len() as u64)?;
+        set.write_buffer(1, params.as_ref(), 0, PARAMS_SIZE as u64)?;
+        set.write_buffer(2, indirect.as_ref(), 0, indirect_size)?;
+        set.write_buffer(3, stats, 0, super::CullingStats::SIZE)?;
+        Ok(Some(GpuCullBuffers {
+            indirect,
+            cluster_count,
+            overlay_readback: None,
+            params,
+            _clusters: clusters,
+            set,
+            _pool: pool,
+        }))
+    }
+
+    /// Write this frame's frustum; the previous frame's commands must have completed.
+    pub(super) fn write_params(
+        &self,
+        device: &dyn Device,
+        buffers: &GpuCullBuffers,
+        view_proj: &[[f32; 4]; 4],
+    ) -> Result<(), String> {
+        device.write_buffer(buffers.params.as_ref(), 0, &params_bytes(&frustum_planes(view_proj), buffers.cluster_count))
+    }
+
+    /// Record the culling dispatch, then the barrier for the indirect draws that read its output.
+    pub(super) fn encode(&self, encoder: &mut dyn CommandEncoder, buffers: &GpuCullBuffers) -> Result<(), String> {
+        {
+            let mut pass = encoder.begin_compute_pass();
+            pass.set_pipeline(self.pipeline.as_ref());
+            pass.bind_descriptor_set(0, buffers.set.as_ref());
+            pass.dispatch(buffers.cluster_count.div_ceil(WORKGROUP_SIZE), 1, 1)?;
+        }
+        encoder.pipeline_barrier_buffer_indirect_read(buffers.indirect.as_ref(), 0, 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Looking down -z: depth = 0.5 - 0.5 * z, x and y in [-1, 1].
+    const VIEW_PROJ: [[f32; 4]; 4] =
+        [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, -0.5, 0.0], [0.0, 0.0, 0.5, 1.0]];
+
+    #[test]
+    fn planes_are_normalized_distances() {
+        let planes = frustum_planes(&VIEW_PROJ);
+        // Right plane x = 1 and far plane z = -1, facing inward.
+        assert_eq!(planes[1], [-1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(planes[5], [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn spheres_are_tested_against_the_clip_volume() {
+        let planes = frustum_planes(&VIEW_PROJ);
+        assert!(sphere_visible(&planes, [0.0, 0.0, 0.0, 0.1]));
+        // Outside the right plane by less than the radius, then by more.
+        assert!(sphere_visible(&planes, [1.2, 0.0, 0.0, 0.5]));
+        assert!(!sphere_visible(&planes, [1.6, 0.0, 0.0, 0.5]));
+        // Beyond the far plane (z = -1).
+        assert!(!sphere_visible(&planes, [0.0, 0.0, -2.0, 0.5]));
+    }
+
+    #[test]
+    fn layouts_match_wgsl() {
+        let bytes = params_bytes(&frustum_planes(&VIEW_PROJ), 7);
+        assert_eq!(bytes.len(), PARAMS_SIZE);
+        assert_eq!(&bytes[96..100], &7u32.to_le_bytes());
+        let cluster = Cluster { vertex_offset: 5, index_offset: 9, triangle_count: 4, bounding_sphere: [1.0, 2.0, 3.0, 0.5] };
+        let bytes = cluster_bytes(&cluster);
+        assert_eq!(&bytes[12..16], &0.5f32.to_le_bytes());
+        assert_eq!(&bytes[16..28], [12u32, 9, 5].map(u32::to_le_bytes).concat().as_slice());
+    }
+
+    #[test]
+    fn cull_shader_compiles_to_spirv() {
+        let spirv = compile_wgsl(include_str!("../../shaders/vg_cull.wgsl"), ShaderStages::COMPUTE, "cs_main").unwrap();
+        assert!(!spirv.is_empty());
+    }
+}
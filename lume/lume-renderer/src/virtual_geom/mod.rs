@@ -1,8 +1,17 @@
-//! Virtual geometry: cluster-based mesh representation and culling (CPU path; GPU culling TODO).
+//! Virtual geometry: cluster-based mesh representation and frustum culling, on the CPU or in a compute
+//! pass ([`VirtualGeometryManager::set_gpu_culling`]).
 
-use lume_rhi::{Buffer, BufferDescriptor, BufferUsage, Device};
+use lume_rhi::{Buffer, BufferDescriptor, BufferUsage, CommandEncoder, Device};
 use std::sync::Arc;
 
+pub mod debug_overlay;
+pub mod gpu_cull;
+
+pub use debug_overlay::{ClusterVisibility, DebugLinePass, DebugLineVertex};
+pub use gpu_cull::{frustum_planes, sphere_visible};
+
+use gpu_cull::{GpuClusterCuller, GpuCullBuffers};
+
 /// Represents a single cluster of triangles (e.g., 128 triangles).
 #[derive(Clone, Debug)]
 pub struct Cluster {
@@ -30,13 +39,63 @@ pub struct DrawIndexedIndirectCommand {
     pub first_instance: u32,
 }
 
+/// Cluster counts of one culling pass. The GPU culling compute accumulates them with atomics into
+/// [`VirtualGeometryManager::stats_buffer`] in this layout (four u32, 16 bytes).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    /// Clusters considered.
+    pub total: u32,
+    /// Clusters that produced a draw command.
+    pub visible: u32,
+    /// Clusters rejected by the view frustum.
+    pub frustum_culled: u32,
+    /// Clusters rejected by their normal cone (back-facing). Always 0 until clusters carry cone data.
+    pub cone_culled: u32,
+}
+
+impl CullingStats {
+    pub const SIZE: u64 = 16;
+
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        for (chunk, v) in out
+            .chunks_exact_mut(4)
+            .zip([self.total, self.visible, self.frustum_culled, self.cone_culled])
+        {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8; 16]) -> Self {
+        let word = |i: usize| u32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]]);
+        Self { total: word(0), visible: word(1), frustum_culled: word(2), cone_culled: word(3) }
+    }
+}
+
 pub struct VirtualGeometryManager {
     device: Arc<dyn Device>,
     meshes: Vec<VirtualMesh>,
     /// Indirect buffer filled each frame by prepare_culling_pass (CPU culling path).
     indirect_buffer: Option<Box<dyn Buffer>>,
+    /// Set while GPU culling is enabled.
+    gpu_culler: Option<GpuClusterCuller>,
+    /// Cluster upload and indirect output of the GPU path; `None` when stale (meshes uploaded since).
+    gpu_buffers: Option<GpuCullBuffers>,
     /// Number of draw commands written to indirect_buffer.
     indirect_draw_count: u32,
+    /// Host-visible [`CullingStats`] of the frame in flight; read back at the next prepare_culling_pass.
+    stats_buffer: Option<Box<dyn Buffer>>,
+    /// Stats computed by the CPU culling path this frame.
+    cpu_stats: CullingStats,
+    /// Stats read back from `stats_buffer` (previous frame).
+    last_frame_stats: Option<CullingStats>,
+    /// Record per-cluster culling results for [`Self::debug_overlay_lines`].
+    debug_overlay: bool,
+    /// Culling result per cluster of this frame (meshes in upload order, then clusters); empty unless
+    /// `debug_overlay` is on.
+    cluster_visibility: Vec<ClusterVisibility>,
 }
 
 impl VirtualGeometryManager {
@@ -45,25 +104,75 @@ impl VirtualGeometryManager {
             device,
             meshes: Vec::new(),
             indirect_buffer: None,
+            gpu_culler: None,
+            gpu_buffers: None,
             indirect_draw_count: 0,
+            stats_buffer: None,
+            cpu_stats: CullingStats::default(),
+            last_frame_stats: None,
+            debug_overlay: false,
+            cluster_visibility: Vec::new(),
         }
     }
 
     /// Registers a mesh. Buffers must be created by the caller (e.g. from lume-tools cluster output).
     pub fn upload_mesh(&mut self, mesh: VirtualMesh) {
         self.meshes.push(mesh);
+        self.gpu_buffers = None;
+    }
+
+    /// Cull clusters in a compute pass ([`Self::encode_culling`]) instead of on the CPU. Enabling
+    /// compiles the culling pipeline.
+    pub fn set_gpu_culling(&mut self, enabled: bool) -> Result<(), String> {
+        if enabled && self.gpu_culler.is_none() {
+            self.gpu_culler = Some(GpuClusterCuller::new(self.device.as_ref())?);
+        } else if !enabled {
+            self.gpu_culler = None;
+            self.gpu_buffers = None;
+        }
+        Ok(())
     }
 
-    /// CPU frustum culling (simplified: no frustum, accept all clusters) and fill indirect buffer.
-    /// View-proj matrix can be used for proper frustum-sphere test in a follow-up.
+    pub fn gpu_culling(&self) -> bool {
+        self.gpu_culler.is_some()
+    }
+
+    /// Frustum-cull every cluster's bounding sphere against `view_proj` (column-major, see
+    /// [`frustum_planes`]) and fill the indirect buffer. With GPU culling this only uploads the
+    /// frustum: [`Self::encode_culling`] writes one command per cluster on the GPU, culled ones with
+    /// instance count 0, and no CPU stats are recorded; the debug overlay results are then those of
+    /// the previous frame, read back like [`Self::last_frame_stats`].
+    ///
+    /// First reads back the previous frame's [`CullingStats`] (see [`Self::last_frame_stats`]), so the
+    /// command buffers of that frame must have completed (fence waited) before this call.
     pub fn prepare_culling_pass(
         &mut self,
-        _view_proj: [[f32; 4]; 4],
+        view_proj: [[f32; 4]; 4],
     ) -> Result<(), String> {
+        self.read_back_stats()?;
+        self.cluster_visibility.clear();
+        if self.gpu_culler.is_some() {
+            return self.prepare_gpu_culling(&view_proj);
+        }
+        let planes = frustum_planes(&view_proj);
+        let mut stats = CullingStats::default();
         let mut commands = Vec::<DrawIndexedIndirectCommand>::new();
         for mesh in &self.meshes {
             for cluster in &mesh.clusters {
-                // TODO: frustum-sphere test using view_proj
+                stats.total += 1;
+                let visible = sphere_visible(&planes, cluster.bounding_sphere);
+                if self.debug_overlay {
+                    self.cluster_visibility.push(if visible {
+                        ClusterVisibility::Visible
+                    } else {
+                        ClusterVisibility::FrustumCulled
+                    });
+                }
+                if !visible {
+                    stats.frustum_culled += 1;
+                    continue;
+                }
+                stats.visible += 1;
                 commands.push(DrawIndexedIndirectCommand {
                     index_count: cluster.triangle_count * 3,
                     instance_count: 1,
@@ -74,6 +183,8 @@ impl VirtualGeometryManager {
             }
         }
         self.indirect_draw_count = commands.len() as u32;
+        self.cpu_stats = stats;
+        self.write_stats(stats)?;
         if commands.is_empty() {
             self.indirect_buffer = None;
             return Ok(());
@@ -81,7 +192,7 @@ impl VirtualGeometryManager {
         let size = (commands.len() * std::mem::size_of::<DrawIndexedIndirectCommand>()) as u64;
         // Reuse existing buffer when size is sufficient to avoid per-frame allocation.
         let buf = match self.indirect_buffer.as_ref() {
-            Some(b) if b.size() >= size => self.indirect_buffer.take().unwrap(),
+            Some(b) if b.size() >= size && b.host_visible() => self.indirect_buffer.take().unwrap(),
             _ => self.device.create_buffer(&BufferDescriptor {
                 label: Some("vg_indirect"),
                 size,
@@ -100,16 +211,291 @@ impl VirtualGeometryManager {
         Ok(())
     }
 
-    /// Returns the indirect buffer and draw count for this frame (after prepare_culling_pass).
+    /// Upload the clusters when meshes changed, the frustum, and zeroed stats for [`Self::encode_culling`].
+    /// With the debug overlay on, first read back the previous frame's per-cluster results.
+    fn prepare_gpu_culling(&mut self, view_proj: &[[f32; 4]; 4]) -> Result<(), String> {
+        self.cpu_stats = CullingStats::default();
+        self.write_stats(CullingStats::default())?;
+        if self.debug_overlay {
+            self.read_back_cluster_visibility()?;
+        }
+        let culler = self.gpu_culler.as_ref().expect("GPU culling enabled");
+        if self.gpu_buffers.is_none() {
+            let stats = self.stats_buffer.as_deref().expect("stats buffer written above");
+            let clusters = self.meshes.iter().flat_map(|m| &m.clusters);
+            self.gpu_buffers = culler.create_buffers(self.device.as_ref(), clusters, stats)?;
+        }
+        if let Some(buffers) = self.gpu_buffers.as_mut() {
+            if !self.debug_overlay {
+                buffers.overlay_readback = None;
+            } else if buffers.overlay_readback.is_none() {
+                buffers.overlay_readback = Some(self.device.create_buffer(&BufferDescriptor {
+                    label: Some("vg_overlay_readback"),
+                    size: buffers.indirect.size(),
+                    usage: BufferUsage::COPY_DST,
+                    memory: lume_rhi::BufferMemoryPreference::HostVisible,
+                })?);
+            }
+        }
+        self.indirect_buffer = None;
+        self.indirect_draw_count = self.gpu_buffers.as_ref().map_or(0, |b| b.cluster_count);
+        match self.gpu_buffers.as_ref() {
+            Some(buffers) => culler.write_params(self.device.as_ref(), buffers, view_proj),
+            None => Ok(()),
+        }
+    }
+
+    /// Record the GPU culling pass prepared by prepare_culling_pass, before the draws of
+    /// [`Self::indirect_draw_info`] and with the stats readback barrier (and the copy of the draws for
+    /// the debug overlay while it is enabled). Nothing to record on the CPU path.
+    pub fn encode_culling(&self, encoder: &mut dyn CommandEncoder) -> Result<(), String> {
+        if let (Some(culler), Some(buffers)) = (&self.gpu_culler, &self.gpu_buffers) {
+            culler.encode(encoder, buffers)?;
+            if let Some(readback) = buffers.overlay_readback.as_deref() {
+                // Debug only: the coarse barrier orders the transfer after the culling writes.
+                encoder.pipeline_barrier_memory();
+                encoder.copy_buffer_to_buffer(buffers.indirect.as_ref(), 0, readback, 0, readback.size());
+                encoder.pipeline_barrier_buffer_host_read(readback, 0, 0);
+            }
+            self.encode_stats_readback_barrier(encoder);
+        }
+        Ok(())
+    }
+
+    /// Returns the indirect buffer and draw count for this frame (after prepare_culling_pass, and
+    /// [`Self::encode_culling`] with GPU culling).
     pub fn indirect_draw_info(&self) -> (Option<&dyn Buffer>, u32) {
-        (
-            self.indirect_buffer.as_deref(),
-            self.indirect_draw_count,
-        )
+        let buffer = match &self.gpu_buffers {
+            Some(buffers) if self.gpu_culler.is_some() => Some(buffers.indirect.as_ref()),
+            _ => self.indirect_buffer.as_deref(),
+        };
+        (buffer, self.indirect_draw_count)
     }
 
     /// All registered meshes (for iteration).
     pub fn meshes(&self) -> &[VirtualMesh] {
         &self.meshes
     }
+
+    /// Stats of this frame's CPU culling, available immediately after prepare_culling_pass (zero with
+    /// GPU culling, whose stats arrive through [`Self::last_frame_stats`]).
+    pub fn cpu_stats(&self) -> CullingStats {
+        self.cpu_stats
+    }
+
+    /// Stats read back from the GPU stats buffer. One frame latent: after prepare_culling_pass for
+    /// frame N this holds frame N-1 (`None` until a second frame has been prepared). This is the
+    /// value a GPU culling pass reports without stalling, and matches [`Self::cpu_stats`] of the
+    /// previous frame while culling runs on the CPU.
+    pub fn last_frame_stats(&self) -> Option<CullingStats> {
+        self.last_frame_stats
+    }
+
+    /// Enable recording per-cluster culling results (from the next prepare_culling_pass) for the debug
+    /// overlay. Off by default: it costs a Vec entry per cluster per frame, and with GPU culling a copy
+    /// and readback of the indirect draws.
+    pub fn set_debug_overlay(&mut self, enabled: bool) {
+        self.debug_overlay = enabled;
+        if !enabled {
+            self.cluster_visibility.clear();
+        }
+    }
+
+    pub fn debug_overlay(&self) -> bool {
+        self.debug_overlay
+    }
+
+    /// Culling result per cluster of the last prepare_culling_pass, meshes in upload order; empty
+    /// unless the debug overlay is enabled. With GPU culling these are the previous frame's results
+    /// (empty for the first frame and after meshes are uploaded), matching [`Self::last_frame_stats`].
+    pub fn cluster_visibility(&self) -> &[ClusterVisibility] {
+        &self.cluster_visibility
+    }
+
+    /// Line-list vertices of every cluster's bounding sphere, colored by its culling result (see
+    /// [`ClusterVisibility::color`]), in the clusters' space. Draw them with depth test off to see
+    /// culled clusters behind geometry. Empty unless the debug overlay is enabled.
+    pub fn debug_overlay_lines(&self) -> Vec<DebugLineVertex> {
+        let mut lines = Vec::new();
+        let clusters = self.meshes.iter().flat_map(|m| &m.clusters);
+        for (cluster, visibility) in clusters.zip(&self.cluster_visibility) {
+            debug_overlay::push_sphere_wireframe(&mut lines, cluster.bounding_sphere, visibility.color());
+        }
+        lines
+    }
+
+    /// Host-visible, storage-bindable [`CullingStats`] buffer for the frame being prepared. A GPU
+    /// culling pass overwrites it and must then call [`Self::encode_stats_readback_barrier`].
+    pub fn stats_buffer(&self) -> Option<&dyn Buffer> {
+        self.stats_buffer.as_deref()
+    }
+
+    /// Record the barrier that makes the culling compute's stats writes visible to the host read
+    /// at the next prepare_culling_pass.
+    pub fn encode_stats_readback_barrier(&self, encoder: &mut dyn CommandEncoder) {
+        if let Some(buf) = self.stats_buffer.as_deref() {
+            encoder.pipeline_barrier_buffer_host_read(buf, 0, CullingStats::SIZE);
+        }
+    }
+
+    fn read_back_stats(&mut self) -> Result<(), String> {
+        if let Some(buf) = self.stats_buffer.as_deref() {
+            let mut bytes = [0u8; 16];
+            self.device.read_buffer(buf, 0, &mut bytes)?;
+            self.last_frame_stats = Some(CullingStats::from_bytes(&bytes));
+        }
+        Ok(())
+    }
+
+    /// Fill `cluster_visibility` from the instance counts of the draws the last GPU culling pass wrote
+    /// (its command buffer must have completed). Nothing to read before the first pass with the
+    /// overlay enabled.
+    fn read_back_cluster_visibility(&mut self) -> Result<(), String> {
+        let Some(buffers) = self.gpu_buffers.as_ref() else { return Ok(()) };
+        let Some(readback) = buffers.overlay_readback.as_deref() else { return Ok(()) };
+        let mut bytes = vec![0u8; readback.size() as usize];
+        self.device.read_buffer(readback, 0, &mut bytes)?;
+        let command_size = std::mem::size_of::<DrawIndexedIndirectCommand>();
+        self.cluster_visibility.extend(bytes.chunks_exact(command_size).map(|command| {
+            let instance_count = u32::from_le_bytes([command[4], command[5], command[6], command[7]]);
+            if instance_count > 0 {
+                ClusterVisibility::Visible
+            } else {
+                ClusterVisibility::FrustumCulled
+            }
+        }));
+        Ok(())
+    }
+
+    /// Seed the stats buffer with the CPU result; a GPU culling pass replaces it on the GPU timeline.
+    fn write_stats(&mut self, stats: CullingStats) -> Result<(), String> {
+        if self.stats_buffer.is_none() {
+            self.stats_buffer = Some(self.device.create_buffer(&BufferDescriptor {
+                label: Some("vg_culling_stats"),
+                size: CullingStats::SIZE,
+                usage: BufferUsage::STORAGE | BufferUsage::COPY_DST,
+                memory: lume_rhi::BufferMemoryPreference::HostVisible,
+            })?);
+        }
+        let buf = self.stats_buffer.as_deref().expect("stats buffer created above");
+        self.device.write_buffer(buf, 0, &stats.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lume_rhi::test_support::test_device;
+
+    #[test]
+    fn culling_stats_byte_layout() {
+        let stats = CullingStats { total: 10, visible: 6, frustum_culled: 3, cone_culled: 1 };
+        let bytes = stats.to_bytes();
+        assert_eq!(bytes.len() as u64, CullingStats::SIZE);
+        assert_eq!(std::mem::size_of::<CullingStats>() as u64, CullingStats::SIZE);
+        assert_eq!(&bytes[8..12], &3u32.to_le_bytes());
+        assert_eq!(CullingStats::from_bytes(&bytes), stats);
+    }
+
+    /// GPU culling writes the same draws and stats as the CPU path. Skipped without a Vulkan device.
+    #[test]
+    fn gpu_culling_matches_cpu() {
+        let Some(device) = test_device("gpu_culling_matches_cpu") else { return };
+        let buffer = |usage: BufferUsage, size: u64| {
+            device
+                .create_buffer(&BufferDescriptor {
+                    label: None,
+                    size,
+                    usage,
+                    memory: lume_rhi::BufferMemoryPreference::HostVisible,
+                })
+                .unwrap()
+        };
+        // Inside, straddling the right plane, and outside it.
+        let cluster = |x: f32, index_offset: u32| Cluster {
+            vertex_offset: 0,
+            index_offset,
+            triangle_count: 2,
+            bounding_sphere: [x, 0.0, 0.0, 0.5],
+        };
+        let mut manager = VirtualGeometryManager::new(device.clone());
+        manager.upload_mesh(VirtualMesh {
+            clusters: vec![cluster(0.0, 0), cluster(1.2, 6), cluster(3.0, 12)],
+            vertex_buffer: buffer(BufferUsage::VERTEX, 64),
+            index_buffer: buffer(BufferUsage::INDEX, 64),
+        });
+        // Looking down -z, x and y in [-1, 1].
+        let view_proj = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, -0.5, 0.0], [0.0, 0.0, 0.5, 1.0]];
+        manager.prepare_culling_pass(view_proj).unwrap();
+        let cpu_stats = manager.cpu_stats();
+        assert_eq!(cpu_stats, CullingStats { total: 3, visible: 2, frustum_culled: 1, cone_culled: 0 });
+        assert_eq!(manager.indirect_draw_info().1, 2);
+
+        manager.set_gpu_culling(true).unwrap();
+        manager.prepare_culling_pass(view_proj).unwrap();
+        let (indirect, count) = manager.indirect_draw_info();
+        assert_eq!(count, 3);
+        let size = count as u64 * std::mem::size_of::<DrawIndexedIndirectCommand>() as u64;
+        let readback = buffer(BufferUsage::COPY_DST, size);
+        let mut encoder = device.create_command_encoder().unwrap();
+        manager.encode_culling(encoder.as_mut()).unwrap();
+        encoder.pipeline_barrier_memory();
+        encoder.copy_buffer_to_buffer(indirect.unwrap(), 0, readback.as_ref(), 0, size);
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+        let mut bytes = vec![0u8; size as usize];
+        device.read_buffer(readback.as_ref(), 0, &mut bytes).unwrap();
+        let words: Vec<u32> = bytes.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+        let commands: Vec<&[u32]> = words.chunks_exact(5).collect();
+        assert_eq!(commands.iter().map(|c| c[1]).collect::<Vec<_>>(), vec![1, 1, 0]);
+        assert_eq!(commands[1], [6, 1, 6, 0, 0]);
+        manager.prepare_culling_pass(view_proj).unwrap();
+        assert_eq!(manager.last_frame_stats(), Some(cpu_stats));
+    }
+
+    /// With GPU culling the debug overlay shows the previous frame's per-cluster results, read back
+    /// alongside its stats. Skipped without a Vulkan device.
+    #[test]
+    fn gpu_culling_feeds_the_debug_overlay() {
+        let Some(device) = test_device("gpu_culling_feeds_the_debug_overlay") else { return };
+        let buffer = |usage: BufferUsage| {
+            device
+                .create_buffer(&BufferDescriptor {
+                    label: None,
+                    size: 64,
+                    usage,
+                    memory: lume_rhi::BufferMemoryPreference::HostVisible,
+                })
+                .unwrap()
+        };
+        let cluster = |x: f32| Cluster { vertex_offset: 0, index_offset: 0, triangle_count: 2, bounding_sphere: [x, 0.0, 0.0, 0.5] };
+        let mut manager = VirtualGeometryManager::new(device.clone());
+        manager.upload_mesh(VirtualMesh {
+            clusters: vec![cluster(0.0), cluster(1.2), cluster(3.0)],
+            vertex_buffer: buffer(BufferUsage::VERTEX),
+            index_buffer: buffer(BufferUsage::INDEX),
+        });
+        manager.set_gpu_culling(true).unwrap();
+        manager.set_debug_overlay(true);
+        let view_proj = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, -0.5, 0.0], [0.0, 0.0, 0.5, 1.0]];
+        manager.prepare_culling_pass(view_proj).unwrap();
+        // Nothing culled on the GPU yet.
+        assert!(manager.cluster_visibility().is_empty());
+        let mut encoder = device.create_command_encoder().unwrap();
+        manager.encode_culling(encoder.as_mut()).unwrap();
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+
+        manager.prepare_culling_pass(view_proj).unwrap();
+        use ClusterVisibility::{FrustumCulled, Visible};
+        assert_eq!(manager.cluster_visibility(), [Visible, Visible, FrustumCulled]);
+        assert_eq!(manager.last_frame_stats(), Some(CullingStats { total: 3, visible: 2, frustum_culled: 1, cone_culled: 0 }));
+        let lines = manager.debug_overlay_lines();
+        assert_eq!(lines.len(), 3 * 3 * debug_overlay::SPHERE_SEGMENTS * 2);
+        assert_eq!(lines.last().unwrap().color, FrustumCulled.color());
+
+        manager.set_debug_overlay(false);
+        manager.prepare_culling_pass(view_proj).unwrap();
+        assert!(manager.cluster_visibility().is_empty());
+    }
 }
@@ -33,6 +33,18 @@ pub enum TextureFormat {
     Rgba32Float,
 }
 
+impl TextureFormat {
+    /// Size of one texel, as laid out by [`CommandEncoder::copy_texture_to_buffer`].
+    pub fn bytes_per_texel(self) -> u32 {
+        match self {
+            TextureFormat::R16Float => 2,
+            TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm | TextureFormat::R32Float | TextureFormat::D32Float => 4,
+            TextureFormat::Rgba16Float => 8,
+            TextureFormat::Rgba32Float => 16,
+        }
+    }
+}
+
 /// Texture dimension / type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TextureDimension {
@@ -48,15 +60,25 @@ pub trait Device: Send + Sync + Debug {
     fn create_buffer(&self, desc: &BufferDescriptor) -> Result<Box<dyn Buffer>, String>;
     fn create_texture(&self, desc: &TextureDescriptor) -> Result<Box<dyn Texture>, String>;
     fn create_sampler(&self, desc: &SamplerDescriptor) -> Result<Box<dyn Sampler>, String>;
+    /// Create a compute pipeline. Backends may return a shared pipeline for a descriptor they have
+    /// seen before (same SPIR-V, entry point and layout bindings); the label is not part of the key.
+    /// Such a cache may evict pipelines that no caller holds any more. Fails if the shader's workgroup
+    /// size exceeds [`DeviceLimits::validate_workgroup_size`].
     fn create_compute_pipeline(
         &self,
         desc: &ComputePipelineDescriptor,
     ) -> Result<Box<dyn ComputePipeline>, String>;
+    /// Always create a new compute pipeline, bypassing any cache used by [`Self::create_compute_pipeline`].
+    fn create_compute_pipeline_uncached(
+        &self,
+        desc: &ComputePipelineDescriptor,
+    ) -> Result<Box<dyn ComputePipeline>, String>;
     /// Create a graphics pipeline. The pipeline is created against a compatible render pass configuration.
     /// **Compatibility:** When recording with [`CommandEncoder::begin_render_pass`], the runtime
-    /// `RenderPassDescriptor` must use the same color/depth formats and load/store ops as the
-    /// `color_targets` and `depth_stencil` in this descriptor, so that the Vulkan render pass
-    /// (or backend equivalent) is compatible with the pipeline.
+    /// `RenderPassDescriptor` must use the same color/depth formats as the `color_targets` and
+    /// `depth_stencil` in this descriptor, so that the Vulkan render pass (or backend equivalent) is
+    /// compatible with the pipeline. The pipeline does not own that render pass; it is shared with
+    /// recording through the device and stays valid after the pipeline is dropped.
     fn create_graphics_pipeline(
         &self,
         desc: &GraphicsPipelineDescriptor,
@@ -91,6 +113,10 @@ pub trait Device: Send + Sync + Debug {
     /// Write data into a buffer (CPU to GPU). Buffer must be host-visible (Buffer::host_visible() == true).
     fn write_buffer(&self, buffer: &dyn Buffer, offset: u64, data: &[u8]) -> Result<(), String>;
 
+    /// Read `out.len()` bytes from a host-visible buffer (GPU to CPU). The caller must ensure the GPU
+    /// writes have completed (fence or wait_idle), e.g. after [`CommandEncoder::copy_texture_to_buffer`].
+    fn read_buffer(&self, buffer: &dyn Buffer, offset: u64, out: &mut [u8]) -> Result<(), String>;
+
     /// Upload data into any buffer (HostVisible or DeviceLocal).
     /// For HostVisible buffers, uses write_buffer. For DeviceLocal, uses staging buffer + copy.
     /// DeviceLocal buffers must have BufferUsage::COPY_DST. Blocks until upload completes.
@@ -134,6 +160,41 @@ pub trait Device: Send + Sync + Debug {
     /// Wait for the device to become idle (all submitted work finished).
     fn wait_idle(&self) -> Result<(), String>;
 
+    /// Copy mip `mip_level` of `texture` (every slice or array layer) to host memory, tightly packed as
+    /// [`CommandEncoder::copy_texture_to_buffer`] lays it out, e.g. for screenshots and golden-image
+    /// tests. `layout` is the texture's current layout; it is moved to [`ImageLayout::TransferSrc`] for
+    /// the copy and back afterwards. The texture needs `COPY_SRC` usage. Blocks until the device is idle.
+    fn read_texture(&self, texture: &dyn Texture, mip_level: u32, layout: ImageLayout) -> Result<Vec<u8>, String> {
+        if layout == ImageLayout::Undefined {
+            return Err("read_texture: texture in ImageLayout::Undefined has no contents".to_string());
+        }
+        if mip_level >= texture.mip_level_count() {
+            return Err(format!("read_texture: mip {} of {}", mip_level, texture.mip_level_count()));
+        }
+        let size = texture.mip_size(mip_level);
+        let len = size.0 as u64 * size.1 as u64 * size.2 as u64 * texture.format().bytes_per_texel() as u64;
+        let staging = self.create_buffer(&BufferDescriptor {
+            label: Some("read_texture_staging"),
+            size: len,
+            usage: BufferUsage::COPY_DST,
+            memory: BufferMemoryPreference::HostVisible,
+        })?;
+        let mip = TextureSubresourceRange::mip(mip_level);
+        let mut encoder = self.create_command_encoder()?;
+        if layout != ImageLayout::TransferSrc {
+            encoder.pipeline_barrier_texture_range(texture, layout, ImageLayout::TransferSrc, mip);
+        }
+        encoder.copy_texture_to_buffer(texture, mip_level, (0, 0, 0), size, staging.as_ref(), 0);
+        if layout != ImageLayout::TransferSrc {
+            encoder.pipeline_barrier_texture_range(texture, ImageLayout::TransferSrc, layout, mip);
+        }
+        self.submit(vec![encoder.finish()?])?;
+        self.wait_idle()?;
+        let mut out = vec![0u8; len as usize];
+        self.read_buffer(staging.as_ref(), 0, &mut out)?;
+        Ok(out)
+    }
+
     /// Create a fence for CPU-GPU synchronization.
     fn create_fence(&self, signaled: bool) -> Result<Box<dyn Fence>, String>;
     /// Create a semaphore for GPU-GPU synchronization.
@@ -150,6 +211,250 @@ pub trait Device: Send + Sync + Debug {
         let _ = (extent, old_swapchain);
         Err("Swapchain not supported (device created without surface)".to_string())
     }
+
+    /// Whether the device's surface was created for `window` (`DeviceCreateParams::surface`), so
+    /// swapchains present to it. Always false for devices without a surface.
+    #[cfg(feature = "window")]
+    fn surface_matches(&self, window: raw_window_handle::RawWindowHandle) -> bool {
+        let _ = window;
+        false
+    }
+
+    /// Hardware limits of the device. The default returns the minimums guaranteed by the Vulkan spec.
+    fn limits(&self) -> DeviceLimits {
+        DeviceLimits::default()
+    }
+
+    /// Optional features the device was created with. The default reports none.
+    fn features(&self) -> DeviceFeatures {
+        DeviceFeatures::default()
+    }
+
+    /// Usages and sample counts `format` supports for optimally tiled 2D textures, so callers can pick
+    /// a fallback before creation fails. The default returns what the Vulkan spec guarantees.
+    fn format_properties(&self, format: TextureFormat) -> FormatProperties {
+        FormatProperties::guaranteed(format)
+    }
+
+    /// Current device-local memory budget and usage, plus what this device has allocated, so hosts
+    /// can evict streamed resources or lower quality before allocations fail. The default reports zeros.
+    fn memory_budget(&self) -> MemoryBudget {
+        MemoryBudget::default()
+    }
+
+    /// Move buffers out of sparsely used memory blocks into fuller ones and free the emptied blocks,
+    /// so large allocations keep succeeding in long-running, streaming-heavy apps. Buffers only:
+    /// textures are suballocated from pools of their own, which this leaves as they are. A buffer moves
+    /// only when created with [`BufferUsage::COPY_SRC`] and [`BufferUsage::COPY_DST`]; blocks holding
+    /// any other buffer, and dedicated allocations, stay where they are.
+    ///
+    /// **Synchronization:** call at a safe point. The device waits idle first, but no other thread may
+    /// use the device or its buffers during the call. Moved buffers get a new backend buffer: descriptor
+    /// sets that reference them (see [`DefragmentReport::moved_buffers`]) must be rewritten, and
+    /// command buffers recorded before the call must not be submitted again. The default moves nothing.
+    fn defragment(&self) -> Result<DefragmentReport, String> {
+        Ok(DefragmentReport::default())
+    }
+}
+
+/// Typed writes over [`Device::write_buffer`] for [`bytemuck::Pod`] data, implemented for every
+/// device (including `dyn Device`). Unlike the raw method, these check that the data fits the buffer.
+pub trait DeviceExt: Device {
+    /// Write one `value` at byte `offset` (e.g. a uniform struct).
+    fn write_buffer_typed<T: bytemuck::Pod>(&self, buffer: &dyn Buffer, offset: u64, value: &T) -> Result<(), String> {
+        self.write_buffer_slice(buffer, offset, std::slice::from_ref(value))
+    }
+
+    /// Write `values` contiguously starting at byte `offset` (e.g. vertices).
+    fn write_buffer_slice<T: bytemuck::Pod>(&self, buffer: &dyn Buffer, offset: u64, values: &[T]) -> Result<(), String> {
+        let data: &[u8] = bytemuck::cast_slice(values);
+        check_buffer_range(buffer, offset, data.len() as u64)?;
+        self.write_buffer(buffer, offset, data)
+    }
+}
+
+impl<D: Device + ?Sized> DeviceExt for D {}
+
+/// Err when `len` bytes at `offset` do not fit in `buffer`.
+fn check_buffer_range(buffer: &dyn Buffer, offset: u64, len: u64) -> Result<(), String> {
+    match offset.checked_add(len) {
+        Some(end) if end <= buffer.size() => Ok(()),
+        _ => Err(format!("write_buffer: {} bytes at offset {} exceed buffer size {}", len, offset, buffer.size())),
+    }
+}
+
+/// Optional device features; backends enable every one the hardware supports at creation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceFeatures {
+    /// [`RasterizationState::depth_clamp`] is available (Vulkan `depthClamp`).
+    pub depth_clamp: bool,
+    /// [`TextureDescriptor::transient`] textures get lazily allocated memory (Vulkan
+    /// `LAZILY_ALLOCATED` memory type, common on tiled mobile and integrated GPUs).
+    pub lazily_allocated_memory: bool,
+}
+
+/// Memory pressure reported by [`Device::memory_budget`]. Device-local figures are summed over every
+/// device-local heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryBudget {
+    /// Bytes of device-local memory this process can use without paging or failing allocations. The
+    /// total heap size when the driver reports no budget (`from_driver == false`).
+    pub device_local_budget: u64,
+    /// Bytes of device-local memory in use by this process. Without a driver report, only what this
+    /// device allocated itself.
+    pub device_local_usage: u64,
+    /// Bytes held by this device's buffers and textures in all heaps.
+    pub allocated: u64,
+    /// Bytes of device memory this device reserved for them (shared blocks and dedicated
+    /// allocations); at least `allocated`.
+    pub reserved: u64,
+    /// Live device memory allocations (Vulkan `vkAllocateMemory`), which drivers cap at
+    /// `maxMemoryAllocationCount` (often 4096). Resources share blocks, so this stays far below the
+    /// resource count.
+    pub device_memory_count: u32,
+    /// Budget and usage come from the driver (Vulkan `VK_EXT_memory_budget`).
+    pub from_driver: bool,
+}
+
+impl MemoryBudget {
+    /// Device-local bytes left before the budget is reached.
+    pub fn device_local_available(&self) -> u64 {
+        self.device_local_budget.saturating_sub(self.device_local_usage)
+    }
+}
+
+/// What [`Device::defragment`] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefragmentReport {
+    /// Buffers that moved; descriptor sets referencing them must be rewritten.
+    pub moved_buffers: Vec<ResourceId>,
+    /// Bytes copied.
+    pub moved_bytes: u64,
+    /// Device memory released back to the driver, and how many allocations that was.
+    pub freed_bytes: u64,
+    pub freed_device_memory: u32,
+}
+
+/// Capabilities of a [`TextureFormat`] on a device (see [`Device::format_properties`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatProperties {
+    /// Usages a texture of this format may be created with.
+    pub usages: TextureUsage,
+    /// Supported sample counts as a bitmask of the counts themselves (bit value 4 set = 4x MSAA).
+    /// Always contains 1 when any usage is supported.
+    pub sample_counts: u32,
+    /// Whether the format can be both source and destination of a [`FilterMode::Linear`] blit, as
+    /// [`CommandEncoder::generate_mipmaps`] records.
+    pub linear_blit: bool,
+}
+
+impl FormatProperties {
+    /// Support required of every Vulkan implementation ("Required Format Support" tables).
+    pub fn guaranteed(format: TextureFormat) -> Self {
+        let all = TextureUsage::COPY_SRC
+            | TextureUsage::COPY_DST
+            | TextureUsage::TEXTURE_BINDING
+            | TextureUsage::STORAGE_BINDING
+            | TextureUsage::RENDER_ATTACHMENT
+            | TextureUsage::INPUT_ATTACHMENT;
+        let usages = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::R32Float | TextureFormat::Rgba16Float | TextureFormat::Rgba32Float => all,
+            TextureFormat::Bgra8Unorm | TextureFormat::R16Float => all - TextureUsage::STORAGE_BINDING,
+            // Only one of D32 and D24S8 has to be a depth attachment.
+            TextureFormat::D32Float => TextureUsage::COPY_SRC | TextureUsage::COPY_DST | TextureUsage::TEXTURE_BINDING,
+        };
+        // Minimum framebufferColorSampleCounts / framebufferDepthSampleCounts.
+        let sample_counts = if usages.contains(TextureUsage::RENDER_ATTACHMENT) { 1 | 4 } else { 1 };
+        // Linear filtering of 32-bit float formats is optional.
+        let linear_blit = matches!(
+            format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm | TextureFormat::Rgba16Float | TextureFormat::R16Float
+        );
+        Self { usages, sample_counts, linear_blit }
+    }
+
+    /// Whether a texture may be created with all of `usage`.
+    pub fn supports(&self, usage: TextureUsage) -> bool {
+        self.usages.contains(usage)
+    }
+
+    /// Whether `count` (1, 2, 4, ...) samples are supported as a render attachment.
+    pub fn supports_sample_count(&self, count: u32) -> bool {
+        count.is_power_of_two() && self.sample_counts & count != 0
+    }
+}
+
+/// Device limits relevant to resource creation and command recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceLimits {
+    /// Max workgroups per dispatch in each dimension (maxComputeWorkGroupCount).
+    pub max_compute_work_group_count: [u32; 3],
+    /// Max local workgroup size in each dimension (maxComputeWorkGroupSize).
+    pub max_compute_work_group_size: [u32; 3],
+    /// Max total invocations in one local workgroup (maxComputeWorkGroupInvocations).
+    pub max_compute_work_group_invocations: u32,
+    /// Max bytes of push constants a pipeline can use (maxPushConstantsSize).
+    pub max_push_constants_size: u32,
+}
+
+impl Default for DeviceLimits {
+    /// Minimum values required by the Vulkan specification.
+    fn default() -> Self {
+        Self {
+            max_compute_work_group_count: [65535, 65535, 65535],
+            max_compute_work_group_size: [128, 128, 64],
+            max_compute_work_group_invocations: 128,
+            max_push_constants_size: 128,
+        }
+    }
+}
+
+impl DeviceLimits {
+    /// Check dispatch dimensions against `max_compute_work_group_count`. Zero-sized dispatches are rejected
+    /// since they are almost always a bug in the caller's size computation.
+    pub fn validate_dispatch(&self, x: u32, y: u32, z: u32) -> Result<(), String> {
+        if x == 0 || y == 0 || z == 0 {
+            return Err(format!("dispatch({}, {}, {}): workgroup count must be non-zero", x, y, z));
+        }
+        let max = self.max_compute_work_group_count;
+        if x > max[0] || y > max[1] || z > max[2] {
+            return Err(format!(
+                "dispatch({}, {}, {}) exceeds max workgroup count ({}, {}, {})",
+                x, y, z, max[0], max[1], max[2]
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that `len` bytes of push constants at `offset` fit in `max_push_constants_size`.
+    pub fn validate_push_constants(&self, offset: u32, len: u32) -> Result<(), String> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.max_push_constants_size => Ok(()),
+            _ => Err(format!(
+                "push constants: {} bytes at offset {} exceed maxPushConstantsSize {}",
+                len, offset, self.max_push_constants_size
+            )),
+        }
+    }
+
+    /// Check a local workgroup size against `max_compute_work_group_size` and `max_compute_work_group_invocations`.
+    pub fn validate_workgroup_size(&self, x: u32, y: u32, z: u32) -> Result<(), String> {
+        let max = self.max_compute_work_group_size;
+        if x == 0 || y == 0 || z == 0 || x > max[0] || y > max[1] || z > max[2] {
+            return Err(format!(
+                "workgroup size ({}, {}, {}) outside limits ({}, {}, {})",
+                x, y, z, max[0], max[1], max[2]
+            ));
+        }
+        let invocations = x as u64 * y as u64 * z as u64;
+        if invocations > self.max_compute_work_group_invocations as u64 {
+            return Err(format!(
+                "workgroup size ({}, {}, {}) has {} invocations, max is {}",
+                x, y, z, invocations, self.max_compute_work_group_invocations
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// Fence: CPU can wait for GPU to complete submitted work.
@@ -212,6 +517,12 @@ pub trait Buffer: Send + Sync + Debug {
     fn host_visible(&self) -> bool {
         true
     }
+    /// False when host writes through a mapping must be flushed (and device writes invalidated before
+    /// reading) because the memory is not HOST_COHERENT. `Device::write_buffer`/`read_buffer` handle
+    /// this; code holding its own mapping must too. Always true for device-local buffers.
+    fn host_coherent(&self) -> bool {
+        true
+    }
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -224,6 +535,11 @@ pub struct TextureDescriptor {
     pub usage: TextureUsage,
     pub dimension: TextureDimension,
     pub mip_level_count: u32,
+    /// Render attachment that only lives within render passes (loaded with clear or don't-care, stored
+    /// with don't-care), e.g. depth or GBuffer targets resolved in the same pass. Backends give it
+    /// lazily allocated memory where available ([`DeviceFeatures::lazily_allocated_memory`]), which
+    /// tiled GPUs never back with physical memory; otherwise it is a normal attachment.
+    pub transient: bool,
 }
 
 impl Default for TextureDescriptor {
@@ -235,7 +551,32 @@ impl Default for TextureDescriptor {
             usage: TextureUsage::empty(),
             dimension: TextureDimension::D2,
             mip_level_count: 1,
+            transient: false,
+        }
+    }
+}
+
+impl TextureDescriptor {
+    /// Transient textures must be single-mip 2D render attachments with no other usage than input
+    /// attachment, since their contents never leave the render pass. Input attachments must also be
+    /// render attachments.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.usage.contains(TextureUsage::INPUT_ATTACHMENT) && !self.usage.contains(TextureUsage::RENDER_ATTACHMENT) {
+            return Err(format!("texture {:?}: INPUT_ATTACHMENT usage requires RENDER_ATTACHMENT", self.label));
         }
+        let attachment_only = TextureUsage::RENDER_ATTACHMENT | TextureUsage::INPUT_ATTACHMENT;
+        if self.transient
+            && (!attachment_only.contains(self.usage)
+                || !self.usage.contains(TextureUsage::RENDER_ATTACHMENT)
+                || self.dimension != TextureDimension::D2
+                || self.mip_level_count > 1)
+        {
+            return Err(format!(
+                "texture {:?}: transient textures must be single-mip 2D with only RENDER_ATTACHMENT (and INPUT_ATTACHMENT) usage, got {:?} {:?} with {} mips",
+                self.label, self.usage, self.dimension, self.mip_level_count
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -247,6 +588,8 @@ bitflags::bitflags! {
         const TEXTURE_BINDING = 1 << 2;
         const STORAGE_BINDING = 1 << 3;
         const RENDER_ATTACHMENT = 1 << 4;
+        /// Read as an input attachment by a later subpass; needs RENDER_ATTACHMENT as well.
+        const INPUT_ATTACHMENT = 1 << 5;
     }
 }
 
@@ -257,6 +600,52 @@ pub trait Texture: Send + Sync + Debug {
     fn dimension(&self) -> TextureDimension;
     fn mip_level_count(&self) -> u32;
     fn as_any(&self) -> &dyn Any;
+
+    /// Size of mip `level`: width and height halve (rounding down, at least 1), as does depth for 3D
+    /// textures; array layers do not.
+    fn mip_size(&self, level: u32) -> (u32, u32, u32) {
+        let (width, height, depth) = self.size();
+        let depth = if self.dimension() == TextureDimension::D3 { (depth >> level).max(1) } else { depth };
+        ((width >> level).max(1), (height >> level).max(1), depth)
+    }
+}
+
+/// A box within one mip level of a texture, for [`CommandEncoder::blit_texture`]. For 3D textures the
+/// third coordinate is depth; otherwise it selects array layers (`origin.2` the first, `size.2` the count).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureRegion {
+    pub mip_level: u32,
+    pub origin: (u32, u32, u32),
+    pub size: (u32, u32, u32),
+}
+
+impl TextureRegion {
+    /// All of mip `level`, including every array layer.
+    pub fn whole_mip(texture: &dyn Texture, level: u32) -> Self {
+        Self { mip_level: level, origin: (0, 0, 0), size: texture.mip_size(level) }
+    }
+}
+
+/// Mip levels and array layers affected by a texture barrier (cube faces are layers 0..6).
+/// A `None` count extends to the last mip or layer, so the default range is the whole texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextureSubresourceRange {
+    pub base_mip_level: u32,
+    pub mip_level_count: Option<u32>,
+    pub base_array_layer: u32,
+    pub array_layer_count: Option<u32>,
+}
+
+impl TextureSubresourceRange {
+    /// A single mip level across all layers (e.g. one step of mipmap generation).
+    pub fn mip(level: u32) -> Self {
+        Self { base_mip_level: level, mip_level_count: Some(1), ..Default::default() }
+    }
+
+    /// A single array layer or cube face across all mips (e.g. one shadow cascade).
+    pub fn layer(layer: u32) -> Self {
+        Self { base_array_layer: layer, array_layer_count: Some(1), ..Default::default() }
+    }
 }
 
 /// Filter mode for sampler min/mag.
@@ -277,6 +666,16 @@ pub enum AddressMode {
     ClampToBorder,
 }
 
+/// Color returned for [`AddressMode::ClampToBorder`] lookups outside the texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BorderColor {
+    #[default]
+    TransparentBlack,
+    OpaqueBlack,
+    /// E.g. shadow maps: depth 1.0 outside the map, so those samples are lit.
+    OpaqueWhite,
+}
+
 #[derive(Debug, Clone)]
 pub struct SamplerDescriptor {
     pub label: Option<&'static str>,
@@ -286,6 +685,8 @@ pub struct SamplerDescriptor {
     pub address_mode_v: AddressMode,
     pub address_mode_w: AddressMode,
     pub anisotropy_clamp: Option<f32>,
+    /// Only used by `ClampToBorder` address modes.
+    pub border_color: BorderColor,
 }
 
 impl Default for SamplerDescriptor {
@@ -298,6 +699,7 @@ impl Default for SamplerDescriptor {
             address_mode_v: AddressMode::Repeat,
             address_mode_w: AddressMode::Repeat,
             anisotropy_clamp: None,
+            border_color: BorderColor::TransparentBlack,
         }
     }
 }
@@ -336,11 +738,73 @@ pub struct GraphicsPipelineDescriptor {
     pub fragment_shader: Option<ShaderStage>,
     pub vertex_input: VertexInputDescriptor,
     pub primitive_topology: PrimitiveTopology,
+    /// Treat the all-ones index (0xFFFF for u16, 0xFFFFFFFF for u32) as the end of a strip, so one
+    /// indexed draw can hold several strips. Only valid with strip topologies; see
+    /// [`Self::validate_primitive_restart`].
+    pub primitive_restart: bool,
     pub rasterization: RasterizationState,
     pub color_targets: Vec<ColorTargetState>,
     pub depth_stencil: Option<DepthStencilState>,
     /// Descriptor set layout bindings for UBO/sampled image etc. Used to create pipeline layout.
     pub layout_bindings: Vec<DescriptorSetLayoutBinding>,
+    /// Create the pipeline for one subpass of a multi-subpass render pass instead of a single-subpass
+    /// pass made from `color_targets` / `depth_stencil`. `color_targets` then lists the subpass's color
+    /// attachments in order, and `depth_stencil` is set exactly when the subpass depth-tests.
+    pub subpass: Option<PipelineSubpass>,
+}
+
+/// The render pass layout and subpass a [`GraphicsPipelineDescriptor`] is drawn in. Formats and
+/// subpasses must equal those of the [`RenderPassDescriptor`] passed to `begin_render_pass`.
+#[derive(Debug, Clone)]
+pub struct PipelineSubpass {
+    pub color_formats: Vec<TextureFormat>,
+    pub depth_format: Option<TextureFormat>,
+    pub subpasses: Vec<SubpassDescription>,
+    /// Subpass the pipeline is bound in.
+    pub index: u32,
+}
+
+impl GraphicsPipelineDescriptor {
+    /// Check that primitive restart is only requested for a strip topology; list topologies have no
+    /// strips to cut, and Vulkan forbids restart on them without an extension.
+    pub fn validate_primitive_restart(&self) -> Result<(), String> {
+        if self.primitive_restart && !self.primitive_topology.is_strip() {
+            return Err(format!(
+                "{:?}: primitive_restart requires a strip topology, got {:?}",
+                self.label, self.primitive_topology
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that [`Self::subpass`] describes a valid pass and that `color_targets` / `depth_stencil`
+    /// match the attachments of the chosen subpass.
+    pub fn validate_subpass(&self) -> Result<(), String> {
+        let Some(target) = &self.subpass else {
+            return Ok(());
+        };
+        validate_subpasses(&target.subpasses, target.color_formats.len() as u32, target.depth_format.is_some())
+            .map_err(|e| format!("{:?}: {}", self.label, e))?;
+        let subpass = target.subpasses.get(target.index as usize).ok_or_else(|| {
+            format!("{:?}: subpass {} out of range ({} subpasses)", self.label, target.index, target.subpasses.len())
+        })?;
+        let formats: Vec<_> = subpass.color_attachments.iter().map(|&i| target.color_formats[i as usize]).collect();
+        let targets: Vec<_> = self.color_targets.iter().map(|t| t.format).collect();
+        if formats != targets {
+            return Err(format!(
+                "{:?}: color_targets {:?} do not match the color attachments {:?} of subpass {}",
+                self.label, targets, formats, target.index
+            ));
+        }
+        let depth = if subpass.depth_stencil { target.depth_format } else { None };
+        if self.depth_stencil.as_ref().map(|d| d.format) != depth {
+            return Err(format!(
+                "{:?}: depth_stencil must be {:?} in subpass {}",
+                self.label, depth, target.index
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -391,14 +855,26 @@ pub enum PrimitiveTopology {
     TriangleList,
     TriangleStrip,
     LineList,
+    LineStrip,
     PointList,
 }
 
+impl PrimitiveTopology {
+    /// True for topologies where consecutive primitives share vertices (and primitive restart applies).
+    pub fn is_strip(self) -> bool {
+        matches!(self, PrimitiveTopology::TriangleStrip | PrimitiveTopology::LineStrip)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct RasterizationState {
     pub cull_mode: CullMode,
     pub front_face: FrontFace,
     pub polygon_mode: PolygonMode,
+    /// Clamp depth to the viewport range instead of clipping at the near/far planes. For shadow maps:
+    /// casters between the light and its near plane still write depth ("pancaking"), so they are not
+    /// lost from the map. Requires [`DeviceFeatures::depth_clamp`]; pipeline creation fails otherwise.
+    pub depth_clamp: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -501,6 +977,62 @@ pub struct RenderPassDescriptor<'a> {
     pub label: Option<&'static str>,
     pub color_attachments: Vec<ColorAttachment<'a>>,
     pub depth_stencil_attachment: Option<DepthStencilAttachment<'a>>,
+    /// Subpasses recorded in order with [`RenderPass::next_subpass`]. Empty is one subpass writing
+    /// every attachment, the usual separate-pass setup; several let e.g. a lighting subpass read the
+    /// GBuffer written by the previous one as input attachments, which tiled GPUs keep on chip.
+    pub subpasses: Vec<SubpassDescription>,
+}
+
+/// One subpass of a multi-subpass render pass. Attachment indices are in framebuffer order: the color
+/// attachments first, then the depth attachment at index `color_attachments.len()`.
+///
+/// Input attachments are read in the fragment shader at the current pixel only. In GLSL,
+/// `layout(input_attachment_index = i, set = 0, binding = b) uniform subpassInput g;` with
+/// `subpassLoad(g)` reads `input_attachments[i]` through binding `b`, which is declared as
+/// [`DescriptorType::InputAttachment`] (fragment stage) and written with
+/// [`DescriptorSet::write_input_attachment`] using the same texture as the attachment. WGSL has no
+/// input attachments, so these shaders come from GLSL or HLSL, or from WGSL through lume-renderer's
+/// `compile_wgsl_with_input_attachments`.
+///
+/// Dependencies between subpasses that write and then read or rewrite an attachment are derived from
+/// the descriptions by the backend.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SubpassDescription {
+    /// Attachments written by fragment outputs `location = 0, 1, ...`.
+    pub color_attachments: Vec<u32>,
+    /// Attachments read with `input_attachment_index = 0, 1, ...`.
+    pub input_attachments: Vec<u32>,
+    /// Depth-test against the pass's depth attachment. Not allowed together with reading the depth
+    /// attachment as an input in the same subpass.
+    pub depth_stencil: bool,
+}
+
+/// Check `subpasses` of a pass with `color_count` color attachments and, when `has_depth`, a depth
+/// attachment: indices in range, no attachment both written and read in one subpass.
+pub fn validate_subpasses(subpasses: &[SubpassDescription], color_count: u32, has_depth: bool) -> Result<(), String> {
+    let attachment_count = color_count + has_depth as u32;
+    for (index, subpass) in subpasses.iter().enumerate() {
+        if subpass.depth_stencil && !has_depth {
+            return Err(format!("subpass {} depth-tests but the pass has no depth attachment", index));
+        }
+        for (n, &color) in subpass.color_attachments.iter().enumerate() {
+            if color >= color_count {
+                return Err(format!("subpass {}: color attachment {} is not a color attachment of the pass", index, color));
+            }
+            if subpass.color_attachments[..n].contains(&color) || subpass.input_attachments.contains(&color) {
+                return Err(format!("subpass {}: attachment {} is used twice", index, color));
+            }
+        }
+        for &input in &subpass.input_attachments {
+            if input >= attachment_count {
+                return Err(format!("subpass {}: input attachment {} out of range ({} attachments)", index, input, attachment_count));
+            }
+            if input == color_count && subpass.depth_stencil {
+                return Err(format!("subpass {} reads depth as an input attachment while depth-testing", index));
+            }
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
@@ -521,6 +1053,30 @@ pub struct ClearColor {
     pub a: f32,
 }
 
+/// Pixel rectangle in a render target, origin at the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect2D {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect2D {
+    /// Check that the rect is non-empty and lies inside a `width` x `height` render area.
+    pub fn validate_within(&self, width: u32, height: u32) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("{:?}: rect must not be empty", self));
+        }
+        let right = self.x as u64 + self.width as u64;
+        let bottom = self.y as u64 + self.height as u64;
+        if right > width as u64 || bottom > height as u64 {
+            return Err(format!("{:?} exceeds the render area ({}x{})", self, width, height));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DepthStencilAttachment<'a> {
     pub texture: &'a dyn Texture,
@@ -566,12 +1122,69 @@ pub trait CommandEncoder: Debug {
         dst_origin: (u32, u32, u32),
         size: (u32, u32, u32),
     );
-    /// Insert a pipeline barrier for layout transitions and synchronization.
+    /// Copy a texture region into a buffer, tightly packed (row, then slice). The source texture must be in
+    /// [`ImageLayout::TransferSrc`]. The copy is made visible to host reads ([`Device::read_buffer`]) once
+    /// the submission completes. As in [`TextureRegion`], the third coordinate selects array layers of
+    /// non-3D textures. Swapchain images can be copied when the surface allows transfer reads.
+    /// [`Device::read_texture`] wraps this for one-off readbacks.
+    fn copy_texture_to_buffer(
+        &mut self,
+        src: &dyn Texture,
+        src_mip: u32,
+        src_origin: (u32, u32, u32),
+        size: (u32, u32, u32),
+        dst: &dyn Buffer,
+        dst_offset: u64,
+    );
+    /// Copy `src_region` of `src` into `dst_region` of `dst`, scaling with `filter` when the sizes
+    /// differ. The source must be in [`ImageLayout::TransferSrc`] and the destination in
+    /// [`ImageLayout::TransferDst`]; [`FilterMode::Linear`] needs [`FormatProperties::linear_blit`].
+    fn blit_texture(
+        &mut self,
+        src: &dyn Texture,
+        src_region: TextureRegion,
+        dst: &dyn Texture,
+        dst_region: TextureRegion,
+        filter: FilterMode,
+    );
+    /// Fill mips 1.. of `texture` by successively halving mip 0 with linear blits, leaving every mip in
+    /// [`ImageLayout::ShaderReadOnly`] for trilinear sampling. The whole texture must be in
+    /// [`ImageLayout::TransferDst`] with mip 0 written (e.g. after [`Self::copy_buffer_to_texture`]),
+    /// created with `COPY_SRC | COPY_DST` and a format with [`FormatProperties::linear_blit`].
+    fn generate_mipmaps(&mut self, texture: &dyn Texture) {
+        for level in 1..texture.mip_level_count() {
+            let src = TextureSubresourceRange::mip(level - 1);
+            self.pipeline_barrier_texture_range(texture, ImageLayout::TransferDst, ImageLayout::TransferSrc, src);
+            self.blit_texture(
+                texture,
+                TextureRegion::whole_mip(texture, level - 1),
+                texture,
+                TextureRegion::whole_mip(texture, level),
+                FilterMode::Linear,
+            );
+            self.pipeline_barrier_texture_range(texture, ImageLayout::TransferSrc, ImageLayout::ShaderReadOnly, src);
+        }
+        let last = TextureSubresourceRange::mip(texture.mip_level_count().max(1) - 1);
+        self.pipeline_barrier_texture_range(texture, ImageLayout::TransferDst, ImageLayout::ShaderReadOnly, last);
+    }
+    /// Insert a pipeline barrier for layout transitions and synchronization, covering every mip level
+    /// and array layer of `texture`.
     fn pipeline_barrier_texture(
         &mut self,
         texture: &dyn Texture,
         old_layout: ImageLayout,
         new_layout: ImageLayout,
+    ) {
+        self.pipeline_barrier_texture_range(texture, old_layout, new_layout, TextureSubresourceRange::default());
+    }
+    /// Like [`Self::pipeline_barrier_texture`], limited to `range` (e.g. one mip while generating
+    /// mipmaps, or one layer of a cascade array or cubemap).
+    fn pipeline_barrier_texture_range(
+        &mut self,
+        texture: &dyn Texture,
+        old_layout: ImageLayout,
+        new_layout: ImageLayout,
+        range: TextureSubresourceRange,
     );
     /// Insert a pipeline barrier for buffer memory (e.g. compute write -> graphics/compute read).
     /// Uses shader write -> shader read with compute stage to fragment/vertex/compute.
@@ -581,6 +1194,19 @@ pub trait CommandEncoder: Debug {
         offset: u64,
         size: u64,
     );
+    /// Order a later write to a buffer after earlier reads of it (write-after-read): an execution
+    /// dependency from vertex input, indirect, shader and transfer reads to shader and transfer writes.
+    fn pipeline_barrier_buffer_after_read(&mut self, buffer: &dyn Buffer, offset: u64, size: u64);
+    /// Full memory barrier: every earlier command finishes and its writes become visible before any
+    /// later command starts. Much coarser than the resource barriers; for accesses they cannot name.
+    fn pipeline_barrier_memory(&mut self);
+    /// Make compute shader and transfer writes to a host-visible buffer visible to
+    /// [`Device::read_buffer`] once the command buffer's fence has signaled (e.g. GPU statistics).
+    /// `size` 0 means to the end of the buffer.
+    fn pipeline_barrier_buffer_host_read(&mut self, buffer: &dyn Buffer, offset: u64, size: u64);
+    /// Make compute shader writes to a buffer visible to later indirect draws and dispatches that read
+    /// their arguments from it (e.g. GPU culling output). `size` 0 means to the end of the buffer.
+    fn pipeline_barrier_buffer_indirect_read(&mut self, buffer: &dyn Buffer, offset: u64, size: u64);
     fn finish(self: Box<Self>) -> Result<Box<dyn CommandBuffer>, String>;
 }
 
@@ -601,6 +1227,8 @@ pub enum ImageLayout {
 pub trait RenderPass: Debug {
     fn set_pipeline(&mut self, pipeline: &dyn GraphicsPipeline);
     /// Bind a descriptor set for the currently bound graphics pipeline (set_index must match layout).
+    /// A set holding input attachments the current subpass does not declare is not bound, and the
+    /// command encoder's `finish` returns the error.
     fn bind_descriptor_set(&mut self, set_index: u32, set: &dyn DescriptorSet);
     fn set_vertex_buffer(&mut self, index: u32, buffer: &dyn Buffer, offset: u64);
     fn set_index_buffer(&mut self, buffer: &dyn Buffer, offset: u64, index_format: IndexFormat);
@@ -615,6 +1243,15 @@ pub trait RenderPass: Debug {
     );
     /// Draw indexed indirect. For VG, use draw_count > 1 and stride = sizeof(DrawIndexedIndirectCommand).
     fn draw_indexed_indirect(&mut self, buffer: &dyn Buffer, offset: u64, draw_count: u32, stride: u32);
+    /// Clear `rect` of the pass's attachments (e.g. one view of a split screen) without touching the
+    /// rest: `color` clears every color attachment, `depth` the depth attachment. Returns `Err` (without
+    /// recording) when the rect is empty or outside the render area, or `depth` is set without a depth
+    /// attachment.
+    fn clear_region(&mut self, rect: Rect2D, color: Option<ClearColor>, depth: Option<f32>) -> Result<(), String>;
+    /// Move on to the next of [`RenderPassDescriptor::subpasses`]; pipelines bound afterwards must be
+    /// created for that subpass ([`GraphicsPipelineDescriptor::subpass`]). Returns `Err` (without
+    /// recording) in the last subpass.
+    fn next_subpass(&mut self) -> Result<(), String>;
     fn end(self: Box<Self>);
 }
 
@@ -627,13 +1264,15 @@ pub enum IndexFormat {
 pub trait ComputePass: Debug {
     fn set_pipeline(&mut self, pipeline: &dyn ComputePipeline);
     fn bind_descriptor_set(&mut self, set_index: u32, set: &dyn DescriptorSet);
-    fn dispatch(&mut self, x: u32, y: u32, z: u32);
+    /// Dispatch `x * y * z` workgroups. Returns `Err` (without recording) when a dimension is zero or exceeds
+    /// [`DeviceLimits::max_compute_work_group_count`].
+    fn dispatch(&mut self, x: u32, y: u32, z: u32) -> Result<(), String>;
     /// Dispatch compute using indirect buffer (offset in bytes to VkDispatchIndirectCommand: x, y, z).
     fn dispatch_indirect(&mut self, buffer: &dyn Buffer, offset: u64);
 }
 
 /// Descriptor binding type for layout.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DescriptorType {
     UniformBuffer,
     StorageBuffer,
@@ -641,6 +1280,9 @@ pub enum DescriptorType {
     SampledImage,
     /// Image + sampler in one binding; use write_sampled_image to bind both.
     CombinedImageSampler,
+    /// Attachment of the current render pass read by a later subpass (`subpassInput`, fragment stage
+    /// only); write it with [`DescriptorSet::write_input_attachment`]. See [`SubpassDescription`].
+    InputAttachment,
 }
 
 /// Descriptor set layout binding.
@@ -652,6 +1294,16 @@ pub struct DescriptorSetLayoutBinding {
     pub stages: ShaderStages,
 }
 
+impl DescriptorSetLayoutBinding {
+    /// Input attachments can only be read by fragment shaders.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.descriptor_type == DescriptorType::InputAttachment && self.stages != ShaderStages::FRAGMENT {
+            return Err(format!("binding {}: input attachments are fragment-only, got {:?}", self.binding, self.stages));
+        }
+        Ok(())
+    }
+}
+
 /// Descriptor for creating a descriptor pool with configurable per-type capacities.
 /// When `pool_sizes` is empty, backends use a default (e.g. max_sets * 4 per type).
 #[derive(Debug, Clone, Default)]
@@ -663,7 +1315,7 @@ pub struct DescriptorPoolDescriptor {
 }
 
 bitflags::bitflags! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct ShaderStages: u32 {
         const VERTEX = 1 << 0;
         const FRAGMENT = 1 << 1;
@@ -706,9 +1358,26 @@ pub trait DescriptorSet: Send + Sync + Debug {
         texture: &dyn Texture,
         sampler: &dyn Sampler,
     ) -> Result<(), String>;
+    /// Bind `texture` to an [`DescriptorType::InputAttachment`] binding. The set may then only be bound
+    /// in a subpass that lists `texture` among its input attachments (see [`validate_input_attachments`]).
+    fn write_input_attachment(&mut self, binding: u32, texture: &dyn Texture) -> Result<(), String>;
     fn as_any(&self) -> &dyn Any;
 }
 
+/// Check that every input attachment written to a descriptor set, as `(binding, texture)`, is one of
+/// the input attachments `declared` by the subpass the set is bound in.
+pub fn validate_input_attachments(bound: &[(u32, ResourceId)], declared: &[ResourceId]) -> Result<(), String> {
+    for &(binding, texture) in bound {
+        if !declared.contains(&texture) {
+            return Err(format!(
+                "binding {}: texture {} is not an input attachment of the current subpass (declared {:?})",
+                binding, texture, declared
+            ));
+        }
+    }
+    Ok(())
+}
+
 pub trait CommandBuffer: Send + Sync + Debug {
     fn as_any(&self) -> &dyn Any;
 }
@@ -717,16 +1386,45 @@ pub trait CommandBuffer: Send + Sync + Debug {
 pub struct SwapchainFrame<'a> {
     pub image_index: u32,
     pub texture: &'a dyn Texture,
+    /// Layout the image is in at acquire, as tracked by the swapchain (`Undefined` on first use,
+    /// `PresentSrc` after a present). Transition from this to `ColorAttachment` before rendering.
+    pub layout: ImageLayout,
+}
+
+/// Timeout [`Swapchain::acquire_next_image`] waits for an image before giving up on the frame.
+pub const DEFAULT_ACQUIRE_TIMEOUT_NS: u64 = 100_000_000;
+
+/// Outcome of acquiring a swapchain image.
+pub enum SwapchainAcquire<'a> {
+    Frame(SwapchainFrame<'a>),
+    /// No image became available within the timeout (e.g. the compositor stalled). The wait semaphore
+    /// is not signaled; skip rendering this frame and try again on the next one.
+    WouldBlock,
 }
 
 /// Swapchain for presenting to a window. Acquire an image, render to it, then present.
 pub trait Swapchain: Send + Sync + Debug {
     fn as_any(&self) -> &dyn Any;
-    /// Acquire the next image. Returns (image_index, texture to use as color attachment).
-    /// Wait semaphore will be signaled when the image is available.
-    fn acquire_next_image(&mut self, wait_semaphore: Option<&dyn Semaphore>) -> Result<SwapchainFrame<'_>, String>;
+    /// Acquire the next image, waiting at most [`DEFAULT_ACQUIRE_TIMEOUT_NS`].
+    fn acquire_next_image(&mut self, wait_semaphore: Option<&dyn Semaphore>) -> Result<SwapchainAcquire<'_>, String> {
+        self.acquire_next_image_timeout(wait_semaphore, DEFAULT_ACQUIRE_TIMEOUT_NS)
+    }
+    /// Acquire the next image, waiting at most `timeout_ns` (0 polls, `u64::MAX` waits forever).
+    /// The frame holds the image index and the texture to use as color attachment; the wait semaphore
+    /// will be signaled when the image is available.
+    fn acquire_next_image_timeout(
+        &mut self,
+        wait_semaphore: Option<&dyn Semaphore>,
+        timeout_ns: u64,
+    ) -> Result<SwapchainAcquire<'_>, String>;
     /// Present the image. Wait semaphore should be signaled when rendering to that image is done.
+    /// The image must have been transitioned to `PresentSrc`; its tracked layout becomes `PresentSrc`.
     fn present(&self, image_index: u32, wait_semaphore: Option<&dyn Semaphore>) -> Result<(), String>;
+    /// Tracked layout of a swapchain image (see [`SwapchainFrame::layout`]).
+    fn image_layout(&self, image_index: u32) -> ImageLayout;
+    /// Override the tracked layout, for callers that transition swapchain images themselves
+    /// (e.g. presenting from a different layout or skipping a frame after acquire).
+    fn set_image_layout(&self, image_index: u32, layout: ImageLayout);
     /// Current extent (width, height). May change on resize.
     fn extent(&self) -> (u32, u32);
     /// Number of swapchain images (for layout tracking).
@@ -837,10 +1535,199 @@ fn create_device_impl_with_surface(
     Ok(device)
 }
 
+mod spirv;
+
+/// SPIR-V descriptor binding reflection; opt in with the `reflect` feature.
+#[cfg(any(test, feature = "reflect"))]
+pub mod reflect;
+
 #[cfg(feature = "vulkan")]
 pub mod vulkan;
 
+/// Fixtures for GPU tests; opt in with the `test-support` feature.
+#[cfg(all(feature = "vulkan", any(test, feature = "test-support")))]
+pub mod test_support;
+
 /// Vulkan backend. Re-exported for advanced use (e.g. Vulkan-specific extensions).
 /// Prefer [`create_device`] for backend-agnostic code.
 #[cfg(feature = "vulkan")]
-pub use vulkan::VulkanDevice;
\ No newline at end of file
+pub use vulkan::VulkanDevice;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch_limits() {
+        let limits = DeviceLimits::default();
+        assert!(limits.validate_dispatch(1, 1, 1).is_ok());
+        assert!(limits.validate_dispatch(65535, 1, 1).is_ok());
+        assert!(limits.validate_dispatch(65536, 1, 1).is_err());
+        assert!(limits.validate_dispatch(0, 1, 1).is_err());
+        assert!(limits.validate_workgroup_size(8, 8, 2).is_ok());
+        assert!(limits.validate_workgroup_size(16, 16, 1).is_err());
+        assert!(limits.validate_push_constants(64, 64).is_ok());
+        assert!(limits.validate_push_constants(64, 68).is_err());
+        assert!(limits.validate_push_constants(u32::MAX, 4).is_err());
+    }
+
+    #[test]
+    fn clear_rect_must_fit_render_area() {
+        let rect = Rect2D { x: 16, y: 8, width: 48, height: 56 };
+        assert!(rect.validate_within(64, 64).is_ok());
+        assert!(rect.validate_within(63, 64).is_err());
+        assert!(rect.validate_within(64, 63).is_err());
+        assert!(Rect2D { width: 0, ..rect }.validate_within(64, 64).is_err());
+        assert!(Rect2D { x: u32::MAX, ..rect }.validate_within(64, 64).is_err());
+    }
+
+    #[test]
+    fn primitive_restart_needs_a_strip() {
+        let stage = ShaderStage { source: Vec::new(), entry_point: "main".to_string() };
+        let mut desc = GraphicsPipelineDescriptor {
+            label: Some("strips"),
+            vertex_shader: stage,
+            fragment_shader: None,
+            vertex_input: Default::default(),
+            primitive_topology: PrimitiveTopology::TriangleStrip,
+            primitive_restart: true,
+            rasterization: Default::default(),
+            color_targets: Vec::new(),
+            depth_stencil: None,
+            layout_bindings: Vec::new(),
+            subpass: None,
+        };
+        assert!(desc.validate_primitive_restart().is_ok());
+        desc.primitive_topology = PrimitiveTopology::LineStrip;
+        assert!(desc.validate_primitive_restart().is_ok());
+        desc.primitive_topology = PrimitiveTopology::TriangleList;
+        assert!(desc.validate_primitive_restart().is_err());
+        desc.primitive_restart = false;
+        assert!(desc.validate_primitive_restart().is_ok());
+    }
+
+    #[test]
+    fn gbuffer_and_lighting_subpasses() {
+        // Two GBuffer targets, an HDR target and depth (attachment 3).
+        let gbuffer = SubpassDescription { color_attachments: vec![0, 1], input_attachments: vec![], depth_stencil: true };
+        let lighting = SubpassDescription { color_attachments: vec![2], input_attachments: vec![0, 1, 3], depth_stencil: false };
+        let subpasses = vec![gbuffer.clone(), lighting.clone()];
+        assert!(validate_subpasses(&subpasses, 3, true).is_ok());
+        assert!(validate_subpasses(&subpasses, 3, false).is_err());
+        let depth_tested = SubpassDescription { depth_stencil: true, ..lighting.clone() };
+        assert!(validate_subpasses(&[gbuffer.clone(), depth_tested], 3, true).is_err());
+        let feedback = SubpassDescription { color_attachments: vec![0], ..lighting.clone() };
+        assert!(validate_subpasses(&[gbuffer.clone(), feedback], 3, true).is_err());
+        let depth_as_color = SubpassDescription { color_attachments: vec![3], ..Default::default() };
+        assert!(validate_subpasses(&[depth_as_color], 3, true).is_err());
+
+        let stage = ShaderStage { source: Vec::new(), entry_point: "main".to_string() };
+        let target = |format| ColorTargetState { format, blend: None, load_op: None, store_op: None };
+        let mut desc = GraphicsPipelineDescriptor {
+            label: Some("lighting"),
+            vertex_shader: stage,
+            fragment_shader: None,
+            vertex_input: Default::default(),
+            primitive_topology: PrimitiveTopology::TriangleList,
+            primitive_restart: false,
+            rasterization: Default::default(),
+            color_targets: vec![target(TextureFormat::Rgba16Float)],
+            depth_stencil: None,
+            layout_bindings: Vec::new(),
+            subpass: Some(PipelineSubpass {
+                color_formats: vec![TextureFormat::Rgba8Unorm, TextureFormat::Rgba16Float, TextureFormat::Rgba16Float],
+                depth_format: Some(TextureFormat::D32Float),
+                subpasses,
+                index: 1,
+            }),
+        };
+        assert!(desc.validate_subpass().is_ok());
+        desc.color_targets = vec![target(TextureFormat::Rgba8Unorm)];
+        assert!(desc.validate_subpass().is_err());
+        desc.color_targets = vec![target(TextureFormat::Rgba8Unorm), target(TextureFormat::Rgba16Float)];
+        desc.subpass.as_mut().unwrap().index = 0;
+        assert!(desc.validate_subpass().unwrap_err().contains("depth_stencil"));
+        desc.subpass.as_mut().unwrap().index = 2;
+        assert!(desc.validate_subpass().is_err());
+    }
+
+    #[test]
+    fn input_attachments_must_be_declared_by_the_subpass() {
+        assert!(validate_input_attachments(&[(0, 7), (1, 9)], &[9, 7, 8]).is_ok());
+        assert!(validate_input_attachments(&[], &[]).is_ok());
+        let err = validate_input_attachments(&[(0, 7), (1, 3)], &[7]).unwrap_err();
+        assert!(err.starts_with("binding 1: texture 3"), "{}", err);
+
+        let mut binding = DescriptorSetLayoutBinding {
+            binding: 0,
+            descriptor_type: DescriptorType::InputAttachment,
+            count: 1,
+            stages: ShaderStages::FRAGMENT,
+        };
+        assert!(binding.validate().is_ok());
+        binding.stages = ShaderStages::FRAGMENT | ShaderStages::COMPUTE;
+        assert!(binding.validate().is_err());
+        binding.descriptor_type = DescriptorType::SampledImage;
+        assert!(binding.validate().is_ok());
+    }
+
+    #[derive(Debug)]
+    struct SizedBuffer(u64);
+
+    impl Buffer for SizedBuffer {
+        fn id(&self) -> ResourceId {
+            0
+        }
+        fn size(&self) -> u64 {
+            self.0
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn buffer_range_must_fit() {
+        let ubo = SizedBuffer(256);
+        assert!(check_buffer_range(&ubo, 0, 256).is_ok());
+        assert!(check_buffer_range(&ubo, 240, 16).is_ok());
+        assert!(check_buffer_range(&ubo, 241, 16).is_err());
+        assert!(check_buffer_range(&ubo, u64::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn transient_textures_are_attachment_only() {
+        let transient = TextureDescriptor {
+            format: TextureFormat::D32Float,
+            usage: TextureUsage::RENDER_ATTACHMENT,
+            transient: true,
+            ..Default::default()
+        };
+        assert!(transient.validate().is_ok());
+        assert!(TextureDescriptor { usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::TEXTURE_BINDING, ..transient.clone() }
+            .validate()
+            .is_err());
+        assert!(TextureDescriptor { usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::INPUT_ATTACHMENT, ..transient.clone() }
+            .validate()
+            .is_ok());
+        assert!(TextureDescriptor { usage: TextureUsage::INPUT_ATTACHMENT, ..Default::default() }.validate().is_err());
+        assert!(TextureDescriptor { mip_level_count: 2, ..transient.clone() }.validate().is_err());
+        assert!(TextureDescriptor { dimension: TextureDimension::D2Array, ..transient.clone() }.validate().is_err());
+        assert!(TextureDescriptor { usage: TextureUsage::COPY_SRC, ..Default::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn guaranteed_format_properties() {
+        let rgba16 = FormatProperties::guaranteed(TextureFormat::Rgba16Float);
+        assert!(rgba16.supports(TextureUsage::STORAGE_BINDING | TextureUsage::RENDER_ATTACHMENT));
+        assert!(rgba16.supports_sample_count(4));
+        assert!(!rgba16.supports_sample_count(8));
+        assert!(!rgba16.supports_sample_count(3));
+        let bgra = FormatProperties::guaranteed(TextureFormat::Bgra8Unorm);
+        assert!(!bgra.supports(TextureUsage::STORAGE_BINDING));
+        let depth = FormatProperties::guaranteed(TextureFormat::D32Float);
+        assert!(!depth.supports(TextureUsage::RENDER_ATTACHMENT));
+        assert_eq!(depth.sample_counts, 1);
+        assert!(rgba16.linear_blit && !depth.linear_blit);
+    }
+}
@@ -0,0 +1,465 @@
+//! Descriptor binding reflection from SPIR-V (feature `reflect`), so pipelines can derive
+//! `layout_bindings` from their shaders or check hand-written ones against them.
+//!
+//! Push-constant blocks are reflected as a [`PushConstantRange`], to size a pipeline's range and to
+//! check writes with [`validate_push_constants`]. Pipeline descriptors do not take push-constant
+//! ranges yet, so for now this only checks shaders and caller data.
+//!
+//! Lume pipelines have a single descriptor set, so every binding must be in set 0. Only the
+//! [`DescriptorType`]s lume has are recognised: standalone samplers and runtime-sized arrays
+//! (bindless) are reported as errors and must be declared by hand.
+
+use crate::spirv;
+use crate::{DescriptorSetLayoutBinding, DescriptorType, DeviceLimits, ShaderStages};
+use std::collections::HashMap;
+
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DIM_SUBPASS_DATA: u32 = 6;
+
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+/// From SPIR-V 1.4 entry point interfaces list every global they use, not just inputs and outputs.
+const VERSION_1_4: u32 = 0x0001_0400;
+
+fn stage_of(model: u32) -> ShaderStages {
+    match model {
+        0 => ShaderStages::VERTEX,
+        4 => ShaderStages::FRAGMENT,
+        5 => ShaderStages::COMPUTE,
+        _ => ShaderStages::empty(),
+    }
+}
+
+/// The declarations of one module reflection needs, indexed by result id.
+#[derive(Default)]
+struct Module {
+    version: u32,
+    entry_points: Vec<(ShaderStages, Vec<u32>)>,
+    /// (pointer type, id, storage class) of each global variable.
+    variables: Vec<(u32, u32, u32)>,
+    set: HashMap<u32, u32>,
+    binding: HashMap<u32, u32>,
+    buffer_block: Vec<u32>,
+    array_stride: HashMap<u32, u32>,
+    /// (struct, member) -> Offset / MatrixStride decoration.
+    member_offset: HashMap<(u32, u32), u32>,
+    member_matrix_stride: HashMap<(u32, u32), u32>,
+    pointers: HashMap<u32, u32>,
+    /// Element type and length constant (None when runtime-sized).
+    arrays: HashMap<u32, (u32, Option<u32>)>,
+    constants: HashMap<u32, u32>,
+    /// Scalar byte sizes.
+    scalars: HashMap<u32, u32>,
+    /// Vectors as (component, count) and matrices as (column, count).
+    vectors: HashMap<u32, (u32, u32)>,
+    matrices: HashMap<u32, (u32, u32)>,
+    structs: HashMap<u32, Vec<u32>>,
+    /// Opaque types: opcode and, for images, the Sampled operand (2: storage image) and Dim.
+    opaque: HashMap<u32, (u32, u32, u32)>,
+}
+
+impl Module {
+    fn parse(spirv: &[u8]) -> Result<Self, String> {
+        let words = spirv::words(spirv)?;
+        let mut m = Module { version: words[1], ..Default::default() };
+        for (opcode, ops) in spirv::declarations(&words)? {
+            match opcode {
+                spirv::OP_ENTRY_POINT if ops.len() >= 3 => {
+                    // Interface ids follow the nul-terminated name.
+                    let name_words = ops[2..].iter().position(|w| w.to_le_bytes().contains(&0)).map_or(0, |p| p + 1);
+                    m.entry_points.push((stage_of(ops[0]), ops[(2 + name_words).min(ops.len())..].to_vec()));
+                }
+                OP_DECORATE if ops.len() >= 2 => match ops[1] {
+                    DECORATION_DESCRIPTOR_SET if ops.len() >= 3 => {
+                        m.set.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_BINDING if ops.len() >= 3 => {
+                        m.binding.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_ARRAY_STRIDE if ops.len() >= 3 => {
+                        m.array_stride.insert(ops[0], ops[2]);
+                    }
+                    DECORATION_BUFFER_BLOCK => m.buffer_block.push(ops[0]),
+                    _ => {}
+                },
+                OP_MEMBER_DECORATE if ops.len() >= 4 => match ops[2] {
+                    DECORATION_OFFSET => {
+                        m.member_offset.insert((ops[0], ops[1]), ops[3]);
+                    }
+                    DECORATION_MATRIX_STRIDE => {
+                        m.member_matrix_stride.insert((ops[0], ops[1]), ops[3]);
+                    }
+                    _ => {}
+                },
+                OP_TYPE_INT | OP_TYPE_FLOAT if ops.len() >= 2 => {
+                    m.scalars.insert(ops[0], ops[1] / 8);
+                }
+                OP_TYPE_VECTOR if ops.len() >= 3 => {
+                    m.vectors.insert(ops[0], (ops[1], ops[2]));
+                }
+                OP_TYPE_MATRIX if ops.len() >= 3 => {
+                    m.matrices.insert(ops[0], (ops[1], ops[2]));
+                }
+                OP_TYPE_STRUCT if !ops.is_empty() => {
+                    m.structs.insert(ops[0], ops[1..].to_vec());
+                }
+                OP_TYPE_POINTER if ops.len() >= 3 => {
+                    m.pointers.insert(ops[0], ops[2]);
+                }
+                OP_TYPE_ARRAY if ops.len() >= 3 => {
+                    m.arrays.insert(ops[0], (ops[1], Some(ops[2])));
+                }
+                OP_TYPE_RUNTIME_ARRAY if ops.len() >= 2 => {
+                    m.arrays.insert(ops[0], (ops[1], None));
+                }
+                OP_CONSTANT if ops.len() >= 3 => {
+                    m.constants.insert(ops[1], ops[2]);
+                }
+                OP_TYPE_IMAGE if ops.len() >= 7 => {
+                    m.opaque.insert(ops[0], (OP_TYPE_IMAGE, ops[6], ops[2]));
+                }
+                OP_TYPE_SAMPLER | OP_TYPE_SAMPLED_IMAGE if !ops.is_empty() => {
+                    m.opaque.insert(ops[0], (opcode, 0, 0));
+                }
+                OP_VARIABLE if ops.len() >= 3 => m.variables.push((ops[0], ops[1], ops[2])),
+                _ => {}
+            }
+        }
+        Ok(m)
+    }
+
+    /// Entry points using global `id`; before SPIR-V 1.4 that cannot be told without walking function
+    /// bodies, so it is every entry point in the module.
+    fn stages_using(&self, id: u32) -> ShaderStages {
+        self.entry_points
+            .iter()
+            .filter(|(_, interface)| self.version < VERSION_1_4 || interface.contains(&id))
+            .fold(ShaderStages::empty(), |acc, (stage, _)| acc | *stage)
+    }
+
+    fn array_length(&self, length: Option<u32>) -> Result<u32, String> {
+        let length = length.ok_or("runtime-sized arrays must be declared by hand")?;
+        self.constants.get(&length).copied().ok_or_else(|| "array length is not a constant".to_string())
+    }
+
+    /// Byte size of a type in an explicitly laid out block. `matrix_stride` is the MatrixStride of the
+    /// struct member or array holding a matrix.
+    fn size_of(&self, ty: u32, matrix_stride: Option<u32>) -> Result<u32, String> {
+        if let Some(&size) = self.scalars.get(&ty) {
+            Ok(size)
+        } else if let Some(&(component, count)) = self.vectors.get(&ty) {
+            Ok(self.size_of(component, None)? * count)
+        } else if let Some(&(column, count)) = self.matrices.get(&ty) {
+            Ok(match matrix_stride {
+                Some(stride) => stride * count,
+                None => self.size_of(column, None)? * count,
+            })
+        } else if let Some(&(element, length)) = self.arrays.get(&ty) {
+            let length = self.array_length(length)?;
+            Ok(match self.array_stride.get(&ty) {
+                Some(stride) => stride * length,
+                None => self.size_of(element, matrix_stride)? * length,
+            })
+        } else if let Some(members) = self.structs.get(&ty) {
+            let mut end = 0;
+            for (i, &member) in members.iter().enumerate() {
+                let key = (ty, i as u32);
+                let offset = self.member_offset.get(&key).copied().unwrap_or(end);
+                end = end.max(offset + self.size_of(member, self.member_matrix_stride.get(&key).copied())?);
+            }
+            Ok(end)
+        } else {
+            Err(format!("type %{} has no size in a block", ty))
+        }
+    }
+}
+
+/// Descriptor bindings one SPIR-V module uses, sorted by binding. `stages` are the entry points that
+/// use the binding; before SPIR-V 1.4 that is every entry point in the module (one per module when
+/// compiled per entry point, as lume does).
+pub fn reflect_bindings(spirv: &[u8]) -> Result<Vec<DescriptorSetLayoutBinding>, String> {
+    let m = Module::parse(spirv)?;
+    let mut out: Vec<DescriptorSetLayoutBinding> = Vec::new();
+    for &(pointer, id, storage) in &m.variables {
+        if ![STORAGE_UNIFORM_CONSTANT, STORAGE_UNIFORM, STORAGE_STORAGE_BUFFER].contains(&storage) {
+            continue;
+        }
+        let Some(&slot) = m.binding.get(&id) else {
+            return Err(format!("descriptor variable %{} has no Binding decoration", id));
+        };
+        let set_index = m.set.get(&id).copied().unwrap_or(0);
+        if set_index != 0 {
+            return Err(format!("binding {} is in descriptor set {}; lume pipelines use set 0 only", slot, set_index));
+        }
+
+        let mut ty = m.pointers.get(&pointer).copied().ok_or_else(|| format!("binding {}: variable type is not a pointer", slot))?;
+        let mut count = 1;
+        while let Some(&(element, length)) = m.arrays.get(&ty) {
+            count *= m.array_length(length).map_err(|e| format!("binding {}: {}", slot, e))?;
+            ty = element;
+        }
+        let descriptor_type = match storage {
+            STORAGE_STORAGE_BUFFER => DescriptorType::StorageBuffer,
+            STORAGE_UNIFORM if m.buffer_block.contains(&ty) => DescriptorType::StorageBuffer,
+            STORAGE_UNIFORM => DescriptorType::UniformBuffer,
+            _ => match m.opaque.get(&ty) {
+                Some(&(OP_TYPE_IMAGE, _, DIM_SUBPASS_DATA)) => DescriptorType::InputAttachment,
+                Some(&(OP_TYPE_IMAGE, 2, _)) => DescriptorType::StorageImage,
+                Some(&(OP_TYPE_IMAGE, _, _)) => DescriptorType::SampledImage,
+                Some(&(OP_TYPE_SAMPLED_IMAGE, _, _)) => DescriptorType::CombinedImageSampler,
+                Some(&(OP_TYPE_SAMPLER, _, _)) => {
+                    return Err(format!("binding {}: standalone samplers have no lume DescriptorType", slot))
+                }
+                _ => return Err(format!("binding {}: unsupported descriptor type", slot)),
+            },
+        };
+        let reflected = DescriptorSetLayoutBinding { binding: slot, descriptor_type, count, stages: m.stages_using(id) };
+        merge(&mut out, reflected)?;
+    }
+    out.sort_by_key(|b| b.binding);
+    Ok(out)
+}
+
+/// Bindings of all stages of a pipeline (e.g. vertex and fragment SPIR-V), with the stages of a
+/// binding used by several modules combined.
+pub fn reflect_pipeline_bindings(modules: &[&[u8]]) -> Result<Vec<DescriptorSetLayoutBinding>, String> {
+    let mut out = Vec::new();
+    for module in modules {
+        for binding in reflect_bindings(module)? {
+            merge(&mut out, binding)?;
+        }
+    }
+    out.sort_by_key(|b| b.binding);
+    Ok(out)
+}
+
+fn merge(out: &mut Vec<DescriptorSetLayoutBinding>, binding: DescriptorSetLayoutBinding) -> Result<(), String> {
+    match out.iter_mut().find(|b| b.binding == binding.binding) {
+        Some(existing) if existing.descriptor_type != binding.descriptor_type || existing.count != binding.count => Err(format!(
+            "binding {} is used as both {:?} x{} and {:?} x{}",
+            binding.binding, existing.descriptor_type, existing.count, binding.descriptor_type, binding.count
+        )),
+        Some(existing) => {
+            existing.stages |= binding.stages;
+            Ok(())
+        }
+        None => {
+            out.push(binding);
+            Ok(())
+        }
+    }
+}
+
+/// Err when `declared` (e.g. `GraphicsPipelineDescriptor::layout_bindings`) misses a reflected
+/// binding or declares it with another type, fewer descriptors or without a stage that uses it.
+/// Declared bindings the shaders do not use are allowed.
+pub fn validate_bindings(declared: &[DescriptorSetLayoutBinding], reflected: &[DescriptorSetLayoutBinding]) -> Result<(), String> {
+    for r in reflected {
+        let Some(d) = declared.iter().find(|d| d.binding == r.binding) else {
+            return Err(format!("binding {} ({:?}) is used by the shader but not declared", r.binding, r.descriptor_type));
+        };
+        if d.descriptor_type != r.descriptor_type {
+            return Err(format!("binding {} is declared {:?} but the shader uses {:?}", r.binding, d.descriptor_type, r.descriptor_type));
+        }
+        if d.count < r.count {
+            return Err(format!("binding {} declares {} descriptors but the shader uses {}", r.binding, d.count, r.count));
+        }
+        if !d.stages.contains(r.stages) {
+            return Err(format!("binding {} is declared for {:?} but used by {:?}", r.binding, d.stages, r.stages));
+        }
+    }
+    Ok(())
+}
+
+/// Push-constant block of a shader, or of all stages of a pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushConstantRange {
+    /// Bytes from offset 0 to the end of the block's last member.
+    pub size: u32,
+    pub stages: ShaderStages,
+}
+
+/// Push-constant block of one SPIR-V module, or None when it has none. `stages` follows the same
+/// rule as [`reflect_bindings`].
+pub fn reflect_push_constants(spirv: &[u8]) -> Result<Option<PushConstantRange>, String> {
+    let m = Module::parse(spirv)?;
+    let mut out: Option<PushConstantRange> = None;
+    for &(pointer, id, storage) in &m.variables {
+        if storage != STORAGE_PUSH_CONSTANT {
+            continue;
+        }
+        let block = m.pointers.get(&pointer).copied().ok_or("push constant variable type is not a pointer")?;
+        let size = m.size_of(block, None).map_err(|e| format!("push constant block: {}", e))?;
+        let range = PushConstantRange { size, stages: m.stages_using(id) };
+        out = Some(out.map_or(range, |o| PushConstantRange { size: o.size.max(size), stages: o.stages | range.stages }));
+    }
+    Ok(out)
+}
+
+/// Push-constant range of all stages of a pipeline: the largest block, used by every stage that
+/// declares one.
+pub fn reflect_pipeline_push_constants(modules: &[&[u8]]) -> Result<Option<PushConstantRange>, String> {
+    let mut out: Option<PushConstantRange> = None;
+    for module in modules {
+        if let Some(range) = reflect_push_constants(module)? {
+            out = Some(out.map_or(range, |o| PushConstantRange { size: o.size.max(range.size), stages: o.stages | range.stages }));
+        }
+    }
+    Ok(out)
+}
+
+/// Err when writing `len` bytes at `offset` would overrun the reflected block or the device's
+/// `max_push_constants_size`.
+pub fn validate_push_constants(range: &PushConstantRange, offset: u32, len: u32, limits: &DeviceLimits) -> Result<(), String> {
+    limits.validate_push_constants(offset, len)?;
+    match offset.checked_add(len) {
+        Some(end) if end <= range.size => Ok(()),
+        _ => Err(format!(
+            "push constants: {} bytes at offset {} exceed the shader's {}-byte block",
+            len, offset, range.size
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spirv(source: &str, stage: naga::ShaderStage, entry_point: &str) -> Vec<u8> {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::default(), naga::valid::Capabilities::default())
+            .validate(&module)
+            .unwrap();
+        let options = naga::back::spv::PipelineOptions { shader_stage: stage, entry_point: entry_point.to_string() };
+        let words = naga::back::spv::write_vec(&module, &info, &naga::back::spv::Options::default(), Some(&options)).unwrap();
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    const SOURCE: &str = "
+        struct Camera { view_proj: mat4x4<f32> }
+        @group(0) @binding(0) var<uniform> camera: Camera;
+        @group(0) @binding(1) var<storage, read> weights: array<f32>;
+        @group(0) @binding(2) var albedo: texture_2d<f32>;
+        @group(0) @binding(3) var output: texture_storage_2d<rgba8unorm, write>;
+        @vertex fn vs(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+            return camera.view_proj * vec4<f32>(f32(i), 0.0, 0.0, 1.0);
+        }
+        @fragment fn fs(@builtin(position) p: vec4<f32>) -> @location(0) vec4<f32> {
+            textureStore(output, vec2<i32>(p.xy), vec4<f32>(weights[0]));
+            return textureLoad(albedo, vec2<i32>(p.xy), 0) * camera.view_proj[0];
+        }";
+
+    #[test]
+    fn reflects_and_merges_stage_bindings() {
+        let vs = spirv(SOURCE, naga::ShaderStage::Vertex, "vs");
+        let fs = spirv(SOURCE, naga::ShaderStage::Fragment, "fs");
+        let vs_bindings = reflect_bindings(&vs).unwrap();
+        assert_eq!(vs_bindings.len(), 1);
+        assert_eq!(vs_bindings[0].descriptor_type, DescriptorType::UniformBuffer);
+        assert_eq!(vs_bindings[0].stages, ShaderStages::VERTEX);
+
+        let bindings = reflect_pipeline_bindings(&[&vs, &fs]).unwrap();
+        let summary: Vec<_> = bindings.iter().map(|b| (b.binding, b.descriptor_type, b.count, b.stages)).collect();
+        assert_eq!(
+            summary,
+            [
+                (0, DescriptorType::UniformBuffer, 1, ShaderStages::VERTEX | ShaderStages::FRAGMENT),
+                (1, DescriptorType::StorageBuffer, 1, ShaderStages::FRAGMENT),
+                (2, DescriptorType::SampledImage, 1, ShaderStages::FRAGMENT),
+                (3, DescriptorType::StorageImage, 1, ShaderStages::FRAGMENT),
+            ]
+        );
+        assert!(validate_bindings(&bindings, &bindings).is_ok());
+    }
+
+    #[test]
+    fn validation_reports_mismatched_declarations() {
+        let fs = spirv(SOURCE, naga::ShaderStage::Fragment, "fs");
+        let reflected = reflect_bindings(&fs).unwrap();
+        let mut declared = reflected.clone();
+        declared.push(DescriptorSetLayoutBinding {
+            binding: 7,
+            descriptor_type: DescriptorType::UniformBuffer,
+            count: 1,
+            stages: ShaderStages::VERTEX,
+        });
+        assert!(validate_bindings(&declared, &reflected).is_ok());
+
+        let err = validate_bindings(&declared[1..], &reflected).unwrap_err();
+        assert!(err.contains("binding 0 (UniformBuffer)"), "{}", err);
+        declared[1].descriptor_type = DescriptorType::UniformBuffer;
+        assert!(validate_bindings(&declared, &reflected).unwrap_err().contains("declared UniformBuffer"));
+        declared[1].descriptor_type = DescriptorType::StorageBuffer;
+        declared[1].stages = ShaderStages::VERTEX;
+        assert!(validate_bindings(&declared, &reflected).unwrap_err().contains("declared for ShaderStages(VERTEX) but used by ShaderStages(FRAGMENT)"));
+    }
+
+    #[test]
+    fn samplers_and_other_sets_are_errors() {
+        let sampler = "@group(0) @binding(0) var s: sampler;
+            @group(0) @binding(1) var t: texture_2d<f32>;
+            @fragment fn fs() -> @location(0) vec4<f32> { return textureSample(t, s, vec2<f32>(0.5)); }";
+        let err = reflect_bindings(&spirv(sampler, naga::ShaderStage::Fragment, "fs")).unwrap_err();
+        assert!(err.contains("standalone samplers"), "{}", err);
+        let set1 = "@group(1) @binding(0) var<uniform> c: vec4<f32>;
+            @fragment fn fs() -> @location(0) vec4<f32> { return c; }";
+        let err = reflect_bindings(&spirv(set1, naga::ShaderStage::Fragment, "fs")).unwrap_err();
+        assert!(err.contains("descriptor set 1"), "{}", err);
+    }
+
+    #[test]
+    fn push_constant_block_size_and_writes() {
+        let source = "struct Params { transform: mat4x4<f32>, tint: vec3<f32>, scale: f32, ids: array<u32, 3> }
+            var<push_constant> params: Params;
+            @vertex fn vs() -> @builtin(position) vec4<f32> { return params.transform[0]; }
+            @fragment fn fs() -> @location(0) vec4<f32> { return vec4<f32>(params.tint, f32(params.ids[2])); }";
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::default(), naga::valid::Capabilities::PUSH_CONSTANT)
+            .validate(&module)
+            .unwrap();
+        let compile = |stage, entry_point: &str| -> Vec<u8> {
+            let options = naga::back::spv::PipelineOptions { shader_stage: stage, entry_point: entry_point.to_string() };
+            let words = naga::back::spv::write_vec(&module, &info, &naga::back::spv::Options::default(), Some(&options)).unwrap();
+            words.iter().flat_map(|w| w.to_le_bytes()).collect()
+        };
+        let vs = compile(naga::ShaderStage::Vertex, "vs");
+        let fs = compile(naga::ShaderStage::Fragment, "fs");
+        // mat4 (64) + vec3 and f32 packed into 16 + 3 x u32 with a 4-byte stride.
+        let range = reflect_pipeline_push_constants(&[&vs, &fs]).unwrap().unwrap();
+        assert_eq!(range, PushConstantRange { size: 92, stages: ShaderStages::VERTEX | ShaderStages::FRAGMENT });
+        assert_eq!(reflect_push_constants(&spirv(SOURCE, naga::ShaderStage::Vertex, "vs")).unwrap(), None);
+
+        let limits = DeviceLimits::default();
+        assert!(validate_push_constants(&range, 0, 92, &limits).is_ok());
+        assert!(validate_push_constants(&range, 80, 12, &limits).is_ok());
+        let err = validate_push_constants(&range, 80, 16, &limits).unwrap_err();
+        assert!(err.contains("exceed the shader's 92-byte block"), "{}", err);
+        let big = PushConstantRange { size: 256, ..range };
+        assert!(validate_push_constants(&big, 0, 256, &limits).unwrap_err().contains("maxPushConstantsSize"));
+    }
+}
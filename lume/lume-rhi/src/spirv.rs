@@ -0,0 +1,164 @@
+//! SPIR-V checks run before shader module and pipeline creation, so mistakes surface as readable
+//! errors instead of opaque driver failures, and the instruction walk shared with `reflect`.
+
+// Only the Vulkan backend creates shader modules.
+#![cfg_attr(not(feature = "vulkan"), allow(dead_code))]
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+pub(crate) const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_FUNCTION: u32 = 54;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+/// SPIR-V execution models of the stages lume pipelines create.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExecutionModel {
+    Vertex = 0,
+    Fragment = 4,
+    GLCompute = 5,
+}
+
+fn model_name(model: u32) -> String {
+    match model {
+        0 => "Vertex".to_string(),
+        4 => "Fragment".to_string(),
+        5 => "GLCompute".to_string(),
+        other => format!("model {}", other),
+    }
+}
+
+/// Little-endian SPIR-V bytes as words, checking alignment and the magic number.
+pub(crate) fn words(source: &[u8]) -> Result<Vec<u32>, String> {
+    if !source.len().is_multiple_of(4) {
+        return Err("SPIR-V must be 4-byte aligned".to_string());
+    }
+    let words: Vec<u32> = source
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+    if words.len() < HEADER_WORDS || words[0] != MAGIC {
+        return Err("shader source is not SPIR-V (missing header or magic number)".to_string());
+    }
+    Ok(words)
+}
+
+/// `(opcode, operands)` of each instruction after the header, stopping at the first `OpFunction`:
+/// everything before it is declarations (entry points, decorations, types, globals).
+pub(crate) fn declarations(words: &[u32]) -> Result<Vec<(u32, &[u32])>, String> {
+    let mut out = Vec::new();
+    let mut i = HEADER_WORDS;
+    while i < words.len() {
+        let count = (words[i] >> 16) as usize;
+        let opcode = words[i] & 0xffff;
+        if count == 0 || i + count > words.len() {
+            return Err(format!("malformed SPIR-V instruction at word {}", i));
+        }
+        if opcode == OP_FUNCTION {
+            break;
+        }
+        out.push((opcode, &words[i + 1..i + count]));
+        i += count;
+    }
+    Ok(out)
+}
+
+/// Err unless `words` declares an `OpEntryPoint` named `name` for `model`; the error lists the entry
+/// points the module does have.
+pub(crate) fn check_entry_point(words: &[u32], name: &str, model: ExecutionModel) -> Result<(), String> {
+    let mut found = Vec::new();
+    for (opcode, operands) in declarations(words)? {
+        if opcode == OP_ENTRY_POINT && operands.len() >= 3 {
+            let entry_name = literal_string(&operands[2..]);
+            if operands[0] == model as u32 && entry_name == name {
+                return Ok(());
+            }
+            found.push(format!("'{}' ({})", entry_name, model_name(operands[0])));
+        }
+    }
+    let available = if found.is_empty() { "none".to_string() } else { found.join(", ") };
+    Err(format!(
+        "entry point '{}' ({:?}) not found in module; it declares: {}",
+        name, model, available
+    ))
+}
+
+/// `LocalSize` execution mode of the compute entry point `name`, if the module declares one as literals
+/// (`LocalSizeId` sizes come from specialization constants and are left to the driver).
+pub(crate) fn local_size(words: &[u32], name: &str) -> Result<Option<[u32; 3]>, String> {
+    let decls = declarations(words)?;
+    let entry_id = decls.iter().find_map(|(opcode, operands)| {
+        (*opcode == OP_ENTRY_POINT
+            && operands.len() >= 3
+            && operands[0] == ExecutionModel::GLCompute as u32
+            && literal_string(&operands[2..]) == name)
+            .then(|| operands[1])
+    });
+    let Some(entry_id) = entry_id else {
+        return Ok(None);
+    };
+    Ok(decls.iter().find_map(|(opcode, operands)| {
+        (*opcode == OP_EXECUTION_MODE
+            && operands.len() >= 5
+            && operands[0] == entry_id
+            && operands[1] == EXECUTION_MODE_LOCAL_SIZE)
+            .then(|| [operands[2], operands[3], operands[4]])
+    }))
+}
+
+/// Nul-terminated UTF-8 literal packed little-endian into words.
+pub(crate) fn literal_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take_while(|&b| b != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module_words() -> Vec<u32> {
+        compile("@vertex fn vs_main() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }
+            @fragment fn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }")
+    }
+
+    fn compile(source: &str) -> Vec<u32> {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::default(), naga::valid::Capabilities::default())
+            .validate(&module)
+            .unwrap();
+        naga::back::spv::write_vec(&module, &info, &naga::back::spv::Options::default(), None).unwrap()
+    }
+
+    #[test]
+    fn entry_point_name_and_stage_must_match() {
+        let bytes: Vec<u8> = module_words().iter().flat_map(|w| w.to_le_bytes()).collect();
+        let words = words(&bytes).unwrap();
+        assert!(check_entry_point(&words, "vs_main", ExecutionModel::Vertex).is_ok());
+        assert!(check_entry_point(&words, "fs_main", ExecutionModel::Fragment).is_ok());
+
+        let err = check_entry_point(&words, "main", ExecutionModel::Vertex).unwrap_err();
+        assert!(err.starts_with("entry point 'main' (Vertex) not found in module"), "{}", err);
+        assert!(err.contains("'vs_main' (Vertex)") && err.contains("'fs_main' (Fragment)"), "{}", err);
+        assert!(check_entry_point(&words, "vs_main", ExecutionModel::Fragment).is_err());
+        assert!(check_entry_point(&words, "vs_main", ExecutionModel::GLCompute).is_err());
+    }
+
+    #[test]
+    fn local_size_of_compute_entry_point() {
+        let words = compile("@compute @workgroup_size(8, 4, 2) fn a() {}
+            @compute @workgroup_size(64) fn b() {}");
+        assert_eq!(local_size(&words, "a").unwrap(), Some([8, 4, 2]));
+        assert_eq!(local_size(&words, "b").unwrap(), Some([64, 1, 1]));
+        assert_eq!(local_size(&words, "c").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_non_spirv() {
+        assert!(words(&[0, 1, 2]).unwrap_err().contains("4-byte aligned"));
+        assert!(words(b"@compute fn main() {}...").unwrap_err().contains("not SPIR-V"));
+    }
+}
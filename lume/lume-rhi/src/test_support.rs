@@ -0,0 +1,17 @@
+//! Device fixture shared by the GPU tests of this crate and the crates built on it (feature
+//! `test-support`).
+
+use std::sync::Arc;
+
+use crate::vulkan::VulkanDevice;
+
+/// A Vulkan device, or `None` after reporting `test` as skipped when there is none (e.g. headless CI).
+pub fn test_device(test: &str) -> Option<Arc<VulkanDevice>> {
+    match VulkanDevice::new() {
+        Ok(device) => Some(device),
+        Err(e) => {
+            eprintln!("skipping {}: {}", test, e);
+            None
+        }
+    }
+}
@@ -1,23 +1,75 @@
 //! Vulkan Buffer implementation.
 
 use crate::{Buffer, ResourceId};
-use ash::vk;
-use std::sync::Arc;
+use ash::vk::{self, Handle};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::memory::MemoryAllocation;
+
+/// The Vulkan buffer and its memory, swapped together when `Device::defragment` moves the
+/// buffer.
+pub(crate) struct BufferSlot {
+    /// Raw `vk::Buffer` handle, read without locking on every bind and copy.
+    buffer: AtomicU64,
+    /// Creation size and usage, to recreate the buffer elsewhere.
+    pub(crate) size: u64,
+    pub(crate) usage: vk::BufferUsageFlags,
+    /// Range of a shared memory block; returned to the allocator after the buffer is destroyed.
+    pub(crate) allocation: Mutex<MemoryAllocation>,
+}
+
+impl BufferSlot {
+    pub(crate) fn new(buffer: vk::Buffer, size: u64, usage: vk::BufferUsageFlags, allocation: MemoryAllocation) -> Self {
+        Self { buffer: AtomicU64::new(buffer.as_raw()), size, usage, allocation: Mutex::new(allocation) }
+    }
+
+    pub(crate) fn buffer(&self) -> vk::Buffer {
+        vk::Buffer::from_raw(self.buffer.load(Ordering::Acquire))
+    }
+
+    /// Point the slot at `buffer`, returning the previous one for the caller to destroy.
+    pub(crate) fn replace_buffer(&self, buffer: vk::Buffer) -> vk::Buffer {
+        vk::Buffer::from_raw(self.buffer.swap(buffer.as_raw(), Ordering::AcqRel))
+    }
+
+    pub(crate) fn allocation(&self) -> MutexGuard<'_, MemoryAllocation> {
+        self.allocation.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether `Device::defragment` may copy the buffer elsewhere.
+    pub(crate) fn movable(&self) -> bool {
+        self.usage.contains(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+    }
+}
 
 pub struct VulkanBuffer {
     pub device: Arc<ash::Device>,
-    pub buffer: vk::Buffer,
-    pub memory: vk::DeviceMemory,
+    /// Shared with the device's defragmenter, which only keeps a weak reference.
+    pub(crate) slot: Arc<BufferSlot>,
     pub size: u64,
     pub id: ResourceId,
     pub host_visible: bool,
+    /// False when the memory is host-visible but not HOST_COHERENT: CPU writes must be flushed and
+    /// reads invalidated (`write_buffer`/`read_buffer` do this).
+    pub host_coherent: bool,
+}
+
+impl VulkanBuffer {
+    /// The current Vulkan buffer. It changes when `Device::defragment` moves this buffer.
+    pub fn raw(&self) -> vk::Buffer {
+        self.slot.buffer()
+    }
+
+    pub(crate) fn allocation(&self) -> MutexGuard<'_, MemoryAllocation> {
+        self.slot.allocation()
+    }
 }
 
 impl Drop for VulkanBuffer {
     fn drop(&mut self) {
         unsafe {
-            self.device.destroy_buffer(self.buffer, None);
-            self.device.free_memory(self.memory, None);
+            self.device.destroy_buffer(self.raw(), None);
         }
     }
 }
@@ -41,6 +93,9 @@ impl Buffer for VulkanBuffer {
     fn host_visible(&self) -> bool {
         self.host_visible
     }
+    fn host_coherent(&self) -> bool {
+        self.host_coherent
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -2,7 +2,7 @@
 
 use crate::{
     Buffer, DescriptorPool, DescriptorPoolDescriptor, DescriptorSet, DescriptorSetLayout,
-    DescriptorSetLayoutBinding, DescriptorType, Sampler, ShaderStages, Texture,
+    DescriptorSetLayoutBinding, DescriptorType, ResourceId, Sampler, ShaderStages, Texture,
 };
 use ash::vk;
 
@@ -67,6 +67,7 @@ pub fn descriptor_type_to_vk(t: DescriptorType) -> vk::DescriptorType {
         DescriptorType::StorageImage => vk::DescriptorType::STORAGE_IMAGE,
         DescriptorType::SampledImage => vk::DescriptorType::SAMPLED_IMAGE,
         DescriptorType::CombinedImageSampler => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        DescriptorType::InputAttachment => vk::DescriptorType::INPUT_ATTACHMENT,
     }
 }
 
@@ -74,6 +75,9 @@ pub fn create_descriptor_set_layout(
     device: &ash::Device,
     bindings: &[DescriptorSetLayoutBinding],
 ) -> Result<VulkanDescriptorSetLayout, String> {
+    for b in bindings {
+        b.validate()?;
+    }
     let vk_bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings
         .iter()
         .map(|b| {
@@ -111,12 +115,13 @@ pub fn create_descriptor_pool_from_descriptor(
     desc: &DescriptorPoolDescriptor,
 ) -> Result<VulkanDescriptorPool, String> {
     let default_per_type = desc.max_sets * DEFAULT_POOL_MULTIPLIER;
-    let types_and_defaults: [(DescriptorType, u32); 5] = [
+    let types_and_defaults: [(DescriptorType, u32); 6] = [
         (DescriptorType::UniformBuffer, default_per_type),
         (DescriptorType::StorageBuffer, default_per_type),
         (DescriptorType::StorageImage, default_per_type),
         (DescriptorType::SampledImage, default_per_type),
         (DescriptorType::CombinedImageSampler, default_per_type),
+        (DescriptorType::InputAttachment, default_per_type),
     ];
     let pool_sizes: Vec<vk::DescriptorPoolSize> = if desc.pool_sizes.is_empty() {
         types_and_defaults
@@ -216,6 +221,7 @@ impl DescriptorPool for VulkanDescriptorPool {
             device: self.device.clone(),
             set: sets[0],
             bindings: vk_layout.bindings().to_vec(),
+            input_attachments: Vec::new(),
         }))
     }
 }
@@ -225,6 +231,8 @@ pub struct VulkanDescriptorSet {
     pub set: vk::DescriptorSet,
     /// Copy of layout bindings so write_buffer/write_texture use correct descriptor type.
     bindings: Vec<DescriptorSetLayoutBinding>,
+    /// `(binding, texture)` of each written input attachment, checked against the subpass on bind.
+    pub(crate) input_attachments: Vec<(u32, ResourceId)>,
 }
 
 impl std::fmt::Debug for VulkanDescriptorSet {
@@ -272,7 +280,7 @@ impl DescriptorSet for VulkanDescriptorSet {
             .downcast_ref::<super::buffer::VulkanBuffer>()
             .ok_or("Buffer must be VulkanBuffer")?;
         let buffer_info = vk::DescriptorBufferInfo::default()
-            .buffer(vk_buf.buffer)
+            .buffer(vk_buf.raw())
             .offset(offset)
             .range(if size > 0 { size } else { buffer.size() - offset });
         let write = vk::WriteDescriptorSet::default()
@@ -291,11 +299,20 @@ impl DescriptorSet for VulkanDescriptorSet {
         let descriptor_type = self
             .descriptor_type_for_binding(binding)
             .ok_or("write_texture_at: binding not found in layout")?;
+        if descriptor_type == DescriptorType::InputAttachment {
+            return Err(format!("write_texture_at: binding {} is an input attachment; use write_input_attachment", binding));
+        }
         let vk_ty = descriptor_type_to_vk(descriptor_type);
         let image_view = texture_view_for_descriptor(texture)?;
+        // Storage images are accessed in GENERAL; sampled ones in SHADER_READ_ONLY_OPTIMAL.
+        let image_layout = if descriptor_type == DescriptorType::StorageImage {
+            vk::ImageLayout::GENERAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        };
         let image_info = vk::DescriptorImageInfo::default()
             .image_view(image_view)
-            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            .image_layout(image_layout);
         let write = vk::WriteDescriptorSet::default()
             .dst_set(self.set)
             .dst_binding(binding)
@@ -340,6 +357,32 @@ impl DescriptorSet for VulkanDescriptorSet {
         Ok(())
     }
 
+    fn write_input_attachment(&mut self, binding: u32, texture: &dyn Texture) -> Result<(), String> {
+        if self.descriptor_type_for_binding(binding) != Some(DescriptorType::InputAttachment) {
+            return Err(format!("write_input_attachment: binding {} is not an InputAttachment binding", binding));
+        }
+        // Matches the layouts the subpass reads its inputs in (see render_pass::create_vk_render_pass).
+        let image_layout = if super::texture::format_is_depth(texture.format()) {
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        };
+        let image_info = vk::DescriptorImageInfo::default()
+            .image_view(texture_view_for_descriptor(texture)?)
+            .image_layout(image_layout);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.set)
+            .dst_binding(binding)
+            .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+            .image_info(std::slice::from_ref(&image_info));
+        unsafe {
+            self.device.update_descriptor_sets(&[write], &[]);
+        }
+        self.input_attachments.retain(|&(b, _)| b != binding);
+        self.input_attachments.push((binding, texture.id()));
+        Ok(())
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
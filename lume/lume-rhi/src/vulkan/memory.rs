@@ -1,8 +1,545 @@
-//! Vulkan memory management: explicit heaps and device-local allocations.
+//! Vulkan memory management: buffer memory type selection, block suballocation for buffers and textures,
+//! explicit heaps and device-local allocations.
 //! Provides foundation for VG cluster streaming and GI SDF textures.
 
 use ash::vk;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+
+use crate::{BufferMemoryPreference, MemoryBudget};
+
+/// Memory type for a texture with `memory_type_bits`: device-local, and also lazily allocated for
+/// transient attachments when the device has such a type.
+pub(crate) fn select_texture_memory_type(
+    props: &vk::PhysicalDeviceMemoryProperties,
+    memory_type_bits: u32,
+    transient: bool,
+) -> Result<u32, String> {
+    let find = |required: vk::MemoryPropertyFlags| {
+        (0..props.memory_type_count).find(|&i| {
+            (memory_type_bits & (1 << i)) != 0 && props.memory_types[i as usize].property_flags.contains(required)
+        })
+    };
+    transient
+        .then(|| find(vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED))
+        .flatten()
+        .or_else(|| find(vk::MemoryPropertyFlags::DEVICE_LOCAL))
+        .ok_or_else(|| "No suitable device-local memory for texture".to_string())
+}
+
+/// Whether any memory type is lazily allocated.
+pub(crate) fn has_lazily_allocated_memory(props: &vk::PhysicalDeviceMemoryProperties) -> bool {
+    props.memory_types[..props.memory_type_count as usize]
+        .iter()
+        .any(|t| t.property_flags.contains(vk::MemoryPropertyFlags::LAZILY_ALLOCATED))
+}
+
+/// Size of the `VkDeviceMemory` blocks buffers and textures are suballocated from. Resources larger than
+/// a quarter block get a dedicated allocation.
+pub(crate) const MEMORY_BLOCK_SIZE: u64 = 64 << 20;
+
+/// Ranges of one memory block not handed out, kept sorted by offset with neighbours merged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FreeList {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl FreeList {
+    pub(crate) fn new(size: u64) -> Self {
+        Self { ranges: vec![(0, size)] }
+    }
+
+    /// First fit: offset of `size` bytes aligned to `alignment` (a power of two), or `None` when no range fits.
+    pub(crate) fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let (i, start) = self.ranges.iter().enumerate().find_map(|(i, &(offset, len))| {
+            let start = offset.next_multiple_of(alignment.max(1));
+            (start + size <= offset + len).then_some((i, start))
+        })?;
+        let (offset, len) = self.ranges.remove(i);
+        let tail = (start + size, offset + len - start - size);
+        for range in [(offset, start - offset), tail].into_iter().rev() {
+            if range.1 > 0 {
+                self.ranges.insert(i, range);
+            }
+        }
+        Some(start)
+    }
+
+    pub(crate) fn free(&mut self, offset: u64, size: u64) {
+        let i = self.ranges.partition_point(|&(o, _)| o < offset);
+        self.ranges.insert(i, (offset, size));
+        if i + 1 < self.ranges.len() && offset + size == self.ranges[i + 1].0 {
+            self.ranges[i].1 += self.ranges.remove(i + 1).1;
+        }
+        if i > 0 && self.ranges[i - 1].0 + self.ranges[i - 1].1 == offset {
+            self.ranges[i - 1].1 += self.ranges.remove(i).1;
+        }
+    }
+
+    /// Nothing is allocated from a block of `size` bytes.
+    pub(crate) fn is_unused(&self, size: u64) -> bool {
+        self.ranges == [(0, size)]
+    }
+}
+
+/// Host pointer of a persistently mapped allocation; only dereferenced by `write_buffer`/`read_buffer`.
+#[derive(Clone, Copy)]
+struct MappedPtr(NonNull<u8>);
+
+// SAFETY: the mapping stays valid until the memory is freed, which waits for every allocation in it.
+unsafe impl Send for MappedPtr {}
+unsafe impl Sync for MappedPtr {}
+
+/// Memory type and whether the resources are linear (buffers) or optimally tiled (textures). Keeping
+/// the two apart means `bufferImageGranularity` never has to be respected inside a block.
+pub(crate) type PoolKey = (u32, bool);
+
+/// A block-suballocated range as seen by the defragmenter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Placement {
+    pub(crate) block: u64,
+    pub(crate) size: u64,
+    pub(crate) alignment: u64,
+    /// False pins the range, and with it its whole block.
+    pub(crate) movable: bool,
+}
+
+/// Plan a defragmentation of one pool: blocks are evacuated emptiest first into fuller ones, and only
+/// when everything in a block can move and fits elsewhere, so every move frees a block. A block that received moves
+/// is not evacuated later, as the planned ranges would stay behind in it. No block is created.
+/// `blocks` are `(id, free list)` and get the moved ranges reserved; returns `(placement index,
+/// target block, offset)` per move.
+pub(crate) fn plan_defragmentation(blocks: &mut [(u64, FreeList)], block_size: u64, placements: &[Placement]) -> Vec<(usize, u64, u64)> {
+    let used = |free: &FreeList| block_size - free.ranges.iter().map(|&(_, len)| len).sum::<u64>();
+    let mut order: Vec<usize> = (0..blocks.len()).collect();
+    order.sort_by_key(|&i| used(&blocks[i].1));
+    let mut moves: Vec<(usize, u64, u64)> = Vec::new();
+    for (rank, &source) in order.iter().enumerate() {
+        let source_id = blocks[source].0;
+        if moves.iter().any(|&(_, target, _)| target == source_id) {
+            continue;
+        }
+        let mut resident: Vec<usize> = (0..placements.len()).filter(|&i| placements[i].block == source_id).collect();
+        if resident.is_empty() || resident.iter().any(|&i| !placements[i].movable) {
+            continue;
+        }
+        // Largest first packs better; fullest targets first keeps the others free for later sources.
+        resident.sort_by_key(|&i| std::cmp::Reverse(placements[i].size));
+        let mut targets: Vec<(u64, FreeList)> = order[rank + 1..].iter().rev().map(|&i| blocks[i].clone()).collect();
+        let planned: Option<Vec<_>> = resident
+            .iter()
+            .map(|&i| {
+                targets.iter_mut().find_map(|(id, free)| {
+                    let offset = free.allocate(placements[i].size, placements[i].alignment)?;
+                    Some((i, *id, offset))
+                })
+            })
+            .collect();
+        if let Some(planned) = planned {
+            for (id, free) in targets {
+                if let Some(block) = blocks.iter_mut().find(|(block, _)| *block == id) {
+                    block.1 = free;
+                }
+            }
+            moves.extend(planned);
+        }
+    }
+    moves
+}
+
+struct MemoryBlock {
+    id: u64,
+    memory: vk::DeviceMemory,
+    mapped: Option<MappedPtr>,
+    free: FreeList,
+}
+
+/// What a device holds, for [`crate::Device::memory_budget`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct AllocationCounts {
+    /// Bytes handed out to resources.
+    pub(crate) allocated: u64,
+    /// Bytes of `VkDeviceMemory` (blocks and dedicated allocations), and how much of it is device-local.
+    pub(crate) reserved: u64,
+    pub(crate) reserved_device_local: u64,
+    pub(crate) device_memory_count: u32,
+}
+
+#[derive(Default)]
+struct AllocatorState {
+    pools: HashMap<PoolKey, Vec<MemoryBlock>>,
+    next_block_id: u64,
+    counts: AllocationCounts,
+}
+
+/// Suballocates buffer and texture memory from [`MEMORY_BLOCK_SIZE`] blocks, so a scene with thousands
+/// of resources stays under `maxMemoryAllocationCount` (often 4096). Host-visible memory is mapped
+/// once per block. Each pool keeps one empty block for reuse, so short-lived buffers (e.g. staging)
+/// do not allocate and map a block each time; [`Self::trim`] frees it.
+pub struct MemoryAllocator {
+    device: Arc<ash::Device>,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// `nonCoherentAtomSize`: flushes and invalidates of non-coherent memory are rounded out to it.
+    non_coherent_atom_size: u64,
+    state: Mutex<AllocatorState>,
+}
+
+impl std::fmt::Debug for MemoryAllocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryAllocator").field("counts", &self.counts()).finish_non_exhaustive()
+    }
+}
+
+impl MemoryAllocator {
+    pub(crate) fn new(
+        device: Arc<ash::Device>,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        non_coherent_atom_size: u64,
+    ) -> Arc<Self> {
+        Arc::new(Self { device, memory_properties, non_coherent_atom_size, state: Mutex::new(AllocatorState::default()) })
+    }
+
+    pub(crate) fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.memory_properties
+    }
+
+    pub(crate) fn counts(&self) -> AllocationCounts {
+        self.state.lock().map(|state| state.counts).unwrap_or_default()
+    }
+
+    /// Plan a defragmentation of pool `key`, whose live allocations are `placements`, and reserve the
+    /// target ranges. Returns the placement index and new allocation for each move; the moved
+    /// resources free their source blocks when they drop their old allocations.
+    pub(crate) fn reserve_moves(self: &Arc<Self>, key: PoolKey, placements: &[Placement]) -> Vec<(usize, MemoryAllocation)> {
+        let Ok(mut state) = self.state.lock() else {
+            return Vec::new();
+        };
+        let Some(pool) = state.pools.get_mut(&key) else {
+            return Vec::new();
+        };
+        let mut blocks: Vec<(u64, FreeList)> =
+            pool.iter_mut().map(|block| (block.id, std::mem::replace(&mut block.free, FreeList { ranges: Vec::new() }))).collect();
+        let moves = plan_defragmentation(&mut blocks, MEMORY_BLOCK_SIZE, placements);
+        for (block, (_, free)) in pool.iter_mut().zip(blocks) {
+            block.free = free;
+        }
+        let targets: Vec<_> = moves
+            .iter()
+            .map(|&(_, id, _)| pool.iter().find(|block| block.id == id).map(|block| (block.memory, block.mapped)).expect("planned block"))
+            .collect();
+        let device_local = self.memory_properties.memory_types[key.0 as usize]
+            .property_flags
+            .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL);
+        moves
+            .into_iter()
+            .zip(targets)
+            .map(|((index, id, offset), (memory, mapped))| {
+                let Placement { size, alignment, .. } = placements[index];
+                state.counts.allocated += size;
+                let allocation = MemoryAllocation {
+                    allocator: Arc::clone(self),
+                    memory,
+                    offset,
+                    size,
+                    alignment,
+                    // SAFETY: offset + size lies inside the mapped block.
+                    mapped: mapped.map(|ptr| MappedPtr(unsafe { NonNull::new_unchecked(ptr.0.as_ptr().add(offset as usize)) })),
+                    block: Some((key, id)),
+                    device_local,
+                };
+                (index, allocation)
+            })
+            .collect()
+    }
+
+    /// Memory for a resource with `requirements` from `memory_type_index`; `linear` is true for buffers.
+    pub(crate) fn allocate(
+        self: &Arc<Self>,
+        requirements: vk::MemoryRequirements,
+        memory_type_index: u32,
+        linear: bool,
+    ) -> Result<MemoryAllocation, String> {
+        let flags = self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        let device_local = flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL);
+        let host_visible = flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE);
+        let atom = if host_visible && !flags.contains(vk::MemoryPropertyFlags::HOST_COHERENT) {
+            self.non_coherent_atom_size
+        } else {
+            1
+        };
+        let requirements = host_access_layout(requirements, atom);
+        let key = (memory_type_index, linear);
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        let mut placed = None;
+        if requirements.size <= MEMORY_BLOCK_SIZE / 4 {
+            let pool = state.pools.entry(key).or_default();
+            placed = pool.iter_mut().find_map(|block| {
+                let offset = block.free.allocate(requirements.size, requirements.alignment)?;
+                Some((block.id, block.memory, block.mapped, offset))
+            });
+            if placed.is_none() {
+                // Out of device memory for a new block: fall through to a dedicated allocation.
+                if let Ok((memory, mapped)) = self.allocate_memory(MEMORY_BLOCK_SIZE, memory_type_index, host_visible) {
+                    let mut free = FreeList::new(MEMORY_BLOCK_SIZE);
+                    let offset = free.allocate(requirements.size, requirements.alignment).ok_or("allocation larger than a block")?;
+                    let id = state.next_block_id;
+                    state.next_block_id += 1;
+                    state.pools.entry(key).or_default().push(MemoryBlock { id, memory, mapped, free });
+                    state.counts.add_memory(MEMORY_BLOCK_SIZE, device_local);
+                    placed = Some((id, memory, mapped, offset));
+                }
+            }
+        }
+        let (block, memory, mapped, offset) = match placed {
+            Some((id, memory, mapped, offset)) => (Some((key, id)), memory, mapped, offset),
+            None => {
+                let (memory, mapped) = self.allocate_memory(requirements.size, memory_type_index, host_visible)?;
+                state.counts.add_memory(requirements.size, device_local);
+                (None, memory, mapped, 0)
+            }
+        };
+        state.counts.allocated += requirements.size;
+        Ok(MemoryAllocation {
+            allocator: Arc::clone(self),
+            memory,
+            offset,
+            size: requirements.size,
+            alignment: requirements.alignment,
+            // SAFETY: offset + size lies inside the mapped memory object.
+            mapped: mapped.map(|ptr| MappedPtr(unsafe { NonNull::new_unchecked(ptr.0.as_ptr().add(offset as usize)) })),
+            block,
+            device_local,
+        })
+    }
+
+    fn allocate_memory(&self, size: u64, memory_type_index: u32, map: bool) -> Result<(vk::DeviceMemory, Option<MappedPtr>), String> {
+        let allocate_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+        let memory = unsafe { self.device.allocate_memory(&allocate_info, None).map_err(|e| e.to_string())? };
+        if !map {
+            return Ok((memory, None));
+        }
+        match unsafe { self.device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) } {
+            Ok(ptr) => Ok((memory, NonNull::new(ptr.cast::<u8>()).map(MappedPtr))),
+            Err(e) => {
+                unsafe { self.device.free_memory(memory, None) };
+                Err(format!("map_memory: {}", e))
+            }
+        }
+    }
+
+    /// Free every empty block, including the one each pool keeps for reuse (e.g. before the device is
+    /// destroyed, or after a defragmentation emptied some).
+    pub(crate) fn trim(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let AllocatorState { pools, counts, .. } = &mut *state;
+        for (&(memory_type_index, _), pool) in pools.iter_mut() {
+            let device_local = self.memory_properties.memory_types[memory_type_index as usize]
+                .property_flags
+                .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL);
+            pool.retain(|block| {
+                if !block.free.is_unused(MEMORY_BLOCK_SIZE) {
+                    return true;
+                }
+                // Freeing also unmaps.
+                unsafe { self.device.free_memory(block.memory, None) };
+                counts.remove_memory(MEMORY_BLOCK_SIZE, device_local);
+                false
+            });
+        }
+    }
+
+    fn free(&self, allocation: &MemoryAllocation) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.counts.allocated -= allocation.size;
+        let freed = match allocation.block {
+            None => Some(allocation.size),
+            Some((key, id)) => {
+                let pool = state.pools.get_mut(&key).expect("block pool outlives its allocations");
+                let index = pool.iter().position(|block| block.id == id).expect("block outlives its allocations");
+                pool[index].free.free(allocation.offset, allocation.size);
+                release_empty_block(pool, index).then_some(MEMORY_BLOCK_SIZE)
+            }
+        };
+        if let Some(size) = freed {
+            // Freeing also unmaps.
+            unsafe { self.device.free_memory(allocation.memory, None) };
+            state.counts.remove_memory(size, allocation.device_local);
+        }
+    }
+}
+
+/// After a free in `pool[index]`: when that block is now empty and the pool already has another empty
+/// block, removes it and returns true (the caller frees its memory). One empty block per pool is kept.
+fn release_empty_block(pool: &mut Vec<MemoryBlock>, index: usize) -> bool {
+    let unused = |block: &MemoryBlock| block.free.is_unused(MEMORY_BLOCK_SIZE);
+    if !unused(&pool[index]) || !pool.iter().enumerate().any(|(i, block)| i != index && unused(block)) {
+        return false;
+    }
+    pool.swap_remove(index);
+    true
+}
+
+/// `requirements` padded for memory whose flushes and invalidates round out to `atom`: the offset is
+/// aligned to the atom and the size rounded up to it, so no atom is shared with a neighbouring range.
+pub(crate) fn host_access_layout(requirements: vk::MemoryRequirements, atom: u64) -> vk::MemoryRequirements {
+    let atom = atom.max(1);
+    vk::MemoryRequirements {
+        size: requirements.size.next_multiple_of(atom),
+        alignment: requirements.alignment.max(atom),
+        ..requirements
+    }
+}
+
+/// `(offset, size)` of a flush or invalidate of `len` bytes at `start` in a memory object of
+/// `memory_size` bytes: rounded out to `atom`, or to the end of the memory (`WHOLE_SIZE`) when the
+/// rounded range reaches it, as `VkMappedMemoryRange` requires for non-coherent memory.
+pub(crate) fn atom_aligned_range(start: u64, len: u64, atom: u64, memory_size: u64) -> (u64, u64) {
+    let atom = atom.max(1);
+    let begin = start / atom * atom;
+    let end = (start + len).next_multiple_of(atom);
+    if end >= memory_size {
+        (begin, vk::WHOLE_SIZE)
+    } else {
+        (begin, end - begin)
+    }
+}
+
+impl AllocationCounts {
+    fn add_memory(&mut self, size: u64, device_local: bool) {
+        self.reserved += size;
+        self.reserved_device_local += if device_local { size } else { 0 };
+        self.device_memory_count += 1;
+    }
+
+    fn remove_memory(&mut self, size: u64, device_local: bool) {
+        self.reserved -= size;
+        self.reserved_device_local -= if device_local { size } else { 0 };
+        self.device_memory_count -= 1;
+    }
+}
+
+/// A buffer's or texture's range of a memory block (or its dedicated allocation); returned to the
+/// allocator on drop, so owners destroy their Vulkan object first.
+pub struct MemoryAllocation {
+    allocator: Arc<MemoryAllocator>,
+    pub(crate) memory: vk::DeviceMemory,
+    pub(crate) offset: u64,
+    size: u64,
+    alignment: u64,
+    mapped: Option<MappedPtr>,
+    /// Pool and block id, or `None` for a dedicated allocation.
+    block: Option<(PoolKey, u64)>,
+    device_local: bool,
+}
+
+impl MemoryAllocation {
+    /// Host pointer to the start of this allocation when its memory is host-visible.
+    pub(crate) fn mapped_ptr(&self) -> Option<*mut u8> {
+        self.mapped.map(|ptr| ptr.0.as_ptr())
+    }
+
+    /// Range to flush or invalidate for host access to `len` bytes at `offset` into this allocation.
+    /// Non-coherent allocations start and end on a `nonCoherentAtomSize` boundary (see
+    /// [`host_access_layout`]), so rounding out never reaches a neighbouring suballocation: invalidating
+    /// its bytes would drop its unflushed writes, and flushing them would overwrite it with stale data.
+    pub(crate) fn mapped_range(&self, offset: u64, len: u64) -> vk::MappedMemoryRange<'static> {
+        let memory_size = if self.block.is_some() { MEMORY_BLOCK_SIZE } else { self.size };
+        let (start, size) =
+            atom_aligned_range(self.offset + offset, len, self.allocator.non_coherent_atom_size, memory_size);
+        vk::MappedMemoryRange::default().memory(self.memory).offset(start).size(size)
+    }
+
+    /// Pool and placement of a block-suballocated range whose resource can (`movable`) or cannot be
+    /// copied elsewhere; `None` for a dedicated allocation, which defragmentation never moves.
+    pub(crate) fn placement(&self, movable: bool) -> Option<(PoolKey, Placement)> {
+        let (key, block) = self.block?;
+        Some((key, Placement { block, size: self.size, alignment: self.alignment, movable }))
+    }
+}
+
+impl std::fmt::Debug for MemoryAllocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryAllocation")
+            .field("offset", &self.offset)
+            .field("size", &self.size)
+            .field("dedicated", &self.block.is_none())
+            .finish()
+    }
+}
+
+impl Drop for MemoryAllocation {
+    fn drop(&mut self) {
+        self.allocator.free(self);
+    }
+}
+
+/// Sum the device-local heaps. `driver` is the `(heap_budget, heap_usage)` arrays of
+/// `VK_EXT_memory_budget` when enabled; otherwise the heap sizes and the memory this device reserved
+/// are used.
+pub(crate) fn memory_budget(
+    props: &vk::PhysicalDeviceMemoryProperties,
+    driver: Option<(&[u64], &[u64])>,
+    counts: AllocationCounts,
+) -> MemoryBudget {
+    let mut budget = MemoryBudget {
+        allocated: counts.allocated,
+        reserved: counts.reserved,
+        device_memory_count: counts.device_memory_count,
+        from_driver: driver.is_some(),
+        ..Default::default()
+    };
+    for (i, heap) in props.memory_heaps[..props.memory_heap_count as usize].iter().enumerate() {
+        if !heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
+            continue;
+        }
+        match driver {
+            Some((heap_budget, heap_usage)) => {
+                budget.device_local_budget += heap_budget[i];
+                budget.device_local_usage += heap_usage[i];
+            }
+            None => budget.device_local_budget += heap.size,
+        }
+    }
+    if driver.is_none() {
+        budget.device_local_usage = counts.reserved_device_local;
+    }
+    budget
+}
+
+/// Memory type for a buffer with `memory_type_bits` (from its requirements); returns the index and
+/// whether it is host-coherent. Host-visible buffers prefer HOST_COHERENT memory and fall back to
+/// host-visible-only types, which need explicit flushes/invalidates around CPU access (some mobile and
+/// integrated GPUs expose little coherent memory). Device-local buffers fall back to any suitable type.
+pub(crate) fn select_buffer_memory_type(
+    props: &vk::PhysicalDeviceMemoryProperties,
+    memory_type_bits: u32,
+    preference: BufferMemoryPreference,
+) -> Result<(u32, bool), String> {
+    let find = |required: vk::MemoryPropertyFlags| {
+        (0..props.memory_type_count).find(|&i| {
+            (memory_type_bits & (1 << i)) != 0 && props.memory_types[i as usize].property_flags.contains(required)
+        })
+    };
+    let index = match preference {
+        BufferMemoryPreference::HostVisible => find(vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
+            .or_else(|| find(vk::MemoryPropertyFlags::HOST_VISIBLE))
+            .ok_or("no host-visible memory type for buffer")?,
+        BufferMemoryPreference::DeviceLocal => find(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .or_else(|| find(vk::MemoryPropertyFlags::empty()))
+            .ok_or("no memory type for buffer")?,
+    };
+    let coherent = props.memory_types[index as usize]
+        .property_flags
+        .contains(vk::MemoryPropertyFlags::HOST_COHERENT);
+    Ok((index, coherent))
+}
 
 /// Memory heap for sub-allocations. Manages a large device allocation.
 /// Used by streaming/upload paths (VG/GI); reserved for future use.
@@ -37,7 +574,7 @@ impl VulkanMemoryHeap {
             .or_else(|| {
                 (0..props.memory_type_count).find(|i| (memory_type_bits & (1 << i)) != 0)
             })
-            .ok_or("No suitable memory type for heap")? as u32;
+            .ok_or("No suitable memory type for heap")?;
 
         let allocate_info = vk::MemoryAllocateInfo::default()
             .allocation_size(size)
@@ -73,3 +610,193 @@ impl Drop for VulkanMemoryHeap {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(types: &[vk::MemoryPropertyFlags]) -> vk::PhysicalDeviceMemoryProperties {
+        let mut props = vk::PhysicalDeviceMemoryProperties { memory_type_count: types.len() as u32, ..Default::default() };
+        for (slot, &flags) in props.memory_types.iter_mut().zip(types) {
+            slot.property_flags = flags;
+        }
+        props
+    }
+
+    #[test]
+    fn host_visible_prefers_coherent_and_falls_back_to_non_coherent() {
+        let visible = vk::MemoryPropertyFlags::HOST_VISIBLE;
+        let coherent = visible | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let local = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let both = props(&[local, visible, coherent]);
+        assert_eq!(select_buffer_memory_type(&both, 0b111, BufferMemoryPreference::HostVisible), Ok((2, true)));
+        // Coherent type not allowed by the buffer: use the one that needs flushing.
+        assert_eq!(select_buffer_memory_type(&both, 0b011, BufferMemoryPreference::HostVisible), Ok((1, false)));
+        assert!(select_buffer_memory_type(&both, 0b001, BufferMemoryPreference::HostVisible).is_err());
+        assert_eq!(select_buffer_memory_type(&both, 0b110, BufferMemoryPreference::DeviceLocal), Ok((1, false)));
+    }
+
+    #[test]
+    fn transient_textures_prefer_lazily_allocated_memory() {
+        let local = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let lazy = local | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+        let tiled = props(&[local, lazy]);
+        assert_eq!(select_texture_memory_type(&tiled, 0b11, true), Ok(1));
+        assert_eq!(select_texture_memory_type(&tiled, 0b11, false), Ok(0));
+        assert!(has_lazily_allocated_memory(&tiled));
+        let desktop = props(&[vk::MemoryPropertyFlags::HOST_VISIBLE, local]);
+        assert_eq!(select_texture_memory_type(&desktop, 0b11, true), Ok(1));
+        assert!(!has_lazily_allocated_memory(&desktop));
+        assert!(select_texture_memory_type(&desktop, 0b01, false).is_err());
+    }
+
+    #[test]
+    fn memory_budget_sums_device_local_heaps() {
+        let mut props = vk::PhysicalDeviceMemoryProperties { memory_heap_count: 3, ..Default::default() };
+        props.memory_heaps[0] = vk::MemoryHeap { size: 8 << 30, flags: vk::MemoryHeapFlags::DEVICE_LOCAL };
+        props.memory_heaps[1] = vk::MemoryHeap { size: 16 << 30, flags: vk::MemoryHeapFlags::empty() };
+        props.memory_heaps[2] = vk::MemoryHeap { size: 256 << 20, flags: vk::MemoryHeapFlags::DEVICE_LOCAL };
+        let counts = AllocationCounts { allocated: 150, reserved: 3 << 20, reserved_device_local: 1 << 20, device_memory_count: 2 };
+
+        let fallback = memory_budget(&props, None, counts);
+        assert_eq!(fallback.device_local_budget, (8 << 30) + (256 << 20));
+        assert_eq!((fallback.device_local_usage, fallback.allocated, fallback.from_driver), (1 << 20, 150, false));
+        assert_eq!((fallback.reserved, fallback.device_memory_count), (3 << 20, 2));
+
+        let driver = memory_budget(&props, Some((&[1000, 9999, 200], &[300, 9999, 100])), counts);
+        assert_eq!((driver.device_local_budget, driver.device_local_usage, driver.from_driver), (1200, 400, true));
+        assert_eq!(driver.device_local_available(), 800);
+    }
+
+    #[test]
+    fn free_list_aligns_splits_and_merges() {
+        let mut free = FreeList::new(1024);
+        assert_eq!(free.allocate(100, 1), Some(0));
+        // Alignment leaves a gap in front that later allocations can use.
+        assert_eq!(free.allocate(100, 256), Some(256));
+        assert_eq!(free.allocate(150, 4), Some(100));
+        assert_eq!(free.allocate(1000, 1), None);
+        assert_eq!(free.ranges, [(250, 6), (356, 668)]);
+        free.free(100, 150);
+        free.free(0, 100);
+        assert_eq!(free.ranges, [(0, 256), (356, 668)]);
+        free.free(256, 100);
+        assert!(free.is_unused(1024));
+    }
+
+    #[test]
+    fn mapped_ranges_round_out_to_the_atom() {
+        assert_eq!(atom_aligned_range(100, 50, 64, 1 << 20), (64, 128));
+        assert_eq!(atom_aligned_range(128, 64, 64, 1 << 20), (128, 64));
+        assert_eq!(atom_aligned_range(10, 5, 1, 1 << 20), (10, 5));
+        // Rounding past the end of the memory object flushes to its end instead.
+        assert_eq!(atom_aligned_range(1000, 20, 256, 1010), (768, vk::WHOLE_SIZE));
+    }
+
+    #[test]
+    fn adjacent_non_coherent_allocations_do_not_share_an_atom() {
+        let atom = 256;
+        let requirements = |size| vk::MemoryRequirements { size, alignment: 16, memory_type_bits: 1 };
+        let mut free = FreeList::new(1 << 20);
+        let ranges: Vec<_> = [100, 300, 8]
+            .into_iter()
+            .map(|size| {
+                let padded = host_access_layout(requirements(size), atom);
+                let offset = free.allocate(padded.size, padded.alignment).expect("room in the block");
+                // A flush of every byte the resource asked for.
+                atom_aligned_range(offset, size, atom, 1 << 20)
+            })
+            .collect();
+        assert_eq!(ranges, [(0, 256), (256, 512), (768, 256)]);
+        for pair in ranges.windows(2) {
+            assert!(pair[0].0 + pair[0].1 <= pair[1].0);
+        }
+        // Coherent memory (atom 1) keeps the resource's own layout.
+        let coherent = host_access_layout(requirements(100), 1);
+        assert_eq!((coherent.size, coherent.alignment), (100, 16));
+    }
+
+    #[test]
+    fn one_empty_block_is_kept_per_pool() {
+        let block = |id, used: bool| {
+            let mut free = FreeList::new(MEMORY_BLOCK_SIZE);
+            if used {
+                free.allocate(64, 1);
+            }
+            MemoryBlock { id, memory: vk::DeviceMemory::null(), mapped: None, free }
+        };
+        let mut pool = vec![block(0, true), block(1, false)];
+        // The only empty block stays; a used one is never released.
+        assert!(!release_empty_block(&mut pool, 1));
+        assert!(!release_empty_block(&mut pool, 0));
+        pool.push(block(2, false));
+        assert!(release_empty_block(&mut pool, 2));
+        assert_eq!(pool.iter().map(|b| b.id).collect::<Vec<_>>(), [0, 1]);
+    }
+
+    #[test]
+    fn defragmentation_empties_the_sparsest_blocks() {
+        let block_size = 1000;
+        let mut blocks = vec![(7, FreeList::new(block_size)), (8, FreeList::new(block_size)), (9, FreeList::new(block_size))];
+        // Block 7: 600 used; block 8: 100 used; block 9: 500 used, in two pieces.
+        let placements = [
+            Placement { block: 7, size: 600, alignment: 1, movable: true },
+            Placement { block: 8, size: 100, alignment: 16, movable: true },
+            Placement { block: 9, size: 300, alignment: 1, movable: true },
+            Placement { block: 9, size: 200, alignment: 1, movable: true },
+        ];
+        for (block, free) in blocks.iter_mut() {
+            for p in placements.iter().filter(|p| p.block == *block) {
+                free.allocate(p.size, p.alignment).unwrap();
+            }
+        }
+        let moves = plan_defragmentation(&mut blocks, block_size, &placements);
+        // Block 8 goes into the fullest block first (7); block 9 no longer fits anywhere as a whole.
+        assert_eq!(moves, [(1, 7, 608)]);
+        assert_eq!(blocks[0].1.ranges, [(600, 8), (708, 292)]);
+        assert!(plan_defragmentation(&mut [(1, FreeList::new(block_size))], block_size, &[]).is_empty());
+    }
+
+    #[test]
+    fn blocks_that_received_moves_are_not_evacuated() {
+        let block_size = 1000;
+        // Block 1: 300 used; block 2: 320 in two pieces; block 3: 660 with only 170-byte gaps left.
+        let placements = [
+            Placement { block: 1, size: 300, alignment: 1, movable: true },
+            Placement { block: 2, size: 160, alignment: 1, movable: true },
+            Placement { block: 2, size: 160, alignment: 1, movable: true },
+            Placement { block: 3, size: 330, alignment: 1, movable: true },
+            Placement { block: 3, size: 330, alignment: 1, movable: true },
+        ];
+        let mut blocks = vec![
+            (1, FreeList { ranges: vec![(300, 700)] }),
+            (2, FreeList { ranges: vec![(320, 680)] }),
+            (3, FreeList { ranges: vec![(330, 170), (830, 170)] }),
+        ];
+        let moves = plan_defragmentation(&mut blocks, block_size, &placements);
+        // Block 1 only fits into block 2, whose own pieces would fit into block 3's gaps; moving
+        // them would leave block 1's range behind, so block 2 stays.
+        assert_eq!(moves, [(0, 2, 320)]);
+        for (index, _, _) in &moves {
+            let evacuated = placements[*index].block;
+            let resident = (0..placements.len()).filter(|&i| placements[i].block == evacuated);
+            assert!(resident.clone().all(|i| moves.iter().any(|&(moved, _, _)| moved == i)), "block {} keeps allocations", evacuated);
+            assert!(moves.iter().all(|&(_, target, _)| target != evacuated));
+        }
+        assert_eq!(blocks[2].1.ranges, [(330, 170), (830, 170)]);
+    }
+
+    #[test]
+    fn blocks_with_pinned_ranges_are_not_evacuated() {
+        let block_size = 1000;
+        let placements = [
+            Placement { block: 1, size: 600, alignment: 1, movable: true },
+            Placement { block: 2, size: 100, alignment: 1, movable: true },
+            Placement { block: 2, size: 100, alignment: 1, movable: false },
+        ];
+        let mut blocks = vec![(1, FreeList { ranges: vec![(600, 400)] }), (2, FreeList { ranges: vec![(200, 800)] })];
+        // Block 2's pieces would fit into block 1, but the pinned one would keep block 2 alive.
+        assert!(plan_defragmentation(&mut blocks, block_size, &placements).is_empty());
+        assert_eq!(blocks[0].1.ranges, [(600, 400)]);
+    }
+}
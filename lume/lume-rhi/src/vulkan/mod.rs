@@ -16,9 +16,9 @@ mod swapchain;
 use crate::{
     Buffer, BufferDescriptor, BufferMemoryPreference, BufferUsage, CommandBuffer, CommandEncoder, ComputePass,
     ComputePipelineDescriptor, DescriptorPoolDescriptor, DescriptorSetLayoutBinding, DescriptorPool,
-    DescriptorSetLayout, Device, Fence, GraphicsPipelineDescriptor, ImageLayout, LoadOp, Queue,
-    RenderPassDescriptor, ResourceId, Sampler, SamplerDescriptor, Semaphore, StoreOp, Texture,
-    TextureDescriptor, TextureFormat,
+    DescriptorSetLayout, DescriptorType, Device, Fence, FilterMode, FormatProperties, GraphicsPipelineDescriptor, ImageLayout, LoadOp, Queue,
+    RenderPassDescriptor, ResourceId, Sampler, SamplerDescriptor, Semaphore, ShaderStages, StoreOp, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureRegion, TextureSubresourceRange, TextureUsage,
 };
 use ash::vk;
 use ash::vk::Handle;
@@ -62,9 +62,10 @@ fn validation_layer_names(_entry: &ash::Entry) -> Vec<CString> {
 
 pub use buffer::VulkanBuffer;
 pub use descriptor::{VulkanDescriptorPool, VulkanDescriptorSet, VulkanDescriptorSetLayout};
-pub use pipeline::{VulkanComputePipeline, VulkanGraphicsPipeline};
+pub use pipeline::{CachedComputePipeline, VulkanComputePipeline, VulkanGraphicsPipeline};
 pub use render_pass::{ColorAttachmentInfo, DepthAttachmentInfo};
 pub use sampler::VulkanSampler;
+pub use memory::{MemoryAllocation, MemoryAllocator};
 pub use texture::{create_texture as create_vulkan_texture, VulkanTexture};
 
 #[cfg(feature = "window")]
@@ -90,6 +91,65 @@ fn texture_to_image_view(texture: &dyn crate::Texture) -> Result<vk::ImageView,
 struct RenderPassCacheKey {
     color: Vec<(TextureFormat, LoadOp, StoreOp, Option<ImageLayout>)>,
     depth: Option<(TextureFormat, LoadOp, StoreOp)>,
+    subpasses: Vec<crate::SubpassDescription>,
+}
+
+impl RenderPassCacheKey {
+    /// One entry per color attachment in order, so MRT configurations (e.g. 4 GBuffer targets) key distinctly.
+    fn new(
+        color: &[render_pass::ColorAttachmentInfo],
+        depth: Option<&render_pass::DepthAttachmentInfo>,
+        subpasses: &[crate::SubpassDescription],
+    ) -> Self {
+        Self {
+            color: color
+                .iter()
+                .map(|a| (a.format, a.load_op, a.store_op, a.initial_layout))
+                .collect(),
+            depth: depth.map(|d| (d.format, d.depth_load_op, d.depth_store_op)),
+            subpasses: subpasses.to_vec(),
+        }
+    }
+}
+
+/// Returns the cached render pass for `key`, calling `create` only on a miss.
+fn cached_render_pass(
+    cache: &Mutex<HashMap<RenderPassCacheKey, vk::RenderPass>>,
+    key: RenderPassCacheKey,
+    create: impl FnOnce() -> Result<vk::RenderPass, String>,
+) -> Result<vk::RenderPass, String> {
+    let mut cache = cache.lock().map_err(|e| format!("render_pass_cache lock: {}", e))?;
+    if let Some(&cached) = cache.get(&key) {
+        return Ok(cached);
+    }
+    let rp = create()?;
+    cache.insert(key, rp);
+    Ok(rp)
+}
+
+/// Cached compute pipelines beyond which a cache miss first evicts the ones no caller holds.
+const COMPUTE_PIPELINE_CACHE_CAPACITY: usize = 64;
+
+/// Key for caching compute pipelines: everything in the descriptor except the label.
+#[derive(Hash, Eq, PartialEq, Clone)]
+struct ComputePipelineCacheKey {
+    shader_source: Vec<u8>,
+    entry_point: String,
+    layout_bindings: Vec<(u32, DescriptorType, u32, ShaderStages)>,
+}
+
+impl ComputePipelineCacheKey {
+    fn new(desc: &ComputePipelineDescriptor) -> Self {
+        Self {
+            shader_source: desc.shader_source.clone(),
+            entry_point: desc.entry_point.clone(),
+            layout_bindings: desc
+                .layout_bindings
+                .iter()
+                .map(|b| (b.binding, b.descriptor_type, b.count, b.stages))
+                .collect(),
+        }
+    }
 }
 
 /// Key for caching VkFramebuffer by render pass and attachment image views.
@@ -119,12 +179,27 @@ pub struct VulkanDevice {
     surface_state: Option<SurfaceState>,
     /// Cached VkRenderPass by attachment config to avoid per-frame create/destroy.
     render_pass_cache: Arc<Mutex<HashMap<RenderPassCacheKey, vk::RenderPass>>>,
+    /// Compute pipelines shared by identical descriptors. Past `COMPUTE_PIPELINE_CACHE_CAPACITY`
+    /// entries, pipelines no longer held outside the cache are evicted on the next miss.
+    compute_pipeline_cache: Mutex<HashMap<ComputePipelineCacheKey, Arc<pipeline::VulkanComputePipeline>>>,
     /// Cached VkFramebuffer by (render_pass, extent, image_views) to avoid per-frame create/destroy.
     framebuffer_cache: Arc<Mutex<HashMap<FramebufferCacheKey, vk::Framebuffer>>>,
+    /// Physical device limits, queried once at creation.
+    limits: crate::DeviceLimits,
+    /// Optional features enabled at creation (see `supported_features`).
+    features: crate::DeviceFeatures,
+    /// `VK_EXT_memory_budget` is enabled, so `memory_budget` reports the driver's figures.
+    memory_budget_ext: bool,
+    /// Suballocates buffer and texture memory from shared blocks.
+    allocator: Arc<memory::MemoryAllocator>,
+    /// Every buffer created, for `defragment`; dead entries are pruned as the list grows.
+    buffer_slots: Mutex<Vec<(ResourceId, std::sync::Weak<buffer::BufferSlot>)>>,
 }
 
 #[cfg(feature = "window")]
 struct SurfaceState {
+    /// `HWND` of the window the surface was created for.
+    hwnd: isize,
     surface: vk::SurfaceKHR,
     surface_loader: ash::khr::surface::Instance,
     swapchain_loader: ash::khr::swapchain::Device,
@@ -143,6 +218,49 @@ fn image_layout_to_vk(l: ImageLayout) -> vk::ImageLayout {
     }
 }
 
+/// Unspecified counts map to VK_REMAINING_*, i.e. through the last mip level / array layer.
+fn subresource_range_to_vk(range: TextureSubresourceRange, aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange::default()
+        .aspect_mask(aspect_mask)
+        .base_mip_level(range.base_mip_level)
+        .level_count(range.mip_level_count.unwrap_or(vk::REMAINING_MIP_LEVELS))
+        .base_array_layer(range.base_array_layer)
+        .layer_count(range.array_layer_count.unwrap_or(vk::REMAINING_ARRAY_LAYERS))
+}
+
+/// The image behind a texture or swapchain image.
+fn texture_image(texture: &dyn Texture) -> vk::Image {
+    if let Some(t) = texture.as_any().downcast_ref::<VulkanTexture>() {
+        return t.image;
+    }
+    #[cfg(feature = "window")]
+    if let Some(s) = texture.as_any().downcast_ref::<VulkanSwapchainImage>() {
+        return s.image;
+    }
+    panic!("texture must be VulkanTexture or VulkanSwapchainImage");
+}
+
+fn texture_aspect(format: TextureFormat) -> vk::ImageAspectFlags {
+    if matches!(format, TextureFormat::D32Float) {
+        vk::ImageAspectFlags::DEPTH
+    } else {
+        vk::ImageAspectFlags::COLOR
+    }
+}
+
+/// Subresource of a copy or blit region at `mip` starting at `z` with extent `depth`, plus the z offset
+/// and extent within it: depth for 3D textures, array layers otherwise (see [`TextureRegion`]).
+fn region_layers(texture: &dyn Texture, mip: u32, z: u32, depth: u32) -> (vk::ImageSubresourceLayers, u32, u32) {
+    let ((base_layer, layer_count), (z, depth)) =
+        if texture.dimension() == TextureDimension::D3 { ((0, 1), (z, depth)) } else { ((z, depth), (0, 1)) };
+    let subresource = vk::ImageSubresourceLayers::default()
+        .aspect_mask(texture_aspect(texture.format()))
+        .mip_level(mip)
+        .base_array_layer(base_layer)
+        .layer_count(layer_count);
+    (subresource, z, depth)
+}
+
 /// Returns (src_stage, src_access, dst_stage, dst_access) for an image layout transition.
 /// When is_depth is true, uses DEPTH_* access flags for attachment layouts.
 fn image_barrier_stages_access(
@@ -163,7 +281,7 @@ fn image_barrier_stages_access(
     let shader_stages = vk::PipelineStageFlags::VERTEX_SHADER
         | vk::PipelineStageFlags::FRAGMENT_SHADER
         | vk::PipelineStageFlags::COMPUTE_SHADER;
-    let result = match (old_layout, new_layout) {
+    match (old_layout, new_layout) {
         (ImageLayout::Undefined, ImageLayout::ColorAttachment)
         | (ImageLayout::PresentSrc, ImageLayout::ColorAttachment)
         | (ImageLayout::Undefined, ImageLayout::DepthStencilAttachment)
@@ -284,6 +402,12 @@ fn image_barrier_stages_access(
             vk::PipelineStageFlags::COMPUTE_SHADER,
             vk::AccessFlags::SHADER_WRITE,
         ),
+        (ImageLayout::General, ImageLayout::TransferSrc) => (
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+        ),
         (ImageLayout::Undefined, ImageLayout::General) => (
             vk::PipelineStageFlags::TOP_OF_PIPE,
             vk::AccessFlags::empty(),
@@ -296,8 +420,86 @@ fn image_barrier_stages_access(
             vk::PipelineStageFlags::ALL_COMMANDS,
             vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
         ),
+    }
+}
+
+fn query_device_limits(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> crate::DeviceLimits {
+    let props = unsafe { instance.get_physical_device_properties(physical_device) };
+    let l = &props.limits;
+    crate::DeviceLimits {
+        max_compute_work_group_count: l.max_compute_work_group_count,
+        max_compute_work_group_size: l.max_compute_work_group_size,
+        max_compute_work_group_invocations: l.max_compute_work_group_invocations,
+        max_push_constants_size: l.max_push_constants_size,
+    }
+}
+
+/// Optional features to enable on the logical device: all the ones lume uses that the hardware has.
+fn supported_features(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> (vk::PhysicalDeviceFeatures, crate::DeviceFeatures) {
+    let available = unsafe { instance.get_physical_device_features(physical_device) };
+    let depth_clamp = available.depth_clamp == vk::TRUE;
+    let enabled = vk::PhysicalDeviceFeatures::default().depth_clamp(depth_clamp);
+    let memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let lazily_allocated_memory = memory::has_lazily_allocated_memory(&memory_properties);
+    (enabled, crate::DeviceFeatures { depth_clamp, lazily_allocated_memory })
+}
+
+/// Whether the physical device has `VK_EXT_memory_budget` (enabled at creation when present).
+fn supports_memory_budget(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> bool {
+    unsafe { instance.enumerate_device_extension_properties(physical_device) }
+        .unwrap_or_default()
+        .iter()
+        .any(|ext| ext.extension_name_as_c_str() == Ok(ash::ext::memory_budget::NAME))
+}
+
+fn query_format_properties(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    format: TextureFormat,
+) -> FormatProperties {
+    let vk_format = texture::texture_format_to_vk(format);
+    let features = unsafe { instance.get_physical_device_format_properties(physical_device, vk_format) }
+        .optimal_tiling_features;
+    let mut usages = TextureUsage::empty();
+    for (feature, usage) in [
+        (vk::FormatFeatureFlags::TRANSFER_SRC, TextureUsage::COPY_SRC),
+        (vk::FormatFeatureFlags::TRANSFER_DST, TextureUsage::COPY_DST),
+        (vk::FormatFeatureFlags::SAMPLED_IMAGE, TextureUsage::TEXTURE_BINDING),
+        (vk::FormatFeatureFlags::STORAGE_IMAGE, TextureUsage::STORAGE_BINDING),
+        (vk::FormatFeatureFlags::COLOR_ATTACHMENT, TextureUsage::RENDER_ATTACHMENT),
+        (vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT, TextureUsage::RENDER_ATTACHMENT),
+        (vk::FormatFeatureFlags::COLOR_ATTACHMENT, TextureUsage::INPUT_ATTACHMENT),
+        (vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT, TextureUsage::INPUT_ATTACHMENT),
+    ] {
+        if features.contains(feature) {
+            usages |= usage;
+        }
+    }
+    // Multisampling only applies to attachments; ask for the counts of a plain render target.
+    let sample_counts = if usages.contains(TextureUsage::RENDER_ATTACHMENT) {
+        unsafe {
+            instance.get_physical_device_image_format_properties(
+                physical_device,
+                vk_format,
+                vk::ImageType::TYPE_2D,
+                vk::ImageTiling::OPTIMAL,
+                texture::texture_usage_to_vk(TextureUsage::RENDER_ATTACHMENT, format),
+                vk::ImageCreateFlags::empty(),
+            )
+        }
+        .map_or(1, |props| props.sample_counts.as_raw())
+    } else if usages.is_empty() {
+        0
+    } else {
+        1
     };
-    result
+    let linear_blit = features.contains(
+        vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+    );
+    FormatProperties { usages, sample_counts, linear_blit }
 }
 
 impl VulkanDevice {
@@ -349,8 +551,13 @@ impl VulkanDevice {
                 );
             }
         }
+        let (enabled_features, features) = supported_features(&instance, physical_device);
+        let memory_budget_ext = supports_memory_budget(&instance, physical_device);
+        let extensions = if memory_budget_ext { vec![ash::ext::memory_budget::NAME.as_ptr()] } else { Vec::new() };
         let device_create_info = vk::DeviceCreateInfo::default()
-            .queue_create_infos(&queue_create_infos);
+            .queue_create_infos(&queue_create_infos)
+            .enabled_extension_names(&extensions)
+            .enabled_features(&enabled_features);
         let device_raw = unsafe {
             instance.create_device(physical_device, &device_create_info, None).map_err(|e| e.to_string())?
         };
@@ -375,6 +582,10 @@ impl VulkanDevice {
             device_raw.create_command_pool(&command_pool_create_info, None).map_err(|e| e.to_string())?
         };
         let device = Arc::new(device_raw);
+        let limits = query_device_limits(&instance, physical_device);
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let atom = unsafe { instance.get_physical_device_properties(physical_device) }.limits.non_coherent_atom_size;
+        let allocator = memory::MemoryAllocator::new(Arc::clone(&device), memory_properties, atom);
         Ok(Arc::new(Self {
             entry,
             instance,
@@ -389,7 +600,13 @@ impl VulkanDevice {
             #[cfg(feature = "window")]
             surface_state: None,
             render_pass_cache: Arc::new(Mutex::new(HashMap::new())),
+            compute_pipeline_cache: Mutex::new(HashMap::new()),
             framebuffer_cache: Arc::new(Mutex::new(HashMap::new())),
+            limits,
+            features,
+            memory_budget_ext,
+            allocator,
+            buffer_slots: Mutex::new(Vec::new()),
         }))
     }
 
@@ -404,13 +621,12 @@ impl VulkanDevice {
     ) -> Result<Arc<Self>, String> {
         use ash::khr::surface::Instance as SurfaceInstance;
         use ash::khr::swapchain::Device as SwapchainDevice;
-        use std::ffi::CStr;
         let handle = window.window_handle().map_err(|e| format!("window_handle: {:?}", e))?;
         let raw = handle.as_raw();
         let (hwnd, hinstance) = match raw {
             raw_window_handle::RawWindowHandle::Win32(win) => {
-                let hwnd = win.hwnd.get() as isize;
-                let hinstance = win.hinstance.map(|h| h.get() as isize).unwrap_or(0);
+                let hwnd = win.hwnd.get();
+                let hinstance = win.hinstance.map(|h| h.get()).unwrap_or(0);
                 (hwnd, hinstance)
             }
             _ => return Err("Only Win32 window is supported".to_string()),
@@ -422,12 +638,7 @@ impl VulkanDevice {
             .api_version(vk::API_VERSION_1_2)
             .application_name(&app_name)
             .engine_name(&engine_name);
-        let ext_names = unsafe {
-            [
-                CStr::from_bytes_with_nul_unchecked(b"VK_KHR_surface\0").as_ptr(),
-                ash::khr::win32_surface::NAME.as_ptr(),
-            ]
-        };
+        let ext_names = [ash::khr::surface::NAME.as_ptr(), ash::khr::win32_surface::NAME.as_ptr()];
         let layer_names: Vec<CString> = validation_layer_names(&entry);
         let layer_ptrs: Vec<*const i8> = layer_names.iter().map(|c| c.as_ptr()).collect();
         let instance_create_info = vk::InstanceCreateInfo::default()
@@ -485,10 +696,16 @@ impl VulkanDevice {
                 );
             }
         }
-        let swapchain_ext = ash::khr::swapchain::NAME.as_ptr();
+        let (enabled_features, features) = supported_features(&instance, physical_devices[0]);
+        let memory_budget_ext = supports_memory_budget(&instance, physical_devices[0]);
+        let mut extensions = vec![ash::khr::swapchain::NAME.as_ptr()];
+        if memory_budget_ext {
+            extensions.push(ash::ext::memory_budget::NAME.as_ptr());
+        }
         let device_create_info = vk::DeviceCreateInfo::default()
             .queue_create_infos(&queue_create_infos)
-            .enabled_extension_names(std::slice::from_ref(&swapchain_ext));
+            .enabled_extension_names(&extensions)
+            .enabled_features(&enabled_features);
         let device_raw = unsafe {
             instance.create_device(physical_devices[0], &device_create_info, None).map_err(|e| e.to_string())?
         };
@@ -514,6 +731,10 @@ impl VulkanDevice {
             device_raw.create_command_pool(&command_pool_create_info, None).map_err(|e| e.to_string())?
         };
         let device = Arc::new(device_raw);
+        let limits = query_device_limits(&instance, physical_devices[0]);
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_devices[0]) };
+        let atom = unsafe { instance.get_physical_device_properties(physical_devices[0]) }.limits.non_coherent_atom_size;
+        let allocator = memory::MemoryAllocator::new(Arc::clone(&device), memory_properties, atom);
         Ok(Arc::new(Self {
             entry,
             instance,
@@ -526,12 +747,19 @@ impl VulkanDevice {
             transfer_command_pool,
             next_id: std::sync::atomic::AtomicU64::new(1),
             surface_state: Some(SurfaceState {
+                hwnd,
                 surface,
                 surface_loader,
                 swapchain_loader,
             }),
             render_pass_cache: Arc::new(Mutex::new(HashMap::new())),
+            compute_pipeline_cache: Mutex::new(HashMap::new()),
             framebuffer_cache: Arc::new(Mutex::new(HashMap::new())),
+            limits,
+            features,
+            memory_budget_ext,
+            allocator,
+            buffer_slots: Mutex::new(Vec::new()),
         }))
     }
 
@@ -569,7 +797,7 @@ impl VulkanDevice {
                 .src_offset(src_offset)
                 .dst_offset(dst_offset)
                 .size(size);
-            device.cmd_copy_buffer(cmd, src_buf.buffer, dst_buf.buffer, &[region]);
+            device.cmd_copy_buffer(cmd, src_buf.raw(), dst_buf.raw(), &[region]);
             device.end_command_buffer(cmd).map_err(|e| e.to_string())?;
         }
         Ok(VulkanCommandBuffer {
@@ -616,6 +844,10 @@ impl Drop for VulkanDevice {
                 }
             }
         }
+        // Pipelines still held by callers keep their Arc; the cache only releases its references.
+        if let Ok(mut cache) = self.compute_pipeline_cache.lock() {
+            cache.clear();
+        }
         if let Ok(mut cache) = self.render_pass_cache.lock() {
             for (_, rp) in cache.drain() {
                 unsafe {
@@ -634,6 +866,8 @@ impl Drop for VulkanDevice {
                 s.surface_loader.destroy_surface(s.surface, None);
             }
         }
+        // Resources are gone by now; only the blocks kept for reuse remain.
+        self.allocator.trim();
         unsafe {
             self.device.destroy_command_pool(self.command_pool, None);
             self.device.destroy_device(None);
@@ -651,9 +885,10 @@ impl std::fmt::Debug for VulkanDevice {
 impl Device for VulkanDevice {
     fn create_buffer(&self, desc: &BufferDescriptor) -> Result<Box<dyn Buffer>, String> {
         let size = desc.size.max(1);
+        let usage = Self::buffer_usage_to_vk(desc.usage);
         let create_info = vk::BufferCreateInfo::default()
             .size(size)
-            .usage(Self::buffer_usage_to_vk(desc.usage))
+            .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let buffer = unsafe {
             self.device
@@ -661,58 +896,43 @@ impl Device for VulkanDevice {
                 .map_err(|e| e.to_string())?
         };
         let requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
-        let props = unsafe {
-            self.instance.get_physical_device_memory_properties(self.physical_device)
-        };
-        let memory_type_index = match desc.memory {
-            BufferMemoryPreference::HostVisible => (0..props.memory_type_count)
-                .find(|i| {
-                    let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
-                    let flags = &props.memory_types[*i as usize].property_flags;
-                    suitable && flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
-                })
-                .unwrap_or(0) as u32,
-            BufferMemoryPreference::DeviceLocal => (0..props.memory_type_count)
-                .find(|i| {
-                    let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
-                    let flags = &props.memory_types[*i as usize].property_flags;
-                    suitable && flags.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
-                })
-                .unwrap_or(0) as u32,
-        };
-        let allocate_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(requirements.size)
-            .memory_type_index(memory_type_index);
-        let memory = unsafe {
-            self.device
-                .allocate_memory(&allocate_info, None)
-                .map_err(|e| e.to_string())?
+        let props = self.allocator.memory_properties();
+        let allocated = memory::select_buffer_memory_type(props, requirements.memory_type_bits, desc.memory)
+            .and_then(|(index, coherent)| Ok((self.allocator.allocate(requirements, index, true)?, coherent)));
+        let (allocation, coherent) = match allocated {
+            Ok(allocated) => allocated,
+            Err(e) => {
+                unsafe { self.device.destroy_buffer(buffer, None) };
+                return Err(e);
+            }
         };
-        unsafe {
-            self.device
-                .bind_buffer_memory(buffer, memory, 0)
-                .map_err(|e| e.to_string())?;
+        if let Err(e) = unsafe { self.device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) } {
+            // The buffer goes before its memory, as on drop.
+            unsafe { self.device.destroy_buffer(buffer, None) };
+            drop(allocation);
+            return Err(e.to_string());
         }
         let id = self.next_id();
         let host_visible = matches!(desc.memory, BufferMemoryPreference::HostVisible);
+        let slot = Arc::new(buffer::BufferSlot::new(buffer, size, usage, allocation));
+        if let Ok(mut slots) = self.buffer_slots.lock() {
+            if slots.len().is_power_of_two() {
+                slots.retain(|(_, slot)| slot.strong_count() > 0);
+            }
+            slots.push((id, Arc::downgrade(&slot)));
+        }
         Ok(Box::new(buffer::VulkanBuffer {
             device: Arc::clone(&self.device),
-            buffer,
-            memory,
+            slot,
             size,
             id,
             host_visible,
+            host_coherent: !host_visible || coherent,
         }))
     }
 
     fn create_texture(&self, desc: &TextureDescriptor) -> Result<Box<dyn Texture>, String> {
-        let tex = texture::create_texture(
-            self.device.clone(),
-            &self.instance,
-            self.physical_device,
-            desc,
-            || self.next_id(),
-        )?;
+        let tex = texture::create_texture(self.device.clone(), &self.allocator, desc, || self.next_id())?;
         Ok(Box::new(tex))
     }
 
@@ -725,7 +945,28 @@ impl Device for VulkanDevice {
         &self,
         desc: &ComputePipelineDescriptor,
     ) -> Result<Box<dyn crate::ComputePipeline>, String> {
-        let pipe = pipeline::VulkanComputePipeline::create(&self.device, desc)?;
+        let key = ComputePipelineCacheKey::new(desc);
+        let mut cache = self
+            .compute_pipeline_cache
+            .lock()
+            .map_err(|e| format!("compute_pipeline_cache lock: {}", e))?;
+        if let Some(cached) = cache.get(&key) {
+            return Ok(Box::new(pipeline::CachedComputePipeline(cached.clone())));
+        }
+        let pipe = Arc::new(pipeline::VulkanComputePipeline::create(&self.device, desc, &self.limits)?);
+        if cache.len() >= COMPUTE_PIPELINE_CACHE_CAPACITY {
+            // Pipelines still in use stay alive through their handles either way.
+            cache.retain(|_, cached| Arc::strong_count(cached) > 1);
+        }
+        cache.insert(key, pipe.clone());
+        Ok(Box::new(pipeline::CachedComputePipeline(pipe)))
+    }
+
+    fn create_compute_pipeline_uncached(
+        &self,
+        desc: &ComputePipelineDescriptor,
+    ) -> Result<Box<dyn crate::ComputePipeline>, String> {
+        let pipe = pipeline::VulkanComputePipeline::create(&self.device, desc, &self.limits)?;
         Ok(Box::new(pipe))
     }
 
@@ -733,7 +974,21 @@ impl Device for VulkanDevice {
         &self,
         desc: &GraphicsPipelineDescriptor,
     ) -> Result<Box<dyn crate::GraphicsPipeline>, String> {
-        let pipe = pipeline::VulkanGraphicsPipeline::create(&self.device, desc)?;
+        if desc.rasterization.depth_clamp && !self.features.depth_clamp {
+            return Err(format!(
+                "create_graphics_pipeline({:?}): depth_clamp requested but the device does not support depthClamp",
+                desc.label
+            ));
+        }
+        desc.validate_primitive_restart()?;
+        desc.validate_subpass()?;
+        let (color_infos, depth_info, subpasses) = pipeline::VulkanGraphicsPipeline::attachment_infos(desc);
+        let render_pass = cached_render_pass(
+            &self.render_pass_cache,
+            RenderPassCacheKey::new(&color_infos, depth_info.as_ref(), &subpasses),
+            || render_pass::create_vk_render_pass(&self.device, &color_infos, depth_info.as_ref(), &subpasses),
+        )?;
+        let pipe = pipeline::VulkanGraphicsPipeline::create(&self.device, desc, render_pass)?;
         Ok(Box::new(pipe))
     }
 
@@ -783,30 +1038,178 @@ impl Device for VulkanDevice {
             finished: false,
             render_pass_cache: Arc::clone(&self.render_pass_cache),
             framebuffer_cache: Arc::clone(&self.framebuffer_cache),
+            limits: self.limits,
+            pass_error: Arc::new(Mutex::new(None)),
         }))
     }
 
+    fn limits(&self) -> crate::DeviceLimits {
+        self.limits
+    }
+
+    fn features(&self) -> crate::DeviceFeatures {
+        self.features
+    }
+
+    fn format_properties(&self, format: TextureFormat) -> FormatProperties {
+        query_format_properties(&self.instance, self.physical_device, format)
+    }
+
+    fn memory_budget(&self) -> crate::MemoryBudget {
+        let mut driver = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut props = vk::PhysicalDeviceMemoryProperties2::default();
+        if self.memory_budget_ext {
+            props = props.push_next(&mut driver);
+        }
+        unsafe { self.instance.get_physical_device_memory_properties2(self.physical_device, &mut props) };
+        let memory_properties = props.memory_properties;
+        let reported = self.memory_budget_ext.then_some((&driver.heap_budget[..], &driver.heap_usage[..]));
+        memory::memory_budget(&memory_properties, reported, self.allocator.counts())
+    }
+
+    fn defragment(&self) -> Result<crate::DefragmentReport, String> {
+        self.wait_idle()?;
+        let before = self.allocator.counts();
+        let slots: Vec<_> = {
+            let mut slots = self.buffer_slots.lock().map_err(|e| e.to_string())?;
+            slots.retain(|(_, slot)| slot.strong_count() > 0);
+            slots.iter().filter_map(|(id, slot)| Some((*id, slot.upgrade()?))).collect()
+        };
+        let mut pools: HashMap<memory::PoolKey, (Vec<usize>, Vec<memory::Placement>)> = HashMap::new();
+        for (i, (_, slot)) in slots.iter().enumerate() {
+            if let Some((key, placement)) = slot.allocation().placement(slot.movable()) {
+                let pool = pools.entry(key).or_default();
+                pool.0.push(i);
+                pool.1.push(placement);
+            }
+        }
+        let moves: Vec<_> = pools
+            .into_iter()
+            .flat_map(|(key, (indices, placements))| {
+                self.allocator.reserve_moves(key, &placements).into_iter().map(move |(p, allocation)| (indices[p], allocation))
+            })
+            .collect();
+        if moves.is_empty() {
+            self.allocator.trim();
+            let after = self.allocator.counts();
+            return Ok(crate::DefragmentReport {
+                freed_bytes: before.reserved - after.reserved,
+                freed_device_memory: before.device_memory_count - after.device_memory_count,
+                ..Default::default()
+            });
+        }
+        let alloc_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(self.command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let cmd = VulkanCommandBuffer {
+            device: Arc::clone(&self.device),
+            command_pool: self.command_pool,
+            buffer: unsafe { self.device.allocate_command_buffers(&alloc_info).map_err(|e| e.to_string())?[0] },
+        };
+        // New buffers bound to the reserved ranges; dropped with their allocations if anything fails.
+        let mut moved: Vec<(usize, vk::Buffer, memory::MemoryAllocation)> = Vec::with_capacity(moves.len());
+        let recorded = (|| unsafe {
+            self.device
+                .begin_command_buffer(cmd.buffer, &vk::CommandBufferBeginInfo::default())
+                .map_err(|e| e.to_string())?;
+            for (i, allocation) in moves {
+                let slot = &slots[i].1;
+                let create_info = vk::BufferCreateInfo::default()
+                    .size(slot.size)
+                    .usage(slot.usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE);
+                let buffer = self.device.create_buffer(&create_info, None).map_err(|e| e.to_string())?;
+                let bound = self.device.bind_buffer_memory(buffer, allocation.memory, allocation.offset);
+                moved.push((i, buffer, allocation));
+                bound.map_err(|e| e.to_string())?;
+                self.device.cmd_copy_buffer(cmd.buffer, slot.buffer(), buffer, &[vk::BufferCopy::default().size(slot.size)]);
+            }
+            self.device.end_command_buffer(cmd.buffer).map_err(|e| e.to_string())?;
+            let submit_info = vk::SubmitInfo::default().command_buffers(std::slice::from_ref(&cmd.buffer));
+            self.device
+                .queue_submit(self.queue, &[submit_info], vk::Fence::null())
+                .map_err(|e| format!("queue submit: {:?}", e))?;
+            self.wait_idle()
+        })();
+        if let Err(e) = recorded {
+            for (_, buffer, _) in moved {
+                unsafe { self.device.destroy_buffer(buffer, None) };
+            }
+            return Err(format!("defragment: {}", e));
+        }
+        let mut report = crate::DefragmentReport::default();
+        for (i, buffer, allocation) in moved {
+            let (id, slot) = &slots[i];
+            unsafe { self.device.destroy_buffer(slot.replace_buffer(buffer), None) };
+            report.moved_bytes += slot.size;
+            report.moved_buffers.push(*id);
+            // The old range goes back to its block.
+            *slot.allocation() = allocation;
+        }
+        // Emptied blocks are kept for reuse until trimmed.
+        self.allocator.trim();
+        let after = self.allocator.counts();
+        report.freed_bytes = before.reserved - after.reserved;
+        report.freed_device_memory = before.device_memory_count - after.device_memory_count;
+        Ok(report)
+    }
+
     fn write_buffer(&self, buffer: &dyn crate::Buffer, offset: u64, data: &[u8]) -> Result<(), String> {
         if !buffer.host_visible() {
             return Err("write_buffer requires a host-visible buffer; use upload_to_buffer for device-local buffers".to_string());
         }
+        // Buffers share memory blocks, so writing past the end would corrupt a neighbour.
+        if offset.checked_add(data.len() as u64).is_none_or(|end| end > buffer.size()) {
+            return Err(format!(
+                "write_buffer: {} bytes at offset {} exceed buffer size {}",
+                data.len(),
+                offset,
+                buffer.size()
+            ));
+        }
         let vk_buf = buffer
             .as_any()
             .downcast_ref::<buffer::VulkanBuffer>()
             .ok_or("Buffer is not a Vulkan buffer")?;
+        let allocation = vk_buf.allocation();
+        let ptr = allocation.mapped_ptr().ok_or("write_buffer: buffer memory is not mapped")?;
         unsafe {
-            let ptr = self
-                .device
-                .map_memory(
-                    vk_buf.memory,
-                    0,
-                    vk::WHOLE_SIZE,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .map_err(|e| e.to_string())?;
-            let dst = ptr.cast::<u8>().add(offset as usize);
-            std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
-            self.device.unmap_memory(vk_buf.memory);
+            std::ptr::copy_nonoverlapping(data.as_ptr(), ptr.add(offset as usize), data.len());
+            if !vk_buf.host_coherent {
+                self.device
+                    .flush_mapped_memory_ranges(&[allocation.mapped_range(offset, data.len() as u64)])
+                    .map_err(|e| format!("write_buffer: flush: {}", e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_buffer(&self, buffer: &dyn crate::Buffer, offset: u64, out: &mut [u8]) -> Result<(), String> {
+        if !buffer.host_visible() {
+            return Err("read_buffer requires a host-visible buffer; copy device-local data into one first".to_string());
+        }
+        if offset.checked_add(out.len() as u64).is_none_or(|end| end > buffer.size()) {
+            return Err(format!(
+                "read_buffer: {} bytes at offset {} exceed buffer size {}",
+                out.len(),
+                offset,
+                buffer.size()
+            ));
+        }
+        let vk_buf = buffer
+            .as_any()
+            .downcast_ref::<buffer::VulkanBuffer>()
+            .ok_or("Buffer is not a Vulkan buffer")?;
+        let allocation = vk_buf.allocation();
+        let ptr = allocation.mapped_ptr().ok_or("read_buffer: buffer memory is not mapped")?;
+        unsafe {
+            if !vk_buf.host_coherent {
+                self.device
+                    .invalidate_mapped_memory_ranges(&[allocation.mapped_range(offset, out.len() as u64)])
+                    .map_err(|e| format!("read_buffer: invalidate: {}", e))?;
+            }
+            std::ptr::copy_nonoverlapping(ptr.add(offset as usize), out.as_mut_ptr(), out.len());
         }
         Ok(())
     }
@@ -819,7 +1222,7 @@ impl Device for VulkanDevice {
             return self.write_buffer(buffer, offset, data);
         }
         let size = data.len() as u64;
-        if offset + size > buffer.size() {
+        if offset.checked_add(size).is_none_or(|end| end > buffer.size()) {
             return Err("upload_to_buffer: offset + data.len() exceeds buffer size".to_string());
         }
         let staging = self.create_buffer(&BufferDescriptor {
@@ -881,7 +1284,7 @@ impl Device for VulkanDevice {
             return self.write_buffer(buffer, offset, data);
         }
         let size = data.len() as u64;
-        if offset + size > buffer.size() {
+        if offset.checked_add(size).is_none_or(|end| end > buffer.size()) {
             return Err("upload_to_buffer_async: offset + data.len() exceeds buffer size".to_string());
         }
         let staging = self.create_buffer(&BufferDescriptor {
@@ -990,6 +1393,14 @@ impl Device for VulkanDevice {
         }))
     }
 
+    #[cfg(feature = "window")]
+    fn surface_matches(&self, window: raw_window_handle::RawWindowHandle) -> bool {
+        match (&self.surface_state, window) {
+            (Some(state), raw_window_handle::RawWindowHandle::Win32(win)) => win.hwnd.get() == state.hwnd,
+            _ => false,
+        }
+    }
+
     #[cfg(feature = "window")]
     fn create_swapchain(
         &self,
@@ -1046,7 +1457,8 @@ impl Device for VulkanDevice {
             .image_color_space(format.color_space)
             .image_extent(extent_vk)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            // Transfer reads, where the surface allows them, let frames be copied out for screenshots.
+            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | (caps.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC))
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(caps.current_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -1086,6 +1498,9 @@ struct VulkanCommandEncoder {
     finished: bool,
     render_pass_cache: Arc<Mutex<HashMap<RenderPassCacheKey, vk::RenderPass>>>,
     framebuffer_cache: Arc<Mutex<HashMap<FramebufferCacheKey, vk::Framebuffer>>>,
+    limits: crate::DeviceLimits,
+    /// First misuse recorded by one of this encoder's render passes; returned by `finish`.
+    pass_error: Arc<Mutex<Option<String>>>,
 }
 
 impl Drop for VulkanCommandEncoder {
@@ -1109,10 +1524,14 @@ impl CommandEncoder for VulkanCommandEncoder {
             buffer: self.buffer,
             pipeline_bound: None,
             pipeline_layout: None,
+            limits: self.limits,
         })
     }
 
     fn begin_render_pass<'a>(&mut self, desc: RenderPassDescriptor<'a>) -> Result<Box<dyn crate::RenderPass>, String> {
+        let has_depth = desc.depth_stencil_attachment.is_some();
+        crate::validate_subpasses(&desc.subpasses, desc.color_attachments.len() as u32, has_depth)
+            .map_err(|e| format!("begin_render_pass({:?}): {}", desc.label, e))?;
         let color_infos: Vec<render_pass::ColorAttachmentInfo> = desc
             .color_attachments
             .iter()
@@ -1132,24 +1551,11 @@ impl CommandEncoder for VulkanCommandEncoder {
             }
         });
 
-        let rp_key = RenderPassCacheKey {
-            color: color_infos
-                .iter()
-                .map(|a| (a.format, a.load_op, a.store_op, a.initial_layout))
-                .collect(),
-            depth: depth_info.as_ref().map(|d| (d.format, d.depth_load_op, d.depth_store_op)),
-        };
-        let vk_render_pass = {
-            let mut cache = self.render_pass_cache.lock().map_err(|e| format!("render_pass_cache lock: {}", e))?;
-            if let Some(&cached) = cache.get(&rp_key) {
-                cached
-            } else {
-                let rp = render_pass::create_vk_render_pass(&self.device, &color_infos, depth_info.as_ref())
-                    .map_err(|e| format!("create render pass: {}", e))?;
-                cache.insert(rp_key.clone(), rp);
-                cache.get(&rp_key).copied().unwrap()
-            }
-        };
+        let rp_key = RenderPassCacheKey::new(&color_infos, depth_info.as_ref(), &desc.subpasses);
+        let vk_render_pass = cached_render_pass(&self.render_pass_cache, rp_key, || {
+            render_pass::create_vk_render_pass(&self.device, &color_infos, depth_info.as_ref(), &desc.subpasses)
+                .map_err(|e| format!("create render pass: {}", e))
+        })?;
 
         let mut image_views = Vec::new();
         for att in &desc.color_attachments {
@@ -1241,6 +1647,22 @@ impl CommandEncoder for VulkanCommandEncoder {
             vk_render_pass,
             framebuffer,
             extent,
+            render_pass::effective_subpasses(&desc.subpasses, desc.color_attachments.len() as u32, has_depth)
+                .iter()
+                .map(|s| render_pass::SubpassAttachments {
+                    color_count: s.color_attachments.len() as u32,
+                    depth: s.depth_stencil,
+                    inputs: s
+                        .input_attachments
+                        .iter()
+                        .map(|&i| match desc.color_attachments.get(i as usize) {
+                            Some(a) => a.texture.id(),
+                            None => desc.depth_stencil_attachment.as_ref().map_or(0, |d| d.texture.id()),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            Arc::clone(&self.pass_error),
         );
 
         Ok(Box::new(recorder))
@@ -1263,39 +1685,27 @@ impl CommandEncoder for VulkanCommandEncoder {
         unsafe {
             self.device.cmd_copy_buffer(
                 self.buffer,
-                src_buf.buffer,
-                dst_buf.buffer,
+                src_buf.raw(),
+                dst_buf.raw(),
                 &[region],
             );
         }
     }
 
-    fn pipeline_barrier_texture(
+    fn pipeline_barrier_texture_range(
         &mut self,
         texture: &dyn Texture,
         old_layout: ImageLayout,
         new_layout: ImageLayout,
+        range: TextureSubresourceRange,
     ) {
-        #[cfg(feature = "window")]
-        let image = if let Some(t) = texture.as_any().downcast_ref::<VulkanTexture>() {
-            t.image
-        } else if let Some(s) = texture.as_any().downcast_ref::<VulkanSwapchainImage>() {
-            s.image
-        } else {
-            panic!("texture must be VulkanTexture or VulkanSwapchainImage");
-        };
-        #[cfg(not(feature = "window"))]
-        let image = texture.as_any().downcast_ref::<VulkanTexture>().expect("texture must be VulkanTexture").image;
+        let image = texture_image(texture);
         let (old_l, new_l) = (
             image_layout_to_vk(old_layout),
             image_layout_to_vk(new_layout),
         );
         let is_depth = matches!(texture.format(), TextureFormat::D32Float);
-        let aspect_mask = if is_depth {
-            vk::ImageAspectFlags::DEPTH
-        } else {
-            vk::ImageAspectFlags::COLOR
-        };
+        let aspect_mask = texture_aspect(texture.format());
         let (src_stage, src_access, dst_stage, dst_access) = image_barrier_stages_access(
             old_layout,
             new_layout,
@@ -1307,14 +1717,7 @@ impl CommandEncoder for VulkanCommandEncoder {
             .image(image)
             .src_access_mask(src_access)
             .dst_access_mask(dst_access)
-            .subresource_range(
-                vk::ImageSubresourceRange::default()
-                    .aspect_mask(aspect_mask)
-                    .base_mip_level(0)
-                    .level_count(1)
-                    .base_array_layer(0)
-                    .layer_count(1),
-            );
+            .subresource_range(subresource_range_to_vk(range, aspect_mask));
         unsafe {
             self.device.cmd_pipeline_barrier(
                 self.buffer,
@@ -1351,7 +1754,7 @@ impl CommandEncoder for VulkanCommandEncoder {
             .dst_access_mask(vk::AccessFlags::SHADER_READ)
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
             .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-            .buffer(vk_buf.buffer)
+            .buffer(vk_buf.raw())
             .offset(offset)
             .size(size);
         unsafe {
@@ -1369,6 +1772,135 @@ impl CommandEncoder for VulkanCommandEncoder {
         }
     }
 
+    fn pipeline_barrier_buffer_after_read(&mut self, buffer: &dyn crate::Buffer, offset: u64, size: u64) {
+        let vk_buf = buffer
+            .as_any()
+            .downcast_ref::<buffer::VulkanBuffer>()
+            .expect("Buffer must be VulkanBuffer");
+        let size = if size == 0 {
+            buffer.size().saturating_sub(offset)
+        } else {
+            size
+        };
+        if size == 0 {
+            return;
+        }
+        // Write-after-read only needs the reads to finish before the writes start: no access masks.
+        let barrier = vk::BufferMemoryBarrier::default()
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(vk_buf.raw())
+            .offset(offset)
+            .size(size);
+        // Every stage that reads buffers: vertex/index fetch, indirect args, shaders and copies.
+        let read_stages = vk::PipelineStageFlags::DRAW_INDIRECT
+            | vk::PipelineStageFlags::VERTEX_INPUT
+            | vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::FRAGMENT_SHADER
+            | vk::PipelineStageFlags::COMPUTE_SHADER
+            | vk::PipelineStageFlags::TRANSFER;
+        let write_stages = vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::FRAGMENT_SHADER
+            | vk::PipelineStageFlags::COMPUTE_SHADER
+            | vk::PipelineStageFlags::TRANSFER;
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.buffer,
+                read_stages,
+                write_stages,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+        }
+    }
+
+    fn pipeline_barrier_memory(&mut self) {
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE);
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    fn pipeline_barrier_buffer_host_read(&mut self, buffer: &dyn crate::Buffer, offset: u64, size: u64) {
+        let vk_buf = buffer
+            .as_any()
+            .downcast_ref::<buffer::VulkanBuffer>()
+            .expect("Buffer must be VulkanBuffer");
+        let size = if size == 0 {
+            buffer.size().saturating_sub(offset)
+        } else {
+            size
+        };
+        if size == 0 {
+            return;
+        }
+        let barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(vk_buf.raw())
+            .offset(offset)
+            .size(size);
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+        }
+    }
+
+    fn pipeline_barrier_buffer_indirect_read(&mut self, buffer: &dyn crate::Buffer, offset: u64, size: u64) {
+        let vk_buf = buffer
+            .as_any()
+            .downcast_ref::<buffer::VulkanBuffer>()
+            .expect("Buffer must be VulkanBuffer");
+        let size = if size == 0 {
+            buffer.size().saturating_sub(offset)
+        } else {
+            size
+        };
+        if size == 0 {
+            return;
+        }
+        let barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::INDIRECT_COMMAND_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(vk_buf.raw())
+            .offset(offset)
+            .size(size);
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[barrier],
+                &[],
+            );
+        }
+    }
+
     fn copy_buffer_to_texture(
         &mut self,
         src: &dyn Buffer,
@@ -1406,7 +1938,7 @@ impl CommandEncoder for VulkanCommandEncoder {
         unsafe {
             self.device.cmd_copy_buffer_to_image(
                 self.buffer,
-                src_buf.buffer,
+                src_buf.raw(),
                 dst_tex.image,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                 &[region],
@@ -1414,6 +1946,99 @@ impl CommandEncoder for VulkanCommandEncoder {
         }
     }
 
+    fn copy_texture_to_buffer(
+        &mut self,
+        src: &dyn Texture,
+        src_mip: u32,
+        src_origin: (u32, u32, u32),
+        size: (u32, u32, u32),
+        dst: &dyn Buffer,
+        dst_offset: u64,
+    ) {
+        let src_image = texture_image(src);
+        let dst_buf = dst.as_any().downcast_ref::<buffer::VulkanBuffer>().expect("dst must be VulkanBuffer");
+        let (width, height, _) = size;
+        let (image_subresource, z, depth) = region_layers(src, src_mip, src_origin.2, size.2);
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(dst_offset)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(image_subresource)
+            .image_offset(vk::Offset3D {
+                x: src_origin.0 as i32,
+                y: src_origin.1 as i32,
+                z: z as i32,
+            })
+            .image_extent(vk::Extent3D { width, height, depth });
+        // Transfer write -> host read, so read_buffer sees the data after the fence / wait_idle.
+        let host_barrier = vk::BufferMemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(dst_buf.raw())
+            .offset(0)
+            .size(vk::WHOLE_SIZE);
+        unsafe {
+            self.device.cmd_copy_image_to_buffer(
+                self.buffer,
+                src_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst_buf.raw(),
+                &[region],
+            );
+            self.device.cmd_pipeline_barrier(
+                self.buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_barrier],
+                &[],
+            );
+        }
+    }
+
+    fn blit_texture(
+        &mut self,
+        src: &dyn Texture,
+        src_region: TextureRegion,
+        dst: &dyn Texture,
+        dst_region: TextureRegion,
+        filter: FilterMode,
+    ) {
+        let src_tex = src.as_any().downcast_ref::<VulkanTexture>().expect("src must be VulkanTexture");
+        let dst_tex = dst.as_any().downcast_ref::<VulkanTexture>().expect("dst must be VulkanTexture");
+        let bounds = |texture: &dyn Texture, region: TextureRegion| {
+            let (x, y, _) = region.origin;
+            let (width, height, _) = region.size;
+            let (subresource, z, depth) = region_layers(texture, region.mip_level, region.origin.2, region.size.2);
+            let offsets = [
+                vk::Offset3D { x: x as i32, y: y as i32, z: z as i32 },
+                vk::Offset3D { x: (x + width) as i32, y: (y + height) as i32, z: (z + depth) as i32 },
+            ];
+            (subresource, offsets)
+        };
+        let (src_subresource, src_offsets) = bounds(src, src_region);
+        let (dst_subresource, dst_offsets) = bounds(dst, dst_region);
+        let region = vk::ImageBlit::default()
+            .src_subresource(src_subresource)
+            .src_offsets(src_offsets)
+            .dst_subresource(dst_subresource)
+            .dst_offsets(dst_offsets);
+        unsafe {
+            self.device.cmd_blit_image(
+                self.buffer,
+                src_tex.image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                dst_tex.image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                sampler::filter_to_vk(filter),
+            );
+        }
+    }
+
     fn finish(mut self: Box<Self>) -> Result<Box<dyn CommandBuffer>, String> {
         unsafe {
             self.device
@@ -1421,6 +2046,11 @@ impl CommandEncoder for VulkanCommandEncoder {
                 .map_err(|e| format!("end command buffer: {:?}", e))?;
         }
         self.finished = true;
+        if let Some(e) = self.pass_error.lock().map_err(|e| e.to_string())?.take() {
+            // The buffer is ended, so dropping it here frees it like any other.
+            drop(VulkanCommandBuffer { device: Arc::clone(&self.device), command_pool: self.command_pool, buffer: self.buffer });
+            return Err(e);
+        }
         Ok(Box::new(VulkanCommandBuffer {
             device: Arc::clone(&self.device),
             command_pool: self.command_pool,
@@ -1434,6 +2064,7 @@ struct VulkanComputePass {
     buffer: vk::CommandBuffer,
     pipeline_bound: Option<vk::Pipeline>,
     pipeline_layout: Option<vk::PipelineLayout>,
+    limits: crate::DeviceLimits,
 }
 
 impl std::fmt::Debug for VulkanComputePass {
@@ -1474,10 +2105,12 @@ impl ComputePass for VulkanComputePass {
         }
     }
 
-    fn dispatch(&mut self, x: u32, y: u32, z: u32) {
+    fn dispatch(&mut self, x: u32, y: u32, z: u32) -> Result<(), String> {
+        self.limits.validate_dispatch(x, y, z)?;
         unsafe {
             self.device.cmd_dispatch(self.buffer, x, y, z);
         }
+        Ok(())
     }
 
     fn dispatch_indirect(&mut self, buffer: &dyn crate::Buffer, offset: u64) {
@@ -1486,7 +2119,7 @@ impl ComputePass for VulkanComputePass {
             .downcast_ref::<buffer::VulkanBuffer>()
             .expect("Buffer must be VulkanBuffer");
         unsafe {
-            self.device.cmd_dispatch_indirect(self.buffer, vk_buf.buffer, offset);
+            self.device.cmd_dispatch_indirect(self.buffer, vk_buf.raw(), offset);
         }
     }
 }
@@ -1578,3 +2211,630 @@ impl Semaphore for VulkanSemaphore {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_device;
+    use render_pass::{ColorAttachmentInfo, DepthAttachmentInfo};
+
+    fn color(format: TextureFormat) -> ColorAttachmentInfo {
+        ColorAttachmentInfo { format, load_op: LoadOp::Clear, store_op: StoreOp::Store, initial_layout: None }
+    }
+
+    /// Looks up `colors` in `cache`, counting creations; handles are fake (never passed to Vulkan).
+    fn lookup(
+        cache: &Mutex<HashMap<RenderPassCacheKey, vk::RenderPass>>,
+        created: &mut u64,
+        colors: &[ColorAttachmentInfo],
+        depth: Option<&DepthAttachmentInfo>,
+    ) -> vk::RenderPass {
+        cached_render_pass(cache, RenderPassCacheKey::new(colors, depth, &[]), || {
+            *created += 1;
+            Ok(vk::RenderPass::from_raw(*created))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn subresource_ranges() {
+        let full = subresource_range_to_vk(TextureSubresourceRange::default(), vk::ImageAspectFlags::COLOR);
+        assert_eq!((full.base_mip_level, full.level_count), (0, vk::REMAINING_MIP_LEVELS));
+        assert_eq!((full.base_array_layer, full.layer_count), (0, vk::REMAINING_ARRAY_LAYERS));
+
+        let mip = subresource_range_to_vk(TextureSubresourceRange::mip(3), vk::ImageAspectFlags::COLOR);
+        assert_eq!((mip.base_mip_level, mip.level_count), (3, 1));
+        assert_eq!(mip.layer_count, vk::REMAINING_ARRAY_LAYERS);
+
+        let face = subresource_range_to_vk(TextureSubresourceRange::layer(5), vk::ImageAspectFlags::DEPTH);
+        assert_eq!((face.base_array_layer, face.layer_count), (5, 1));
+        assert_eq!((face.base_mip_level, face.level_count), (0, vk::REMAINING_MIP_LEVELS));
+        assert_eq!(face.aspect_mask, vk::ImageAspectFlags::DEPTH);
+    }
+
+    #[test]
+    fn render_pass_cache_mrt_keys() {
+        let cache = Mutex::new(HashMap::new());
+        let mut created = 0;
+        let depth = DepthAttachmentInfo {
+            format: TextureFormat::D32Float,
+            depth_load_op: LoadOp::Clear,
+            depth_store_op: StoreOp::Store,
+        };
+        let one = [color(TextureFormat::Rgba16Float)];
+        let two = [color(TextureFormat::Rgba8Unorm), color(TextureFormat::Rgba8Unorm)];
+        let four = [
+            color(TextureFormat::Rgba8Unorm),
+            color(TextureFormat::Rgba8Unorm),
+            color(TextureFormat::Rgba8Unorm),
+            color(TextureFormat::Rgba8Unorm),
+        ];
+        let rp1 = lookup(&cache, &mut created, &one, None);
+        let rp2 = lookup(&cache, &mut created, &two, None);
+        let rp4 = lookup(&cache, &mut created, &four, Some(&depth));
+        assert_eq!(created, 3);
+        assert!(rp1 != rp2 && rp2 != rp4 && rp1 != rp4);
+
+        // Identical configurations reuse the cached pass.
+        assert_eq!(lookup(&cache, &mut created, &one, None), rp1);
+        assert_eq!(lookup(&cache, &mut created, &two, None), rp2);
+        assert_eq!(lookup(&cache, &mut created, &four, Some(&depth)), rp4);
+        assert_eq!(created, 3);
+
+        // Depth presence, per-attachment ops and attachment order all distinguish keys.
+        lookup(&cache, &mut created, &four, None);
+        let mut four_load = four.map(|a| color(a.format));
+        four_load[3].load_op = LoadOp::Load;
+        lookup(&cache, &mut created, &four_load, Some(&depth));
+        lookup(&cache, &mut created, &[color(TextureFormat::Rgba8Unorm), color(TextureFormat::Rgba16Float)], None);
+        lookup(&cache, &mut created, &[color(TextureFormat::Rgba16Float), color(TextureFormat::Rgba8Unorm)], None);
+        assert_eq!(created, 7);
+        assert_eq!(cache.lock().unwrap().len(), 7);
+    }
+
+    /// Compile one WGSL entry point to SPIR-V bytes for tests that need real shaders.
+    fn spirv(source: &str, stage: naga::ShaderStage, entry_point: &str) -> Vec<u8> {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let info = naga::valid::Validator::new(naga::valid::ValidationFlags::default(), naga::valid::Capabilities::default())
+            .validate(&module)
+            .unwrap();
+        let options = naga::back::spv::PipelineOptions { shader_stage: stage, entry_point: entry_point.to_string() };
+        let words = naga::back::spv::write_vec(&module, &info, &naga::back::spv::Options::default(), Some(&options)).unwrap();
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    /// Pipelines borrow their render pass from the device cache: creating and dropping many of them
+    /// leaves a single cached pass that later recordings can still use. Skipped without a Vulkan device.
+    #[test]
+    fn pipelines_share_cached_render_pass() {
+        let Some(device) = test_device("pipelines_share_cached_render_pass") else { return };
+        let source = "@vertex fn vs(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+                return vec4<f32>(f32(i & 1u), f32(i >> 1u), 0.0, 1.0);
+            }
+            @fragment fn fs() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }";
+        let desc = GraphicsPipelineDescriptor {
+            label: Some("cache_test"),
+            vertex_shader: crate::ShaderStage { source: spirv(source, naga::ShaderStage::Vertex, "vs"), entry_point: "vs".to_string() },
+            fragment_shader: Some(crate::ShaderStage {
+                source: spirv(source, naga::ShaderStage::Fragment, "fs"),
+                entry_point: "fs".to_string(),
+            }),
+            vertex_input: Default::default(),
+            primitive_topology: crate::PrimitiveTopology::TriangleList,
+            primitive_restart: false,
+            rasterization: Default::default(),
+            color_targets: vec![crate::ColorTargetState {
+                format: TextureFormat::Rgba8Unorm,
+                blend: None,
+                load_op: None,
+                store_op: None,
+            }],
+            depth_stencil: None,
+            layout_bindings: Vec::new(),
+            subpass: None,
+        };
+        let mut pipelines: Vec<_> = (0..32).map(|_| device.create_graphics_pipeline(&desc).unwrap()).collect();
+        assert_eq!(device.render_pass_cache.lock().unwrap().len(), 1);
+        pipelines.truncate(1);
+
+        let target = device
+            .create_texture(&crate::TextureDescriptor {
+                label: Some("cache_test_target"),
+                size: (4, 4, 1),
+                format: TextureFormat::Rgba8Unorm,
+                usage: crate::TextureUsage::RENDER_ATTACHMENT,
+                ..Default::default()
+            })
+            .unwrap();
+        let mut encoder = device.create_command_encoder().unwrap();
+        encoder.pipeline_barrier_texture(target.as_ref(), ImageLayout::Undefined, ImageLayout::ColorAttachment);
+        {
+            let mut pass = encoder
+                .begin_render_pass(RenderPassDescriptor {
+                    label: Some("cache_test"),
+                    color_attachments: vec![crate::ColorAttachment {
+                        texture: target.as_ref(),
+                        load_op: LoadOp::Clear,
+                        store_op: StoreOp::Store,
+                        clear_value: None,
+                        initial_layout: Some(ImageLayout::ColorAttachment),
+                    }],
+                    depth_stencil_attachment: None,
+                    subpasses: Vec::new(),
+                })
+                .unwrap();
+            pass.set_pipeline(pipelines[0].as_ref());
+            pass.draw(3, 1, 0, 0);
+            pass.end();
+        }
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+        drop(pipelines);
+        assert_eq!(device.render_pass_cache.lock().unwrap().len(), 1);
+    }
+
+    /// A GBuffer subpass and a lighting subpass reading it as an input attachment record in one pass.
+    /// Skipped without a Vulkan device.
+    #[test]
+    fn gbuffer_and_lighting_share_one_render_pass() {
+        let Some(device) = test_device("gbuffer_and_lighting_share_one_render_pass") else { return };
+        let texture = |format, usage, transient| {
+            device
+                .create_texture(&crate::TextureDescriptor { size: (8, 8, 1), format, usage, transient, ..Default::default() })
+                .unwrap()
+        };
+        let on_chip = crate::TextureUsage::RENDER_ATTACHMENT | crate::TextureUsage::INPUT_ATTACHMENT;
+        let albedo = texture(TextureFormat::Rgba8Unorm, on_chip, true);
+        let lit = texture(TextureFormat::Rgba8Unorm, crate::TextureUsage::RENDER_ATTACHMENT, false);
+        let depth = texture(TextureFormat::D32Float, on_chip, true);
+        let input = |binding| DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: DescriptorType::InputAttachment,
+            count: 1,
+            stages: ShaderStages::FRAGMENT,
+        };
+        let layout = device.create_descriptor_set_layout(&[input(0), input(1)]).unwrap();
+        let pool = device.create_descriptor_pool(1).unwrap();
+        let mut set = pool.allocate_set(layout.as_ref()).unwrap();
+        set.write_input_attachment(0, albedo.as_ref()).unwrap();
+        set.write_input_attachment(1, depth.as_ref()).unwrap();
+        assert!(set.write_texture(0, albedo.as_ref()).is_err());
+        assert!(set.write_input_attachment(2, albedo.as_ref()).is_err());
+        assert!(device
+            .create_descriptor_set_layout(&[DescriptorSetLayoutBinding { stages: ShaderStages::COMPUTE, ..input(0) }])
+            .is_err());
+        fn attachment(texture: &dyn Texture, store_op: StoreOp) -> crate::ColorAttachment<'_> {
+            crate::ColorAttachment { texture, load_op: LoadOp::Clear, store_op, clear_value: None, initial_layout: None }
+        }
+        let mut encoder = device.create_command_encoder().unwrap();
+        let mut pass = encoder
+            .begin_render_pass(RenderPassDescriptor {
+                label: Some("deferred"),
+                color_attachments: vec![attachment(albedo.as_ref(), StoreOp::DontCare), attachment(lit.as_ref(), StoreOp::Store)],
+                depth_stencil_attachment: Some(crate::DepthStencilAttachment {
+                    texture: depth.as_ref(),
+                    depth_load_op: LoadOp::Clear,
+                    depth_store_op: StoreOp::DontCare,
+                    stencil_load_op: LoadOp::Clear,
+                    stencil_store_op: StoreOp::DontCare,
+                    clear_depth: 1.0,
+                }),
+                subpasses: vec![
+                    crate::SubpassDescription { color_attachments: vec![0], input_attachments: vec![], depth_stencil: true },
+                    crate::SubpassDescription { color_attachments: vec![1], input_attachments: vec![0, 2], depth_stencil: false },
+                ],
+            })
+            .unwrap();
+        let rect = crate::Rect2D { x: 0, y: 0, width: 8, height: 8 };
+        assert!(pass.clear_region(rect, None, Some(0.5)).is_ok());
+        pass.next_subpass().unwrap();
+        assert!(pass.clear_region(rect, None, Some(0.5)).is_err());
+        assert!(pass.next_subpass().is_err());
+        pass.end();
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+
+        // The GBuffer subpass declares no inputs: binding the set there is reported by finish.
+        let mut encoder = device.create_command_encoder().unwrap();
+        let mut pass = encoder
+            .begin_render_pass(RenderPassDescriptor {
+                label: Some("deferred"),
+                color_attachments: vec![attachment(albedo.as_ref(), StoreOp::DontCare), attachment(lit.as_ref(), StoreOp::Store)],
+                depth_stencil_attachment: Some(crate::DepthStencilAttachment {
+                    texture: depth.as_ref(),
+                    depth_load_op: LoadOp::Clear,
+                    depth_store_op: StoreOp::DontCare,
+                    stencil_load_op: LoadOp::Clear,
+                    stencil_store_op: StoreOp::DontCare,
+                    clear_depth: 1.0,
+                }),
+                subpasses: vec![
+                    crate::SubpassDescription { color_attachments: vec![0], input_attachments: vec![], depth_stencil: true },
+                    crate::SubpassDescription { color_attachments: vec![1], input_attachments: vec![0, 2], depth_stencil: false },
+                ],
+            })
+            .unwrap();
+        pass.bind_descriptor_set(0, set.as_ref());
+        pass.next_subpass().unwrap();
+        pass.end();
+        let err = encoder.finish().unwrap_err();
+        assert!(err.contains("bind_descriptor_set"), "{}", err);
+    }
+
+    /// Identical descriptors share one pipeline; the uncached path and any descriptor change create a
+    /// new one. Skipped without a Vulkan device.
+    #[test]
+    fn compute_pipeline_cache_reuses_pipelines() {
+        let Some(device) = test_device("compute_pipeline_cache_reuses_pipelines") else { return };
+        let source = "@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+            @compute @workgroup_size(64) fn a(@builtin(global_invocation_id) id: vec3<u32>) { data[id.x] = id.x; }
+            @compute @workgroup_size(64) fn b(@builtin(global_invocation_id) id: vec3<u32>) { data[id.x] = 0u; }";
+        let desc = |entry: &str| ComputePipelineDescriptor {
+            label: Some("cache_test"),
+            shader_source: spirv(source, naga::ShaderStage::Compute, entry),
+            entry_point: entry.to_string(),
+            layout_bindings: vec![DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: DescriptorType::StorageBuffer,
+                count: 1,
+                stages: ShaderStages::COMPUTE,
+            }],
+        };
+        let raw = |p: &dyn crate::ComputePipeline| {
+            p.as_any().downcast_ref::<pipeline::VulkanComputePipeline>().unwrap() as *const pipeline::VulkanComputePipeline
+        };
+        let first = device.create_compute_pipeline(&desc("a")).unwrap();
+        let second = device.create_compute_pipeline(&ComputePipelineDescriptor { label: None, ..desc("a") }).unwrap();
+        assert_eq!(raw(first.as_ref()), raw(second.as_ref()));
+
+        let uncached = device.create_compute_pipeline_uncached(&desc("a")).unwrap();
+        assert_ne!(raw(first.as_ref()), raw(uncached.as_ref()));
+        let other_entry = device.create_compute_pipeline(&desc("b")).unwrap();
+        assert_ne!(raw(first.as_ref()), raw(other_entry.as_ref()));
+        assert_eq!(device.compute_pipeline_cache.lock().unwrap().len(), 2);
+
+        // Cached pipelines outlive the handles that created them.
+        drop((first, second));
+        let again = device.create_compute_pipeline(&desc("a")).unwrap();
+        assert_eq!(device.compute_pipeline_cache.lock().unwrap().len(), 2);
+
+        // A full cache drops the pipelines nobody holds and keeps the live ones.
+        let unused_binding = |count: u32| ComputePipelineDescriptor {
+            layout_bindings: vec![
+                desc("b").layout_bindings[0].clone(),
+                DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: DescriptorType::StorageBuffer,
+                    count,
+                    stages: ShaderStages::COMPUTE,
+                },
+            ],
+            ..desc("b")
+        };
+        let filler = COMPUTE_PIPELINE_CACHE_CAPACITY as u32 - 2;
+        for count in 1..=filler {
+            device.create_compute_pipeline(&unused_binding(count)).unwrap();
+        }
+        assert_eq!(device.compute_pipeline_cache.lock().unwrap().len(), COMPUTE_PIPELINE_CACHE_CAPACITY);
+        device.create_compute_pipeline(&unused_binding(filler + 1)).unwrap();
+        // "a" (held by `again`), "b" (held by `other_entry`) and the newest one.
+        assert_eq!(device.compute_pipeline_cache.lock().unwrap().len(), 3);
+        let again_too = device.create_compute_pipeline(&desc("a")).unwrap();
+        assert_eq!(raw(again.as_ref()), raw(again_too.as_ref()));
+        drop((again, again_too));
+    }
+
+    /// A workgroup larger than the device allows fails at pipeline creation, not at dispatch.
+    /// Skipped without a Vulkan device.
+    #[test]
+    fn compute_pipeline_rejects_oversized_workgroup() {
+        let Some(device) = test_device("compute_pipeline_rejects_oversized_workgroup") else { return };
+        let max = device.limits().max_compute_work_group_size[0];
+        let source = format!("@compute @workgroup_size({}) fn main() {{}}", max + 1);
+        let desc = ComputePipelineDescriptor {
+            label: Some("oversized"),
+            shader_source: spirv(&source, naga::ShaderStage::Compute, "main"),
+            entry_point: "main".to_string(),
+            layout_bindings: Vec::new(),
+        };
+        let err = device.create_compute_pipeline(&desc).unwrap_err();
+        assert!(err.contains("workgroup size"), "{}", err);
+        assert!(device.create_compute_pipeline_uncached(&desc).is_err());
+    }
+
+    /// Zero and oversized dispatches are rejected by the compute pass itself, and the encoder still
+    /// records and submits the valid dispatch afterwards. Skipped without a Vulkan device.
+    #[test]
+    fn compute_pass_rejects_invalid_dispatch() {
+        let Some(device) = test_device("compute_pass_rejects_invalid_dispatch") else { return };
+        let desc = ComputePipelineDescriptor {
+            label: Some("dispatch_limits"),
+            shader_source: spirv("@compute @workgroup_size(1) fn main() {}", naga::ShaderStage::Compute, "main"),
+            entry_point: "main".to_string(),
+            layout_bindings: Vec::new(),
+        };
+        let pipeline = device.create_compute_pipeline(&desc).unwrap();
+        let max = device.limits().max_compute_work_group_count;
+        let mut encoder = device.create_command_encoder().unwrap();
+        {
+            let mut pass = encoder.begin_compute_pass();
+            pass.set_pipeline(pipeline.as_ref());
+            for axis in 0..3 {
+                // A u32::MAX limit cannot be exceeded.
+                let Some(over) = max[axis].checked_add(1) else { continue };
+                let mut count = [1; 3];
+                count[axis] = over;
+                let err = pass.dispatch(count[0], count[1], count[2]).unwrap_err();
+                assert!(err.contains("exceeds max workgroup count"), "{}", err);
+            }
+            let err = pass.dispatch(0, 1, 1).unwrap_err();
+            assert!(err.contains("non-zero"), "{}", err);
+            pass.dispatch(1, 1, 1).unwrap();
+        }
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+    }
+
+    /// Reads at an offset return the bytes from that offset on; reads and writes past the end are
+    /// rejected.
+    /// Skipped without a Vulkan device.
+    #[test]
+    fn read_buffer_honors_offset() {
+        let Some(device) = test_device("read_buffer_honors_offset") else { return };
+        let buffer = device.create_buffer(&BufferDescriptor { size: 256, ..Default::default() }).unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        device.write_buffer(buffer.as_ref(), 0, &data).unwrap();
+        let mut out = [0u8; 16];
+        device.read_buffer(buffer.as_ref(), 100, &mut out).unwrap();
+        assert_eq!(out[..], data[100..116]);
+        assert!(device.read_buffer(buffer.as_ref(), 250, &mut out).is_err());
+        assert!(device.read_buffer(buffer.as_ref(), u64::MAX, &mut out).is_err());
+        // Writes past the end fail instead of reaching the rest of the memory block.
+        assert!(device.write_buffer(buffer.as_ref(), 250, &out).is_err());
+        assert!(device.write_buffer(buffer.as_ref(), u64::MAX, &out).is_err());
+    }
+
+    /// The device must report at least what the spec guarantees. Skipped without a Vulkan device.
+    #[test]
+    fn format_properties_cover_guaranteed_support() {
+        let Some(device) = test_device("format_properties_cover_guaranteed_support") else { return };
+        for format in [TextureFormat::Rgba8Unorm, TextureFormat::Rgba16Float, TextureFormat::D32Float] {
+            let props = device.format_properties(format);
+            let guaranteed = FormatProperties::guaranteed(format);
+            assert!(props.supports(guaranteed.usages), "{:?}: {:?}", format, props);
+            assert!(props.supports_sample_count(1), "{:?}: {:?}", format, props);
+        }
+    }
+
+    /// Allocated bytes grow with new buffers and return when they are dropped; small buffers share one
+    /// memory block, and host-visible ones in the same block keep their own data. Skipped without a
+    /// Vulkan device.
+    #[test]
+    fn memory_budget_tracks_allocations() {
+        let Some(device) = test_device("memory_budget_tracks_allocations") else { return };
+        let before = device.memory_budget();
+        assert!(before.device_local_budget > 0);
+        let buffers: Vec<_> = (0..64u8)
+            .map(|i| {
+                let buffer = device.create_buffer(&BufferDescriptor { size: 4096, ..Default::default() }).unwrap();
+                device.write_buffer(buffer.as_ref(), 0, &[i; 16]).unwrap();
+                buffer
+            })
+            .collect();
+        let used = device.memory_budget();
+        assert!(used.allocated >= before.allocated + 64 * 4096);
+        assert!(used.device_memory_count <= before.device_memory_count + 1, "{:?}", used);
+        let mut out = [0u8; 16];
+        device.read_buffer(buffers[5].as_ref(), 0, &mut out).unwrap();
+        assert_eq!(out, [5; 16]);
+        drop(buffers);
+        // The emptied block is kept for reuse until defragment trims it.
+        let kept = device.memory_budget();
+        assert_eq!(kept.allocated, before.allocated);
+        assert!(kept.reserved <= before.reserved + memory::MEMORY_BLOCK_SIZE, "{:?}", kept);
+        device.defragment().unwrap();
+        let after = device.memory_budget();
+        assert_eq!(
+            (after.allocated, after.reserved, after.device_memory_count),
+            (before.allocated, before.reserved, before.device_memory_count)
+        );
+    }
+
+    /// A buffer alone in a second block moves into a gap of the first, keeps its contents and frees
+    /// the second block; one without copy usage stays. Skipped without a Vulkan device.
+    #[test]
+    fn defragment_moves_buffers_into_fuller_blocks() {
+        let Some(device) = test_device("defragment_moves_buffers_into_fuller_blocks") else { return };
+        let usage = BufferUsage::VERTEX | BufferUsage::COPY_SRC | BufferUsage::COPY_DST;
+        let desc = BufferDescriptor { size: 7 << 20, usage, ..Default::default() };
+        // Nine fill most of the first block; the tenth opens a second one.
+        let mut buffers: Vec<_> = (0..10).map(|_| device.create_buffer(&desc).unwrap()).collect();
+        let last = buffers.last().unwrap();
+        device.write_buffer(last.as_ref(), 0, &[7; 64]).unwrap();
+        let last_id = last.id();
+        buffers.remove(0);
+        let report = device.defragment().unwrap();
+        assert_eq!(report.moved_buffers, [last_id]);
+        assert_eq!((report.freed_bytes, report.freed_device_memory), (memory::MEMORY_BLOCK_SIZE, 1));
+        let mut out = [0u8; 64];
+        device.read_buffer(buffers.last().unwrap().as_ref(), 0, &mut out).unwrap();
+        assert_eq!(out, [7; 64]);
+        assert!(device.defragment().unwrap().moved_buffers.is_empty());
+        // The first block is full again, so this opens another block that a gap would fit.
+        let pinned = device.create_buffer(&BufferDescriptor { usage: BufferUsage::VERTEX, ..desc }).unwrap();
+        buffers.remove(0);
+        assert!(device.defragment().unwrap().moved_buffers.is_empty());
+        drop(pinned);
+    }
+
+    /// Depth clamp builds when the device reports the feature and is rejected otherwise.
+    /// Skipped without a Vulkan device.
+    #[test]
+    fn depth_clamp_follows_device_feature() {
+        let Some(device) = test_device("depth_clamp_follows_device_feature") else { return };
+        let source = "@vertex fn vs(@location(0) p: vec3<f32>) -> @builtin(position) vec4<f32> { return vec4<f32>(p, 1.0); }";
+        let desc = GraphicsPipelineDescriptor {
+            label: Some("shadow_depth_clamp"),
+            vertex_shader: crate::ShaderStage { source: spirv(source, naga::ShaderStage::Vertex, "vs"), entry_point: "vs".to_string() },
+            fragment_shader: None,
+            vertex_input: crate::VertexInputDescriptor {
+                attributes: vec![crate::VertexAttribute { location: 0, binding: 0, format: crate::VertexFormat::Float32x3, offset: 0 }],
+                bindings: vec![crate::VertexBinding { binding: 0, stride: 12, input_rate: crate::VertexInputRate::Vertex }],
+            },
+            primitive_topology: crate::PrimitiveTopology::TriangleList,
+            primitive_restart: false,
+            rasterization: crate::RasterizationState { depth_clamp: true, ..Default::default() },
+            color_targets: Vec::new(),
+            depth_stencil: Some(crate::DepthStencilState {
+                format: TextureFormat::D32Float,
+                depth_write_enabled: true,
+                depth_compare: crate::CompareOp::LessOrEqual,
+                depth_load_op: None,
+                depth_store_op: None,
+            }),
+            layout_bindings: Vec::new(),
+            subpass: None,
+        };
+        let result = device.create_graphics_pipeline(&desc);
+        assert_eq!(result.is_ok(), device.features().depth_clamp, "{:?}", result.err());
+    }
+
+    /// Clears the right half of a black target to red inside the pass. Skipped without a Vulkan device.
+    #[test]
+    fn clear_region_clears_only_the_rect() {
+        let Some(device) = test_device("clear_region_clears_only_the_rect") else { return };
+        let target = device
+            .create_texture(&crate::TextureDescriptor {
+                label: Some("clear_region_target"),
+                size: (4, 4, 1),
+                format: TextureFormat::Rgba8Unorm,
+                usage: crate::TextureUsage::RENDER_ATTACHMENT | crate::TextureUsage::COPY_SRC,
+                ..Default::default()
+            })
+            .unwrap();
+        let readback = device
+            .create_buffer(&crate::BufferDescriptor {
+                label: Some("clear_region_readback"),
+                size: 64,
+                usage: crate::BufferUsage::COPY_DST,
+                memory: crate::BufferMemoryPreference::HostVisible,
+            })
+            .unwrap();
+        let red = crate::ClearColor { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
+        let mut encoder = device.create_command_encoder().unwrap();
+        {
+            let mut pass = encoder
+                .begin_render_pass(RenderPassDescriptor {
+                    label: Some("clear_region"),
+                    color_attachments: vec![crate::ColorAttachment {
+                        texture: target.as_ref(),
+                        load_op: LoadOp::Clear,
+                        store_op: StoreOp::Store,
+                        clear_value: Some(crate::ClearColor { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }),
+                        initial_layout: None,
+                    }],
+                    depth_stencil_attachment: None,
+                    subpasses: Vec::new(),
+                })
+                .unwrap();
+            let outside = crate::Rect2D { x: 2, y: 0, width: 4, height: 4 };
+            assert!(pass.clear_region(outside, Some(red), None).is_err());
+            let right_half = crate::Rect2D { x: 2, y: 0, width: 2, height: 4 };
+            assert!(pass.clear_region(right_half, None, Some(1.0)).is_err());
+            pass.clear_region(right_half, Some(red), None).unwrap();
+            pass.end();
+        }
+        encoder.pipeline_barrier_texture(target.as_ref(), ImageLayout::ColorAttachment, ImageLayout::TransferSrc);
+        encoder.copy_texture_to_buffer(target.as_ref(), 0, (0, 0, 0), (4, 4, 1), readback.as_ref(), 0);
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+        let mut texels = [0u8; 64];
+        device.read_buffer(readback.as_ref(), 0, &mut texels).unwrap();
+        for (i, texel) in texels.chunks_exact(4).enumerate() {
+            let expected = if i % 4 >= 2 { [255, 0, 0, 255] } else { [0, 0, 0, 255] };
+            assert_eq!(texel, expected, "texel ({}, {})", i % 4, i / 4);
+        }
+    }
+
+    /// Mips of a 4x4 texture, white on the left half and black on the right, average down to mid grey.
+    /// Skipped without a Vulkan device.
+    #[test]
+    fn generate_mipmaps_averages_down_the_chain() {
+        let Some(device) = test_device("generate_mipmaps_averages_down_the_chain") else { return };
+        assert!(device.format_properties(TextureFormat::Rgba8Unorm).linear_blit);
+        let texture = device
+            .create_texture(&crate::TextureDescriptor {
+                label: Some("mipmapped"),
+                size: (4, 4, 1),
+                format: TextureFormat::Rgba8Unorm,
+                usage: crate::TextureUsage::COPY_SRC | crate::TextureUsage::COPY_DST | crate::TextureUsage::TEXTURE_BINDING,
+                mip_level_count: 3,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!((texture.mip_size(1), texture.mip_size(5)), ((2, 2, 1), (1, 1, 1)));
+        let buffer = |label, memory| {
+            device
+                .create_buffer(&crate::BufferDescriptor {
+                    label: Some(label),
+                    size: 64,
+                    usage: crate::BufferUsage::COPY_SRC | crate::BufferUsage::COPY_DST,
+                    memory,
+                })
+                .unwrap()
+        };
+        let upload = buffer("mip0_upload", crate::BufferMemoryPreference::HostVisible);
+        let texels: Vec<u8> = (0..16).flat_map(|i| if i % 4 < 2 { [255; 4] } else { [0, 0, 0, 255] }).collect();
+        device.write_buffer(upload.as_ref(), 0, &texels).unwrap();
+        let readback = buffer("mip_readback", crate::BufferMemoryPreference::HostVisible);
+        let mut encoder = device.create_command_encoder().unwrap();
+        encoder.pipeline_barrier_texture(texture.as_ref(), ImageLayout::Undefined, ImageLayout::TransferDst);
+        encoder.copy_buffer_to_texture(upload.as_ref(), 0, texture.as_ref(), 0, (0, 0, 0), (4, 4, 1));
+        encoder.generate_mipmaps(texture.as_ref());
+        encoder.pipeline_barrier_texture(texture.as_ref(), ImageLayout::ShaderReadOnly, ImageLayout::TransferSrc);
+        encoder.copy_texture_to_buffer(texture.as_ref(), 1, (0, 0, 0), (2, 2, 1), readback.as_ref(), 0);
+        encoder.copy_texture_to_buffer(texture.as_ref(), 2, (0, 0, 0), (1, 1, 1), readback.as_ref(), 16);
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        device.wait_idle().unwrap();
+        let mut mips = [0u8; 20];
+        device.read_buffer(readback.as_ref(), 0, &mut mips).unwrap();
+        assert_eq!(&mips[..16], [[255; 4], [0, 0, 0, 255], [255; 4], [0, 0, 0, 255]].concat().as_slice());
+        assert!((126..=129).contains(&mips[16]) && mips[19] == 255, "{:?}", &mips[16..]);
+    }
+
+    /// Reads back an uploaded texture from, and restores, the layout it is sampled in. Skipped without a
+    /// Vulkan device.
+    #[test]
+    fn read_texture_returns_texels() {
+        let Some(device) = test_device("read_texture_returns_texels") else { return };
+        let texture = device
+            .create_texture(&crate::TextureDescriptor {
+                label: Some("read_texture_source"),
+                size: (2, 2, 1),
+                format: TextureFormat::Rgba8Unorm,
+                usage: crate::TextureUsage::COPY_SRC | crate::TextureUsage::COPY_DST | crate::TextureUsage::TEXTURE_BINDING,
+                ..Default::default()
+            })
+            .unwrap();
+        let upload = device
+            .create_buffer(&crate::BufferDescriptor {
+                label: Some("read_texture_upload"),
+                size: 16,
+                usage: crate::BufferUsage::COPY_SRC,
+                memory: crate::BufferMemoryPreference::HostVisible,
+            })
+            .unwrap();
+        let texels: Vec<u8> = (0..16).collect();
+        device.write_buffer(upload.as_ref(), 0, &texels).unwrap();
+        let mut encoder = device.create_command_encoder().unwrap();
+        encoder.pipeline_barrier_texture(texture.as_ref(), ImageLayout::Undefined, ImageLayout::TransferDst);
+        encoder.copy_buffer_to_texture(upload.as_ref(), 0, texture.as_ref(), 0, (0, 0, 0), (2, 2, 1));
+        encoder.pipeline_barrier_texture(texture.as_ref(), ImageLayout::TransferDst, ImageLayout::ShaderReadOnly);
+        device.submit(vec![encoder.finish().unwrap()]).unwrap();
+        assert_eq!(device.read_texture(texture.as_ref(), 0, ImageLayout::ShaderReadOnly).unwrap(), texels);
+        // Still readable from the restored layout; out-of-range mips and undefined contents are errors.
+        assert_eq!(device.read_texture(texture.as_ref(), 0, ImageLayout::ShaderReadOnly).unwrap(), texels);
+        assert!(device.read_texture(texture.as_ref(), 1, ImageLayout::ShaderReadOnly).is_err());
+        assert!(device.read_texture(texture.as_ref(), 0, ImageLayout::Undefined).is_err());
+    }
+}
@@ -1,10 +1,12 @@
 //! Vulkan Compute Pipeline.
 
-use crate::{ComputePipeline, ComputePipelineDescriptor};
+use crate::{ComputePipeline, ComputePipelineDescriptor, DeviceLimits};
 use ash::vk;
 use std::ffi::CString;
+use std::sync::Arc;
 
 use super::super::descriptor;
+use crate::spirv;
 
 pub struct VulkanComputePipeline {
     pub(crate) device: ash::Device,
@@ -14,15 +16,15 @@ pub struct VulkanComputePipeline {
 }
 
 impl VulkanComputePipeline {
-    pub fn create(device: &ash::Device, desc: &ComputePipelineDescriptor) -> Result<Self, String> {
-        let code = &desc.shader_source[..];
-        if code.len() % 4 != 0 {
-            return Err("SPIR-V must be 4-byte aligned".to_string());
+    pub fn create(device: &ash::Device, desc: &ComputePipelineDescriptor, limits: &DeviceLimits) -> Result<Self, String> {
+        let code_u32 = spirv::words(&desc.shader_source)?;
+        spirv::check_entry_point(&code_u32, &desc.entry_point, spirv::ExecutionModel::GLCompute)
+            .map_err(|e| format!("create_compute_pipeline({:?}): {}", desc.label, e))?;
+        if let Some([x, y, z]) = spirv::local_size(&code_u32, &desc.entry_point)? {
+            limits
+                .validate_workgroup_size(x, y, z)
+                .map_err(|e| format!("create_compute_pipeline({:?}): {}", desc.label, e))?;
         }
-        let code_u32: Vec<u32> = code
-            .chunks_exact(4)
-            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
-            .collect();
         let shader_create_info = vk::ShaderModuleCreateInfo::default().code(&code_u32);
         let shader_module = unsafe {
             device
@@ -94,3 +96,19 @@ impl ComputePipeline for VulkanComputePipeline {
         self
     }
 }
+
+/// Handle to a pipeline owned by the device's compute pipeline cache. `as_any` exposes the shared
+/// [`VulkanComputePipeline`], so it binds exactly like an uncached one.
+pub struct CachedComputePipeline(pub(crate) Arc<VulkanComputePipeline>);
+
+impl std::fmt::Debug for CachedComputePipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CachedComputePipeline").field(&self.0).finish()
+    }
+}
+
+impl ComputePipeline for CachedComputePipeline {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self.0.as_ref()
+    }
+}
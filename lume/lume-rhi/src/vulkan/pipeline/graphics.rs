@@ -2,7 +2,7 @@
 
 use crate::{
     BlendOp, CullMode, FrontFace, GraphicsPipeline, GraphicsPipelineDescriptor, PolygonMode,
-    PrimitiveTopology, VertexFormat, VertexInputRate,
+    PrimitiveTopology, SubpassDescription, VertexFormat, VertexInputRate,
 };
 use ash::vk;
 use std::ffi::CString;
@@ -10,19 +10,46 @@ use std::ffi::CString;
 use super::super::descriptor;
 use super::super::render_pass::{ColorAttachmentInfo, DepthAttachmentInfo};
 use super::super::texture::texture_format_to_vk;
+use crate::spirv;
 
+/// The pipeline does not own a VkRenderPass: it is created against a pass from the device's render
+/// pass cache (see [`Self::attachment_infos`]), which outlives every pipeline and is the same cache
+/// `begin_render_pass` records with. Dropping a pipeline therefore never invalidates a pass in use.
 pub struct VulkanGraphicsPipeline {
     pub(crate) device: ash::Device,
     pub(crate) pipeline: vk::Pipeline,
     pub(crate) layout: vk::PipelineLayout,
-    pub(crate) render_pass: vk::RenderPass,
     #[allow(dead_code)]
     pub(crate) _set_layout: Option<descriptor::VulkanDescriptorSetLayout>,
 }
 
 impl VulkanGraphicsPipeline {
-    pub fn create(device: &ash::Device, desc: &GraphicsPipelineDescriptor) -> Result<Self, String> {
-        let color_attachments: Vec<ColorAttachmentInfo> = desc
+    /// Attachment configuration of the render pass the pipeline is created against. Unset ops default
+    /// to what `begin_render_pass` is typically called with (Clear/Store color, Load/Store depth);
+    /// Vulkan render pass compatibility ignores ops and layouts, so any matching formats work at draw time.
+    /// With [`GraphicsPipelineDescriptor::subpass`] the attachments and subpasses are those of that pass.
+    pub(crate) fn attachment_infos(
+        desc: &GraphicsPipelineDescriptor,
+    ) -> (Vec<ColorAttachmentInfo>, Option<DepthAttachmentInfo>, Vec<SubpassDescription>) {
+        if let Some(target) = &desc.subpass {
+            let color_attachments = target
+                .color_formats
+                .iter()
+                .map(|&format| ColorAttachmentInfo {
+                    format,
+                    load_op: crate::LoadOp::Clear,
+                    store_op: crate::StoreOp::Store,
+                    initial_layout: Some(crate::ImageLayout::ColorAttachment),
+                })
+                .collect();
+            let depth_attachment = target.depth_format.map(|format| DepthAttachmentInfo {
+                format,
+                depth_load_op: crate::LoadOp::Load,
+                depth_store_op: crate::StoreOp::Store,
+            });
+            return (color_attachments, depth_attachment, target.subpasses.clone());
+        }
+        let color_attachments = desc
             .color_targets
             .iter()
             .map(|t| ColorAttachmentInfo {
@@ -32,28 +59,32 @@ impl VulkanGraphicsPipeline {
                 initial_layout: Some(crate::ImageLayout::ColorAttachment),
             })
             .collect();
-
         let depth_attachment = desc.depth_stencil.as_ref().map(|ds| DepthAttachmentInfo {
             format: ds.format,
             depth_load_op: ds.depth_load_op.unwrap_or(crate::LoadOp::Load),
             depth_store_op: ds.depth_store_op.unwrap_or(crate::StoreOp::Store),
         });
-        // Pipeline render pass must match the one used in begin_render_pass (same initial_layout, load_op, store_op).
+        (color_attachments, depth_attachment, Vec::new())
+    }
 
-        let render_pass = super::super::render_pass::create_vk_render_pass(
-            device,
-            &color_attachments,
-            depth_attachment.as_ref(),
-        )?;
+    /// `render_pass` must be a cached pass for [`Self::attachment_infos`] of `desc`; it is borrowed,
+    /// not destroyed with the pipeline.
+    pub fn create(device: &ash::Device, desc: &GraphicsPipelineDescriptor, render_pass: vk::RenderPass) -> Result<Self, String> {
         let mut stage_modules = Vec::new();
         let mut entry_names: Vec<CString> = Vec::new();
 
-        let vs_module = Self::create_shader_module(device, &desc.vertex_shader.source)?;
+        let vs_module = Self::create_shader_module(device, desc, &desc.vertex_shader, spirv::ExecutionModel::Vertex)?;
         stage_modules.push(vs_module);
         entry_names.push(CString::new(desc.vertex_shader.entry_point.as_str()).map_err(|e| e.to_string())?);
 
         if let Some(ref fs) = desc.fragment_shader {
-            let fs_module = Self::create_shader_module(device, &fs.source)?;
+            let fs_module = match Self::create_shader_module(device, desc, fs, spirv::ExecutionModel::Fragment) {
+                Ok(module) => module,
+                Err(e) => {
+                    unsafe { device.destroy_shader_module(vs_module, None) };
+                    return Err(e);
+                }
+            };
             stage_modules.push(fs_module);
             entry_names.push(CString::new(fs.entry_point.as_str()).map_err(|e| e.to_string())?);
         }
@@ -80,14 +111,14 @@ impl VulkanGraphicsPipeline {
             .vertex_attribute_descriptions(&attribute_descriptions);
         let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
             .topology(Self::topology_to_vk(desc.primitive_topology))
-            .primitive_restart_enable(false);
+            .primitive_restart_enable(desc.primitive_restart);
 
         let viewport_state = vk::PipelineViewportStateCreateInfo::default()
             .viewport_count(1)
             .scissor_count(1);
 
         let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
-            .depth_clamp_enable(false)
+            .depth_clamp_enable(desc.rasterization.depth_clamp)
             .rasterizer_discard_enable(false)
             .polygon_mode(Self::polygon_mode_to_vk(desc.rasterization.polygon_mode))
             .line_width(1.0)
@@ -182,7 +213,7 @@ impl VulkanGraphicsPipeline {
             .color_blend_state(&color_blend)
             .layout(pipeline_layout)
             .render_pass(render_pass)
-            .subpass(0)
+            .subpass(desc.subpass.as_ref().map_or(0, |s| s.index))
             .depth_stencil_state(&depth_stencil_create_info)
             .dynamic_state(&dynamic_state);
 
@@ -207,19 +238,20 @@ impl VulkanGraphicsPipeline {
             device: device.clone(),
             pipeline,
             layout: pipeline_layout,
-            render_pass,
             _set_layout,
         })
     }
 
-    fn create_shader_module(device: &ash::Device, source: &[u8]) -> Result<vk::ShaderModule, String> {
-        if source.len() % 4 != 0 {
-            return Err("SPIR-V must be 4-byte aligned".to_string());
-        }
-        let code_u32: Vec<u32> = source
-            .chunks_exact(4)
-            .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
-            .collect();
+    /// Module for one stage, after checking `stage.entry_point` is declared for `model`.
+    fn create_shader_module(
+        device: &ash::Device,
+        desc: &GraphicsPipelineDescriptor,
+        stage: &crate::ShaderStage,
+        model: spirv::ExecutionModel,
+    ) -> Result<vk::ShaderModule, String> {
+        let code_u32 = spirv::words(&stage.source)?;
+        spirv::check_entry_point(&code_u32, &stage.entry_point, model)
+            .map_err(|e| format!("create_graphics_pipeline({:?}): {}", desc.label, e))?;
         let create_info = vk::ShaderModuleCreateInfo::default().code(&code_u32);
         unsafe {
             device
@@ -277,6 +309,7 @@ impl VulkanGraphicsPipeline {
             PrimitiveTopology::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
             PrimitiveTopology::TriangleStrip => vk::PrimitiveTopology::TRIANGLE_STRIP,
             PrimitiveTopology::LineList => vk::PrimitiveTopology::LINE_LIST,
+            PrimitiveTopology::LineStrip => vk::PrimitiveTopology::LINE_STRIP,
             PrimitiveTopology::PointList => vk::PrimitiveTopology::POINT_LIST,
         }
     }
@@ -342,7 +375,7 @@ impl Drop for VulkanGraphicsPipeline {
         unsafe {
             self.device.destroy_pipeline(self.pipeline, None);
             self.device.destroy_pipeline_layout(self.layout, None);
-            self.device.destroy_render_pass(self.render_pass, None);
+            // The render pass belongs to the device cache and is destroyed with the device.
             // _set_layout drops and destroys descriptor set layout
         }
     }
@@ -1,5 +1,5 @@
 mod compute;
 mod graphics;
 
-pub use compute::VulkanComputePipeline;
+pub use compute::{CachedComputePipeline, VulkanComputePipeline};
 pub use graphics::VulkanGraphicsPipeline;
@@ -1,8 +1,8 @@
 //! Vulkan Render Pass creation and recording.
 
-use crate::{DescriptorSet, ImageLayout, IndexFormat, LoadOp, StoreOp};
+use crate::{ClearColor, DescriptorSet, ImageLayout, IndexFormat, LoadOp, Rect2D, ResourceId, StoreOp, SubpassDescription};
 use ash::vk;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use super::buffer::VulkanBuffer;
 use super::descriptor::VulkanDescriptorSet;
@@ -22,18 +22,80 @@ fn image_layout_to_vk(l: ImageLayout) -> vk::ImageLayout {
     }
 }
 
-/// Create a VkRenderPass from attachment formats and load/store ops.
-/// Used by both pipeline creation and begin_render_pass.
+/// The subpasses a pass records: `subpasses`, or when empty a single one writing every attachment.
+pub(crate) fn effective_subpasses(subpasses: &[SubpassDescription], color_count: u32, has_depth: bool) -> Vec<SubpassDescription> {
+    if !subpasses.is_empty() {
+        return subpasses.to_vec();
+    }
+    vec![SubpassDescription {
+        color_attachments: (0..color_count).collect(),
+        input_attachments: Vec::new(),
+        depth_stencil: has_depth,
+    }]
+}
+
+/// Every attachment a subpass references: its colors, inputs and, with `depth_stencil`, `depth_index`.
+fn referenced_attachments(s: &SubpassDescription, depth_index: u32) -> Vec<u32> {
+    let mut used: Vec<u32> = s.color_attachments.iter().chain(&s.input_attachments).copied().collect();
+    if s.depth_stencil {
+        used.push(depth_index);
+    }
+    used
+}
+
+/// `pPreserveAttachments` of each subpass: the attachments an earlier and a later subpass both
+/// reference but this one does not, whose contents would otherwise be undefined by the later one.
+pub(crate) fn subpass_preserve_attachments(subpasses: &[SubpassDescription], depth_index: u32) -> Vec<Vec<u32>> {
+    let used: Vec<Vec<u32>> = subpasses.iter().map(|s| referenced_attachments(s, depth_index)).collect();
+    (0..subpasses.len())
+        .map(|i| {
+            let mut preserve: Vec<u32> = used[..i]
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|a| !used[i].contains(a) && used[i + 1..].iter().any(|later| later.contains(a)))
+                .collect();
+            preserve.sort_unstable();
+            preserve.dedup();
+            preserve
+        })
+        .collect()
+}
+
+/// `(src, dst)` subpass pairs that need a dependency: `dst` reads as an input, or writes again, an
+/// attachment that `src` wrote. `depth_index` is the framebuffer index of the depth attachment.
+pub(crate) fn subpass_dependency_pairs(subpasses: &[SubpassDescription], depth_index: u32) -> Vec<(u32, u32)> {
+    let written = |s: &SubpassDescription| {
+        let mut w = s.color_attachments.clone();
+        if s.depth_stencil {
+            w.push(depth_index);
+        }
+        w
+    };
+    let mut pairs = Vec::new();
+    for (dst, d) in subpasses.iter().enumerate() {
+        let used = referenced_attachments(d, depth_index);
+        for (src, s) in subpasses[..dst].iter().enumerate() {
+            if written(s).iter().any(|a| used.contains(a)) {
+                pairs.push((src as u32, dst as u32));
+            }
+        }
+    }
+    pairs
+}
+
+/// Create a VkRenderPass from attachment formats, load/store ops and subpasses (see
+/// [`effective_subpasses`]). Only called through the device's render pass cache, which pipeline
+/// creation and begin_render_pass share.
 pub fn create_vk_render_pass(
     device: &ash::Device,
     color_attachments: &[ColorAttachmentInfo],
     depth_attachment: Option<&DepthAttachmentInfo>,
+    subpasses: &[SubpassDescription],
 ) -> Result<vk::RenderPass, String> {
     let mut attachments = Vec::new();
-    let mut color_refs = Vec::new();
-    let mut depth_ref = None;
 
-    for (i, att) in color_attachments.iter().enumerate() {
+    for att in color_attachments {
         let (load_op, store_op) = (load_op_to_vk(att.load_op), store_op_to_vk(att.store_op));
         let format = texture_format_to_vk(att.format);
         let initial = att
@@ -51,15 +113,10 @@ pub fn create_vk_render_pass(
                 .initial_layout(initial)
                 .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
         );
-        color_refs.push(
-            vk::AttachmentReference::default()
-                .attachment(i as u32)
-                .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
-        );
     }
 
+    let depth_index = attachments.len() as u32;
     if let Some(dep) = depth_attachment {
-        let idx = attachments.len();
         attachments.push(
             vk::AttachmentDescription::default()
                 .format(texture_format_to_vk(dep.format))
@@ -71,37 +128,86 @@ pub fn create_vk_render_pass(
                 .initial_layout(vk::ImageLayout::UNDEFINED)
                 .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
         );
-        depth_ref = Some(
-            vk::AttachmentReference::default()
-                .attachment(idx as u32)
-                .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
-        );
     }
 
-    let subpass = if let Some(ref d) = depth_ref {
-        vk::SubpassDescription::default()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&color_refs)
-            .depth_stencil_attachment(d)
-    } else {
-        vk::SubpassDescription::default()
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&color_refs)
-    };
+    let subpasses = effective_subpasses(subpasses, color_attachments.len() as u32, depth_attachment.is_some());
+    let reference = |attachment: u32, layout: vk::ImageLayout| vk::AttachmentReference::default().attachment(attachment).layout(layout);
+    let color_refs: Vec<Vec<vk::AttachmentReference>> = subpasses
+        .iter()
+        .map(|s| s.color_attachments.iter().map(|&a| reference(a, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)).collect())
+        .collect();
+    // Inputs are read-only for the subpass: the GBuffer in SHADER_READ_ONLY, depth in DEPTH_STENCIL_READ_ONLY.
+    let input_refs: Vec<Vec<vk::AttachmentReference>> = subpasses
+        .iter()
+        .map(|s| {
+            s.input_attachments
+                .iter()
+                .map(|&a| {
+                    let layout = if a == depth_index {
+                        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+                    } else {
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    };
+                    reference(a, layout)
+                })
+                .collect()
+        })
+        .collect();
+    let depth_ref = reference(depth_index, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let preserve = subpass_preserve_attachments(&subpasses, depth_index);
+    let vk_subpasses: Vec<vk::SubpassDescription> = subpasses
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let subpass = vk::SubpassDescription::default()
+                .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                .color_attachments(&color_refs[i])
+                .input_attachments(&input_refs[i])
+                .preserve_attachments(&preserve[i]);
+            if s.depth_stencil {
+                subpass.depth_stencil_attachment(&depth_ref)
+            } else {
+                subpass
+            }
+        })
+        .collect();
 
-    // Wait for any prior layout transition or acquire before the first subpass uses the attachment.
-    let dependency = vk::SubpassDependency::default()
+    // Wait for any prior layout transition, acquire or depth write before the first subpass uses the
+    // color or depth attachments.
+    let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    let attachment_writes = vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+    let mut dependencies = vec![vk::SubpassDependency::default()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .dst_subpass(0)
-        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | fragment_tests)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | fragment_tests)
+        .src_access_mask(attachment_writes)
+        .dst_access_mask(attachment_writes | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ)];
+    // Later subpasses only touch the pixel the earlier one wrote, so the dependencies are by region,
+    // which lets tiled GPUs keep the attachments on chip.
+    for (src, dst) in subpass_dependency_pairs(&subpasses, depth_index) {
+        dependencies.push(
+            vk::SubpassDependency::default()
+                .src_subpass(src)
+                .dst_subpass(dst)
+                .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | fragment_tests)
+                .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | fragment_tests)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_access_mask(
+                    vk::AccessFlags::INPUT_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_READ
+                        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                )
+                .dependency_flags(vk::DependencyFlags::BY_REGION),
+        );
+    }
 
     let create_info = vk::RenderPassCreateInfo::default()
         .attachments(&attachments)
-        .subpasses(std::slice::from_ref(&subpass))
-        .dependencies(std::slice::from_ref(&dependency));
+        .subpasses(&vk_subpasses)
+        .dependencies(&dependencies);
 
     unsafe {
         device
@@ -138,6 +244,14 @@ fn store_op_to_vk(op: StoreOp) -> vk::AttachmentStoreOp {
     }
 }
 
+/// What the recorder checks per subpass: attachments for `clear_region`, input attachment textures
+/// for `bind_descriptor_set`.
+pub(crate) struct SubpassAttachments {
+    pub color_count: u32,
+    pub depth: bool,
+    pub inputs: Vec<ResourceId>,
+}
+
 /// Vulkan render pass recording - implements RenderPass trait.
 /// Render pass and framebuffer are cached on the device and are not destroyed in end().
 pub struct VulkanRenderPassRecorder {
@@ -148,10 +262,14 @@ pub struct VulkanRenderPassRecorder {
     #[allow(dead_code)]
     pub(crate) framebuffer: vk::Framebuffer,
     pub(crate) extent: vk::Extent2D,
+    pub(crate) subpasses: Vec<SubpassAttachments>,
+    pub(crate) subpass: usize,
     pub(crate) pipeline_bound: Option<vk::Pipeline>,
     pub(crate) pipeline_layout: Option<vk::PipelineLayout>,
     pub(crate) vertex_buffers: Vec<Option<(vk::Buffer, u64)>>,
     pub(crate) index_buffer: Option<(vk::Buffer, u64, vk::IndexType)>,
+    /// Shared with the command encoder, whose `finish` returns the first error recorded here.
+    pub(crate) error: Arc<Mutex<Option<String>>>,
 }
 
 impl VulkanRenderPassRecorder {
@@ -161,6 +279,8 @@ impl VulkanRenderPassRecorder {
         render_pass: vk::RenderPass,
         framebuffer: vk::Framebuffer,
         extent: vk::Extent2D,
+        subpasses: Vec<SubpassAttachments>,
+        error: Arc<Mutex<Option<String>>>,
     ) -> Self {
         Self {
             device,
@@ -168,10 +288,20 @@ impl VulkanRenderPassRecorder {
             render_pass,
             framebuffer,
             extent,
+            subpasses,
+            subpass: 0,
             pipeline_bound: None,
             pipeline_layout: None,
             vertex_buffers: vec![],
             index_buffer: None,
+            error,
+        }
+    }
+
+    /// Keep the first error for the encoder's `finish`.
+    fn record_error(&self, error: String) {
+        if let Ok(mut slot) = self.error.lock() {
+            slot.get_or_insert(error);
         }
     }
 }
@@ -205,18 +335,23 @@ impl crate::RenderPass for VulkanRenderPassRecorder {
     }
 
     fn bind_descriptor_set(&mut self, set_index: u32, set: &dyn DescriptorSet) {
+        let Some(vk_set) = set.as_any().downcast_ref::<VulkanDescriptorSet>() else {
+            return;
+        };
+        if let Err(e) = crate::validate_input_attachments(&vk_set.input_attachments, &self.subpasses[self.subpass].inputs) {
+            self.record_error(format!("bind_descriptor_set (subpass {}): {}", self.subpass, e));
+            return;
+        }
         if let Some(layout) = self.pipeline_layout {
-            if let Some(vk_set) = set.as_any().downcast_ref::<VulkanDescriptorSet>() {
-                unsafe {
-                    self.device.cmd_bind_descriptor_sets(
-                        self.command_buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        layout,
-                        set_index,
-                        &[vk_set.set],
-                        &[],
-                    );
-                }
+            unsafe {
+                self.device.cmd_bind_descriptor_sets(
+                    self.command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    layout,
+                    set_index,
+                    &[vk_set.set],
+                    &[],
+                );
             }
         }
     }
@@ -226,7 +361,7 @@ impl crate::RenderPass for VulkanRenderPassRecorder {
             .as_any()
             .downcast_ref::<VulkanBuffer>()
             .expect("Buffer must be VulkanBuffer");
-        let vk_buffer = vk_buf.buffer;
+        let vk_buffer = vk_buf.raw();
         while self.vertex_buffers.len() <= index as usize {
             self.vertex_buffers.push(None);
         }
@@ -250,11 +385,11 @@ impl crate::RenderPass for VulkanRenderPassRecorder {
             IndexFormat::Uint16 => vk::IndexType::UINT16,
             IndexFormat::Uint32 => vk::IndexType::UINT32,
         };
-        self.index_buffer = Some((vk_buf.buffer, offset, index_type));
+        self.index_buffer = Some((vk_buf.raw(), offset, index_type));
         unsafe {
             self.device.cmd_bind_index_buffer(
                 self.command_buffer,
-                vk_buf.buffer,
+                vk_buf.raw(),
                 offset,
                 index_type,
             );
@@ -308,7 +443,7 @@ impl crate::RenderPass for VulkanRenderPassRecorder {
         unsafe {
             self.device.cmd_draw_indexed_indirect(
                 self.command_buffer,
-                vk_buf.buffer,
+                vk_buf.raw(),
                 offset,
                 draw_count.max(1),
                 stride,
@@ -316,6 +451,59 @@ impl crate::RenderPass for VulkanRenderPassRecorder {
         }
     }
 
+    fn clear_region(&mut self, rect: Rect2D, color: Option<ClearColor>, depth: Option<f32>) -> Result<(), String> {
+        rect.validate_within(self.extent.width, self.extent.height)
+            .map_err(|e| format!("clear_region: {}", e))?;
+        let current = &self.subpasses[self.subpass];
+        if depth.is_some() && !current.depth {
+            return Err("clear_region: depth clear requested but the subpass has no depth attachment".to_string());
+        }
+        let mut attachments = Vec::new();
+        if let Some(c) = color {
+            attachments.extend((0..current.color_count).map(|i| vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                color_attachment: i,
+                clear_value: vk::ClearValue { color: vk::ClearColorValue { float32: [c.r, c.g, c.b, c.a] } },
+            }));
+        }
+        if let Some(d) = depth {
+            attachments.push(vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                color_attachment: 0,
+                clear_value: vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: d, stencil: 0 } },
+            });
+        }
+        if attachments.is_empty() {
+            return Ok(());
+        }
+        let clear_rect = vk::ClearRect {
+            rect: vk::Rect2D {
+                offset: vk::Offset2D { x: rect.x as i32, y: rect.y as i32 },
+                extent: vk::Extent2D { width: rect.width, height: rect.height },
+            },
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        unsafe {
+            self.device.cmd_clear_attachments(self.command_buffer, &attachments, &[clear_rect]);
+        }
+        Ok(())
+    }
+
+    fn next_subpass(&mut self) -> Result<(), String> {
+        if self.subpass + 1 >= self.subpasses.len() {
+            return Err(format!("next_subpass: already in the last of {} subpasses", self.subpasses.len()));
+        }
+        unsafe {
+            self.device.cmd_next_subpass(self.command_buffer, vk::SubpassContents::INLINE);
+        }
+        self.subpass += 1;
+        // The bound pipeline belongs to the previous subpass.
+        self.pipeline_bound = None;
+        self.pipeline_layout = None;
+        Ok(())
+    }
+
     fn end(self: Box<Self>) {
         unsafe {
             self.device.cmd_end_render_pass(self.command_buffer);
@@ -331,3 +519,34 @@ impl std::fmt::Debug for VulkanRenderPassRecorder {
             .finish_non_exhaustive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lighting_subpass_depends_on_the_gbuffer_subpass() {
+        let gbuffer = SubpassDescription { color_attachments: vec![0, 1], input_attachments: vec![], depth_stencil: true };
+        let lighting = SubpassDescription { color_attachments: vec![2], input_attachments: vec![0, 1, 3], depth_stencil: false };
+        let overlay = SubpassDescription { color_attachments: vec![2], input_attachments: vec![], depth_stencil: false };
+        assert_eq!(subpass_dependency_pairs(&[gbuffer.clone(), lighting.clone()], 3), [(0, 1)]);
+        // The overlay rewrites the lit target but nothing of the GBuffer.
+        assert_eq!(subpass_dependency_pairs(&[gbuffer, lighting, overlay], 3), [(0, 1), (1, 2)]);
+
+        let single = effective_subpasses(&[], 2, true);
+        assert_eq!(single, [SubpassDescription { color_attachments: vec![0, 1], input_attachments: vec![], depth_stencil: true }]);
+        assert!(subpass_dependency_pairs(&single, 2).is_empty());
+    }
+
+    #[test]
+    fn skipped_attachments_are_preserved_until_their_last_use() {
+        let write = |color: u32| SubpassDescription { color_attachments: vec![color], input_attachments: vec![], depth_stencil: false };
+        let read = SubpassDescription { color_attachments: vec![2], input_attachments: vec![0], depth_stencil: true };
+        // Attachment 0 is written by subpass 0, skipped by 1 and read by 2; depth (3) is first used in 2.
+        let subpasses = [write(0), write(1), read];
+        assert_eq!(subpass_preserve_attachments(&subpasses, 3), [vec![], vec![0], vec![]]);
+        assert_eq!(subpass_dependency_pairs(&subpasses, 3), [(0, 2)]);
+        // Nothing is used after the last subpass, so nothing needs preserving around it.
+        assert_eq!(subpass_preserve_attachments(&[write(0), write(1)], 3), [vec![], vec![]]);
+    }
+}
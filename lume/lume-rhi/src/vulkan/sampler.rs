@@ -1,10 +1,10 @@
 //! Vulkan Sampler implementation.
 
-use crate::{AddressMode, FilterMode, Sampler, SamplerDescriptor};
+use crate::{AddressMode, BorderColor, FilterMode, Sampler, SamplerDescriptor};
 use ash::vk;
 use std::sync::Arc;
 
-fn filter_to_vk(f: FilterMode) -> vk::Filter {
+pub(super) fn filter_to_vk(f: FilterMode) -> vk::Filter {
     match f {
         FilterMode::Nearest => vk::Filter::NEAREST,
         FilterMode::Linear => vk::Filter::LINEAR,
@@ -20,6 +20,14 @@ fn address_mode_to_vk(a: AddressMode) -> vk::SamplerAddressMode {
     }
 }
 
+fn border_color_to_vk(c: BorderColor) -> vk::BorderColor {
+    match c {
+        BorderColor::TransparentBlack => vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        BorderColor::OpaqueBlack => vk::BorderColor::FLOAT_OPAQUE_BLACK,
+        BorderColor::OpaqueWhite => vk::BorderColor::FLOAT_OPAQUE_WHITE,
+    }
+}
+
 pub fn create_sampler(
     device: Arc<ash::Device>,
     desc: &SamplerDescriptor,
@@ -31,6 +39,7 @@ pub fn create_sampler(
         .address_mode_u(address_mode_to_vk(desc.address_mode_u))
         .address_mode_v(address_mode_to_vk(desc.address_mode_v))
         .address_mode_w(address_mode_to_vk(desc.address_mode_w))
+        .border_color(border_color_to_vk(desc.border_color))
         .anisotropy_enable(anisotropy.is_some())
         .max_anisotropy(anisotropy.unwrap_or(1.0))
         .unnormalized_coordinates(false);
@@ -72,3 +81,21 @@ impl Sampler for VulkanSampler {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn border_colors_map_to_float_vk_colors() {
+        assert_eq!(border_color_to_vk(BorderColor::TransparentBlack), vk::BorderColor::FLOAT_TRANSPARENT_BLACK);
+        assert_eq!(border_color_to_vk(BorderColor::OpaqueBlack), vk::BorderColor::FLOAT_OPAQUE_BLACK);
+        assert_eq!(border_color_to_vk(BorderColor::OpaqueWhite), vk::BorderColor::FLOAT_OPAQUE_WHITE);
+    }
+
+    #[test]
+    fn default_border_is_transparent_black() {
+        assert_eq!(BorderColor::default(), BorderColor::TransparentBlack);
+        assert_eq!(SamplerDescriptor::default().border_color, BorderColor::TransparentBlack);
+    }
+}
@@ -1,11 +1,12 @@
 //! Vulkan swapchain and surface support (feature "window").
 
 use crate::{
-    ResourceId, Semaphore, Swapchain, SwapchainFrame, Texture, TextureDimension, TextureFormat,
+    ImageLayout, ResourceId, Semaphore, Swapchain, SwapchainAcquire, SwapchainFrame, Texture, TextureDimension,
+    TextureFormat,
 };
 use ash::vk;
 use ash::khr::swapchain::Device as SwapchainDevice;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
 
 use super::texture::texture_format_to_vk;
 use super::VulkanSemaphore;
@@ -71,6 +72,8 @@ pub struct VulkanSwapchain {
     swapchain_loader: SwapchainDevice,
     pub(crate) swapchain: vk::SwapchainKHR,
     images: Vec<VulkanSwapchainImage>,
+    /// Current layout per image; `present` takes `&self`, hence the lock.
+    image_layouts: Mutex<Vec<ImageLayout>>,
     queue: vk::Queue,
     extent: (u32, u32),
     format: TextureFormat,
@@ -121,11 +124,13 @@ impl VulkanSwapchain {
                 id,
             });
         }
+        let image_layouts = Mutex::new(vec![ImageLayout::Undefined; images.len()]);
         Ok(Self {
             device,
             swapchain_loader,
             swapchain,
             images,
+            image_layouts,
             queue,
             extent,
             format,
@@ -157,7 +162,11 @@ impl Swapchain for VulkanSwapchain {
         self
     }
 
-    fn acquire_next_image(&mut self, wait_semaphore: Option<&dyn Semaphore>) -> Result<SwapchainFrame<'_>, String> {
+    fn acquire_next_image_timeout(
+        &mut self,
+        wait_semaphore: Option<&dyn Semaphore>,
+        timeout_ns: u64,
+    ) -> Result<SwapchainAcquire<'_>, String> {
         let (semaphore, _) = wait_semaphore
             .map(|s| {
                 let vk_s = s.as_any().downcast_ref::<VulkanSemaphore>().map(|vs| vs.semaphore);
@@ -165,16 +174,19 @@ impl Swapchain for VulkanSwapchain {
             })
             .unwrap_or((None, ()));
         let sem = semaphore.unwrap_or(vk::Semaphore::null());
-        let (index, _suboptimal) = unsafe {
-            self.swapchain_loader
-                .acquire_next_image(self.swapchain, u64::MAX, sem, vk::Fence::null())
-                .map_err(|e| format!("acquire_next_image: {:?}", e))?
+        let acquired = unsafe { self.swapchain_loader.acquire_next_image(self.swapchain, timeout_ns, sem, vk::Fence::null()) };
+        let (index, _suboptimal) = match acquired {
+            Ok(acquired) => acquired,
+            Err(vk::Result::TIMEOUT | vk::Result::NOT_READY) => return Ok(SwapchainAcquire::WouldBlock),
+            Err(e) => return Err(format!("acquire_next_image: {:?}", e)),
         };
+        let layout = self.image_layout(index);
         let texture = &self.images[index as usize];
-        Ok(SwapchainFrame {
+        Ok(SwapchainAcquire::Frame(SwapchainFrame {
             image_index: index,
             texture,
-        })
+            layout,
+        }))
     }
 
     fn present(&self, image_index: u32, wait_semaphore: Option<&dyn Semaphore>) -> Result<(), String> {
@@ -182,6 +194,8 @@ impl Swapchain for VulkanSwapchain {
             s.as_any().downcast_ref::<VulkanSemaphore>().map(|vs| vs.semaphore)
         });
         let wait_semas: Vec<vk::Semaphore> = semaphore.into_iter().collect();
+        // The submitted work left the image in PRESENT_SRC, whether or not presentation succeeds.
+        self.set_image_layout(image_index, ImageLayout::PresentSrc);
         let image_indices = [image_index];
         let present_info = vk::PresentInfoKHR::default()
             .wait_semaphores(&wait_semas)
@@ -195,6 +209,18 @@ impl Swapchain for VulkanSwapchain {
         Ok(())
     }
 
+    fn image_layout(&self, image_index: u32) -> ImageLayout {
+        let layouts = self.image_layouts.lock().unwrap_or_else(PoisonError::into_inner);
+        layouts.get(image_index as usize).copied().unwrap_or(ImageLayout::Undefined)
+    }
+
+    fn set_image_layout(&self, image_index: u32, layout: ImageLayout) {
+        let mut layouts = self.image_layouts.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(l) = layouts.get_mut(image_index as usize) {
+            *l = layout;
+        }
+    }
+
     fn extent(&self) -> (u32, u32) {
         self.extent
     }
@@ -4,11 +4,12 @@ use crate::{ResourceId, Texture, TextureDescriptor, TextureDimension, TextureFor
 use ash::vk;
 use std::sync::Arc;
 
+use super::memory::{select_texture_memory_type, MemoryAllocator};
+
 /// Create a Vulkan texture from descriptor.
 pub fn create_texture(
     device: Arc<ash::Device>,
-    instance: &ash::Instance,
-    physical_device: vk::PhysicalDevice,
+    allocator: &Arc<MemoryAllocator>,
     descriptor: &TextureDescriptor,
     next_id: impl FnOnce() -> ResourceId,
 ) -> Result<VulkanTexture, String> {
@@ -19,8 +20,12 @@ pub fn create_texture(
         depth: depth_or_layers.max(1),
     };
 
+    descriptor.validate()?;
     let vk_format = texture_format_to_vk(descriptor.format);
-    let usage_flags = texture_usage_to_vk(descriptor.usage, descriptor.format);
+    let mut usage_flags = texture_usage_to_vk(descriptor.usage, descriptor.format);
+    if descriptor.transient {
+        usage_flags |= vk::ImageUsageFlags::TRANSIENT_ATTACHMENT;
+    }
     let image_type = texture_dimension_to_image_type(descriptor.dimension);
 
     let mut array_layers = 1u32;
@@ -62,32 +67,21 @@ pub fn create_texture(
     };
 
     let requirements = unsafe { device.get_image_memory_requirements(image) };
-    let memory_props = unsafe { instance.get_physical_device_memory_properties(physical_device) };
-    let memory_type_index = (0..memory_props.memory_type_count)
-        .find(|i| {
-            let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
-            let mem_type = &memory_props.memory_types[*i as usize];
-            let device_local = mem_type
-                .property_flags
-                .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL);
-            suitable && device_local
-        })
-        .ok_or("No suitable device-local memory for texture")? as u32;
-
-    let allocate_info = vk::MemoryAllocateInfo::default()
-        .allocation_size(requirements.size)
-        .memory_type_index(memory_type_index);
-
-    let memory = unsafe {
-        device
-            .allocate_memory(&allocate_info, None)
-            .map_err(|e| e.to_string())?
+    let memory_type_index =
+        select_texture_memory_type(allocator.memory_properties(), requirements.memory_type_bits, descriptor.transient);
+    let allocation = match memory_type_index.and_then(|index| allocator.allocate(requirements, index, false)) {
+        Ok(allocation) => allocation,
+        Err(e) => {
+            unsafe { device.destroy_image(image, None) };
+            return Err(e);
+        }
     };
 
-    unsafe {
-        device
-            .bind_image_memory(image, memory, 0)
-            .map_err(|e| e.to_string())?;
+    if let Err(e) = unsafe { device.bind_image_memory(image, allocation.memory, allocation.offset) } {
+        // The image goes before its memory, as on drop.
+        unsafe { device.destroy_image(image, None) };
+        drop(allocation);
+        return Err(e.to_string());
     }
 
     let view_type = texture_dimension_to_view_type(descriptor.dimension, descriptor.size);
@@ -110,23 +104,26 @@ pub fn create_texture(
                 .layer_count(array_layers),
         );
 
-    let view = unsafe {
-        device
-            .create_image_view(&view_create_info, None)
-            .map_err(|e| e.to_string())?
+    let view = match unsafe { device.create_image_view(&view_create_info, None) } {
+        Ok(view) => view,
+        Err(e) => {
+            unsafe { device.destroy_image(image, None) };
+            drop(allocation);
+            return Err(e.to_string());
+        }
     };
 
     Ok(VulkanTexture {
         device,
         image,
-        memory,
         view,
         format: descriptor.format,
         size: descriptor.size,
         dimension: descriptor.dimension,
         mip_level_count: mip_levels,
         id: next_id(),
-        image_type,
+        _image_type: image_type,
+        _allocation: allocation,
     })
 }
 
@@ -134,15 +131,15 @@ pub fn create_texture(
 pub struct VulkanTexture {
     pub(crate) device: Arc<ash::Device>,
     pub(crate) image: vk::Image,
-    pub(crate) memory: vk::DeviceMemory,
     pub(crate) view: vk::ImageView,
     pub(crate) format: TextureFormat,
     pub(crate) size: (u32, u32, u32),
     pub(crate) dimension: TextureDimension,
     pub(crate) mip_level_count: u32,
     pub(crate) id: ResourceId,
-    #[allow(dead_code)]
-    pub(crate) image_type: vk::ImageType,
+    pub(crate) _image_type: vk::ImageType,
+    /// Returned to the allocator after the image is destroyed.
+    pub(crate) _allocation: super::memory::MemoryAllocation,
 }
 
 impl VulkanTexture {
@@ -166,7 +163,6 @@ impl Drop for VulkanTexture {
         unsafe {
             self.device.destroy_image_view(self.view, None);
             self.device.destroy_image(self.image, None);
-            self.device.free_memory(self.memory, None);
         }
     }
 }
@@ -236,10 +232,13 @@ pub fn texture_usage_to_vk(usage: TextureUsage, format: TextureFormat) -> vk::Im
             flags |= vk::ImageUsageFlags::COLOR_ATTACHMENT;
         }
     }
+    if usage.contains(TextureUsage::INPUT_ATTACHMENT) {
+        flags |= vk::ImageUsageFlags::INPUT_ATTACHMENT;
+    }
     flags
 }
 
-fn format_is_depth(format: TextureFormat) -> bool {
+pub(crate) fn format_is_depth(format: TextureFormat) -> bool {
     matches!(format, TextureFormat::D32Float)
 }
 
@@ -4,4 +4,4 @@ pub mod cluster;
 pub mod sdf;
 
 pub use cluster::{subdivide_mesh, ClusterDesc, SubdivideOptions};
-pub use sdf::{generate_mesh_sdf, MeshSdfOutput};
+pub use sdf::{generate_mesh_sdf, mesh_sdf_grid, MeshSdfOutput};
@@ -1,24 +1,232 @@
 //! Mesh SDF generation for GI (signed distance field). Offline preprocessing.
+//!
+//! Brute force: every voxel center is tested against every triangle (O(voxels × triangles)).
+//! Distance is to the nearest triangle; the sign comes from the generalized winding number, so the
+//! mesh should be closed. `lume_renderer::gi::MeshSdfGenerator` is the compute-shader equivalent
+//! and uses the same grid and math.
 
-/// Output of mesh SDF generation: a 3D grid of signed distances.
+use std::f32::consts::PI;
+
+/// Output of mesh SDF generation: a 3D grid of signed distances (negative inside).
 #[derive(Clone, Debug)]
 pub struct MeshSdfOutput {
     /// Grid resolution (e.g. 32 or 64 per axis).
     pub resolution: (u32, u32, u32),
+    /// World-space corner of voxel (0, 0, 0); voxel centers are at `origin + (i + 0.5) * voxel_size`.
+    pub origin: [f32; 3],
+    /// Voxel extent per axis.
+    pub voxel_size: [f32; 3],
     /// Signed distance values, row-major, then slice. Length = resolution.0 * resolution.1 * resolution.2.
     pub data: Vec<f32>,
 }
 
-/// Generate a low-resolution SDF for a mesh (vertices + indices).
-/// TODO: Implement actual SDF baking (e.g. voxelize mesh, compute distances).
-pub fn generate_mesh_sdf(
-    _positions: &[f32],
-    _indices: &[u32],
-    resolution: u32,
-) -> MeshSdfOutput {
-    let n = (resolution as usize) * (resolution as usize) * (resolution as usize);
-    MeshSdfOutput {
-        resolution: (resolution, resolution, resolution),
-        data: vec![f32::MAX; n],
+/// Fraction of the largest mesh extent added around the bounds so the surface is not on the grid edge.
+const BOUNDS_MARGIN: f32 = 0.1;
+
+/// Grid covering the mesh bounds plus a margin: returns (origin, voxel_size).
+pub fn mesh_sdf_grid(positions: &[f32], resolution: u32) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in positions.chunks_exact(3) {
+        for c in 0..3 {
+            min[c] = min[c].min(p[c]);
+            max[c] = max[c].max(p[c]);
+        }
+    }
+    if min[0] > max[0] {
+        return ([0.0; 3], [0.0; 3]);
+    }
+    let largest = (0..3).map(|c| max[c] - min[c]).fold(0.0f32, f32::max);
+    // Flat or degenerate meshes still get a non-zero grid.
+    let margin = (largest * BOUNDS_MARGIN).max(1e-3);
+    let res = resolution.max(1) as f32;
+    let origin = [min[0] - margin, min[1] - margin, min[2] - margin];
+    let voxel_size = [
+        (max[0] - min[0] + 2.0 * margin) / res,
+        (max[1] - min[1] + 2.0 * margin) / res,
+        (max[2] - min[2] + 2.0 * margin) / res,
+    ];
+    (origin, voxel_size)
+}
+
+/// Generate a low-resolution SDF for a mesh (positions as xyz triples + triangle indices).
+/// Returns all `f32::MAX` when the mesh has no triangles; errors when `positions` is not made of xyz
+/// triples or an index points past the last vertex.
+pub fn generate_mesh_sdf(positions: &[f32], indices: &[u32], resolution: u32) -> Result<MeshSdfOutput, String> {
+    if !positions.len().is_multiple_of(3) {
+        return Err(format!("mesh sdf: positions length {} is not a multiple of 3", positions.len()));
+    }
+    let vertex_count = positions.len() / 3;
+    if let Some(&i) = indices.iter().find(|&&i| i as usize >= vertex_count) {
+        return Err(format!("mesh sdf: index {} out of range ({} vertices)", i, vertex_count));
+    }
+    let r = resolution as usize;
+    let (origin, voxel_size) = mesh_sdf_grid(positions, resolution);
+    let vertex = |i: u32| {
+        let i = i as usize * 3;
+        [positions[i], positions[i + 1], positions[i + 2]]
+    };
+    let triangles: Vec<[[f32; 3]; 3]> = indices
+        .chunks_exact(3)
+        .map(|t| [vertex(t[0]), vertex(t[1]), vertex(t[2])])
+        .collect();
+    let mut data = vec![f32::MAX; r * r * r];
+    if triangles.is_empty() {
+        return Ok(MeshSdfOutput { resolution: (resolution, resolution, resolution), origin, voxel_size, data });
+    }
+    for z in 0..r {
+        for y in 0..r {
+            for x in 0..r {
+                let p = [
+                    origin[0] + (x as f32 + 0.5) * voxel_size[0],
+                    origin[1] + (y as f32 + 0.5) * voxel_size[1],
+                    origin[2] + (z as f32 + 0.5) * voxel_size[2],
+                ];
+                let mut dist_sq = f32::MAX;
+                let mut solid_angle = 0.0;
+                for [a, b, c] in &triangles {
+                    let q = closest_point_on_triangle(p, *a, *b, *c);
+                    dist_sq = dist_sq.min(length_sq(sub(p, q)));
+                    solid_angle += triangle_solid_angle(p, *a, *b, *c);
+                }
+                let inside = solid_angle / (4.0 * PI) > 0.5;
+                let dist = dist_sq.sqrt();
+                data[x + y * r + z * r * r] = if inside { -dist } else { dist };
+            }
+        }
+    }
+    Ok(MeshSdfOutput { resolution: (resolution, resolution, resolution), origin, voxel_size, data })
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn length_sq(a: [f32; 3]) -> f32 {
+    dot(a, a)
+}
+
+fn add_scaled(a: [f32; 3], b: [f32; 3], s: f32) -> [f32; 3] {
+    [a[0] + b[0] * s, a[1] + b[1] * s, a[2] + b[2] * s]
+}
+
+/// Closest point on triangle abc to p (Ericson, Real-Time Collision Detection 5.1.5).
+fn closest_point_on_triangle(p: [f32; 3], a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let ab = sub(b, a);
+    let ac = sub(c, a);
+    let ap = sub(p, a);
+    let d1 = dot(ab, ap);
+    let d2 = dot(ac, ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = sub(p, b);
+    let d3 = dot(ab, bp);
+    let d4 = dot(ac, bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return add_scaled(a, ab, d1 / (d1 - d3));
+    }
+    let cp = sub(p, c);
+    let d5 = dot(ab, cp);
+    let d6 = dot(ac, cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return add_scaled(a, ac, d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return add_scaled(b, sub(c, b), (d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    add_scaled(add_scaled(a, ab, vb * denom), ac, vc * denom)
+}
+
+/// Signed solid angle of triangle abc seen from p (Van Oosterom & Strackee). Sums to 4π over a
+/// closed, outward-facing (counter-clockwise) mesh for points inside and 0 outside.
+fn triangle_solid_angle(p: [f32; 3], a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> f32 {
+    let (a, b, c) = (sub(a, p), sub(b, p), sub(c, p));
+    let (la, lb, lc) = (length_sq(a).sqrt(), length_sq(b).sqrt(), length_sq(c).sqrt());
+    let det = dot(a, cross(b, c));
+    let denom = la * lb * lc + dot(a, b) * lc + dot(a, c) * lb + dot(b, c) * la;
+    2.0 * det.atan2(denom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit cube centered at the origin, counter-clockwise when seen from outside.
+    fn cube() -> (Vec<f32>, Vec<u32>) {
+        let positions = vec![
+            -0.5, -0.5, -0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5, 0.5, -0.5, //
+            -0.5, -0.5, 0.5, 0.5, -0.5, 0.5, 0.5, 0.5, 0.5, -0.5, 0.5, 0.5,
+        ];
+        let indices = vec![
+            0, 2, 1, 0, 3, 2, // -z
+            4, 5, 6, 4, 6, 7, // +z
+            0, 1, 5, 0, 5, 4, // -y
+            3, 6, 2, 3, 7, 6, // +y
+            0, 4, 7, 0, 7, 3, // -x
+            1, 2, 6, 1, 6, 5, // +x
+        ];
+        (positions, indices)
+    }
+
+    /// Exact signed distance to the unit cube.
+    fn cube_distance(p: [f32; 3]) -> f32 {
+        let q = [p[0].abs() - 0.5, p[1].abs() - 0.5, p[2].abs() - 0.5];
+        let outside = length_sq([q[0].max(0.0), q[1].max(0.0), q[2].max(0.0)]).sqrt();
+        outside + q[0].max(q[1]).max(q[2]).min(0.0)
+    }
+
+    #[test]
+    fn cube_sdf_matches_analytic_distance() {
+        let (positions, indices) = cube();
+        let res = 8;
+        let sdf = generate_mesh_sdf(&positions, &indices, res).unwrap();
+        let r = res as usize;
+        assert_eq!(sdf.data.len(), r * r * r);
+        for z in 0..r {
+            for y in 0..r {
+                for x in 0..r {
+                    let p = [
+                        sdf.origin[0] + (x as f32 + 0.5) * sdf.voxel_size[0],
+                        sdf.origin[1] + (y as f32 + 0.5) * sdf.voxel_size[1],
+                        sdf.origin[2] + (z as f32 + 0.5) * sdf.voxel_size[2],
+                    ];
+                    let got = sdf.data[x + y * r + z * r * r];
+                    assert!((got - cube_distance(p)).abs() < 1e-4, "voxel ({x},{y},{z}): {got} vs {}", cube_distance(p));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_mesh_is_all_far() {
+        let sdf = generate_mesh_sdf(&[], &[], 4).unwrap();
+        assert!(sdf.data.iter().all(|&d| d == f32::MAX));
+    }
+
+    #[test]
+    fn malformed_mesh_is_rejected() {
+        let (positions, mut indices) = cube();
+        assert!(generate_mesh_sdf(&positions[..positions.len() - 1], &indices, 4).is_err());
+        indices[4] = 8;
+        assert!(generate_mesh_sdf(&positions, &indices, 4).is_err());
     }
 }
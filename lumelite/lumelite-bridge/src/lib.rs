@@ -1,7 +1,11 @@
 //! Lumelite bridge: implements render_api::RenderBackend using lumelite-renderer.
 
+mod lod;
 mod plugin;
+mod sort;
+mod upload;
 mod window_backend;
 
 pub use plugin::LumelitePlugin;
-pub use window_backend::LumeliteWindowBackend;
+pub use sort::{sort_front_to_back, view_distance_sq};
+pub use window_backend::{present_format, select_surface_format, LumeliteBackendOptions, LumeliteWindowBackend};
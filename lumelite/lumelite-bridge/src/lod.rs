@@ -0,0 +1,72 @@
+//! Discrete mesh LOD selection (`ExtractedMesh::lods`) from the projected size of the mesh bounds.
+
+use lumelite_renderer::Aabb;
+
+/// Screen size of a mesh: diameter of its world-space bounding sphere over the viewport height, from
+/// a column-major view-projection (perspective or orthographic). Infinite when the mesh center is at
+/// or behind the camera plane, so such meshes keep LOD 0.
+pub(crate) fn screen_size(view_proj: &[f32; 16], transform: &[f32; 16], bounds: &Aabb) -> f32 {
+    let m = transform;
+    let local: [f32; 3] = std::array::from_fn(|i| 0.5 * (bounds.min[i] + bounds.max[i]));
+    let center: [f32; 3] = std::array::from_fn(|r| m[r] * local[0] + m[4 + r] * local[1] + m[8 + r] * local[2] + m[12 + r]);
+    let half_diagonal = (0..3).map(|i| (0.5 * (bounds.max[i] - bounds.min[i])).powi(2)).sum::<f32>().sqrt();
+    let max_scale = (0..3)
+        .map(|c| (m[c * 4] * m[c * 4] + m[c * 4 + 1] * m[c * 4 + 1] + m[c * 4 + 2] * m[c * 4 + 2]).sqrt())
+        .fold(0.0f32, f32::max);
+    let vp = view_proj;
+    let w = vp[3] * center[0] + vp[7] * center[1] + vp[11] * center[2] + vp[15];
+    if w <= 1e-6 {
+        return f32::INFINITY;
+    }
+    // Row 1 of view_proj is the projection's y scale times a unit view axis: its length is that scale.
+    let y_scale = (vp[1] * vp[1] + vp[5] * vp[5] + vp[9] * vp[9]).sqrt();
+    half_diagonal * max_scale * y_scale / w
+}
+
+/// Index into `[LOD 0, lods...]` for `screen_size`, given the thresholds of the coarser LODs in
+/// decreasing order: the last LOD whose threshold the screen size does not exceed.
+pub(crate) fn select_lod(thresholds: impl IntoIterator<Item = f32>, screen_size: f32) -> usize {
+    thresholds.into_iter().take_while(|&t| screen_size <= t).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+    /// Right-handed perspective looking down -z (90 degree vertical fov: y scale 1).
+    #[rustfmt::skip]
+    const PERSPECTIVE: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, -1.0, 0.0, 0.0, -0.1, 0.0];
+
+    fn translate(z: f32, scale: f32) -> [f32; 16] {
+        [scale, 0.0, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0, 0.0, scale, 0.0, 0.0, 0.0, z, 1.0]
+    }
+
+    #[test]
+    fn screen_size_shrinks_with_distance_and_grows_with_scale() {
+        // Unit cube: bounding sphere radius sqrt(3) / 2.
+        let cube = Aabb { min: [-0.5; 3], max: [0.5; 3] };
+        let radius = 3f32.sqrt() / 2.0;
+        let near = screen_size(&PERSPECTIVE, &translate(-5.0, 1.0), &cube);
+        assert!((near - radius / 5.0).abs() < 1e-5);
+        let far = screen_size(&PERSPECTIVE, &translate(-20.0, 1.0), &cube);
+        assert!((far - near / 4.0).abs() < 1e-5);
+        let scaled = screen_size(&PERSPECTIVE, &translate(-20.0, 4.0), &cube);
+        assert!((scaled - near).abs() < 1e-5);
+        assert_eq!(screen_size(&PERSPECTIVE, &translate(3.0, 1.0), &cube), f32::INFINITY);
+        // Orthographic 2 units tall: diameter over height.
+        assert!((screen_size(&IDENTITY, &IDENTITY, &cube) - radius).abs() < 1e-5);
+    }
+
+    #[test]
+    fn lod_is_the_last_threshold_not_exceeded() {
+        let thresholds = [0.5, 0.2];
+        assert_eq!(select_lod(thresholds, 0.8), 0);
+        assert_eq!(select_lod(thresholds, 0.5), 1);
+        assert_eq!(select_lod(thresholds, 0.3), 1);
+        assert_eq!(select_lod(thresholds, 0.05), 2);
+        assert_eq!(select_lod(thresholds, f32::INFINITY), 0);
+        assert_eq!(select_lod([], 0.01), 0);
+    }
+}
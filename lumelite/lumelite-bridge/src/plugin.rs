@@ -1,21 +1,28 @@
 //! Lumelite plugin: implements RenderBackend for the host.
 //! Single PBR pipeline: vertices are 32-byte (position+normal+uv); material optional (default 1x1 textures).
 
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use render_api::{
-    ExtractedMesh, ExtractedMeshes, ExtractedPbrMaterial, ExtractedView, PbrTextureData,
-    RenderBackend,
+    log, log_enabled, mat4_inverse, mat4_mul, ExtractedMesh, ExtractedMeshes, ExtractedPbrMaterial, ExtractedView, LogLevel,
+    MeshLod, PbrTextureData, RenderBackend, ShaderVariant, Sphere,
 };
-use lumelite_renderer::{LumeliteConfig, MeshDraw, PbrTextureViews, Renderer};
+use wgpu::util::DeviceExt;
+use lumelite_renderer::{
+    is_mirrored, Aabb, DirectTriangleOutput, FrameView, LodBias, LumeliteConfig, MeshDraw, OcclusionCulling, PbrTextureViews, Renderer,
+};
+
+use crate::{lod, sort, upload};
 
 /// Build orthographic projection (column-major): left, right, bottom, top, near, far.
+/// Right-handed, mapping view depth to wgpu's [0, 1] clip range (near -> 0, far -> 1); a GL-style
+/// [-1, 1] mapping would clip the near half of the shadow frustum away.
 fn ortho(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> [f32; 16] {
     let sx = 2.0 / (right - left);
     let sy = 2.0 / (top - bottom);
-    let sz = -2.0 / (far - near);
+    let sz = -1.0 / (far - near);
     let tx = -(right + left) / (right - left);
     let ty = -(top + bottom) / (top - bottom);
-    let tz = -(far + near) / (far - near);
+    let tz = -near / (far - near);
     [
         sx, 0.0, 0.0, 0.0,
         0.0, sy, 0.0, 0.0,
@@ -40,19 +47,6 @@ fn look_at(eye: [f32; 3], center: [f32; 3], up: [f32; 3]) -> [f32; 16] {
     ]
 }
 
-fn mat4_mul(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
-    let mut c = [0.0f32; 16];
-    for col in 0..4 {
-        for row in 0..4 {
-            c[col * 4 + row] = a[row] * b[col * 4 + 0]
-                + a[4 + row] * b[col * 4 + 1]
-                + a[8 + row] * b[col * 4 + 2]
-                + a[12 + row] * b[col * 4 + 3];
-        }
-    }
-    c
-}
-
 /// Build light view-projection for shadow map (orthographic, directional light).
 fn build_light_view_proj(direction: [f32; 3]) -> [f32; 16] {
     let dist = 20.0;
@@ -70,48 +64,100 @@ fn build_light_view_proj(direction: [f32; 3]) -> [f32; 16] {
     mat4_mul(&proj, &view)
 }
 
-/// Invert 4x4 matrix (column-major). Returns None if singular.
-fn invert_view_proj(m: &[f32; 16]) -> Option<[f32; 16]> {
-    let mut inv = [0.0f32; 16];
-    inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15] + m[9] * m[7] * m[14] + m[13] * m[6] * m[11] - m[13] * m[7] * m[10];
-    inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15] - m[9] * m[3] * m[14] - m[13] * m[2] * m[11] + m[13] * m[3] * m[10];
-    inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15] + m[5] * m[3] * m[14] + m[13] * m[2] * m[7] - m[13] * m[3] * m[6];
-    inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11] - m[5] * m[3] * m[10] - m[9] * m[2] * m[7] + m[9] * m[3] * m[6];
-    inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15] - m[8] * m[7] * m[14] - m[12] * m[6] * m[11] + m[12] * m[7] * m[10];
-    inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15] + m[8] * m[3] * m[14] + m[12] * m[2] * m[11] - m[12] * m[3] * m[10];
-    inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15] - m[4] * m[3] * m[14] - m[12] * m[2] * m[7] + m[12] * m[3] * m[6];
-    inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11] + m[4] * m[3] * m[10] + m[8] * m[2] * m[7] - m[8] * m[3] * m[6];
-    inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15] + m[8] * m[7] * m[13] + m[12] * m[5] * m[11] - m[12] * m[7] * m[9];
-    inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15] - m[8] * m[3] * m[13] - m[12] * m[1] * m[11] + m[12] * m[3] * m[9];
-    inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15] + m[4] * m[3] * m[13] + m[12] * m[1] * m[7] - m[12] * m[3] * m[5];
-    inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11] - m[4] * m[3] * m[9] - m[8] * m[1] * m[7] + m[8] * m[3] * m[5];
-    inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14] - m[8] * m[6] * m[13] - m[12] * m[5] * m[10] + m[12] * m[6] * m[9];
-    inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14] + m[8] * m[2] * m[13] + m[12] * m[1] * m[10] - m[12] * m[2] * m[9];
-    inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14] - m[4] * m[2] * m[13] - m[12] * m[1] * m[6] + m[12] * m[2] * m[5];
-    inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10] + m[4] * m[2] * m[9] + m[8] * m[1] * m[6] - m[8] * m[2] * m[5];
-    let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
-    if det.abs() < 1e-10 {
-        return None;
-    }
-    let s = 1.0 / det;
-    for x in &mut inv {
-        *x *= s;
-    }
-    Some(inv)
+/// Largest texture side to upload and whether to downscale larger ones (`LumeliteConfig`).
+#[derive(Clone, Copy)]
+struct TextureSizeLimit {
+    max_dimension: u32,
+    downscale: bool,
+}
+
+impl TextureSizeLimit {
+    fn new(device: &wgpu::Device, config: &LumeliteConfig) -> Self {
+        let device_max = device.limits().max_texture_dimension_2d;
+        Self {
+            max_dimension: config.max_texture_dimension.map_or(device_max, |m| m.min(device_max)),
+            downscale: config.downscale_oversized_textures,
+        }
+    }
+}
+
+/// Result of [`upload::fit_texture`] for one oversized texture, with what it was computed from.
+struct FittedTexture {
+    /// Source texels, compared on a key hit so a hash collision cannot swap two textures.
+    source: Vec<u8>,
+    limit: (u32, bool),
+    result: Result<(u32, u32, Vec<u8>), String>,
+}
+
+/// Oversized textures already downscaled (or rejected), so a texture that stays in the scene is fitted
+/// and warned about once instead of every `prepare`. Entries not looked up between two
+/// [`Self::retain_used`] calls are dropped.
+#[derive(Default)]
+struct FittedTextures {
+    entries: std::collections::HashMap<upload::TextureKey, FittedTexture>,
+    used: std::collections::HashSet<upload::TextureKey>,
+}
+
+impl FittedTextures {
+    /// Size and texels to upload for `bytes`: as given when they fit `limit`, otherwise the cached or
+    /// newly computed [`upload::fit_texture`] result.
+    fn fit<'a>(
+        &'a mut self,
+        label: &str,
+        width: u32,
+        height: u32,
+        bytes: &'a [u8],
+        limit: TextureSizeLimit,
+    ) -> Result<(u32, u32, &'a [u8]), String> {
+        if width <= limit.max_dimension && height <= limit.max_dimension {
+            return upload::check_texture_len(width, height, bytes).map(|()| (width, height, bytes));
+        }
+        let key = upload::texture_key(width, height, bytes);
+        self.used.insert(key);
+        let limit_key = (limit.max_dimension, limit.downscale);
+        let cached = self.entries.get(&key).is_some_and(|e| e.limit == limit_key && e.source == bytes);
+        if !cached {
+            let result = upload::fit_texture(width, height, bytes, limit.max_dimension, limit.downscale)
+                .map(|fitted| fitted.unwrap_or_else(|| (width, height, bytes.to_vec())));
+            if let Ok((w, h, _)) = &result {
+                log(
+                    LogLevel::Warn,
+                    "lumelite",
+                    format_args!(
+                        "{} is {}x{}, downscaled to {}x{} (max texture dimension {})",
+                        label, width, height, w, h, limit.max_dimension
+                    ),
+                );
+            }
+            self.entries.insert(key, FittedTexture { source: bytes.to_vec(), limit: limit_key, result });
+        }
+        match &self.entries[&key].result {
+            Ok((w, h, texels)) => Ok((*w, *h, texels)),
+            Err(e) => Err(e.clone()),
+        }
+    }
+
+    /// Drop the entries not looked up since the last call.
+    fn retain_used(&mut self) {
+        let used = std::mem::take(&mut self.used);
+        self.entries.retain(|key, _| used.contains(key));
+    }
 }
 
-/// Create a texture view from optional RGBA8 data or a 1x1 default pixel.
-fn create_texture_view(
+/// Format of color textures (base color): sRGB-encoded, decoded to linear when sampled.
+const COLOR_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// Format of data textures (normal, metallic/roughness, AO): linear, sampled as stored.
+const DATA_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+fn upload_texture(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     label: &str,
-    data: Option<&PbrTextureData>,
-    default_rgba: [u8; 4],
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    bytes: &[u8],
 ) -> Arc<wgpu::TextureView> {
-    let (width, height, bytes) = match data {
-        Some(d) if !d.data.is_empty() && d.width > 0 && d.height > 0 => (d.width, d.height, d.data.as_slice()),
-        _ => (1u32, 1u32, default_rgba.as_slice()),
-    };
     let texture = device.create_texture(&wgpu::TextureDescriptor {
         label: Some(label),
         size: wgpu::Extent3d {
@@ -122,7 +168,7 @@ fn create_texture_view(
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8Unorm,
+        format,
         usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
     });
@@ -148,75 +194,202 @@ fn create_texture_view(
     Arc::new(texture.create_view(&Default::default()))
 }
 
-/// Build PbrTextureViews from optional material or use defaults.
+/// Build PbrTextureViews from optional material or use defaults. Textures rejected by `limit` are
+/// replaced by the default texel and their errors appended to `errors`.
 fn material_to_views(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     material: Option<&ExtractedPbrMaterial>,
     default_views: &PbrTextureViews,
+    limit: TextureSizeLimit,
+    fitted: &mut FittedTextures,
+    errors: &mut Vec<String>,
 ) -> PbrTextureViews {
     let mat = match material {
         Some(m) => m,
         None => return default_views.clone(),
     };
+    // A `format` texture view from optional RGBA8 data or a 1x1 default pixel. Data over `limit` is
+    // downscaled or, when that is off (or the data is short), replaced by the default pixel.
+    let mut view = |label, format, data: Option<&PbrTextureData>, default_rgba: [u8; 4]| {
+        let (width, height, bytes) = match data {
+            Some(d) if !d.data.is_empty() && d.width > 0 && d.height > 0 => (d.width, d.height, d.data.as_slice()),
+            _ => (1u32, 1u32, default_rgba.as_slice()),
+        };
+        match fitted.fit(label, width, height, bytes, limit) {
+            Ok((w, h, texels)) => upload_texture(device, queue, label, format, w, h, texels),
+            Err(e) => {
+                errors.push(format!("{}: {}", label, e));
+                upload_texture(device, queue, label, format, 1, 1, &default_rgba)
+            }
+        }
+    };
     PbrTextureViews {
-        base_color: create_texture_view(
-            device,
-            queue,
-            "lumelite_base_color",
-            mat.base_color.as_ref(),
-            [255, 255, 255, 255],
-        ),
-        normal: create_texture_view(
-            device,
-            queue,
-            "lumelite_normal",
-            mat.normal.as_ref(),
-            [128, 128, 255, 255],
-        ),
-        metallic_roughness: create_texture_view(
-            device,
-            queue,
-            "lumelite_metallic_roughness",
-            mat.metallic_roughness.as_ref(),
-            [0, 128, 0, 0],
-        ),
-        ao: create_texture_view(
-            device,
-            queue,
-            "lumelite_ao",
-            mat.ao.as_ref(),
-            [255, 255, 255, 255],
-        ),
+        base_color: view("lumelite_base_color", COLOR_TEXTURE_FORMAT, mat.base_color.as_ref(), [255, 255, 255, 255]),
+        normal: view("lumelite_normal", DATA_TEXTURE_FORMAT, mat.normal.as_ref(), [128, 128, 255, 255]),
+        metallic_roughness: view("lumelite_metallic_roughness", DATA_TEXTURE_FORMAT, mat.metallic_roughness.as_ref(), [0, 128, 0, 0]),
+        ao: view("lumelite_ao", DATA_TEXTURE_FORMAT, mat.ao.as_ref(), [255, 255, 255, 255]),
     }
 }
 
 /// Create default 1x1 PBR texture views (white base, flat normal, 0 metal 0.5 rough, white AO).
 fn create_default_pbr_views(device: &wgpu::Device, queue: &wgpu::Queue) -> PbrTextureViews {
     PbrTextureViews {
-        base_color: create_texture_view(device, queue, "lumelite_default_bc", None::<&PbrTextureData>, [255, 255, 255, 255]),
-        normal: create_texture_view(device, queue, "lumelite_default_n", None::<&PbrTextureData>, [128, 128, 255, 255]),
-        metallic_roughness: create_texture_view(device, queue, "lumelite_default_mr", None::<&PbrTextureData>, [0, 128, 0, 0]),
-        ao: create_texture_view(device, queue, "lumelite_default_ao", None::<&PbrTextureData>, [255, 255, 255, 255]),
+        base_color: upload_texture(device, queue, "lumelite_default_bc", COLOR_TEXTURE_FORMAT, 1, 1, &[255, 255, 255, 255]),
+        normal: upload_texture(device, queue, "lumelite_default_n", DATA_TEXTURE_FORMAT, 1, 1, &[128, 128, 255, 255]),
+        metallic_roughness: upload_texture(device, queue, "lumelite_default_mr", DATA_TEXTURE_FORMAT, 1, 1, &[0, 128, 0, 0]),
+        ao: upload_texture(device, queue, "lumelite_default_ao", DATA_TEXTURE_FORMAT, 1, 1, &[255, 255, 255, 255]),
     }
 }
 
-/// Cached GPU buffers, transform, and PBR texture views for one mesh.
-struct CachedMesh {
+fn is_double_sided(mesh: &ExtractedMesh) -> bool {
+    mesh.material.as_ref().is_some_and(|m| m.double_sided)
+}
+
+fn emissive_factor(mesh: &ExtractedMesh) -> [f32; 3] {
+    mesh.material.as_ref().map_or([0.0; 3], |m| m.emissive_factor)
+}
+
+/// Index buffer of one of `ExtractedMesh::lods`.
+struct CachedLod {
+    index_buf: Arc<wgpu::Buffer>,
+    index_count: u32,
+    screen_size: f32,
+}
+
+impl CachedLod {
+    fn new(device: &wgpu::Device, lod: &MeshLod) -> Self {
+        let index_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("lumelite_mesh_lod_index"),
+            contents: &lod.index_data,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+        });
+        Self { index_buf: Arc::new(index_buf), index_count: (lod.index_data.len() / 4) as u32, screen_size: lod.screen_size }
+    }
+}
+
+/// Mesh-space box enclosing the world-space `sphere` seen through `transform` (exact for the ellipsoid
+/// the sphere becomes in mesh space); `None` when `transform` is singular.
+fn sphere_mesh_bounds(sphere: &Sphere, transform: &[f32; 16]) -> Option<Aabb> {
+    let inv = mat4_inverse(transform)?;
+    let [x, y, z] = sphere.center;
+    let mut min = [0.0; 3];
+    let mut max = [0.0; 3];
+    for i in 0..3 {
+        let center = inv[i] * x + inv[4 + i] * y + inv[8 + i] * z + inv[12 + i];
+        let extent = sphere.radius * (inv[i] * inv[i] + inv[4 + i] * inv[4 + i] + inv[8 + i] * inv[8 + i]).sqrt();
+        min[i] = center - extent;
+        max[i] = center + extent;
+    }
+    Some(Aabb { min, max })
+}
+
+/// GPU buffers of one source geometry, shared by every entity whose mesh has the same
+/// [`upload::GeometryKey`] (each still gets its own draw and transform).
+struct MeshGeometry {
     vertex_buf: Arc<wgpu::Buffer>,
     index_buf: Arc<wgpu::Buffer>,
+    /// Vertex color stream, for meshes in `VertexFormat::PositionNormalColorUv`.
+    color_buf: Option<Arc<wgpu::Buffer>>,
+    /// Tangent stream, for meshes in `VertexFormat::PositionNormalTangentUv`.
+    tangent_buf: Option<Arc<wgpu::Buffer>>,
     index_count: u32,
     vertex_len: usize,
     index_len: usize,
+    /// Mesh-space bounds; only computed while the renderer culls on the GPU or the mesh has LODs, and
+    /// never for meshes with a bounds override (see [`upload::GeometryKey`]).
+    bounds: Option<Aabb>,
+    /// Coarser LODs after `index_buf` (LOD 0), in decreasing screen size order.
+    lods: Vec<CachedLod>,
+}
+
+impl MeshGeometry {
+    /// Whether `mesh` can be written into these buffers in place.
+    fn fits(&self, mesh: &ExtractedMesh) -> bool {
+        self.vertex_len == upload::vertex_len_32(mesh)
+            && self.index_len == mesh.index_data.len()
+            && self.color_buf.as_ref().map_or(0, |b| b.size()) == upload::vertex_color_len(mesh) as u64
+            && self.tangent_buf.as_ref().map_or(0, |b| b.size()) == upload::tangent_len(mesh) as u64
+            && self.lods.len() == mesh.lods.len()
+            && self.lods.iter().zip(&mesh.lods).all(|(c, l)| c.index_buf.size() == l.index_data.len() as u64)
+    }
+}
+
+/// Cached geometry, transform, and PBR texture views for one entity.
+struct CachedMesh {
+    geometry: Arc<MeshGeometry>,
+    geometry_key: upload::GeometryKey,
+    /// `ExtractedMesh::geometry_revision` the geometry was keyed at; while it holds (and is not 0), the
+    /// key is reused.
+    geometry_revision: u64,
     transform: [f32; 16],
+    /// Determinant sign of `transform`, computed when the transform changes.
+    mirrored: bool,
+    double_sided: bool,
+    emissive: [f32; 3],
+    shader_variant: ShaderVariant,
     pbr_textures: PbrTextureViews,
+    /// Mesh-space box around `ExtractedMesh::bounds_override`; used instead of the geometry's bounds.
+    bounds_override: Option<Aabb>,
+}
+
+impl CachedMesh {
+    fn new(
+        geometry: Arc<MeshGeometry>,
+        geometry_key: upload::GeometryKey,
+        mesh: &ExtractedMesh,
+        pbr_textures: PbrTextureViews,
+    ) -> Self {
+        Self {
+            geometry,
+            geometry_key,
+            geometry_revision: mesh.geometry_revision,
+            transform: mesh.transform,
+            mirrored: is_mirrored(&mesh.transform),
+            double_sided: is_double_sided(mesh),
+            emissive: emissive_factor(mesh),
+            shader_variant: mesh.shader_variant,
+            pbr_textures,
+            bounds_override: mesh.bounds_override.and_then(|sphere| sphere_mesh_bounds(&sphere, &mesh.transform)),
+        }
+    }
+
+    /// Mesh-space bounds for culling and LOD selection: the host's override, else the geometry's.
+    fn bounds(&self) -> Option<Aabb> {
+        self.bounds_override.or(self.geometry.bounds)
+    }
+
+    /// Index buffer and count of the LOD for the mesh's current screen size in `view_proj`, scaled by
+    /// the renderer's `LodBias` (`LumeliteConfig::triangle_budget`).
+    fn select_lod(&self, view_proj: &[f32; 16], bias: &LodBias) -> (&Arc<wgpu::Buffer>, u32) {
+        let geometry = &*self.geometry;
+        let level = match (self.bounds(), geometry.lods.is_empty()) {
+            (Some(bounds), false) => lod::select_lod(
+                geometry.lods.iter().map(|l| l.screen_size),
+                lod::screen_size(view_proj, &self.transform, &bounds) * bias.screen_size_scale(),
+            ),
+            _ => 0,
+        };
+        match level {
+            0 => (&geometry.index_buf, geometry.index_count),
+            n => (&geometry.lods[n - 1].index_buf, geometry.lods[n - 1].index_count),
+        }
+    }
 }
 
 /// Lumelite plugin: owns the wgpu device/queue and renderer; implements RenderBackend.
 pub struct LumelitePlugin {
     renderer: Renderer,
     mesh_cache: std::collections::HashMap<u64, CachedMesh>,
+    /// Live geometries by source key; an entry dies with the last entity using it.
+    geometry_cache: std::collections::HashMap<upload::GeometryKey, Weak<MeshGeometry>>,
     default_pbr_textures: PbrTextureViews,
+    /// Textures rejected by the last `prepare` (`LumeliteConfig::downscale_oversized_textures` off),
+    /// sorted; see [`Self::texture_errors`].
+    texture_errors: Vec<String>,
+    fitted_textures: FittedTextures,
+    /// Submission of the last rendered frame, for [`Self::wait_frame_done`].
+    last_submission: Option<wgpu::SubmissionIndex>,
 }
 
 impl LumelitePlugin {
@@ -232,7 +405,11 @@ impl LumelitePlugin {
         Ok(Self {
             renderer,
             mesh_cache: std::collections::HashMap::new(),
+            geometry_cache: std::collections::HashMap::new(),
             default_pbr_textures,
+            texture_errors: Vec::new(),
+            fitted_textures: FittedTextures::default(),
+            last_submission: None,
         })
     }
 
@@ -246,28 +423,51 @@ impl LumelitePlugin {
     pub fn renderer(&self) -> &Renderer {
         &self.renderer
     }
-}
+    /// Format of the targets the plugin presents to (`LumeliteConfig::swapchain_format`).
+    pub fn swapchain_format(&self) -> wgpu::TextureFormat {
+        self.renderer.config().swapchain_format
+    }
+    /// Mutable renderer access (e.g. render scale, frame time reporting for dynamic resolution).
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+    /// Warm every pipeline before the first frame (see `Renderer::prewarm`). Idempotent.
+    pub fn prewarm(&mut self) -> Result<(), String> {
+        self.renderer.prewarm()
+    }
+    /// Run `callback` once the GPU has finished everything submitted so far, i.e. the last rendered
+    /// frame when called right after it (each registration covers the submissions made before it, so
+    /// register once per frame). wgpu calls it while the device is polled: the host must call
+    /// `device().poll(wgpu::Maintain::Poll)` regularly, or [`Self::wait_frame_done`]. The callback runs
+    /// on the polling thread.
+    pub fn on_frame_done(&self, callback: impl FnOnce() + Send + 'static) {
+        self.renderer.queue().on_submitted_work_done(callback);
+    }
 
-impl LumelitePlugin {
-    /// Ensure vertex data is 32-byte stride (position+normal+uv). Pad 24-byte to 32 if needed.
-    fn vertex_data_32(&self, mesh: &ExtractedMesh) -> Vec<u8> {
-        let v = &mesh.vertex_data;
-        if v.is_empty() {
-            return Vec::new();
-        }
-        if v.len() % 32 == 0 {
-            return v.clone();
-        }
-        if v.len() % 24 == 0 {
-            let n = v.len() / 24;
-            let mut out = Vec::with_capacity(n * 32);
-            for i in 0..n {
-                out.extend_from_slice(&v[i * 24..(i + 1) * 24]);
-                out.extend_from_slice(&[0u8; 8]);
-            }
-            return out;
+    /// Block until the last rendered frame's GPU work has completed (returns at once before the first
+    /// frame), running due [`Self::on_frame_done`] callbacks.
+    pub fn wait_frame_done(&self) {
+        if let Some(index) = &self.last_submission {
+            self.renderer.device().poll(wgpu::Maintain::WaitForSubmissionIndex(index.clone()));
         }
-        v.clone()
+    }
+
+    /// Depth under output pixel (`x`, `y`) of the last rendered frame, for picking (see
+    /// `Renderer::read_depth_pixel`). `None` before the first frame, outside the output, or if readback fails.
+    pub fn read_depth_pixel(&self, x: u32, y: u32) -> Option<f32> {
+        self.renderer.read_depth_pixel(x, y).ok().flatten()
+    }
+
+    /// Material textures the last `prepare` rejected (over the size limit with
+    /// `LumeliteConfig::downscale_oversized_textures` off, or with short data), one message each. They
+    /// render with the default texel instead; frames still succeed.
+    pub fn texture_errors(&self) -> &[String] {
+        &self.texture_errors
+    }
+
+    /// Number of distinct vertex/index buffer sets held; entities with identical geometry share one.
+    pub fn geometry_count(&self) -> usize {
+        self.geometry_cache.len()
     }
 }
 
@@ -275,57 +475,130 @@ impl RenderBackend for LumelitePlugin {
     fn prepare(&mut self, extracted: &ExtractedMeshes) {
         let device = self.renderer.device();
         let queue = self.renderer.queue();
+        let culling = self.renderer.gpu_culling_active();
+        let texture_limit = TextureSizeLimit::new(device, self.renderer.config());
+        let previous_texture_errors = std::mem::take(&mut self.texture_errors);
+        // Meshes with a host override skip the vertex scan; the override is part of the geometry key,
+        // so they never share buffers with a mesh that needs computed bounds.
+        let bounds = |mesh: &ExtractedMesh| {
+            (mesh.bounds_override.is_none() && (culling || !mesh.lods.is_empty())).then(|| upload::mesh_bounds(mesh)).flatten()
+        };
         let current_entities: std::collections::HashSet<u64> =
             extracted.meshes.keys().copied().collect();
         self.mesh_cache.retain(|k, _| current_entities.contains(k));
+        let mut uploads: Vec<upload::MeshUpload> = Vec::new();
+        // Entities waiting for a new geometry: (entity, index into `uploads`, key, textures).
+        let mut pending = Vec::new();
+        let mut pending_keys: std::collections::HashMap<upload::GeometryKey, usize> = std::collections::HashMap::new();
         for (&entity_id, mesh) in &extracted.meshes {
-            if !mesh.visible || mesh.vertex_data.is_empty() || mesh.index_data.is_empty() {
+            if !mesh.visible {
+                continue;
+            }
+            // Nothing to draw (this includes wide-format data shorter than one vertex); a zero-size
+            // buffer cannot be mapped, and a previous geometry must not stay on screen.
+            if upload::vertex_len_32(mesh) == 0 || mesh.index_data.is_empty() {
+                self.mesh_cache.remove(&entity_id);
                 continue;
             }
-            let vertex_data = self.vertex_data_32(mesh);
-            let vertex_len = vertex_data.len();
-            let index_len = mesh.index_data.len();
-            let index_count = (index_len / 4) as u32;
             let pbr_textures = material_to_views(
                 device,
                 queue,
                 mesh.material.as_ref(),
                 &self.default_pbr_textures,
+                texture_limit,
+                &mut self.fitted_textures,
+                &mut self.texture_errors,
             );
-            if let Some(cached) = self.mesh_cache.get_mut(&entity_id) {
-                if cached.vertex_len == vertex_len && cached.index_len == index_len {
-                    queue.write_buffer(&cached.vertex_buf, 0, &vertex_data);
-                    queue.write_buffer(&cached.index_buf, 0, &mesh.index_data);
-                    cached.transform = mesh.transform;
-                    cached.pbr_textures = pbr_textures;
-                    continue;
+            // Same tracked revision and lengths: the geometry is as uploaded, so it is neither hashed nor
+            // read. Untracked meshes (revision 0) are hashed every time, so in-place edits are seen.
+            if let Some(cached) = self.mesh_cache.get(&entity_id).filter(|c| {
+                mesh.geometry_revision != 0
+                    && c.geometry_revision == mesh.geometry_revision
+                    && c.geometry.vertex_len == upload::vertex_len_32(mesh)
+                    && c.geometry.index_len == mesh.index_data.len()
+                    && c.geometry_key.bounds_override() == mesh.bounds_override.is_some()
+            }) {
+                let (geometry, key) = (Arc::clone(&cached.geometry), cached.geometry_key);
+                self.mesh_cache.insert(entity_id, CachedMesh::new(geometry, key, mesh, pbr_textures));
+                continue;
+            }
+            let key = upload::geometry_key(mesh);
+            let shared = match self.mesh_cache.get(&entity_id) {
+                Some(cached) if cached.geometry_key == key => Some(Arc::clone(&cached.geometry)),
+                _ => self.geometry_cache.get(&key).and_then(Weak::upgrade),
+            };
+            if let Some(geometry) = shared {
+                self.mesh_cache.insert(entity_id, CachedMesh::new(geometry, key, mesh, pbr_textures));
+                continue;
+            }
+            // Changed geometry used by no other entity: rewrite its buffers when the sizes still fit.
+            if let Some(mut cached) = self.mesh_cache.remove(&entity_id) {
+                if Arc::strong_count(&cached.geometry) == 1 {
+                    // The entry may already name a newer geometry with the old key.
+                    if self.geometry_cache.get(&cached.geometry_key).is_some_and(|g| Weak::as_ptr(g) == Arc::as_ptr(&cached.geometry)) {
+                        self.geometry_cache.remove(&cached.geometry_key);
+                    }
+                    let geometry = Arc::get_mut(&mut cached.geometry).expect("geometry has no other references");
+                    if geometry.fits(mesh) {
+                        queue.write_buffer(&geometry.vertex_buf, 0, &upload::vertex_data_32(mesh));
+                        if let (Some(color_buf), Some(colors)) = (&geometry.color_buf, upload::vertex_colors(mesh)) {
+                            queue.write_buffer(color_buf, 0, &colors);
+                        }
+                        if let (Some(tangent_buf), Some(tangents)) = (&geometry.tangent_buf, upload::tangents(mesh)) {
+                            queue.write_buffer(tangent_buf, 0, &tangents);
+                        }
+                        queue.write_buffer(&geometry.index_buf, 0, &mesh.index_data);
+                        for (cached_lod, lod) in geometry.lods.iter_mut().zip(&mesh.lods) {
+                            queue.write_buffer(&cached_lod.index_buf, 0, &lod.index_data);
+                            cached_lod.screen_size = lod.screen_size;
+                        }
+                        geometry.bounds = bounds(mesh);
+                        self.geometry_cache.insert(key, Arc::downgrade(&cached.geometry));
+                        self.mesh_cache.insert(entity_id, CachedMesh::new(cached.geometry, key, mesh, pbr_textures));
+                        continue;
+                    }
                 }
             }
-            let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("lumelite_mesh_vertex"),
-                size: vertex_len as u64,
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
+            let upload_index = *pending_keys.entry(key).or_insert_with(|| {
+                uploads.push(upload::MeshUpload::new(device, mesh));
+                uploads.len() - 1
             });
-            queue.write_buffer(&vertex_buf, 0, &vertex_data);
-            let index_buf = device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("lumelite_mesh_index"),
-                size: index_len as u64,
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            });
-            queue.write_buffer(&index_buf, 0, &mesh.index_data);
-            self.mesh_cache.insert(
-                entity_id,
-                CachedMesh {
-                    vertex_buf: Arc::new(vertex_buf),
-                    index_buf: Arc::new(index_buf),
-                    index_count,
-                    vertex_len,
-                    index_len,
-                    transform: mesh.transform,
-                    pbr_textures,
-                },
+            pending.push((entity_id, upload_index, key, pbr_textures));
+        }
+        // New geometry: buffers are created mapped above, filled here and reach the GPU together with
+        // the next submit.
+        upload::fill_uploads(&uploads);
+        let geometries: Vec<Arc<MeshGeometry>> = uploads
+            .into_iter()
+            .map(|upload| {
+                let mesh = upload.mesh;
+                Arc::new(MeshGeometry {
+                    vertex_buf: Arc::new(upload.vertex_buf),
+                    color_buf: upload.color_buf.map(Arc::new),
+                    tangent_buf: upload.tangent_buf.map(Arc::new),
+                    index_buf: Arc::new(upload.index_buf),
+                    index_count: (mesh.index_data.len() / 4) as u32,
+                    vertex_len: upload::vertex_len_32(mesh),
+                    index_len: mesh.index_data.len(),
+                    bounds: bounds(mesh),
+                    lods: mesh.lods.iter().map(|l| CachedLod::new(device, l)).collect(),
+                })
+            })
+            .collect();
+        for (entity_id, upload_index, key, pbr_textures) in pending {
+            let geometry = Arc::clone(&geometries[upload_index]);
+            self.geometry_cache.insert(key, Arc::downgrade(&geometry));
+            self.mesh_cache.insert(entity_id, CachedMesh::new(geometry, key, &extracted.meshes[&entity_id], pbr_textures));
+        }
+        self.geometry_cache.retain(|_, geometry| geometry.strong_count() > 0);
+        self.fitted_textures.retain_used();
+        // Warned about when the set changes, not on every prepare that still holds the same textures.
+        self.texture_errors.sort_unstable();
+        if !self.texture_errors.is_empty() && self.texture_errors != previous_texture_errors {
+            log(
+                LogLevel::Warn,
+                "lumelite",
+                format_args!("oversized textures replaced by defaults: {}", self.texture_errors.join("; ")),
             );
         }
     }
@@ -333,6 +606,10 @@ impl RenderBackend for LumelitePlugin {
     fn render_frame(&mut self, view: &ExtractedView) -> Result<(), String> {
         self.render_frame_impl(view, None)
     }
+
+    fn render_resolution(&self) -> Option<(u32, u32)> {
+        self.renderer.render_resolution()
+    }
 }
 
 impl LumelitePlugin {
@@ -345,26 +622,142 @@ impl LumelitePlugin {
         self.render_frame_impl(view, Some(swapchain_view))
     }
 
+    /// Render one frame into a host-acquired surface texture and present it, for hosts that own their
+    /// `wgpu::Surface` (e.g. alongside egui). The surface must have `RENDER_ATTACHMENT` usage and be
+    /// configured with `LumeliteConfig::swapchain_format`, or with its sRGB or non-sRGB counterpart and
+    /// `swapchain_format` in `view_formats` (e.g. a `Bgra8Unorm` surface viewed as `Bgra8UnormSrgb`); on
+    /// error the texture is dropped unpresented.
+    pub fn render_frame_to_surface(
+        &mut self,
+        view: &ExtractedView,
+        surface_texture: wgpu::SurfaceTexture,
+    ) -> Result<(), String> {
+        let format = surface_texture.texture.format();
+        let expected = self.renderer.config().swapchain_format;
+        if format.remove_srgb_suffix() != expected.remove_srgb_suffix() {
+            return Err(format!(
+                "render_frame_to_surface: surface format {:?} does not match swapchain_format {:?}",
+                format, expected
+            ));
+        }
+        let swapchain_view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor { format: Some(expected), ..Default::default() });
+        self.render_frame_impl(view, Some(&swapchain_view))?;
+        surface_texture.present();
+        Ok(())
+    }
+
+    /// Render one frame headless and read it back as tightly packed RGBA8 rows, top row first, at
+    /// `view.viewport_size` (e.g. for screenshots and golden-image tests). `swapchain_format` must be an
+    /// 8-bit RGBA or BGRA format; BGRA is swizzled to RGBA. Blocks until the copy is mapped.
+    pub fn render_frame_to_rgba8(&mut self, view: &ExtractedView) -> Result<Vec<u8>, String> {
+        let format = self.renderer.config().swapchain_format;
+        let bgra = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(format!("render_frame_to_rgba8: swapchain_format {:?} is not 8-bit RGBA", format)),
+        };
+        let (width, height) = view.viewport_size;
+        if width == 0 || height == 0 {
+            return Err(format!("render_frame_to_rgba8: empty viewport {}x{}", width, height));
+        }
+        let device = self.renderer.device();
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lumelite_readback_target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_frame_impl(view, Some(&target_view))?;
+
+        let row = width as usize * 4;
+        let padded_row = row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize);
+        let device = self.renderer.device();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lumelite_frame_readback"),
+            size: (padded_row * height as usize) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("lumelite_frame_readback") });
+        encoder.copy_texture_to_buffer(
+            target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row as u32),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.renderer.queue().submit([encoder.finish()]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback.slice(..).map_async(wgpu::MapMode::Read, move |r| {
+            let _ = sender.send(r);
+        });
+        self.renderer.device().poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| format!("frame readback: {}", e))?
+            .map_err(|e| format!("frame readback: {}", e))?;
+        let mut pixels = unpad_rows(&readback.slice(..).get_mapped_range(), padded_row, row);
+        if bgra {
+            pixels.chunks_exact_mut(4).for_each(|texel| texel.swap(0, 2));
+        }
+        Ok(pixels)
+    }
+
     fn render_frame_impl(
         &mut self,
         view: &ExtractedView,
         swapchain_view: Option<&wgpu::TextureView>,
     ) -> Result<(), String> {
-        let meshes: Vec<MeshDraw> = self
-            .mesh_cache
-            .values()
-            .map(|c| MeshDraw {
-                vertex_buf: Arc::clone(&c.vertex_buf),
-                index_buf: Arc::clone(&c.index_buf),
-                index_count: c.index_count,
-                transform: c.transform,
-                pbr_textures: c.pbr_textures.clone(),
+        let started = log_enabled(LogLevel::Debug, "lumelite").then(std::time::Instant::now);
+        let lod_bias = self.renderer.lod_bias();
+        // Two-pass occlusion culling keeps last frame's visibility per draw index, so it needs the same
+        // order every frame: by entity instead of by distance.
+        let two_pass = self.renderer.config().occlusion_culling == OcclusionCulling::TwoPass;
+        let mut cached: Vec<(&u64, &CachedMesh)> = self.mesh_cache.iter().collect();
+        if two_pass {
+            cached.sort_unstable_by_key(|&(entity_id, _)| *entity_id);
+        }
+        let mut meshes: Vec<MeshDraw> = cached
+            .into_iter()
+            .map(|(_, c)| {
+                let (index_buf, index_count) = c.select_lod(&view.view_proj, &lod_bias);
+                MeshDraw {
+                    vertex_buf: Arc::clone(&c.geometry.vertex_buf),
+                    index_buf: Arc::clone(index_buf),
+                    index_count,
+                    transform: c.transform,
+                    pbr_textures: c.pbr_textures.clone(),
+                    mirrored: c.mirrored,
+                    double_sided: c.double_sided,
+                    bounds: c.bounds(),
+                    shader_variant: c.shader_variant,
+                    vertex_colors: c.geometry.color_buf.clone(),
+                    tangents: c.geometry.tangent_buf.clone(),
+                    emissive: c.emissive,
+                }
             })
             .collect();
+        let camera_pos = view.camera_pos;
+        // All meshes are opaque (no transparent pass yet): nearest first for early depth rejection.
+        if !two_pass {
+            sort::sort_front_to_back(&mut meshes, |m| sort::view_distance_sq(m, camera_pos));
+        }
         let (width, height) = view.viewport_size;
         let directional_light = view.directional_light
             .unwrap_or(([0.3f32, -0.8, 0.5], [1.0, 1.0, 1.0]));
-        let inv_view_proj = invert_view_proj(&view.view_proj).unwrap_or([
+        let inv_view_proj = mat4_inverse(&view.view_proj).unwrap_or([
             1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
         ]);
         let device = self.renderer.device();
@@ -379,27 +772,260 @@ impl LumelitePlugin {
         };
         if self.renderer.config().debug_direct_triangle {
             if let Some(sv) = swapchain_view {
-                self.renderer.encode_direct_triangle(&mut encoder, sv, &meshes, &view.view_proj)?;
+                let format = self.renderer.config().swapchain_format;
+                let output = DirectTriangleOutput { view: sv, format, width, height };
+                self.renderer.encode_direct_triangle(&mut encoder, output, &meshes, &view.view_proj)?;
             }
         } else {
-            self.renderer.encode_frame(
-                &mut encoder,
-                width,
-                height,
-                &view.view_proj,
-                &inv_view_proj,
-                &meshes,
+            let frame_view = FrameView {
+                view_proj: &view.view_proj,
+                inv_view_proj: &inv_view_proj,
+                camera_pos,
                 directional_light,
-                &view.point_lights,
-                &view.spot_lights,
-                light_view_proj.as_ref(),
-            )?;
+                point_lights: &view.point_lights,
+                spot_lights: &view.spot_lights,
+                light_view_proj: light_view_proj.as_ref(),
+            };
+            self.renderer.encode_frame(&mut encoder, width, height, &meshes, &frame_view)?;
             if let Some(sv) = swapchain_view {
-                self.renderer.encode_present_to(&mut encoder, sv)?;
+                self.renderer.encode_present_with(&mut encoder, sv, view.tone_mapping, view.exposure)?;
             }
         }
         let cmd = encoder.finish();
-        self.renderer.submit([cmd]);
+        self.last_submission = Some(self.renderer.submit([cmd]));
+        self.renderer.frame_submitted()?;
+        if let Some(started) = started {
+            log(
+                LogLevel::Debug,
+                "lumelite",
+                format_args!("frame of {} meshes encoded and submitted in {:.2?} (CPU)", meshes.len(), started.elapsed()),
+            );
+        }
         Ok(())
     }
 }
+
+/// Copy the first `row` bytes of every `padded_row`-byte row of a texture-to-buffer copy.
+fn unpad_rows(data: &[u8], padded_row: usize, row: usize) -> Vec<u8> {
+    data.chunks_exact(padded_row).flat_map(|r| &r[..row]).copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use lumelite_renderer::test_support;
+
+    /// Device and queue of the default adapter, or `None` after reporting `test` as skipped when there
+    /// is none or it is GL (see `test_support::skip_gl`).
+    fn gpu(test: &str) -> Option<(wgpu::Device, wgpu::Queue)> {
+        let adapter = test_support::adapter(test)?;
+        (!test_support::skip_gl(&adapter, test)).then(|| test_support::device(&adapter))
+    }
+
+    #[test]
+    fn ortho_maps_near_and_far_to_zero_and_one() {
+        let m = ortho(-1.0, 1.0, -1.0, 1.0, 0.5, 40.0);
+        // Right-handed: the view looks down -z. Clip z = m[10] * z + m[14], w stays 1.
+        let depth = |z: f32| m[10] * z + m[14];
+        assert!(depth(-0.5).abs() < 1e-6);
+        assert!((depth(-40.0) - 1.0).abs() < 1e-6);
+        assert!((depth(-20.25) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sphere_override_maps_into_mesh_space() {
+        // Scale 2 on x, then translate by (10, 0, 0).
+        let transform = [2.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 10.0, 0.0, 0.0, 1.0];
+        let sphere = Sphere { center: [12.0, 1.0, 0.0], radius: 2.0 };
+        let bounds = sphere_mesh_bounds(&sphere, &transform).unwrap();
+        let expected = Aabb { min: [0.0, -1.0, -2.0], max: [2.0, 3.0, 2.0] };
+        for i in 0..3 {
+            assert!((bounds.min[i] - expected.min[i]).abs() < 1e-5 && (bounds.max[i] - expected.max[i]).abs() < 1e-5, "{:?}", bounds);
+        }
+        assert!(sphere_mesh_bounds(&sphere, &[0.0; 16]).is_none());
+    }
+
+    #[test]
+    fn fitted_textures_are_cached_while_used() {
+        let limit = TextureSizeLimit { max_dimension: 2, downscale: true };
+        let texels = vec![200u8; 4 * 4 * 4];
+        let mut fitted = FittedTextures::default();
+        assert_eq!(fitted.fit("t", 4, 4, &texels, limit).unwrap().0, 2);
+        assert_eq!(fitted.fit("t", 4, 4, &texels, limit).unwrap().0, 2);
+        assert_eq!(fitted.entries.len(), 1);
+        // Textures that fit, or are too short, are not cached.
+        assert!(fitted.fit("t", 2, 2, &texels[..16], limit).is_ok());
+        assert!(fitted.fit("t", 2, 2, &texels[..15], limit).is_err());
+        assert!(fitted.fit("t", 4, 4, &texels[..60], limit).is_err());
+        assert_eq!(fitted.entries.len(), 2);
+        fitted.retain_used();
+        assert_eq!(fitted.entries.len(), 2);
+        fitted.retain_used();
+        assert!(fitted.entries.is_empty());
+    }
+
+    #[test]
+    fn unpad_rows_drops_row_padding() {
+        let data = [1, 2, 3, 0, 0, 4, 5, 6, 0, 0];
+        assert_eq!(unpad_rows(&data, 5, 3), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn frame_done_callback_fires_after_wait() {
+        let Some((device, queue)) = gpu("frame_done_callback_fires_after_wait") else {
+            return;
+        };
+        // Direct-triangle mode without a swapchain submits an empty frame, which every backend can run.
+        let config = LumeliteConfig { debug_direct_triangle: true, ..Default::default() };
+        let mut plugin = LumelitePlugin::new_with_config(device, queue, config).unwrap();
+        plugin.wait_frame_done();
+
+        plugin.render_frame(&ExtractedView::default()).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&done);
+        plugin.on_frame_done(move || flag.store(true, Ordering::SeqCst));
+        plugin.wait_frame_done();
+        assert!(done.load(Ordering::SeqCst));
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn render_frame_to_rgba8_reads_back_the_frame() {
+        let Some((device, queue)) = gpu("render_frame_to_rgba8_reads_back_the_frame") else {
+            return;
+        };
+        // Direct-triangle mode draws flat 0.6 gray over black; a non-sRGB target keeps that exact.
+        let config = LumeliteConfig {
+            debug_direct_triangle: true,
+            swapchain_format: wgpu::TextureFormat::Rgba8Unorm,
+            ..Default::default()
+        };
+        let mut plugin = LumelitePlugin::new_with_config(device, queue, config).unwrap();
+        // Left half of the view; 70 pixels wide so each row is padded for the copy.
+        let corners = [[-1.0f32, -1.0], [0.0, -1.0], [0.0, 1.0], [-1.0, 1.0]];
+        let vertex_data = corners
+            .iter()
+            .flat_map(|c| [c[0], c[1], 0.5, 0.0, 0.0, 1.0, 0.0, 0.0])
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        let quad = ExtractedMesh {
+            vertex_data,
+            index_data: [0u32, 1, 2, 0, 2, 3].iter().flat_map(|i| i.to_le_bytes()).collect(),
+            ..Default::default()
+        };
+        let mut extracted = ExtractedMeshes::default();
+        extracted.meshes.insert(0, quad);
+        plugin.prepare(&extracted);
+        let identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        let view = ExtractedView { view_proj: identity, viewport_size: (70, 3), ..Default::default() };
+        let pixels = plugin.render_frame_to_rgba8(&view).unwrap();
+        assert_eq!(pixels.len(), 70 * 3 * 4);
+        for (i, texel) in pixels.chunks_exact(4).enumerate() {
+            let expected = if i % 70 < 35 { [153, 153, 153, 255] } else { [0, 0, 0, 255] };
+            assert_eq!(texel, expected, "pixel ({}, {})", i % 70, i / 70);
+        }
+        assert!(plugin.render_frame_to_rgba8(&ExtractedView { viewport_size: (0, 3), ..view }).is_err());
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn identical_meshes_share_one_geometry() {
+        let Some((device, queue)) = gpu("identical_meshes_share_one_geometry") else {
+            return;
+        };
+        let mut plugin = LumelitePlugin::new(device, queue).unwrap();
+        let triangle = ExtractedMesh {
+            vertex_data: vec![0; 3 * 32],
+            index_data: [0u32, 1, 2].iter().flat_map(|i| i.to_le_bytes()).collect(),
+            geometry_revision: 1,
+            ..Default::default()
+        };
+        let mut extracted = ExtractedMeshes::default();
+        for id in 0..100u64 {
+            let mut transform = triangle.transform;
+            transform[12] = id as f32;
+            extracted.meshes.insert(id, ExtractedMesh { entity_id: id, transform, ..triangle.clone() });
+        }
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.geometry_count(), 1);
+        let first = &plugin.mesh_cache[&0];
+        assert!(plugin.mesh_cache.values().all(|c| Arc::ptr_eq(&c.geometry, &first.geometry)));
+        assert_eq!(plugin.mesh_cache[&7].transform[12], 7.0);
+
+        // Changing one entity's geometry splits it off once its revision says so; the rest keep sharing.
+        let reversed: Vec<u8> = [2u32, 1, 0].iter().flat_map(|i| i.to_le_bytes()).collect();
+        extracted.meshes.get_mut(&7).unwrap().index_data = reversed.clone();
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.geometry_count(), 1);
+        extracted.meshes.get_mut(&7).unwrap().geometry_revision = 2;
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.geometry_count(), 2);
+        // A new revision with geometry another entity already has joins it.
+        let eight = extracted.meshes.get_mut(&8).unwrap();
+        eight.index_data = reversed;
+        eight.geometry_revision = 2;
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.geometry_count(), 2);
+        assert!(Arc::ptr_eq(&plugin.mesh_cache[&7].geometry, &plugin.mesh_cache[&8].geometry));
+        extracted.meshes.retain(|&id, _| id != 7 && id != 8);
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.geometry_count(), 1);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn untracked_geometry_edited_in_place_is_reuploaded() {
+        let Some((device, queue)) = gpu("untracked_geometry_edited_in_place_is_reuploaded") else {
+            return;
+        };
+        let mut plugin = LumelitePlugin::new(device, queue).unwrap();
+        let triangle = ExtractedMesh {
+            vertex_data: vec![0; 3 * 32],
+            index_data: [0u32, 1, 2].iter().flat_map(|i| i.to_le_bytes()).collect(),
+            ..Default::default()
+        };
+        let mut extracted = ExtractedMeshes::default();
+        extracted.meshes.insert(0, triangle);
+        plugin.prepare(&extracted);
+        let before = plugin.mesh_cache[&0].geometry_key;
+        // Same length, same (default) revision: still picked up.
+        extracted.meshes.get_mut(&0).unwrap().index_data = [2u32, 1, 0].iter().flat_map(|i| i.to_le_bytes()).collect();
+        plugin.prepare(&extracted);
+        let after = plugin.mesh_cache[&0].geometry_key;
+        assert_ne!(before, after);
+        assert_eq!(after, upload::geometry_key(&extracted.meshes[&0]));
+        // A tracked revision that did not move is still re-read when the lengths change.
+        extracted.meshes.get_mut(&0).unwrap().geometry_revision = 5;
+        plugin.prepare(&extracted);
+        extracted.meshes.get_mut(&0).unwrap().index_data.extend([0u32, 2, 1].iter().flat_map(|i| i.to_le_bytes()));
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.mesh_cache[&0].geometry.index_count, 6);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn meshes_without_a_whole_vertex_are_skipped() {
+        let Some((device, queue)) = gpu("meshes_without_a_whole_vertex_are_skipped") else {
+            return;
+        };
+        let mut plugin = LumelitePlugin::new(device, queue).unwrap();
+        let triangle = ExtractedMesh {
+            vertex_format: render_api::VertexFormat::PositionNormalTangentUv,
+            vertex_data: vec![0; 3 * 48],
+            index_data: [0u32, 1, 2].iter().flat_map(|i| i.to_le_bytes()).collect(),
+            ..Default::default()
+        };
+        let mut extracted = ExtractedMeshes::default();
+        extracted.meshes.insert(0, triangle);
+        plugin.prepare(&extracted);
+        assert_eq!(plugin.geometry_count(), 1);
+        // 40 bytes of 48-byte vertices convert to nothing: no zero-size buffer, and the old one is dropped.
+        extracted.meshes.get_mut(&0).unwrap().vertex_data.truncate(40);
+        plugin.prepare(&extracted);
+        assert!(plugin.mesh_cache.is_empty());
+        assert_eq!(plugin.geometry_count(), 0);
+    }
+}
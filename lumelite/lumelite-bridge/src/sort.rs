@@ -0,0 +1,51 @@
+//! Per-frame draw ordering by distance from the camera: opaque meshes front-to-back so early depth
+//! testing rejects hidden fragments.
+
+use lumelite_renderer::MeshDraw;
+
+/// Sort key of a mesh: squared distance from `camera_pos` to the world-space center of its bounds
+/// (its origin when it has none).
+pub fn view_distance_sq(mesh: &MeshDraw, camera_pos: [f32; 3]) -> f32 {
+    let m = &mesh.transform;
+    let local: [f32; 3] = mesh.bounds.map_or([0.0; 3], |b| std::array::from_fn(|i| 0.5 * (b.min[i] + b.max[i])));
+    let center: [f32; 3] = std::array::from_fn(|r| m[r] * local[0] + m[4 + r] * local[1] + m[8 + r] * local[2] + m[12 + r]);
+    (0..3).map(|i| (center[i] - camera_pos[i]).powi(2)).sum()
+}
+
+/// Order `items` nearest first, keyed by `distance_sq` (computed once per item).
+pub fn sort_front_to_back<T>(items: &mut [T], distance_sq: impl Fn(&T) -> f32) {
+    items.sort_by_cached_key(|item| OrderedDistance(distance_sq(item)));
+}
+
+/// Total order over distances (NaN sorts last).
+#[derive(PartialEq)]
+struct OrderedDistance(f32);
+
+impl Eq for OrderedDistance {}
+
+impl PartialOrd for OrderedDistance {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedDistance {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opaque_sorts_near_first() {
+        let camera = [0.0, 0.0, 5.0];
+        let distance_sq = |z: &f32| (z - camera[2]).powi(2);
+        // Overlapping quads along the view axis, one behind the camera.
+        let mut opaque = [-3.0f32, 4.0, 0.0, 7.0, -1.0];
+        sort_front_to_back(&mut opaque, distance_sq);
+        assert_eq!(opaque, [4.0, 7.0, 0.0, -1.0, -3.0]);
+    }
+}
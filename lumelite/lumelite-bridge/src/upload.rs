@@ -0,0 +1,462 @@
+//! Mesh upload for `LumelitePlugin::prepare`: new meshes get buffers mapped at creation, and the
+//! vertex conversion + copy writes straight into those mappings. Everything is flushed with the
+//! frame's first submit.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use lumelite_renderer::{compute_aabb, Aabb};
+use render_api::{ExtractedMesh, VertexFormat};
+
+/// Whether `mesh`'s vertices are 48 bytes with uv at 40 (tangents or colors between normal and uv).
+fn is_wide_format(mesh: &ExtractedMesh) -> bool {
+    matches!(mesh.vertex_format, VertexFormat::PositionNormalTangentUv | VertexFormat::PositionNormalColorUv)
+}
+
+/// Byte length of `mesh`'s vertices after [`write_vertex_data_32`]; 0 for wide-format data shorter
+/// than one vertex.
+pub(crate) fn vertex_len_32(mesh: &ExtractedMesh) -> usize {
+    let len = mesh.vertex_data.len();
+    if is_wide_format(mesh) {
+        len / 48 * 32
+    } else if !len.is_multiple_of(32) && len.is_multiple_of(24) {
+        len / 24 * 32
+    } else {
+        len
+    }
+}
+
+/// Write vertices as 32-byte stride (position+normal+uv) into `out` (at least [`vertex_len_32`] bytes).
+/// Pads 24-byte vertices to 32, drops tangents from PositionNormalTangentUv (see [`write_tangents`])
+/// and colors from PositionNormalColorUv (see [`write_vertex_colors`]); anything else is copied as is.
+pub(crate) fn write_vertex_data_32(mesh: &ExtractedMesh, out: &mut [u8]) {
+    let v = &mesh.vertex_data;
+    if is_wide_format(mesh) {
+        for (src, dst) in v.chunks_exact(48).zip(out.chunks_exact_mut(32)) {
+            dst[0..24].copy_from_slice(&src[0..24]);
+            dst[24..32].copy_from_slice(&src[40..48]);
+        }
+    } else if !v.len().is_multiple_of(32) && v.len().is_multiple_of(24) {
+        for (src, dst) in v.chunks_exact(24).zip(out.chunks_exact_mut(32)) {
+            dst[0..24].copy_from_slice(src);
+            dst[24..32].fill(0);
+        }
+    } else {
+        out[..v.len()].copy_from_slice(v);
+    }
+}
+
+/// Vertices of `mesh` as 32-byte stride, for updating an existing buffer with `queue.write_buffer`.
+pub(crate) fn vertex_data_32(mesh: &ExtractedMesh) -> Vec<u8> {
+    let mut out = vec![0u8; vertex_len_32(mesh)];
+    write_vertex_data_32(mesh, &mut out);
+    out
+}
+
+/// Byte length of `mesh`'s vertex color stream (`lumelite_renderer::VERTEX_COLOR_LAYOUT`); 0 when
+/// the format carries no colors.
+pub(crate) fn vertex_color_len(mesh: &ExtractedMesh) -> usize {
+    if mesh.vertex_format == VertexFormat::PositionNormalColorUv {
+        mesh.vertex_data.len() / 48 * 16
+    } else {
+        0
+    }
+}
+
+/// Write the RGBA colors of a PositionNormalColorUv mesh into `out` (at least [`vertex_color_len`] bytes).
+pub(crate) fn write_vertex_colors(mesh: &ExtractedMesh, out: &mut [u8]) {
+    for (src, dst) in mesh.vertex_data.chunks_exact(48).zip(out.chunks_exact_mut(16)) {
+        dst.copy_from_slice(&src[24..40]);
+    }
+}
+
+/// Vertex colors of `mesh` for updating an existing buffer; `None` when it has none.
+pub(crate) fn vertex_colors(mesh: &ExtractedMesh) -> Option<Vec<u8>> {
+    let len = vertex_color_len(mesh);
+    (len > 0).then(|| {
+        let mut out = vec![0u8; len];
+        write_vertex_colors(mesh, &mut out);
+        out
+    })
+}
+
+/// Byte length of `mesh`'s tangent stream (`lumelite_renderer::TANGENT_LAYOUT`); 0 when the format
+/// carries no tangents.
+pub(crate) fn tangent_len(mesh: &ExtractedMesh) -> usize {
+    if mesh.vertex_format == VertexFormat::PositionNormalTangentUv {
+        mesh.vertex_data.len() / 48 * 16
+    } else {
+        0
+    }
+}
+
+/// Write the tangents of a PositionNormalTangentUv mesh into `out` (at least [`tangent_len`] bytes).
+pub(crate) fn write_tangents(mesh: &ExtractedMesh, out: &mut [u8]) {
+    for (src, dst) in mesh.vertex_data.chunks_exact(48).zip(out.chunks_exact_mut(16)) {
+        dst.copy_from_slice(&src[24..40]);
+    }
+}
+
+/// Tangents of `mesh` for updating an existing buffer; `None` when it has none.
+pub(crate) fn tangents(mesh: &ExtractedMesh) -> Option<Vec<u8>> {
+    let len = tangent_len(mesh);
+    (len > 0).then(|| {
+        let mut out = vec![0u8; len];
+        write_tangents(mesh, &mut out);
+        out
+    })
+}
+
+/// Mesh-space bounds of `mesh`'s positions for GPU culling, reading vertices with the same stride
+/// detection as [`write_vertex_data_32`].
+pub(crate) fn mesh_bounds(mesh: &ExtractedMesh) -> Option<Aabb> {
+    let len = mesh.vertex_data.len();
+    let stride = if is_wide_format(mesh) {
+        48
+    } else if !len.is_multiple_of(32) && len.is_multiple_of(24) {
+        24
+    } else {
+        32
+    };
+    compute_aabb(&mesh.vertex_data, stride, 0).ok().flatten()
+}
+
+/// Content hash of a mesh's source geometry (vertex format and bytes, indices, LODs), so entities with
+/// identical geometry share one set of GPU buffers. Computed on every prepare for untracked meshes
+/// (revision 0), otherwise once when an entity's `ExtractedMesh::geometry_revision` changes; at 128 bits plus the lengths it is trusted without
+/// comparing bytes, so no CPU copy of uploaded geometry is kept. Meshes with a bounds override get
+/// their own geometry, as its bounds are never computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct GeometryKey {
+    hash: [u64; 2],
+    vertex_len: usize,
+    index_len: usize,
+    bounds_override: bool,
+}
+
+pub(crate) fn geometry_key(mesh: &ExtractedMesh) -> GeometryKey {
+    // Two independently seeded 64-bit hashes make up the 128.
+    let hash = |seed: u64| {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        std::mem::discriminant(&mesh.vertex_format).hash(&mut hasher);
+        mesh.vertex_data.hash(&mut hasher);
+        mesh.index_data.hash(&mut hasher);
+        for lod in &mesh.lods {
+            lod.index_data.hash(&mut hasher);
+            lod.screen_size.to_bits().hash(&mut hasher);
+        }
+        hasher.finish()
+    };
+    GeometryKey {
+        hash: [hash(0), hash(1)],
+        vertex_len: mesh.vertex_data.len(),
+        index_len: mesh.index_data.len(),
+        bounds_override: mesh.bounds_override.is_some(),
+    }
+}
+
+impl GeometryKey {
+    /// Whether the geometry was keyed for a mesh with a bounds override.
+    pub(crate) fn bounds_override(&self) -> bool {
+        self.bounds_override
+    }
+}
+
+/// Identity of an RGBA8 texture's texels, for caching work done on it across frames. A hit only
+/// names a candidate: callers compare the texels they kept before trusting it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct TextureKey {
+    hash: u64,
+    width: u32,
+    height: u32,
+}
+
+pub(crate) fn texture_key(width: u32, height: u32, data: &[u8]) -> TextureKey {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    TextureKey { hash: hasher.finish(), width, height }
+}
+
+/// Error unless `data` holds all `width`x`height` RGBA8 texels.
+pub(crate) fn check_texture_len(width: u32, height: u32, data: &[u8]) -> Result<(), String> {
+    let expected = width as u64 * height as u64 * 4;
+    if (data.len() as u64) < expected {
+        return Err(format!("texture data is {} bytes, a {}x{} RGBA8 texture needs {}", data.len(), width, height, expected));
+    }
+    Ok(())
+}
+
+/// An RGBA8 image that fits within `max_dimension` per side: `Ok(None)` when it already does,
+/// otherwise downscaled by the smallest integer factor that makes it fit (box filter; partial blocks
+/// at the right and bottom edges average the texels they have), or an error when `downscale` is off
+/// or `data` is shorter than the size says.
+pub(crate) fn fit_texture(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    max_dimension: u32,
+    downscale: bool,
+) -> Result<Option<(u32, u32, Vec<u8>)>, String> {
+    check_texture_len(width, height, data)?;
+    if width <= max_dimension && height <= max_dimension {
+        return Ok(None);
+    }
+    if !downscale || max_dimension == 0 {
+        return Err(format!(
+            "texture is {}x{}, larger than the max texture dimension {}",
+            width, height, max_dimension
+        ));
+    }
+    let factor = width.max(height).div_ceil(max_dimension);
+    let (out_width, out_height) = (width.div_ceil(factor), height.div_ceil(factor));
+    let mut out = Vec::with_capacity((out_width * out_height * 4) as usize);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let mut sum = [0u32; 4];
+            let mut count = 0;
+            for sy in y * factor..((y + 1) * factor).min(height) {
+                for sx in x * factor..((x + 1) * factor).min(width) {
+                    let i = ((sy * width + sx) * 4) as usize;
+                    for c in 0..4 {
+                        sum[c] += data[i + c] as u32;
+                    }
+                    count += 1;
+                }
+            }
+            out.extend(sum.map(|s| ((s + count / 2) / count) as u8));
+        }
+    }
+    Ok(Some((out_width, out_height, out)))
+}
+
+/// A new mesh whose buffers are still mapped; [`fill_uploads`] writes and unmaps them.
+pub(crate) struct MeshUpload<'a> {
+    pub mesh: &'a ExtractedMesh,
+    pub vertex_buf: wgpu::Buffer,
+    pub index_buf: wgpu::Buffer,
+    /// Set when the mesh has vertex colors.
+    pub color_buf: Option<wgpu::Buffer>,
+    /// Set when the mesh has tangents.
+    pub tangent_buf: Option<wgpu::Buffer>,
+}
+
+impl<'a> MeshUpload<'a> {
+    pub(crate) fn new(device: &wgpu::Device, mesh: &'a ExtractedMesh) -> Self {
+        Self::with_usage(device, mesh, wgpu::BufferUsages::empty())
+    }
+
+    /// Like [`Self::new`], with `extra` usages on every buffer (e.g. COPY_SRC to read them back).
+    fn with_usage(device: &wgpu::Device, mesh: &'a ExtractedMesh, extra: wgpu::BufferUsages) -> Self {
+        // Mapped-at-creation sizes must be a multiple of 4; the padding is never read.
+        let create = |label, len: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (len as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+                usage: usage | extra,
+                mapped_at_creation: true,
+            })
+        };
+        Self {
+            mesh,
+            vertex_buf: create("lumelite_mesh_vertex", vertex_len_32(mesh), wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST),
+            index_buf: create("lumelite_mesh_index", mesh.index_data.len(), wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST),
+            color_buf: match vertex_color_len(mesh) {
+                0 => None,
+                len => Some(create("lumelite_mesh_color", len, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST)),
+            },
+            tangent_buf: match tangent_len(mesh) {
+                0 => None,
+                len => Some(create("lumelite_mesh_tangent", len, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST)),
+            },
+        }
+    }
+
+    fn fill(&self) {
+        {
+            let mut vertices = self.vertex_buf.slice(..).get_mapped_range_mut();
+            write_vertex_data_32(self.mesh, &mut vertices);
+            let mut indices = self.index_buf.slice(..).get_mapped_range_mut();
+            indices[..self.mesh.index_data.len()].copy_from_slice(&self.mesh.index_data);
+            if let Some(color_buf) = &self.color_buf {
+                write_vertex_colors(self.mesh, &mut color_buf.slice(..).get_mapped_range_mut());
+            }
+            if let Some(tangent_buf) = &self.tangent_buf {
+                write_tangents(self.mesh, &mut tangent_buf.slice(..).get_mapped_range_mut());
+            }
+        }
+        self.vertex_buf.unmap();
+        self.index_buf.unmap();
+        if let Some(color_buf) = &self.color_buf {
+            color_buf.unmap();
+        }
+        if let Some(tangent_buf) = &self.tangent_buf {
+            tangent_buf.unmap();
+        }
+    }
+}
+
+/// Fill and unmap all uploads.
+pub(crate) fn fill_uploads(uploads: &[MeshUpload]) {
+    uploads.iter().for_each(MeshUpload::fill);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use render_api::{MeshLod, Sphere};
+
+    fn mesh(vertex_format: VertexFormat, vertex_data: Vec<u8>) -> ExtractedMesh {
+        ExtractedMesh { vertex_data, vertex_format, ..Default::default() }
+    }
+
+    #[test]
+    fn vertex_conversion_sizes_match_output() {
+        let tangent = mesh(VertexFormat::PositionNormalTangentUv, (0..96).collect());
+        let out = vertex_data_32(&tangent);
+        assert_eq!(out.len(), 64);
+        assert_eq!(&out[24..32], &tangent.vertex_data[40..48]);
+        assert_eq!(&out[56..64], &tangent.vertex_data[88..96]);
+        let stream = tangents(&tangent).unwrap();
+        assert_eq!(stream.len(), 32);
+        assert_eq!(&stream[..16], &tangent.vertex_data[24..40]);
+        assert_eq!(&stream[16..], &tangent.vertex_data[72..88]);
+        assert_eq!(vertex_colors(&tangent), None);
+
+        let colored = mesh(VertexFormat::PositionNormalColorUv, (0..96).collect());
+        assert_eq!(vertex_data_32(&colored), out);
+        let colors = vertex_colors(&colored).unwrap();
+        assert_eq!(colors.len(), 32);
+        assert_eq!(&colors[..16], &colored.vertex_data[24..40]);
+        assert_eq!(&colors[16..], &colored.vertex_data[72..88]);
+
+        let padded = vertex_data_32(&mesh(VertexFormat::PositionNormalUv, vec![1; 72]));
+        assert_eq!(padded.len(), 96);
+        assert_eq!(&padded[24..32], &[0; 8]);
+        assert_eq!(&padded[32..56], &[1; 24]);
+
+        let as_is = mesh(VertexFormat::PositionNormalUv, (0..64).collect());
+        assert_eq!(vertex_data_32(&as_is), as_is.vertex_data);
+        assert_eq!(tangents(&as_is), None);
+    }
+
+    #[test]
+    fn bounds_follow_source_stride() {
+        let vertices = |stride: usize, positions: &[[f32; 3]]| -> Vec<u8> {
+            positions
+                .iter()
+                .flat_map(|p| {
+                    let mut v = vec![0u8; stride];
+                    for (dst, c) in v.chunks_exact_mut(4).zip(p) {
+                        dst.copy_from_slice(&c.to_le_bytes());
+                    }
+                    v
+                })
+                .collect()
+        };
+        let positions = [[1.0, -2.0, 3.0], [-1.0, 4.0, 0.5], [0.0, 0.0, -6.0]];
+        let expected = Aabb { min: [-1.0, -2.0, -6.0], max: [1.0, 4.0, 3.0] };
+        for (format, stride) in [
+            (VertexFormat::PositionNormalUv, 32),
+            (VertexFormat::PositionNormal, 24),
+            (VertexFormat::PositionNormalTangentUv, 48),
+            (VertexFormat::PositionNormalColorUv, 48),
+        ] {
+            assert_eq!(mesh_bounds(&mesh(format, vertices(stride, &positions))), Some(expected), "{:?}", format);
+        }
+        assert_eq!(mesh_bounds(&mesh(VertexFormat::PositionNormalUv, Vec::new())), None);
+    }
+
+    #[test]
+    fn geometry_key_ignores_transform() {
+        let a = ExtractedMesh { transform: [2.0; 16], ..mesh(VertexFormat::PositionNormalUv, (0..64).collect()) };
+        let b = mesh(VertexFormat::PositionNormalUv, (0..64).collect());
+        assert_eq!(geometry_key(&a), geometry_key(&b));
+        let mut c = b.clone();
+        c.vertex_data[5] = 0;
+        assert_ne!(geometry_key(&b), geometry_key(&c));
+        let d = ExtractedMesh { lods: vec![MeshLod { index_data: vec![0; 12], screen_size: 0.5 }], ..b.clone() };
+        assert_ne!(geometry_key(&b), geometry_key(&d));
+        let e = ExtractedMesh { bounds_override: Some(Sphere { center: [0.0; 3], radius: 1.0 }), ..b.clone() };
+        assert_ne!(geometry_key(&b), geometry_key(&e));
+        let f = ExtractedMesh { vertex_format: VertexFormat::PositionNormalTangentUv, ..b.clone() };
+        assert_ne!(geometry_key(&b), geometry_key(&f));
+    }
+
+    /// Buffer contents of `uploads` after filling, copied back through readback buffers.
+    fn read_uploads(device: &wgpu::Device, queue: &wgpu::Queue, uploads: &[MeshUpload]) -> Vec<Vec<u8>> {
+        let buffers: Vec<&wgpu::Buffer> = uploads
+            .iter()
+            .flat_map(|u| [Some(&u.vertex_buf), Some(&u.index_buf), u.color_buf.as_ref(), u.tangent_buf.as_ref()])
+            .flatten()
+            .collect();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let readbacks: Vec<wgpu::Buffer> = buffers
+            .iter()
+            .map(|buffer| {
+                let readback = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    size: buffer.size(),
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, buffer.size());
+                readback
+            })
+            .collect();
+        queue.submit([encoder.finish()]);
+        for readback in &readbacks {
+            readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        }
+        device.poll(wgpu::Maintain::Wait);
+        readbacks.iter().map(|r| r.slice(..).get_mapped_range().to_vec()).collect()
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn mapped_fills_write_the_converted_data() {
+        let Some(adapter) = lumelite_renderer::test_support::adapter("mapped_fills_write_the_converted_data") else {
+            return;
+        };
+        let (device, queue) = lumelite_renderer::test_support::device(&adapter);
+        let bytes = |n: usize, seed: usize| (0..n).map(|i| (i * 7 + seed) as u8).collect::<Vec<u8>>();
+        let formats = [
+            VertexFormat::PositionNormalUv,
+            VertexFormat::PositionNormal,
+            VertexFormat::PositionNormalTangentUv,
+            VertexFormat::PositionNormalColorUv,
+        ];
+        // Index data of 6 bytes exercises the padding to a multiple of 4.
+        let meshes: Vec<ExtractedMesh> = (0..4)
+            .map(|i| ExtractedMesh { index_data: bytes(6 * (i + 1), i), ..mesh(formats[i], bytes(96 * (i + 1), i)) })
+            .collect();
+        let uploads: Vec<MeshUpload> =
+            meshes.iter().map(|m| MeshUpload::with_usage(&device, m, wgpu::BufferUsages::COPY_SRC)).collect();
+        fill_uploads(&uploads);
+        let filled = read_uploads(&device, &queue, &uploads);
+        let expected: Vec<Vec<u8>> = meshes
+            .iter()
+            .flat_map(|m| [Some(vertex_data_32(m)), Some(m.index_data.clone()), vertex_colors(m), tangents(m)])
+            .flatten()
+            .collect();
+        assert_eq!(filled.len(), expected.len());
+        for (filled, expected) in filled.iter().zip(&expected) {
+            assert_eq!(&filled[..expected.len()], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn oversized_textures_downscale_or_fail() {
+        let texels: Vec<u8> = (0..5 * 3).flat_map(|i| [i as u8 * 10, 0, 0, 255]).collect();
+        assert_eq!(fit_texture(5, 3, &texels, 5, true), Ok(None));
+        assert!(fit_texture(5, 3, &texels, 4, false).unwrap_err().contains("5x3"));
+        // Factor 2: 3x2 texels; the last column and row average the partial blocks.
+        let (w, h, out) = fit_texture(5, 3, &texels, 4, true).unwrap().unwrap();
+        assert_eq!((w, h), (3, 2));
+        let red: Vec<u8> = out.chunks_exact(4).map(|t| t[0]).collect();
+        assert_eq!(red, vec![30, 50, 65, 105, 125, 140]);
+        assert!(out.chunks_exact(4).all(|t| t[3] == 255));
+        // Short data is rejected whether or not the texture needs fitting.
+        assert!(fit_texture(5, 3, &texels[..56], 5, true).unwrap_err().contains("56 bytes"));
+        assert!(fit_texture(5, 3, &texels[..56], 4, true).unwrap_err().contains("56 bytes"));
+    }
+}
@@ -1,11 +1,70 @@
 //! Window-capable backend: created from a window, implements RenderBackendWindow.
 
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
-use render_api::{ExtractedMeshes, ExtractedView, RenderBackend, RenderBackendWindow};
+use render_api::{log, ExtractedMeshes, ExtractedView, LogLevel, RenderBackend, RenderBackendWindow, SwapchainInfo};
 use wgpu::SurfaceTargetUnsafe;
 
 use crate::plugin::LumelitePlugin;
-use lumelite_renderer::LumeliteConfig;
+use lumelite_renderer::{is_hdr_format, AdapterCapabilities, LumeliteConfig};
+
+/// Device requirements and renderer settings of [`LumeliteWindowBackend::from_window_with_options`]. Lumelite itself needs no
+/// optional features and the default limits; hosts add what their own passes need (e.g.
+/// `POLYGON_MODE_LINE`).
+#[derive(Clone, Debug, Default)]
+pub struct LumeliteBackendOptions {
+    /// Features requested at device creation; every one must be supported by the adapter.
+    pub required_features: wgpu::Features,
+    /// Limits requested at device creation; every one must be within the adapter's limits.
+    pub required_limits: wgpu::Limits,
+    /// Renderer settings; `swapchain_format` is replaced by the format of the views presented to
+    /// (see [`present_format`]) of the surface format chosen with [`select_surface_format`] (HDR when
+    /// `hdr_output` is set and the surface offers it), and `adapter_capabilities` by the adapter's.
+    pub config: LumeliteConfig,
+}
+
+impl LumeliteBackendOptions {
+    /// Check the requested features and limits against an adapter's, naming everything it lacks.
+    pub fn validate(&self, adapter_features: wgpu::Features, adapter_limits: &wgpu::Limits) -> Result<(), String> {
+        let mut problems = Vec::new();
+        let missing = self.required_features - adapter_features;
+        if !missing.is_empty() {
+            problems.push(format!("features {:?}", missing));
+        }
+        self.required_limits.check_limits_with_fail_fn(adapter_limits, false, |name, requested, allowed| {
+            problems.push(format!("{} = {} (adapter allows {})", name, requested, allowed));
+        });
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("adapter does not support the requested {}", problems.join(", ")))
+        }
+    }
+}
+
+/// Pick the swapchain format from a surface's `formats` (preferred first): the first HDR format when
+/// `hdr` is set and one is offered, otherwise the first sRGB SDR format, then the first SDR format, so
+/// a float format the surface happens to prefer is never used without being asked for.
+pub fn select_surface_format(formats: &[wgpu::TextureFormat], hdr: bool) -> wgpu::TextureFormat {
+    let hdr_format = formats.iter().copied().find(|&f| is_hdr_format(f));
+    let srgb_format = formats.iter().copied().find(|&f| !is_hdr_format(f) && f.is_srgb());
+    let sdr_format = formats.iter().copied().find(|&f| !is_hdr_format(f));
+    match (hdr, hdr_format, srgb_format.or(sdr_format)) {
+        (true, Some(f), _) => f,
+        (_, _, Some(f)) => f,
+        _ => formats.first().copied().unwrap_or(wgpu::TextureFormat::Rgba8Unorm),
+    }
+}
+
+/// Format of the views present writes to on a `surface_format` surface: its sRGB variant for SDR
+/// formats, so the GPU encodes present's linear output on store, and the format itself otherwise
+/// (HDR formats are linear).
+pub fn present_format(surface_format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+    if is_hdr_format(surface_format) {
+        surface_format
+    } else {
+        surface_format.add_srgb_suffix()
+    }
+}
 
 /// Backend that owns wgpu Instance and LumelitePlugin; can present to a window.
 /// Created via `LumeliteWindowBackend::from_window(window)`; each frame use
@@ -15,6 +74,10 @@ use lumelite_renderer::LumeliteConfig;
 pub struct LumeliteWindowBackend {
     instance: wgpu::Instance,
     plugin: LumelitePlugin,
+    /// Format the surface is configured with; views of it use `LumeliteConfig::swapchain_format`.
+    surface_format: wgpu::TextureFormat,
+    /// Surface configuration of the last presented frame.
+    surface_config: Option<wgpu::SurfaceConfiguration>,
 }
 
 impl LumeliteWindowBackend {
@@ -24,19 +87,29 @@ impl LumeliteWindowBackend {
     /// `render_frame_to_window`.
     pub fn from_window(
         window: &(impl HasWindowHandle + HasDisplayHandle),
+    ) -> Result<Box<dyn RenderBackendWindow>, String> {
+        Self::from_window_with_options(window, &LumeliteBackendOptions::default())
+    }
+
+    /// Like [`Self::from_window`], requesting the device with `options`' features and limits. Fails with
+    /// a list of what is missing when the adapter cannot provide them.
+    pub fn from_window_with_options(
+        window: &(impl HasWindowHandle + HasDisplayHandle),
+        options: &LumeliteBackendOptions,
     ) -> Result<Box<dyn RenderBackendWindow>, String> {
         let (raw_window, raw_display) = {
             let wh = window.window_handle().map_err(|e| e.to_string())?;
             let dh = window.display_handle().map_err(|e| e.to_string())?;
             (wh.as_raw(), dh.as_raw())
         };
-        let backend = pollster::block_on(Self::from_raw_handles_async(raw_window, raw_display))?;
+        let backend = pollster::block_on(Self::from_raw_handles_async(raw_window, raw_display, options))?;
         Ok(Box::new(backend))
     }
 
     async fn from_raw_handles_async(
         raw_window_handle: raw_window_handle::RawWindowHandle,
         raw_display_handle: raw_window_handle::RawDisplayHandle,
+        options: &LumeliteBackendOptions,
     ) -> Result<Self, String> {
         let instance = wgpu::Instance::default();
         let target = SurfaceTargetUnsafe::RawHandle {
@@ -52,23 +125,31 @@ impl LumeliteWindowBackend {
             })
             .await
             .ok_or("No adapter")?;
+        options.validate(adapter.features(), &adapter.limits())?;
+        let descriptor = wgpu::DeviceDescriptor {
+            required_features: options.required_features,
+            required_limits: options.required_limits.clone(),
+            ..Default::default()
+        };
         let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default(), None)
+            .request_device(&descriptor, None)
             .await
             .map_err(|e| e.to_string())?;
         let caps = surface.get_capabilities(&adapter);
-        let format = caps
-            .formats
-            .first()
-            .copied()
-            .unwrap_or(wgpu::TextureFormat::Rgba8Unorm);
+        let format = select_surface_format(&caps.formats, options.config.hdr_output);
+        if options.config.hdr_output && !is_hdr_format(format) {
+            log(LogLevel::Info, "lumelite::window", format_args!("no HDR surface format in {:?}, presenting SDR", caps.formats));
+        }
         let config = LumeliteConfig {
-            swapchain_format: format,
-            ..LumeliteConfig::default()
+            swapchain_format: present_format(format),
+            adapter_capabilities: AdapterCapabilities::from_adapter(&adapter),
+            ..options.config.clone()
         };
-        let plugin = LumelitePlugin::new_with_config(device, queue, config)?;
+        let mut plugin = LumelitePlugin::new_with_config(device, queue, config)?;
+        // Pay driver shader compilation here rather than on the first presented frame.
+        plugin.prewarm()?;
         drop(surface);
-        Ok(Self { instance, plugin })
+        Ok(Self { instance, plugin, surface_format: format, surface_config: None })
     }
 
     fn surface_config(format: wgpu::TextureFormat, width: u32, height: u32) -> wgpu::SurfaceConfiguration {
@@ -79,10 +160,29 @@ impl LumeliteWindowBackend {
             height,
             present_mode: wgpu::PresentMode::Fifo,
             alpha_mode: wgpu::CompositeAlphaMode::Opaque,
-            view_formats: vec![],
+            view_formats: if present_format(format) == format { vec![] } else { vec![present_format(format)] },
             desired_maximum_frame_latency: 2,
         }
     }
+
+    pub fn swapchain_format(&self) -> wgpu::TextureFormat {
+        self.plugin.swapchain_format()
+    }
+
+    /// Present mode of the last presented frame; `None` before the first one.
+    pub fn present_mode(&self) -> Option<wgpu::PresentMode> {
+        self.surface_config.as_ref().map(|c| c.present_mode)
+    }
+}
+
+fn swapchain_info(config: &wgpu::SurfaceConfiguration) -> SwapchainInfo {
+    SwapchainInfo {
+        size: (config.width, config.height),
+        format: format!("{:?}", config.format),
+        hdr: is_hdr_format(config.format),
+        vsync: matches!(config.present_mode, wgpu::PresentMode::Fifo | wgpu::PresentMode::FifoRelaxed | wgpu::PresentMode::AutoVsync),
+        max_frame_latency: config.desired_maximum_frame_latency,
+    }
 }
 
 impl RenderBackend for LumeliteWindowBackend {
@@ -93,6 +193,10 @@ impl RenderBackend for LumeliteWindowBackend {
     fn render_frame(&mut self, view: &ExtractedView) -> Result<(), String> {
         self.plugin.render_frame(view)
     }
+
+    fn render_resolution(&self) -> Option<(u32, u32)> {
+        self.plugin.render_resolution()
+    }
 }
 
 impl RenderBackendWindow for LumeliteWindowBackend {
@@ -112,11 +216,7 @@ impl RenderBackendWindow for LumeliteWindowBackend {
                 .map_err(|e| e.to_string())?
         };
         let (width, height) = view.viewport_size;
-        let config = Self::surface_config(
-            self.plugin.renderer().config().swapchain_format,
-            width.max(1),
-            height.max(1),
-        );
+        let config = Self::surface_config(self.surface_format, width.max(1), height.max(1));
         surface.configure(self.plugin.device(), &config);
 
         let frame = match surface.get_current_texture() {
@@ -134,13 +234,68 @@ impl RenderBackendWindow for LumeliteWindowBackend {
         };
         let swapchain_format = self.plugin.renderer().config().swapchain_format;
         let viewport = frame.texture.create_view(&wgpu::TextureViewDescriptor {
-            format: Some(swapchain_format.add_srgb_suffix()),
+            format: Some(swapchain_format),
             ..Default::default()
         });
         self.plugin
             .render_frame_to_swapchain(view, &viewport)
             .map_err(|e| e.to_string())?;
         frame.present();
+        self.surface_config = Some(config);
         Ok(())
     }
+
+    fn swapchain_info(&self) -> Option<SwapchainInfo> {
+        self.surface_config.as_ref().map(swapchain_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_names_missing_features_and_limits() {
+        let adapter_limits = wgpu::Limits::downlevel_defaults();
+        assert!(LumeliteBackendOptions::default().validate(wgpu::Features::empty(), &wgpu::Limits::default()).is_ok());
+        let options = LumeliteBackendOptions {
+            required_features: wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::MULTI_DRAW_INDIRECT,
+            required_limits: wgpu::Limits { max_texture_dimension_2d: 16384, ..adapter_limits.clone() },
+            ..Default::default()
+        };
+        assert!(options.validate(wgpu::Features::all(), &wgpu::Limits { max_texture_dimension_2d: 16384, ..adapter_limits.clone() }).is_ok());
+        let err = options.validate(wgpu::Features::POLYGON_MODE_LINE, &adapter_limits).unwrap_err();
+        assert!(err.contains("MULTI_DRAW_INDIRECT") && !err.contains("POLYGON_MODE_LINE"), "{}", err);
+        assert!(err.contains("max_texture_dimension_2d = 16384"), "{}", err);
+    }
+
+    #[test]
+    fn swapchain_info_describes_the_surface_config() {
+        let info = swapchain_info(&LumeliteWindowBackend::surface_config(wgpu::TextureFormat::Rgba16Float, 640, 360));
+        assert_eq!(
+            info,
+            SwapchainInfo { size: (640, 360), format: "Rgba16Float".to_string(), hdr: true, vsync: true, max_frame_latency: 2 }
+        );
+    }
+
+    #[test]
+    fn surface_format_prefers_hdr_only_when_asked() {
+        use wgpu::TextureFormat::{Bgra8UnormSrgb, Rgba16Float, Rgba8Unorm};
+        let formats = [Rgba16Float, Bgra8UnormSrgb, Rgba8Unorm];
+        assert_eq!(select_surface_format(&formats, false), Bgra8UnormSrgb);
+        assert_eq!(select_surface_format(&formats, true), Rgba16Float);
+        assert_eq!(select_surface_format(&formats[1..], true), Bgra8UnormSrgb);
+        assert_eq!(select_surface_format(&[], true), Rgba8Unorm);
+        assert_eq!(select_surface_format(&[Rgba8Unorm, Bgra8UnormSrgb], false), Bgra8UnormSrgb);
+    }
+
+    #[test]
+    fn sdr_surfaces_present_through_an_srgb_view() {
+        use wgpu::TextureFormat::{Bgra8Unorm, Bgra8UnormSrgb, Rgba16Float};
+        assert_eq!(present_format(Bgra8Unorm), Bgra8UnormSrgb);
+        assert_eq!(present_format(Bgra8UnormSrgb), Bgra8UnormSrgb);
+        assert_eq!(present_format(Rgba16Float), Rgba16Float);
+        assert_eq!(LumeliteWindowBackend::surface_config(Bgra8Unorm, 1, 1).view_formats, vec![Bgra8UnormSrgb]);
+        assert!(LumeliteWindowBackend::surface_config(Bgra8UnormSrgb, 1, 1).view_formats.is_empty());
+    }
 }
@@ -0,0 +1,95 @@
+// Bounds reduction: min/max of vertex positions into a 32-byte AABB (min.xyz, pad, max.xyz, pad).
+// Dispatch order: cs_init (1) -> cs_reduce (N) -> cs_finalize (1). See src/bounds/mod.rs.
+
+struct BoundsParams {
+    vertex_count: u32,
+    stride_words: u32,
+    position_offset_words: u32,
+    _pad: u32,
+}
+
+@group(0) @binding(0) var<storage, read> vertices: array<u32>;
+@group(0) @binding(1) var<uniform> params: BoundsParams;
+// min.xyz, max.xyz in the order-preserving encoding below.
+@group(0) @binding(2) var<storage, read_write> scratch: array<atomic<u32>, 6>;
+@group(0) @binding(3) var<storage, read_write> bounds: array<vec4<f32>, 2>;
+
+const WORKGROUP_SIZE: u32 = 64u;
+
+// Order-preserving float <-> uint mapping so atomicMin/atomicMax can reduce floats.
+fn float_to_ordered(f: f32) -> u32 {
+    let b = bitcast<u32>(f);
+    if (b & 0x80000000u) != 0u { return ~b; }
+    return b | 0x80000000u;
+}
+
+fn ordered_to_float(u: u32) -> f32 {
+    if (u & 0x80000000u) != 0u { return bitcast<f32>(u & 0x7fffffffu); }
+    return bitcast<f32>(~u);
+}
+
+var<workgroup> wg_min: array<atomic<u32>, 3>;
+var<workgroup> wg_max: array<atomic<u32>, 3>;
+
+@compute @workgroup_size(1) fn cs_init() {
+    for (var i = 0u; i < 3u; i++) {
+        atomicStore(&scratch[i], 0xffffffffu);
+        atomicStore(&scratch[i + 3u], 0u);
+    }
+}
+
+@compute @workgroup_size(64) fn cs_reduce(
+    @builtin(global_invocation_id) gid: vec3<u32>,
+    @builtin(local_invocation_index) lid: u32,
+    @builtin(num_workgroups) num_groups: vec3<u32>,
+) {
+    if lid == 0u {
+        for (var c = 0u; c < 3u; c++) {
+            atomicStore(&wg_min[c], 0xffffffffu);
+            atomicStore(&wg_max[c], 0u);
+        }
+    }
+    workgroupBarrier();
+
+    // Grid-stride loop: the dispatch is capped by the device limit, each thread covers several vertices.
+    var lo = vec3<u32>(0xffffffffu);
+    var hi = vec3<u32>(0u);
+    let step = num_groups.x * WORKGROUP_SIZE;
+    for (var i = gid.x; i < params.vertex_count; i += step) {
+        let base = i * params.stride_words + params.position_offset_words;
+        let p = vec3<u32>(
+            float_to_ordered(bitcast<f32>(vertices[base])),
+            float_to_ordered(bitcast<f32>(vertices[base + 1u])),
+            float_to_ordered(bitcast<f32>(vertices[base + 2u])),
+        );
+        lo = min(lo, p);
+        hi = max(hi, p);
+    }
+    for (var c = 0u; c < 3u; c++) {
+        atomicMin(&wg_min[c], lo[c]);
+        atomicMax(&wg_max[c], hi[c]);
+    }
+    workgroupBarrier();
+
+    if lid == 0u {
+        for (var c = 0u; c < 3u; c++) {
+            atomicMin(&scratch[c], atomicLoad(&wg_min[c]));
+            atomicMax(&scratch[c + 3u], atomicLoad(&wg_max[c]));
+        }
+    }
+}
+
+@compute @workgroup_size(1) fn cs_finalize() {
+    bounds[0] = vec4<f32>(
+        ordered_to_float(atomicLoad(&scratch[0])),
+        ordered_to_float(atomicLoad(&scratch[1])),
+        ordered_to_float(atomicLoad(&scratch[2])),
+        0.0,
+    );
+    bounds[1] = vec4<f32>(
+        ordered_to_float(atomicLoad(&scratch[3])),
+        ordered_to_float(atomicLoad(&scratch[4])),
+        ordered_to_float(atomicLoad(&scratch[5])),
+        0.0,
+    );
+}
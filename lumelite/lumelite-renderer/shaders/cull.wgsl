@@ -0,0 +1,147 @@
+// GPU frustum and occlusion culling: one thread per MeshDraw writes its DrawIndexedIndirect args, with
+// instance_count 0 when the world-space AABB is outside the frustum or behind the previous frame's
+// depth (Hi-Z pyramid, see hiz.wgsl). See src/culling/mod.rs.
+//
+// Two-pass occlusion culling runs this twice around the first GBuffer draw: the early phase draws the
+// meshes visible last frame (per `visibility`), the late phase tests the rest against a Hi-Z of that
+// draw, draws the disoccluded ones and records every mesh's visibility for the next frame.
+
+struct DrawInput {
+    model: mat4x4<f32>,
+    bounds_min: vec3<f32>,
+    index_count: u32,
+    bounds_max: vec3<f32>,
+    // 0: mesh has no bounds and is always drawn.
+    has_bounds: u32,
+}
+
+struct CullParams {
+    // left, right, bottom, top, near, far: inside where dot(xyz, p) + w >= 0.
+    planes: array<vec4<f32>, 6>,
+    view_proj: mat4x4<f32>,
+    // View-projection the Hi-Z depth was rendered with; boxes are projected with it to test occlusion.
+    hiz_view_proj: mat4x4<f32>,
+    draw_count: u32,
+    // 0: no Hi-Z pyramid this frame, occlusion is not tested.
+    hiz_levels: u32,
+    hiz_size: vec2<u32>,
+    // 0: single pass, 1: early, 2: late.
+    phase: u32,
+}
+
+// Layout of wgpu's DrawIndexedIndirectArgs.
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<storage, read> draws: array<DrawInput>;
+@group(0) @binding(1) var<uniform> params: CullParams;
+@group(0) @binding(2) var<storage, read_write> args: array<DrawIndexedIndirect>;
+@group(0) @binding(3) var hiz: texture_2d<f32>;
+// Meshes drawn (single pass or early phase), frustum-culled, occluded, and drawn by the late phase,
+// then the triangles drawn by every phase (CullStats).
+@group(0) @binding(4) var<storage, read_write> stats: array<atomic<u32>, 5>;
+const TRIANGLES = 4u;
+// Outcome of a mesh not drawn by this phase and counted by the other.
+const UNCOUNTED = 5u;
+// Two-pass only: 1 where the mesh was visible last frame.
+@group(0) @binding(5) var<storage, read_write> visibility: array<u32>;
+
+// Set with instanced_draws_supported: first_instance is the mesh index, which the GBuffer and unlit
+// shaders read their per-mesh data with. Otherwise it must stay 0.
+override FIRST_INSTANCE: bool = false;
+
+// World-space AABB of the transformed box as center + extent [Arvo 1990], tested against each plane.
+fn aabb_visible(d: DrawInput) -> bool {
+    let center = (d.model * vec4<f32>(0.5 * (d.bounds_min + d.bounds_max), 1.0)).xyz;
+    let abs_model = mat3x3<f32>(abs(d.model[0].xyz), abs(d.model[1].xyz), abs(d.model[2].xyz));
+    let extent = abs_model * (0.5 * (d.bounds_max - d.bounds_min));
+    for (var i = 0u; i < 6u; i++) {
+        let p = params.planes[i];
+        if dot(p.xyz, center) + p.w < -dot(abs(p.xyz), extent) {
+            return false;
+        }
+    }
+    return true;
+}
+
+fn hiz_texel(texel: vec2<u32>, level: u32) -> f32 {
+    let lod = i32(level);
+    return textureLoad(hiz, min(texel, textureDimensions(hiz, lod) - 1u), lod).r;
+}
+
+// Whether the box's nearest depth is behind the farthest depth of the Hi-Z texels under its screen
+// rect. The mip is picked so the rect spans at most 2x2 texels. Boxes reaching behind the near plane
+// are never occluded.
+fn occluded(d: DrawInput) -> bool {
+    var ndc_min = vec3<f32>(1.0e30);
+    var ndc_max = vec2<f32>(-1.0e30);
+    for (var i = 0u; i < 8u; i++) {
+        let corner = select(d.bounds_min, d.bounds_max, vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u));
+        let clip = params.hiz_view_proj * d.model * vec4<f32>(corner, 1.0);
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        ndc_min = min(ndc_min, ndc);
+        ndc_max = max(ndc_max, ndc.xy);
+    }
+    if ndc_min.z < 0.0 {
+        return false;
+    }
+    // NDC y points up, texel rows down.
+    let size = vec2<f32>(params.hiz_size);
+    let rect_min = clamp((vec2<f32>(ndc_min.x, -ndc_max.y) * 0.5 + 0.5) * size, vec2<f32>(0.0), size - 1.0);
+    let rect_max = clamp((vec2<f32>(ndc_max.x, -ndc_min.y) * 0.5 + 0.5) * size, vec2<f32>(0.0), size - 1.0);
+    let extent = max(rect_max.x - rect_min.x, rect_max.y - rect_min.y);
+    let level = min(u32(ceil(log2(max(extent, 1.0)))), params.hiz_levels - 1u);
+    let lo = vec2<u32>(rect_min) >> vec2<u32>(level);
+    let hi = vec2<u32>(rect_max) >> vec2<u32>(level);
+    let farthest = max(
+        max(hiz_texel(lo, level), hiz_texel(vec2<u32>(hi.x, lo.y), level)),
+        max(hiz_texel(vec2<u32>(lo.x, hi.y), level), hiz_texel(hi, level)),
+    );
+    return ndc_min.z > farthest;
+}
+
+@compute @workgroup_size(64) fn cs_cull(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let i = gid.x;
+    if i >= params.draw_count {
+        return;
+    }
+    let d = draws[i];
+    // Index into stats, or UNCOUNTED.
+    var outcome = 0u;
+    if d.has_bounds == 0u {
+        // Always drawn, by the first phase.
+        outcome = select(0u, UNCOUNTED, params.phase == 2u);
+    } else if !aabb_visible(d) {
+        outcome = select(1u, UNCOUNTED, params.phase == 2u);
+        if params.phase == 1u {
+            visibility[i] = 0u;
+        }
+    } else if params.phase == 1u {
+        outcome = select(UNCOUNTED, 0u, visibility[i] != 0u);
+    } else {
+        let hidden = params.hiz_levels != 0u && occluded(d);
+        if params.phase == 2u {
+            let drawn_early = visibility[i] != 0u;
+            visibility[i] = select(1u, 0u, hidden);
+            outcome = select(select(3u, 2u, hidden), UNCOUNTED, drawn_early);
+        } else {
+            outcome = select(0u, 2u, hidden);
+        }
+    }
+    if outcome < UNCOUNTED {
+        atomicAdd(&stats[outcome], 1u);
+    }
+    let drawn = outcome == 0u || outcome == 3u;
+    if drawn {
+        atomicAdd(&stats[TRIANGLES], d.index_count / 3u);
+    }
+    args[i] = DrawIndexedIndirect(d.index_count, select(0u, 1u, drawn), 0u, 0, select(0u, i, FIRST_INSTANCE));
+}
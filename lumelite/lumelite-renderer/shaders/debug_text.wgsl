@@ -0,0 +1,31 @@
+// Debug text: instanced screen-space quads textured from the 5x7 bitmap font atlas (see
+// debug_text/mod.rs). Each quad carries its rect in NDC, the atlas texels it covers and a color;
+// rectangles use a zero-size texel rect inside the solid cell.
+
+struct Quad {
+    @location(0) rect: vec4<f32>,
+    @location(1) texels: vec4<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) texel: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var atlas: texture_2d<f32>;
+
+@vertex fn vs(@builtin(vertex_index) vertex_index: u32, quad: Quad) -> VertexOutput {
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(quad.rect.xy + corner * quad.rect.zw, 0.0, 1.0);
+    out.texel = mix(quad.texels.xy, quad.texels.zw, corner);
+    out.color = quad.color;
+    return out;
+}
+
+@fragment fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureLoad(atlas, vec2<i32>(floor(in.texel)), 0).r;
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -1,9 +1,17 @@
-// Flax-style PBR GBuffer: position+normal+uv (stride 32), sample base_color, normal, metallic_roughness, ao.
+// Flax-style PBR GBuffer: position+normal+uv (stride 32) plus vertex color and tangent streams, sample
+// base_color, normal, metallic_roughness, ao.
+// Packing into the targets lives in gbuffer_pack.wgsl, the per-mesh data binding in MeshData::wgsl, and
+// FragmentOutput with write_gbuffer (one output per target of the layout) in GBufferLayout::output_wgsl;
+// all are prepended at load. Clear values: see GBufferPacking in src/gbuffer/mod.rs.
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
     @location(2) uv: vec2<f32>,
+    // Linear RGBA from the second vertex stream; only meaningful when mesh.vertex_color is set.
+    @location(3) color: vec4<f32>,
+    // Mesh-space tangent, bitangent sign in w, from the third stream; only meaningful when mesh.tangents is set.
+    @location(4) tangent: vec4<f32>,
 }
 
 struct VertexOutput {
@@ -11,10 +19,30 @@ struct VertexOutput {
     @location(0) world_normal: vec3<f32>,
     @location(1) uv: vec2<f32>,
     @location(2) world_pos: vec3<f32>,
+    @location(3) color: vec4<f32>,
+    // World-space tangent and bitangent sign; zero without a tangent stream.
+    @location(4) world_tangent: vec4<f32>,
+    // Clip position under view_proj and previous_view_proj, for the velocity target.
+    @location(5) clip: vec4<f32>,
+    @location(6) previous_clip: vec4<f32>,
 }
 
 @group(0) @binding(0) var<uniform> view_proj: mat4x4<f32>;
-@group(0) @binding(1) var<uniform> model: mat4x4<f32>;
+struct MeshUniform {
+    model: mat4x4<f32>,
+    // Added to the light buffer by the unlit pass (UnlitPass::encode); unused here.
+    emissive: vec3<f32>,
+    // 1 when the mesh has vertex colors; otherwise the color stream aliases the vertex buffer and is ignored.
+    vertex_color: u32,
+    // 1 when the mesh has tangents; otherwise the tangent stream aliases the vertex buffer and is ignored.
+    tangents: u32,
+}
+// Binding 0/1 and `fn mesh_data(instance_index) -> MeshUniform` are prepended (MeshData::wgsl).
+// World-space plane (normal.xyz, d) for fs_clipped; see GBufferPass::encode.
+@group(0) @binding(2) var<uniform> clip_plane: vec4<f32>;
+// Last frame's view_proj (camera motion only: model is the same for both); equal to view_proj when
+// there is no previous frame. Only written with a velocity target.
+@group(0) @binding(3) var<uniform> previous_view_proj: mat4x4<f32>;
 
 @group(1) @binding(0) var base_color_tex: texture_2d<f32>;
 @group(1) @binding(1) var normal_tex: texture_2d<f32>;
@@ -22,20 +50,28 @@ struct VertexOutput {
 @group(1) @binding(3) var ao_tex: texture_2d<f32>;
 @group(1) @binding(4) var tex_sampler: sampler;
 
-@vertex fn vs(in: VertexInput) -> VertexOutput {
+// Set per pipeline from the mesh's ShaderVariant: Unlit writes the unlit shading model.
+override UNLIT: bool = false;
+
+@vertex fn vs(in: VertexInput, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let mesh = mesh_data(instance);
     var out: VertexOutput;
-    let world_pos = (model * vec4<f32>(in.position, 1.0)).xyz;
+    let world_pos = (mesh.model * vec4<f32>(in.position, 1.0)).xyz;
     out.clip_position = view_proj * vec4<f32>(world_pos, 1.0);
-    out.world_normal = (model * vec4<f32>(in.normal, 0.0)).xyz;
+    out.world_normal = (mesh.model * vec4<f32>(in.normal, 0.0)).xyz;
+    out.color = select(vec4<f32>(1.0), in.color, mesh.vertex_color != 0u);
+    // A mirroring model flips the bitangent along with the winding.
+    let model3 = mat3x3<f32>(mesh.model[0].xyz, mesh.model[1].xyz, mesh.model[2].xyz);
+    let handedness = select(1.0, -1.0, in.tangent.w < 0.0) * select(1.0, -1.0, determinant(model3) < 0.0);
+    let world_tangent = vec4<f32>(model3 * in.tangent.xyz, handedness);
+    out.world_tangent = select(vec4<f32>(0.0), world_tangent, mesh.tangents != 0u);
     out.uv = in.uv;
     out.world_pos = world_pos;
+    out.clip = out.clip_position;
+    out.previous_clip = previous_view_proj * vec4<f32>(world_pos, 1.0);
     return out;
 }
 
-fn encode_normal(n: vec3<f32>) -> vec3<f32> {
-    return n * 0.5 + 0.5;
-}
-
 // Unpack tangent-space normal from RGBA; z from xy
 fn unpack_normal_ts(enc: vec3<f32>) -> vec3<f32> {
     let n = enc * 2.0 - 1.0;
@@ -43,7 +79,8 @@ fn unpack_normal_ts(enc: vec3<f32>) -> vec3<f32> {
     return vec3<f32>(n.xy, z);
 }
 
-// TBN from world_normal and uv derivatives (simplified: use world_normal as basis for flat shading when normal map is neutral)
+// Fallback tangent for meshes without a tangent stream: any vector perpendicular to the normal, so only
+// normal maps without a fixed orientation shade correctly.
 fn tangent_from_world_normal(world_normal: vec3<f32>) -> vec3<f32> {
     let n = normalize(world_normal);
     if abs(n.z) < 0.999 {
@@ -52,31 +89,63 @@ fn tangent_from_world_normal(world_normal: vec3<f32>) -> vec3<f32> {
     return normalize(cross(vec3<f32>(0.0, 1.0, 0.0), n));
 }
 
-struct FragmentOutput {
-    @location(0) gbuffer0: vec4<f32>,
-    @location(1) gbuffer1: vec4<f32>,
-    @location(2) gbuffer2: vec4<f32>,
-    @location(3) gbuffer3: vec4<f32>,
+// Texture-space (uv) motion since the previous frame: current uv - previous uv.
+fn uv_motion(in: VertexOutput) -> vec2<f32> {
+    let ndc_motion = in.clip.xy / in.clip.w - in.previous_clip.xy / in.previous_clip.w;
+    return ndc_motion * vec2<f32>(0.5, -0.5);
 }
 
-@fragment fn fs(in: VertexOutput) -> FragmentOutput {
-    var out: FragmentOutput;
-    let base_color = textureSample(base_color_tex, tex_sampler, in.uv).rgb;
+// `normal_sign` is -1 on the back faces of double-sided meshes, turning the normal toward the viewer.
+fn shade_gbuffer(in: VertexOutput, normal_sign: f32) -> GBufferData {
+    let base_color = textureSample(base_color_tex, tex_sampler, in.uv).rgb * in.color.rgb;
     let ao_val = textureSample(ao_tex, tex_sampler, in.uv).r;
     let mr = textureSample(metallic_roughness_tex, tex_sampler, in.uv);
     let roughness = max(mr.g, 0.04);
     let metalness = mr.r;
-    let specular_val = 0.5;
 
     let n_ts = unpack_normal_ts(textureSample(normal_tex, tex_sampler, in.uv).rgb);
-    let tangent = tangent_from_world_normal(in.world_normal);
-    let bitangent = cross(in.world_normal, tangent);
-    let tbn = mat3x3<f32>(tangent, bitangent, normalize(in.world_normal));
+    let geometric_normal = in.world_normal * normal_sign;
+    let n = normalize(geometric_normal);
+    var tangent = tangent_from_world_normal(geometric_normal);
+    var bitangent = cross(n, tangent);
+    if any(in.world_tangent.xyz != vec3<f32>(0.0)) {
+        // Gram-Schmidt against the interpolated normal. w flips the bitangent for mirrored UVs, and
+        // normal_sign undoes the flip the back face's normal gives it.
+        tangent = normalize(in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz));
+        bitangent = cross(n, tangent) * select(1.0, -1.0, in.world_tangent.w < 0.0) * normal_sign;
+    }
+    let tbn = mat3x3<f32>(tangent, bitangent, n);
     let world_normal = normalize(tbn * n_ts);
 
-    out.gbuffer0 = vec4<f32>(base_color, ao_val);
-    out.gbuffer1 = vec4<f32>(encode_normal(world_normal), 1.0 / 3.0);
-    out.gbuffer2 = vec4<f32>(roughness, metalness, specular_val, 0.0);
-    out.gbuffer3 = vec4<f32>(0.0, 0.0, 0.0, 0.0);
-    return out;
+    if UNLIT {
+        // Lights skip unlit surfaces and UnlitPass draws their color later; only depth and the shading
+        // model matter here.
+        return GBufferData(base_color, 1.0, normalize(geometric_normal), 1.0, 0.0, DEFAULT_SPECULAR, SHADING_MODEL_UNLIT);
+    }
+    return GBufferData(base_color, ao_val, world_normal, roughness, metalness, DEFAULT_SPECULAR, SHADING_MODEL_LIT);
+}
+
+// Planar reflections: drop everything on the negative side of the clip plane.
+fn apply_clip_plane(world_pos: vec3<f32>) {
+    if dot(clip_plane.xyz, world_pos) + clip_plane.w < 0.0 {
+        discard;
+    }
+}
+
+@fragment fn fs(in: VertexOutput) -> FragmentOutput {
+    return write_gbuffer(shade_gbuffer(in, 1.0), in);
+}
+
+@fragment fn fs_clipped(in: VertexOutput) -> FragmentOutput {
+    apply_clip_plane(in.world_pos);
+    return write_gbuffer(shade_gbuffer(in, 1.0), in);
+}
+
+@fragment fn fs_double_sided(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> FragmentOutput {
+    return write_gbuffer(shade_gbuffer(in, select(-1.0, 1.0, front_facing)), in);
+}
+
+@fragment fn fs_double_sided_clipped(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> FragmentOutput {
+    apply_clip_plane(in.world_pos);
+    return write_gbuffer(shade_gbuffer(in, select(-1.0, 1.0, front_facing)), in);
 }
@@ -0,0 +1,77 @@
+// GBuffer packing shared by gbuffer.wgsl (pack) and lights.wgsl (unpack); prepended to both at load.
+// Layouts and formats: see GBufferLayout in src/gbuffer/mod.rs.
+
+// Surface attributes stored in the GBuffer, independent of the layout.
+struct GBufferData {
+    base_color: vec3<f32>,
+    ao: f32,
+    // World space, normalized.
+    normal: vec3<f32>,
+    roughness: f32,
+    metalness: f32,
+    specular: f32,
+    // Shading model id / 3, as stored in gbuffer1.a of the full layout.
+    shading_model: f32,
+}
+
+// Shading model ids (Flax numbering): lights skip unlit surfaces, which UnlitPass draws afterwards.
+const SHADING_MODEL_UNLIT: f32 = 0.0;
+const SHADING_MODEL_LIT: f32 = 1.0 / 3.0;
+// Specular of every surface written by gbuffer.wgsl; the compact layout does not store it.
+const DEFAULT_SPECULAR: f32 = 0.5;
+
+fn is_unlit(d: GBufferData) -> bool {
+    return d.shading_model < SHADING_MODEL_LIT * 0.5;
+}
+
+// Octahedral normal encoding [Cigolle et al. 2014]: unit vector -> [0, 1]^2.
+fn encode_octahedral(n: vec3<f32>) -> vec2<f32> {
+    let p = n.xy / (abs(n.x) + abs(n.y) + abs(n.z));
+    let sign_p = select(vec2<f32>(-1.0), vec2<f32>(1.0), p >= vec2<f32>(0.0));
+    let folded = (1.0 - abs(p.yx)) * sign_p;
+    return select(folded, p, n.z >= 0.0) * 0.5 + 0.5;
+}
+
+fn decode_octahedral(enc: vec2<f32>) -> vec3<f32> {
+    let e = enc * 2.0 - 1.0;
+    let n = vec3<f32>(e, 1.0 - abs(e.x) - abs(e.y));
+    let t = max(-n.z, 0.0);
+    let sign_n = select(vec2<f32>(1.0), vec2<f32>(-1.0), n.xy >= vec2<f32>(0.0));
+    return normalize(vec3<f32>(n.xy + sign_n * t, n.z));
+}
+
+// Full layout, 4 x Rgba8Unorm:
+//   0: rgb = base color, a = ambient occlusion
+//   1: rgb = normal * 0.5 + 0.5, a = shading model id
+//   2: r = roughness, g = metalness, b = specular
+//   3: custom data (unused)
+fn pack_gbuffer_full(d: GBufferData) -> array<vec4<f32>, 4> {
+    return array<vec4<f32>, 4>(
+        vec4<f32>(d.base_color, d.ao),
+        vec4<f32>(d.normal * 0.5 + 0.5, d.shading_model),
+        vec4<f32>(d.roughness, d.metalness, d.specular, 0.0),
+        vec4<f32>(0.0),
+    );
+}
+
+fn unpack_gbuffer_full(g0: vec4<f32>, g1: vec4<f32>, g2: vec4<f32>) -> GBufferData {
+    return GBufferData(g0.rgb, g0.a, normalize(g1.rgb * 2.0 - 1.0), g2.r, g2.g, g2.b, g1.a);
+}
+
+// Compact layout, Rgba8Unorm + Rgb10a2Unorm (half the bytes per pixel):
+//   0: rgb = base color, a = ambient occlusion
+//   1: rg = octahedral normal, b = roughness, a = metalness (2 bits: 0, 1/3, 2/3, 1)
+// Specular is always DEFAULT_SPECULAR. There is no shading model channel: roughness 0 marks unlit
+// surfaces (lit ones are written with roughness >= 0.04).
+fn pack_gbuffer_compact(d: GBufferData) -> array<vec4<f32>, 2> {
+    let roughness = select(d.roughness, 0.0, is_unlit(d));
+    return array<vec4<f32>, 2>(
+        vec4<f32>(d.base_color, d.ao),
+        vec4<f32>(encode_octahedral(d.normal), roughness, d.metalness),
+    );
+}
+
+fn unpack_gbuffer_compact(g0: vec4<f32>, g1: vec4<f32>) -> GBufferData {
+    let shading_model = select(SHADING_MODEL_LIT, SHADING_MODEL_UNLIT, g1.b < 0.5 / 1023.0);
+    return GBufferData(g0.rgb, g0.a, decode_octahedral(g1.rg), g1.b, g1.a, DEFAULT_SPECULAR, shading_model);
+}
@@ -0,0 +1,68 @@
+// Editor ground grid on the world y = 0 plane: each pixel's view ray is intersected with the plane,
+// lines are anti-aliased with the screen-space derivative of the plane coordinate and fade with
+// distance. Writes the hit depth so the depth test against the scene hides the grid behind geometry.
+
+struct VertexOutput { @builtin(position) clip_position: vec4<f32>, @location(0) ndc: vec2<f32> }
+
+@vertex fn vs(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32((vertex_index << 1u) & 2u);
+    let y = f32(vertex_index & 2u);
+    out.ndc = vec2<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0);
+    out.clip_position = vec4<f32>(out.ndc, 0.0, 1.0);
+    return out;
+}
+
+// See GridPass::encode.
+struct GridUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    camera_pos: vec4<f32>,
+    // rgb + opacity.
+    color: vec4<f32>,
+    // rgb + lines per major line (0: none).
+    major_color: vec4<f32>,
+    // spacing, fade distance.
+    params: vec4<f32>,
+}
+@group(0) @binding(0) var<uniform> grid: GridUniform;
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// 1 on a line of unit spacing in `coord`, falling to 0 one pixel away.
+fn line_coverage(coord: vec2<f32>) -> f32 {
+    let width = max(fwidth(coord), vec2<f32>(1e-6));
+    let distance = abs(fract(coord - 0.5) - 0.5) / width;
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+@fragment fn fs(in: VertexOutput) -> FragmentOutput {
+    let near = grid.inv_view_proj * vec4<f32>(in.ndc, 0.0, 1.0);
+    let far = grid.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let origin = near.xyz / near.w;
+    let ray = far.xyz / far.w - origin;
+    // Rays parallel to the plane or hitting it behind the camera / past the far plane miss.
+    let denom = select(ray.y, 1e-6, abs(ray.y) < 1e-6);
+    let t = -origin.y / denom;
+    let hit = origin + ray * t;
+    let clip = grid.view_proj * vec4<f32>(hit, 1.0);
+
+    // Derivatives before any discard, while every pixel of the quad is still running.
+    let coord = hit.xz / grid.params.x;
+    let minor = line_coverage(coord);
+    let major_every = grid.major_color.w;
+    let major = select(0.0, line_coverage(coord / max(major_every, 1.0)), major_every > 0.0);
+
+    let fade = clamp(1.0 - length(hit - grid.camera_pos.xyz) / grid.params.y, 0.0, 1.0);
+    let alpha = max(minor, major) * grid.color.a * fade * fade;
+    if abs(ray.y) < 1e-6 || t <= 0.0 || t > 1.0 || alpha <= 0.0 {
+        discard;
+    }
+    var out: FragmentOutput;
+    out.color = vec4<f32>(select(grid.color.rgb, grid.major_color.rgb, major >= minor && major > 0.0), alpha);
+    out.depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    return out;
+}
@@ -0,0 +1,32 @@
+// Hi-Z pyramid for occlusion culling: mip 0 is a copy of the depth buffer, every further mip holds the
+// max (farthest) depth of the texels it covers. Mip sizes halve rounding down, so the last texel of an
+// odd-sized row or column also takes the texel left over. See src/culling/hiz.rs.
+
+@group(0) @binding(0) var depth: texture_depth_2d;
+@group(0) @binding(1) var dst: texture_storage_2d<r32float, write>;
+@group(0) @binding(2) var src: texture_2d<f32>;
+
+@compute @workgroup_size(8, 8) fn cs_copy_depth(@builtin(global_invocation_id) gid: vec3<u32>) {
+    if any(gid.xy >= textureDimensions(dst)) {
+        return;
+    }
+    textureStore(dst, gid.xy, vec4<f32>(textureLoad(depth, gid.xy, 0), 0.0, 0.0, 0.0));
+}
+
+@compute @workgroup_size(8, 8) fn cs_downsample(@builtin(global_invocation_id) gid: vec3<u32>) {
+    let dst_size = textureDimensions(dst);
+    if any(gid.xy >= dst_size) {
+        return;
+    }
+    let src_size = textureDimensions(src);
+    let last = (src_size & vec2<u32>(1u)) == vec2<u32>(1u) & gid.xy == dst_size - 1u;
+    let span = select(vec2<u32>(2u), vec2<u32>(3u), last);
+    var farthest = 0.0;
+    for (var y = 0u; y < span.y; y++) {
+        for (var x = 0u; x < span.x; x++) {
+            let texel = min(gid.xy * 2u + vec2<u32>(x, y), src_size - 1u);
+            farthest = max(farthest, textureLoad(src, texel, 0).r);
+        }
+    }
+    textureStore(dst, gid.xy, vec4<f32>(farthest, 0.0, 0.0, 0.0));
+}
@@ -7,9 +7,8 @@ struct VertexOutput { @builtin(position) clip_position: vec4<f32>, @location(0)
     out.clip_position = vec4<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0, 1.0);
     return out;
 }
-@group(0) @binding(0) var gbuffer0: texture_2d<f32>;
-@group(0) @binding(1) var gbuffer1: texture_2d<f32>;
-@group(0) @binding(2) var gbuffer2: texture_2d<f32>;
+// GBuffer layout: see gbuffer_pack.wgsl. The bindings 0.. of the targets the packing has lights read and
+// `load_gbuffer` come from GBufferPacking::load_wgsl; both are prepended at load.
 @group(0) @binding(3) var depth_tex: texture_depth_2d;
 @group(0) @binding(4) var gbuffer_sampler: sampler;
 struct LightUniform {
@@ -18,10 +17,14 @@ struct LightUniform {
     color: vec3<f32>,
     _pad1: f32,
     inv_view_proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    _pad2: f32,
 }
 @group(0) @binding(5) var<uniform> light: LightUniform;
 
-fn decode_normal(enc: vec3<f32>) -> vec3<f32> { return normalize(enc * 2.0 - 1.0); }
+// Set per pipeline from LumeliteConfig::reverse_z: the far plane (background) is at depth 0.
+override REVERSE_Z: bool = false;
+
 const PI: f32 = 3.14159265359;
 
 // ——— Flax BRDF (Source/Shaders/BRDF.hlsl, Lighting.hlsl, GBufferCommon.hlsl) ———
@@ -62,29 +65,33 @@ fn GetSpecularColor(color: vec3<f32>, specular: f32, metalness: f32) -> vec3<f32
     return mix(vec3<f32>(dielectric_f0, dielectric_f0, dielectric_f0), color, vec3<f32>(metalness, metalness, metalness));
 }
 
-@fragment fn fs_directional(in: VertexOutput) -> @location(0) vec4<f32> {
-    let g0 = textureSample(gbuffer0, gbuffer_sampler, in.uv);
-    let g1 = textureSample(gbuffer1, gbuffer_sampler, in.uv);
-    let g2 = textureSample(gbuffer2, gbuffer_sampler, in.uv);
+fn load_scene_depth(uv: vec2<f32>) -> f32 {
     let dims = vec2<f32>(textureDimensions(depth_tex));
-    let pix = vec2<i32>(min(floor(in.uv * dims), dims - vec2<f32>(1.0, 1.0)));
-    let depth_val = textureLoad(depth_tex, pix, 0);
-    if depth_val >= 1.0 { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let pix = vec2<i32>(min(floor(uv * dims), dims - vec2<f32>(1.0, 1.0)));
+    return textureLoad(depth_tex, pix, 0);
+}
 
-    let n = decode_normal(g1.rgb);
-    let roughness = max(g2.r, 0.04);
-    let metalness = g2.g;
-    let specular_val = g2.b;
-    let base_color = g0.rgb;
-    let ao = g0.a;
+// No geometry: the depth buffer still holds the far-plane clear value.
+fn is_background(depth_val: f32) -> bool {
+    return select(depth_val >= 1.0, depth_val <= 0.0, REVERSE_Z);
+}
+
+// Directional light shading; `visibility` scales the result (1 = unshadowed).
+fn shade_directional(uv: vec2<f32>, depth_val: f32, visibility: f32) -> vec4<f32> {
+    let g = load_gbuffer(uv);
+    if is_unlit(g) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let n = g.normal;
+    let roughness = max(g.roughness, 0.04);
+    let metalness = g.metalness;
+    let specular_val = g.specular;
+    let base_color = g.base_color;
+    let ao = g.ao;
 
     // Reconstruct world position from depth and NDC
-    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth_val, 1.0);
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth_val, 1.0);
     let world_h = light.inv_view_proj * ndc;
     let world_pos = world_h.xyz / world_h.w;
-    let cam_col = light.inv_view_proj * vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    let camera_pos = cam_col.xyz / cam_col.w;
-    let v = normalize(camera_pos - world_pos);
+    let v = normalize(light.camera_pos - world_pos);
 
     // direction = where light shines (from light toward scene); l = toward light (from surface)
     let l = -normalize(light.direction);
@@ -108,7 +115,124 @@ fn GetSpecularColor(color: vec3<f32>, specular: f32, metalness: f32) -> vec3<f32
     let F = F_Schlick(specular_color, v_dot_h);
     lit += (D * Vis) * F * light.color * n_dot_l;
 
-    return vec4<f32>(lit, 1.0);
+    return vec4<f32>(lit * visibility, 1.0);
+}
+
+@fragment fn fs_directional(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    return shade_directional(in.uv, depth_val, 1.0);
+}
+
+// Shadowed directional variant: only bound when shadows are enabled (see LightPass::encode_directional).
+@group(0) @binding(6) var shadow_map: texture_depth_2d;
+@group(0) @binding(7) var shadow_sampler: sampler_comparison;
+struct DirectionalShadowUniform {
+    light_view_proj: mat4x4<f32>,
+    depth_bias: f32,
+    texel_size: f32,
+    // Penumbra radius (shadow-map UV) per unit of light-space depth; 0 = fixed 3x3 PCF.
+    penumbra_scale: f32,
+    _pad: f32,
+}
+@group(0) @binding(8) var<uniform> shadow: DirectionalShadowUniform;
+
+const SOFT_SHADOW_TAPS: u32 = 16u;
+// Caps the disk so wide suns don't smear the whole map; in texels.
+const MAX_PENUMBRA_TEXELS: f32 = 24.0;
+
+// Tap i of a Vogel (golden-angle spiral) disk of unit radius, rotated by `angle`.
+fn vogel_disk(i: u32, angle: f32) -> vec2<f32> {
+    let r = sqrt((f32(i) + 0.5) / f32(SOFT_SHADOW_TAPS));
+    let theta = f32(i) * 2.39996323 + angle;
+    return r * vec2<f32>(cos(theta), sin(theta));
+}
+
+// Per-pixel rotation so the disk's banding turns into fine noise (interleaved gradient noise).
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+// Sun-sized light: average the depth of occluders around the receiver, size the filter from the
+// receiver/blocker distance, then PCF over a rotated disk. 1 = lit, 0 = fully occluded.
+fn soft_shadow_visibility(shadow_uv: vec2<f32>, ref_depth: f32, pixel: vec2<f32>) -> f32 {
+    let angle = interleaved_gradient_noise(pixel) * 6.28318531;
+    let max_radius = MAX_PENUMBRA_TEXELS * shadow.texel_size;
+    let dims = vec2<f32>(textureDimensions(shadow_map));
+    // Blockers can sit anywhere between the light (depth 0) and the receiver.
+    let search_radius = clamp(shadow.penumbra_scale * ref_depth, shadow.texel_size, max_radius);
+    var blocker_sum = 0.0;
+    var blocker_count = 0.0;
+    for (var i = 0u; i < SOFT_SHADOW_TAPS; i++) {
+        let uv = clamp(shadow_uv + vogel_disk(i, angle) * search_radius, vec2<f32>(0.0), vec2<f32>(1.0));
+        let texel = min(vec2<i32>(uv * dims), vec2<i32>(dims) - vec2<i32>(1));
+        let d = textureLoad(shadow_map, texel, 0);
+        if d < ref_depth {
+            blocker_sum += d;
+            blocker_count += 1.0;
+        }
+    }
+    if blocker_count == 0.0 { return 1.0; }
+    let blocker_depth = blocker_sum / blocker_count;
+    let radius = clamp(shadow.penumbra_scale * (ref_depth - blocker_depth), shadow.texel_size, max_radius);
+    var sum = 0.0;
+    for (var i = 0u; i < SOFT_SHADOW_TAPS; i++) {
+        let uv = shadow_uv + vogel_disk(i, angle) * radius;
+        sum += textureSampleCompareLevel(shadow_map, shadow_sampler, uv, ref_depth);
+    }
+    return sum / f32(SOFT_SHADOW_TAPS);
+}
+
+// 1 = lit, 0 = fully occluded. Outside the light frustum counts as lit. Hard-edged 3x3 PCF unless the
+// sun has an angular size (see LumeliteConfig::sun_angular_size).
+fn directional_shadow_visibility(world_pos: vec3<f32>, n: vec3<f32>, pixel: vec2<f32>) -> f32 {
+    let light_clip = shadow.light_view_proj * vec4<f32>(world_pos, 1.0);
+    let light_ndc = light_clip.xyz / light_clip.w;
+    let shadow_uv = vec2<f32>(light_ndc.x * 0.5 + 0.5, 0.5 - light_ndc.y * 0.5);
+    if any(shadow_uv < vec2<f32>(0.0, 0.0)) || any(shadow_uv > vec2<f32>(1.0, 1.0)) || light_ndc.z > 1.0 {
+        return 1.0;
+    }
+    // Slope-scaled bias: grow the bias as the surface turns away from the light to avoid acne.
+    let n_dot_l = clamp(dot(n, -normalize(light.direction)), 0.0, 1.0);
+    let bias = shadow.depth_bias * (1.0 + 4.0 * (1.0 - n_dot_l));
+    let ref_depth = light_ndc.z - bias;
+    if shadow.penumbra_scale > 0.0 {
+        return soft_shadow_visibility(shadow_uv, ref_depth, pixel);
+    }
+    var sum = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            sum += textureSampleCompareLevel(shadow_map, shadow_sampler, shadow_uv + offset, ref_depth);
+        }
+    }
+    return sum / 9.0;
+}
+
+@fragment fn fs_directional_shadowed(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth_val, 1.0);
+    let world_h = light.inv_view_proj * ndc;
+    let world_pos = world_h.xyz / world_h.w;
+    let n = load_gbuffer(in.uv).normal;
+    return shade_directional(in.uv, depth_val, directional_shadow_visibility(world_pos, n, in.clip_position.xy));
+}
+
+// Ambient fill: base pass before the lights (see LumeliteConfig::ambient). Like every light it skips
+// unlit surfaces, which UnlitPass draws afterwards.
+struct AmbientUniform {
+    color: vec3<f32>,
+    _pad0: f32,
+}
+@group(0) @binding(5) var<uniform> ambient: AmbientUniform;
+
+@fragment fn fs_ambient(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let g = load_gbuffer(in.uv);
+    if is_unlit(g) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    return vec4<f32>(g.base_color * g.ao * ambient.color, 1.0);
 }
 
 // Point light: fullscreen, attenuation by distance
@@ -121,6 +245,8 @@ struct PointLightUniform {
     falloff_exponent: f32,
     _pad2: vec2<f32>,
     inv_view_proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    _pad3: f32,
 }
 @group(0) @binding(5) var<uniform> point_light: PointLightUniform;
 
@@ -129,28 +255,21 @@ fn GetRadialLightAttenuation(dist: f32, radius: f32, falloff: f32) -> f32 {
     return pow(t, falloff);
 }
 
-@fragment fn fs_point(in: VertexOutput) -> @location(0) vec4<f32> {
-    let g0 = textureSample(gbuffer0, gbuffer_sampler, in.uv);
-    let g1 = textureSample(gbuffer1, gbuffer_sampler, in.uv);
-    let g2 = textureSample(gbuffer2, gbuffer_sampler, in.uv);
-    let dims = vec2<f32>(textureDimensions(depth_tex));
-    let pix = vec2<i32>(min(floor(in.uv * dims), dims - vec2<f32>(1.0, 1.0)));
-    let depth_val = textureLoad(depth_tex, pix, 0);
-    if depth_val >= 1.0 { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
-
-    let n = decode_normal(g1.rgb);
-    let roughness = max(g2.r, 0.04);
-    let metalness = g2.g;
-    let specular_val = g2.b;
-    let base_color = g0.rgb;
-    let ao = g0.a;
-
-    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth_val, 1.0);
+// Point light shading; `visibility` scales the result (1 = unshadowed).
+fn shade_point(uv: vec2<f32>, depth_val: f32, visibility: f32) -> vec4<f32> {
+    let g = load_gbuffer(uv);
+    if is_unlit(g) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let n = g.normal;
+    let roughness = max(g.roughness, 0.04);
+    let metalness = g.metalness;
+    let specular_val = g.specular;
+    let base_color = g.base_color;
+    let ao = g.ao;
+
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth_val, 1.0);
     let world_h = point_light.inv_view_proj * ndc;
     let world_pos = world_h.xyz / world_h.w;
-    let cam_col = point_light.inv_view_proj * vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    let camera_pos = cam_col.xyz / cam_col.w;
-    let v = normalize(camera_pos - world_pos);
+    let v = normalize(point_light.camera_pos - world_pos);
 
     let to_light = point_light.position - world_pos;
     let dist = length(to_light);
@@ -173,7 +292,13 @@ fn GetRadialLightAttenuation(dist: f32, radius: f32, falloff: f32) -> f32 {
     let F = F_Schlick(specular_color, v_dot_h);
     lit += (D * Vis) * F * point_light.color * n_dot_l * attenuation;
 
-    return vec4<f32>(lit, 1.0);
+    return vec4<f32>(lit * visibility, 1.0);
+}
+
+@fragment fn fs_point(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    return shade_point(in.uv, depth_val, 1.0);
 }
 
 // Spot light: fullscreen, attenuation by distance + cone
@@ -189,6 +314,8 @@ struct SpotLightUniform {
     outer_cos: f32,
     _pad3: f32,
     inv_view_proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    _pad4: f32,
 }
 @group(0) @binding(5) var<uniform> spot_light: SpotLightUniform;
 
@@ -197,28 +324,21 @@ fn GetSpotConeAttenuation(l_dir: vec3<f32>, spot_dir: vec3<f32>, inner_cos: f32,
     return smoothstep(outer_cos, inner_cos, cos_angle);
 }
 
-@fragment fn fs_spot(in: VertexOutput) -> @location(0) vec4<f32> {
-    let g0 = textureSample(gbuffer0, gbuffer_sampler, in.uv);
-    let g1 = textureSample(gbuffer1, gbuffer_sampler, in.uv);
-    let g2 = textureSample(gbuffer2, gbuffer_sampler, in.uv);
-    let dims = vec2<f32>(textureDimensions(depth_tex));
-    let pix = vec2<i32>(min(floor(in.uv * dims), dims - vec2<f32>(1.0, 1.0)));
-    let depth_val = textureLoad(depth_tex, pix, 0);
-    if depth_val >= 1.0 { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
-
-    let n = decode_normal(g1.rgb);
-    let roughness = max(g2.r, 0.04);
-    let metalness = g2.g;
-    let specular_val = g2.b;
-    let base_color = g0.rgb;
-    let ao = g0.a;
-
-    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth_val, 1.0);
+// Spot light shading; `visibility` scales the result (1 = unshadowed).
+fn shade_spot(uv: vec2<f32>, depth_val: f32, visibility: f32) -> vec4<f32> {
+    let g = load_gbuffer(uv);
+    if is_unlit(g) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let n = g.normal;
+    let roughness = max(g.roughness, 0.04);
+    let metalness = g.metalness;
+    let specular_val = g.specular;
+    let base_color = g.base_color;
+    let ao = g.ao;
+
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth_val, 1.0);
     let world_h = spot_light.inv_view_proj * ndc;
     let world_pos = world_h.xyz / world_h.w;
-    let cam_col = spot_light.inv_view_proj * vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    let camera_pos = cam_col.xyz / cam_col.w;
-    let v = normalize(camera_pos - world_pos);
+    let v = normalize(spot_light.camera_pos - world_pos);
 
     let to_light = spot_light.position - world_pos;
     let dist = length(to_light);
@@ -243,5 +363,128 @@ fn GetSpotConeAttenuation(l_dir: vec3<f32>, spot_dir: vec3<f32>, inner_cos: f32,
     let F = F_Schlick(specular_color, v_dot_h);
     lit += (D * Vis) * F * spot_light.color * n_dot_l * attenuation;
 
-    return vec4<f32>(lit, 1.0);
+    return vec4<f32>(lit * visibility, 1.0);
+}
+
+@fragment fn fs_spot(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    return shade_spot(in.uv, depth_val, 1.0);
+}
+
+// Shadowed point and spot variants: only bound for lights given a local shadow map (see
+// LightPass::encode_point / encode_spot).
+@group(0) @binding(6) var local_shadow_maps: texture_depth_2d_array;
+@group(0) @binding(7) var local_shadow_sampler: sampler_comparison;
+struct LocalShadowUniform {
+    // Cube faces +X, -X, +Y, -Y, +Z, -Z of a point light; a spot light only uses the first.
+    view_proj: array<mat4x4<f32>, 6>,
+    first_layer: u32,
+    // tan(fov / 2) of the shadow frusta, for the world-space size of a shadow texel.
+    tan_half_fov: f32,
+    texel_size: f32,
+    _pad: f32,
+}
+@group(0) @binding(8) var<uniform> local_shadow: LocalShadowUniform;
+
+// Cube face (+X, -X, +Y, -Y, +Z, -Z) through which `dir`, pointing away from the light, leaves.
+fn cube_face(dir: vec3<f32>) -> u32 {
+    let a = abs(dir);
+    if a.x >= a.y && a.x >= a.z { return select(1u, 0u, dir.x > 0.0); }
+    if a.y >= a.z { return select(3u, 2u, dir.y > 0.0); }
+    return select(5u, 4u, dir.z > 0.0);
+}
+
+// 3x3 PCF of face `face` of a local light `dist` away. Bias is a normal offset of about one shadow
+// texel, since a constant depth bias does not hold up under the perspective depth distribution.
+// 1 = lit, 0 = fully occluded; outside the face's frustum counts as lit.
+fn local_shadow_visibility(world_pos: vec3<f32>, n: vec3<f32>, dist: f32, face: u32) -> f32 {
+    let texel_world = 2.0 * dist * local_shadow.tan_half_fov * local_shadow.texel_size;
+    let biased = world_pos + n * (1.5 * texel_world);
+    let clip = local_shadow.view_proj[face] * vec4<f32>(biased, 1.0);
+    if clip.w <= 0.0 { return 1.0; }
+    let ndc = clip.xyz / clip.w;
+    let shadow_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    if any(shadow_uv < vec2<f32>(0.0, 0.0)) || any(shadow_uv > vec2<f32>(1.0, 1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let layer = i32(local_shadow.first_layer + face);
+    var sum = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * local_shadow.texel_size;
+            sum += textureSampleCompareLevel(local_shadow_maps, local_shadow_sampler, shadow_uv + offset, layer, ndc.z);
+        }
+    }
+    return sum / 9.0;
+}
+
+// World position and normal of the pixel at `uv`, for the shadowed variants.
+fn local_shadow_surface(uv: vec2<f32>, depth_val: f32, inv_view_proj: mat4x4<f32>) -> array<vec3<f32>, 2> {
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth_val, 1.0);
+    let world_h = inv_view_proj * ndc;
+    return array<vec3<f32>, 2>(world_h.xyz / world_h.w, load_gbuffer(uv).normal);
+}
+
+@fragment fn fs_point_shadowed(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let surface = local_shadow_surface(in.uv, depth_val, point_light.inv_view_proj);
+    let from_light = surface[0] - point_light.position;
+    let dist = length(from_light);
+    if dist >= point_light.radius { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let visibility = local_shadow_visibility(surface[0], surface[1], dist, cube_face(from_light));
+    return shade_point(in.uv, depth_val, visibility);
+}
+
+@fragment fn fs_spot_shadowed(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    if is_background(depth_val) { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let surface = local_shadow_surface(in.uv, depth_val, spot_light.inv_view_proj);
+    let dist = length(surface[0] - spot_light.position);
+    if dist >= spot_light.radius { return vec4<f32>(0.0, 0.0, 0.0, 0.0); }
+    let visibility = local_shadow_visibility(surface[0], surface[1], dist, 0u);
+    return shade_spot(in.uv, depth_val, visibility);
+}
+
+// Fog: blended over the light buffer after all lights (src alpha = opacity); see FogConfig::opacity.
+struct FogUniform {
+    color: vec3<f32>,
+    // 0 = linear (start, end), 1 = exponential (density)
+    mode: u32,
+    inv_view_proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    density: f32,
+    start: f32,
+    end: f32,
+    height_falloff: f32,
+    base_height: f32,
+}
+@group(0) @binding(5) var<uniform> fog: FogUniform;
+
+fn fog_height_density_scale(camera_height: f32, point_height: f32) -> f32 {
+    let k = fog.height_falloff;
+    if k <= 0.0 { return 1.0; }
+    let at_camera = exp(-k * (camera_height - fog.base_height));
+    let t = k * (point_height - camera_height);
+    if abs(t) < 1e-4 { return at_camera; }
+    return at_camera * (1.0 - exp(-t)) / t;
+}
+
+@fragment fn fs_fog(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth_val = load_scene_depth(in.uv);
+    // Background: full fog, so geometry fades into it rather than ending at the far plane.
+    if is_background(depth_val) { return vec4<f32>(fog.color, 1.0); }
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth_val, 1.0);
+    let world_h = fog.inv_view_proj * ndc;
+    let world_pos = world_h.xyz / world_h.w;
+    let dist = length(world_pos - fog.camera_pos);
+    let density_scale = fog_height_density_scale(fog.camera_pos.y, world_pos.y);
+    var opacity: f32;
+    if fog.mode == 0u {
+        opacity = clamp((dist - fog.start) / max(fog.end - fog.start, 1e-4), 0.0, 1.0) * density_scale;
+    } else {
+        opacity = 1.0 - exp(-fog.density * dist * density_scale);
+    }
+    return vec4<f32>(fog.color, clamp(opacity, 0.0, 1.0));
 }
@@ -9,12 +9,84 @@ struct VertexOutput { @builtin(position) clip_position: vec4<f32>, @location(0)
 }
 @group(0) @binding(0) var light_buffer: texture_2d<f32>;
 @group(0) @binding(1) var light_sampler: sampler;
-struct PresentUniform { tone_mode: u32, }
-@group(0) @binding(2) var<uniform> present_uniform: PresentUniform; // tone_mode: 0 = Reinhard, 1 = None
+struct PresentUniform { tone_mode: u32, upscale_mode: u32, exposure: f32, color_lut: u32, vignette: f32, chromatic_aberration: f32, _pad0: u32, _pad1: u32, }
+// tone_mode: 0 = Reinhard, 1 = None, 2 = HDR output (no compression); upscale_mode: 0 = bilinear, 1 = Catmull-Rom;
+// exposure: linear scale before tone mapping; color_lut: 1 = grade with color_lut_tex; vignette: corner darkening;
+// chromatic_aberration: red/blue uv offset per unit distance from the center
+@group(0) @binding(2) var<uniform> present_uniform: PresentUniform;
+@group(0) @binding(3) var color_lut_tex: texture_3d<f32>;
+@group(0) @binding(4) var color_lut_sampler: sampler;
 fn tonemap_reinhard(c: vec3<f32>) -> vec3<f32> { return c / (1.0 + c); }
 fn tonemap_none(c: vec3<f32>) -> vec3<f32> { return clamp(c, vec3<f32>(0.0), vec3<f32>(1.0)); }
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+// LUT cells are centered on texels: 0 and 1 land on the first and last texel centers.
+fn grade(ldr: vec3<f32>) -> vec3<f32> {
+    let size = f32(textureDimensions(color_lut_tex).x);
+    let coord = linear_to_srgb(ldr) * ((size - 1.0) / size) + 0.5 / size;
+    return srgb_to_linear(textureSampleLevel(color_lut_tex, color_lut_sampler, coord, 0.0).rgb);
+}
+// Catmull-Rom bicubic using 9 bilinear taps (weights of the two middle texels folded into one tap per axis).
+fn sample_catmull_rom(uv: vec2<f32>) -> vec4<f32> {
+    let tex_size = vec2<f32>(textureDimensions(light_buffer));
+    let inv_size = 1.0 / tex_size;
+    let sample_pos = uv * tex_size;
+    let tex_pos1 = floor(sample_pos - 0.5) + 0.5;
+    let f = sample_pos - tex_pos1;
+    let w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    let w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    let w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    let w3 = f * f * (-0.5 + 0.5 * f);
+    let w12 = w1 + w2;
+    let offset12 = w2 / w12;
+    let uv0 = (tex_pos1 - 1.0) * inv_size;
+    let uv3 = (tex_pos1 + 2.0) * inv_size;
+    let uv12 = (tex_pos1 + offset12) * inv_size;
+    var result = vec4<f32>(0.0);
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv0.x, uv0.y), 0.0) * w0.x * w0.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv12.x, uv0.y), 0.0) * w12.x * w0.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv3.x, uv0.y), 0.0) * w3.x * w0.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv0.x, uv12.y), 0.0) * w0.x * w12.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv12.x, uv12.y), 0.0) * w12.x * w12.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv3.x, uv12.y), 0.0) * w3.x * w12.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv0.x, uv3.y), 0.0) * w0.x * w3.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv12.x, uv3.y), 0.0) * w12.x * w3.y;
+    result += textureSampleLevel(light_buffer, light_sampler, vec2<f32>(uv3.x, uv3.y), 0.0) * w3.x * w3.y;
+    // Negative lobes can undershoot around bright edges; keep HDR input non-negative for tone mapping.
+    return max(result, vec4<f32>(0.0));
+}
+fn sample_light(uv: vec2<f32>) -> vec3<f32> {
+    if (present_uniform.upscale_mode == 1u) {
+        return sample_catmull_rom(uv).rgb;
+    }
+    return textureSampleLevel(light_buffer, light_sampler, uv, 0.0).rgb;
+}
+// Tone mapping is per channel, so splitting channels before it matches splitting the final image.
+fn sample_aberrated(uv: vec2<f32>) -> vec3<f32> {
+    let center = sample_light(uv);
+    if (present_uniform.chromatic_aberration <= 0.0) {
+        return center;
+    }
+    let offset = (uv - 0.5) * present_uniform.chromatic_aberration;
+    return vec3<f32>(sample_light(uv + offset).r, center.g, sample_light(uv - offset).b);
+}
+fn vignette(uv: vec2<f32>) -> f32 {
+    let d = uv - 0.5;
+    return 1.0 - present_uniform.vignette * clamp(dot(d, d) * 2.0, 0.0, 1.0);
+}
 @fragment fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
-    let hdr = textureSample(light_buffer, light_sampler, in.uv);
-    let ldr_rgb = select(tonemap_none(hdr.rgb), tonemap_reinhard(hdr.rgb), present_uniform.tone_mode == 0u);
-    return vec4<f32>(ldr_rgb, 1.0);
+    let exposed = sample_aberrated(in.uv) * present_uniform.exposure;
+    if (present_uniform.tone_mode == 2u) {
+        // Float swapchain (scRGB): 1.0 is SDR reference white, brighter values reach into HDR headroom.
+        return vec4<f32>(max(exposed, vec3<f32>(0.0)) * vignette(in.uv), 1.0);
+    }
+    var ldr_rgb = select(tonemap_none(exposed), tonemap_reinhard(exposed), present_uniform.tone_mode == 0u);
+    if (present_uniform.color_lut == 1u) {
+        ldr_rgb = grade(ldr_rgb);
+    }
+    return vec4<f32>(ldr_rgb * vignette(in.uv), 1.0);
 }
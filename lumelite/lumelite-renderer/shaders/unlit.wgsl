@@ -0,0 +1,61 @@
+// Unlit forward pass: base color straight into the light buffer after the lit passes (fs), and the
+// emissive factor added on top of it (fs_emissive). Same vertex streams as gbuffer.wgsl; depth-tested
+// against the scene depth, which the GBuffer pass already wrote for these meshes.
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) world_pos: vec3<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) @interpolate(flat) emissive: vec3<f32>,
+}
+
+@group(0) @binding(0) var<uniform> view_proj: mat4x4<f32>;
+// Same layout as gbuffer.wgsl's MeshUniform.
+struct MeshUniform {
+    model: mat4x4<f32>,
+    emissive: vec3<f32>,
+    vertex_color: u32,
+    tangents: u32,
+}
+// Binding 0/1 and `fn mesh_data(instance_index) -> MeshUniform` are prepended (MeshData::wgsl).
+// World-space plane (normal.xyz, d); (0, 0, 0, 1) keeps everything. See UnlitPass::encode.
+@group(0) @binding(2) var<uniform> clip_plane: vec4<f32>;
+
+@group(1) @binding(0) var base_color_tex: texture_2d<f32>;
+@group(1) @binding(1) var tex_sampler: sampler;
+
+@vertex fn vs(in: VertexInput, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let mesh = mesh_data(instance);
+    var out: VertexOutput;
+    let world_pos = (mesh.model * vec4<f32>(in.position, 1.0)).xyz;
+    out.clip_position = view_proj * vec4<f32>(world_pos, 1.0);
+    out.uv = in.uv;
+    out.world_pos = world_pos;
+    out.color = select(vec4<f32>(1.0), in.color, mesh.vertex_color != 0u);
+    out.emissive = mesh.emissive;
+    return out;
+}
+
+@fragment fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Same clipping as the GBuffer pass, so clipped parts do not show through the depth test.
+    if dot(clip_plane.xyz, in.world_pos) + clip_plane.w < 0.0 {
+        discard;
+    }
+    return vec4<f32>(textureSample(base_color_tex, tex_sampler, in.uv).rgb * in.color.rgb, 1.0);
+}
+
+// Blended additively; alpha adds nothing.
+@fragment fn fs_emissive(in: VertexOutput) -> @location(0) vec4<f32> {
+    if dot(clip_plane.xyz, in.world_pos) + clip_plane.w < 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.emissive, 0.0);
+}
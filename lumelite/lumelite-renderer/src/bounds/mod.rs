@@ -0,0 +1,313 @@
+//! Mesh bounds: CPU `compute_aabb` and a GPU reduction pass for vertex buffers generated on-device
+//! (skinning, procedural). The GPU result is a 32-byte storage buffer usable by culling or read back.
+
+use wgpu::CommandEncoder;
+
+const BOUNDS_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/bounds.wgsl"));
+
+/// Must match `WORKGROUP_SIZE` in bounds.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Axis-aligned bounding box in mesh space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// Size of the GPU output written by `BoundsReducePass`: `vec4 min, vec4 max` (w unused).
+    pub const GPU_SIZE: u64 = 32;
+}
+
+/// Input of [`BoundsReducePass::encode`]: float3 positions at `position_offset` within each
+/// `stride`-byte vertex of the first `vertex_count` vertices in `buffer` (which needs `STORAGE` usage).
+#[derive(Clone, Copy, Debug)]
+pub struct VertexPositions<'a> {
+    pub buffer: &'a wgpu::Buffer,
+    pub vertex_count: u32,
+    pub stride: u32,
+    pub position_offset: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BoundsParams {
+    vertex_count: u32,
+    stride_words: u32,
+    position_offset_words: u32,
+    _pad: u32,
+}
+
+fn validate_layout(stride: u32, position_offset: u32) -> Result<(), String> {
+    if !stride.is_multiple_of(4) || !position_offset.is_multiple_of(4) {
+        return Err(format!(
+            "bounds: stride ({}) and position offset ({}) must be multiples of 4",
+            stride, position_offset
+        ));
+    }
+    if position_offset + 12 > stride {
+        return Err(format!(
+            "bounds: position at offset {} does not fit in stride {}",
+            position_offset, stride
+        ));
+    }
+    Ok(())
+}
+
+/// CPU bounds of float3 positions at `position_offset` in each `stride`-byte vertex. None for empty input.
+pub fn compute_aabb(vertex_data: &[u8], stride: u32, position_offset: u32) -> Result<Option<Aabb>, String> {
+    validate_layout(stride, position_offset)?;
+    let mut aabb: Option<Aabb> = None;
+    for vertex in vertex_data.chunks_exact(stride as usize) {
+        let p: [f32; 3] = bytemuck::pod_read_unaligned(&vertex[position_offset as usize..position_offset as usize + 12]);
+        let b = aabb.get_or_insert(Aabb { min: p, max: p });
+        for (c, v) in p.into_iter().enumerate() {
+            b.min[c] = b.min[c].min(v);
+            b.max[c] = b.max[c].max(v);
+        }
+    }
+    Ok(aabb)
+}
+
+/// GPU min/max reduction over a vertex buffer. The vertex buffer must have `STORAGE` usage.
+pub struct BoundsReducePass {
+    init_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    finalize_pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    max_workgroups: u32,
+}
+
+impl BoundsReducePass {
+    pub fn new(device: &wgpu::Device) -> Result<Self, String> {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bounds_shader"),
+            source: wgpu::ShaderSource::Wgsl(BOUNDS_SHADER.into()),
+        });
+        let storage = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bounds_bind_group_layout"),
+            entries: &[
+                storage(0, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<BoundsParams>() as u64),
+                    },
+                    count: None,
+                },
+                storage(2, false),
+                storage(3, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bounds_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let make_pipeline = |label: &str, entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        Ok(Self {
+            init_pipeline: make_pipeline("bounds_init_pipeline", "cs_init"),
+            reduce_pipeline: make_pipeline("bounds_reduce_pipeline", "cs_reduce"),
+            finalize_pipeline: make_pipeline("bounds_finalize_pipeline", "cs_finalize"),
+            bind_group_layout,
+            max_workgroups: device.limits().max_compute_workgroups_per_dimension,
+        })
+    }
+
+    /// Output buffer for `encode`: `STORAGE` for GPU culling, `COPY_SRC` for `read_back`.
+    pub fn create_output_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh_bounds"),
+            size: Aabb::GPU_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Reduce `positions` into `output` (see `create_output_buffer`).
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        positions: VertexPositions,
+        output: &wgpu::Buffer,
+    ) -> Result<(), String> {
+        let VertexPositions { buffer: vertex_buf, vertex_count, stride, position_offset } = positions;
+        validate_layout(stride, position_offset)?;
+        if vertex_count == 0 {
+            return Err("bounds: vertex_count must be > 0".to_string());
+        }
+        if (vertex_count as u64) * (stride as u64) > vertex_buf.size() {
+            return Err(format!(
+                "bounds: {} vertices of stride {} exceed vertex buffer size {}",
+                vertex_count,
+                stride,
+                vertex_buf.size()
+            ));
+        }
+        if output.size() < Aabb::GPU_SIZE {
+            return Err(format!("bounds: output buffer must be at least {} bytes", Aabb::GPU_SIZE));
+        }
+        // Per-call params/scratch so several meshes can be reduced in one submission.
+        let params = BoundsParams {
+            vertex_count,
+            stride_words: stride / 4,
+            position_offset_words: position_offset / 4,
+            _pad: 0,
+        };
+        let params_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bounds_params"),
+            size: std::mem::size_of::<BoundsParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&params_buf, 0, bytemuck::bytes_of(&params));
+        let scratch_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bounds_scratch"),
+            size: 24,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bounds_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: vertex_buf.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: params_buf.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: scratch_buf.as_entire_binding() },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: output,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(Aabb::GPU_SIZE),
+                    }),
+                },
+            ],
+        });
+        let workgroups = vertex_count.div_ceil(WORKGROUP_SIZE).min(self.max_workgroups);
+        let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("bounds_reduce"),
+            timestamp_writes: None,
+        });
+        cp.set_bind_group(0, &bind_group, &[]);
+        cp.set_pipeline(&self.init_pipeline);
+        cp.dispatch_workgroups(1, 1, 1);
+        cp.set_pipeline(&self.reduce_pipeline);
+        cp.dispatch_workgroups(workgroups, 1, 1);
+        cp.set_pipeline(&self.finalize_pipeline);
+        cp.dispatch_workgroups(1, 1, 1);
+        Ok(())
+    }
+
+    /// Blocking CPU readback of an output buffer written by `encode` (submits its own copy).
+    pub fn read_back(device: &wgpu::Device, queue: &wgpu::Queue, output: &wgpu::Buffer) -> Result<Aabb, String> {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh_bounds_readback"),
+            size: Aabb::GPU_SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mesh_bounds_readback"),
+        });
+        encoder.copy_buffer_to_buffer(output, 0, &staging, 0, Aabb::GPU_SIZE);
+        queue.submit([encoder.finish()]);
+        let slice = staging.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv()
+            .map_err(|e| format!("bounds readback: {}", e))?
+            .map_err(|e| format!("bounds readback: {}", e))?;
+        let values: [f32; 8] = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        staging.unmap();
+        Ok(Aabb {
+            min: [values[0], values[1], values[2]],
+            max: [values[4], values[5], values[6]],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const STRIDE: u32 = 32;
+
+    fn test_vertices() -> Vec<u8> {
+        let positions: [[f32; 3]; 5] = [
+            [1.0, -2.0, 0.5],
+            [-3.5, 4.0, 0.0],
+            [0.25, 0.0, -7.0],
+            [2.0, 1.0, 3.0],
+            [-0.0, -0.5, 1.0],
+        ];
+        let mut data = Vec::new();
+        for p in positions {
+            data.extend_from_slice(bytemuck::cast_slice(&p));
+            data.extend_from_slice(bytemuck::cast_slice(&[0.0f32, 1.0, 0.0, 0.5, 0.5]));
+        }
+        data
+    }
+
+    #[test]
+    fn cpu_aabb_of_static_buffer() {
+        let aabb = compute_aabb(&test_vertices(), STRIDE, 0).unwrap().unwrap();
+        assert_eq!(aabb, Aabb { min: [-3.5, -2.0, -7.0], max: [2.0, 4.0, 3.0] });
+        assert_eq!(compute_aabb(&[], STRIDE, 0).unwrap(), None);
+        assert!(compute_aabb(&test_vertices(), STRIDE, 24).is_err());
+    }
+
+    /// GPU reduction must match the CPU result. Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn gpu_aabb_matches_cpu() {
+        let Some(adapter) = test_support::adapter("gpu_aabb_matches_cpu") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let data = test_vertices();
+        let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("test_vertices"),
+            size: data.len() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&vertex_buf, 0, &data);
+        let pass = BoundsReducePass::new(&device).unwrap();
+        let output = BoundsReducePass::create_output_buffer(&device);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let positions = VertexPositions { buffer: &vertex_buf, vertex_count: 5, stride: STRIDE, position_offset: 0 };
+        pass.encode(&mut encoder, &device, &queue, positions, &output).unwrap();
+        queue.submit([encoder.finish()]);
+        let gpu = BoundsReducePass::read_back(&device, &queue, &output).unwrap();
+        assert_eq!(Some(gpu), compute_aabb(&data, STRIDE, 0).unwrap());
+    }
+}
@@ -1,12 +1,364 @@
 //! Lumelite configuration: lights, shadows, tone mapping, swapchain.
 
-/// Tone mapping mode for present pass.
-#[derive(Clone, Copy, Debug, Default)]
-pub enum ToneMapping {
+use crate::gbuffer::GBufferLayout;
+use crate::present::ColorLut;
+
+/// Tone mapping mode for present pass; `ExtractedView::tone_mapping` can override it per view.
+pub use render_api::ToneMapping;
+
+/// Filter used by the present pass when the light buffer is resampled to the output size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Single hardware bilinear tap.
+    #[default]
+    Bilinear,
+    /// Sharper bicubic Catmull-Rom (9 bilinear taps).
+    CatmullRom,
+}
+
+/// Occlusion culling on top of GPU frustum culling; see `LumeliteConfig::occlusion_culling`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OcclusionCulling {
     #[default]
-    Reinhard,
-    /// No tone mapping (clamp).
-    None,
+    Off,
+    /// Test against a Hi-Z pyramid of the previous frame's depth of the same view (see `HiZPyramid`),
+    /// projecting boxes with that frame's view-projection. Being a frame late, meshes coming out from
+    /// behind an occluder, moving or revealed by camera motion can be missing for a frame.
+    SinglePass,
+    /// Draw the meshes visible last frame, build the Hi-Z pyramid from their depth, then test the rest
+    /// and draw the disoccluded ones in a second GBuffer pass. No latency, at the cost of a second cull
+    /// dispatch and GBuffer pass. Visibility is kept per draw index, so meshes must keep their order
+    /// between frames (lumelite-bridge then orders them by entity instead of by camera distance).
+    TwoPass,
+}
+
+/// Dynamic resolution: adjusts render scale toward a target frame time reported by the host.
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicResolution {
+    /// Frame time budget in milliseconds (e.g. 16.6 for 60 Hz).
+    pub target_frame_ms: f32,
+    /// Lowest scale the controller may pick, in (0, 1].
+    pub min_scale: f32,
+}
+
+impl DynamicResolution {
+    /// Scale step; keeps internal targets from being reallocated for tiny fluctuations.
+    const STEP: f32 = 0.05;
+
+    /// Next render scale given the current one and the last measured frame time.
+    /// Pixel cost is roughly quadratic in scale, so the correction uses the square root of the ratio.
+    pub fn next_scale(&self, current: f32, frame_time_ms: f32) -> f32 {
+        if frame_time_ms <= 0.0 || self.target_frame_ms <= 0.0 {
+            return current;
+        }
+        let desired = current * (self.target_frame_ms / frame_time_ms).sqrt();
+        let stepped = current + (desired - current).clamp(-Self::STEP, Self::STEP);
+        let quantized = (stepped / Self::STEP).round() * Self::STEP;
+        quantized.clamp(self.min_scale.clamp(Self::STEP, 1.0), 1.0)
+    }
+}
+
+/// Filtering of material textures (`LumeliteConfig::material_sampling`), used by the GBuffer and
+/// unlit passes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialSampling {
+    /// Max anisotropy, clamped to 1..=16 (1 = off). The driver further limits it to what the GPU
+    /// supports; wgpu needs no device feature for it.
+    pub anisotropy: u16,
+    /// Blend between mip levels (trilinear); off snaps to the nearest mip (bilinear). Anisotropic
+    /// filtering always blends mips, since wgpu requires linear filters with it.
+    pub trilinear: bool,
+}
+
+impl Default for MaterialSampling {
+    fn default() -> Self {
+        Self { anisotropy: 1, trilinear: true }
+    }
+}
+
+impl MaterialSampling {
+    /// Repeat-addressed sampler with these filtering settings.
+    pub fn sampler_descriptor<'a>(&self, label: Option<&'a str>) -> wgpu::SamplerDescriptor<'a> {
+        let anisotropy_clamp = self.anisotropy.clamp(1, 16);
+        let mipmap_filter = if self.trilinear || anisotropy_clamp > 1 {
+            wgpu::FilterMode::Linear
+        } else {
+            wgpu::FilterMode::Nearest
+        };
+        wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter,
+            anisotropy_clamp,
+            ..Default::default()
+        }
+    }
+}
+
+/// Constant ambient fill added before the light passes so lit geometry never renders fully black.
+#[derive(Clone, Copy, Debug)]
+pub struct AmbientLight {
+    /// Linear RGB color of the fill.
+    pub color: [f32; 3],
+    /// Multiplier on `color`; 0 disables the fill like `None` does.
+    pub intensity: f32,
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self { color: [1.0, 1.0, 1.0], intensity: 0.03 }
+    }
+}
+
+/// Cheap lens effects applied by the present pass on the final image. All default to 0 (off).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PresentEffects {
+    /// Darkening toward the edges in [0, 1]: the corners are scaled by `1 - vignette`, the center is untouched.
+    pub vignette: f32,
+    /// Radial red/blue split, as a uv offset per unit of distance from the center (red outward, blue
+    /// inward); around 0.01 is subtle.
+    pub chromatic_aberration: f32,
+}
+
+impl PresentEffects {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.vignette) {
+            return Err(format!("vignette must be in [0, 1], got {}", self.vignette));
+        }
+        if !(self.chromatic_aberration >= 0.0 && self.chromatic_aberration.is_finite()) {
+            return Err(format!("chromatic_aberration must be finite and >= 0, got {}", self.chromatic_aberration));
+        }
+        Ok(())
+    }
+}
+
+/// How fog opacity grows with view distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    /// No fog up to `start`, full fog from `end` (world units).
+    Linear { start: f32, end: f32 },
+    /// `1 - exp(-density * distance)`: never fully opaque, the usual choice for atmosphere.
+    Exponential { density: f32 },
+}
+
+/// Distance/height fog blended over the lit scene after the light passes. Background pixels get
+/// the full fog color, so distant geometry fades into it instead of ending at the far plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogConfig {
+    /// Linear RGB color the scene fades to (in light buffer units, i.e. before tone mapping).
+    pub color: [f32; 3],
+    pub mode: FogMode,
+    /// Exponential falloff of fog density with height above `base_height`; 0 gives uniform fog.
+    pub height_falloff: f32,
+    /// World-space height at which the fog has its nominal density.
+    pub base_height: f32,
+}
+
+impl Default for FogConfig {
+    fn default() -> Self {
+        Self {
+            color: [0.5, 0.6, 0.7],
+            mode: FogMode::Exponential { density: 0.02 },
+            height_falloff: 0.0,
+            base_height: 0.0,
+        }
+    }
+}
+
+impl FogConfig {
+    /// Fog opacity in [0, 1] for a point `distance` away at height `point_height` seen from
+    /// `camera_height`. CPU version of `fs_fog` in lights.wgsl (e.g. for gameplay visibility checks).
+    pub fn opacity(&self, distance: f32, camera_height: f32, point_height: f32) -> f32 {
+        let density_scale = self.height_density_scale(camera_height, point_height);
+        match self.mode {
+            FogMode::Linear { start, end } => {
+                ((distance - start) / (end - start).max(1e-4)).clamp(0.0, 1.0) * density_scale
+            }
+            FogMode::Exponential { density } => 1.0 - (-density * distance * density_scale).exp(),
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    /// Average of `exp(-height_falloff * (y - base_height))` along the segment from the camera to the point.
+    fn height_density_scale(&self, camera_height: f32, point_height: f32) -> f32 {
+        let k = self.height_falloff;
+        if k <= 0.0 {
+            return 1.0;
+        }
+        let at_camera = (-k * (camera_height - self.base_height)).exp();
+        let t = k * (point_height - camera_height);
+        if t.abs() < 1e-4 {
+            at_camera
+        } else {
+            at_camera * (1.0 - (-t).exp()) / t
+        }
+    }
+}
+
+/// How the GBuffer and shadow passes start their depth targets each frame. Both are Depth32Float, so
+/// there is no stencil to clear.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthLoadOps {
+    /// Scene depth. `Load` keeps depth already written this frame (e.g. a host depth prepass into
+    /// `FrameResources::depth`). With `LumeliteConfig::reverse_z` the far plane is 0.0, so clear to
+    /// that instead (see [`DepthLoadOps::reverse_z`]).
+    pub gbuffer: wgpu::LoadOp<f32>,
+    /// Shadow map depth, e.g. `Load` to accumulate casters over several sub-frames.
+    pub shadow: wgpu::LoadOp<f32>,
+}
+
+impl Default for DepthLoadOps {
+    fn default() -> Self {
+        Self {
+            gbuffer: wgpu::LoadOp::Clear(crate::gbuffer::GBUFFER_DEPTH_CLEAR),
+            shadow: wgpu::LoadOp::Clear(1.0),
+        }
+    }
+}
+
+impl DepthLoadOps {
+    /// Clears for `LumeliteConfig::reverse_z`: scene depth to 0.0; the shadow map keeps forward depth.
+    pub fn reverse_z() -> Self {
+        Self { gbuffer: wgpu::LoadOp::Clear(crate::gbuffer::REVERSE_Z_DEPTH_CLEAR), ..Self::default() }
+    }
+}
+
+/// Editor ground grid on the world y = 0 plane, drawn over the lit scene and depth-tested against it
+/// (see `GridPass`). Meant for model viewers and editors, so `LumeliteConfig::grid` is off by default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridConfig {
+    /// World units between grid lines.
+    pub spacing: f32,
+    /// Linear RGB of the lines (light buffer units, before tone mapping).
+    pub color: [f32; 3],
+    /// Every `major_every`-th line is drawn in `major_color`; 0 draws only minor lines.
+    pub major_every: u32,
+    pub major_color: [f32; 3],
+    /// Line opacity near the camera.
+    pub opacity: f32,
+    /// Distance from the camera at which the grid has faded out completely (world units). Fading keeps
+    /// distant lines, thinner than a pixel, from aliasing into moiré.
+    pub fade_distance: f32,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            spacing: 1.0,
+            color: [0.3, 0.3, 0.3],
+            major_every: 10,
+            major_color: [0.6, 0.6, 0.6],
+            opacity: 0.8,
+            fade_distance: 50.0,
+        }
+    }
+}
+
+/// How the GBuffer pass stores its targets when it ends. `Discard` lets tiled GPUs skip writing an
+/// attachment back to memory, but leaves it undefined afterwards, so it only suits targets nothing reads
+/// later: `Renderer::encode_frame` always stores (the light passes read every target), while a host
+/// encoding [`crate::gbuffer::GBufferPass`] itself (e.g. a pass whose depth is only used for testing
+/// within it) can discard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GBufferStoreOps {
+    /// Every GBuffer color target.
+    pub color: wgpu::StoreOp,
+    /// Scene depth.
+    pub depth: wgpu::StoreOp,
+}
+
+impl Default for GBufferStoreOps {
+    fn default() -> Self {
+        Self { color: wgpu::StoreOp::Store, depth: wgpu::StoreOp::Store }
+    }
+}
+
+/// Settings for rendering a planar reflection (mirror, water): the host renders the scene with a view
+/// mirrored by [`Self::reflection_matrix`], and the GBuffer pass discards everything behind the plane
+/// so geometry below the surface does not leak into the reflection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReflectionRenderConfig {
+    /// World-space plane `(n.xyz, d)`; fragments with `dot(n, p) + d < 0` are discarded.
+    pub clip_plane: [f32; 4],
+}
+
+impl ReflectionRenderConfig {
+    /// Plane through `point` facing `normal` (normalized here); the kept side is the one `normal` points to.
+    pub fn from_point_normal(point: [f32; 3], normal: [f32; 3]) -> Self {
+        let len = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt().max(1e-8);
+        let n = [normal[0] / len, normal[1] / len, normal[2] / len];
+        let d = -(n[0] * point[0] + n[1] * point[1] + n[2] * point[2]);
+        Self { clip_plane: [n[0], n[1], n[2], d] }
+    }
+
+    /// Column-major world-space mirror across the clip plane; multiply the view matrix by it
+    /// (`view * reflection`) to get the reflected camera. Assumes a normalized plane normal.
+    /// The mirror flips triangle winding; the renderer accounts for it when culling while a reflection is set.
+    #[rustfmt::skip]
+    pub fn reflection_matrix(&self) -> [f32; 16] {
+        let [a, b, c, d] = self.clip_plane;
+        [
+            1.0 - 2.0 * a * a, -2.0 * a * b, -2.0 * a * c, 0.0,
+            -2.0 * a * b, 1.0 - 2.0 * b * b, -2.0 * b * c, 0.0,
+            -2.0 * a * c, -2.0 * b * c, 1.0 - 2.0 * c * c, 0.0,
+            -2.0 * a * d, -2.0 * b * d, -2.0 * c * d, 1.0,
+        ]
+    }
+}
+
+/// Adapter capabilities that decide whether the optional compute passes can run. wgpu only reports
+/// them on the adapter, not the device, so hosts fill them in with [`Self::from_adapter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdapterCapabilities {
+    /// `Adapter::get_downlevel_capabilities().flags`; GPU culling needs `COMPUTE_SHADERS` and
+    /// `INDIRECT_EXECUTION`.
+    pub downlevel_flags: wgpu::DownlevelFlags,
+    /// Compute shaders can load depth textures, which building the Hi-Z pyramid needs. False on the GL
+    /// backend.
+    pub compute_depth_loads: bool,
+}
+
+impl AdapterCapabilities {
+    pub fn from_adapter(adapter: &wgpu::Adapter) -> Self {
+        Self {
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
+            compute_depth_loads: adapter.get_info().backend != wgpu::Backend::Gl,
+        }
+    }
+}
+
+impl Default for AdapterCapabilities {
+    /// A fully WebGPU-compliant adapter (Vulkan, DX12, Metal).
+    fn default() -> Self {
+        Self { downlevel_flags: wgpu::DownlevelFlags::compliant(), compute_depth_loads: true }
+    }
+}
+
+/// A step of `Renderer::encode_frame` that can be skipped at runtime with `Renderer::set_pass_enabled`,
+/// e.g. to find which pass causes an artifact or a slow frame. Lumelite has no SSAO, bloom or GI pass yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FramePass {
+    /// Directional shadow map; the directional light is then unshadowed.
+    Shadow,
+    /// GPU frustum culling (`LumeliteConfig::gpu_culling`); every mesh is then drawn.
+    GpuCulling,
+    /// Hi-Z occlusion culling (`LumeliteConfig::occlusion_culling`); only the frustum test is made.
+    OcclusionCulling,
+    /// Ambient fill; the light buffer is still cleared.
+    Ambient,
+    Directional,
+    PointLights,
+    SpotLights,
+    /// Unlit meshes keep whatever the light passes wrote under them, and emissive factors are not added.
+    Unlit,
+    Fog,
+    Grid,
+    /// The host hook (`Renderer::set_post_process`); the light buffer is presented directly.
+    PostProcess,
 }
 
 /// Lumelite renderer and bridge configuration.
@@ -18,6 +370,13 @@ pub struct LumeliteConfig {
     pub debug_clear_green: bool,
     /// When true, draw triangle directly to swapchain (bypass GBuffer/Light/Present).
     pub debug_direct_triangle: bool,
+    /// Time the shadow, GBuffer, light and present passes with timestamp queries and draw the timings
+    /// over the presented image (`Renderer::gpu_timings`). Needs `TIMESTAMP_QUERY |
+    /// TIMESTAMP_QUERY_INSIDE_ENCODERS` on the device; ignored with a log message otherwise.
+    pub debug_gpu_timings: bool,
+    /// MSAA sample count of the direct triangle path (`debug_direct_triangle`): 1 (off) or 4. The
+    /// multisampled target is resolved into the swapchain view, which must be `swapchain_format`.
+    pub msaa_samples: u32,
     /// Max point lights (reserved for P1 extension).
     pub max_point_lights: u32,
     /// Max spot lights (reserved for P1 extension).
@@ -26,10 +385,100 @@ pub struct LumeliteConfig {
     pub shadow_enabled: bool,
     /// Shadow map resolution (e.g. 1024).
     pub shadow_resolution: u32,
+    /// Number of lights that get a shadow map per frame (see `shadows::allocate_shadows`): the directional
+    /// light first, then point and spot lights by `shadow_priority`. Needs `shadow_enabled`; capped so
+    /// the local maps fit the device's texture array layers. 0 disables shadows.
+    pub max_shadowed_lights: u32,
+    /// Side of each point light cube face and spot light shadow map (e.g. 512).
+    pub local_shadow_resolution: u32,
+    /// Light-space depth bias for the shadow test (scaled up on surfaces at grazing angles to the light).
+    pub shadow_depth_bias: f32,
+    /// Apparent diameter of the sun in radians (the real sun is about 0.0093). Above 0 the directional
+    /// shadow uses a blocker search and a rotated 16-tap disk for contact-hardening penumbrae; 0 keeps
+    /// the cheaper fixed 3x3 PCF.
+    pub sun_angular_size: f32,
+    /// Ambient fill applied to all geometry before the light passes; `None` (the default) skips the pass.
+    pub ambient: Option<AmbientLight>,
     /// Tone mapping for present pass.
     pub tone_mapping: ToneMapping,
-    /// Swapchain texture format for present (e.g. Rgba8Unorm or Bgra8Unorm).
+    /// Linear multiplier on the light buffer before tone mapping (1 = unchanged). Overridable per view
+    /// with `Renderer::encode_present_with` / `ExtractedView::exposure`.
+    pub exposure: f32,
+    /// Resampling filter for present when light buffer and output sizes differ.
+    pub upscale_filter: UpscaleFilter,
+    /// Max anisotropy for the present sampler (1 = off, clamped to 1..=16).
+    pub present_anisotropy: u16,
+    /// 3D LUT graded over the tone-mapped image in present (not applied to HDR outputs); `None` (the
+    /// default) leaves colors untouched. Load `.cube` files with [`ColorLut::parse_cube`].
+    pub color_lut: Option<ColorLut>,
+    /// Vignette and chromatic aberration in present; off by default.
+    pub present_effects: PresentEffects,
+    /// Material texture filtering (anisotropy, trilinear); defaults to trilinear without anisotropy.
+    pub material_sampling: MaterialSampling,
+    /// Largest material texture side the bridge uploads; `None` uses the device's
+    /// `max_texture_dimension_2d` (always the upper bound).
+    pub max_texture_dimension: Option<u32>,
+    /// Downscale material textures over the max dimension (with a warning) instead of rejecting them;
+    /// rejected textures fall back to the default texel, are logged as a warning and listed by the
+    /// bridge's `LumelitePlugin::texture_errors`; frames still render and succeed.
+    pub downscale_oversized_textures: bool,
+    /// Internal resolution as a fraction of the output size, in (0, 1]. GBuffer, depth and light buffer
+    /// render at this scale; present upscales to the output. The shadow map shrinks with it (down to 256
+    /// texels a side), keeping shadow texels per screen pixel about the same.
+    pub render_scale: f32,
+    /// When set, render scale is driven by the GPU frame times of `Renderer::report_frame_time`.
+    pub dynamic_resolution: Option<DynamicResolution>,
+    /// Depth clear/load of the GBuffer and shadow passes; default clears to 1.0. Can be changed per
+    /// frame with `Renderer::set_depth_load`.
+    pub depth_load: DepthLoadOps,
+    /// Scene depth is reverse-Z (near = 1, far = 0), matching the host's projection: geometry passes
+    /// test GreaterEqual and the light pass treats depth 0 as background. Needs `depth_load` to clear
+    /// to 0.0 (`DepthLoadOps::reverse_z()`); occlusion culling is off, as Hi-Z keeps the max depth.
+    /// Off by default.
+    pub reverse_z: bool,
+    /// Planar reflection clip plane applied to the GBuffer pass; `None` (default) draws everything.
+    /// Can be changed per frame with `Renderer::set_reflection`.
+    pub reflection: Option<ReflectionRenderConfig>,
+    /// Cull back faces in the GBuffer pass. Meshes must be wound counter-clockwise seen from outside;
+    /// `MeshDraw::mirrored` meshes are handled. Off by default since imported meshes are not always
+    /// consistently wound.
+    pub backface_culling: bool,
+    /// Keep a copy of the previous frame's light buffer (`FrameResources::light_history`) for temporal
+    /// passes. Costs one full-resolution Rgba16Float texture and a copy per frame.
+    pub light_history: bool,
+    /// Distance/height fog applied after lighting; `None` (default) disables it.
+    pub fog: Option<FogConfig>,
+    /// Ground grid drawn after fog; `None` (default) disables it.
+    pub grid: Option<GridConfig>,
+    /// Frustum-cull meshes on the GPU (`MeshDraw::bounds`) and draw the GBuffer pass with indirect draws.
+    /// Falls back to CPU draws of every mesh when `adapter_capabilities` lack support (see `GpuCullPass::is_supported`).
+    /// The shadow pass always draws every mesh, since casters outside the view still cast into it.
+    pub gpu_culling: bool,
+    /// With `gpu_culling` active, also cull meshes hidden behind other geometry. Default `Off`; ignored
+    /// without `AdapterCapabilities::compute_depth_loads`.
+    pub occlusion_culling: OcclusionCulling,
+    /// Triangles per frame to stay under by biasing LOD selection toward coarser LODs
+    /// (`Renderer::lod_bias`). Counted by the cull pass with `gpu_culling`, else over every mesh drawn.
+    /// `None` (default): unbounded, no bias.
+    pub triangle_budget: Option<u32>,
+    /// GBuffer packing and target formats. `GBufferLayout::COMPACT` halves GBuffer bandwidth at a small
+    /// precision cost; `with_velocity()` adds a screen-space motion target (`Renderer::velocity`).
+    /// `Renderer::new` errors if the device cannot render the layout's targets.
+    pub gbuffer_layout: GBufferLayout,
+    /// Clear color of the present target outside the scene region (letterbox bars, split-screen gaps);
+    /// see `Renderer::encode_present_region`. Default black.
+    pub background_color: wgpu::Color,
+    /// Ask the window backend for an HDR swapchain (`Rgba16Float`, scRGB: linear with extended range)
+    /// when the surface offers one; otherwise it falls back to an SDR format. Present writes unclamped
+    /// linear values to HDR formats and ignores `tone_mapping` (see `present::is_hdr_format`).
+    pub hdr_output: bool,
+    /// Format of the targets present writes to. Present writes linear values, so an sRGB format (the
+    /// default `Rgba8UnormSrgb`, or `Bgra8UnormSrgb`) has the GPU encode them to sRGB on store; a plain
+    /// `Unorm` format receives them unencoded.
     pub swapchain_format: wgpu::TextureFormat,
+    /// What the adapter supports, for `gpu_culling` and `occlusion_culling`. The window backend fills it
+    /// in from its adapter; other hosts should use `AdapterCapabilities::from_adapter`.
+    pub adapter_capabilities: AdapterCapabilities,
 }
 
 impl Default for LumeliteConfig {
@@ -38,12 +487,123 @@ impl Default for LumeliteConfig {
             debug_show_gbuffer: false,
             debug_clear_green: false, // swapchain verified OK
             debug_direct_triangle: false,
+            debug_gpu_timings: false,
+            msaa_samples: 1,
             max_point_lights: 8,
             max_spot_lights: 4,
             shadow_enabled: false,
             shadow_resolution: 1024,
+            max_shadowed_lights: 1,
+            local_shadow_resolution: 512,
+            shadow_depth_bias: 0.002,
+            sun_angular_size: 0.0,
+            ambient: None,
             tone_mapping: ToneMapping::default(),
-            swapchain_format: wgpu::TextureFormat::Rgba8Unorm,
+            exposure: 1.0,
+            upscale_filter: UpscaleFilter::default(),
+            present_anisotropy: 1,
+            color_lut: None,
+            present_effects: PresentEffects::default(),
+            material_sampling: MaterialSampling::default(),
+            max_texture_dimension: None,
+            downscale_oversized_textures: true,
+            render_scale: 1.0,
+            dynamic_resolution: None,
+            depth_load: DepthLoadOps::default(),
+            reverse_z: false,
+            reflection: None,
+            backface_culling: false,
+            light_history: false,
+            fog: None,
+            grid: None,
+            gpu_culling: false,
+            occlusion_culling: OcclusionCulling::Off,
+            triangle_budget: None,
+            gbuffer_layout: GBufferLayout::default(),
+            background_color: wgpu::Color::BLACK,
+            hdr_output: false,
+            swapchain_format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            adapter_capabilities: AdapterCapabilities::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_scale(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "scale {actual}, expected {expected}");
+    }
+
+    #[test]
+    fn dynamic_resolution_steps_toward_the_budget() {
+        let dr = DynamicResolution { target_frame_ms: 16.0, min_scale: 0.5 };
+        // Twice over budget wants ~0.71 but moves at most one step per frame.
+        assert_scale(dr.next_scale(1.0, 32.0), 0.95);
+        // Well under budget scales back up, again one step at a time.
+        assert_scale(dr.next_scale(0.6, 8.0), 0.65);
+        // No measurement keeps the current scale.
+        assert_scale(dr.next_scale(0.8, 0.0), 0.8);
+    }
+
+    #[test]
+    fn dynamic_resolution_clamps_to_min_and_max() {
+        let dr = DynamicResolution { target_frame_ms: 16.0, min_scale: 0.5 };
+        assert_scale(dr.next_scale(0.5, 100.0), 0.5);
+        assert_scale(dr.next_scale(1.0, 1.0), 1.0);
+        // A min_scale of 0 would let the targets collapse; the floor is one step.
+        let no_floor = DynamicResolution { target_frame_ms: 16.0, min_scale: 0.0 };
+        assert_scale(no_floor.next_scale(0.05, 100.0), 0.05);
+    }
+
+    #[test]
+    fn dynamic_resolution_holds_near_the_budget() {
+        let dr = DynamicResolution { target_frame_ms: 16.0, min_scale: 0.5 };
+        // Within half a step of the desired scale, quantization keeps the current one, so the
+        // targets are not reallocated for small frame-time noise.
+        assert_scale(dr.next_scale(0.75, 16.5), 0.75);
+        assert_scale(dr.next_scale(0.75, 15.5), 0.75);
+        assert_scale(dr.next_scale(1.0, 16.5), 1.0);
+    }
+
+    #[test]
+    fn reflection_plane_mirrors_points_across_it() {
+        let cfg = ReflectionRenderConfig::from_point_normal([0.0, 2.0, 0.0], [0.0, 3.0, 0.0]);
+        assert_eq!(cfg.clip_plane, [0.0, 1.0, 0.0, -2.0]);
+        let m = cfg.reflection_matrix();
+        let p = [1.0, 5.0, -4.0, 1.0];
+        let mirrored: Vec<f32> = (0..4).map(|r| (0..4).map(|c| m[c * 4 + r] * p[c]).sum()).collect();
+        assert_eq!(mirrored, vec![1.0, -1.0, -4.0, 1.0]);
+    }
+
+    #[test]
+    fn fog_opacity_by_mode_and_height() {
+        let linear = FogConfig { mode: FogMode::Linear { start: 10.0, end: 30.0 }, ..Default::default() };
+        assert_eq!(linear.opacity(5.0, 0.0, 0.0), 0.0);
+        assert_eq!(linear.opacity(20.0, 0.0, 0.0), 0.5);
+        assert_eq!(linear.opacity(100.0, 0.0, 0.0), 1.0);
+
+        let exp = FogConfig { mode: FogMode::Exponential { density: 0.1 }, ..Default::default() };
+        assert!((exp.opacity(10.0, 0.0, 0.0) - (1.0 - (-1.0f32).exp())).abs() < 1e-6);
+
+        // With height falloff, a ray climbing out of the fog sees less of it than a level one,
+        // and a level ray high above the base sees less than one at the base.
+        let height = FogConfig { height_falloff: 0.5, ..exp };
+        let level = height.opacity(10.0, 0.0, 0.0);
+        assert!((level - exp.opacity(10.0, 0.0, 0.0)).abs() < 1e-6);
+        assert!(height.opacity(10.0, 0.0, 8.0) < level);
+        assert!(height.opacity(10.0, 4.0, 4.0) < level);
+    }
+
+    #[test]
+    fn material_sampling_clamps_anisotropy_and_keeps_linear_mips_with_it() {
+        let desc = MaterialSampling::default().sampler_descriptor(None);
+        assert_eq!((desc.anisotropy_clamp, desc.mipmap_filter), (1, wgpu::FilterMode::Linear));
+        let bilinear = MaterialSampling { anisotropy: 1, trilinear: false }.sampler_descriptor(None);
+        assert_eq!(bilinear.mipmap_filter, wgpu::FilterMode::Nearest);
+        let anisotropic = MaterialSampling { anisotropy: 64, trilinear: false }.sampler_descriptor(None);
+        assert_eq!((anisotropic.anisotropy_clamp, anisotropic.mipmap_filter), (16, wgpu::FilterMode::Linear));
+        assert_eq!(MaterialSampling { anisotropy: 0, trilinear: true }.sampler_descriptor(None).anisotropy_clamp, 1);
+    }
+}
@@ -0,0 +1,200 @@
+//! Hi-Z pyramid: an `R32Float` mip chain of the max depth of the GBuffer depth buffer, built by compute
+//! downsampling after the GBuffer pass. The cull pass tests each mesh's screen rect against it (see
+//! cull.wgsl): the next frame's single pass, one frame late, or the late pass of two-pass culling.
+
+use wgpu::CommandEncoder;
+
+const HIZ_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/hiz.wgsl"));
+
+/// Must match `@workgroup_size` of hiz.wgsl.
+const WORKGROUP_SIZE: u32 = 8;
+
+pub const HIZ_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Mip count of a `width` x `height` pyramid, down to 1x1.
+pub fn hiz_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+pub struct HiZPyramid {
+    texture: wgpu::Texture,
+    /// One view per mip, for both the build and the cull pass.
+    mip_views: Vec<wgpu::TextureView>,
+    view: wgpu::TextureView,
+    /// Set once the pyramid holds a frame's depth.
+    built: bool,
+    /// View-projection the depth it holds was rendered with.
+    view_proj: [f32; 16],
+}
+
+impl HiZPyramid {
+    /// A pyramid for a `width` x `height` depth buffer; it holds nothing until built.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("hiz_pyramid"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: hiz_levels(width, height),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HIZ_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let mip_views = (0..texture.mip_level_count())
+            .map(|mip| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("hiz_mip"),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let view = texture.create_view(&Default::default());
+        Self { texture, mip_views, view, built: false, view_proj: [0.0; 16] }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+
+    pub fn levels(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// View-projection of the depth it was last built from; the cull pass projects boxes with it.
+    pub fn view_proj(&self) -> &[f32; 16] {
+        &self.view_proj
+    }
+
+    /// View of every mip, as the cull pass samples it.
+    pub(crate) fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+/// Compute pipelines that fill a [`HiZPyramid`] from a depth buffer. Like the cull pass, it needs a
+/// device with `GpuCullPass::is_supported` and `AdapterCapabilities::compute_depth_loads` (the GL backend
+/// cannot load depth textures in compute).
+pub struct HiZPass {
+    copy_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    copy_layout: wgpu::BindGroupLayout,
+    downsample_layout: wgpu::BindGroupLayout,
+}
+
+impl HiZPass {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("hiz_shader"),
+            source: wgpu::ShaderSource::Wgsl(HIZ_SHADER.into()),
+        });
+        let entry = |binding: u32, ty: wgpu::BindingType| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty,
+            count: None,
+        };
+        let texture = |sample_type| wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let storage = wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: HIZ_FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        };
+        let copy_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hiz_copy_bind_group_layout"),
+            entries: &[entry(0, texture(wgpu::TextureSampleType::Depth)), entry(1, storage)],
+        });
+        let downsample_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hiz_downsample_bind_group_layout"),
+            entries: &[entry(1, storage), entry(2, texture(wgpu::TextureSampleType::Float { filterable: false }))],
+        });
+        let pipeline = |label: &str, layout: &wgpu::BindGroupLayout, entry_point: &str| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        Self {
+            copy_pipeline: pipeline("hiz_copy_pipeline", &copy_layout, "cs_copy_depth"),
+            downsample_pipeline: pipeline("hiz_downsample_pipeline", &downsample_layout, "cs_downsample"),
+            copy_layout,
+            downsample_layout,
+        }
+    }
+
+    /// Copy `depth_view` (the size of `pyramid`, rendered with `view_proj`) into mip 0 and downsample the
+    /// rest of the chain, for the next `GpuCullPass::encode` or `encode_late`.
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        depth_view: &wgpu::TextureView,
+        view_proj: &[f32; 16],
+        pyramid: &mut HiZPyramid,
+    ) {
+        let bind_groups: Vec<wgpu::BindGroup> = (0..pyramid.mip_views.len())
+            .map(|mip| {
+                let dst = wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&pyramid.mip_views[mip]),
+                };
+                if mip == 0 {
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("hiz_copy_bind_group"),
+                        layout: &self.copy_layout,
+                        entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(depth_view) }, dst],
+                    })
+                } else {
+                    let src = wgpu::BindingResource::TextureView(&pyramid.mip_views[mip - 1]);
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: Some("hiz_downsample_bind_group"),
+                        layout: &self.downsample_layout,
+                        entries: &[dst, wgpu::BindGroupEntry { binding: 2, resource: src }],
+                    })
+                }
+            })
+            .collect();
+        let (width, height) = pyramid.size();
+        let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("hiz_build"), timestamp_writes: None });
+        for (mip, bind_group) in bind_groups.iter().enumerate() {
+            cp.set_pipeline(if mip == 0 { &self.copy_pipeline } else { &self.downsample_pipeline });
+            cp.set_bind_group(0, bind_group, &[]);
+            let (w, h) = ((width >> mip).max(1), (height >> mip).max(1));
+            cp.dispatch_workgroups(w.div_ceil(WORKGROUP_SIZE), h.div_ceil(WORKGROUP_SIZE), 1);
+        }
+        drop(cp);
+        pyramid.built = true;
+        pyramid.view_proj = *view_proj;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_reach_one_texel() {
+        assert_eq!(hiz_levels(1, 1), 1);
+        assert_eq!(hiz_levels(16, 16), 5);
+        assert_eq!(hiz_levels(1920, 1080), 11);
+        assert_eq!(hiz_levels(5, 1), 3);
+    }
+}
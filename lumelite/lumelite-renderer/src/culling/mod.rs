@@ -0,0 +1,794 @@
+//! GPU frustum culling: a compute pass tests each mesh's bounds against the camera frustum and writes
+//! one `DrawIndexedIndirectArgs` per [`MeshDraw`] (instance count 0 when culled), which the GBuffer
+//! pass then draws with `draw_indexed_indirect`, or with one `multi_draw_indexed_indirect` per run of
+//! meshes sharing buffers and textures when the device has [`MULTI_DRAW_FEATURES`] (see
+//! [`instanced_draws_supported`]). Enabled by `LumeliteConfig::gpu_culling`; with
+//! `LumeliteConfig::occlusion_culling`, meshes behind the previous frame's depth ([`HiZPyramid`], built
+//! by [`HiZPass`]) are culled as well. Two-pass occlusion culling ([`GpuCullPass::encode_early`] and
+//! [`GpuCullPass::encode_late`]) instead tests against the current frame's depth.
+
+mod hiz;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+use wgpu::CommandEncoder;
+
+use crate::config::AdapterCapabilities;
+use crate::gbuffer::MeshDraw;
+use crate::timings::{MAP_DONE, MAP_FAILED, MAP_PENDING};
+
+pub use hiz::{hiz_levels, HiZPass, HiZPyramid, HIZ_FORMAT};
+
+const CULL_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/cull.wgsl"));
+
+/// Must match `@workgroup_size` of `cs_cull` in cull.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Bytes of indirect args per mesh (`wgpu::util::DrawIndexedIndirectArgs`).
+pub const DRAW_ARGS_SIZE: u64 = 20;
+
+/// Device features that let the GBuffer and unlit passes draw a run of meshes with one
+/// `multi_draw_indexed_indirect`; hosts must request them in `DeviceDescriptor::required_features`.
+/// `INDIRECT_FIRST_INSTANCE` alone still gives [`instanced_draws_supported`], with an indirect draw per
+/// mesh.
+pub const MULTI_DRAW_FEATURES: wgpu::Features =
+    wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+/// Whether the GBuffer and unlit passes draw mesh `i` as instance `i` of one storage buffer of
+/// per-mesh data (bound once per pass) rather than with a uniform buffer and bind group per mesh.
+/// Needs `INDIRECT_FIRST_INSTANCE`, which the cull pass then sets to the mesh index, vertex-stage
+/// storage buffers, and an `instance_index` that includes an indirect draw's first instance (not on
+/// DX12).
+pub fn instanced_draws_supported(device: &wgpu::Device, capabilities: &AdapterCapabilities) -> bool {
+    let needed = wgpu::DownlevelFlags::VERTEX_STORAGE
+        | wgpu::DownlevelFlags::VERTEX_AND_INSTANCE_INDEX_RESPECTS_RESPECTIVE_FIRST_VALUE_IN_INDIRECT_DRAW;
+    device.features().contains(wgpu::Features::INDIRECT_FIRST_INSTANCE) && capabilities.downlevel_flags.contains(needed)
+}
+
+/// Layout of `DrawInput` in cull.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawInput {
+    model: [f32; 16],
+    bounds_min: [f32; 3],
+    index_count: u32,
+    bounds_max: [f32; 3],
+    has_bounds: u32,
+}
+
+impl DrawInput {
+    fn new(mesh: &MeshDraw) -> Self {
+        let (bounds_min, bounds_max) = mesh.bounds.map_or(([0.0; 3], [0.0; 3]), |b| (b.min, b.max));
+        Self {
+            model: mesh.transform,
+            bounds_min,
+            index_count: mesh.index_count,
+            bounds_max,
+            has_bounds: mesh.bounds.is_some() as u32,
+        }
+    }
+}
+
+/// Layout of `CullParams` in cull.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    view_proj: [f32; 16],
+    hiz_view_proj: [f32; 16],
+    draw_count: u32,
+    hiz_levels: u32,
+    hiz_size: [u32; 2],
+    phase: u32,
+    _pad: [u32; 3],
+}
+
+/// Stats readback buffers in flight; a frame's stats are dropped when all of them are still busy.
+const STATS_READBACK_SLOTS: usize = 3;
+
+/// `CullParams::phase`.
+const PHASE_SINGLE: u32 = 0;
+const PHASE_EARLY: u32 = 1;
+const PHASE_LATE: u32 = 2;
+
+/// What a cull dispatch works on: the args of `meshes[i]`, tested against `view_proj`, are written at
+/// `i * DRAW_ARGS_SIZE` in `indirect` (see [`GpuCullPass::create_indirect_buffer`]).
+#[derive(Clone, Copy)]
+pub struct CullDraws<'a> {
+    pub meshes: &'a [MeshDraw],
+    pub view_proj: &'a [f32; 16],
+    pub indirect: &'a wgpu::Buffer,
+}
+
+/// Phase-specific inputs of `GpuCullPass::dispatch`.
+struct Phase<'a> {
+    /// `PHASE_*`.
+    id: u32,
+    hiz: Option<&'a HiZPyramid>,
+    visibility: &'a wgpu::Buffer,
+}
+
+/// Inputs and parameters of one phase's dispatch, rewritten by every dispatch of that phase.
+struct DispatchBuffers {
+    /// `DrawInput` per mesh, grown to the largest mesh count seen.
+    inputs: wgpu::Buffer,
+    params: wgpu::Buffer,
+}
+
+/// Counts of the last cull (a [`GpuCullPass::encode`], or an `encode_early` and `encode_late` pair):
+/// meshes by outcome, which add up to the mesh count, and the triangles drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    /// Drawn by the single pass, or by the early pass of two-pass culling (visible last frame).
+    pub drawn: u32,
+    /// Bounds outside the camera frustum.
+    pub frustum_culled: u32,
+    /// Inside the frustum but behind the Hi-Z pyramid's depth.
+    pub occluded: u32,
+    /// Drawn by the late pass of two-pass culling: hidden last frame, visible now.
+    pub disoccluded: u32,
+    /// Triangles of every drawn mesh (`MeshDraw::index_count / 3`), by both passes of two-pass culling.
+    pub triangles: u32,
+}
+
+impl CullStats {
+    /// Stats from the counters of cull.wgsl's `stats`, in field order.
+    fn from_counters(bytes: &[u8]) -> Self {
+        let [drawn, frustum_culled, occluded, disoccluded, triangles]: [u32; 5] = bytemuck::pod_read_unaligned(bytes);
+        Self { drawn, frustum_culled, occluded, disoccluded, triangles }
+    }
+}
+
+enum StatsSlot {
+    Free,
+    /// Stats copied by a frame's encoder; mapped by `stats_submitted` once it has been submitted.
+    Copied,
+    Mapping(Arc<AtomicU8>),
+}
+
+/// Frustum planes (left, right, bottom, top, near, far) of a column-major view-projection with
+/// wgpu's [0, 1] depth range [Gribb & Hartmann 2001]. A point is inside when `dot(xyz, p) + w >= 0`
+/// for every plane; planes are not normalized.
+pub fn frustum_planes(view_proj: &[f32; 16]) -> [[f32; 4]; 6] {
+    let row = |r: usize| [view_proj[r], view_proj[4 + r], view_proj[8 + r], view_proj[12 + r]];
+    let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+    let add = |a: [f32; 4], b: [f32; 4]| std::array::from_fn(|i| a[i] + b[i]);
+    let sub = |a: [f32; 4], b: [f32; 4]| std::array::from_fn(|i| a[i] - b[i]);
+    [add(r3, r0), sub(r3, r0), add(r3, r1), sub(r3, r1), r2, sub(r3, r2)]
+}
+
+pub struct GpuCullPass {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    max_workgroups: u32,
+    /// Bound in place of the pyramid when occlusion is not tested.
+    empty_hiz: HiZPyramid,
+    /// `CullStats` as five counters, cleared by every `encode` and `encode_early`.
+    stats_buf: wgpu::Buffer,
+    /// Non-blocking readback of `stats_buf` (`copy_stats`, `stats_submitted` and `poll_stats`), like
+    /// `GpuTimer`'s.
+    stats_readback: Vec<wgpu::Buffer>,
+    stats_slots: Vec<StatsSlot>,
+    latest_stats: Option<CullStats>,
+    /// Bound in place of the visibility buffer by single-pass culling.
+    empty_visibility: wgpu::Buffer,
+    /// Indexed by phase. `queue.write_buffer` lands before the commands of the next submission, so a
+    /// phase must run at most once per submission; one frame dispatches each at most once.
+    dispatch_buffers: Mutex<[Option<DispatchBuffers>; 3]>,
+}
+
+impl GpuCullPass {
+    /// Culling needs compute shaders and indirect draws (one `draw_indexed_indirect` per mesh).
+    pub fn is_supported(capabilities: &AdapterCapabilities) -> bool {
+        let needed = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        capabilities.downlevel_flags.contains(needed)
+    }
+
+    /// `capabilities` decide, with the device's features, whether the args carry the mesh index as
+    /// their first instance ([`instanced_draws_supported`]); the passes drawing them must agree.
+    pub fn new(device: &wgpu::Device, capabilities: &AdapterCapabilities) -> Result<Self, String> {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("cull_shader"),
+            source: wgpu::ShaderSource::Wgsl(CULL_SHADER.into()),
+        });
+        let storage = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("cull_bind_group_layout"),
+            entries: &[
+                storage(0, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<CullParams>() as u64),
+                    },
+                    count: None,
+                },
+                storage(2, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                storage(4, false),
+                storage(5, false),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("cull_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("cull_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_cull"),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &HashMap::from([(
+                    "FIRST_INSTANCE".to_string(),
+                    if instanced_draws_supported(device, capabilities) { 1.0 } else { 0.0 },
+                )]),
+                ..Default::default()
+            },
+            cache: None,
+        });
+        Ok(Self {
+            pipeline,
+            bind_group_layout,
+            max_workgroups: device.limits().max_compute_workgroups_per_dimension,
+            empty_hiz: HiZPyramid::new(device, 1, 1),
+            stats_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("cull_stats"),
+                size: std::mem::size_of::<CullStats>() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            stats_readback: (0..STATS_READBACK_SLOTS)
+                .map(|_| {
+                    device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("cull_stats_readback"),
+                        size: std::mem::size_of::<CullStats>() as u64,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    })
+                })
+                .collect(),
+            stats_slots: (0..STATS_READBACK_SLOTS).map(|_| StatsSlot::Free).collect(),
+            latest_stats: None,
+            empty_visibility: Self::create_visibility_buffer(device, 1),
+            dispatch_buffers: Mutex::new([None, None, None]),
+        })
+    }
+
+    /// Indirect args buffer for up to `draw_count` meshes.
+    pub fn create_indirect_buffer(device: &wgpu::Device, draw_count: usize) -> wgpu::Buffer {
+        // Only tests read the args back.
+        let readback = if cfg!(test) { wgpu::BufferUsages::COPY_SRC } else { wgpu::BufferUsages::empty() };
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lumelite_cull_indirect"),
+            size: draw_count.max(1) as u64 * DRAW_ARGS_SIZE,
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::STORAGE | readback,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Per-mesh visibility of two-pass culling for up to `draw_count` meshes, carried from one frame's
+    /// `encode_late` to the next frame's `encode_early`. Starts as all hidden, so the first frame draws
+    /// everything in the late pass.
+    pub fn create_visibility_buffer(device: &wgpu::Device, draw_count: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lumelite_cull_visibility"),
+            size: draw_count.max(1) as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Write the indirect args of `draws`. Meshes without `MeshDraw::bounds` are always drawn. With a
+    /// built `hiz` (of an earlier frame's depth), meshes behind it, projected with the view-projection it
+    /// was built with, are culled too.
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        draws: CullDraws,
+        hiz: Option<&HiZPyramid>,
+    ) -> Result<(), String> {
+        let hiz = hiz.filter(|h| h.is_built());
+        let phase = Phase { id: PHASE_SINGLE, hiz, visibility: &self.empty_visibility };
+        self.dispatch(encoder, device, queue, draws, phase)
+    }
+
+    /// First pass of two-pass occlusion culling: args in `draws.indirect` draw the meshes in the frustum
+    /// that `visibility` (see [`Self::create_visibility_buffer`]) marks visible last frame, and those
+    /// without bounds. `draws.meshes` must be in the same order every frame.
+    pub fn encode_early(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        draws: CullDraws,
+        visibility: &wgpu::Buffer,
+    ) -> Result<(), String> {
+        self.dispatch(encoder, device, queue, draws, Phase { id: PHASE_EARLY, hiz: None, visibility })
+    }
+
+    /// Second pass of two-pass occlusion culling, after the early meshes are drawn and `hiz` is built
+    /// from their depth: args in a second indirect buffer (`draws.indirect`) draw the meshes the early
+    /// pass skipped that are not behind `hiz`, and `visibility` is updated for the next frame.
+    pub fn encode_late(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        draws: CullDraws,
+        visibility: &wgpu::Buffer,
+        hiz: &HiZPyramid,
+    ) -> Result<(), String> {
+        if !hiz.is_built() {
+            return Err("cull: the late pass needs a built Hi-Z pyramid".to_string());
+        }
+        self.dispatch(encoder, device, queue, draws, Phase { id: PHASE_LATE, hiz: Some(hiz), visibility })
+    }
+
+    fn dispatch(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        draws: CullDraws,
+        phase: Phase,
+    ) -> Result<(), String> {
+        let CullDraws { meshes, view_proj, indirect } = draws;
+        let Phase { id: phase, hiz, visibility } = phase;
+        if phase != PHASE_LATE {
+            encoder.clear_buffer(&self.stats_buf, 0, None);
+        }
+        if meshes.is_empty() {
+            return Ok(());
+        }
+        if phase != PHASE_SINGLE && (meshes.len() as u64) * 4 > visibility.size() {
+            return Err(format!("cull: visibility buffer too small for {} meshes", meshes.len()));
+        }
+        if (meshes.len() as u64) * DRAW_ARGS_SIZE > indirect.size() {
+            return Err(format!(
+                "cull: {} meshes need {} bytes of indirect args, buffer has {}",
+                meshes.len(),
+                meshes.len() as u64 * DRAW_ARGS_SIZE,
+                indirect.size()
+            ));
+        }
+        let workgroups = (meshes.len() as u32).div_ceil(WORKGROUP_SIZE);
+        if workgroups > self.max_workgroups {
+            return Err(format!("cull: {} meshes exceed the dispatch limit", meshes.len()));
+        }
+        let inputs: Vec<DrawInput> = meshes.iter().map(DrawInput::new).collect();
+        let inputs_size = std::mem::size_of_val(inputs.as_slice()) as u64;
+        let mut dispatch_buffers = self.dispatch_buffers.lock().map_err(|e| format!("cull: dispatch buffers lock: {}", e))?;
+        let buffers = &mut dispatch_buffers[phase as usize];
+        if buffers.as_ref().is_none_or(|b| b.inputs.size() < inputs_size) {
+            *buffers = Some(DispatchBuffers {
+                inputs: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("cull_inputs"),
+                    size: inputs_size.next_power_of_two(),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                params: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("cull_params"),
+                    size: std::mem::size_of::<CullParams>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+            });
+        }
+        let DispatchBuffers { inputs: inputs_buf, params: params_buf } = buffers.as_ref().unwrap();
+        queue.write_buffer(inputs_buf, 0, bytemuck::cast_slice(&inputs));
+        let params = CullParams {
+            planes: frustum_planes(view_proj),
+            view_proj: *view_proj,
+            hiz_view_proj: hiz.map_or(*view_proj, |h| *h.view_proj()),
+            draw_count: meshes.len() as u32,
+            hiz_levels: hiz.map_or(0, HiZPyramid::levels),
+            hiz_size: hiz.map_or([0; 2], |h| h.size().into()),
+            phase,
+            _pad: [0; 3],
+        };
+        queue.write_buffer(params_buf, 0, bytemuck::bytes_of(&params));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("cull_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: inputs_buf.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: params_buf.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: indirect.as_entire_binding() },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(hiz.unwrap_or(&self.empty_hiz).view()),
+                },
+                wgpu::BindGroupEntry { binding: 4, resource: self.stats_buf.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 5, resource: visibility.as_entire_binding() },
+            ],
+        });
+        let mut cp = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("gpu_cull"),
+            timestamp_writes: None,
+        });
+        cp.set_pipeline(&self.pipeline);
+        cp.set_bind_group(0, &bind_group, &[]);
+        cp.dispatch_workgroups(workgroups, 1, 1);
+        Ok(())
+    }
+
+    /// Read back the stats of the last `encode` once it has been submitted. Blocks until the GPU is
+    /// done, so this is for debug overlays and tests rather than every frame.
+    pub fn read_stats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<CullStats, String> {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cull_stats_readback"),
+            size: self.stats_buf.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("cull_stats_readback") });
+        encoder.copy_buffer_to_buffer(&self.stats_buf, 0, &staging, 0, self.stats_buf.size());
+        queue.submit([encoder.finish()]);
+        let slice = staging.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv()
+            .map_err(|e| format!("cull stats readback: {}", e))?
+            .map_err(|e| format!("cull stats readback: {}", e))?;
+        let stats = CullStats::from_counters(&slice.get_mapped_range());
+        staging.unmap();
+        Ok(stats)
+    }
+
+    /// Copy the stats of the last cull aside, to put back with [`Self::restore_stats`] after culls that
+    /// must not show in [`Self::read_stats`] (e.g. `Renderer::prewarm`).
+    pub fn save_stats(&self, device: &wgpu::Device, encoder: &mut CommandEncoder) -> wgpu::Buffer {
+        let saved = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cull_stats_saved"),
+            size: self.stats_buf.size(),
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(&self.stats_buf, 0, &saved, 0, self.stats_buf.size());
+        saved
+    }
+
+    /// Put back stats copied by [`Self::save_stats`].
+    pub fn restore_stats(&self, encoder: &mut CommandEncoder, saved: &wgpu::Buffer) {
+        encoder.copy_buffer_to_buffer(saved, 0, &self.stats_buf, 0, self.stats_buf.size());
+    }
+
+    /// Queue the stats of the cull just encoded for a later [`Self::poll_stats`], without waiting for
+    /// the GPU. Dropped when every readback is still busy.
+    pub fn copy_stats(&mut self, encoder: &mut CommandEncoder) {
+        let Some(index) = self.stats_slots.iter().position(|s| matches!(s, StatsSlot::Free)) else { return };
+        encoder.copy_buffer_to_buffer(&self.stats_buf, 0, &self.stats_readback[index], 0, self.stats_buf.size());
+        self.stats_slots[index] = StatsSlot::Copied;
+    }
+
+    /// Start mapping the stats queued by [`Self::copy_stats`] since the last call. Call only once the
+    /// encoders holding those copies have been submitted; mapping a buffer that an unsubmitted copy
+    /// still writes fails validation on submit.
+    pub fn stats_submitted(&mut self) {
+        for (slot, buffer) in self.stats_slots.iter_mut().zip(&self.stats_readback) {
+            if matches!(slot, StatsSlot::Copied) {
+                let state = Arc::new(AtomicU8::new(MAP_PENDING));
+                let callback_state = Arc::clone(&state);
+                buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                    callback_state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+                });
+                *slot = StatsSlot::Mapping(state);
+            }
+        }
+    }
+
+    /// Collect the stats readbacks that finished mapping. Returns [`Self::latest_stats`].
+    pub fn poll_stats(&mut self, device: &wgpu::Device) -> Option<CullStats> {
+        device.poll(wgpu::Maintain::Poll);
+        for (slot, buffer) in self.stats_slots.iter_mut().zip(&self.stats_readback) {
+            let StatsSlot::Mapping(state) = slot else { continue };
+            match state.load(Ordering::Acquire) {
+                MAP_DONE => {
+                    self.latest_stats = Some(CullStats::from_counters(&buffer.slice(..).get_mapped_range()));
+                    buffer.unmap();
+                    *slot = StatsSlot::Free;
+                }
+                MAP_FAILED => *slot = StatsSlot::Free,
+                _ => {}
+            }
+        }
+        self.latest_stats
+    }
+
+    /// Stats of the most recent cull read back by [`Self::poll_stats`], a frame or more late.
+    pub fn latest_stats(&self) -> Option<CullStats> {
+        self.latest_stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounds::Aabb;
+    use crate::test_support;
+
+    // Looking down -z: depth = 0.5 - 0.5 * z, x and y in [-1, 1].
+    #[rustfmt::skip]
+    const VIEW_PROJ: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 1.0];
+
+    #[test]
+    fn frustum_planes_bound_the_clip_volume() {
+        let planes = frustum_planes(&VIEW_PROJ);
+        let inside = |p: [f32; 3]| planes.iter().all(|pl| pl[0] * p[0] + pl[1] * p[1] + pl[2] * p[2] + pl[3] >= 0.0);
+        assert!(inside([0.0, 0.0, 0.0]));
+        assert!(inside([0.9, -0.9, 0.9]));
+        assert!(!inside([1.5, 0.0, 0.0]));
+        assert!(!inside([0.0, -1.5, 0.0]));
+        // Depth 0.5 - 0.5 * z leaves [0, 1] outside z in [-1, 1].
+        assert!(!inside([0.0, 0.0, 1.5]));
+        assert!(!inside([0.0, 0.0, -1.5]));
+    }
+
+    #[test]
+    fn layouts_match_wgsl() {
+        assert_eq!(std::mem::size_of::<DrawInput>(), 96);
+        assert_eq!(std::mem::size_of::<CullParams>(), 256);
+        assert_eq!(DRAW_ARGS_SIZE as usize, std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>());
+    }
+
+    #[test]
+    fn culling_needs_compute_and_indirect_execution() {
+        assert!(GpuCullPass::is_supported(&AdapterCapabilities::default()));
+        let downlevel_flags = wgpu::DownlevelFlags::compliant() - wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        assert!(!GpuCullPass::is_supported(&AdapterCapabilities { downlevel_flags, ..Default::default() }));
+    }
+
+    /// A quad translated by `offset`; only its transform, bounds and index count are read.
+    fn test_mesh(device: &wgpu::Device, queue: &wgpu::Queue, offset: [f32; 3], bounds: Option<Aabb>) -> MeshDraw {
+        let view = test_support::texture(device, queue, [255; 4]);
+        let mesh = test_support::mesh(device, &[[0.0; 8]; 4], &[0, 1, 2, 0, 2, 3], test_support::pbr_textures(view));
+        let [x, y, z] = offset;
+        MeshDraw { transform: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, x, y, z, 1.0], bounds, ..mesh }
+    }
+
+    /// Run `pass` over `meshes` and read back each mesh's instance count.
+    fn instance_counts(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pass: &GpuCullPass,
+        meshes: &[MeshDraw],
+        hiz: Option<&HiZPyramid>,
+    ) -> Vec<u32> {
+        let indirect = GpuCullPass::create_indirect_buffer(device, meshes.len());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let draws = CullDraws { meshes, view_proj: &VIEW_PROJ, indirect: &indirect };
+        pass.encode(&mut encoder, device, queue, draws, hiz).unwrap();
+        read_instance_counts(device, queue, encoder, &indirect)
+    }
+
+    /// Submit `encoder`, then read back the instance count of each draw in `indirect`.
+    fn read_instance_counts(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: wgpu::CommandEncoder,
+        indirect: &wgpu::Buffer,
+    ) -> Vec<u32> {
+        read_args(device, queue, encoder, indirect).iter().map(|a| a[1]).collect()
+    }
+
+    /// Submit `encoder`, then read back the args of each draw in `indirect` (for six-index meshes).
+    fn read_args(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut encoder: wgpu::CommandEncoder,
+        indirect: &wgpu::Buffer,
+    ) -> Vec<[u32; 5]> {
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: indirect.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_buffer_to_buffer(indirect, 0, &readback, 0, indirect.size());
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let args: Vec<[u32; 5]> = bytemuck::cast_slice(&readback.slice(..).get_mapped_range()).to_vec();
+        assert!(args.iter().all(|a| a[0] == 6 && a[2..4] == [0, 0]));
+        args
+    }
+
+    /// A Hi-Z pyramid of a 13x9 depth buffer (odd sizes exercise the pyramid's edge texels) cleared to
+    /// `depth_value`.
+    fn hiz_at(device: &wgpu::Device, queue: &wgpu::Queue, depth_value: f32) -> HiZPyramid {
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 13, height: 9, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: crate::gbuffer::GBUFFER_DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let depth_view = depth.create_view(&Default::default());
+        let mut hiz = HiZPyramid::new(device, 13, 9);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(depth_value), store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        HiZPass::new(device).encode(&mut encoder, device, &depth_view, &VIEW_PROJ, &mut hiz);
+        queue.submit([encoder.finish()]);
+        assert!(hiz.is_built());
+        hiz
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or it cannot run compute/indirect draws.
+    #[test]
+    fn culled_meshes_get_zero_instances() {
+        let Some(adapter) = test_support::adapter("culled_meshes_get_zero_instances") else {
+            return;
+        };
+        let needed = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        if !adapter.get_downlevel_capabilities().flags.contains(needed) {
+            eprintln!("culled_meshes_get_zero_instances: no compute/indirect support, skipped");
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        let unit = Aabb { min: [-0.5; 3], max: [0.5; 3] };
+        let mesh = |x: f32, bounds: Option<Aabb>| test_mesh(&device, &queue, [x, 0.0, 0.0], bounds);
+        // Inside, straddling the right plane, fully outside, and outside but without bounds.
+        let meshes = [mesh(0.0, Some(unit)), mesh(1.2, Some(unit)), mesh(3.0, Some(unit)), mesh(3.0, None)];
+        let mut pass = GpuCullPass::new(&device, &AdapterCapabilities::default()).unwrap();
+        assert_eq!(instance_counts(&device, &queue, &pass, &meshes, None), vec![1, 1, 0, 1]);
+        let stats = pass.read_stats(&device, &queue).unwrap();
+        assert_eq!(stats, CullStats { drawn: 3, frustum_culled: 1, occluded: 0, disoccluded: 0, triangles: 6 });
+        // The same stats without blocking, once the copy's map has finished.
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pass.copy_stats(&mut encoder);
+        // Polling before the submit must leave the buffer the copy writes unmapped.
+        assert_eq!(pass.poll_stats(&device), None);
+        queue.submit([encoder.finish()]);
+        pass.stats_submitted();
+        device.poll(wgpu::Maintain::Wait);
+        assert_eq!(pass.poll_stats(&device), Some(stats));
+    }
+
+    /// Skipped without an adapter or on GL, whose compute shaders cannot load depth textures.
+    #[test]
+    fn meshes_behind_the_hiz_are_occluded() {
+        let Some(adapter) = test_support::adapter("meshes_behind_the_hiz_are_occluded") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "meshes_behind_the_hiz_are_occluded") {
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        let hiz = hiz_at(&device, &queue, 0.3);
+        assert_eq!(hiz.levels(), 4);
+
+        let small = Aabb { min: [-0.1; 3], max: [0.1; 3] };
+        let large = Aabb { min: [-0.9, -0.9, -0.1], max: [0.9, 0.9, 0.1] };
+        let mesh = |z: f32, bounds: Option<Aabb>| test_mesh(&device, &queue, [0.0, 0.0, z], bounds);
+        // In front of the depth (0.15..0.25), behind it (0.75..0.85) small and screen-filling, and
+        // behind it without bounds.
+        let meshes = [mesh(0.6, Some(small)), mesh(-0.6, Some(small)), mesh(-0.6, Some(large)), mesh(-0.6, None)];
+        let pass = GpuCullPass::new(&device, &AdapterCapabilities::default()).unwrap();
+        assert_eq!(instance_counts(&device, &queue, &pass, &meshes, Some(&hiz)), vec![1, 0, 0, 1]);
+        let stats = pass.read_stats(&device, &queue).unwrap();
+        assert_eq!(stats, CullStats { drawn: 2, frustum_culled: 0, occluded: 2, disoccluded: 0, triangles: 4 });
+        // Without the pyramid, only the frustum is tested.
+        assert_eq!(instance_counts(&device, &queue, &pass, &meshes, None), vec![1, 1, 1, 1]);
+    }
+
+    /// Skipped when no adapter is available or on GL (no depth texture loads in compute).
+    #[test]
+    fn two_pass_draws_disoccluded_meshes_late() {
+        let Some(adapter) = test_support::adapter("two_pass_draws_disoccluded_meshes_late") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "two_pass_draws_disoccluded_meshes_late") {
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        // Stands in for the depth the early pass draws.
+        let hiz = hiz_at(&device, &queue, 0.3);
+        let small = Aabb { min: [-0.1; 3], max: [0.1; 3] };
+        let mesh = |z: f32, bounds: Option<Aabb>| test_mesh(&device, &queue, [0.0, 0.0, z], bounds);
+        // In front of the depth, behind it, and without bounds.
+        let meshes = [mesh(0.6, Some(small)), mesh(-0.6, Some(small)), mesh(0.0, None)];
+        let pass = GpuCullPass::new(&device, &AdapterCapabilities::default()).unwrap();
+        let visibility = GpuCullPass::create_visibility_buffer(&device, meshes.len());
+        let frame = || {
+            let early = GpuCullPass::create_indirect_buffer(&device, meshes.len());
+            let late = GpuCullPass::create_indirect_buffer(&device, meshes.len());
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let draws = |indirect| CullDraws { meshes: &meshes, view_proj: &VIEW_PROJ, indirect };
+            pass.encode_early(&mut encoder, &device, &queue, draws(&early), &visibility).unwrap();
+            let early = read_instance_counts(&device, &queue, encoder, &early);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            pass.encode_late(&mut encoder, &device, &queue, draws(&late), &visibility, &hiz).unwrap();
+            let late = read_instance_counts(&device, &queue, encoder, &late);
+            (early, late, pass.read_stats(&device, &queue).unwrap())
+        };
+        // Nothing was visible before the first frame, so the mesh in front is disoccluded.
+        let (early, late, stats) = frame();
+        assert_eq!((early, late), (vec![0, 0, 1], vec![1, 0, 0]));
+        assert_eq!(stats, CullStats { drawn: 1, frustum_culled: 0, occluded: 1, disoccluded: 1, triangles: 4 });
+        // Then it is drawn early, and the late pass has nothing left to draw.
+        let (early, late, stats) = frame();
+        assert_eq!((early, late), (vec![1, 0, 1], vec![0, 0, 0]));
+        assert_eq!(stats, CullStats { drawn: 2, frustum_culled: 0, occluded: 1, disoccluded: 0, triangles: 4 });
+    }
+
+    /// Skipped without an adapter, compute/indirect support or instanced draws.
+    #[test]
+    fn instanced_draws_get_the_mesh_index_as_first_instance() {
+        let test = "instanced_draws_get_the_mesh_index_as_first_instance";
+        let Some(adapter) = test_support::adapter(test) else {
+            return;
+        };
+        let capabilities = AdapterCapabilities::from_adapter(&adapter);
+        if !GpuCullPass::is_supported(&capabilities) {
+            eprintln!("{}: no compute/indirect support, skipped", test);
+            return;
+        }
+        let unit = Aabb { min: [-0.5; 3], max: [0.5; 3] };
+        let first_instances = |device: &wgpu::Device, queue: &wgpu::Queue| {
+            let mesh = |x: f32| test_mesh(device, queue, [x, 0.0, 0.0], Some(unit));
+            let meshes = [mesh(0.0), mesh(3.0), mesh(0.0)];
+            let pass = GpuCullPass::new(device, &capabilities).unwrap();
+            let indirect = GpuCullPass::create_indirect_buffer(device, meshes.len());
+            let mut encoder = device.create_command_encoder(&Default::default());
+            pass.encode(&mut encoder, device, queue, CullDraws { meshes: &meshes, view_proj: &VIEW_PROJ, indirect: &indirect }, None).unwrap();
+            read_args(device, queue, encoder, &indirect).iter().map(|a| a[4]).collect::<Vec<_>>()
+        };
+        // Without the feature, first_instance must stay 0.
+        let (device, queue) = test_support::device(&adapter);
+        assert!(!instanced_draws_supported(&device, &capabilities));
+        assert_eq!(first_instances(&device, &queue), vec![0, 0, 0]);
+        let features = wgpu::Features::INDIRECT_FIRST_INSTANCE;
+        let Some((device, queue)) = test_support::device_with_features(&adapter, features, test) else {
+            return;
+        };
+        if !instanced_draws_supported(&device, &capabilities) {
+            eprintln!("{}: no vertex storage or indirect instance_index, skipped", test);
+            return;
+        }
+        assert_eq!(first_instances(&device, &queue), vec![0, 1, 2]);
+    }
+}
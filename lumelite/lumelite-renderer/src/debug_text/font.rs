@@ -0,0 +1,95 @@
+//! 5x7 bitmap font of [`super::DebugText`]: every printable ASCII character.
+
+/// Glyph cell in the atlas: the 5x7 glyph plus one blank column and row.
+pub(crate) const CELL: (u32, u32) = (6, 8);
+pub(crate) const GLYPH: (u32, u32) = (5, 7);
+
+const FIRST: char = ' ';
+
+/// Glyphs of ' ' through '~', five columns each from left to right, the top row in bit 0.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], // ' ' ! "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], // # $ %
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00], // & ' (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08], // ) * +
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], // , - .
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], // / 0 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10], // 2 3 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03], // 5 6 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], // 8 9 :
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], // ; < =
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E], // > ? @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22], // A B C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01], // D E F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], // G H I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40], // J K L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E], // M N O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], // P Q R
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], // S T U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F], [0x63, 0x14, 0x08, 0x14, 0x63], // V W X
+    [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00], // Y Z [
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], // \ ] ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], // _ ` a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F], // b c d
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E], // e f g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], // h i j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], // k l m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08], // n o p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20], // q r s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], // t u v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], // w x y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00], // z { |
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],                                 // } ~
+];
+
+/// Atlas cell of a fully set block, for panels and bars; it follows the glyphs.
+pub(crate) const SOLID_CELL: u32 = GLYPHS.len() as u32;
+
+/// Atlas cell of `c`, or None for characters the font lacks (drawn as blanks).
+pub(crate) fn glyph_cell(c: char) -> Option<u32> {
+    let index = (c as u32).checked_sub(FIRST as u32)?;
+    (index < SOLID_CELL).then_some(index)
+}
+
+/// `R8Unorm` atlas with every glyph and the solid block in one row of cells: (width, height, texels).
+pub(crate) fn atlas() -> (u32, u32, Vec<u8>) {
+    let (width, height) = ((SOLID_CELL + 1) * CELL.0, CELL.1);
+    let mut texels = vec![0u8; (width * height) as usize];
+    let solid = [0x7F; 5];
+    for (cell, columns) in GLYPHS.iter().chain([&solid]).enumerate() {
+        for (x, column) in columns.iter().enumerate() {
+            for y in 0..GLYPH.1 as usize {
+                if column >> y & 1 != 0 {
+                    texels[y * width as usize + cell * CELL.0 as usize + x] = 255;
+                }
+            }
+        }
+    }
+    (width, height, texels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_holds_every_glyph_and_a_solid_block() {
+        let (width, height, texels) = atlas();
+        assert_eq!((width, height), ((SOLID_CELL + 1) * 6, 8));
+        let texel = |cell: u32, x: u32, y: u32| texels[(y * width + cell * CELL.0 + x) as usize];
+        // '1': the stem in column 2, the base spanning columns 1..=3; blank gap column and row.
+        let one = glyph_cell('1').unwrap();
+        assert!((0..7).all(|y| texel(one, 2, y) == 255));
+        assert_eq!([texel(one, 0, 6), texel(one, 1, 6), texel(one, 4, 6)], [0, 255, 0]);
+        assert!((0..8).all(|y| texel(one, 5, y) == 0) && (0..6).all(|x| texel(one, x, 7) == 0));
+        assert!((0..5).all(|x| (0..7).all(|y| texel(SOLID_CELL, x, y) == 255)));
+        // 'T': the bar across the top row, then only the stem.
+        let t = glyph_cell('T').unwrap();
+        assert!((0..5).all(|x| texel(t, x, 0) == 255));
+        assert_eq!([texel(t, 0, 3), texel(t, 2, 3), texel(t, 4, 3)], [0, 255, 0]);
+        assert_eq!(glyph_cell('é'), None);
+        assert_eq!(glyph_cell('\n'), None);
+        assert_eq!((glyph_cell(' '), glyph_cell('0'), glyph_cell('~')), (Some(0), Some(16), Some(94)));
+    }
+}
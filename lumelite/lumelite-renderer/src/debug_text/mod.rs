@@ -0,0 +1,328 @@
+//! Debug text: ASCII strings in a built-in 5x7 bitmap font, plus solid rectangles for panels and bars,
+//! queued with [`DebugText::draw`] and drawn over the presented image by [`DebugText::flush`] as one
+//! instanced draw of alpha-blended screen-space quads (see debug_text.wgsl).
+
+mod font;
+
+use std::sync::{Mutex, PoisonError};
+
+use wgpu::util::DeviceExt;
+use wgpu::CommandEncoder;
+
+const DEBUG_TEXT_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/debug_text.wgsl"));
+
+/// Output pixels per font pixel.
+const SCALE: f32 = 2.0;
+/// Advance of one character and one line, in output pixels.
+pub const CHAR_SIZE: (f32, f32) = (font::CELL.0 as f32 * SCALE, font::CELL.1 as f32 * SCALE);
+/// Quads (characters and rectangles) one flush draws; later draws of the frame are dropped.
+const MAX_QUADS: usize = 4096;
+/// Full flushes the instance buffer holds before it wraps. Each flush writes its own range, so
+/// several flushes encoded before one submit (a present per split-screen region) keep their quads.
+const RING_FLUSHES: usize = 4;
+
+/// One instance of debug_text.wgsl: `rect` (x, y, width, height) in output pixels from the top-left
+/// until [`to_ndc`] converts it, `texels` the atlas rect (x0, y0, x1, y1) it shows.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TextQuad {
+    pub(crate) rect: [f32; 4],
+    pub(crate) texels: [f32; 4],
+    pub(crate) color: [f32; 4],
+}
+
+/// A quad of the solid atlas cell covering `rect`, for panels and bars.
+pub(crate) fn rect_quad(rect: [f32; 4], color: [f32; 4]) -> TextQuad {
+    let x = (font::SOLID_CELL * font::CELL.0) as f32 + 2.5;
+    TextQuad { rect, texels: [x, 3.5, x, 3.5], color }
+}
+
+/// Quads of `text` with its top-left corner at (x, y). `\n` starts a new line; blanks and characters
+/// the font lacks only advance.
+pub(crate) fn text_quads(text: &str, x: f32, y: f32, color: [f32; 4]) -> impl Iterator<Item = TextQuad> + '_ {
+    let (width, height) = (font::GLYPH.0 as f32, font::GLYPH.1 as f32);
+    text.split('\n').enumerate().flat_map(move |(line, text)| {
+        text.chars().enumerate().filter_map(move |(column, c)| {
+            let cell_x = (font::glyph_cell(c).filter(|_| c != ' ')? * font::CELL.0) as f32;
+            Some(TextQuad {
+                rect: [x + column as f32 * CHAR_SIZE.0, y + line as f32 * CHAR_SIZE.1, width * SCALE, height * SCALE],
+                texels: [cell_x, 0.0, cell_x + width, height],
+                color,
+            })
+        })
+    })
+}
+
+/// `quad` with its pixel rect converted to an NDC origin and extent for a `width` x `height` output.
+fn to_ndc(quad: TextQuad, width: u32, height: u32) -> TextQuad {
+    let (w, h) = (width as f32, height as f32);
+    let [x, y, qw, qh] = quad.rect;
+    TextQuad { rect: [x / w * 2.0 - 1.0, 1.0 - y / h * 2.0, qw / w * 2.0, -qh / h * 2.0], ..quad }
+}
+
+/// `quad` moved by (`x`, `y`) output pixels.
+fn offset(quad: TextQuad, x: u32, y: u32) -> TextQuad {
+    let [qx, qy, qw, qh] = quad.rect;
+    TextQuad { rect: [qx + x as f32, qy + y as f32, qw, qh], ..quad }
+}
+
+/// Overlay of debug text and rectangles. Draws are queued from `&self` (behind a mutex, so they can be
+/// issued while the renderer presents) and kept until the next [`Self::flush`].
+pub struct DebugText {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    /// Ring of [`RING_FLUSHES`] x [`MAX_QUADS`] instances; `ring_cursor` is the first free one.
+    instance_buf: wgpu::Buffer,
+    ring_cursor: Mutex<usize>,
+    quads: Mutex<Vec<TextQuad>>,
+}
+
+impl DebugText {
+    /// `output_format` is the format of the views `flush` draws into (the swapchain format).
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, output_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("debug_text_shader"),
+            source: wgpu::ShaderSource::Wgsl(DEBUG_TEXT_SHADER.into()),
+        });
+        let (width, height, texels) = font::atlas();
+        let atlas = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("debug_text_font_atlas"),
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &texels,
+        );
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("debug_text_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("debug_text_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&atlas.create_view(&Default::default())),
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_text_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("debug_text_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<TextQuad>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4],
+                }],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs"),
+                // Alpha-blend the color; the output's alpha is left as is.
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState { topology: wgpu::PrimitiveTopology::TriangleStrip, ..Default::default() },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let instance_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("debug_text_quads"),
+            size: (RING_FLUSHES * MAX_QUADS * std::mem::size_of::<TextQuad>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { pipeline, bind_group, instance_buf, ring_cursor: Mutex::new(0), quads: Mutex::new(Vec::new()) }
+    }
+
+    /// Queue `text` with its top-left corner at (`x`, `y`) output pixels; each character is
+    /// [`CHAR_SIZE`] and `\n` starts a new line. `color` is straight (not premultiplied) RGBA.
+    pub fn draw(&self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        self.push(text_quads(text, x, y, color));
+    }
+
+    /// Queue a solid rectangle (x, y, width, height in output pixels), e.g. a panel behind text.
+    pub fn draw_rect(&self, rect: [f32; 4], color: [f32; 4]) {
+        self.push([rect_quad(rect, color)]);
+    }
+
+    pub(crate) fn push(&self, quads: impl IntoIterator<Item = TextQuad>) {
+        // The queue is plain data, so a panic while it was held cannot leave it inconsistent.
+        let mut queued = self.quads.lock().unwrap_or_else(PoisonError::into_inner);
+        let room = MAX_QUADS - queued.len();
+        queued.extend(quads.into_iter().take(room));
+    }
+
+    /// Draw everything queued since the last flush over `output_view`, which is `output_size` pixels
+    /// and already holds the frame, then clear the queue. With a `region` (x, y, width, height in
+    /// output pixels) positions are relative to its top-left corner and drawing is clipped to it.
+    /// Does nothing when the queue is empty.
+    pub fn flush(
+        &self,
+        encoder: &mut CommandEncoder,
+        queue: &wgpu::Queue,
+        output_view: &wgpu::TextureView,
+        output_size: (u32, u32),
+        region: Option<[u32; 4]>,
+    ) -> Result<(), String> {
+        let (width, height) = output_size;
+        let quads: Vec<TextQuad> = std::mem::take(&mut *self.quads.lock().unwrap_or_else(PoisonError::into_inner));
+        if quads.is_empty() {
+            return Ok(());
+        }
+        if width == 0 || height == 0 {
+            return Err(format!("DebugText::flush: empty output {}x{}", width, height));
+        }
+        let [x, y, w, h] = region.unwrap_or([0, 0, width, height]);
+        if w == 0 || h == 0 || x as u64 + w as u64 > width as u64 || y as u64 + h as u64 > height as u64 {
+            return Err(format!("DebugText::flush: region {:?} outside the {}x{} output", [x, y, w, h], width, height));
+        }
+        let quads: Vec<TextQuad> = quads.into_iter().map(|q| to_ndc(offset(q, x, y), width, height)).collect();
+        let first = {
+            let mut cursor = self.ring_cursor.lock().unwrap_or_else(PoisonError::into_inner);
+            if *cursor + quads.len() > RING_FLUSHES * MAX_QUADS {
+                *cursor = 0;
+            }
+            let first = *cursor;
+            *cursor += quads.len();
+            first
+        };
+        let size = std::mem::size_of::<TextQuad>() as u64;
+        let range = first as u64 * size..(first + quads.len()) as u64 * size;
+        queue.write_buffer(&self.instance_buf, range.start, bytemuck::cast_slice(&quads));
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_text_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rp.set_scissor_rect(x, y, w, h);
+        rp.set_pipeline(&self.pipeline);
+        rp.set_bind_group(0, &self.bind_group, &[]);
+        rp.set_vertex_buffer(0, self.instance_buf.slice(range));
+        rp.draw(0..4, 0..quads.len() as u32);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn text_advances_by_character_and_line() {
+        const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+        let quads: Vec<_> = text_quads("a b\n?", 10.0, 20.0, RED).collect();
+        // The blank draws nothing but still advances.
+        let origins: Vec<_> = quads.iter().map(|q| [q.rect[0], q.rect[1]]).collect();
+        assert_eq!(origins, [[10.0, 20.0], [10.0 + 2.0 * CHAR_SIZE.0, 20.0], [10.0, 20.0 + CHAR_SIZE.1]]);
+        assert_eq!(quads[0].rect[2..], [10.0, 14.0]);
+        let cell_x = (font::glyph_cell('?').unwrap() * font::CELL.0) as f32;
+        assert_eq!(quads[2].texels, [cell_x, 0.0, cell_x + 5.0, 7.0]);
+        assert!(quads.iter().all(|q| q.color == RED));
+
+        let ndc = to_ndc(rect_quad([8.0, 8.0, 100.0, 25.0], RED), 400, 100);
+        let expected = [-0.96, 0.84, 0.5, -0.5];
+        assert!(ndc.rect.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", ndc.rect);
+        // In a region the rect is relative to the region's corner but still in output NDC.
+        let ndc = to_ndc(offset(rect_quad([8.0, 8.0, 100.0, 25.0], RED), 200, 50), 400, 100);
+        let expected = [0.04, -0.16, 0.5, -0.5];
+        assert!(ndc.rect.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", ndc.rect);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn flushes_before_one_submit_keep_their_own_quads() {
+        let Some(adapter) = test_support::adapter("flushes_before_one_submit_keep_their_own_quads") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let (width, height) = (64, 8);
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_view = output.create_view(&Default::default());
+        let text = DebugText::new(&device, &queue, wgpu::TextureFormat::Rgba8Unorm);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        // Split screen: a red panel in the left region, a green one in the right, one submit.
+        text.draw_rect([0.0, 0.0, 32.0, 8.0], [1.0, 0.0, 0.0, 1.0]);
+        text.flush(&mut encoder, &queue, &output_view, (width, height), Some([0, 0, 32, 8])).unwrap();
+        text.draw_rect([0.0, 0.0, 32.0, 8.0], [0.0, 1.0, 0.0, 1.0]);
+        text.flush(&mut encoder, &queue, &output_view, (width, height), Some([32, 0, 32, 8])).unwrap();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (256 * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        let pixel = |x: usize| [data[4 * 256 + x * 4], data[4 * 256 + x * 4 + 1]];
+        assert_eq!(pixel(16), [255, 0]);
+        assert_eq!(pixel(48), [0, 255]);
+    }
+}
@@ -1,20 +1,39 @@
 //! Direct triangle pass: draw triangle to swapchain. Debug - bypass GBuffer/Light/Present.
 //! Step 1: uses vertex buffer + view_proj (same layout as GBuffer) to verify mesh renders.
+//! With `LumeliteConfig::msaa_samples` > 1 it draws into a multisampled target that the pass resolves
+//! into the (single-sample) output view.
 
 use wgpu::CommandEncoder;
 
-use crate::gbuffer::MeshDraw;
+use crate::gbuffer::{MeshDraw, MESH_VERTEX_LAYOUT};
 
 const SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/direct_triangle.wgsl"));
 
+/// Where [`DirectTrianglePass::encode`] draws: `view`, a `width` x `height` view of `format`.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectTriangleOutput<'a> {
+    pub view: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
 pub struct DirectTrianglePass {
     pipeline: wgpu::RenderPipeline,
     bind_group_layout: wgpu::BindGroupLayout,
     view_proj_buf: wgpu::Buffer,
+    output_format: wgpu::TextureFormat,
+    sample_count: u32,
+    /// Multisampled color target resolved into the output; recreated when the output size changes.
+    msaa_target: Option<wgpu::Texture>,
 }
 
 impl DirectTrianglePass {
-    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Result<Self, String> {
+    /// `sample_count` must be 1 (no MSAA) or 4, the counts wgpu guarantees for every renderable format.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat, sample_count: u32) -> Result<Self, String> {
+        if sample_count != 1 && sample_count != 4 {
+            return Err(format!("direct triangle: MSAA sample count must be 1 or 4, got {}", sample_count));
+        }
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("direct_triangle_shader"),
             source: wgpu::ShaderSource::Wgsl(SHADER.into()),
@@ -55,15 +74,7 @@ impl DirectTrianglePass {
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: 32,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[
-                        wgpu::VertexAttribute { offset: 0, shader_location: 0, format: wgpu::VertexFormat::Float32x3 },
-                        wgpu::VertexAttribute { offset: 12, shader_location: 1, format: wgpu::VertexFormat::Float32x3 },
-                        wgpu::VertexAttribute { offset: 24, shader_location: 2, format: wgpu::VertexFormat::Float32x2 },
-                    ],
-                }],
+                buffers: &[MESH_VERTEX_LAYOUT],
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
@@ -74,7 +85,7 @@ impl DirectTrianglePass {
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
             multiview: None,
             cache: None,
         });
@@ -84,28 +95,61 @@ impl DirectTrianglePass {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        Ok(Self { pipeline, bind_group_layout, view_proj_buf })
+        Ok(Self { pipeline, bind_group_layout, view_proj_buf, output_format, sample_count, msaa_target: None })
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
     }
 
+    /// Draw `meshes` into `output`. With MSAA the output is the resolve target, so its format must be
+    /// the pipeline's (resolves cannot convert).
     pub fn encode(
-        &self,
+        &mut self,
         encoder: &mut CommandEncoder,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        output_view: &wgpu::TextureView,
+        output: DirectTriangleOutput,
         meshes: &[MeshDraw],
         view_proj: &[f32; 16],
     ) -> Result<(), String> {
+        let DirectTriangleOutput { view: output_view, format: output_format, width, height } = output;
+        if output_format != self.output_format {
+            return Err(format!(
+                "direct triangle: output format {:?} does not match the pipeline format {:?}",
+                output_format, self.output_format
+            ));
+        }
+        let size = wgpu::Extent3d { width: width.max(1), height: height.max(1), depth_or_array_layers: 1 };
+        let msaa_view = if self.sample_count > 1 {
+            if self.msaa_target.as_ref().is_none_or(|t| t.size() != size) {
+                self.msaa_target = Some(device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("direct_triangle_msaa"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: self.sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: self.output_format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                }));
+            }
+            self.msaa_target.as_ref().map(|t| t.create_view(&Default::default()))
+        } else {
+            None
+        };
+        // Multisampled samples are only needed until the resolve at the end of the pass.
+        let (view, resolve_target, store) = match &msaa_view {
+            Some(msaa_view) => (msaa_view, Some(output_view), wgpu::StoreOp::Discard),
+            None => (output_view, None, wgpu::StoreOp::Store),
+        };
         queue.write_buffer(&self.view_proj_buf, 0, bytemuck::cast_slice(view_proj));
         let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("direct_triangle"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: output_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
-                },
+                view,
+                resolve_target,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store },
             })],
             depth_stencil_attachment: None,
             timestamp_writes: None,
@@ -137,3 +181,92 @@ impl DirectTrianglePass {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+    #[rustfmt::skip]
+    const IDENTITY: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+    /// Red channel of a 64x4 output after drawing the lower-left half triangle of the view.
+    fn render_red(device: &wgpu::Device, queue: &wgpu::Queue, sample_count: u32) -> Vec<u8> {
+        let (width, height) = (64, 4);
+        let mut pass = DirectTrianglePass::new(device, FORMAT, sample_count).unwrap();
+        #[rustfmt::skip]
+        let vertices: [[f32; 8]; 3] = [
+            [-1.0, -1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0], [1.0, -1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [-1.0, 1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0],
+        ];
+        let view = test_support::texture(device, queue, [255; 4]);
+        let mesh = test_support::mesh(device, &vertices, &[0, 1, 2], test_support::pbr_textures(view));
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (width * height * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let output_view = output.create_view(&Default::default());
+        let target = DirectTriangleOutput { view: &output_view, format: FORMAT, width, height };
+        pass.encode(&mut encoder, device, queue, target, &[mesh], &IDENTITY).unwrap();
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(width * 4), rows_per_image: None },
+            },
+            output.size(),
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let texels = readback.slice(..).get_mapped_range();
+        texels.chunks_exact(4).map(|t| t[0]).collect()
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn msaa_resolves_partially_covered_pixels() {
+        let Some(adapter) = test_support::adapter("msaa_resolves_partially_covered_pixels") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let is_edge = |red: &u8| *red != 0 && *red != 153;
+        assert!(!render_red(&device, &queue, 1).iter().any(is_edge));
+        let resolved = render_red(&device, &queue, 4);
+        assert!(resolved.iter().any(is_edge), "{:?}", resolved);
+        // Fully covered and uncovered corners are unaffected by the resolve.
+        assert_eq!((resolved[64 * 3], resolved[63]), (153, 0));
+
+        assert!(DirectTrianglePass::new(&device, FORMAT, 3).is_err());
+        let mut pass = DirectTrianglePass::new(&device, FORMAT, 4).unwrap();
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Bgra8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let view = output.create_view(&Default::default());
+        let output = DirectTriangleOutput { view: &view, format: wgpu::TextureFormat::Bgra8Unorm, width: 1, height: 1 };
+        let result = pass.encode(&mut encoder, &device, &queue, output, &[], &IDENTITY);
+        assert!(result.unwrap_err().contains("does not match"));
+    }
+}
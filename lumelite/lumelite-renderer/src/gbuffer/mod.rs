@@ -1,10 +1,262 @@
-//! GBuffer pass: fill 4 RTs + depth (Flax layout). Single PBR pipeline, stride 32, four texture bindings.
+//! GBuffer pass: fill the GBuffer RTs + depth (Flax layout or the compact two-target layout, optionally
+//! followed by a velocity target, in the formats of a validated `GBufferLayout`). One pipeline set per
+//! `ShaderVariant` (PBR or unlit), stride 32, four texture bindings.
 
+use std::collections::HashMap;
+use std::ops::Range;
 use std::sync::Arc;
+use render_api::ShaderVariant;
+use wgpu::util::DeviceExt;
 use wgpu::CommandEncoder;
 
+use crate::config::{AdapterCapabilities, GBufferStoreOps, MaterialSampling};
+use crate::culling::{instanced_draws_supported, DRAW_ARGS_SIZE};
+
+/// Pack/unpack functions shared with the light pass; prepended to both shaders.
+pub(crate) const GBUFFER_PACK_SHADER: &str =
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/gbuffer_pack.wgsl"));
 const GBUFFER_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/gbuffer.wgsl"));
 
+// GBuffer channel layouts: see gbuffer_pack.wgsl, which gbuffer.wgsl encodes with and lights.wgsl
+// decodes with. Cleared texels are background: the light pass skips them by depth, so clear values
+// only need to be valid encodings, not meaningful surfaces.
+
+/// Number of GBuffer color targets of the full layout.
+pub const GBUFFER_COUNT: usize = 4;
+/// Color format of every GBuffer target of the full layout.
+pub const GBUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+/// Format of the velocity target of [`GBufferLayout::with_velocity`].
+pub const GBUFFER_VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+/// Scene depth format (sampled by the light pass for position reconstruction).
+pub const GBUFFER_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Clear value per GBuffer target of the full layout. gbuffer2 clears to roughness 1 (fully rough,
+/// non-metal) so any stray sample reads as a diffuse surface.
+pub const GBUFFER_CLEAR_VALUES: [wgpu::Color; GBUFFER_COUNT] = [
+    wgpu::Color::TRANSPARENT,
+    wgpu::Color::TRANSPARENT,
+    wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 0.0 },
+    wgpu::Color::TRANSPARENT,
+];
+
+/// How surface attributes are packed into GBuffer targets; selects the GBuffer and light shader
+/// entry points.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GBufferPacking {
+    /// Four targets (Flax layout); gbuffer3 is unused.
+    #[default]
+    Full,
+    /// Base color/AO + octahedral normal/roughness/metalness in two targets. Metalness is quantized to
+    /// the second target's alpha bits and specular is fixed at 0.5 (which is all the GBuffer shader
+    /// writes anyway).
+    Compact,
+}
+
+impl GBufferPacking {
+    /// Number of color targets the packing writes.
+    pub fn target_count(self) -> usize {
+        match self {
+            Self::Full => GBUFFER_COUNT,
+            Self::Compact => 2,
+        }
+    }
+
+    /// Clear value of each color target. The compact normal clears to +z and roughness to 1.
+    pub fn clear_values(self) -> &'static [wgpu::Color] {
+        match self {
+            Self::Full => &GBUFFER_CLEAR_VALUES,
+            Self::Compact => &[wgpu::Color::TRANSPARENT, wgpu::Color { r: 0.5, g: 0.5, b: 1.0, a: 0.0 }],
+        }
+    }
+
+    /// Number of targets the light pass binds and reads, from gbuffer0 on; gbuffer3 of the full
+    /// packing holds nothing yet.
+    pub fn light_inputs(self) -> usize {
+        match self {
+            Self::Full => 3,
+            Self::Compact => 2,
+        }
+    }
+
+    /// Names of the pack and unpack functions of gbuffer_pack.wgsl for this packing.
+    fn wgsl_functions(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Full => ("pack_gbuffer_full", "unpack_gbuffer_full"),
+            Self::Compact => ("pack_gbuffer_compact", "unpack_gbuffer_compact"),
+        }
+    }
+
+    /// WGSL prepended to lights.wgsl: bindings 0.. of the [`Self::light_inputs`] targets and
+    /// `load_gbuffer`, which reads a pixel's surface from them.
+    pub(crate) fn load_wgsl(self) -> String {
+        let count = self.light_inputs();
+        let bindings: String =
+            (0..count).map(|i| format!("@group(0) @binding({i}) var gbuffer{i}: texture_2d<f32>;\n")).collect();
+        let loads: Vec<String> = (0..count).map(|i| format!("textureLoad(gbuffer{i}, pix, 0)")).collect();
+        format!(
+            "{bindings}\n// Packed values must not be filtered, so texels are loaded rather than sampled.\n\
+             fn load_gbuffer(uv: vec2<f32>) -> GBufferData {{\n    \
+             let dims = vec2<f32>(textureDimensions(gbuffer0));\n    \
+             let pix = vec2<i32>(min(floor(uv * dims), dims - vec2<f32>(1.0, 1.0)));\n    \
+             return {}({});\n}}\n",
+            self.wgsl_functions().1,
+            loads.join(", ")
+        )
+    }
+}
+
+/// GBuffer targets (`LumeliteConfig::gbuffer_layout`): a packing, the format of each of its targets,
+/// and an optional velocity target after them, checked against the device by [`Self::validate`].
+/// Formats may trade bandwidth for precision (e.g. Rgba16Float normals) as long as they are filterable,
+/// four-channel, and not sRGB. The GBuffer pass writes one fragment output per target and the light
+/// pass binds the ones it reads ([`GBufferPacking::light_inputs`]), so the count runs from 2 (compact)
+/// to 5 (full with velocity).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GBufferLayout {
+    pub packing: GBufferPacking,
+    /// Format of each target of the packing, in attachment order; one per [`GBufferPacking::target_count`].
+    pub formats: &'static [wgpu::TextureFormat],
+    /// Format of the velocity target, attached after the packing's targets; `None` for none. It holds
+    /// each pixel's texture-space motion since the view's previous frame (current uv - previous uv) in
+    /// its first two channels, for temporal passes (`FrameResources::velocity`); lighting does not read
+    /// it. Only camera motion is included: meshes carry no previous transform.
+    pub velocity: Option<wgpu::TextureFormat>,
+}
+
+impl GBufferLayout {
+    /// Four Rgba8Unorm targets, 16 bytes per pixel.
+    pub const FULL: Self = Self { packing: GBufferPacking::Full, formats: &[GBUFFER_FORMAT; GBUFFER_COUNT], velocity: None };
+    /// Rgba8Unorm + Rgb10a2Unorm, 8 bytes per pixel: halves GBuffer bandwidth; metalness gets 4 levels.
+    pub const COMPACT: Self = Self {
+        packing: GBufferPacking::Compact,
+        formats: &[wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureFormat::Rgb10a2Unorm],
+        velocity: None,
+    };
+
+    /// This layout plus a [`GBUFFER_VELOCITY_FORMAT`] velocity target. `FULL.with_velocity()` needs 36
+    /// color attachment bytes per sample, above the WebGPU default of 32, so the device must be
+    /// created with a raised `max_color_attachment_bytes_per_sample`; `COMPACT.with_velocity()` needs 20.
+    pub const fn with_velocity(self) -> Self {
+        Self { velocity: Some(GBUFFER_VELOCITY_FORMAT), ..self }
+    }
+
+    /// Number of color targets, including the velocity target.
+    pub fn target_count(self) -> usize {
+        self.formats.len() + self.velocity.is_some() as usize
+    }
+
+    /// Format of every color target, in attachment order.
+    pub fn color_formats(self) -> impl Iterator<Item = wgpu::TextureFormat> {
+        self.formats.iter().copied().chain(self.velocity)
+    }
+
+    /// Clear value of each color target (see [`GBufferPacking::clear_values`]); velocity clears to no
+    /// motion.
+    pub fn clear_values(self) -> Vec<wgpu::Color> {
+        let velocity = self.velocity.map(|_| wgpu::Color::TRANSPARENT);
+        self.packing.clear_values().iter().copied().chain(velocity).collect()
+    }
+
+    /// Color bytes written per pixel by the GBuffer pass.
+    pub fn bytes_per_pixel(self) -> u32 {
+        self.color_formats().map(|f| f.block_copy_size(None).unwrap_or(0)).sum()
+    }
+
+    /// Render target cost of one sample across all color targets, as checked against
+    /// `Limits::max_color_attachment_bytes_per_sample` (targets are aligned to their component size).
+    pub fn attachment_bytes_per_sample(self) -> u32 {
+        self.color_formats().fold(0, |total, f| {
+            let cost = f.target_pixel_byte_cost().unwrap_or(0);
+            let align = f.target_component_alignment().unwrap_or(1);
+            total.next_multiple_of(align) + cost
+        })
+    }
+
+    /// WGSL prepended to gbuffer.wgsl: `FragmentOutput`, with one location per color target, and
+    /// `write_gbuffer`, which packs a surface (and, with a velocity target, the pixel's motion) into it.
+    pub(crate) fn output_wgsl(self) -> String {
+        let count = self.packing.target_count();
+        let mut fields: Vec<String> = (0..count).map(|i| format!("    @location({i}) gbuffer{i}: vec4<f32>,\n")).collect();
+        let mut values: Vec<String> = (0..count).map(|i| format!("g[{i}]")).collect();
+        if self.velocity.is_some() {
+            // vec4 fits velocity formats of any channel count; extra channels are dropped.
+            fields.push(format!("    @location({count}) velocity: vec4<f32>,\n"));
+            values.push("vec4<f32>(uv_motion(in), 0.0, 0.0)".to_string());
+        }
+        format!(
+            "struct FragmentOutput {{\n{}}}\n\n\
+             fn write_gbuffer(d: GBufferData, in: VertexOutput) -> FragmentOutput {{\n    \
+             let g = {}(d);\n    \
+             return FragmentOutput({});\n}}\n",
+            fields.concat(),
+            self.packing.wgsl_functions().0,
+            values.join(", ")
+        )
+    }
+
+    /// Errors unless there is one format per target of the packing, each renderable and filterable
+    /// (the light pass samples them) with four linear channels, a velocity format is renderable,
+    /// filterable and linear with at least two channels, and the targets fit `device`'s color
+    /// attachment limits.
+    pub fn validate(self, device: &wgpu::Device) -> Result<(), String> {
+        let expected = self.packing.target_count();
+        if self.formats.len() != expected {
+            return Err(format!("GBuffer packing {:?} needs {} formats, got {}", self.packing, expected, self.formats.len()));
+        }
+        let usages = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        let usable = |format: wgpu::TextureFormat| {
+            let features = format.guaranteed_format_features(device.features());
+            !format.is_srgb()
+                && features.allowed_usages.contains(usages)
+                && features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+        };
+        for &format in self.formats {
+            if format.components() != 4 || !usable(format) {
+                return Err(format!("{:?} cannot be a GBuffer target (needs a filterable, renderable four-channel linear format)", format));
+            }
+        }
+        if let Some(format) = self.velocity.filter(|&f| f.components() < 2 || !usable(f)) {
+            return Err(format!(
+                "{:?} cannot be a GBuffer velocity target (needs a filterable, renderable linear format with two or more channels)",
+                format
+            ));
+        }
+        let limits = device.limits();
+        if self.target_count() > limits.max_color_attachments as usize {
+            return Err(format!(
+                "GBuffer layout needs {} color attachments, device allows {}",
+                self.target_count(),
+                limits.max_color_attachments
+            ));
+        }
+        let bytes_per_sample = self.attachment_bytes_per_sample();
+        if bytes_per_sample > limits.max_color_attachment_bytes_per_sample {
+            return Err(format!(
+                "GBuffer layout needs {} color attachment bytes per sample, device allows {}",
+                bytes_per_sample, limits.max_color_attachment_bytes_per_sample
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for GBufferLayout {
+    fn default() -> Self { Self::FULL }
+}
+
+/// Depth clear value (far plane; the light pass treats depth >= 1.0 as background).
+pub const GBUFFER_DEPTH_CLEAR: f32 = 1.0;
+/// Far plane with `LumeliteConfig::reverse_z`; the light pass then treats depth <= 0.0 as background.
+pub const REVERSE_Z_DEPTH_CLEAR: f32 = 0.0;
+
+/// Depth test of the passes drawing scene geometry: nearer is smaller depth, or larger with reverse-Z.
+pub fn scene_depth_compare(reverse_z: bool) -> wgpu::CompareFunction {
+    if reverse_z {
+        wgpu::CompareFunction::GreaterEqual
+    } else {
+        wgpu::CompareFunction::LessEqual
+    }
+}
+
 /// Four PBR texture views (base_color, normal, metallic_roughness, ao). Required per mesh; use default when no material.
 #[derive(Clone)]
 pub struct PbrTextureViews {
@@ -14,8 +266,157 @@ pub struct PbrTextureViews {
     pub ao: Arc<wgpu::TextureView>,
 }
 
+/// Vertex layout of `MeshDraw::vertex_buf`: position, normal, uv (stride 32). Every mesh pipeline
+/// (GBuffer, shadow, unlit, direct triangle) binds this one layout, so they can share vertex buffers.
+pub const MESH_VERTEX_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: 32,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2],
+};
+
+/// Second vertex stream of the GBuffer and unlit pipelines: linear RGBA per vertex (stride 16),
+/// multiplied into the base color. See [`MeshDraw::vertex_colors`].
+pub const VERTEX_COLOR_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: 16,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &wgpu::vertex_attr_array![3 => Float32x4],
+};
+
+/// Third vertex stream of the GBuffer pipelines: mesh-space tangent with the bitangent sign in w
+/// (stride 16; `render_api::generate_tangents`), for normal mapping. See [`MeshDraw::tangents`].
+pub const TANGENT_LAYOUT: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: 16,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &wgpu::vertex_attr_array![4 => Float32x4],
+};
+
+/// Per-draw uniform of the GBuffer and unlit shaders (`MeshUniform` in gbuffer.wgsl).
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct MeshUniform {
+    model: [f32; 16],
+    emissive: [f32; 3],
+    vertex_color: u32,
+    tangents: u32,
+    _padding: [u32; 3],
+}
+
+pub(crate) const MESH_UNIFORM_SIZE: u64 = std::mem::size_of::<MeshUniform>() as u64;
+
+impl MeshUniform {
+    pub(crate) fn new(mesh: &MeshDraw) -> Self {
+        Self {
+            model: mesh.transform,
+            emissive: mesh.emissive,
+            vertex_color: mesh.vertex_colors.is_some() as u32,
+            tangents: mesh.tangents.is_some() as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// How the GBuffer and unlit shaders get a mesh's [`MeshUniform`]: `mesh_data(instance_index)`, defined
+/// by [`Self::wgsl`] prepended to them, reading binding 0/1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MeshData {
+    /// A uniform buffer and bind group per mesh, one draw call each.
+    PerDraw,
+    /// One storage buffer of every mesh's data, indexed by instance: mesh `i` is drawn as instance `i`
+    /// (see [`instanced_draws_supported`]), so runs of meshes sharing buffers and textures share their
+    /// bindings, and with `multi_draw` ([`crate::culling::MULTI_DRAW_FEATURES`]) are one
+    /// `multi_draw_indexed_indirect`.
+    Instanced { multi_draw: bool },
+}
+
+impl MeshData {
+    pub(crate) fn new(device: &wgpu::Device, capabilities: &AdapterCapabilities) -> Self {
+        if instanced_draws_supported(device, capabilities) {
+            Self::Instanced { multi_draw: device.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT) }
+        } else {
+            Self::PerDraw
+        }
+    }
+
+    pub(crate) fn wgsl(self) -> &'static str {
+        match self {
+            Self::PerDraw => {
+                "@group(0) @binding(1) var<uniform> mesh_uniform: MeshUniform;\n\
+                 fn mesh_data(instance: u32) -> MeshUniform { return mesh_uniform; }\n"
+            }
+            Self::Instanced { .. } => {
+                "@group(0) @binding(1) var<storage, read> mesh_instances: array<MeshUniform>;\n\
+                 fn mesh_data(instance: u32) -> MeshUniform { return mesh_instances[instance]; }\n"
+            }
+        }
+    }
+
+    /// Layout entry of binding 0/1.
+    pub(crate) fn layout_entry(self) -> wgpu::BindGroupLayoutEntry {
+        let ty = match self {
+            Self::PerDraw => wgpu::BufferBindingType::Uniform,
+            Self::Instanced { .. } => wgpu::BufferBindingType::Storage { read_only: true },
+        };
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: std::num::NonZeroU64::new(MESH_UNIFORM_SIZE) },
+            count: None,
+        }
+    }
+
+    /// The buffer bound at 0/1 for drawing `meshes[i]` (`MeshData::PerDraw`) or all of `meshes`.
+    pub(crate) fn create_buffer(device: &wgpu::Device, meshes: &[MeshDraw]) -> wgpu::Buffer {
+        let data: Vec<MeshUniform> = meshes.iter().map(MeshUniform::new).collect();
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("mesh_data"),
+            contents: bytemuck::cast_slice(&data),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::STORAGE,
+        })
+    }
+}
+
+/// Runs of consecutive `meshes` with equal `key`s that share every buffer and texture, so
+/// [`MeshData::Instanced`] draws each run with one set of bindings. Meshes keyed `None` are skipped.
+pub(crate) fn mesh_runs<K: PartialEq>(meshes: &[MeshDraw], key: impl Fn(&MeshDraw) -> Option<K>) -> Vec<(K, Range<usize>)> {
+    let mut runs: Vec<(K, Range<usize>)> = Vec::new();
+    for (i, mesh) in meshes.iter().enumerate() {
+        let Some(k) = key(mesh) else { continue };
+        match runs.last_mut() {
+            Some((last, run)) if run.end == i && *last == k && meshes[run.start].shares_bindings(mesh) => run.end += 1,
+            _ => runs.push((k, i..i + 1)),
+        }
+    }
+    runs
+}
+
+/// Draws `meshes[run]`, whose bindings are set, as instances `run` for [`MeshData::Instanced`]: with
+/// `multi_draw`, one `multi_draw_indexed_indirect` over their args in `indirect`, otherwise an indirect
+/// draw each, or a `draw_indexed` each without `indirect`.
+pub(crate) fn draw_instanced(
+    rp: &mut wgpu::RenderPass,
+    meshes: &[MeshDraw],
+    run: Range<usize>,
+    indirect: Option<&wgpu::Buffer>,
+    multi_draw: bool,
+) {
+    match indirect {
+        Some(args) if multi_draw => rp.multi_draw_indexed_indirect(args, run.start as u64 * DRAW_ARGS_SIZE, run.len() as u32),
+        Some(args) => {
+            for i in run {
+                rp.draw_indexed_indirect(args, i as u64 * DRAW_ARGS_SIZE);
+            }
+        }
+        None => {
+            for i in run {
+                rp.draw_indexed(0..meshes[i].index_count, 0, i as u32..i as u32 + 1);
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct MeshDraw {
+    /// Vertices in [`MESH_VERTEX_LAYOUT`].
     pub vertex_buf: Arc<wgpu::Buffer>,
     pub index_buf: Arc<wgpu::Buffer>,
     pub index_count: u32,
@@ -23,26 +424,140 @@ pub struct MeshDraw {
     pub transform: [f32; 16],
     /// PBR textures for this mesh (always set; use default when host has no material).
     pub pbr_textures: PbrTextureViews,
+    /// `transform` has a negative determinant (odd number of negative scales), which flips the
+    /// winding of its triangles. See [`is_mirrored`].
+    pub mirrored: bool,
+    /// Never culled; back faces are shaded with the flipped normal (`ExtractedPbrMaterial::double_sided`).
+    pub double_sided: bool,
+    /// Mesh-space bounds for GPU frustum culling (`LumeliteConfig::gpu_culling`); `None` is never culled.
+    pub bounds: Option<crate::bounds::Aabb>,
+    /// Which of the GBuffer pass's pipeline sets draws the mesh (`ExtractedMesh::shader_variant`).
+    pub shader_variant: ShaderVariant,
+    /// Per-vertex colors in [`VERTEX_COLOR_LAYOUT`], one per vertex of `vertex_buf`; `None` is white.
+    pub vertex_colors: Option<Arc<wgpu::Buffer>>,
+    /// Per-vertex tangents in [`TANGENT_LAYOUT`], one per vertex of `vertex_buf`; `None` derives a
+    /// tangent basis from the normal alone, which only suits normal maps without a fixed orientation.
+    pub tangents: Option<Arc<wgpu::Buffer>>,
+    /// Linear radiance added to the light buffer where the mesh is visible
+    /// (`ExtractedPbrMaterial::emissive_factor`); zero emits nothing.
+    pub emissive: [f32; 3],
+}
+
+impl MeshDraw {
+    /// Buffer for the vertex color stream. Without colors the vertex buffer stands in (it is at least
+    /// as long and the shader ignores it), so no per-mesh white buffer is needed.
+    pub(crate) fn color_stream(&self) -> wgpu::BufferSlice<'_> {
+        self.vertex_colors.as_deref().unwrap_or(&self.vertex_buf).slice(..)
+    }
+
+    /// Buffer for the tangent stream, with the vertex buffer standing in like [`Self::color_stream`].
+    pub(crate) fn tangent_stream(&self) -> wgpu::BufferSlice<'_> {
+        self.tangents.as_deref().unwrap_or(&self.vertex_buf).slice(..)
+    }
+
+    /// Whether `other` has the same vertex streams, indices and textures, so both draw with the same
+    /// bindings.
+    fn shares_bindings(&self, other: &MeshDraw) -> bool {
+        let same_stream = |a: &Option<Arc<wgpu::Buffer>>, b: &Option<Arc<wgpu::Buffer>>| match (a, b) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        let (t, u) = (&self.pbr_textures, &other.pbr_textures);
+        Arc::ptr_eq(&self.vertex_buf, &other.vertex_buf)
+            && Arc::ptr_eq(&self.index_buf, &other.index_buf)
+            && same_stream(&self.vertex_colors, &other.vertex_colors)
+            && same_stream(&self.tangents, &other.tangents)
+            && Arc::ptr_eq(&t.base_color, &u.base_color)
+            && Arc::ptr_eq(&t.normal, &u.normal)
+            && Arc::ptr_eq(&t.metallic_roughness, &u.metallic_roughness)
+            && Arc::ptr_eq(&t.ao, &u.ao)
+    }
+}
+
+/// Whether a column-major 4x4 transform mirrors geometry (negative determinant of its upper 3x3).
+pub fn is_mirrored(transform: &[f32; 16]) -> bool {
+    let m = transform;
+    let det = m[0] * (m[5] * m[10] - m[6] * m[9]) - m[4] * (m[1] * m[10] - m[2] * m[9])
+        + m[8] * (m[1] * m[6] - m[2] * m[5]);
+    det < 0.0
+}
+
+/// How [`GBufferPass::encode`] draws: the default draws every mesh with an identity view into cleared
+/// targets, unclipped, and stores them.
+#[derive(Clone, Copy, Debug)]
+pub struct GBufferDrawOptions<'a> {
+    pub view_proj: &'a [f32; 16],
+    /// `view_proj` of the view's previous frame, for [`GBufferLayout::velocity`]; `None` (first frame,
+    /// camera cut) writes no motion.
+    pub previous_view_proj: Option<&'a [f32; 16]>,
+    /// World-space `(n.xyz, d)`; fragments with `dot(n, p) + d < 0` are discarded, e.g. geometry below a
+    /// mirror or water surface when rendering its reflection. `None` draws everything.
+    pub clip_plane: Option<[f32; 4]>,
+    /// Set when `view_proj` itself mirrors (planar reflection), which flips the winding of every mesh on
+    /// top of [`MeshDraw::mirrored`].
+    pub mirrored_view: bool,
+    /// Args written by `GpuCullPass::encode` for the same meshes on the same device: mesh `i` is drawn
+    /// from the args at `i * DRAW_ARGS_SIZE`, so culled meshes draw nothing. `None` draws every mesh.
+    pub indirect: Option<&'a wgpu::Buffer>,
+    /// How the scene depth starts: `Clear(GBUFFER_DEPTH_CLEAR)` normally, `Load` to reuse a depth
+    /// prepass.
+    pub depth_load: wgpu::LoadOp<f32>,
+    /// Draw over the color targets as they are instead of clearing them; with `depth_load: Load`, the
+    /// second (disoccluded) draw of two-pass occlusion culling, `indirect` from `GpuCullPass::encode_late`.
+    pub load_colors: bool,
+    /// Which targets are kept for later passes (see [`GBufferStoreOps`]).
+    pub store: GBufferStoreOps,
+}
+
+impl Default for GBufferDrawOptions<'_> {
+    fn default() -> Self {
+        Self {
+            view_proj: &crate::IDENTITY,
+            previous_view_proj: None,
+            clip_plane: None,
+            mirrored_view: false,
+            indirect: None,
+            depth_load: wgpu::LoadOp::Clear(GBUFFER_DEPTH_CLEAR),
+            load_colors: false,
+            store: GBufferStoreOps::default(),
+        }
+    }
 }
 
 pub struct GBufferPass {
-    pipeline: wgpu::RenderPipeline,
+    layout: GBufferLayout,
+    /// Per shader variant, indexed by [`Self::pipeline_index`]: without/with clip plane,
+    /// counter-clockwise/clockwise front faces, single/double-sided.
+    pipelines: HashMap<ShaderVariant, [wgpu::RenderPipeline; 8]>,
+    mesh_data: MeshData,
     bind_group_layout_0: wgpu::BindGroupLayout,
     bind_group_layout_1: wgpu::BindGroupLayout,
     view_proj_buf: wgpu::Buffer,
+    /// World-space clip plane for `clipped_pipeline` (binding 0/2).
+    clip_plane_buf: wgpu::Buffer,
+    /// Previous frame's view_proj for the velocity target (binding 0/3).
+    previous_view_proj_buf: wgpu::Buffer,
     sampler: wgpu::Sampler,
 }
 
 impl GBufferPass {
+    /// `cull_mode` applies to every single-sided mesh; mirrored meshes get clockwise front faces so
+    /// the same side of them is culled as for regular meshes. Frames passed to [`Self::encode`] must
+    /// have been created with the same `layout`. `depth_compare` comes from [`scene_depth_compare`].
+    /// `capabilities` must be the ones the `GpuCullPass` writing [`GBufferDrawOptions::indirect`] was
+    /// created with.
     pub fn new(
         device: &wgpu::Device,
-        format_gbuffer: wgpu::TextureFormat,
+        layout: GBufferLayout,
         format_depth: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        cull_mode: Option<wgpu::Face>,
+        material_sampling: MaterialSampling,
+        capabilities: &AdapterCapabilities,
     ) -> Result<Self, String> {
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("gbuffer_shader"),
-            source: wgpu::ShaderSource::Wgsl(GBUFFER_SHADER.into()),
-        });
+        layout.validate(device)?;
+        let targets: Vec<Option<wgpu::ColorTargetState>> = layout.color_formats().map(|f| Some(f.into())).collect();
+        let mesh_data = MeshData::new(device, capabilities);
 
         let bind_group_layout_0 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("gbuffer_bind_group_layout_0"),
@@ -57,8 +572,19 @@ impl GBufferPass {
                     },
                     count: None,
                 },
+                mesh_data.layout_entry(),
                 wgpu::BindGroupLayoutEntry {
-                    binding: 1,
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: std::num::NonZeroU64::new(16),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
                     visibility: wgpu::ShaderStages::VERTEX,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
@@ -128,58 +654,68 @@ impl GBufferPass {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("gbuffer_pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: 32,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            offset: 0,
-                            shader_location: 0,
-                            format: wgpu::VertexFormat::Float32x3,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 12,
-                            shader_location: 1,
-                            format: wgpu::VertexFormat::Float32x3,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 24,
-                            shader_location: 2,
-                            format: wgpu::VertexFormat::Float32x2,
-                        },
-                    ],
-                }],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs"),
-                targets: &[
-                    Some(format_gbuffer.into()),
-                    Some(format_gbuffer.into()),
-                    Some(format_gbuffer.into()),
-                    Some(format_gbuffer.into()),
-                ],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: format_depth,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-            cache: None,
-        });
+        let create_pipeline = |label: &str,
+                               shader: &wgpu::ShaderModule,
+                               fs_entry: &str,
+                               constants: &HashMap<String, f64>,
+                               front_face,
+                               cull_mode| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: Some("vs"),
+                    buffers: &[MESH_VERTEX_LAYOUT, VERTEX_COLOR_LAYOUT, TANGENT_LAYOUT],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: Some(fs_entry),
+                    targets: &targets,
+                    compilation_options: wgpu::PipelineCompilationOptions { constants, ..Default::default() },
+                }),
+                primitive: wgpu::PrimitiveState { front_face, cull_mode, ..Default::default() },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: format_depth,
+                    depth_write_enabled: true,
+                    depth_compare,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        // The clip-plane variants are only used when a clip plane is set, so regular frames keep
+        // early depth testing. Shader variants differ only in the `UNLIT` override constant, but each
+        // gets its own module: the GL backend caches linked programs by module and entry point only.
+        let variant_pipelines = |variant: ShaderVariant| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("gbuffer_shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!("{}\n{}\n{}\n{}", mesh_data.wgsl(), GBUFFER_PACK_SHADER, layout.output_wgsl(), GBUFFER_SHADER).into(),
+                ),
+            });
+            let constants = HashMap::from([("UNLIT".to_string(), if variant == ShaderVariant::Unlit { 1.0 } else { 0.0 })]);
+            let variant_label = if variant == ShaderVariant::Unlit { "_unlit" } else { "" };
+            std::array::from_fn(|index| {
+                let (clipped, mirrored, double_sided) = (index & 1 != 0, index & 2 != 0, index & 4 != 0);
+                let fs_entry = match (double_sided, clipped) {
+                    (false, false) => "fs",
+                    (false, true) => "fs_clipped",
+                    (true, false) => "fs_double_sided",
+                    (true, true) => "fs_double_sided_clipped",
+                };
+                // Also decides which side `front_facing` reports in the double-sided shaders.
+                let front_face = if mirrored { wgpu::FrontFace::Cw } else { wgpu::FrontFace::Ccw };
+                let mirrored_label = if mirrored { "_mirrored" } else { "" };
+                let label = format!("gbuffer_{}{}{}_pipeline", fs_entry, variant_label, mirrored_label);
+                create_pipeline(&label, &shader, fs_entry, &constants, front_face, if double_sided { None } else { cull_mode })
+            })
+        };
+        let pipelines = ShaderVariant::ALL.into_iter().map(|v| (v, variant_pipelines(v))).collect();
 
         let view_proj_buf = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("gbuffer_view_proj"),
@@ -188,26 +724,105 @@ impl GBufferPass {
             mapped_at_creation: false,
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("gbuffer_sampler"),
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
+        let sampler = device.create_sampler(&material_sampling.sampler_descriptor(Some("gbuffer_sampler")));
+
+        let clip_plane_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gbuffer_clip_plane"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let previous_view_proj_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gbuffer_previous_view_proj"),
+            size: 64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
         Ok(Self {
-            pipeline,
+            layout,
+            pipelines,
+            mesh_data,
             bind_group_layout_0,
             bind_group_layout_1,
             view_proj_buf,
+            clip_plane_buf,
+            previous_view_proj_buf,
             sampler,
         })
     }
 
+    pub fn layout(&self) -> GBufferLayout { self.layout }
+
+    /// Bind group 0 with `mesh_data` from [`MeshData::create_buffer`].
+    fn bind_group_0(&self, device: &wgpu::Device, mesh_data: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("gbuffer_bind_group_0"),
+            layout: &self.bind_group_layout_0,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.view_proj_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: mesh_data.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.clip_plane_buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.previous_view_proj_buf.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    /// Binds the textures (group 1), vertex streams and indices of `mesh`.
+    fn bind_mesh(&self, rp: &mut wgpu::RenderPass, device: &wgpu::Device, mesh: &MeshDraw) {
+        let bg1 = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("gbuffer_bind_group_1"),
+            layout: &self.bind_group_layout_1,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.base_color),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(
+                        &mesh.pbr_textures.metallic_roughness,
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.ao),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        rp.set_bind_group(1, &bg1, &[]);
+        rp.set_vertex_buffer(0, mesh.vertex_buf.slice(..));
+        rp.set_vertex_buffer(1, mesh.color_stream());
+        rp.set_vertex_buffer(2, mesh.tangent_stream());
+        rp.set_index_buffer(mesh.index_buf.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    fn pipeline_index(clipped: bool, mirrored: bool, double_sided: bool) -> usize {
+        clipped as usize | (mirrored as usize) << 1 | (double_sided as usize) << 2
+    }
+
+    /// Draw `meshes` into the GBuffer targets of `frame` as `options` says.
     pub fn encode(
         &self,
         encoder: &mut CommandEncoder,
@@ -215,127 +830,633 @@ impl GBufferPass {
         queue: &wgpu::Queue,
         frame: &crate::resources::FrameResources,
         meshes: &[MeshDraw],
-        view_proj: &[f32; 16],
+        options: GBufferDrawOptions,
     ) -> Result<(), String> {
+        let GBufferDrawOptions { view_proj, previous_view_proj, clip_plane, mirrored_view, indirect, depth_load, load_colors, store } =
+            options;
+        if frame.gbuffer_layout() != self.layout {
+            return Err(format!(
+                "GBufferPass::encode: frame has the {:?} GBuffer layout, pass was created for {:?}",
+                frame.gbuffer_layout(),
+                self.layout
+            ));
+        }
+        if let Some(args) = indirect {
+            if args.size() < meshes.len() as u64 * DRAW_ARGS_SIZE {
+                return Err(format!("GBufferPass::encode: indirect buffer too small for {} meshes", meshes.len()));
+            }
+        }
         queue.write_buffer(&self.view_proj_buf, 0, bytemuck::cast_slice(view_proj));
-        let gbuffer0 = frame.gbuffer0_view();
-        let gbuffer1 = frame.gbuffer1_view();
-        let gbuffer2 = frame.gbuffer2_view();
-        let gbuffer3 = frame.gbuffer3_view();
-        let depth_view = frame.depth_view();
-        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("gbuffer_pass"),
-            color_attachments: &[
-                Some(wgpu::RenderPassColorAttachment {
-                    view: &gbuffer0,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                }),
-                Some(wgpu::RenderPassColorAttachment {
-                    view: &gbuffer1,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                }),
-                Some(wgpu::RenderPassColorAttachment {
-                    view: &gbuffer2,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 1.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 0.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                }),
+        if self.layout.velocity.is_some() {
+            let previous = previous_view_proj.unwrap_or(view_proj);
+            queue.write_buffer(&self.previous_view_proj_buf, 0, bytemuck::cast_slice(previous));
+        }
+        if let Some(plane) = clip_plane {
+            queue.write_buffer(&self.clip_plane_buf, 0, bytemuck::cast_slice(&plane));
+        }
+        let gbuffer_views = frame.gbuffer_views();
+        let color_attachments: Vec<_> = gbuffer_views
+            .iter()
+            .zip(self.layout.clear_values())
+            .map(|(view, clear)| {
                 Some(wgpu::RenderPassColorAttachment {
-                    view: &gbuffer3,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
+                        load: if load_colors { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(clear) },
+                        store: store.color,
                     },
-                }),
-            ],
+                })
+            })
+            .collect();
+        let depth_view = frame.depth_view();
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("gbuffer_pass"),
+            color_attachments: &color_attachments,
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &depth_view,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: wgpu::StoreOp::Store,
+                    load: depth_load,
+                    store: store.depth,
                 }),
                 stencil_ops: None,
             }),
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        rp.set_pipeline(&self.pipeline);
         let w = frame.width() as f32;
         let h = frame.height() as f32;
         rp.set_viewport(0.0, 0.0, w, h, 0.0, 1.0);
-        for mesh in meshes {
-            let model_buf = device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("gbuffer_model"),
-                size: 64,
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            });
-            queue.write_buffer(&model_buf, 0, bytemuck::cast_slice(&mesh.transform));
-            let bg0 = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("gbuffer_bind_group_0"),
-                layout: &self.bind_group_layout_0,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: self.view_proj_buf.as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: model_buf.as_entire_binding(),
-                    },
-                ],
-            });
-            let bg1 = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("gbuffer_bind_group_1"),
-                layout: &self.bind_group_layout_1,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.base_color),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.normal),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(
-                            &mesh.pbr_textures.metallic_roughness,
-                        ),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.ao),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 4,
-                        resource: wgpu::BindingResource::Sampler(&self.sampler),
-                    },
-                ],
-            });
-            rp.set_bind_group(0, &bg0, &[]);
-            rp.set_bind_group(1, &bg1, &[]);
-            rp.set_vertex_buffer(0, mesh.vertex_buf.slice(..));
-            rp.set_index_buffer(mesh.index_buf.slice(..), wgpu::IndexFormat::Uint32);
-            rp.draw_indexed(0..mesh.index_count, 0, 0..1);
+        let pipeline_key = |mesh: &MeshDraw| {
+            (mesh.shader_variant, Self::pipeline_index(clip_plane.is_some(), mesh.mirrored != mirrored_view, mesh.double_sided))
+        };
+        let pipeline = |(variant, index)| &self.pipelines[&variant][index];
+        match self.mesh_data {
+            MeshData::Instanced { multi_draw } if !meshes.is_empty() => {
+                let bg0 = self.bind_group_0(device, &MeshData::create_buffer(device, meshes));
+                rp.set_bind_group(0, &bg0, &[]);
+                for (key, run) in mesh_runs(meshes, |mesh| Some(pipeline_key(mesh))) {
+                    rp.set_pipeline(pipeline(key));
+                    self.bind_mesh(&mut rp, device, &meshes[run.start]);
+                    draw_instanced(&mut rp, meshes, run, indirect, multi_draw);
+                }
+            }
+            _ => {
+                for (i, mesh) in meshes.iter().enumerate() {
+                    rp.set_pipeline(pipeline(pipeline_key(mesh)));
+                    let bg0 = self.bind_group_0(device, &MeshData::create_buffer(device, std::slice::from_ref(mesh)));
+                    rp.set_bind_group(0, &bg0, &[]);
+                    self.bind_mesh(&mut rp, device, mesh);
+                    match indirect {
+                        Some(args) => rp.draw_indexed_indirect(args, i as u64 * DRAW_ARGS_SIZE),
+                        None => rp.draw_indexed(0..mesh.index_count, 0, 0..1),
+                    }
+                }
+            }
         }
         drop(rp);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use wgpu::util::DeviceExt;
+
+    const LESS_EQUAL: wgpu::CompareFunction = wgpu::CompareFunction::LessEqual;
+
+    #[rustfmt::skip]
+    const MIRROR_X: [f32; 16] = [-1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+    #[test]
+    fn determinant_sign_detects_mirroring() {
+        let identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        assert!(!is_mirrored(&identity));
+        assert!(is_mirrored(&MIRROR_X));
+        // Two negative scales are a rotation.
+        let flip_xy = [-1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 5.0, 0.0, 0.0, 1.0];
+        assert!(!is_mirrored(&flip_xy));
+    }
+
+    /// What [`render_center_texels`] draws: the +z and -z faces of a cube (counter-clockwise from
+    /// outside; `faces` = 1 draws only the +z one) with back-face culling, seen from +z. The +z face is
+    /// at depth 0.25, the -z face at 0.75. With `cull_bounds`, the draw goes through `GpuCullPass` and
+    /// indirect args. `vertex_color` and `tangent` are one value for every vertex. The default draws the
+    /// +z face with an identity transform into the full layout, without either stream and with a flat
+    /// normal map. `previous_view_proj` feeds the velocity target of layouts that have one.
+    #[derive(Clone, Copy)]
+    struct TestDraw {
+        layout: GBufferLayout,
+        previous_view_proj: Option<[f32; 16]>,
+        faces: usize,
+        transform: [f32; 16],
+        mirrored: bool,
+        double_sided: bool,
+        cull_bounds: Option<crate::bounds::Aabb>,
+        depth_load: wgpu::LoadOp<f32>,
+        depth_compare: wgpu::CompareFunction,
+        store: GBufferStoreOps,
+        shader_variant: ShaderVariant,
+        vertex_color: Option<[f32; 4]>,
+        tangent: Option<[f32; 4]>,
+        normal_texel: [u8; 4],
+    }
+
+    impl Default for TestDraw {
+        fn default() -> Self {
+            Self {
+                layout: GBufferLayout::FULL,
+                previous_view_proj: None,
+                faces: 1,
+                transform: crate::IDENTITY,
+                mirrored: false,
+                double_sided: false,
+                cull_bounds: None,
+                depth_load: wgpu::LoadOp::Clear(GBUFFER_DEPTH_CLEAR),
+                depth_compare: LESS_EQUAL,
+                store: GBufferStoreOps::default(),
+                shader_variant: ShaderVariant::Pbr,
+                vertex_color: None,
+                tangent: None,
+                normal_texel: [128, 128, 255, 255],
+            }
+        }
+    }
+
+    /// Renders `draw` into an 8x8 frame and returns the center texel of every target of its layout (all
+    /// of them are 4 bytes per texel).
+    fn render_center_texels(device: &wgpu::Device, queue: &wgpu::Queue, draw: TestDraw) -> Vec<[u8; 4]> {
+        let TestDraw {
+            layout,
+            previous_view_proj,
+            faces,
+            transform,
+            mirrored,
+            double_sided,
+            cull_bounds,
+            depth_load,
+            depth_compare,
+            store,
+            shader_variant,
+            vertex_color,
+            tangent,
+            normal_texel,
+        } = draw;
+        let pass = GBufferPass::new(device, layout, GBUFFER_DEPTH_FORMAT, depth_compare, Some(wgpu::Face::Back), MaterialSampling::default(), &Default::default()).unwrap();
+        let frame = crate::resources::FrameResources::ensure_size(device, None, 8, 8, false, 0, layout).unwrap();
+        #[rustfmt::skip]
+        let vertices: [[f32; 8]; 8] = [
+            [-1.0, -1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0], [1.0, -1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0], [-1.0, 1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [-1.0, -1.0, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0], [-1.0, 1.0, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0],
+            [1.0, 1.0, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0], [1.0, -1.0, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0],
+        ];
+        let indices = &[0u32, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7][..faces * 6];
+        let white = test_support::texture(device, queue, [255; 4]);
+        let pbr_textures = PbrTextureViews {
+            base_color: white.clone(),
+            normal: test_support::texture(device, queue, normal_texel),
+            metallic_roughness: white.clone(),
+            ao: white,
+        };
+        let stream = |value: Option<[f32; 4]>| {
+            value.map(|value| {
+                Arc::new(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::cast_slice(&[value; 8]),
+                    usage: wgpu::BufferUsages::VERTEX,
+                }))
+            })
+        };
+        let mesh = MeshDraw {
+            transform,
+            mirrored,
+            double_sided,
+            bounds: cull_bounds,
+            shader_variant,
+            vertex_colors: stream(vertex_color),
+            tangents: stream(tangent),
+            ..test_support::mesh(device, &vertices, indices, pbr_textures)
+        };
+        // Looking down -z: depth = 0.5 - 0.5 * z, so the +z face is in front.
+        #[rustfmt::skip]
+        let view_proj = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 1.0];
+        let targets: Vec<&wgpu::Texture> =
+            [Some(&frame.gbuffer0), Some(&frame.gbuffer1), frame.gbuffer2(), frame.gbuffer3(), frame.velocity()]
+                .into_iter()
+                .flatten()
+                .collect();
+        const TARGET_SIZE: u64 = 256 * 8;
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: TARGET_SIZE * targets.len() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let indirect = cull_bounds.map(|_| crate::culling::GpuCullPass::create_indirect_buffer(device, 1));
+        if let Some(indirect) = &indirect {
+            let cull_pass = crate::culling::GpuCullPass::new(device, &Default::default()).unwrap();
+            let draws = crate::culling::CullDraws { meshes: std::slice::from_ref(&mesh), view_proj: &view_proj, indirect };
+            cull_pass.encode(&mut encoder, device, queue, draws, None).unwrap();
+        }
+        let draw = GBufferDrawOptions {
+            view_proj: &view_proj,
+            previous_view_proj: previous_view_proj.as_ref(),
+            indirect: indirect.as_ref(),
+            depth_load,
+            store,
+            ..Default::default()
+        };
+        pass.encode(&mut encoder, device, queue, &frame, &[mesh], draw).unwrap();
+        for (i, target) in targets.iter().enumerate() {
+            encoder.copy_texture_to_buffer(
+                target.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout { offset: i as u64 * TARGET_SIZE, bytes_per_row: Some(256), rows_per_image: None },
+                },
+                wgpu::Extent3d { width: 8, height: 8, depth_or_array_layers: 1 },
+            );
+        }
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        let center = |i: usize| i * TARGET_SIZE as usize + 4 * 256 + 4 * 4;
+        (0..targets.len()).map(|i| data[center(i)..center(i) + 4].try_into().unwrap()).collect()
+    }
+
+    /// Encoded normal z of the center texel of the full layout's gbuffer1 (0 where nothing was drawn).
+    fn render_center_normal_z(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: usize,
+        transform: [f32; 16],
+        mirrored: bool,
+        double_sided: bool,
+    ) -> u8 {
+        render_center_texels(device, queue, TestDraw { faces, transform, mirrored, double_sided, ..Default::default() })[1][2]
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn mirrored_mesh_keeps_front_faces_when_culling() {
+        let Some(adapter) = test_support::adapter("mirrored_mesh_keeps_front_faces_when_culling") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        // Encoded normal z: ~255 for the +z face (front), ~0 for the -z face behind it.
+        assert!(render_center_normal_z(&device, &queue, 2, identity, false, false) > 200);
+        assert!(render_center_normal_z(&device, &queue, 2, MIRROR_X, true, false) > 200);
+        // Without the flag the mirrored winding culls the front face and the back one shows through.
+        assert!(render_center_normal_z(&device, &queue, 2, MIRROR_X, false, false) < 55);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn double_sided_quad_faces_the_viewer_from_both_sides() {
+        let Some(adapter) = test_support::adapter("double_sided_quad_faces_the_viewer_from_both_sides") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        // Half turn around y: the quad shows its back to the camera.
+        let turned = [-1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        assert!(render_center_normal_z(&device, &queue, 1, identity, false, true) > 200);
+        assert!(render_center_normal_z(&device, &queue, 1, turned, false, true) > 200);
+        // Also correct for mirrored double-sided meshes, whose front_facing follows the flipped winding.
+        assert!(render_center_normal_z(&device, &queue, 1, MIRROR_X, true, true) > 200);
+        // Single-sided, the back face is culled.
+        assert_eq!(render_center_normal_z(&device, &queue, 1, turned, false, false), 0);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or it cannot run compute/indirect draws.
+    #[test]
+    fn indirect_draw_skips_culled_meshes() {
+        let Some(adapter) = test_support::adapter("indirect_draw_skips_culled_meshes") else {
+            return;
+        };
+        let needed = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        if !adapter.get_downlevel_capabilities().flags.contains(needed) {
+            eprintln!("indirect_draw_skips_culled_meshes: no compute/indirect support, skipped");
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        let draw = |bounds| render_center_texels(&device, &queue, TestDraw { cull_bounds: Some(bounds), ..Default::default() })[1][2];
+        let quad = crate::bounds::Aabb { min: [-1.0, -1.0, 0.5], max: [1.0, 1.0, 0.5] };
+        assert!(draw(quad) > 200);
+        // Bounds out to the side of the view: the quad is culled even though it would cover the center.
+        assert_eq!(draw(crate::bounds::Aabb { min: [4.0, -1.0, 0.5], max: [6.0, 1.0, 0.5] }), 0);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn depth_clear_value_limits_what_is_drawn() {
+        let Some(adapter) = test_support::adapter("depth_clear_value_limits_what_is_drawn") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let draw = |clear| {
+            render_center_texels(&device, &queue, TestDraw { depth_load: wgpu::LoadOp::Clear(clear), ..Default::default() })[1][2]
+        };
+        // The +z face sits at depth 0.25 and only passes LessEqual against a farther clear value.
+        assert!(draw(0.3) > 200);
+        assert_eq!(draw(0.2), 0);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn discarded_depth_still_tests_within_the_pass() {
+        let Some(adapter) = test_support::adapter("discarded_depth_still_tests_within_the_pass") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let store = GBufferStoreOps { color: wgpu::StoreOp::Store, depth: wgpu::StoreOp::Discard };
+        // Both cube faces, double-sided: the -z face behind must still lose the depth test.
+        let texels = render_center_texels(&device, &queue, TestDraw { faces: 2, double_sided: true, store, ..Default::default() });
+        assert!(texels[1][2] > 200, "{:?}", texels[1]);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn reverse_z_keeps_the_larger_depth() {
+        let Some(adapter) = test_support::adapter("reverse_z_keeps_the_larger_depth") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let draw = |clear| {
+            let draw = TestDraw { depth_load: wgpu::LoadOp::Clear(clear), depth_compare: scene_depth_compare(true), ..Default::default() };
+            render_center_texels(&device, &queue, draw)[1][2]
+        };
+        // The +z face sits at depth 0.25: reverse-Z draws it over a farther (smaller) depth only.
+        assert!(draw(REVERSE_Z_DEPTH_CLEAR) > 200);
+        assert!(draw(0.2) > 200);
+        assert_eq!(draw(0.3), 0);
+    }
+
+    #[test]
+    fn compact_layout_halves_bytes_per_pixel() {
+        assert_eq!(GBufferLayout::FULL.bytes_per_pixel(), 16);
+        assert_eq!(GBufferLayout::COMPACT.bytes_per_pixel(), 8);
+        // Rgba8Unorm costs 8 render target bytes: the full layout is at the default limit of 32.
+        assert_eq!(GBufferLayout::FULL.attachment_bytes_per_sample(), 32);
+        for layout in [GBufferLayout::FULL, GBufferLayout::COMPACT] {
+            assert_eq!(layout.target_count(), layout.packing.target_count());
+            assert_eq!(layout.target_count(), layout.clear_values().len());
+        }
+    }
+
+    #[test]
+    fn velocity_adds_one_target_to_either_packing() {
+        let fat = GBufferLayout::FULL.with_velocity();
+        assert_eq!((fat.target_count(), fat.bytes_per_pixel(), fat.attachment_bytes_per_sample()), (5, 20, 36));
+        let compact = GBufferLayout::COMPACT.with_velocity();
+        assert_eq!((compact.target_count(), compact.bytes_per_pixel(), compact.attachment_bytes_per_sample()), (3, 12, 20));
+        for layout in [fat, compact] {
+            assert_eq!(layout.target_count(), layout.clear_values().len());
+            let output = layout.output_wgsl();
+            assert!(output.contains(&format!("@location({}) velocity", layout.target_count() - 1)), "{}", output);
+        }
+        // Lighting never reads the velocity target.
+        assert!(GBufferPacking::Compact.load_wgsl().contains("@binding(1)"));
+        assert!(!GBufferPacking::Compact.load_wgsl().contains("@binding(2)"));
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn layouts_are_validated_against_the_device() {
+        let Some(adapter) = test_support::adapter("layouts_are_validated_against_the_device") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        use wgpu::TextureFormat::{Rg16Float, Rgb10a2Unorm, Rgba16Float, Rgba32Float, Rgba8Unorm, Rgba8UnormSrgb, Rgba8Uint};
+        let full = |formats| GBufferLayout { packing: GBufferPacking::Full, formats, velocity: None };
+        let error = |layout: GBufferLayout| layout.validate(&device).unwrap_err();
+        assert!(error(full(&[Rgba8Unorm; 2])).contains("needs 4 formats, got 2"));
+        // Two channels, sRGB, not filterable.
+        let invalid: [&'static [wgpu::TextureFormat]; 3] = [
+            &[Rgba8Unorm, Rg16Float, Rgba8Unorm, Rgba8Unorm],
+            &[Rgba8UnormSrgb, Rgba8Unorm, Rgba8Unorm, Rgba8Unorm],
+            &[Rgba8Unorm, Rgba8Unorm, Rgba8Uint, Rgba8Unorm],
+        ];
+        for formats in invalid {
+            assert!(error(full(formats)).contains("cannot be a GBuffer target"));
+        }
+        let err = GBufferPass::new(&device, full(&[Rgba8Unorm; 2]), GBUFFER_DEPTH_FORMAT, LESS_EQUAL, None, MaterialSampling::default(), &Default::default()).err().unwrap();
+        assert!(err.contains("needs 4 formats"), "{}", err);
+        // Half floats cost as much render target space as Rgba8Unorm: 32 bytes, at the default limit.
+        full(&[Rgba16Float; 4]).validate(&device).unwrap();
+        let velocity = |format| GBufferLayout { velocity: Some(format), ..GBufferLayout::COMPACT };
+        assert!(error(velocity(wgpu::TextureFormat::R16Float)).contains("cannot be a GBuffer velocity target"));
+        // 32 + 4 bytes: the fat layout is over the default limit.
+        assert!(error(GBufferLayout::FULL.with_velocity()).contains("needs 36 color attachment bytes"));
+        velocity(Rgba16Float).validate(&device).unwrap();
+        // 10-bit normals in the full packing: the +z normal encodes to the top of the 10-bit blue channel.
+        let layout = full(&[Rgba8Unorm, Rgb10a2Unorm, Rgba8Unorm, Rgba8Unorm]);
+        layout.validate(&device).unwrap();
+        let texels = render_center_texels(&device, &queue, TestDraw { layout, ..Default::default() });
+        assert!((u32::from_le_bytes(texels[1]) >> 20) & 1023 > 1000);
+        // Filterable Rgba32Float targets cost 64 bytes per sample.
+        let Some((device, _)) = test_support::device_with_features(&adapter, wgpu::Features::FLOAT32_FILTERABLE, "layouts_are_validated_against_the_device") else {
+            return;
+        };
+        let err = full(&[Rgba32Float; 4]).validate(&device).unwrap_err();
+        assert!(err.contains("needs 64 color attachment bytes per sample, device allows 32"), "{}", err);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn vertex_colors_multiply_base_color() {
+        let Some(adapter) = test_support::adapter("vertex_colors_multiply_base_color") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let render = |vertex_color| render_center_texels(&device, &queue, TestDraw { vertex_color, ..Default::default() })[0];
+        let white = render(None);
+        assert_eq!(&white[..3], &[255; 3]);
+        let tinted = render(Some([0.5, 0.25, 1.0, 1.0]));
+        assert!(tinted[0].abs_diff(128) <= 1 && tinted[1].abs_diff(64) <= 1 && tinted[2] == 255, "{:?}", tinted);
+    }
+
+    /// A normal map texel along the tangent must follow the tangent stream, not the basis the shader
+    /// derives from the normal. Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn tangents_orient_the_normal_map() {
+        let Some(adapter) = test_support::adapter("tangents_orient_the_normal_map") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        // Tangent-space (1, 0, ~0): the shaded normal is the tangent.
+        let normal = |tangent| {
+            let draw = TestDraw { tangent, normal_texel: [255, 128, 128, 255], ..Default::default() };
+            render_center_texels(&device, &queue, draw)[1]
+        };
+        let up = normal(Some([0.0, 1.0, 0.0, 1.0]));
+        assert!(up[1] > 240 && up[0].abs_diff(128) < 16, "{:?}", up);
+        let down = normal(Some([0.0, -1.0, 0.0, 1.0]));
+        assert!(down[1] < 16 && down[0].abs_diff(128) < 16, "{:?}", down);
+        // Without tangents the +z face gets the derived +x tangent.
+        let derived = normal(None);
+        assert!(derived[0] > 240, "{:?}", derived);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn unlit_variant_writes_the_unlit_shading_model() {
+        let Some(adapter) = test_support::adapter("unlit_variant_writes_the_unlit_shading_model") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let gbuffer1 = |layout, shader_variant| {
+            render_center_texels(&device, &queue, TestDraw { layout, shader_variant, ..Default::default() })[1]
+        };
+        // Full layout: shading model id / 3 in gbuffer1.a.
+        assert_eq!(gbuffer1(GBufferLayout::FULL, ShaderVariant::Pbr)[3], 85);
+        assert_eq!(gbuffer1(GBufferLayout::FULL, ShaderVariant::Unlit)[3], 0);
+        // Compact layout: roughness 0 (bits 20..30) marks unlit.
+        let roughness = |variant| (u32::from_le_bytes(gbuffer1(GBufferLayout::COMPACT, variant)) >> 20) & 1023;
+        assert_eq!(roughness(ShaderVariant::Pbr), 1023);
+        assert_eq!(roughness(ShaderVariant::Unlit), 0);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn velocity_target_holds_uv_motion_since_the_previous_view() {
+        let Some(adapter) = test_support::adapter("velocity_target_holds_uv_motion_since_the_previous_view") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let layout = GBufferLayout::COMPACT.with_velocity();
+        let velocity = |previous_view_proj| {
+            let texels = render_center_texels(&device, &queue, TestDraw { layout, previous_view_proj, ..Default::default() });
+            // Rg16Float halves.
+            let texel = texels[layout.target_count() - 1];
+            [u16::from_le_bytes([texel[0], texel[1]]), u16::from_le_bytes([texel[2], texel[3]])]
+        };
+        // No previous view: the pixel did not move (either sign of zero).
+        assert_eq!(velocity(None).map(|half| half & 0x7fff), [0, 0]);
+        // The previous view saw the center half an NDC unit right and up of where it is now, so it moved
+        // -0.25 in u and +0.25 in v (v points down).
+        #[rustfmt::skip]
+        let previous = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.5, 0.5, 0.5, 1.0];
+        assert_eq!(velocity(Some(previous)), [0xb400, 0x3400]);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn compact_layout_packs_octahedral_normal_and_material() {
+        let Some(adapter) = test_support::adapter("compact_layout_packs_octahedral_normal_and_material") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        // Rgb10a2Unorm: r in bits 0..10, g 10..20, b 20..30, a 30..32.
+        let texel = u32::from_le_bytes(render_center_texels(&device, &queue, TestDraw { layout: GBufferLayout::COMPACT, ..Default::default() })[1]);
+        let channel = |shift: u32, bits: u32| (texel >> shift) & ((1 << bits) - 1);
+        // +z normal sits at the center of the octahedral square.
+        assert!(channel(0, 10).abs_diff(512) <= 1, "octahedral x {}", channel(0, 10));
+        assert!(channel(10, 10).abs_diff(512) <= 1, "octahedral y {}", channel(10, 10));
+        // White metallic-roughness texture: roughness 1, metalness 1.
+        assert_eq!(channel(20, 10), 1023);
+        assert_eq!(channel(30, 2), 3);
+    }
+
+    /// Base color (gbuffer0 rgb) at the centers of the bottom-left, bottom-right, top-right and
+    /// top-left quadrants after GPU-culled draws of: a white quad in each of the bottom quadrants, a
+    /// culled white one between them, and a red one top right. The white quads share every binding.
+    fn render_quadrant_base_colors(device: &wgpu::Device, queue: &wgpu::Queue, capabilities: &AdapterCapabilities) -> [[u8; 3]; 4] {
+        let pass = GBufferPass::new(device, GBufferLayout::FULL, GBUFFER_DEPTH_FORMAT, LESS_EQUAL, None, MaterialSampling::default(), capabilities).unwrap();
+        let frame = crate::resources::FrameResources::ensure_size(device, None, 8, 8, false, 0, GBufferLayout::FULL).unwrap();
+        #[rustfmt::skip]
+        let vertices: [[f32; 8]; 4] = [
+            [-0.5, -0.5, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0], [0.5, -0.5, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [0.5, 0.5, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0], [-0.5, 0.5, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+        ];
+        let white = test_support::mesh(device, &vertices, &[0, 1, 2, 0, 2, 3], test_support::pbr_textures(test_support::texture(device, queue, [255; 4])));
+        let red = MeshDraw { pbr_textures: test_support::pbr_textures(test_support::texture(device, queue, [255, 0, 0, 255])), ..white.clone() };
+        let at = |mesh: &MeshDraw, x: f32, y: f32| MeshDraw {
+            transform: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, x, y, 0.0, 1.0],
+            bounds: Some(crate::bounds::Aabb { min: [-0.5, -0.5, 0.0], max: [0.5, 0.5, 0.0] }),
+            ..mesh.clone()
+        };
+        let meshes = [at(&white, -0.5, -0.5), at(&white, 5.0, 0.0), at(&white, 0.5, -0.5), at(&red, 0.5, 0.5)];
+        let view_proj = crate::IDENTITY;
+        let indirect = crate::culling::GpuCullPass::create_indirect_buffer(device, meshes.len());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let draws = crate::culling::CullDraws { meshes: &meshes, view_proj: &view_proj, indirect: &indirect };
+        crate::culling::GpuCullPass::new(device, capabilities).unwrap().encode(&mut encoder, device, queue, draws, None).unwrap();
+        let draw = GBufferDrawOptions { view_proj: &view_proj, indirect: Some(&indirect), ..Default::default() };
+        pass.encode(&mut encoder, device, queue, &frame, &meshes, draw).unwrap();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 8,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            frame.gbuffer0.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            wgpu::Extent3d { width: 8, height: 8, depth_or_array_layers: 1 },
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        [(2, 6), (6, 6), (6, 2), (2, 2)].map(|(x, y)| data[y * 256 + x * 4..][..3].try_into().unwrap())
+    }
+
+    /// Per-mesh draws always run; instanced ones when the adapter supports them, with one
+    /// `multi_draw_indexed_indirect` per run when it also has `MULTI_DRAW_INDIRECT`. Skipped without an
+    /// adapter or compute/indirect support.
+    #[test]
+    fn instanced_draws_match_per_mesh_draws() {
+        let test = "instanced_draws_match_per_mesh_draws";
+        let Some(adapter) = test_support::adapter(test) else {
+            return;
+        };
+        let capabilities = AdapterCapabilities::from_adapter(&adapter);
+        if !crate::culling::GpuCullPass::is_supported(&capabilities) {
+            eprintln!("{}: no compute/indirect support, skipped", test);
+            return;
+        }
+        let clear = [0; 3];
+        let expected = [[255; 3], [255; 3], [255, 0, 0], clear];
+        let (device, queue) = test_support::device(&adapter);
+        assert_eq!(MeshData::new(&device, &capabilities), MeshData::PerDraw);
+        assert_eq!(render_quadrant_base_colors(&device, &queue, &capabilities), expected);
+        for features in [wgpu::Features::INDIRECT_FIRST_INSTANCE, crate::culling::MULTI_DRAW_FEATURES] {
+            let Some((device, queue)) = test_support::device_with_features(&adapter, features, test) else {
+                return;
+            };
+            let multi_draw = features.contains(wgpu::Features::MULTI_DRAW_INDIRECT);
+            if MeshData::new(&device, &capabilities) != (MeshData::Instanced { multi_draw }) {
+                eprintln!("{}: no vertex storage or indirect instance_index, skipped", test);
+                return;
+            }
+            assert_eq!(render_quadrant_base_colors(&device, &queue, &capabilities), expected);
+        }
+    }
+
+    #[test]
+    fn mesh_runs_split_on_key_and_bindings() {
+        let Some(adapter) = test_support::adapter("mesh_runs_split_on_key_and_bindings") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let textures = test_support::pbr_textures(test_support::texture(&device, &queue, [255; 4]));
+        let a = test_support::mesh(&device, &[[0.0; 8]; 3], &[0, 1, 2], textures.clone());
+        let b = test_support::mesh(&device, &[[0.0; 8]; 3], &[0, 1, 2], textures);
+        let meshes = [a.clone(), a.clone(), b, a.clone(), MeshDraw { double_sided: true, ..a.clone() }, a];
+        let runs = mesh_runs(&meshes, |m| Some(m.double_sided));
+        assert_eq!(runs, vec![(false, 0..2), (false, 2..3), (false, 3..4), (true, 4..5), (false, 5..6)]);
+        // Skipped meshes end a run.
+        let runs = mesh_runs(&meshes, |m| (!m.double_sided).then_some(()));
+        assert_eq!(runs, vec![((), 0..2), ((), 2..3), ((), 3..4), ((), 5..6)]);
+    }
+}
@@ -1,6 +1,7 @@
 //! Lumelite Render Graph: task dependency ordering (wgpu-based).
 
 use std::collections::HashMap;
+use render_api::{DotAccess, GraphDot};
 use wgpu::CommandEncoder;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -23,6 +24,13 @@ impl ResourceUsage {
     pub fn is_read(&self) -> bool {
         matches!(self, ResourceUsage::Read | ResourceUsage::ReadWrite)
     }
+    fn dot_access(&self) -> DotAccess {
+        match self {
+            ResourceUsage::Read => DotAccess::Read,
+            ResourceUsage::Write => DotAccess::Write,
+            ResourceUsage::ReadWrite => DotAccess::ReadWrite,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +68,7 @@ impl ResourceHandle {
     }
 }
 
+#[derive(Default)]
 pub struct RenderGraph {
     nodes: Vec<Box<dyn RenderGraphNode>>,
     node_resource_usage: Vec<Vec<(ResourceId, ResourceUsage, Option<TextureBarrierHint>)>>,
@@ -69,19 +78,6 @@ pub struct RenderGraph {
     next_resource_id: usize,
 }
 
-impl Default for RenderGraph {
-    fn default() -> Self {
-        Self {
-            nodes: Vec::new(),
-            node_resource_usage: Vec::new(),
-            edges: Vec::new(),
-            resources: HashMap::new(),
-            next_node_id: 0,
-            next_resource_id: 0,
-        }
-    }
-}
-
 impl RenderGraph {
     pub fn new() -> Self { Self::default() }
     pub fn add_node(&mut self, node: Box<dyn RenderGraphNode>, resource_usage: Vec<(ResourceId, ResourceUsage, Option<TextureBarrierHint>)>) -> NodeId {
@@ -117,6 +113,28 @@ impl RenderGraph {
         if order.len() != n { return Err("Render graph has a cycle".to_string()); }
         Ok(order)
     }
+    /// Graphviz (DOT) dump for debugging, in the same format as the Lume graph ([`render_api::GraphDot`]);
+    /// texture barrier hints label their edge with the needed usage.
+    pub fn to_dot(&self) -> String {
+        let mut dot = GraphDot::new(self.nodes.len());
+        for (rid, handle) in &self.resources {
+            let kind = match handle {
+                ResourceHandle::Buffer(_) => "buffer",
+                ResourceHandle::Texture { .. } => "texture",
+            };
+            dot.resource(rid.0, kind);
+        }
+        for (i, usage) in self.node_resource_usage.iter().enumerate() {
+            for (rid, ru, hint) in usage {
+                let label = hint.as_ref().map(|h| format!("{:?}", h.need_usage));
+                dot.access(i, rid.0, ru.dot_access(), label.as_deref());
+            }
+        }
+        for &(NodeId(a), NodeId(b)) in &self.edges {
+            dot.edge(a, b, false);
+        }
+        dot.finish()
+    }
     pub fn execute(&self, device: &wgpu::Device) -> Result<wgpu::CommandBuffer, String> {
         let order = self.topological_order()?;
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("lumelite_render_graph") });
@@ -127,3 +145,37 @@ impl RenderGraph {
         Ok(encoder.finish())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EmptyNode;
+
+    impl RenderGraphNode for EmptyNode {
+        fn encode(&self, _: &mut CommandEncoder, _: &HashMap<ResourceId, &ResourceHandle>, _: &wgpu::Device) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dot_dump_lists_nodes_resource_usage_and_edges() {
+        let mut graph = RenderGraph::new();
+        let hint = TextureBarrierHint { need_usage: wgpu::TextureUsages::TEXTURE_BINDING, after_pass_usage: None };
+        let a = graph.add_node(Box::new(EmptyNode), vec![(ResourceId(0), ResourceUsage::Write, None)]);
+        let b = graph.add_node(
+            Box::new(EmptyNode),
+            vec![(ResourceId(0), ResourceUsage::Read, Some(hint)), (ResourceId(1), ResourceUsage::ReadWrite, None)],
+        );
+        graph.add_edge(a, b);
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph render_graph {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(dot.contains("n1 [shape=box, label=\"node 1\"];"));
+        assert!(dot.contains("r0 [shape=ellipse, label=\"resource 0\\nunregistered\"];"));
+        assert!(dot.contains("n0 -> r0 [color=red];"));
+        assert!(dot.contains("r0 -> n1 [color=blue, label=\"TextureUsages(TEXTURE_BINDING)\"];"));
+        assert!(dot.contains("n1 -> r1 [color=purple, dir=both];"));
+        assert!(dot.contains("n0 -> n1 [style=bold];"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Grid pass: editor/model-viewer ground grid on the world y = 0 plane, blended over the light buffer
+//! after the scene. A fullscreen triangle intersects each pixel's view ray with the plane (see
+//! grid.wgsl) and writes the hit depth, so geometry in front of the plane hides the grid.
+
+use wgpu::CommandEncoder;
+
+use crate::config::GridConfig;
+use crate::gbuffer::GBUFFER_DEPTH_FORMAT;
+use crate::resources::FrameResources;
+use crate::FrameView;
+
+const GRID_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/grid.wgsl"));
+
+/// `GridUniform` in grid.wgsl: two matrices and four vec4s.
+const GRID_UNIFORM_SIZE: u64 = 2 * 64 + 4 * 16;
+
+pub struct GridPass {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl GridPass {
+    pub fn new(
+        device: &wgpu::Device,
+        light_buffer_format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+    ) -> Result<Self, String> {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("grid_shader"),
+            source: wgpu::ShaderSource::Wgsl(GRID_SHADER.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("grid_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: std::num::NonZeroU64::new(GRID_UNIFORM_SIZE),
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("grid_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("grid_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs"),
+                // Alpha-blend the color; the light buffer's alpha is left as is.
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: light_buffer_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GBUFFER_DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("grid_uniform"),
+            size: GRID_UNIFORM_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("grid_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: uniform_buf.as_entire_binding() }],
+        });
+        Ok(Self { pipeline, uniform_buf, bind_group })
+    }
+
+    /// Blends the grid over the light buffer, depth-tested against the scene depth. `view_proj`,
+    /// `inv_view_proj` and `camera_pos` of `view` are the frame's camera.
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        queue: &wgpu::Queue,
+        frame: &FrameResources,
+        grid: &GridConfig,
+        view: &FrameView,
+    ) -> Result<(), String> {
+        let FrameView { view_proj, inv_view_proj, camera_pos, .. } = *view;
+        if grid.spacing <= 0.0 || grid.fade_distance <= 0.0 {
+            return Err(format!(
+                "GridPass::encode: spacing and fade_distance must be > 0, got {} and {}",
+                grid.spacing, grid.fade_distance
+            ));
+        }
+        let mut uniform = Vec::with_capacity(GRID_UNIFORM_SIZE as usize / 4);
+        uniform.extend_from_slice(view_proj);
+        uniform.extend_from_slice(inv_view_proj);
+        uniform.extend_from_slice(&[camera_pos[0], camera_pos[1], camera_pos[2], 0.0]);
+        uniform.extend_from_slice(&[grid.color[0], grid.color[1], grid.color[2], grid.opacity]);
+        let major = grid.major_color;
+        uniform.extend_from_slice(&[major[0], major[1], major[2], grid.major_every as f32]);
+        uniform.extend_from_slice(&[grid.spacing, grid.fade_distance, 0.0, 0.0]);
+        queue.write_buffer(&self.uniform_buf, 0, bytemuck::cast_slice(&uniform));
+        let light_view = frame.light_buffer_view();
+        let depth_view = frame.depth_view();
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("grid_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &light_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rp.set_pipeline(&self.pipeline);
+        rp.set_bind_group(0, &self.bind_group, &[]);
+        rp.draw(0..3, 0..1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbuffer::GBufferLayout;
+    use crate::resources::LIGHT_BUFFER_FORMAT;
+    use crate::test_support;
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn grid_lines_draw_on_the_plane_and_hide_behind_geometry() {
+        let Some(adapter) = test_support::adapter("grid_lines_draw_on_the_plane_and_hide_behind_geometry") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let pass = GridPass::new(&device, LIGHT_BUFFER_FORMAT, wgpu::CompareFunction::LessEqual).unwrap();
+        let frame = FrameResources::ensure_size(&device, None, 8, 8, false, 0, GBufferLayout::FULL).unwrap();
+        // Orthographic, looking down -y from y = 5: pixel (i, j) sees world (i - 3, 0, j - 3) at depth 0.5.
+        #[rustfmt::skip]
+        let view_proj = [0.25, 0.0, 0.0, 0.0, 0.0, 0.0, -0.1, 0.0, 0.0, -0.25, 0.0, 0.0, -0.125, 0.125, 0.5, 1.0];
+        #[rustfmt::skip]
+        let inv_view_proj = [4.0, 0.0, 0.0, 0.0, 0.0, 0.0, -4.0, 0.0, 0.0, -10.0, 0.0, 0.0, 0.5, 5.0, 0.5, 1.0];
+        let view = FrameView { view_proj: &view_proj, inv_view_proj: &inv_view_proj, camera_pos: [0.0, 5.0, 0.0], ..Default::default() };
+        let grid = GridConfig { spacing: 4.0, major_every: 0, ..Default::default() };
+        let red_channel = |scene_depth: f32| -> Vec<u16> {
+            let mut encoder = device.create_command_encoder(&Default::default());
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &frame.light_buffer_view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &frame.depth_view(),
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(scene_depth), store: wgpu::StoreOp::Store }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+            pass.encode(&mut encoder, &queue, &frame, &grid, &view).unwrap();
+            let readback = device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: 256 * 8,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            encoder.copy_texture_to_buffer(
+                frame.light_buffer.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+                },
+                frame.light_buffer.size(),
+            );
+            queue.submit([encoder.finish()]);
+            readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+            device.poll(wgpu::Maintain::Wait);
+            let data = readback.slice(..).get_mapped_range();
+            (0..64)
+                .map(|i| {
+                    let offset = (i / 8) * 256 + (i % 8) * 8;
+                    u16::from_le_bytes([data[offset], data[offset + 1]])
+                })
+                .collect()
+        };
+        let open = red_channel(1.0);
+        // Lines at x = 0 (column 3) and z = 0 (row 3); (5, 5) is between lines.
+        assert_ne!(open[3 * 8 + 5], 0);
+        assert_ne!(open[5 * 8 + 3], 0);
+        assert_eq!(open[5 * 8 + 5], 0);
+        // Scene depth in front of the plane hides the whole grid.
+        assert!(red_channel(0.3).iter().all(|&r| r == 0));
+    }
+}
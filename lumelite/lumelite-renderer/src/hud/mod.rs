@@ -0,0 +1,145 @@
+//! Timing HUD: per-pass GPU milliseconds ([`GpuTimings`]) as text and bars in the top-left corner,
+//! queued on a [`DebugText`] and so drawn over the presented image.
+
+use crate::debug_text::{rect_quad, text_quads, DebugText, TextQuad, CHAR_SIZE};
+use crate::timings::GpuTimings;
+
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const LINE_HEIGHT: f32 = CHAR_SIZE.1 + 2.0;
+/// Height of the bars and the last line's glyphs, without the blank row under them.
+const ROW_HEIGHT: f32 = CHAR_SIZE.1 - 2.0;
+/// Gap between the text and the bars.
+const GAP: f32 = 8.0;
+/// A full bar is one 60 Hz frame.
+const BAR_WIDTH: f32 = 160.0;
+const FRAME_BUDGET_MS: f32 = 1000.0 / 60.0;
+/// Characters per line: an 8-wide label, a 6-wide value and " MS".
+const TEXT_COLUMNS: usize = 17;
+
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+/// Bar colors of the shadow, GBuffer, light and present passes, then the total.
+const BAR_COLORS: [[f32; 4]; 5] = [
+    [0.55, 0.55, 0.95, 1.0],
+    [0.3, 0.85, 0.4, 1.0],
+    [1.0, 0.8, 0.3, 1.0],
+    [0.9, 0.4, 0.9, 1.0],
+    [1.0, 1.0, 1.0, 1.0],
+];
+
+/// The HUD for `timings` in output pixels: a translucent panel, then a line per pass and the total,
+/// each with a bar of its share of a 60 Hz frame.
+fn layout(timings: &GpuTimings) -> Vec<TextQuad> {
+    let passes = timings.passes();
+    let lines = passes.iter().copied().chain([("TOTAL", timings.total_ms())]);
+    let text_width = TEXT_COLUMNS as f32 * CHAR_SIZE.0;
+    let panel_height = passes.len() as f32 * LINE_HEIGHT + ROW_HEIGHT;
+    let mut quads = vec![rect_quad(
+        [MARGIN, MARGIN, text_width + GAP + BAR_WIDTH + 2.0 * PADDING, panel_height + 2.0 * PADDING],
+        PANEL_COLOR,
+    )];
+    let x = MARGIN + PADDING;
+    for (i, (name, ms)) in lines.enumerate() {
+        let y = MARGIN + PADDING + i as f32 * LINE_HEIGHT;
+        quads.extend(text_quads(&format!("{:<8}{:>6.2} MS", name, ms), x, y, TEXT_COLOR));
+        let bar = (ms / FRAME_BUDGET_MS).clamp(0.0, 1.0) * BAR_WIDTH;
+        if bar > 0.0 {
+            quads.push(rect_quad([x + text_width + GAP, y, bar, ROW_HEIGHT], BAR_COLORS[i]));
+        }
+    }
+    quads
+}
+
+/// Queue the HUD for `timings` on `text`.
+pub fn draw_timings(text: &DebugText, timings: &GpuTimings) {
+    text.push(layout(timings));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const TIMINGS: GpuTimings = GpuTimings { shadow_ms: 0.0, gbuffer_ms: 1000.0 / 120.0, light_ms: 40.0, present_ms: 0.5 };
+
+    #[test]
+    fn layout_has_a_line_and_bar_per_pass() {
+        let quads = layout(&TIMINGS);
+        let bars: Vec<_> = quads.iter().filter(|q| BAR_COLORS.contains(&q.color) && q.color != TEXT_COLOR).collect();
+        // No bar for the idle shadow pass; the light pass is clamped to one frame.
+        let close = |a: &[f32], b: &[f32]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4);
+        let widths: Vec<_> = bars.iter().map(|q| q.rect[2]).collect();
+        assert!(close(&widths, &[BAR_WIDTH / 2.0, BAR_WIDTH, BAR_WIDTH * 0.03]), "{:?}", widths);
+        // "GBUFFER    8.33 MS": 13 glyphs on the second line.
+        let line_y = MARGIN + PADDING + LINE_HEIGHT;
+        assert_eq!(quads.iter().filter(|q| q.color == TEXT_COLOR && q.rect[1] == line_y).count(), 13);
+        let panel = quads[0].rect;
+        assert!(quads.iter().all(|q| q.rect[0] + q.rect[2] <= panel[0] + panel[2] && q.rect[1] + q.rect[3] <= panel[1] + panel[3]));
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn hud_draws_bars_over_the_frame() {
+        let Some(adapter) = test_support::adapter("hud_draws_bars_over_the_frame") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let (width, height) = (448, 128);
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_view = output.create_view(&Default::default());
+        let text = DebugText::new(&device, &queue, wgpu::TextureFormat::Rgba8Unorm);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &output_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLUE), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        draw_timings(&text, &TIMINGS);
+        text.flush(&mut encoder, &queue, &output_view, (width, height), None).unwrap();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (width * height * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(width * 4), rows_per_image: None },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            [data[i], data[i + 1], data[i + 2]]
+        };
+        let near = |a: [u8; 3], b: [u8; 3]| a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= 2);
+        // Outside the panel: untouched. Inside, past the half-length GBuffer bar: the panel over blue.
+        assert_eq!(pixel(2, 2), [0, 0, 255]);
+        let bar_x = (MARGIN + PADDING + TEXT_COLUMNS as f32 * CHAR_SIZE.0 + GAP) as u32;
+        let gbuffer_y = (MARGIN + PADDING + LINE_HEIGHT) as u32 + 6;
+        assert!(near(pixel(bar_x + 4, gbuffer_y), [77, 217, 102]), "{:?}", pixel(bar_x + 4, gbuffer_y));
+        assert!(near(pixel(bar_x + 120, gbuffer_y), [0, 0, 102]), "{:?}", pixel(bar_x + 120, gbuffer_y));
+    }
+}
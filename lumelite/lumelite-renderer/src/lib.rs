@@ -1,24 +1,94 @@
 //! Lumelite Renderer: wgpu-based GBuffer + Flax-style Light Pass + Present.
 
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use render_api::{log, LogLevel, ShaderVariant};
+
+pub mod bounds;
 pub mod config;
+pub mod culling;
+pub mod debug_text;
 pub mod direct_triangle;
 pub mod gbuffer;
 pub mod gi;
+pub mod grid;
 pub mod graph;
+pub mod hud;
 pub mod light_pass;
+pub mod lod_bias;
 pub mod present;
 pub mod resources;
 pub mod shadows;
+pub mod timings;
+pub mod unlit;
 pub mod virtual_geom;
+/// Fixtures for GPU tests; opt in with the `test-support` feature.
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 
-pub use config::{LumeliteConfig, ToneMapping};
-pub use direct_triangle::DirectTrianglePass;
-pub use gbuffer::{GBufferPass, MeshDraw, PbrTextureViews};
+pub use bounds::{compute_aabb, Aabb, BoundsReducePass, VertexPositions};
+pub use config::{
+    AdapterCapabilities, AmbientLight, DepthLoadOps, DynamicResolution, FogConfig, FramePass, FogMode, GBufferStoreOps, GridConfig, LumeliteConfig, MaterialSampling, OcclusionCulling, PresentEffects, ReflectionRenderConfig, ToneMapping, UpscaleFilter,
+};
+pub use culling::{instanced_draws_supported, CullDraws, CullStats, GpuCullPass, HiZPass, HiZPyramid, MULTI_DRAW_FEATURES};
+pub use debug_text::DebugText;
+pub use direct_triangle::{DirectTriangleOutput, DirectTrianglePass};
+pub use gbuffer::{is_mirrored, GBufferDrawOptions, GBufferLayout, GBufferPacking, GBufferPass, MeshDraw, PbrTextureViews, MESH_VERTEX_LAYOUT, TANGENT_LAYOUT, VERTEX_COLOR_LAYOUT};
 pub use graph::{NodeId, RenderGraph, RenderGraphNode, ResourceHandle, ResourceId, ResourceUsage, TextureBarrierHint};
-pub use light_pass::LightPass;
-pub use present::PresentPass;
-pub use shadows::ShadowPass;
-pub use resources::FrameResources;
+pub use light_pass::{LightPass, LocalShadow};
+pub use lod_bias::LodBias;
+pub use grid::GridPass;
+pub use present::{is_hdr_format, ColorLut, PresentParams, PresentPass, COLOR_LUT_SIZE_RANGE};
+pub use shadows::{allocate_shadows, LocalShadowMaps, ShadowAllocation, ShadowDrawOptions, ShadowPass};
+pub use timings::{GpuTimer, GpuTimings};
+pub use unlit::UnlitPass;
+pub use resources::{FrameResources, TempTexture, TempTextureDesc, LIGHT_BUFFER_FORMAT};
+
+/// Format of the light buffer, which is the input of a [`PostProcessHook`] and also the format of its
+/// output.
+pub const POST_PROCESS_FORMAT: wgpu::TextureFormat = LIGHT_BUFFER_FORMAT;
+
+/// Host post-process run at the end of every `encode_frame` (color grading, vignette, ...), called with
+/// `(encoder, input, output, device, queue)`. `input` is the finished light buffer: linear HDR
+/// [`POST_PROCESS_FORMAT`] at the internal render resolution, readable as a texture. `output` has the
+/// same size and format and `RENDER_ATTACHMENT | TEXTURE_BINDING | STORAGE_BINDING | COPY_DST` usage;
+/// its contents are undefined until the hook writes every pixel of it. The present pass then tone maps
+/// `output` instead of the light buffer.
+pub type PostProcessHook = Box<
+    dyn Fn(&mut wgpu::CommandEncoder, &wgpu::TextureView, &wgpu::TextureView, &wgpu::Device, &wgpu::Queue) -> Result<(), String>
+        + Send,
+>;
+
+/// Camera and lights of one [`Renderer::encode_frame`]. The default is an identity camera at the
+/// origin with the directional light off (black), no point or spot lights and no shadows.
+#[derive(Clone, Copy)]
+pub struct FrameView<'a> {
+    pub view_proj: &'a [f32; 16],
+    pub inv_view_proj: &'a [f32; 16],
+    /// World-space eye position, for specular and fog distance.
+    pub camera_pos: [f32; 3],
+    /// (direction, color) of the directional light.
+    pub directional_light: ([f32; 3], [f32; 3]),
+    pub point_lights: &'a [render_api::PointLight],
+    pub spot_lights: &'a [render_api::SpotLight],
+    /// View-projection of the directional shadow map; `None` renders the light unshadowed.
+    pub light_view_proj: Option<&'a [f32; 16]>,
+}
+
+impl Default for FrameView<'_> {
+    fn default() -> Self {
+        Self {
+            view_proj: &IDENTITY,
+            inv_view_proj: &IDENTITY,
+            camera_pos: [0.0; 3],
+            directional_light: ([0.0, -1.0, 0.0], [0.0; 3]),
+            point_lights: &[],
+            spot_lights: &[],
+            light_view_proj: None,
+        }
+    }
+}
 
 pub struct Renderer {
     device: wgpu::Device,
@@ -29,7 +99,135 @@ pub struct Renderer {
     light_pass: LightPass,
     present_pass: PresentPass,
     shadow_pass: Option<ShadowPass>,
+    unlit_pass: UnlitPass,
+    /// Set when `config.grid` is.
+    grid_pass: Option<GridPass>,
+    /// Set when `config.gpu_culling` is on and the device supports it.
+    cull_pass: Option<GpuCullPass>,
+    /// Indirect args written by `cull_pass`, grown to the largest mesh count seen.
+    indirect_buf: Option<wgpu::Buffer>,
+    /// Late-pass args of two-pass occlusion culling, grown with `indirect_buf`.
+    late_indirect_buf: Option<wgpu::Buffer>,
+    /// Set with `cull_pass` when `config.occlusion_culling` is on.
+    hiz_pass: Option<HiZPass>,
+    /// History of frames rendered without a reflection set.
+    main_view: ViewHistory,
+    /// History of frames rendered while a reflection is set.
+    reflection_view: ViewHistory,
+    lod_bias: LodBias,
+    /// Set when `config.debug_gpu_timings` is on and the device supports it.
+    gpu_timer: Option<GpuTimer>,
+    /// Number of the `gpu_timer` sample dynamic resolution last stepped on; 0 before the first.
+    timing_sample_used: u64,
+    /// Flushed over the output after every present.
+    debug_text: DebugText,
+    frame_resources: Option<FrameResources>,
+    /// Output size `frame_resources` were last sized for (before the render scale).
+    output_size: (u32, u32),
+    /// Current render scale; starts at `config.render_scale`, updated by dynamic resolution.
+    render_scale: f32,
+    /// Current planar reflection clip plane; starts at `config.reflection`.
+    reflection: Option<ReflectionRenderConfig>,
+    /// Current depth clear/load of the GBuffer and shadow passes; starts at `config.depth_load`.
+    depth_load: DepthLoadOps,
+    /// Lights given a shadow map by the last `encode_frame`.
+    shadow_allocation: ShadowAllocation,
+    /// Shadow maps of the point and spot lights in `shadow_allocation`; grown as needed.
+    local_shadow_maps: Option<LocalShadowMaps>,
+    /// Passes switched off with `set_pass_enabled`; kept until switched back on.
+    disabled_passes: HashSet<FramePass>,
+    /// Set once `prewarm` has run.
+    prewarmed: bool,
+    post_process: Option<PostProcessHook>,
+    /// What `post_process` wrote in the last `encode_frame`; presented instead of the light buffer.
+    post_process_output: Option<TempTexture>,
+}
+
+/// State a view carries between its frames. The main view and the reflection each have their own, so a
+/// reflection frame never culls against (or overwrites) the main view's depth or velocity history.
+#[derive(Default)]
+struct ViewHistory {
+    /// `view_proj` of the view's last `encode_frame`, for the velocity GBuffer target.
+    previous_view_proj: Option<[f32; 16]>,
+    /// Depth of the view's last (first) GBuffer pass for `cull_pass`; set while occlusion culling runs.
+    hiz: Option<HiZPyramid>,
+    /// Per-mesh visibility of two-pass occlusion culling, grown with the mesh count.
+    visibility_buf: Option<wgpu::Buffer>,
+}
+
+/// Everything `encode_frame` and present change outside the passes' own caches. `prewarm` swaps a
+/// default one in around its throwaway frame, which also leaves out the host's post-process hook, GPU
+/// timer, disabled passes and triangle budget (so no LOD bias or cull stats readback).
+#[derive(Default)]
+struct FrameState {
     frame_resources: Option<FrameResources>,
+    output_size: (u32, u32),
+    main_view: ViewHistory,
+    reflection_view: ViewHistory,
+    lod_bias: LodBias,
+    triangle_budget: Option<u32>,
+    shadow_allocation: ShadowAllocation,
+    local_shadow_maps: Option<LocalShadowMaps>,
+    indirect_buf: Option<wgpu::Buffer>,
+    late_indirect_buf: Option<wgpu::Buffer>,
+    gpu_timer: Option<GpuTimer>,
+    timing_sample_used: u64,
+    disabled_passes: HashSet<FramePass>,
+    post_process: Option<PostProcessHook>,
+    post_process_output: Option<TempTexture>,
+}
+
+impl FrameState {
+    /// Exchange this state with the renderer's.
+    fn swap(&mut self, renderer: &mut Renderer) {
+        use std::mem::swap;
+        swap(&mut self.frame_resources, &mut renderer.frame_resources);
+        swap(&mut self.output_size, &mut renderer.output_size);
+        swap(&mut self.main_view, &mut renderer.main_view);
+        swap(&mut self.reflection_view, &mut renderer.reflection_view);
+        swap(&mut self.lod_bias, &mut renderer.lod_bias);
+        swap(&mut self.triangle_budget, &mut renderer.config.triangle_budget);
+        swap(&mut self.shadow_allocation, &mut renderer.shadow_allocation);
+        swap(&mut self.local_shadow_maps, &mut renderer.local_shadow_maps);
+        swap(&mut self.indirect_buf, &mut renderer.indirect_buf);
+        swap(&mut self.late_indirect_buf, &mut renderer.late_indirect_buf);
+        swap(&mut self.gpu_timer, &mut renderer.gpu_timer);
+        swap(&mut self.timing_sample_used, &mut renderer.timing_sample_used);
+        swap(&mut self.disabled_passes, &mut renderer.disabled_passes);
+        swap(&mut self.post_process, &mut renderer.post_process);
+        swap(&mut self.post_process_output, &mut renderer.post_process_output);
+    }
+}
+
+fn validate_render_scale(scale: f32) -> Result<(), String> {
+    if scale > 0.0 && scale <= 1.0 {
+        Ok(())
+    } else {
+        Err(format!("render_scale must be in (0, 1], got {}", scale))
+    }
+}
+
+/// Smallest shadow map side the render scale shrinks `shadow_resolution` to.
+const MIN_SCALED_SHADOW_RESOLUTION: u32 = 256;
+
+/// Frame time for the dynamic resolution controller to step on: `frame_time_ms` until GPU timings are
+/// read back, then each sample once. `None` while the latest sample is the one numbered `used`: it is
+/// several frames old, and stepping on it again would overshoot before the scale change shows up.
+fn controller_frame_time(frame_time_ms: f32, sample: Option<(u64, GpuTimings)>, used: &mut u64) -> Option<f32> {
+    match sample {
+        None => Some(frame_time_ms),
+        Some((index, _)) if index == *used => None,
+        Some((index, timings)) => {
+            *used = index;
+            Some(timings.total_ms())
+        }
+    }
+}
+
+/// Shadow map side at `render_scale`: texels per screen pixel stay roughly constant, so the map shrinks
+/// with the internal resolution, down to [`MIN_SCALED_SHADOW_RESOLUTION`] (or `resolution` if smaller).
+fn scaled_shadow_resolution(resolution: u32, render_scale: f32) -> u32 {
+    ((resolution as f32 * render_scale).round() as u32).clamp(resolution.min(MIN_SCALED_SHADOW_RESOLUTION), resolution)
 }
 
 impl Renderer {
@@ -38,25 +236,117 @@ impl Renderer {
     }
 
     pub fn new_with_config(device: wgpu::Device, queue: wgpu::Queue, config: LumeliteConfig) -> Result<Self, String> {
-        let direct_triangle_pass = DirectTrianglePass::new(&device, config.swapchain_format)?;
-        let gbuffer_pass = GBufferPass::new(&device, wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureFormat::Depth32Float)?;
-        let light_pass = LightPass::new(&device, wgpu::TextureFormat::Rgba16Float)?;
-        let present_pass = PresentPass::new(&device, config.swapchain_format, config.tone_mapping)?;
+        validate_render_scale(config.render_scale)?;
+        if config.reverse_z && config.depth_load.gbuffer == wgpu::LoadOp::Clear(gbuffer::GBUFFER_DEPTH_CLEAR) {
+            return Err("reverse_z needs depth_load.gbuffer to clear to the far plane at 0.0 (DepthLoadOps::reverse_z)".to_string());
+        }
+        let depth_compare = gbuffer::scene_depth_compare(config.reverse_z);
+        let direct_triangle_pass = DirectTrianglePass::new(&device, config.swapchain_format, config.msaa_samples)?;
+        let cull_mode = config.backface_culling.then_some(wgpu::Face::Back);
+        let gbuffer_pass = GBufferPass::new(
+            &device,
+            config.gbuffer_layout,
+            gbuffer::GBUFFER_DEPTH_FORMAT,
+            depth_compare,
+            cull_mode,
+            config.material_sampling,
+            &config.adapter_capabilities,
+        )?;
+        let light_pass = LightPass::new(
+            &device,
+            LIGHT_BUFFER_FORMAT,
+            config.shadow_enabled,
+            config.shadow_depth_bias,
+            config.sun_angular_size,
+            config.gbuffer_layout,
+            config.reverse_z,
+        )?;
+        let mut present_pass = PresentPass::new(
+            &device,
+            &queue,
+            config.swapchain_format,
+            config.hdr_output,
+            config.upscale_filter,
+            config.present_anisotropy,
+        )?;
+        present_pass.set_color_lut(&device, &queue, config.color_lut.as_ref())?;
+        present_pass.set_effects(config.present_effects)?;
         let shadow_pass = if config.shadow_enabled {
             Some(ShadowPass::new(&device, config.shadow_resolution)?)
         } else {
             None
         };
+        let unlit_pass = UnlitPass::new(
+            &device,
+            LIGHT_BUFFER_FORMAT,
+            depth_compare,
+            cull_mode,
+            config.material_sampling,
+            &config.adapter_capabilities,
+        )?;
+        let grid_pass = match config.grid {
+            Some(_) => Some(GridPass::new(&device, LIGHT_BUFFER_FORMAT, depth_compare)?),
+            None => None,
+        };
+        let capabilities = config.adapter_capabilities;
+        let cull_pass = if config.gpu_culling && GpuCullPass::is_supported(&capabilities) {
+            Some(GpuCullPass::new(&device, &config.adapter_capabilities)?)
+        } else {
+            if config.gpu_culling {
+                log(LogLevel::Info, "lumelite", format_args!("GPU culling unsupported by the adapter, drawing every mesh"));
+            }
+            None
+        };
+        let occlusion_requested = cull_pass.is_some() && config.occlusion_culling != OcclusionCulling::Off;
+        if occlusion_requested && !capabilities.compute_depth_loads {
+            log(LogLevel::Info, "lumelite", format_args!("Hi-Z needs depth loads in compute, occlusion culling off"));
+        }
+        if occlusion_requested && config.reverse_z {
+            log(LogLevel::Info, "lumelite", format_args!("Hi-Z assumes forward depth, occlusion culling off with reverse_z"));
+        }
+        let occlusion = occlusion_requested && capabilities.compute_depth_loads && !config.reverse_z;
+        let hiz_pass = occlusion.then(|| HiZPass::new(&device));
+        let gpu_timer = if config.debug_gpu_timings && GpuTimer::is_supported(&device) {
+            Some(GpuTimer::new(&device, &queue)?)
+        } else {
+            if config.debug_gpu_timings {
+                log(LogLevel::Info, "lumelite", format_args!("Timestamp queries unsupported by the device, GPU timings off"));
+            }
+            None
+        };
+        let debug_text = DebugText::new(&device, &queue, config.swapchain_format);
         Ok(Self {
             device,
             queue,
-            config,
             direct_triangle_pass,
             gbuffer_pass,
             light_pass,
             present_pass,
             shadow_pass,
+            unlit_pass,
+            grid_pass,
+            cull_pass,
+            indirect_buf: None,
+            late_indirect_buf: None,
+            hiz_pass,
+            main_view: ViewHistory::default(),
+            reflection_view: ViewHistory::default(),
+            lod_bias: LodBias::default(),
+            gpu_timer,
+            timing_sample_used: 0,
+            debug_text,
             frame_resources: None,
+            output_size: (0, 0),
+            render_scale: config.render_scale,
+            reflection: config.reflection,
+            depth_load: config.depth_load,
+            shadow_allocation: ShadowAllocation::default(),
+            local_shadow_maps: None,
+            disabled_passes: HashSet::new(),
+            prewarmed: false,
+            post_process: None,
+            post_process_output: None,
+            config,
         })
     }
 
@@ -64,17 +354,146 @@ impl Renderer {
     pub fn queue(&self) -> &wgpu::Queue { &self.queue }
     pub fn config(&self) -> &LumeliteConfig { &self.config }
 
+    pub fn render_scale(&self) -> f32 { self.render_scale }
+
+    /// Size of the GBuffer/light targets of the last frame (the output size at the render scale then
+    /// in effect); `None` before the first frame.
+    pub fn render_resolution(&self) -> Option<(u32, u32)> {
+        self.frame_resources.as_ref().map(|f| (f.width(), f.height()))
+    }
+
+    /// Output size the last frame was rendered for (before the render scale), e.g. to map cursor
+    /// positions for [`Self::read_depth_pixel`].
+    pub fn output_size(&self) -> (u32, u32) { self.output_size }
+
+    /// Whether the GBuffer pass is GPU-culled: `config.gpu_culling` is on and the device supports it.
+    pub fn gpu_culling_active(&self) -> bool { self.cull_pass.is_some() }
+
+    /// Mesh and triangle counts of the last frame's GPU cull pass (`None` when GPU culling is
+    /// inactive). Blocks until the GPU has finished the frame, which must have been submitted.
+    pub fn read_cull_stats(&self) -> Result<Option<CullStats>, String> {
+        self.cull_pass.as_ref().map(|pass| pass.read_stats(&self.device, &self.queue)).transpose()
+    }
+
+    /// LOD bias for `config.triangle_budget`, updated by every `encode_frame`; hosts selecting mesh LODs
+    /// apply it to the next frame's selection.
+    pub fn lod_bias(&self) -> LodBias { self.lod_bias }
+
+    /// Per-pass GPU timings of a recent frame when `config.debug_gpu_timings` is active; they lag the
+    /// current frame by the readback latency. `None` until the first frame is read back.
+    pub fn gpu_timings(&self) -> Option<GpuTimings> { self.gpu_timer.as_ref().and_then(GpuTimer::latest) }
+
+    /// Overlay text and rectangles queued here are drawn over the output by the next present
+    /// (`encode_present_region` and the functions built on it).
+    pub fn debug_text(&self) -> &DebugText { &self.debug_text }
+
+    /// Override the current render scale (must be in (0, 1]). Takes effect on the next frame.
+    pub fn set_render_scale(&mut self, scale: f32) -> Result<(), String> {
+        validate_render_scale(scale)?;
+        self.render_scale = scale;
+        Ok(())
+    }
+
+    pub fn reflection(&self) -> Option<ReflectionRenderConfig> { self.reflection }
+
+    /// Set or clear the planar reflection clip plane used by the GBuffer pass from the next frame.
+    pub fn set_reflection(&mut self, reflection: Option<ReflectionRenderConfig>) {
+        self.reflection = reflection;
+        if reflection.is_none() {
+            self.reflection_view = ViewHistory::default();
+        }
+    }
+
+    pub fn depth_load(&self) -> DepthLoadOps { self.depth_load }
+
+    /// Lights given a shadow map by the last frame (`config.max_shadowed_lights`).
+    pub fn shadow_allocation(&self) -> &ShadowAllocation { &self.shadow_allocation }
+
+    pub fn pass_enabled(&self, pass: FramePass) -> bool { !self.disabled_passes.contains(&pass) }
+
+    /// Skip (`false`) or restore `pass` in every following `encode_frame`, without recreating pipelines.
+    /// All passes start enabled; passes the config never created stay off regardless.
+    pub fn set_pass_enabled(&mut self, pass: FramePass, enabled: bool) {
+        if enabled {
+            self.disabled_passes.remove(&pass);
+        } else {
+            self.disabled_passes.insert(pass);
+        }
+    }
+
+    /// Install (`Some`) or remove the host post-process run between lighting and present from the next
+    /// `encode_frame`. See [`PostProcessHook`] for the input and output it gets.
+    pub fn set_post_process(&mut self, hook: Option<PostProcessHook>) {
+        self.post_process = hook;
+    }
+
+    /// Replace (`Some`) or remove the present color grading LUT from the next frame. Invalid tables are
+    /// rejected and leave the current one in place.
+    pub fn set_color_lut(&mut self, lut: Option<ColorLut>) -> Result<(), String> {
+        self.present_pass.set_color_lut(&self.device, &self.queue, lut.as_ref())?;
+        self.config.color_lut = lut;
+        Ok(())
+    }
+
+    /// Change the present vignette and chromatic aberration from the next frame.
+    pub fn set_present_effects(&mut self, effects: PresentEffects) -> Result<(), String> {
+        self.present_pass.set_effects(effects)?;
+        self.config.present_effects = effects;
+        Ok(())
+    }
+
+    /// Set how the GBuffer and shadow passes start their depth targets from the next frame (e.g. `Load`
+    /// after a host depth prepass).
+    pub fn set_depth_load(&mut self, depth_load: DepthLoadOps) {
+        self.depth_load = depth_load;
+    }
+
+    /// Step the dynamic resolution controller once per frame. No-op when disabled. With
+    /// `config.debug_gpu_timings` active the controller is fed the measured GPU time of each read-back
+    /// frame (`GpuTimings::total_ms`) once, and does not step until the next one arrives;
+    /// `frame_time_ms` is only used until the first one does. Otherwise the host must pass the frame's
+    /// GPU time in ms: CPU frame time includes vsync and present waits, which lowering the render scale
+    /// cannot shorten.
+    pub fn report_frame_time(&mut self, frame_time_ms: f32) {
+        if let Some(dynamic) = self.config.dynamic_resolution {
+            let sample = self.gpu_timer.as_ref().and_then(GpuTimer::latest_sample);
+            if let Some(frame_time_ms) = controller_frame_time(frame_time_ms, sample, &mut self.timing_sample_used) {
+                self.render_scale = dynamic.next_scale(self.render_scale, frame_time_ms);
+            }
+        }
+    }
+
+    /// Internal render size for an output size at the current render scale.
+    pub fn internal_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |v: u32| ((v as f32 * self.render_scale).round() as u32).max(1);
+        (scale(width), scale(height))
+    }
+
+    /// Ensure GBuffer/depth/light buffer exist for the given output size, scaled by the render scale.
     pub fn ensure_frame_resources(&mut self, width: u32, height: u32) -> Result<(), String> {
+        let (internal_width, internal_height) = self.internal_size(width, height);
         let existing = self.frame_resources.take();
+        // `ensure_size` keeps the targets unless one of these changed.
+        let key = |r: &FrameResources| (r.width(), r.height(), r.shadow_map.as_ref().map(|t| t.width()), r.gbuffer_layout());
+        let previous = existing.as_ref().map(key);
         let new_res = FrameResources::ensure_size(
             &self.device,
             existing,
-            width,
-            height,
+            internal_width,
+            internal_height,
             self.config.shadow_enabled,
-            self.config.shadow_resolution,
+            scaled_shadow_resolution(self.config.shadow_resolution, self.render_scale),
+            self.config.gbuffer_layout,
         )?;
+        if previous != Some(key(&new_res)) {
+            log(
+                LogLevel::Info,
+                "lumelite",
+                format_args!("frame resources created at {}x{} (output {}x{})", internal_width, internal_height, width, height),
+            );
+        }
         self.frame_resources = Some(new_res);
+        self.output_size = (width, height);
         Ok(())
     }
 
@@ -82,112 +501,870 @@ impl Renderer {
         self.frame_resources.as_ref().map(|f| &f.light_buffer)
     }
 
-    /// Encode direct triangle to output view (debug path). Bypasses GBuffer/Light/Present.
+    /// Previous frame's light buffer when `LumeliteConfig::light_history` is on (see `FrameResources::light_history`).
+    pub fn light_history(&self) -> Option<&wgpu::Texture> {
+        self.frame_resources.as_ref().and_then(|f| f.light_history())
+    }
+
+    /// Last frame's velocity target when `LumeliteConfig::gbuffer_layout` has one (see `FrameResources::velocity`).
+    pub fn velocity(&self) -> Option<&wgpu::Texture> {
+        self.frame_resources.as_ref().and_then(|f| f.velocity())
+    }
+
+    /// Scene depth under output pixel (`x`, `y`) of the last frame, as the host's projection wrote it:
+    /// 0 near .. 1 far (1 where nothing was drawn), or 1 near .. 0 far (0 where nothing was drawn) with
+    /// `LumeliteConfig::reverse_z`. For picking, test against the background value for the convention
+    /// in use; with `inv_view_proj`, the NDC point `(u * 2 - 1, 1 - v * 2, depth)` is the
+    /// world position under the cursor. Copies just that texel (a single 4-byte row, so no row padding)
+    /// and blocks until it is read; submit the frame first. `Ok(None)` without a frame or outside the output.
+    pub fn read_depth_pixel(&self, x: u32, y: u32) -> Result<Option<f32>, String> {
+        let Some(frame) = self.frame_resources.as_ref() else {
+            return Ok(None);
+        };
+        let (width, height) = self.output_size;
+        if x >= width || y >= height {
+            return Ok(None);
+        }
+        // Depth is rendered at the render scale.
+        let texel_x = (x as u64 * frame.width() as u64 / width as u64) as u32;
+        let texel_y = (y as u64 * frame.height() as u64 / height as u64) as u32;
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("depth_pixel_readback"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder =
+            self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("depth_pixel_readback") });
+        // wgpu transitions the depth texture from attachment/sampled use to copy source.
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &frame.depth,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: texel_x, y: texel_y, z: 0 },
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: None, rows_per_image: None },
+            },
+            wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+        );
+        self.queue.submit([encoder.finish()]);
+        let slice = staging.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |r| {
+            let _ = tx.send(r);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        rx.recv()
+            .map_err(|e| format!("depth readback: {}", e))?
+            .map_err(|e| format!("depth readback: {}", e))?;
+        let depth: f32 = bytemuck::pod_read_unaligned(&slice.get_mapped_range());
+        staging.unmap();
+        Ok(Some(depth))
+    }
+
+    /// Encode direct triangle to `output` (debug path). Bypasses GBuffer/Light/Present. `output.format` must be
+    /// `config.swapchain_format`; with `config.msaa_samples` > 1 the pass resolves into `output.view`.
     pub fn encode_direct_triangle(
-        &self,
+        &mut self,
         encoder: &mut wgpu::CommandEncoder,
-        output_view: &wgpu::TextureView,
+        output: DirectTriangleOutput,
         meshes: &[MeshDraw],
         view_proj: &[f32; 16],
     ) -> Result<(), String> {
-        self.direct_triangle_pass.encode(
-            encoder,
-            &self.device,
-            &self.queue,
-            output_view,
-            meshes,
-            view_proj,
-        )
+        self.direct_triangle_pass.encode(encoder, &self.device, &self.queue, output, meshes, view_proj)
     }
 
-    /// Encode GBuffer + Light pass into the given encoder. Call ensure_frame_resources (or render_frame) first so frame size is set.
+    /// Encode GBuffer + Light pass into the given encoder. `width`/`height` are the output size; internal targets
+    /// are sized by the render scale.
     pub fn encode_frame(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
         width: u32,
         height: u32,
-        view_proj: &[f32; 16],
-        inv_view_proj: &[f32; 16],
         meshes: &[MeshDraw],
-        directional_light: ([f32; 3], [f32; 3]),
-        point_lights: &[render_api::PointLight],
-        spot_lights: &[render_api::SpotLight],
-        light_view_proj: Option<&[f32; 16]>,
+        view: &FrameView,
     ) -> Result<(), String> {
+        let FrameView { view_proj, inv_view_proj, camera_pos, directional_light, point_lights, spot_lights, light_view_proj } = *view;
         self.ensure_frame_resources(width, height)?;
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.begin_frame(&self.device, encoder)?;
+        }
+        if let Some(frame) = self.frame_resources.as_mut() {
+            frame.begin_frame();
+            if let Some(output) = self.post_process_output.take() {
+                frame.release_temp(output);
+            }
+        }
+        let max_point = self.config.max_point_lights as usize;
+        let max_spot = self.config.max_spot_lights as usize;
+        let enabled = |pass| !self.disabled_passes.contains(&pass);
+        let point_lights = if enabled(FramePass::PointLights) { &point_lights[..point_lights.len().min(max_point)] } else { &[] };
+        let spot_lights = if enabled(FramePass::SpotLights) { &spot_lights[..spot_lights.len().min(max_spot)] } else { &[] };
+        // Nothing renders shadows without the shadow pass. A point light takes six layers, so this budget
+        // always fits the device's array layers.
+        let shadow_budget = match (&self.shadow_pass, enabled(FramePass::Shadow)) {
+            (Some(_), true) => self.config.max_shadowed_lights.min(self.device.limits().max_texture_array_layers / 6),
+            _ => 0,
+        };
+        self.shadow_allocation =
+            allocate_shadows(shadow_budget, light_view_proj.is_some(), point_lights, spot_lights);
+        let light_view_proj = light_view_proj.filter(|_| self.shadow_allocation.directional);
+        let local_layers = self.shadow_allocation.local_layer_count();
+        if local_layers > 0 {
+            let resolution = self.config.local_shadow_resolution.max(1);
+            let existing = self.local_shadow_maps.take();
+            self.local_shadow_maps = Some(LocalShadowMaps::ensure(&self.device, existing, resolution, local_layers));
+        }
         let frame = self.frame_resources.as_ref().unwrap();
         if let (Some(ref shadow_pass), Some(lvp)) = (&self.shadow_pass, light_view_proj) {
-            shadow_pass.encode(encoder, &self.device, &self.queue, frame, meshes, lvp)?;
+            let draw = ShadowDrawOptions { light_view_proj: lvp, depth_load: self.depth_load.shadow };
+            shadow_pass.encode(encoder, &self.device, &self.queue, frame, meshes, draw)?;
         }
-        self.gbuffer_pass.encode(encoder, &self.device, &self.queue, frame, meshes, view_proj)?;
-        self.light_pass.encode_directional(
-            encoder,
-            &self.device,
-            &self.queue,
-            frame,
-            directional_light.0,
-            directional_light.1,
-            inv_view_proj,
-        )?;
-        let max_point = self.config.max_point_lights as usize;
-        for light in point_lights.iter().take(max_point) {
-            self.light_pass.encode_point(
-                encoder,
+        // Local shadow layers: each allocated point light's six cube faces, then one per spot light, in
+        // allocation order (see `local_shadow` below).
+        if let (Some(shadow_pass), Some(maps)) = (&self.shadow_pass, self.local_shadow_maps.as_ref().filter(|_| local_layers > 0)) {
+            let mut layer = 0;
+            for &i in &self.shadow_allocation.point_lights {
+                for view_proj in &shadows::point_shadow_view_projs(&point_lights[i]) {
+                    shadow_pass.encode_local(encoder, &self.device, &self.queue, &maps.layer_view(layer), meshes, view_proj);
+                    layer += 1;
+                }
+            }
+            for &i in &self.shadow_allocation.spot_lights {
+                let view_proj = shadows::spot_shadow_view_proj(&spot_lights[i]);
+                shadow_pass.encode_local(encoder, &self.device, &self.queue, &maps.layer_view(layer), meshes, &view_proj);
+                layer += 1;
+            }
+        }
+        if let Some(timer) = &self.gpu_timer {
+            timer.mark(encoder, timings::SHADOW_END)?;
+        }
+        // A reflection is rendered with a view mirrored by `ReflectionRenderConfig::reflection_matrix`.
+        let clip_plane = self.reflection.map(|r| r.clip_plane);
+        let mirrored_view = self.reflection.is_some();
+        // No Hi-Z pass when the adapter cannot build the pyramid.
+        let occlusion = if enabled(FramePass::OcclusionCulling) && self.hiz_pass.is_some() {
+            self.config.occlusion_culling
+        } else {
+            OcclusionCulling::Off
+        };
+        let cull_pass = self.cull_pass.as_ref().filter(|_| enabled(FramePass::GpuCulling));
+        let two_pass = cull_pass.is_some() && occlusion == OcclusionCulling::TwoPass;
+        if cull_pass.is_some() {
+            let needed = meshes.len() as u64 * culling::DRAW_ARGS_SIZE;
+            let count = meshes.len().next_power_of_two();
+            if self.indirect_buf.as_ref().is_none_or(|b| b.size() < needed) {
+                self.indirect_buf = Some(GpuCullPass::create_indirect_buffer(&self.device, count));
+            }
+            if two_pass && self.late_indirect_buf.as_ref().is_none_or(|b| b.size() < needed) {
+                self.late_indirect_buf = Some(GpuCullPass::create_indirect_buffer(&self.device, count));
+            }
+        }
+        let view = if mirrored_view { &mut self.reflection_view } else { &mut self.main_view };
+        if two_pass && view.visibility_buf.as_ref().is_none_or(|b| b.size() < meshes.len() as u64 * 4) {
+            view.visibility_buf = Some(GpuCullPass::create_visibility_buffer(&self.device, meshes.len().next_power_of_two()));
+        }
+        let indirect = self.indirect_buf.as_ref().filter(|_| cull_pass.is_some());
+        if let (Some(cull_pass), Some(indirect)) = (cull_pass, indirect) {
+            if two_pass {
+                let visibility = view.visibility_buf.as_ref().unwrap();
+                cull_pass.encode_early(encoder, &self.device, &self.queue, CullDraws { meshes, view_proj, indirect }, visibility)?;
+            } else {
+                let hiz = view.hiz.as_ref().filter(|_| occlusion == OcclusionCulling::SinglePass);
+                cull_pass.encode(encoder, &self.device, &self.queue, CullDraws { meshes, view_proj, indirect }, hiz)?;
+            }
+        }
+        let previous_view_proj = view.previous_view_proj.replace(*view_proj);
+        let draw = GBufferDrawOptions {
+            view_proj,
+            previous_view_proj: previous_view_proj.as_ref(),
+            clip_plane,
+            mirrored_view,
+            indirect,
+            depth_load: self.depth_load.gbuffer,
+            ..Default::default()
+        };
+        self.gbuffer_pass.encode(encoder, &self.device, &self.queue, frame, meshes, draw)?;
+        // Hi-Z of the depth so far: for the late pass of two-pass culling, or single-pass culling next
+        // frame.
+        match self.hiz_pass.as_ref().filter(|_| cull_pass.is_some() && occlusion != OcclusionCulling::Off) {
+            Some(hiz_pass) => {
+                let size = (frame.width(), frame.height());
+                if view.hiz.as_ref().is_none_or(|h| h.size() != size) {
+                    view.hiz = Some(HiZPyramid::new(&self.device, size.0, size.1));
+                }
+                hiz_pass.encode(encoder, &self.device, &frame.depth_view(), view_proj, view.hiz.as_mut().unwrap());
+            }
+            // Not kept while unused, so it is never more than a frame of its view old.
+            None => view.hiz = None,
+        }
+        let late_indirect = match cull_pass.filter(|_| two_pass) {
+            Some(cull_pass) => {
+                let late_indirect = self.late_indirect_buf.as_ref().unwrap();
+                let visibility = view.visibility_buf.as_ref().unwrap();
+                let hiz = view.hiz.as_ref().unwrap();
+                let draws = CullDraws { meshes, view_proj, indirect: late_indirect };
+                cull_pass.encode_late(encoder, &self.device, &self.queue, draws, visibility, hiz)?;
+                let late_draw = GBufferDrawOptions {
+                    indirect: Some(late_indirect),
+                    depth_load: wgpu::LoadOp::Load,
+                    load_colors: true,
+                    ..draw
+                };
+                self.gbuffer_pass.encode(encoder, &self.device, &self.queue, frame, meshes, late_draw)?;
+                Some(late_indirect)
+            }
+            None => None,
+        };
+        if self.config.triangle_budget.is_some() {
+            // Culled frames count their triangles on the GPU, read back a frame or more late.
+            let triangles = match self.cull_pass.as_mut().filter(|_| indirect.is_some()) {
+                Some(cull_pass) => {
+                    let latest = cull_pass.poll_stats(&self.device);
+                    cull_pass.copy_stats(encoder);
+                    latest.map(|stats| stats.triangles)
+                }
+                None => Some(meshes.iter().map(|m| m.index_count / 3).sum()),
+            };
+            if let Some(triangles) = triangles {
+                self.lod_bias.update(triangles, self.config.triangle_budget);
+            }
+        }
+        if let Some(timer) = &self.gpu_timer {
+            timer.mark(encoder, timings::GBUFFER_END)?;
+        }
+        // The lights left after the limits, pass toggles and shadow allocation above.
+        let lit = FrameView { view_proj, inv_view_proj, camera_pos, directional_light, point_lights, spot_lights, light_view_proj };
+        let ambient = self.config.ambient.as_ref().filter(|_| enabled(FramePass::Ambient));
+        self.light_pass.encode_ambient(encoder, &self.device, &self.queue, frame, ambient)?;
+        if enabled(FramePass::Directional) {
+            self.light_pass.encode_directional(encoder, &self.device, &self.queue, frame, &lit)?;
+        }
+        let local_shadow = |first_layer| {
+            self.local_shadow_maps.as_ref().filter(|_| local_layers > 0).map(|maps| LocalShadow { maps, first_layer })
+        };
+        let allocated_points = self.shadow_allocation.point_lights.len() as u32;
+        for (i, light) in point_lights.iter().enumerate() {
+            let slot = self.shadow_allocation.point_lights.iter().position(|&p| p == i);
+            let shadow = slot.and_then(|slot| local_shadow(slot as u32 * 6));
+            self.light_pass.encode_point(encoder, &self.device, frame, light, &lit, shadow)?;
+        }
+        for (i, light) in spot_lights.iter().enumerate() {
+            let slot = self.shadow_allocation.spot_lights.iter().position(|&s| s == i);
+            let shadow = slot.and_then(|slot| local_shadow(allocated_points * 6 + slot as u32));
+            self.light_pass.encode_spot(encoder, &self.device, frame, light, &lit, shadow)?;
+        }
+        // Unlit meshes replace whatever the lights wrote under them; fog still applies on top.
+        if enabled(FramePass::Unlit) {
+            self.unlit_pass.encode(encoder, &self.device, &self.queue, frame, meshes, draw)?;
+            // Two-pass culling draws each mesh in one of the passes, so emissive meshes add once.
+            if let Some(late_indirect) = late_indirect {
+                let late_draw = GBufferDrawOptions { indirect: Some(late_indirect), ..draw };
+                self.unlit_pass.encode(encoder, &self.device, &self.queue, frame, meshes, late_draw)?;
+            }
+        }
+        if let Some(fog) = self.config.fog.as_ref().filter(|_| enabled(FramePass::Fog)) {
+            self.light_pass.encode_fog(encoder, &self.device, &self.queue, frame, fog, &lit)?;
+        }
+        if let (Some(grid_pass), Some(grid)) = (&self.grid_pass, self.config.grid.as_ref().filter(|_| enabled(FramePass::Grid))) {
+            grid_pass.encode(encoder, &self.queue, frame, grid, &lit)?;
+        }
+        // Last in the frame: temporal passes above still read the previous frame's history.
+        if self.config.light_history {
+            if let Some(frame) = self.frame_resources.as_mut() {
+                frame.copy_light_to_history(&self.device, encoder);
+            }
+        }
+        if let (Some(hook), Some(frame)) = (self.post_process.as_ref().filter(|_| enabled(FramePass::PostProcess)), self.frame_resources.as_mut()) {
+            let output = frame.acquire_temp(
                 &self.device,
-                &self.queue,
-                frame,
-                light,
-                inv_view_proj,
-            )?;
+                &TempTextureDesc {
+                    width: frame.width(),
+                    height: frame.height(),
+                    format: POST_PROCESS_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::STORAGE_BINDING
+                        | wgpu::TextureUsages::COPY_DST,
+                },
+            );
+            hook(encoder, &frame.light_buffer_view(), &output.view(), &self.device, &self.queue)?;
+            self.post_process_output = Some(output);
         }
-        let max_spot = self.config.max_spot_lights as usize;
-        for light in spot_lights.iter().take(max_spot) {
-            self.light_pass.encode_spot(encoder, &self.device, &self.queue, frame, light, inv_view_proj)?;
+        if let Some(timer) = &self.gpu_timer {
+            timer.mark(encoder, timings::LIGHT_END)?;
         }
         Ok(())
     }
 
     /// Encode present pass: light buffer -> output view (e.g. swapchain). Requires encode_frame to have been called this frame.
     /// When debug_show_gbuffer is true, presents GBuffer0 directly (bypasses Light pass for debugging).
+    /// `output_view` must be [`Self::output_size`]; use [`Self::encode_present_region`] for other targets.
     pub fn encode_present_to(
         &self,
         encoder: &mut wgpu::CommandEncoder,
         output_view: &wgpu::TextureView,
+    ) -> Result<(), String> {
+        self.encode_present_with(encoder, output_view, None, None)
+    }
+
+    /// Like [`Self::encode_present_to`], with per-view tone mapping and exposure overrides (e.g. from
+    /// `ExtractedView`); `None` uses `config.tone_mapping` / `config.exposure`.
+    pub fn encode_present_with(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        tone_mapping: Option<ToneMapping>,
+        exposure: Option<f32>,
+    ) -> Result<(), String> {
+        self.encode_present_region(encoder, output_view, self.output_size, None, tone_mapping, exposure)
+    }
+
+    /// Like [`Self::encode_present_with`], but draws the scene into `region` (x, y, width, height in
+    /// output pixels) for aspect-preserving or split-screen layouts. The whole output is cleared to
+    /// `config.background_color` first. `output_size` is the pixel size of `output_view` (the other
+    /// present functions assume [`Self::output_size`]); a region outside it is an error. Debug text is
+    /// laid out against the region, or the whole output without one.
+    pub fn encode_present_region(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output_view: &wgpu::TextureView,
+        output_size: (u32, u32),
+        region: Option<[u32; 4]>,
+        tone_mapping: Option<ToneMapping>,
+        exposure: Option<f32>,
     ) -> Result<(), String> {
         let frame = self.frame_resources.as_ref().ok_or("encode_present_to: no frame (call encode_frame first)")?;
         let source = if self.config.debug_show_gbuffer {
             frame.gbuffer0_view()
+        } else if let Some(output) = &self.post_process_output {
+            output.view()
         } else {
             frame.light_buffer_view()
         };
-        self.present_pass.encode(
-            encoder,
-            &self.device,
-            &self.queue,
-            &source,
-            output_view,
-            self.config.debug_clear_green,
-        )
+        let params = PresentParams {
+            output_size,
+            tone_mapping: tone_mapping.unwrap_or(self.config.tone_mapping),
+            exposure: exposure.unwrap_or(self.config.exposure),
+            background: self.config.background_color,
+            region,
+            debug_clear_green: self.config.debug_clear_green,
+        };
+        self.present_pass.encode(encoder, &self.device, &self.queue, &source, output_view, &params)?;
+        if let Some(timer) = &self.gpu_timer {
+            timer.end_frame(encoder)?;
+            if let Some(timings) = timer.latest() {
+                hud::draw_timings(&self.debug_text, &timings);
+            }
+        }
+        self.debug_text.flush(encoder, &self.queue, output_view, output_size, region)
     }
 
-    pub fn render_frame(
-        &mut self,
-        width: u32,
-        height: u32,
-        view_proj: &[f32; 16],
-        inv_view_proj: &[f32; 16],
-        meshes: &[MeshDraw],
-        directional_light: ([f32; 3], [f32; 3]),
-        point_lights: &[render_api::PointLight],
-        spot_lights: &[render_api::SpotLight],
-        light_view_proj: Option<&[f32; 16]>,
-    ) -> Result<wgpu::CommandBuffer, String> {
+    pub fn render_frame(&mut self, width: u32, height: u32, meshes: &[MeshDraw], view: &FrameView) -> Result<wgpu::CommandBuffer, String> {
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("lumelite_frame") });
-        self.encode_frame(&mut encoder, width, height, view_proj, inv_view_proj, meshes, directional_light, point_lights, spot_lights, light_view_proj)?;
+        self.encode_frame(&mut encoder, width, height, meshes, view)?;
         Ok(encoder.finish())
     }
 
-    pub fn submit(&self, command_buffers: impl IntoIterator<Item = wgpu::CommandBuffer>) {
-        self.queue.submit(command_buffers);
+    /// Submit to the renderer's queue; the index identifies the submission for `Maintain::WaitForSubmissionIndex`.
+    pub fn submit(&self, command_buffers: impl IntoIterator<Item = wgpu::CommandBuffer>) -> wgpu::SubmissionIndex {
+        self.queue.submit(command_buffers)
+    }
+
+    /// Start the non-blocking readbacks (GPU timings, culling stats) that the frames encoded since the
+    /// last call queued. Call it after submitting each frame, with [`Self::submit`] or to the queue
+    /// directly, since mapping a buffer an unsubmitted copy writes fails validation.
+    pub fn frame_submitted(&mut self) -> Result<(), String> {
+        if let Some(timer) = &self.gpu_timer {
+            timer.frame_submitted()?;
+        }
+        if let Some(cull_pass) = &mut self.cull_pass {
+            cull_pass.stats_submitted();
+        }
+        Ok(())
+    }
+
+    /// Avoid the first-frame hitch: pipelines already exist after `new`, but drivers finish shader
+    /// compilation and allocate internal state on first use. This records a 1x1 throwaway frame that
+    /// uses every pipeline the config can reach (shadow, every GBuffer variant, ambient,
+    /// directional/point/spot lights, fog, present) and waits for it. Everything a frame changes
+    /// (`FrameState`, and the cull stats) is swapped out around it, and the throwaway frame runs
+    /// neither the post-process hook nor the GPU timer nor debug text, so it can be called at any point
+    /// between frames; later calls are no-ops.
+    pub fn prewarm(&mut self) -> Result<(), String> {
+        if self.prewarmed {
+            return Ok(());
+        }
+        let mut state = FrameState::default();
+        state.swap(self);
+        let result = self.encode_prewarm_frame();
+        state.swap(self);
+        self.queue.submit([result?.finish()]);
+        self.device.poll(wgpu::Maintain::Wait);
+        self.prewarmed = true;
+        Ok(())
+    }
+
+    fn encode_prewarm_frame(&mut self) -> Result<wgpu::CommandEncoder, String> {
+        let device = &self.device;
+        let make_buffer = |label, size, usage| {
+            Arc::new(device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size, usage, mapped_at_creation: false }))
+        };
+        let make_view = |label, format| {
+            Arc::new(
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        view_formats: &[],
+                    })
+                    .create_view(&Default::default()),
+            )
+        };
+        // One degenerate (zeroed) triangle: enough to bind and draw with every pipeline.
+        let texture = make_view("lumelite_prewarm_texture", wgpu::TextureFormat::Rgba8Unorm);
+        let mesh = MeshDraw {
+            vertex_buf: make_buffer("lumelite_prewarm_vertices", 96, wgpu::BufferUsages::VERTEX),
+            index_buf: make_buffer("lumelite_prewarm_indices", 12, wgpu::BufferUsages::INDEX),
+            index_count: 3,
+            transform: IDENTITY,
+            pbr_textures: PbrTextureViews {
+                base_color: texture.clone(),
+                normal: texture.clone(),
+                metallic_roughness: texture.clone(),
+                ao: texture,
+            },
+            mirrored: false,
+            double_sided: false,
+            bounds: None,
+            shader_variant: ShaderVariant::Pbr,
+            vertex_colors: None,
+            tangents: None,
+            emissive: [0.0; 3],
+        };
+        let output = make_view("lumelite_prewarm_output", self.config.swapchain_format);
+        // Copies for the clockwise-front-face and double-sided GBuffer variants, for every shader variant.
+        let meshes: Vec<MeshDraw> = ShaderVariant::ALL
+            .into_iter()
+            .flat_map(|shader_variant| {
+                let mesh = MeshDraw { shader_variant, ..mesh.clone() };
+                let mirrored = MeshDraw { mirrored: true, ..mesh.clone() };
+                let double_sided = MeshDraw { double_sided: true, ..mesh.clone() };
+                [mesh, mirrored.clone(), double_sided, MeshDraw { double_sided: true, ..mirrored }]
+            })
+            .collect();
+        let mut encoder =
+            self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("lumelite_prewarm") });
+        let saved_stats = self.cull_pass.as_ref().map(|pass| pass.save_stats(&self.device, &mut encoder));
+        if self.config.debug_direct_triangle {
+            let output = DirectTriangleOutput { view: &output, format: self.config.swapchain_format, width: 1, height: 1 };
+            self.encode_direct_triangle(&mut encoder, output, &meshes, &IDENTITY)?;
+        }
+        let light = ([0.0, -1.0, 0.0], [1.0, 1.0, 1.0]);
+        let point = render_api::PointLight { radius: 1.0, ..Default::default() };
+        let spot = render_api::SpotLight { direction: [0.0, -1.0, 0.0], radius: 1.0, ..Default::default() };
+        let shadow = self.shadow_pass.is_some().then_some(&IDENTITY);
+        let view = FrameView {
+            directional_light: light,
+            point_lights: &[point],
+            spot_lights: &[spot],
+            light_view_proj: shadow,
+            ..Default::default()
+        };
+        self.encode_frame(&mut encoder, 1, 1, &meshes, &view)?;
+        // The clip-plane GBuffer variant only runs while a reflection is set; warm it as well.
+        let frame = self.frame_resources.as_ref().ok_or("prewarm: no frame resources")?;
+        let draw = GBufferDrawOptions { clip_plane: Some([0.0, 1.0, 0.0, 0.0]), depth_load: self.depth_load.gbuffer, ..Default::default() };
+        self.gbuffer_pass.encode(&mut encoder, &self.device, &self.queue, frame, &meshes, draw)?;
+        // Straight to the present pass: `encode_present_to` would flush the host's queued debug text.
+        let params = PresentParams {
+            tone_mapping: self.config.tone_mapping,
+            exposure: self.config.exposure,
+            background: self.config.background_color,
+            debug_clear_green: self.config.debug_clear_green,
+            ..PresentParams::new((1, 1))
+        };
+        self.present_pass.encode(&mut encoder, &self.device, &self.queue, &frame.light_buffer_view(), &output, &params)?;
+        if let (Some(pass), Some(saved)) = (&self.cull_pass, &saved_stats) {
+            pass.restore_stats(&mut encoder, saved);
+        }
+        Ok(encoder)
+    }
+}
+
+const IDENTITY: [f32; 16] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadow_map_scales_with_render_scale() {
+        assert_eq!(scaled_shadow_resolution(2048, 1.0), 2048);
+        assert_eq!(scaled_shadow_resolution(2048, 0.5), 1024);
+        assert_eq!(scaled_shadow_resolution(1024, 0.1), MIN_SCALED_SHADOW_RESOLUTION);
+        assert_eq!(scaled_shadow_resolution(128, 0.5), 128);
+    }
+
+    #[test]
+    fn dynamic_resolution_steps_once_per_timing_sample() {
+        let timings = GpuTimings { gbuffer_ms: 20.0, ..Default::default() };
+        let mut used = 0;
+        // Host time until the first readback.
+        assert_eq!(controller_frame_time(12.0, None, &mut used), Some(12.0));
+        assert_eq!(controller_frame_time(12.0, Some((1, timings)), &mut used), Some(20.0));
+        // The same sample on later frames is skipped, a new one is used.
+        assert_eq!(controller_frame_time(12.0, Some((1, timings)), &mut used), None);
+        assert_eq!(controller_frame_time(12.0, Some((1, timings)), &mut used), None);
+        assert_eq!(controller_frame_time(12.0, Some((2, timings)), &mut used), Some(20.0));
+
+        // Fed every frame, a stale over-budget sample lowers the scale by a single step.
+        let dynamic = DynamicResolution { target_frame_ms: 10.0, min_scale: 0.25 };
+        let sample = Some((3, timings));
+        let mut scale = 1.0;
+        for _ in 0..5 {
+            if let Some(ms) = controller_frame_time(12.0, sample, &mut used) {
+                scale = dynamic.next_scale(scale, ms);
+            }
+        }
+        assert_eq!(scale, dynamic.next_scale(1.0, 20.0));
+        assert!(scale < 1.0);
+    }
+
+    /// Prewarm must record a valid frame through every pass and leave the renderer's frame state, and
+    /// the host's post-process hook, as they were. Skipped when no adapter is available (e.g. headless
+    /// CI) or on GL.
+    #[test]
+    fn prewarm_is_idempotent_and_keeps_frame_resources() {
+        let Some(adapter) = test_support::adapter("prewarm_is_idempotent_and_keeps_frame_resources") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        if test_support::skip_gl(&adapter, "prewarm_is_idempotent_and_keeps_frame_resources") {
+            return;
+        }
+        let config = LumeliteConfig {
+            shadow_enabled: true,
+            fog: Some(FogConfig::default()),
+            grid: Some(GridConfig::default()),
+            ..Default::default()
+        };
+        let mut renderer = Renderer::new_with_config(device, queue, config).unwrap();
+        let hook_ran = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = hook_ran.clone();
+        renderer.set_post_process(Some(Box::new(move |_, _, _, _, _| {
+            flag.store(true, std::sync::atomic::Ordering::Relaxed);
+            Ok(())
+        })));
+        renderer.ensure_frame_resources(8, 6).unwrap();
+        renderer.prewarm().unwrap();
+        renderer.prewarm().unwrap();
+        assert_eq!(renderer.render_resolution(), Some((8, 6)));
+        assert_eq!(renderer.output_size(), (8, 6));
+        assert!(!hook_ran.load(std::sync::atomic::Ordering::Relaxed));
+        assert!(renderer.post_process.is_some() && renderer.post_process_output.is_none());
+        assert_eq!(renderer.shadow_allocation, ShadowAllocation::default());
+        assert!(renderer.local_shadow_maps.is_none());
+        assert!([&renderer.main_view, &renderer.reflection_view].iter().all(|v| v.hiz.is_none() && v.visibility_buf.is_none() && v.previous_view_proj.is_none()));
+        assert_eq!(renderer.lod_bias, LodBias::default());
+    }
+
+    fn f16_to_f32(bits: u16) -> f32 {
+        let exponent = (bits >> 10) & 0x1f;
+        let mantissa = (bits & 0x3ff) as f32;
+        let magnitude = match exponent {
+            0 => mantissa * 2f32.powi(-24),
+            0x1f => f32::INFINITY,
+            e => (1.0 + mantissa / 1024.0) * 2f32.powi(e as i32 - 15),
+        };
+        if bits & 0x8000 != 0 { -magnitude } else { magnitude }
+    }
+
+    /// Lights a quad with varying normals under a directional and a point light and returns the light
+    /// buffer's rgb values. A `point_shadow_priority` above 0 gives the point light a shadow map.
+    fn render_lit_quad(
+        adapter: &wgpu::Adapter,
+        gbuffer_layout: GBufferLayout,
+        shader_variant: ShaderVariant,
+        disabled: &[FramePass],
+        point_shadow_priority: u32,
+    ) -> Vec<f32> {
+        const SIZE: u32 = 16;
+        let (device, queue) = test_support::device(adapter);
+        // Corner normals lean outward, so every pixel gets a different interpolated normal.
+        let corner = |x: f32, y: f32| {
+            let len = (x * x * 0.25 + y * y * 0.25 + 1.0f32).sqrt();
+            [x, y, 0.5, x * 0.5 / len, y * 0.5 / len, 1.0 / len, 0.0, 0.0]
+        };
+        let vertices = [corner(-1.0, -1.0), corner(1.0, -1.0), corner(1.0, 1.0), corner(-1.0, 1.0)];
+        let texture = |texel| test_support::texture(&device, &queue, texel);
+        let pbr_textures = PbrTextureViews {
+            base_color: texture([200, 150, 100, 255]),
+            normal: texture([128, 128, 255, 255]),
+            // Dielectric (metalness is exact in both layouts), roughness ~0.5.
+            metallic_roughness: texture([0, 128, 0, 255]),
+            ao: texture([255; 4]),
+        };
+        let mesh = MeshDraw { shader_variant, ..test_support::mesh(&device, &vertices, &[0, 1, 2, 0, 2, 3], pbr_textures) };
+        // Looking down -z: depth = 0.5 - 0.5 * z.
+        let view_proj = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 1.0];
+        let inv_view_proj = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -2.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        let point = render_api::PointLight {
+            position: [0.5, 0.5, 1.5],
+            color: [2.0; 3],
+            radius: 4.0,
+            shadow_priority: point_shadow_priority,
+            ..Default::default()
+        };
+        let sun = ([-0.3, -0.4, -1.0], [1.0, 1.0, 1.0]);
+        let config = LumeliteConfig {
+            gbuffer_layout,
+            ambient: None,
+            shadow_enabled: point_shadow_priority > 0,
+            ..Default::default()
+        };
+        let mut renderer = Renderer::new_with_config(device, queue, config).unwrap();
+        for &pass in disabled {
+            renderer.set_pass_enabled(pass, false);
+        }
+        let mut encoder = renderer.device().create_command_encoder(&Default::default());
+        let view = FrameView {
+            view_proj: &view_proj,
+            inv_view_proj: &inv_view_proj,
+            camera_pos: [0.0, 0.0, 3.0],
+            directional_light: sun,
+            point_lights: &[point],
+            ..Default::default()
+        };
+        renderer.encode_frame(&mut encoder, SIZE, SIZE, &[mesh], &view).unwrap();
+        let readback = renderer.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * SIZE as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let light_buffer = renderer.current_light_buffer().unwrap();
+        encoder.copy_texture_to_buffer(
+            light_buffer.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            light_buffer.size(),
+        );
+        renderer.submit([encoder.finish()]);
+        renderer.frame_submitted().unwrap();
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        renderer.device().poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        (0..SIZE as usize)
+            .flat_map(|y| (0..SIZE as usize * 4).map(move |c| (y, c)))
+            .filter(|(_, c)| c % 4 != 3)
+            .map(|(y, c)| f16_to_f32(u16::from_le_bytes([data[y * 256 + c * 2], data[y * 256 + c * 2 + 1]])))
+            .collect()
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or on GL (see above).
+    #[test]
+    fn compact_gbuffer_lighting_matches_full_layout() {
+        let Some(adapter) = test_support::adapter("compact_gbuffer_lighting_matches_full_layout") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "compact_gbuffer_lighting_matches_full_layout") {
+            return;
+        }
+        let full = render_lit_quad(&adapter, GBufferLayout::FULL, ShaderVariant::Pbr, &[], 0);
+        let compact = render_lit_quad(&adapter, GBufferLayout::COMPACT, ShaderVariant::Pbr, &[], 0);
+        let peak = full.iter().cloned().fold(0.0f32, f32::max);
+        assert!(peak > 0.1, "scene is unlit");
+        for (i, (a, b)) in full.iter().zip(&compact).enumerate() {
+            // Quantization differences only: 8-bit normal/roughness vs 10-bit octahedral/roughness.
+            assert!((a - b).abs() <= 0.02 * peak + 1e-3, "component {}: full {} vs compact {}", i, a, b);
+        }
+    }
+
+    /// A shadowed point light over a quad with nothing between them must light it like an unshadowed one
+    /// (no acne from the shadow test). Skipped when no adapter is available (e.g. headless CI) or on GL.
+    #[test]
+    fn unoccluded_shadowed_point_light_matches_unshadowed() {
+        let Some(adapter) = test_support::adapter("unoccluded_shadowed_point_light_matches_unshadowed") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "unoccluded_shadowed_point_light_matches_unshadowed") {
+            return;
+        }
+        let disabled = [FramePass::Directional];
+        let unshadowed = render_lit_quad(&adapter, GBufferLayout::FULL, ShaderVariant::Pbr, &disabled, 0);
+        let shadowed = render_lit_quad(&adapter, GBufferLayout::FULL, ShaderVariant::Pbr, &disabled, 1);
+        let peak = unshadowed.iter().cloned().fold(0.0f32, f32::max);
+        assert!(peak > 0.1, "scene is unlit");
+        for (i, (a, b)) in unshadowed.iter().zip(&shadowed).enumerate() {
+            assert!((a - b).abs() <= 0.02 * peak + 1e-3, "component {}: unshadowed {} vs shadowed {}", i, a, b);
+        }
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or on GL (see above).
+    #[test]
+    fn unlit_mesh_shows_base_color_under_lights() {
+        let Some(adapter) = test_support::adapter("unlit_mesh_shows_base_color_under_lights") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "unlit_mesh_shows_base_color_under_lights") {
+            return;
+        }
+        let base_color = [200.0 / 255.0, 150.0 / 255.0, 100.0 / 255.0];
+        // The velocity target is attached after the lit inputs and must not shift their bindings.
+        for layout in [GBufferLayout::FULL, GBufferLayout::COMPACT, GBufferLayout::COMPACT.with_velocity()] {
+            let unlit = render_lit_quad(&adapter, layout, ShaderVariant::Unlit, &[], 0);
+            for (i, value) in unlit.iter().enumerate() {
+                let expected = base_color[i % 3];
+                assert!((value - expected).abs() < 0.01, "{:?} component {}: {} vs {}", layout, i, value, expected);
+            }
+        }
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or on GL (see above).
+    #[test]
+    fn disabled_light_passes_leave_the_scene_dark() {
+        let Some(adapter) = test_support::adapter("disabled_light_passes_leave_the_scene_dark") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "disabled_light_passes_leave_the_scene_dark") {
+            return;
+        }
+        let point_only = render_lit_quad(&adapter, GBufferLayout::FULL, ShaderVariant::Pbr, &[FramePass::Directional], 0);
+        assert!(point_only.iter().any(|&v| v > 0.01), "point light was skipped too");
+        let dark = render_lit_quad(&adapter, GBufferLayout::FULL, ShaderVariant::Pbr, &[FramePass::Directional, FramePass::PointLights], 0);
+        assert!(dark.iter().all(|&v| v == 0.0), "{:?}", dark);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or on GL (see above).
+    #[test]
+    fn post_process_hook_output_is_presented() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let Some(adapter) = test_support::adapter("post_process_hook_output_is_presented") else {
+            return;
+        };
+        if test_support::skip_gl(&adapter, "post_process_hook_output_is_presented") {
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        let config = LumeliteConfig { tone_mapping: ToneMapping::None, ..Default::default() };
+        let mut renderer = Renderer::new_with_config(device, queue, config).unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        renderer.set_post_process(Some(Box::new(move |encoder, _input, output, _device, _queue| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.5, g: 0.25, b: 0.0, a: 1.0 }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            Ok(())
+        })));
+        let target = renderer.device().create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let present = |renderer: &mut Renderer| -> [u8; 4] {
+            let mut encoder = renderer.device().create_command_encoder(&Default::default());
+            let sun = ([0.0, -1.0, 0.0], [1.0; 3]);
+            let view = FrameView { directional_light: sun, ..Default::default() };
+            renderer.encode_frame(&mut encoder, 4, 4, &[], &view).unwrap();
+            renderer.encode_present_to(&mut encoder, &target.create_view(&Default::default())).unwrap();
+            let readback = renderer.device().create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: 256 * 4,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            encoder.copy_texture_to_buffer(
+                target.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+                },
+                target.size(),
+            );
+            renderer.submit([encoder.finish()]);
+            renderer.frame_submitted().unwrap();
+            readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+            renderer.device().poll(wgpu::Maintain::Wait);
+            let data = readback.slice(..).get_mapped_range();
+            [data[0], data[1], data[2], data[3]]
+        };
+        let graded = present(&mut renderer);
+        assert!(graded[0].abs_diff(128) <= 1 && graded[1].abs_diff(64) <= 1 && graded[2] == 0, "{:?}", graded);
+        // Switched off per frame, then removed: the light buffer (empty scene) is presented again.
+        renderer.set_pass_enabled(FramePass::PostProcess, false);
+        let direct = present(&mut renderer);
+        assert_ne!(direct, graded);
+        renderer.set_pass_enabled(FramePass::PostProcess, true);
+        renderer.set_post_process(None);
+        assert_eq!(present(&mut renderer), direct);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI) or it cannot copy depth textures.
+    #[test]
+    fn depth_pixel_readback_maps_output_pixels_to_scene_depth() {
+        let Some(adapter) = test_support::adapter("depth_pixel_readback_maps_output_pixels_to_scene_depth") else {
+            return;
+        };
+        let flags = adapter.get_downlevel_capabilities().flags;
+        if !flags.contains(wgpu::DownlevelFlags::DEPTH_TEXTURE_AND_BUFFER_COPIES) {
+            eprintln!("depth_pixel_readback_maps_output_pixels_to_scene_depth: no depth copies, skipped");
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        let white = test_support::texture(&device, &queue, [255; 4]);
+        // Quad over the left half of the view at z = 0.5.
+        #[rustfmt::skip]
+        let vertices: [[f32; 8]; 4] = [
+            [-1.0, -1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0], [0.0, -1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0], [-1.0, 1.0, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0],
+        ];
+        let mesh = test_support::mesh(&device, &vertices, &[0, 1, 2, 0, 2, 3], test_support::pbr_textures(white));
+        // Looking down -z: depth = 0.5 - 0.5 * z.
+        let view_proj = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 1.0];
+        let config = LumeliteConfig { render_scale: 0.5, ..Default::default() };
+        let mut renderer = Renderer::new_with_config(device, queue, config).unwrap();
+        assert_eq!(renderer.read_depth_pixel(0, 0).unwrap(), None);
+        assert_eq!(renderer.render_resolution(), None);
+        renderer.ensure_frame_resources(16, 16).unwrap();
+        assert_eq!((renderer.render_resolution(), renderer.output_size()), (Some((8, 8)), (16, 16)));
+        let mut encoder = renderer.device().create_command_encoder(&Default::default());
+        let frame = renderer.frame_resources.as_ref().unwrap();
+        let depth_load = renderer.depth_load.gbuffer;
+        let draw = GBufferDrawOptions { view_proj: &view_proj, depth_load, ..Default::default() };
+        renderer.gbuffer_pass.encode(&mut encoder, &renderer.device, &renderer.queue, frame, &[mesh], draw).unwrap();
+        renderer.submit([encoder.finish()]);
+        // Output pixels map onto the half-resolution depth buffer.
+        assert_eq!(renderer.read_depth_pixel(3, 8).unwrap(), Some(0.25));
+        assert_eq!(renderer.read_depth_pixel(12, 8).unwrap(), Some(1.0));
+        assert_eq!(renderer.read_depth_pixel(16, 0).unwrap(), None);
     }
 }
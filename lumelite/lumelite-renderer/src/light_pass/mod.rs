@@ -1,9 +1,17 @@
 //! Light pass: fullscreen directional, point, and spot lights (Flax-style).
 
+use std::collections::HashMap;
+
+use wgpu::util::DeviceExt;
 use wgpu::CommandEncoder;
 
 use render_api::{PointLight, SpotLight};
 
+use crate::config::{AmbientLight, FogConfig, FogMode};
+use crate::gbuffer::{GBufferLayout, GBufferPacking, GBUFFER_PACK_SHADER};
+use crate::shadows::{self, LocalShadowMaps};
+use crate::FrameView;
+
 const LIGHTS_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/lights.wgsl"));
 
 #[repr(C)]
@@ -14,6 +22,46 @@ struct LightUniform {
     color: [f32; 3],
     _pad1: f32,
     inv_view_proj: [f32; 16],
+    camera_pos: [f32; 3],
+    _pad2: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AmbientUniform {
+    /// color * intensity
+    color: [f32; 3],
+    _pad0: f32,
+}
+
+/// Light-space transform and shadow test parameters for the shadowed directional variant (binding 8).
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DirectionalShadowUniform {
+    light_view_proj: [f32; 16],
+    depth_bias: f32,
+    /// 1 / shadow map resolution (PCF tap spacing).
+    texel_size: f32,
+    /// Penumbra radius in shadow-map UV per unit of light-space depth between receiver and blocker;
+    /// 0 selects the fixed 3x3 PCF (see `penumbra_scale`).
+    penumbra_scale: f32,
+    _pad: f32,
+}
+
+/// Soft-shadow scale for a sun of `angular_size` radians: a blocker `d` world units above the receiver
+/// casts a penumbra of radius `d * tan(angular_size / 2)`. Converted to shadow-map UV per unit of NDC
+/// depth using the scale of the (orthographic) light projection's x and z rows.
+fn penumbra_scale(light_view_proj: &[f32; 16], angular_size: f32) -> f32 {
+    if angular_size <= 0.0 {
+        return 0.0;
+    }
+    let m = light_view_proj;
+    let uv_per_world = 0.5 * (m[0] * m[0] + m[4] * m[4] + m[8] * m[8]).sqrt();
+    let ndc_z_per_world = (m[2] * m[2] + m[6] * m[6] + m[10] * m[10]).sqrt();
+    if ndc_z_per_world <= 0.0 {
+        return 0.0;
+    }
+    (angular_size * 0.5).tan() * uv_per_world / ndc_z_per_world
 }
 
 #[repr(C)]
@@ -27,8 +75,8 @@ struct PointLightUniform {
     falloff_exponent: f32,
     _pad2: [f32; 2],
     inv_view_proj: [f32; 16],
-    /// 满足 wgpu uniform 最小 128 字节对齐
-    _pad3: [f32; 4],
+    camera_pos: [f32; 3],
+    _pad3: f32,
 }
 
 #[repr(C)]
@@ -45,25 +93,134 @@ struct SpotLightUniform {
     outer_cos: f32,
     _pad3: f32,
     inv_view_proj: [f32; 16],
+    camera_pos: [f32; 3],
+    _pad4: f32,
+}
+
+/// Shadow frusta of a shadowed point or spot light (binding 8 of the shadowed local variants).
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LocalShadowUniform {
+    /// Cube faces +X, -X, +Y, -Y, +Z, -Z of a point light; a spot light only fills the first.
+    view_proj: [[f32; 16]; 6],
+    first_layer: u32,
+    tan_half_fov: f32,
+    /// 1 / shadow map resolution (PCF tap spacing).
+    texel_size: f32,
+    _pad: f32,
+}
+
+/// Where a point or spot light's shadow map lives: its first layer in `maps`, as rendered by
+/// `ShadowPass::encode_local` for the light's `ShadowAllocation` entry.
+#[derive(Clone, Copy)]
+pub struct LocalShadow<'a> {
+    pub maps: &'a LocalShadowMaps,
+    pub first_layer: u32,
+}
+
+/// Layout of `FogUniform` in lights.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct FogUniform {
+    color: [f32; 3],
+    mode: u32,
+    inv_view_proj: [f32; 16],
+    camera_pos: [f32; 3],
+    density: f32,
+    start: f32,
+    end: f32,
+    height_falloff: f32,
+    base_height: f32,
+}
+
+/// Layout entries of bindings 0.., one per GBuffer target `packing` has the lights read.
+fn gbuffer_layout_entries(packing: GBufferPacking) -> impl Iterator<Item = wgpu::BindGroupLayoutEntry> {
+    (0..packing.light_inputs() as u32).map(|binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    })
+}
+
+/// Views of `frame`'s GBuffer targets for the entries of [`gbuffer_layout_entries`].
+fn gbuffer_views(frame: &crate::resources::FrameResources) -> Vec<wgpu::TextureView> {
+    let mut views = frame.gbuffer_views();
+    views.truncate(frame.gbuffer_layout().packing.light_inputs());
+    views
+}
+
+/// Bind group entries 0.. for `views` from [`gbuffer_views`].
+fn gbuffer_entries(views: &[wgpu::TextureView]) -> impl Iterator<Item = wgpu::BindGroupEntry<'_>> {
+    views
+        .iter()
+        .enumerate()
+        .map(|(i, view)| wgpu::BindGroupEntry { binding: i as u32, resource: wgpu::BindingResource::TextureView(view) })
+}
+
+/// Directional light variant that additionally binds the shadow map (6), a comparison sampler (7) and the
+/// light-space matrix (8). Only created when shadows are enabled, so the unshadowed layout stays unchanged.
+struct ShadowedDirectional {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buf: wgpu::Buffer,
+    depth_bias: f32,
+    sun_angular_size: f32,
+}
+
+/// Point and spot variants that additionally bind the local shadow maps (6), a comparison sampler (7) and
+/// the light's shadow frusta (8). Only created when shadows are enabled.
+struct ShadowedLocal {
+    point_pipeline: wgpu::RenderPipeline,
+    spot_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
 }
 
 pub struct LightPass {
+    ambient_pipeline: wgpu::RenderPipeline,
     pipeline: wgpu::RenderPipeline,
+    shadowed: Option<ShadowedDirectional>,
+    shadowed_local: Option<ShadowedLocal>,
     point_pipeline: wgpu::RenderPipeline,
     spot_pipeline: wgpu::RenderPipeline,
+    fog_pipeline: wgpu::RenderPipeline,
     bind_group_layout: wgpu::BindGroupLayout,
     sampler: wgpu::Sampler,
+    ambient_uniform_buf: wgpu::Buffer,
     light_uniform_buf: wgpu::Buffer,
-    point_light_uniform_buf: wgpu::Buffer,
-    spot_light_uniform_buf: wgpu::Buffer,
+    fog_uniform_buf: wgpu::Buffer,
 }
 
 impl LightPass {
-    pub fn new(device: &wgpu::Device, light_buffer_format: wgpu::TextureFormat) -> Result<Self, String> {
+    /// `shadow_enabled` additionally builds the shadowed directional, point and spot variants (see
+    /// `encode_directional`, `encode_point`, `encode_spot`);
+    /// `shadow_depth_bias` is the light-space depth bias used by its shadow test and `sun_angular_size`
+    /// (radians) its penumbra width; 0 keeps the 3x3 PCF. `gbuffer_layout` must match the frames
+    /// passed to the `encode_*` methods; `reverse_z` treats depth 0 rather than 1 as background.
+    pub fn new(
+        device: &wgpu::Device,
+        light_buffer_format: wgpu::TextureFormat,
+        shadow_enabled: bool,
+        shadow_depth_bias: f32,
+        sun_angular_size: f32,
+        gbuffer_layout: GBufferLayout,
+        reverse_z: bool,
+    ) -> Result<Self, String> {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("lights_shader"),
-            source: wgpu::ShaderSource::Wgsl(LIGHTS_SHADER.into()),
+            source: wgpu::ShaderSource::Wgsl(
+                format!("{}\n{}\n{}", GBUFFER_PACK_SHADER, gbuffer_layout.packing.load_wgsl(), LIGHTS_SHADER).into(),
+            ),
         });
+        let constants = HashMap::from([("REVERSE_Z".to_string(), if reverse_z { 1.0 } else { 0.0 })]);
+        let compilation_options =
+            || wgpu::PipelineCompilationOptions { constants: &constants, ..Default::default() };
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("gbuffer_sampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -76,14 +233,15 @@ impl LightPass {
         });
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("light_pass_bind_group_layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry { binding: 0, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: true }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
-                wgpu::BindGroupLayoutEntry { binding: 1, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: true }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
-                wgpu::BindGroupLayoutEntry { binding: 2, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable: true }, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
-                wgpu::BindGroupLayoutEntry { binding: 3, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Depth, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
-                wgpu::BindGroupLayoutEntry { binding: 4, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
-                wgpu::BindGroupLayoutEntry { binding: 5, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: std::num::NonZeroU64::new(128) }, count: None },
-            ],
+            entries: &gbuffer_layout_entries(gbuffer_layout.packing)
+                .chain([
+                    wgpu::BindGroupLayoutEntry { binding: 3, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Depth, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 4, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
+                    // Shared by every light type and their uniforms differ in size (the spot light's is the
+                    // largest), so the size is checked per pipeline at draw time instead.
+                    wgpu::BindGroupLayoutEntry { binding: 5, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None }, count: None },
+                ])
+                .collect::<Vec<_>>(),
         });
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("light_pass_pipeline_layout"),
@@ -113,7 +271,7 @@ impl LightPass {
                     }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
-                compilation_options: Default::default(),
+                compilation_options: compilation_options(),
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
@@ -150,7 +308,7 @@ impl LightPass {
                     }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
-                compilation_options: Default::default(),
+                compilation_options: compilation_options(),
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
@@ -181,7 +339,7 @@ impl LightPass {
                     }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
-                compilation_options: Default::default(),
+                compilation_options: compilation_options(),
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
@@ -189,60 +347,377 @@ impl LightPass {
             multiview: None,
             cache: None,
         });
-        let point_light_uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("point_light_uniform"),
-            size: 128,
+        let ambient_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("light_pass_ambient_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: Some("vs_fullscreen"), buffers: &[], compilation_options: Default::default() },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_ambient"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: light_buffer_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: compilation_options(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        // Fog: lerp toward the fog color by the fragment's alpha; the light buffer's alpha is kept.
+        let fog_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("light_pass_fog_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: Some("vs_fullscreen"), buffers: &[], compilation_options: Default::default() },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_fog"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: light_buffer_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::Zero,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: compilation_options(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let fog_uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("fog_uniform"),
+            size: std::mem::size_of::<FogUniform>() as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let spot_light_uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("spot_light_uniform"),
-            size: 128,
+        let ambient_uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ambient_uniform"),
+            size: std::mem::size_of::<AmbientUniform>() as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let shadowed = if shadow_enabled {
+            Some(Self::create_shadowed_directional(
+                device,
+                &shader,
+                compilation_options(),
+                light_buffer_format,
+                gbuffer_layout.packing,
+                shadow_depth_bias,
+                sun_angular_size,
+            ))
+        } else {
+            None
+        };
+        let shadowed_local = if shadow_enabled {
+            Some(Self::create_shadowed_local(device, &shader, compilation_options, light_buffer_format, gbuffer_layout.packing))
+        } else {
+            None
+        };
         Ok(Self {
+            ambient_pipeline,
             pipeline,
+            shadowed,
+            shadowed_local,
             point_pipeline,
             spot_pipeline,
+            fog_pipeline,
             bind_group_layout,
             sampler,
+            ambient_uniform_buf,
             light_uniform_buf,
-            point_light_uniform_buf,
-            spot_light_uniform_buf,
+            fog_uniform_buf,
         })
     }
 
+    fn create_shadowed_directional(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        compilation_options: wgpu::PipelineCompilationOptions,
+        light_buffer_format: wgpu::TextureFormat,
+        packing: GBufferPacking,
+        depth_bias: f32,
+        sun_angular_size: f32,
+    ) -> ShadowedDirectional {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow_map_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light_pass_shadowed_bind_group_layout"),
+            entries: &gbuffer_layout_entries(packing)
+                .chain([
+                    wgpu::BindGroupLayoutEntry { binding: 3, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Depth, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 4, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 5, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: std::num::NonZeroU64::new(128) }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 6, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Depth, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 7, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison), count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 8, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<DirectionalShadowUniform>() as u64) }, count: None },
+                ])
+                .collect::<Vec<_>>(),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("light_pass_shadowed_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("light_pass_shadowed_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: shader, entry_point: Some("vs_fullscreen"), buffers: &[], compilation_options: Default::default() },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: Some("fs_directional_shadowed"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: light_buffer_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::One,
+                            dst_factor: wgpu::BlendFactor::One,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options,
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("directional_shadow_uniform"),
+            size: std::mem::size_of::<DirectionalShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        ShadowedDirectional { pipeline, bind_group_layout, sampler, uniform_buf, depth_bias, sun_angular_size }
+    }
+
+    fn create_shadowed_local<'a>(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        compilation_options: impl Fn() -> wgpu::PipelineCompilationOptions<'a>,
+        light_buffer_format: wgpu::TextureFormat,
+        packing: GBufferPacking,
+    ) -> ShadowedLocal {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("local_shadow_map_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("light_pass_shadowed_local_bind_group_layout"),
+            entries: &gbuffer_layout_entries(packing)
+                .chain([
+                    wgpu::BindGroupLayoutEntry { binding: 3, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Depth, view_dimension: wgpu::TextureViewDimension::D2, multisampled: false }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 4, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering), count: None },
+                    // Point or spot light uniform; both pipelines share this layout.
+                    wgpu::BindGroupLayoutEntry { binding: 5, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 6, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Depth, view_dimension: wgpu::TextureViewDimension::D2Array, multisampled: false }, count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 7, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison), count: None },
+                    wgpu::BindGroupLayoutEntry { binding: 8, visibility: wgpu::ShaderStages::FRAGMENT, ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: std::num::NonZeroU64::new(std::mem::size_of::<LocalShadowUniform>() as u64) }, count: None },
+                ])
+                .collect::<Vec<_>>(),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("light_pass_shadowed_local_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState { module: shader, entry_point: Some("vs_fullscreen"), buffers: &[], compilation_options: Default::default() },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: light_buffer_format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::One,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: compilation_options(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+        ShadowedLocal {
+            point_pipeline: create_pipeline("light_pass_point_shadowed_pipeline", "fs_point_shadowed"),
+            spot_pipeline: create_pipeline("light_pass_spot_shadowed_pipeline", "fs_spot_shadowed"),
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Whether the shadowed directional variant exists (i.e. the pass was created with shadows enabled).
+    pub fn has_shadow_variant(&self) -> bool {
+        self.shadowed.is_some()
+    }
+
+    /// Base pass: clears the light buffer, then adds the ambient fill (base color * AO * ambient) for every
+    /// covered lit pixel. With `None` or zero intensity only the clear is recorded. Must run before the other lights.
+    pub fn encode_ambient(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &crate::resources::FrameResources,
+        ambient: Option<&AmbientLight>,
+    ) -> Result<(), String> {
+        let fill = ambient
+            .filter(|a| a.intensity > 0.0)
+            .map(|a| [a.color[0] * a.intensity, a.color[1] * a.intensity, a.color[2] * a.intensity]);
+        let light_view = frame.light_buffer_view();
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("light_pass_ambient"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &light_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let Some(color) = fill else {
+            return Ok(());
+        };
+        let uniform = AmbientUniform { color, _pad0: 0.0 };
+        queue.write_buffer(&self.ambient_uniform_buf, 0, bytemuck::bytes_of(&uniform));
+        let gbuffer_views = gbuffer_views(frame);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("light_pass_ambient_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &gbuffer_entries(&gbuffer_views)
+                .chain([
+                    wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&frame.depth_view()) },
+                    wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                    wgpu::BindGroupEntry { binding: 5, resource: self.ambient_uniform_buf.as_entire_binding() },
+                ])
+                .collect::<Vec<_>>(),
+        });
+        rp.set_pipeline(&self.ambient_pipeline);
+        rp.set_bind_group(0, &bind_group, &[]);
+        rp.draw(0..3, 0..1);
+        Ok(())
+    }
+
+    /// `view`'s directional light; adds onto the light buffer prepared by `encode_ambient`. When its
+    /// `light_view_proj` is set, the pass was created with shadows enabled and `frame` has a shadow map,
+    /// the shadowed variant is used; otherwise the shadow map is not bound.
     pub fn encode_directional(
         &self,
         encoder: &mut CommandEncoder,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         frame: &crate::resources::FrameResources,
-        direction: [f32; 3],
-        color: [f32; 3],
-        inv_view_proj: &[f32; 16],
+        view: &FrameView,
     ) -> Result<(), String> {
+        let FrameView { inv_view_proj, camera_pos, directional_light: (direction, color), light_view_proj, .. } = *view;
         let light_uniform = LightUniform {
             direction: [direction[0], direction[1], direction[2]],
             _pad0: 0.0,
             color: [color[0], color[1], color[2]],
             _pad1: 0.0,
             inv_view_proj: *inv_view_proj,
+            camera_pos,
+            _pad2: 0.0,
         };
         queue.write_buffer(&self.light_uniform_buf, 0, bytemuck::bytes_of(&light_uniform));
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("light_pass_bind_group"),
-            layout: &self.bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&frame.gbuffer0_view()) },
-                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&frame.gbuffer1_view()) },
-                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&frame.gbuffer2_view()) },
-                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&frame.depth_view()) },
+        let shadow = match (&self.shadowed, light_view_proj, &frame.shadow_map) {
+            (Some(shadowed), Some(lvp), Some(_)) => Some((shadowed, lvp)),
+            _ => None,
+        };
+        let gbuffer_views = gbuffer_views(frame);
+        let depth_view = frame.depth_view();
+        let common_entries: Vec<_> = gbuffer_entries(&gbuffer_views)
+            .chain([
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&depth_view) },
                 wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.sampler) },
                 wgpu::BindGroupEntry { binding: 5, resource: self.light_uniform_buf.as_entire_binding() },
-            ],
-        });
+            ])
+            .collect();
+        let (pipeline, bind_group) = if let Some((shadowed, lvp)) = shadow {
+            let shadow_size = frame.shadow_map.as_ref().map(|t| t.width()).unwrap_or(1).max(1);
+            let shadow_uniform = DirectionalShadowUniform {
+                light_view_proj: *lvp,
+                depth_bias: shadowed.depth_bias,
+                texel_size: 1.0 / shadow_size as f32,
+                penumbra_scale: penumbra_scale(lvp, shadowed.sun_angular_size),
+                _pad: 0.0,
+            };
+            queue.write_buffer(&shadowed.uniform_buf, 0, bytemuck::bytes_of(&shadow_uniform));
+            let shadow_view = frame.shadow_map_view();
+            let mut entries = common_entries.to_vec();
+            entries.push(wgpu::BindGroupEntry { binding: 6, resource: wgpu::BindingResource::TextureView(&shadow_view) });
+            entries.push(wgpu::BindGroupEntry { binding: 7, resource: wgpu::BindingResource::Sampler(&shadowed.sampler) });
+            entries.push(wgpu::BindGroupEntry { binding: 8, resource: shadowed.uniform_buf.as_entire_binding() });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("light_pass_shadowed_bind_group"),
+                layout: &shadowed.bind_group_layout,
+                entries: &entries,
+            });
+            (&shadowed.pipeline, bind_group)
+        } else {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("light_pass_bind_group"),
+                layout: &self.bind_group_layout,
+                entries: &common_entries,
+            });
+            (&self.pipeline, bind_group)
+        };
         let light_view = frame.light_buffer_view();
         {
             let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -250,27 +725,30 @@ impl LightPass {
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &light_view,
                     resolve_target: None,
-                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
                 })],
                 depth_stencil_attachment: None,
                 timestamp_writes: None,
                 occlusion_query_set: None,
             });
-            rp.set_pipeline(&self.pipeline);
+            rp.set_pipeline(pipeline);
             rp.set_bind_group(0, &bind_group, &[]);
             rp.draw(0..3, 0..1);
         }
         Ok(())
     }
 
+    /// Point light, shaded for `view`'s camera; adds onto the light buffer. With `shadow` (and a pass
+    /// created with shadows enabled) the light is shadowed by its six cube-face layers, otherwise it
+    /// lights unshadowed.
     pub fn encode_point(
         &self,
         encoder: &mut CommandEncoder,
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
         frame: &crate::resources::FrameResources,
         light: &PointLight,
-        inv_view_proj: &[f32; 16],
+        view: &FrameView,
+        shadow: Option<LocalShadow>,
     ) -> Result<(), String> {
         let uniform = PointLightUniform {
             position: light.position,
@@ -280,51 +758,25 @@ impl LightPass {
             radius: light.radius,
             falloff_exponent: light.falloff_exponent,
             _pad2: [0.0; 2],
-            inv_view_proj: *inv_view_proj,
-            _pad3: [0.0; 4],
+            inv_view_proj: *view.inv_view_proj,
+            camera_pos: view.camera_pos,
+            _pad3: 0.0,
         };
-        queue.write_buffer(&self.point_light_uniform_buf, 0, bytemuck::bytes_of(&uniform));
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("light_pass_point_bind_group"),
-            layout: &self.bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&frame.gbuffer0_view()) },
-                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&frame.gbuffer1_view()) },
-                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&frame.gbuffer2_view()) },
-                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&frame.depth_view()) },
-                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.sampler) },
-                wgpu::BindGroupEntry { binding: 5, resource: self.point_light_uniform_buf.as_entire_binding() },
-            ],
-        });
-        let light_view = frame.light_buffer_view();
-        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("light_pass_point"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &light_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Load,
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-        rp.set_pipeline(&self.point_pipeline);
-        rp.set_bind_group(0, &bind_group, &[]);
-        rp.draw(0..3, 0..1);
-        Ok(())
+        // 90 degree cube faces: tan(fov / 2) = 1.
+        let shadow = shadow.map(|s| (s, shadows::point_shadow_view_projs(light), 1.0));
+        self.encode_local_light(encoder, device, frame, bytemuck::bytes_of(&uniform), shadow, false)
     }
 
+    /// Spot light, shaded for `view`'s camera; adds onto the light buffer. With `shadow` (and a pass
+    /// created with shadows enabled) the light is shadowed by its layer, otherwise it lights unshadowed.
     pub fn encode_spot(
         &self,
         encoder: &mut CommandEncoder,
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
         frame: &crate::resources::FrameResources,
         light: &SpotLight,
-        inv_view_proj: &[f32; 16],
+        view: &FrameView,
+        shadow: Option<LocalShadow>,
     ) -> Result<(), String> {
         let inner_cos = light.inner_angle.cos();
         let outer_cos = light.outer_angle.cos();
@@ -339,24 +791,148 @@ impl LightPass {
             inner_cos,
             outer_cos,
             _pad3: 0.0,
-            inv_view_proj: *inv_view_proj,
+            inv_view_proj: *view.inv_view_proj,
+            camera_pos: view.camera_pos,
+            _pad4: 0.0,
+        };
+        let shadow = shadow.map(|s| {
+            let mut view_projs = [[0.0; 16]; 6];
+            view_projs[0] = shadows::spot_shadow_view_proj(light);
+            (s, view_projs, (shadows::spot_shadow_fov(light) * 0.5).tan())
+        });
+        self.encode_local_light(encoder, device, frame, bytemuck::bytes_of(&uniform), shadow, true)
+    }
+
+    /// Shared body of `encode_point` and `encode_spot`. The uniforms get their own buffers per call, so
+    /// every light of a frame keeps its own data until the submit.
+    fn encode_local_light(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        frame: &crate::resources::FrameResources,
+        light_uniform: &[u8],
+        shadow: Option<(LocalShadow, [[f32; 16]; 6], f32)>,
+        spot: bool,
+    ) -> Result<(), String> {
+        let label = if spot { "light_pass_spot" } else { "light_pass_point" };
+        let light_uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: light_uniform,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let gbuffer_views = gbuffer_views(frame);
+        let depth_view = frame.depth_view();
+        let common_entries: Vec<_> = gbuffer_entries(&gbuffer_views)
+            .chain([
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&depth_view) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                wgpu::BindGroupEntry { binding: 5, resource: light_uniform_buf.as_entire_binding() },
+            ])
+            .collect();
+        let (pipeline, bind_group) = match (&self.shadowed_local, shadow) {
+            (Some(shadowed), Some((shadow, view_proj, tan_half_fov))) => {
+                let needed = shadow.first_layer + if spot { 1 } else { 6 };
+                if needed > shadow.maps.layer_count() {
+                    return Err(format!(
+                        "{}: shadow layers up to {} requested, the local shadow maps have {}",
+                        label,
+                        needed,
+                        shadow.maps.layer_count()
+                    ));
+                }
+                let shadow_uniform = LocalShadowUniform {
+                    view_proj,
+                    first_layer: shadow.first_layer,
+                    tan_half_fov,
+                    texel_size: 1.0 / shadow.maps.resolution().max(1) as f32,
+                    _pad: 0.0,
+                };
+                let shadow_uniform_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("local_shadow_uniform"),
+                    contents: bytemuck::bytes_of(&shadow_uniform),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let maps_view = shadow.maps.array_view();
+                let mut entries = common_entries.to_vec();
+                entries.push(wgpu::BindGroupEntry { binding: 6, resource: wgpu::BindingResource::TextureView(&maps_view) });
+                entries.push(wgpu::BindGroupEntry { binding: 7, resource: wgpu::BindingResource::Sampler(&shadowed.sampler) });
+                entries.push(wgpu::BindGroupEntry { binding: 8, resource: shadow_uniform_buf.as_entire_binding() });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("light_pass_shadowed_local_bind_group"),
+                    layout: &shadowed.bind_group_layout,
+                    entries: &entries,
+                });
+                (if spot { &shadowed.spot_pipeline } else { &shadowed.point_pipeline }, bind_group)
+            }
+            _ => {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(label),
+                    layout: &self.bind_group_layout,
+                    entries: &common_entries,
+                });
+                (if spot { &self.spot_pipeline } else { &self.point_pipeline }, bind_group)
+            }
         };
-        queue.write_buffer(&self.spot_light_uniform_buf, 0, bytemuck::bytes_of(&uniform));
+        let light_view = frame.light_buffer_view();
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &light_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        rp.set_pipeline(pipeline);
+        rp.set_bind_group(0, &bind_group, &[]);
+        rp.draw(0..3, 0..1);
+        Ok(())
+    }
+
+    /// Fade the lit scene toward the fog color by distance from the camera (see [`FogConfig`]).
+    /// Runs after all lights, since it blends over their sum.
+    pub fn encode_fog(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &crate::resources::FrameResources,
+        fog: &FogConfig,
+        view: &FrameView,
+    ) -> Result<(), String> {
+        let (mode, density, start, end) = match fog.mode {
+            FogMode::Linear { start, end } => (0, 0.0, start, end),
+            FogMode::Exponential { density } => (1, density, 0.0, 0.0),
+        };
+        let uniform = FogUniform {
+            color: fog.color,
+            mode,
+            inv_view_proj: *view.inv_view_proj,
+            camera_pos: view.camera_pos,
+            density,
+            start,
+            end,
+            height_falloff: fog.height_falloff,
+            base_height: fog.base_height,
+        };
+        queue.write_buffer(&self.fog_uniform_buf, 0, bytemuck::bytes_of(&uniform));
+        let gbuffer_views = gbuffer_views(frame);
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("light_pass_spot_bind_group"),
+            label: Some("light_pass_fog_bind_group"),
             layout: &self.bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&frame.gbuffer0_view()) },
-                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&frame.gbuffer1_view()) },
-                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::TextureView(&frame.gbuffer2_view()) },
-                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&frame.depth_view()) },
-                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.sampler) },
-                wgpu::BindGroupEntry { binding: 5, resource: self.spot_light_uniform_buf.as_entire_binding() },
-            ],
+            entries: &gbuffer_entries(&gbuffer_views)
+                .chain([
+                    wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&frame.depth_view()) },
+                    wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                    wgpu::BindGroupEntry { binding: 5, resource: self.fog_uniform_buf.as_entire_binding() },
+                ])
+                .collect::<Vec<_>>(),
         });
         let light_view = frame.light_buffer_view();
         let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("light_pass_spot"),
+            label: Some("light_pass_fog"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &light_view,
                 resolve_target: None,
@@ -366,9 +942,43 @@ impl LightPass {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        rp.set_pipeline(&self.spot_pipeline);
+        rp.set_pipeline(&self.fog_pipeline);
         rp.set_bind_group(0, &bind_group, &[]);
         rp.draw(0..3, 0..1);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_uniform_matches_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<FogUniform>(), 112);
+    }
+
+    #[test]
+    fn ambient_uniform_matches_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<AmbientUniform>(), 16);
+    }
+
+    #[test]
+    fn local_shadow_uniform_matches_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<LocalShadowUniform>(), 6 * 64 + 16);
+    }
+
+    #[test]
+    fn penumbra_scale_from_ortho_light() {
+        // Ortho 20 x 20 world units, depth range 50: uv per world = 1/20, ndc z per world = 1/50.
+        let mut m = [0.0f32; 16];
+        m[0] = 2.0 / 20.0;
+        m[5] = 2.0 / 20.0;
+        m[10] = -1.0 / 50.0;
+        m[15] = 1.0;
+        assert_eq!(penumbra_scale(&m, 0.0), 0.0);
+        let angle = 0.02f32;
+        let expected = (angle * 0.5).tan() * (1.0 / 20.0) * 50.0;
+        assert!((penumbra_scale(&m, angle) - expected).abs() < 1e-6);
+    }
+}
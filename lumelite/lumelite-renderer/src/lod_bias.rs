@@ -0,0 +1,72 @@
+//! Automatic LOD bias for `LumeliteConfig::triangle_budget`: raised while frames draw more triangles
+//! than the budget, lowered once they are comfortably under it. Hosts that select mesh LODs scale the
+//! projected screen size by [`LodBias::screen_size_scale`], so coarser LODs are picked everywhere.
+
+/// Upper bound of the bias: meshes are then treated as 16 times smaller on screen.
+const MAX_BIAS: f32 = 4.0;
+/// Bias change per update for a frame twice (or half) the budget.
+const GAIN: f32 = 0.25;
+/// Largest bias change per update, so LODs switch a few meshes at a time instead of popping together.
+const MAX_STEP: f32 = 0.05;
+/// Between this fraction of the budget and the budget the bias holds, so it does not oscillate.
+const HOLD_BAND: f32 = 0.85;
+
+/// The renderer's current LOD bias (`Renderer::lod_bias`), updated once per frame from the triangles
+/// it drew.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LodBias {
+    bias: f32,
+}
+
+impl LodBias {
+    /// Current bias in octaves of screen size; 0 when no budget is set or it has never been exceeded.
+    pub fn bias(&self) -> f32 {
+        self.bias
+    }
+
+    /// Factor for a mesh's projected screen size before LOD selection: `2^-bias`.
+    pub fn screen_size_scale(&self) -> f32 {
+        (-self.bias).exp2()
+    }
+
+    /// Move the bias toward the budget given a frame's drawn triangles. Without a budget the bias is
+    /// reset to 0.
+    pub fn update(&mut self, triangles: u32, budget: Option<u32>) {
+        let Some(budget) = budget else {
+            self.bias = 0.0;
+            return;
+        };
+        let load = triangles as f32 / budget.max(1) as f32;
+        if (HOLD_BAND..=1.0).contains(&load) {
+            return;
+        }
+        let step = (load.max(1e-3).log2() * GAIN).clamp(-MAX_STEP, MAX_STEP);
+        self.bias = (self.bias + step).clamp(0.0, MAX_BIAS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bias_rises_over_budget_and_settles_back() {
+        let mut lod = LodBias::default();
+        lod.update(2_000_000, Some(1_000_000));
+        assert_eq!(lod.bias(), MAX_STEP);
+        // Slightly over: a smaller step.
+        lod.update(1_100_000, Some(1_000_000));
+        assert!(lod.bias() > MAX_STEP && lod.bias() < 2.0 * MAX_STEP);
+        // Just under the budget holds; far under steps back down to 0.
+        let held = lod.bias();
+        lod.update(900_000, Some(1_000_000));
+        assert_eq!(lod.bias(), held);
+        (0..10).for_each(|_| lod.update(100_000, Some(1_000_000)));
+        assert_eq!(lod.bias(), 0.0);
+        // Capped, and reset without a budget.
+        (0..1000).for_each(|_| lod.update(u32::MAX, Some(1)));
+        assert_eq!((lod.bias(), lod.screen_size_scale()), (MAX_BIAS, 1.0 / 16.0));
+        lod.update(u32::MAX, None);
+        assert_eq!(lod.screen_size_scale(), 1.0);
+    }
+}
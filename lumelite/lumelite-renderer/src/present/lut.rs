@@ -0,0 +1,117 @@
+//! 3D color lookup tables for present-time color grading, and a loader for the `.cube` format.
+
+/// Smallest and largest accepted edge length of a [`ColorLut`].
+pub const COLOR_LUT_SIZE_RANGE: (u32, u32) = (2, 128);
+
+/// A `size`³ color grading table. It maps sRGB-encoded display colors (what grading tools and `.cube`
+/// files work on) to graded sRGB-encoded colors; entries are ordered with red fastest, then green,
+/// then blue, and values outside [0, 1] are clamped on upload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorLut {
+    pub size: u32,
+    pub data: Vec<[f32; 3]>,
+}
+
+impl ColorLut {
+    /// The neutral table: every color maps to itself (exactly, with linear filtering, at any size).
+    pub fn identity(size: u32) -> Self {
+        let step = 1.0 / (size.max(2) - 1) as f32;
+        let data = (0..size * size * size)
+            .map(|i| [i % size, i / size % size, i / (size * size)].map(|c| c as f32 * step))
+            .collect();
+        Self { size, data }
+    }
+
+    /// Parse an Adobe/Resolve `.cube` 3D LUT. `TITLE` and comments are skipped; `DOMAIN_MIN` /
+    /// `DOMAIN_MAX` must be the default [0, 1], and 1D tables are rejected.
+    pub fn parse_cube(text: &str) -> Result<Self, String> {
+        let mut size = None;
+        let mut data = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let floats = |words: std::str::SplitWhitespace| -> Result<Vec<f32>, String> {
+                words.map(|w| w.parse::<f32>().map_err(|e| format!(".cube line {}: {}: {}", number + 1, w, e))).collect()
+            };
+            match keyword {
+                "LUT_3D_SIZE" => {
+                    let value = words.next().unwrap_or_default();
+                    size = Some(value.parse::<u32>().map_err(|e| format!(".cube line {}: {}: {}", number + 1, value, e))?);
+                }
+                "LUT_1D_SIZE" => return Err(".cube: 1D LUTs are not supported".to_string()),
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let expected = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    if floats(words)? != [expected; 3] {
+                        return Err(format!(".cube line {}: only the default {} is supported", number + 1, keyword));
+                    }
+                }
+                _ => {
+                    let values = floats(line.split_whitespace())?;
+                    let [r, g, b] = values[..] else {
+                        return Err(format!(".cube line {}: expected 3 values, got {:?}", number + 1, line));
+                    };
+                    data.push([r, g, b]);
+                }
+            }
+        }
+        let lut = Self { size: size.ok_or(".cube: missing LUT_3D_SIZE")?, data };
+        lut.validate()?;
+        Ok(lut)
+    }
+
+    /// Check the size is within [`COLOR_LUT_SIZE_RANGE`] and there is one entry per cell.
+    pub fn validate(&self) -> Result<(), String> {
+        let (min, max) = COLOR_LUT_SIZE_RANGE;
+        if !(min..=max).contains(&self.size) {
+            return Err(format!("color LUT size {} outside {}..={}", self.size, min, max));
+        }
+        let expected = (self.size as usize).pow(3);
+        if self.data.len() != expected {
+            return Err(format!("color LUT of size {} needs {} entries, got {}", self.size, expected, self.data.len()));
+        }
+        Ok(())
+    }
+
+    /// Texels for an `Rgba8Unorm` 3D texture of `size`³.
+    pub(crate) fn to_rgba8(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|rgb| {
+                let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_files_parse_red_fastest() {
+        let text = "# graded\nTITLE \"invert\"\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1.0 1.0 1.0\n\
+                    1 1 1\n0 1 1\n1 0 1\n0 0 1\n1 1 0\n0 1 0\n1 0 0\n0 0 0\n";
+        let lut = ColorLut::parse_cube(text).unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.data[1], [0.0, 1.0, 1.0]);
+        assert_eq!(&lut.to_rgba8()[4..8], &[0, 255, 255, 255]);
+        assert_eq!(ColorLut::identity(2).data[1], [1.0, 0.0, 0.0]);
+        assert_eq!(ColorLut::identity(3).data[3 + 1], [0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn malformed_cube_files_are_rejected() {
+        assert!(ColorLut::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(ColorLut::parse_cube("0 0 0\n").is_err());
+        assert!(ColorLut::parse_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(ColorLut::parse_cube("LUT_3D_SIZE 2\nDOMAIN_MAX 2 2 2\n").is_err());
+        assert!(ColorLut::parse_cube(&format!("LUT_3D_SIZE 2\n{}", "0 0\n".repeat(8))).is_err());
+        assert!(ColorLut::identity(1).validate().is_err());
+        assert!(ColorLut { size: 2, data: vec![[0.0; 3]; 7] }.validate().is_err());
+    }
+}
@@ -1,31 +1,135 @@
-//! Present pass: sample light buffer (Rgba16Float), tone map, render to swapchain.
+//! Present pass: sample light buffer (Rgba16Float), tone map, optionally color grade with a 3D LUT and
+//! apply lens effects (vignette, chromatic aberration), render to swapchain.
 
+mod lut;
+
+pub use lut::{ColorLut, COLOR_LUT_SIZE_RANGE};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use wgpu::util::DeviceExt;
 use wgpu::CommandEncoder;
 
-use crate::config::ToneMapping;
+use crate::config::{PresentEffects, ToneMapping, UpscaleFilter};
 
 const PRESENT_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/present.wgsl"));
 
-/// Uniform: tone_mode (u32). 0 = Reinhard, 1 = None. Uses uniform buffer for backend compatibility.
+// Uniform: tone_mode (u32, 0 = Reinhard, 1 = None, 2 = HDR output), upscale_mode (u32, 0 = bilinear, 1 = Catmull-Rom),
+// exposure (f32), color_lut (u32, 1 = grade with the LUT), vignette (f32), chromatic_aberration (f32), pad.
+// Uses uniform buffer for backend compatibility.
+const PRESENT_UNIFORM_SIZE: u64 = 32;
+
+/// Slots of the present uniform buffer, used in turn by `PresentPass::encode`: up to this many presents
+/// in one submission keep their own settings.
+const PRESENT_UNIFORM_SLOTS: u64 = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PresentUniform {
+    tone_mode: u32,
+    upscale_mode: u32,
+    exposure: f32,
+    color_lut: u32,
+    vignette: f32,
+    chromatic_aberration: f32,
+    _pad: [u32; 2],
+}
+
+fn create_lut_view(device: &wgpu::Device, queue: &wgpu::Queue, lut: &ColorLut) -> wgpu::TextureView {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("present_color_lut"),
+            size: wgpu::Extent3d { width: lut.size, height: lut.size, depth_or_array_layers: lut.size },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &lut.to_rgba8(),
+    );
+    texture.create_view(&Default::default())
+}
+
+/// Whether `format` is an HDR output format: with `LumeliteConfig::hdr_output` on, present writes linear
+/// values above 1.0 to it instead of tone mapping into [0, 1].
+pub fn is_hdr_format(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float)
+}
+
+/// Per-call settings of [`PresentPass::encode`]; [`Self::new`] gives Reinhard at exposure 1 over the
+/// whole output, cleared to black.
+#[derive(Clone, Copy, Debug)]
+pub struct PresentParams {
+    /// Pixel size of the output view.
+    pub output_size: (u32, u32),
+    /// Ignored by HDR outputs (see [`PresentPass::new`]).
+    pub tone_mapping: ToneMapping,
+    /// Linear multiplier on the light buffer before tone mapping.
+    pub exposure: f32,
+    /// Clear color of the whole output, visible around `region`.
+    pub background: wgpu::Color,
+    /// x, y, width, height in output pixels to draw the scene into; `None` covers the whole output.
+    pub region: Option<[u32; 4]>,
+    /// Only clear the output to green (swapchain bring-up check).
+    pub debug_clear_green: bool,
+}
+
+impl PresentParams {
+    pub fn new(output_size: (u32, u32)) -> Self {
+        Self {
+            output_size,
+            tone_mapping: ToneMapping::default(),
+            exposure: 1.0,
+            background: wgpu::Color::BLACK,
+            region: None,
+            debug_clear_green: false,
+        }
+    }
+}
 
 pub struct PresentPass {
     pipeline: wgpu::RenderPipeline,
     bind_group_layout: wgpu::BindGroupLayout,
     sampler: wgpu::Sampler,
-    tone_mapping: ToneMapping,
-    tone_uniform_buf: wgpu::Buffer,
+    upscale_filter: UpscaleFilter,
+    /// `PRESENT_UNIFORM_SLOTS` uniforms, `uniform_stride` apart, bound with a dynamic offset.
+    uniform_buf: wgpu::Buffer,
+    uniform_stride: u64,
+    next_uniform_slot: AtomicU64,
+    hdr_output: bool,
+    /// Color grading table; a 2³ identity while `color_lut` is off, so the binding is always valid.
+    lut_view: wgpu::TextureView,
+    lut_sampler: wgpu::Sampler,
+    color_lut: bool,
+    effects: PresentEffects,
 }
 
 impl PresentPass {
+    /// `hdr_output` (`LumeliteConfig::hdr_output`) skips tone mapping when `output_format` is an HDR
+    /// format; other float targets, such as offscreen `Rgba16Float` outputs, are still tone mapped.
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         output_format: wgpu::TextureFormat,
-        tone_mapping: ToneMapping,
+        hdr_output: bool,
+        upscale_filter: UpscaleFilter,
+        anisotropy: u16,
     ) -> Result<Self, String> {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("present_shader"),
             source: wgpu::ShaderSource::Wgsl(PRESENT_SHADER.into()),
         });
+        // wgpu requires all filters to be linear when anisotropy_clamp > 1.
+        let anisotropy_clamp = anisotropy.clamp(1, 16);
+        let mipmap_filter = if anisotropy_clamp > 1 {
+            wgpu::FilterMode::Linear
+        } else {
+            wgpu::FilterMode::Nearest
+        };
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("present_sampler"),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -33,7 +137,8 @@ impl PresentPass {
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter,
+            anisotropy_clamp,
             ..Default::default()
         });
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -60,11 +165,27 @@ impl PresentPass {
                     visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: std::num::NonZeroU64::new(4),
+                        has_dynamic_offset: true,
+                        min_binding_size: std::num::NonZeroU64::new(PRESENT_UNIFORM_SIZE),
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
             ],
         });
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -97,28 +218,80 @@ impl PresentPass {
             multiview: None,
             cache: None,
         });
-        let tone_uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
+        let uniform_stride = PRESENT_UNIFORM_SIZE.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
+        let uniform_buf = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("present_tone_uniform"),
-            size: 4,
+            size: uniform_stride * PRESENT_UNIFORM_SLOTS,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("present_lut_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
         Ok(Self {
             pipeline,
             bind_group_layout,
             sampler,
-            tone_mapping,
-            tone_uniform_buf,
+            upscale_filter,
+            uniform_buf,
+            uniform_stride,
+            next_uniform_slot: AtomicU64::new(0),
+            hdr_output: hdr_output && is_hdr_format(output_format),
+            lut_view: create_lut_view(device, queue, &ColorLut::identity(2)),
+            lut_sampler,
+            color_lut: false,
+            effects: PresentEffects::default(),
         })
     }
 
-    fn tone_mode_u32(&self) -> u32 {
-        match self.tone_mapping {
+    /// Vignette and chromatic aberration for every following present (also applied to HDR outputs).
+    pub fn set_effects(&mut self, effects: PresentEffects) -> Result<(), String> {
+        effects.validate()?;
+        self.effects = effects;
+        Ok(())
+    }
+
+    /// Grade every following present with `lut` after tone mapping (`None` turns grading off). The LUT
+    /// is looked up with the sRGB-encoded tone-mapped color; HDR outputs are not graded.
+    pub fn set_color_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lut: Option<&ColorLut>) -> Result<(), String> {
+        let identity = ColorLut::identity(2);
+        let table = match lut {
+            Some(lut) => {
+                lut.validate()?;
+                lut
+            }
+            None => &identity,
+        };
+        self.lut_view = create_lut_view(device, queue, table);
+        self.color_lut = lut.is_some();
+        Ok(())
+    }
+
+    fn tone_mode_u32(&self, tone_mapping: ToneMapping) -> u32 {
+        if self.hdr_output {
+            return 2;
+        }
+        match tone_mapping {
             ToneMapping::Reinhard => 0,
             ToneMapping::None => 1,
         }
     }
 
+    fn upscale_mode_u32(&self) -> u32 {
+        match self.upscale_filter {
+            UpscaleFilter::Bilinear => 0,
+            UpscaleFilter::CatmullRom => 1,
+        }
+    }
+
+    /// Tone map `light_buffer_view` into `output_view` with `params`. Settings are per call, so several
+    /// views (up to `PRESENT_UNIFORM_SLOTS`) with different exposure can be presented in one submission.
+    /// The whole output is cleared to `params.background` first; the scene is then drawn into
+    /// `params.region`. A region that is empty or reaches past `params.output_size` is an error. HDR
+    /// outputs (see [`Self::new`]) skip tone mapping and receive the exposed linear values.
     pub fn encode(
         &self,
         encoder: &mut CommandEncoder,
@@ -126,8 +299,17 @@ impl PresentPass {
         queue: &wgpu::Queue,
         light_buffer_view: &wgpu::TextureView,
         output_view: &wgpu::TextureView,
-        debug_clear_green: bool,
+        params: &PresentParams,
     ) -> Result<(), String> {
+        let PresentParams { output_size, tone_mapping, exposure, background, region, debug_clear_green } = *params;
+        if let Some(r @ [_, _, 0, _] | r @ [_, _, _, 0]) = region {
+            return Err(format!("present: empty region {:?}", r));
+        }
+        if let Some(r @ [x, y, w, h]) = region {
+            if x as u64 + w as u64 > output_size.0 as u64 || y as u64 + h as u64 > output_size.1 as u64 {
+                return Err(format!("present: region {:?} exceeds the {}x{} output", r, output_size.0, output_size.1));
+            }
+        }
         if debug_clear_green {
             // Minimal test: just clear to green (no draw) - verify swapchain displays
             let rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -147,8 +329,18 @@ impl PresentPass {
             drop(rp);
             return Ok(());
         }
-        let mode: u32 = self.tone_mode_u32();
-        queue.write_buffer(&self.tone_uniform_buf, 0, bytemuck::cast_slice(&[mode]));
+        let uniform = PresentUniform {
+            tone_mode: self.tone_mode_u32(tone_mapping),
+            upscale_mode: self.upscale_mode_u32(),
+            exposure,
+            color_lut: self.color_lut as u32,
+            vignette: self.effects.vignette,
+            chromatic_aberration: self.effects.chromatic_aberration,
+            _pad: [0; 2],
+        };
+        let slot = self.next_uniform_slot.fetch_add(1, Ordering::Relaxed) % PRESENT_UNIFORM_SLOTS;
+        let uniform_offset = slot * self.uniform_stride;
+        queue.write_buffer(&self.uniform_buf, uniform_offset, bytemuck::bytes_of(&uniform));
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("present_bind_group"),
             layout: &self.bind_group_layout,
@@ -163,8 +355,14 @@ impl PresentPass {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: self.tone_uniform_buf.as_entire_binding(),
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.uniform_buf,
+                        offset: 0,
+                        size: std::num::NonZeroU64::new(PRESENT_UNIFORM_SIZE),
+                    }),
                 },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(&self.lut_view) },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::Sampler(&self.lut_sampler) },
             ],
         });
         let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -173,7 +371,7 @@ impl PresentPass {
                 view: output_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: wgpu::LoadOp::Clear(background),
                     store: wgpu::StoreOp::Store,
                 },
             })],
@@ -181,10 +379,369 @@ impl PresentPass {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
+        if let Some([x, y, w, h]) = region {
+            rp.set_viewport(x as f32, y as f32, w as f32, h as f32, 0.0, 1.0);
+        }
         rp.set_pipeline(&self.pipeline);
-        rp.set_bind_group(0, &bind_group, &[]);
+        rp.set_bind_group(0, &bind_group, &[uniform_offset as u32]);
         rp.draw(0..3, 0..1);
         drop(rp);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Two presents of the same light buffer in one submission keep their own tone mapping and exposure.
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn per_call_exposure_and_tone_mapping() {
+        let Some(adapter) = test_support::adapter("per_call_exposure_and_tone_mapping") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let size = wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 };
+        // 1.0 in f16 for every channel.
+        let light = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &[0x00, 0x3c].repeat(16 * 4),
+        );
+        let light_view = light.create_view(&Default::default());
+        let pass = PresentPass::new(&device, &queue, wgpu::TextureFormat::Rgba8Unorm, false, UpscaleFilter::Bilinear, 1).unwrap();
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 4 * 2,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        for (i, (tone_mapping, exposure)) in [(ToneMapping::Reinhard, 3.0), (ToneMapping::None, 0.25)].into_iter().enumerate() {
+            let output = device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let output_view = output.create_view(&Default::default());
+            let params = PresentParams { tone_mapping, exposure, ..PresentParams::new((4, 4)) };
+            pass.encode(&mut encoder, &device, &queue, &light_view, &output_view, &params).unwrap();
+            encoder.copy_texture_to_buffer(
+                output.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout { offset: 1024 * i as u64, bytes_per_row: Some(256), rows_per_image: None },
+                },
+                size,
+            );
+        }
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        // Reinhard of 3.0 is 0.75; clamp of 0.25 is 0.25.
+        assert!(data[0].abs_diff(191) <= 1, "reinhard {}", data[0]);
+        assert!(data[1024].abs_diff(64) <= 1, "none {}", data[1024]);
+    }
+
+    /// An identity LUT leaves the image unchanged; an inverting LUT inverts the sRGB-encoded color.
+    #[test]
+    fn color_lut_grades_after_tone_mapping() {
+        let Some(adapter) = test_support::adapter("color_lut_grades_after_tone_mapping") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let size = wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 };
+        let light = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &[0x00, 0x3c].repeat(16 * 4),
+        );
+        let light_view = light.create_view(&Default::default());
+        let mut pass = PresentPass::new(&device, &queue, wgpu::TextureFormat::Rgba8Unorm, false, UpscaleFilter::Bilinear, 1).unwrap();
+        let invert = ColorLut {
+            size: 2,
+            data: ColorLut::identity(2).data.into_iter().map(|rgb| rgb.map(|c| 1.0 - c)).collect(),
+        };
+        assert!(pass.set_color_lut(&device, &queue, Some(&ColorLut::identity(1))).is_err());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 4 * 2,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        for (i, lut) in [ColorLut::identity(5), invert].iter().enumerate() {
+            pass.set_color_lut(&device, &queue, Some(lut)).unwrap();
+            let output = device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let output_view = output.create_view(&Default::default());
+            let params = PresentParams { tone_mapping: ToneMapping::None, exposure: 0.25, ..PresentParams::new((4, 4)) };
+            pass.encode(&mut encoder, &device, &queue, &light_view, &output_view, &params).unwrap();
+            encoder.copy_texture_to_buffer(
+                output.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout { offset: 1024 * i as u64, bytes_per_row: Some(256), rows_per_image: None },
+                },
+                size,
+            );
+        }
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        // 0.25 linear is 0.537 sRGB-encoded; inverted that is 0.463, or 0.181 linear.
+        assert!(data[0].abs_diff(64) <= 2, "identity {}", data[0]);
+        assert!(data[1024].abs_diff(46) <= 2, "inverted {}", data[1024]);
+    }
+
+    /// Vignette darkens the corners more than the middle; chromatic aberration pulls red from outside a
+    /// pixel and blue from inside it, splitting an edge into red and blue fringes.
+    #[test]
+    fn present_effects_vignette_and_aberration() {
+        let Some(adapter) = test_support::adapter("present_effects_vignette_and_aberration") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let size = wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 };
+        let light_view = |texels: Vec<u8>| {
+            device
+                .create_texture_with_data(
+                    &queue,
+                    &wgpu::TextureDescriptor {
+                        label: None,
+                        size,
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                        view_formats: &[],
+                    },
+                    wgpu::util::TextureDataOrder::LayerMajor,
+                    &texels,
+                )
+                .create_view(&Default::default())
+        };
+        // Uniform 1.0, and a 1.0 right column over black.
+        let uniform = light_view([0x00, 0x3c].repeat(16 * 4));
+        let edge = light_view([vec![0u8; 24], [0x00, 0x3c].repeat(4)].concat().repeat(4));
+        let mut pass = PresentPass::new(&device, &queue, wgpu::TextureFormat::Rgba8Unorm, false, UpscaleFilter::Bilinear, 1).unwrap();
+        assert!(pass.set_effects(PresentEffects { vignette: 1.5, ..Default::default() }).is_err());
+        assert!(pass.set_effects(PresentEffects { chromatic_aberration: -0.1, ..Default::default() }).is_err());
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 4 * 2,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let cases = [
+            (&uniform, PresentEffects { vignette: 1.0, ..Default::default() }),
+            (&edge, PresentEffects { chromatic_aberration: 0.1, ..Default::default() }),
+        ];
+        for (i, (light, effects)) in cases.into_iter().enumerate() {
+            pass.set_effects(effects).unwrap();
+            let output = device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let output_view = output.create_view(&Default::default());
+            let params = PresentParams { tone_mapping: ToneMapping::None, ..PresentParams::new((4, 4)) };
+            pass.encode(&mut encoder, &device, &queue, light, &output_view, &params).unwrap();
+            encoder.copy_texture_to_buffer(
+                output.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout { offset: 1024 * i as u64, bytes_per_row: Some(256), rows_per_image: None },
+                },
+                size,
+            );
+        }
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        // Corner texel center is 0.375 from the middle on both axes (factor 0.4375), inner one 0.125 (0.9375).
+        let (corner, inner) = (data[0], data[256 + 4]);
+        assert!(corner.abs_diff(112) <= 2 && inner.abs_diff(239) <= 2, "vignette corner {} inner {}", corner, inner);
+        // Column 2 sits just left of the lit column: red reaches into it, blue samples further left.
+        let fringe = &data[1024 + 8..1024 + 12];
+        assert!(fringe[0] > fringe[2] + 5 && fringe[2] == 0, "aberration {:?}", fringe);
+    }
+
+    /// The output outside the region keeps the background color; the scene fills only the region.
+    #[test]
+    fn region_composites_over_background() {
+        let Some(adapter) = test_support::adapter("region_composites_over_background") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let size = wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 };
+        let light = device.create_texture_with_data(
+            &queue,
+            &wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &[0x00, 0x3c].repeat(16 * 4),
+        );
+        let light_view = light.create_view(&Default::default());
+        let output = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_view = output.create_view(&Default::default());
+        let pass = PresentPass::new(&device, &queue, wgpu::TextureFormat::Rgba8Unorm, false, UpscaleFilter::Bilinear, 1).unwrap();
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let params = |region, exposure| PresentParams {
+            tone_mapping: ToneMapping::None,
+            exposure,
+            background: wgpu::Color::RED,
+            region: Some(region),
+            ..PresentParams::new((4, 4))
+        };
+        assert!(pass.encode(&mut encoder, &device, &queue, &light_view, &output_view, &params([0, 0, 0, 4], 1.0)).is_err());
+        assert!(pass
+            .encode(&mut encoder, &device, &queue, &light_view, &output_view, &params([2, 0, 3, 4], 1.0))
+            .unwrap_err()
+            .contains("exceeds the 4x4 output"));
+        pass.encode(&mut encoder, &device, &queue, &light_view, &output_view, &params([2, 0, 2, 4], 0.5)).unwrap();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            size,
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        for row in 0..4 {
+            let texels = &data[row * 256..row * 256 + 16];
+            assert_eq!(&texels[..8], &[255, 0, 0, 255, 255, 0, 0, 255], "row {} background", row);
+            assert!(texels[8..].chunks(4).all(|t| t[0].abs_diff(128) <= 1 && t[1].abs_diff(128) <= 1), "row {} scene {:?}", row, texels);
+        }
+    }
+
+    /// A float output receives the exposed value above 1.0 even when Reinhard is requested, unless HDR
+    /// output is off.
+    #[test]
+    fn hdr_output_skips_tone_mapping() {
+        let Some(adapter) = test_support::adapter("hdr_output_skips_tone_mapping") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        assert!(is_hdr_format(wgpu::TextureFormat::Rgba16Float) && !is_hdr_format(wgpu::TextureFormat::Bgra8UnormSrgb));
+        let size = wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 };
+        let texture = |usage, data: Option<&[u8]>| {
+            let desc = wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba16Float,
+                usage,
+                view_formats: &[],
+            };
+            match data {
+                Some(data) => device.create_texture_with_data(&queue, &desc, wgpu::util::TextureDataOrder::LayerMajor, data),
+                None => device.create_texture(&desc),
+            }
+        };
+        let light = texture(wgpu::TextureUsages::TEXTURE_BINDING, Some(&[0x00, 0x3c].repeat(16 * 4)));
+        let output = texture(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC, None);
+        let pass = PresentPass::new(&device, &queue, wgpu::TextureFormat::Rgba16Float, true, UpscaleFilter::Bilinear, 1).unwrap();
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let light_view = light.create_view(&Default::default());
+        let output_view = output.create_view(&Default::default());
+        let params = PresentParams { exposure: 3.0, ..PresentParams::new((4, 4)) };
+        pass.encode(&mut encoder, &device, &queue, &light_view, &output_view, &params).unwrap();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            output.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            size,
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        // 3.0 in f16.
+        assert_eq!(&data[..2], &[0x00, 0x42]);
+        let offscreen = PresentPass::new(&device, &queue, wgpu::TextureFormat::Rgba16Float, false, UpscaleFilter::Bilinear, 1).unwrap();
+        assert_eq!(offscreen.tone_mode_u32(ToneMapping::Reinhard), 0);
+    }
+}
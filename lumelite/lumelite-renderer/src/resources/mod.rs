@@ -1,15 +1,36 @@
-//! Frame resources: GBuffer (4 RTs), Depth, Light Buffer, optional Shadow Map. Flax-compatible layout.
+//! Frame resources: GBuffer (4 RTs, 2 with the compact packing, plus an optional velocity RT), Depth,
+//! Light Buffer, optional Shadow Map.
+//! Temporary targets for post-process passes come from a per-size texture pool; temporal passes read
+//! the previous frame's light buffer from a history copy.
+
+mod pool;
+
+pub use pool::{TempTexture, TempTextureDesc, TexturePool};
 
 use wgpu::TextureView;
 
+use crate::gbuffer::{GBufferLayout, GBUFFER_DEPTH_FORMAT};
+
+/// Format of the light buffer (linear HDR), and so of every pass that draws into it.
+pub const LIGHT_BUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
 pub struct FrameResources {
     pub gbuffer0: wgpu::Texture,
     pub gbuffer1: wgpu::Texture,
-    pub gbuffer2: wgpu::Texture,
-    pub gbuffer3: wgpu::Texture,
+    /// `None` with [`GBufferLayout::COMPACT`].
+    gbuffer2: Option<wgpu::Texture>,
+    /// `None` with [`GBufferLayout::COMPACT`].
+    gbuffer3: Option<wgpu::Texture>,
+    /// `None` unless the layout has [`GBufferLayout::velocity`].
+    velocity: Option<wgpu::Texture>,
+    gbuffer_layout: GBufferLayout,
     pub depth: wgpu::Texture,
     pub light_buffer: wgpu::Texture,
     pub shadow_map: Option<wgpu::Texture>,
+    /// Light buffer of the previous frame (see [`Self::copy_light_to_history`]); created on first copy.
+    light_history: Option<wgpu::Texture>,
+    /// Recycled temporaries; rebuilt with the other targets on resize.
+    temp_pool: TexturePool,
     width: u32,
     height: u32,
 }
@@ -22,16 +43,23 @@ impl FrameResources {
         height: u32,
         shadow_enabled: bool,
         shadow_resolution: u32,
+        gbuffer_layout: GBufferLayout,
     ) -> Result<Self, String> {
         if width == 0 || height == 0 {
             return Err("FrameResources: width and height must be > 0".to_string());
         }
+        gbuffer_layout.validate(device)?;
         if let Some(r) = existing {
-            if r.width == width && r.height == height && r.shadow_map.is_some() == shadow_enabled {
+            let shadow_size = r.shadow_map.as_ref().map(|t| t.width());
+            if r.width == width
+                && r.height == height
+                && shadow_size == (shadow_enabled && shadow_resolution > 0).then_some(shadow_resolution)
+                && r.gbuffer_layout == gbuffer_layout
+            {
                 return Ok(r);
             }
         }
-        let make_rt = |label: &str, format: wgpu::TextureFormat| {
+        let make_rt = |label: &str, format: wgpu::TextureFormat, extra_usage: wgpu::TextureUsages| {
             device.create_texture(&wgpu::TextureDescriptor {
                 label: Some(label),
                 size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
@@ -39,25 +67,34 @@ impl FrameResources {
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | extra_usage,
                 view_formats: &[],
             })
         };
-        let gbuffer0 = make_rt("gbuffer0", wgpu::TextureFormat::Rgba8Unorm);
-        let gbuffer1 = make_rt("gbuffer1", wgpu::TextureFormat::Rgba8Unorm);
-        let gbuffer2 = make_rt("gbuffer2", wgpu::TextureFormat::Rgba8Unorm);
-        let gbuffer3 = make_rt("gbuffer3", wgpu::TextureFormat::Rgba8Unorm);
+        // Only tests copy out of the GBuffer targets.
+        let gbuffer_usage = if cfg!(test) { wgpu::TextureUsages::COPY_SRC } else { wgpu::TextureUsages::empty() };
+        let formats = gbuffer_layout.formats;
+        let gbuffer_rt = |index: usize| formats.get(index).map(|&f| make_rt(&format!("gbuffer{}", index), f, gbuffer_usage));
+        let gbuffer0 = make_rt("gbuffer0", formats[0], gbuffer_usage);
+        let gbuffer1 = make_rt("gbuffer1", formats[1], gbuffer_usage);
+        let gbuffer2 = gbuffer_rt(2);
+        let gbuffer3 = gbuffer_rt(3);
+        let velocity = gbuffer_layout.velocity.map(|f| make_rt("gbuffer_velocity", f, gbuffer_usage));
         let depth = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("depth"),
             size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: GBUFFER_DEPTH_FORMAT,
+            // COPY_SRC: single-pixel readback for picking (`Renderer::read_depth_pixel`).
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         });
-        let light_buffer = make_rt("light_buffer", wgpu::TextureFormat::Rgba16Float);
+        // COPY_SRC: copied into the history texture (`copy_light_to_history`).
+        let light_buffer = make_rt("light_buffer", LIGHT_BUFFER_FORMAT, wgpu::TextureUsages::COPY_SRC);
         let shadow_map = if shadow_enabled && shadow_resolution > 0 {
             Some(device.create_texture(&wgpu::TextureDescriptor {
                 label: Some("shadow_map"),
@@ -81,9 +118,13 @@ impl FrameResources {
             gbuffer1,
             gbuffer2,
             gbuffer3,
+            velocity,
+            gbuffer_layout,
             depth,
             light_buffer,
             shadow_map,
+            light_history: None,
+            temp_pool: TexturePool::default(),
             width,
             height,
         })
@@ -92,8 +133,31 @@ impl FrameResources {
     pub fn height(&self) -> u32 { self.height }
     pub fn gbuffer0_view(&self) -> TextureView { self.gbuffer0.create_view(&Default::default()) }
     pub fn gbuffer1_view(&self) -> TextureView { self.gbuffer1.create_view(&Default::default()) }
-    pub fn gbuffer2_view(&self) -> TextureView { self.gbuffer2.create_view(&Default::default()) }
-    pub fn gbuffer3_view(&self) -> TextureView { self.gbuffer3.create_view(&Default::default()) }
+    /// `None` with [`GBufferLayout::COMPACT`].
+    pub fn gbuffer2(&self) -> Option<&wgpu::Texture> { self.gbuffer2.as_ref() }
+    /// `None` with [`GBufferLayout::COMPACT`].
+    pub fn gbuffer3(&self) -> Option<&wgpu::Texture> { self.gbuffer3.as_ref() }
+    pub fn gbuffer2_view(&self) -> Option<TextureView> {
+        self.gbuffer2.as_ref().map(|t| t.create_view(&Default::default()))
+    }
+    pub fn gbuffer3_view(&self) -> Option<TextureView> {
+        self.gbuffer3.as_ref().map(|t| t.create_view(&Default::default()))
+    }
+    /// Texture-space motion of each pixel since the view's previous frame (see
+    /// [`GBufferLayout::velocity`]); `None` unless the layout has a velocity target.
+    pub fn velocity(&self) -> Option<&wgpu::Texture> { self.velocity.as_ref() }
+    pub fn velocity_view(&self) -> Option<TextureView> {
+        self.velocity.as_ref().map(|t| t.create_view(&Default::default()))
+    }
+    pub fn gbuffer_layout(&self) -> GBufferLayout { self.gbuffer_layout }
+    /// Views of every GBuffer target of the layout, in attachment order.
+    pub fn gbuffer_views(&self) -> Vec<TextureView> {
+        [Some(&self.gbuffer0), Some(&self.gbuffer1), self.gbuffer2.as_ref(), self.gbuffer3.as_ref(), self.velocity.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(|t| t.create_view(&Default::default()))
+            .collect()
+    }
     pub fn depth_view(&self) -> TextureView { self.depth.create_view(&Default::default()) }
     pub fn light_buffer_view(&self) -> TextureView {
         self.light_buffer.create_view(&Default::default())
@@ -104,4 +168,103 @@ impl FrameResources {
             .expect("shadow_map_view called but shadow_map is None")
             .create_view(&Default::default())
     }
+
+    /// Last frame's light buffer for temporal passes (TAA, SSR, temporal GI). `None` until a frame at
+    /// the current size has been copied, e.g. right after a resize, where temporal passes should reset.
+    pub fn light_history(&self) -> Option<&wgpu::Texture> { self.light_history.as_ref() }
+    pub fn light_history_view(&self) -> Option<TextureView> {
+        self.light_history.as_ref().map(|t| t.create_view(&Default::default()))
+    }
+
+    /// Copy the light buffer into the history texture. Record it after every pass that samples the
+    /// history this frame, so they still see the previous frame.
+    pub fn copy_light_to_history(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let size = self.light_buffer.size();
+        let history = self.light_history.get_or_insert_with(|| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("light_history"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.light_buffer.format(),
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })
+        });
+        encoder.copy_texture_to_texture(self.light_buffer.as_image_copy(), history.as_image_copy(), size);
+    }
+
+    /// Temporary render target (e.g. a bloom mip or SSAO buffer), reused from earlier releases with the
+    /// same descriptor when possible. Hand it back with [`Self::release_temp`] once the pass is encoded.
+    pub fn acquire_temp(&mut self, device: &wgpu::Device, desc: &TempTextureDesc) -> TempTexture {
+        self.temp_pool.acquire(device, desc)
+    }
+
+    pub fn release_temp(&mut self, temp: TempTexture) {
+        self.temp_pool.release(temp);
+    }
+
+    /// Called by the renderer at the start of every frame; frees temporaries idle for a whole frame.
+    pub fn begin_frame(&mut self) {
+        self.temp_pool.begin_frame();
+    }
+
+    pub fn temp_pool(&self) -> &TexturePool { &self.temp_pool }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn light_buffer_is_copied_to_history_and_reset_on_resize() {
+        let Some(adapter) = test_support::adapter("light_buffer_is_copied_to_history_and_reset_on_resize") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        let mut frame = FrameResources::ensure_size(&device, None, 4, 4, false, 0, GBufferLayout::FULL).unwrap();
+        assert!(frame.light_history().is_none());
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &frame.light_buffer_view(),
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::RED), store: wgpu::StoreOp::Store },
+            })],
+            ..Default::default()
+        });
+        frame.copy_light_to_history(&device, &mut encoder);
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let history = frame.light_history().unwrap();
+        assert_eq!(history.size(), frame.light_buffer.size());
+        encoder.copy_texture_to_buffer(
+            history.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            history.size(),
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        // Rgba16Float red: 1.0 (0x3C00), 0, 0, 1.0.
+        let texel: Vec<u8> = readback.slice(..).get_mapped_range()[..8].to_vec();
+        assert_eq!(texel, [0x00, 0x3C, 0, 0, 0, 0, 0x00, 0x3C]);
+
+        let frame = FrameResources::ensure_size(&device, Some(frame), 8, 4, false, 0, GBufferLayout::FULL).unwrap();
+        assert!(frame.light_history().is_none());
+    }
 }
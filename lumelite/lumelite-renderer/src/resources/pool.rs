@@ -0,0 +1,131 @@
+//! Transient render target pool: temporary textures for post-process chains (bloom, SSAO, TAA),
+//! recycled by (size, format, usage) instead of being allocated every frame.
+
+use std::collections::HashMap;
+
+/// Key of a pooled texture. Two requests with equal descriptors may get the same texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TempTextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+/// A texture handed out by [`TexturePool::acquire`]; give it back with [`TexturePool::release`].
+/// Dropping it instead just frees the texture.
+#[derive(Debug)]
+pub struct TempTexture {
+    desc: TempTextureDesc,
+    texture: wgpu::Texture,
+}
+
+impl TempTexture {
+    pub fn desc(&self) -> &TempTextureDesc { &self.desc }
+    pub fn texture(&self) -> &wgpu::Texture { &self.texture }
+    pub fn view(&self) -> wgpu::TextureView { self.texture.create_view(&Default::default()) }
+}
+
+struct FreeTexture {
+    texture: wgpu::Texture,
+    /// Value of `TexturePool::frame` when the texture was last released.
+    released_frame: u64,
+}
+
+#[derive(Default)]
+pub struct TexturePool {
+    free: HashMap<TempTextureDesc, Vec<FreeTexture>>,
+    frame: u64,
+    allocated: usize,
+}
+
+impl TexturePool {
+    /// Reuse a released texture matching `desc`, or create one.
+    pub fn acquire(&mut self, device: &wgpu::Device, desc: &TempTextureDesc) -> TempTexture {
+        if let Some(entry) = self.free.get_mut(desc).and_then(|list| list.pop()) {
+            return TempTexture { desc: *desc, texture: entry.texture };
+        }
+        self.allocated += 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("lumelite_temp"),
+            size: wgpu::Extent3d { width: desc.width, height: desc.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+            view_formats: &[],
+        });
+        TempTexture { desc: *desc, texture }
+    }
+
+    /// Return a texture for reuse later this frame or in following frames. Commands already encoded
+    /// with it stay valid; the next user simply overwrites it.
+    pub fn release(&mut self, temp: TempTexture) {
+        self.free
+            .entry(temp.desc)
+            .or_default()
+            .push(FreeTexture { texture: temp.texture, released_frame: self.frame });
+    }
+
+    /// Start a new frame: free textures that were not used during the previous frame, so targets
+    /// of a disabled effect or an old resolution do not linger.
+    pub fn begin_frame(&mut self) {
+        let frame = self.frame;
+        self.free.retain(|_, list| {
+            list.retain(|t| t.released_frame == frame);
+            !list.is_empty()
+        });
+        self.frame += 1;
+    }
+
+    /// Textures created by the pool over its lifetime (reuse does not count).
+    pub fn allocated_count(&self) -> usize { self.allocated }
+
+    /// Released textures currently waiting for reuse.
+    pub fn free_count(&self) -> usize { self.free.values().map(Vec::len).sum() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn released_temp_is_reused_and_idle_ones_are_reclaimed() {
+        let Some(adapter) = test_support::adapter("released_temp_is_reused_and_idle_ones_are_reclaimed") else {
+            return;
+        };
+        let (device, _queue) = test_support::device(&adapter);
+        let desc = TempTextureDesc {
+            width: 64,
+            height: 32,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        };
+        let mut pool = TexturePool::default();
+        pool.begin_frame();
+        let first = pool.acquire(&device, &desc);
+        pool.release(first);
+        let second = pool.acquire(&device, &desc);
+        assert_eq!(pool.allocated_count(), 1);
+
+        // A different key never aliases, even while a matching texture is free.
+        let half = TempTextureDesc { width: 32, ..desc };
+        pool.release(second);
+        let other = pool.acquire(&device, &half);
+        assert_eq!(pool.allocated_count(), 2);
+        pool.release(other);
+        assert_eq!(pool.free_count(), 2);
+
+        // Released this frame: kept. Unused for a whole frame: freed.
+        pool.begin_frame();
+        assert_eq!(pool.free_count(), 2);
+        let reused = pool.acquire(&device, &desc);
+        pool.release(reused);
+        pool.begin_frame();
+        assert_eq!(pool.free_count(), 1);
+        assert_eq!(pool.allocated_count(), 2);
+    }
+}
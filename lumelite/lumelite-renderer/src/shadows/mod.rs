@@ -1,14 +1,199 @@
-//! Shadow map pass: render depth from directional light view (single cascade).
+//! Shadow map pass: render depth from directional light view (single cascade), and from the point and
+//! spot lights picked by [`allocate_shadows`] into [`LocalShadowMaps`].
 
+use wgpu::util::DeviceExt;
 use wgpu::CommandEncoder;
 
-use crate::gbuffer::MeshDraw;
+use crate::gbuffer::{MeshDraw, MESH_VERTEX_LAYOUT};
 use crate::resources::FrameResources;
 
 const SHADOW_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/shadow.wgsl"));
 
+/// Which lights get a shadow map this frame, from [`allocate_shadows`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShadowAllocation {
+    pub directional: bool,
+    /// Indices into the frame's point lights, highest priority first.
+    pub point_lights: Vec<usize>,
+    /// Indices into the frame's spot lights, highest priority first.
+    pub spot_lights: Vec<usize>,
+}
+
+/// Hand out `budget` shadow maps: the directional light first (when it casts shadows), then point and
+/// spot lights by descending `shadow_priority`, earlier lights winning ties. Lights with priority 0 get none.
+pub fn allocate_shadows(
+    budget: u32,
+    directional: bool,
+    point_lights: &[render_api::PointLight],
+    spot_lights: &[render_api::SpotLight],
+) -> ShadowAllocation {
+    let mut remaining = budget as usize;
+    let directional = directional && remaining > 0;
+    remaining -= directional as usize;
+    // (priority, is spot, index); point lights before spot lights at equal priority.
+    let mut local: Vec<(u32, bool, usize)> = point_lights
+        .iter()
+        .enumerate()
+        .map(|(i, l)| (l.shadow_priority, false, i))
+        .chain(spot_lights.iter().enumerate().map(|(i, l)| (l.shadow_priority, true, i)))
+        .filter(|&(priority, _, _)| priority > 0)
+        .collect();
+    local.sort_by_key(|&(priority, _, _)| std::cmp::Reverse(priority));
+    let mut allocation = ShadowAllocation { directional, ..Default::default() };
+    for (_, spot, index) in local.into_iter().take(remaining) {
+        if spot {
+            allocation.spot_lights.push(index);
+        } else {
+            allocation.point_lights.push(index);
+        }
+    }
+    allocation
+}
+
+impl ShadowAllocation {
+    /// Layers of [`LocalShadowMaps`] the local lights need: six per point light, one per spot light.
+    pub fn local_layer_count(&self) -> u32 {
+        (self.point_lights.len() * 6 + self.spot_lights.len()) as u32
+    }
+}
+
+/// Near plane of the local light shadow projections, in world units.
+const LOCAL_SHADOW_NEAR: f32 = 0.05;
+/// Widest spot cone that still gets a shadow map; wider cones are clamped (the rim is then unshadowed).
+const MAX_SPOT_SHADOW_FOV: f32 = 170.0 * std::f32::consts::PI / 180.0;
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len > 1e-6 { [v[0] / len, v[1] / len, v[2] / len] } else { [0.0, 0.0, -1.0] }
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Column-major view-projection from `eye` looking along `forward`: square right-handed perspective
+/// with a vertical field of view `fov`, mapping `near`..`far` to wgpu's [0, 1] depth.
+fn perspective_look_to(eye: [f32; 3], forward: [f32; 3], fov: f32, far: f32) -> [f32; 16] {
+    let f = normalize(forward);
+    let up = if f[1].abs() > 0.99 { [0.0, 0.0, 1.0] } else { [0.0, 1.0, 0.0] };
+    let s = normalize(cross(f, up));
+    let u = cross(s, f);
+    let near = LOCAL_SHADOW_NEAR;
+    let far = far.max(near * 2.0);
+    let y = 1.0 / (fov * 0.5).tan();
+    let a = far / (near - far);
+    let b = near * far / (near - far);
+    // proj * view with view rows (s, u, -f) and translation -eye.
+    let (te_s, te_u, te_f) = (-dot(s, eye), -dot(u, eye), dot(f, eye));
+    [
+        y * s[0], y * u[0], -a * f[0], f[0],
+        y * s[1], y * u[1], -a * f[1], f[1],
+        y * s[2], y * u[2], -a * f[2], f[2],
+        y * te_s, y * te_u, a * te_f + b, -te_f,
+    ]
+}
+
+/// Field of view of [`spot_shadow_view_proj`]: the outer cone, clamped.
+pub fn spot_shadow_fov(light: &render_api::SpotLight) -> f32 {
+    (light.outer_angle * 2.0).clamp(0.01, MAX_SPOT_SHADOW_FOV)
+}
+
+/// Shadow view-projection of `light`: a perspective frustum along its direction covering the outer cone
+/// out to its radius.
+pub fn spot_shadow_view_proj(light: &render_api::SpotLight) -> [f32; 16] {
+    perspective_look_to(light.position, light.direction, spot_shadow_fov(light), light.radius)
+}
+
+/// Shadow view-projections of `light`'s cube faces, in layer order +X, -X, +Y, -Y, +Z, -Z.
+pub fn point_shadow_view_projs(light: &render_api::PointLight) -> [[f32; 16]; 6] {
+    let axes = [[1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0]];
+    axes.map(|axis| perspective_look_to(light.position, axis, std::f32::consts::FRAC_PI_2, light.radius))
+}
+
+/// Depth maps of the local lights in a [`ShadowAllocation`], in one `Depth32Float` array: each point
+/// light takes six layers (see [`point_shadow_view_projs`]), each spot light one. Grows as needed.
+pub struct LocalShadowMaps {
+    texture: wgpu::Texture,
+}
+
+impl LocalShadowMaps {
+    /// `existing` when it already has `resolution` and at least `layers` layers, otherwise a new array.
+    pub fn ensure(device: &wgpu::Device, existing: Option<Self>, resolution: u32, layers: u32) -> Self {
+        if let Some(maps) = existing.filter(|m| m.resolution() == resolution && m.layer_count() >= layers) {
+            return maps;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("local_shadow_maps"),
+            size: wgpu::Extent3d { width: resolution, height: resolution, depth_or_array_layers: layers.max(1) },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        Self { texture }
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn layer_count(&self) -> u32 {
+        self.texture.depth_or_array_layers()
+    }
+
+    /// Every layer, for sampling in the light pass.
+    pub fn array_view(&self) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        })
+    }
+
+    /// One layer, as a depth target.
+    pub fn layer_view(&self, layer: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+}
+
+/// How [`ShadowPass::encode`] draws the directional shadow map. The default is an identity
+/// `light_view_proj` into a cleared map.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowDrawOptions<'a> {
+    pub light_view_proj: &'a [f32; 16],
+    /// How the shadow map starts: usually `Clear(1.0)`, `Load` to add to earlier casters.
+    pub depth_load: wgpu::LoadOp<f32>,
+}
+
+impl Default for ShadowDrawOptions<'_> {
+    fn default() -> Self {
+        Self { light_view_proj: &crate::IDENTITY, depth_load: wgpu::LoadOp::Clear(1.0) }
+    }
+}
+
+/// Depth target of one `ShadowPass::draw`.
+struct DrawTarget<'a> {
+    pipeline: &'a wgpu::RenderPipeline,
+    view: &'a wgpu::TextureView,
+    view_proj_buf: &'a wgpu::Buffer,
+    depth_load: wgpu::LoadOp<f32>,
+}
+
 pub struct ShadowPass {
     pipeline: wgpu::RenderPipeline,
+    /// Depth-only variant for the perspective local light maps, which need the rasterizer's depth: the
+    /// interpolated z / w the directional pipeline writes is exact only for orthographic projections.
+    local_pipeline: wgpu::RenderPipeline,
     bind_group_layout: wgpu::BindGroupLayout,
     view_proj_buf: wgpu::Buffer,
 }
@@ -55,27 +240,7 @@ impl ShadowPass {
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: Some("vs"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: 32,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[
-                        wgpu::VertexAttribute {
-                            offset: 0,
-                            shader_location: 0,
-                            format: wgpu::VertexFormat::Float32x3,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 12,
-                            shader_location: 1,
-                            format: wgpu::VertexFormat::Float32x3,
-                        },
-                        wgpu::VertexAttribute {
-                            offset: 24,
-                            shader_location: 2,
-                            format: wgpu::VertexFormat::Float32x2,
-                        },
-                    ],
-                }],
+                buffers: &[MESH_VERTEX_LAYOUT],
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
@@ -96,6 +261,28 @@ impl ShadowPass {
             multiview: None,
             cache: None,
         });
+        let local_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("local_shadow_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs"),
+                buffers: &[MESH_VERTEX_LAYOUT],
+                compilation_options: Default::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
         let view_proj_buf = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("shadow_view_proj"),
             size: 64,
@@ -104,11 +291,40 @@ impl ShadowPass {
         });
         Ok(Self {
             pipeline,
+            local_pipeline,
             bind_group_layout,
             view_proj_buf,
         })
     }
 
+    /// Render `meshes` into `target`, cleared first: a layer of [`LocalShadowMaps`] (see
+    /// `LocalShadowMaps::layer_view`) as seen through `view_proj` (from [`spot_shadow_view_proj`] or
+    /// [`point_shadow_view_projs`]). Each call has its own matrix, so every local light of a frame can be
+    /// encoded before one submit.
+    pub fn encode_local(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &wgpu::TextureView,
+        meshes: &[MeshDraw],
+        view_proj: &[f32; 16],
+    ) {
+        let view_proj_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("local_shadow_view_proj"),
+            contents: bytemuck::cast_slice(view_proj),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let target = DrawTarget {
+            pipeline: &self.local_pipeline,
+            view: target,
+            view_proj_buf: &view_proj_buf,
+            depth_load: wgpu::LoadOp::Clear(1.0),
+        };
+        self.draw(encoder, device, queue, target, meshes);
+    }
+
+    /// Render `meshes` into `frame`'s shadow map as `draw` says.
     pub fn encode(
         &self,
         encoder: &mut CommandEncoder,
@@ -116,17 +332,29 @@ impl ShadowPass {
         queue: &wgpu::Queue,
         frame: &FrameResources,
         meshes: &[MeshDraw],
-        light_view_proj: &[f32; 16],
+        draw: ShadowDrawOptions,
     ) -> Result<(), String> {
-        queue.write_buffer(&self.view_proj_buf, 0, bytemuck::cast_slice(light_view_proj));
+        queue.write_buffer(&self.view_proj_buf, 0, bytemuck::cast_slice(draw.light_view_proj));
         let shadow_view = frame.shadow_map_view();
+        let target = DrawTarget {
+            pipeline: &self.pipeline,
+            view: &shadow_view,
+            view_proj_buf: &self.view_proj_buf,
+            depth_load: draw.depth_load,
+        };
+        self.draw(encoder, device, queue, target, meshes);
+        Ok(())
+    }
+
+    fn draw(&self, encoder: &mut CommandEncoder, device: &wgpu::Device, queue: &wgpu::Queue, target: DrawTarget, meshes: &[MeshDraw]) {
+        let DrawTarget { pipeline, view: target, view_proj_buf, depth_load } = target;
         let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("shadow_pass"),
             color_attachments: &[],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &shadow_view,
+                view: target,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: depth_load,
                     store: wgpu::StoreOp::Store,
                 }),
                 stencil_ops: None,
@@ -134,7 +362,7 @@ impl ShadowPass {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        rp.set_pipeline(&self.pipeline);
+        rp.set_pipeline(pipeline);
         for mesh in meshes {
             let model_buf = device.create_buffer(&wgpu::BufferDescriptor {
                 label: Some("shadow_model"),
@@ -149,7 +377,7 @@ impl ShadowPass {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: self.view_proj_buf.as_entire_binding(),
+                        resource: view_proj_buf.as_entire_binding(),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
@@ -162,7 +390,155 @@ impl ShadowPass {
             rp.set_index_buffer(mesh.index_buf.slice(..), wgpu::IndexFormat::Uint32);
             rp.draw_indexed(0..mesh.index_count, 0, 0..1);
         }
-        drop(rp);
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbuffer::{GBufferDrawOptions, GBufferLayout, GBufferPass, GBUFFER_DEPTH_FORMAT};
+    use crate::test_support;
+
+    #[test]
+    fn shadow_budget_goes_to_directional_then_priority() {
+        let point = |shadow_priority| render_api::PointLight { shadow_priority, ..Default::default() };
+        let spot = |shadow_priority| render_api::SpotLight { shadow_priority, ..Default::default() };
+        let points = [point(1), point(0), point(5)];
+        let spots = [spot(3), spot(5)];
+        let allocation = allocate_shadows(4, true, &points, &spots);
+        assert!(allocation.directional);
+        assert_eq!(allocation.point_lights, [2]);
+        assert_eq!(allocation.spot_lights, [1, 0]);
+        let allocation = allocate_shadows(2, false, &points, &spots);
+        assert!(!allocation.directional);
+        assert_eq!((allocation.point_lights, allocation.spot_lights), (vec![2], vec![1]));
+        assert_eq!(allocate_shadows(0, true, &points, &spots), ShadowAllocation::default());
+        assert_eq!(allocate_shadows(10, true, &points, &spots).point_lights, [2, 0]);
+    }
+
+    fn project(m: &[f32; 16], p: [f32; 3]) -> [f32; 3] {
+        let clip: Vec<f32> = (0..4).map(|r| m[r] * p[0] + m[4 + r] * p[1] + m[8 + r] * p[2] + m[12 + r]).collect();
+        [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]]
+    }
+
+    fn inside(ndc: [f32; 3]) -> bool {
+        ndc[0].abs() <= 1.0 && ndc[1].abs() <= 1.0 && (0.0..=1.0).contains(&ndc[2])
+    }
+
+    #[test]
+    fn local_shadow_frusta_cover_the_light() {
+        let spot = render_api::SpotLight {
+            position: [1.0, 2.0, 3.0],
+            direction: [0.0, -1.0, 0.0],
+            radius: 10.0,
+            outer_angle: 0.5,
+            ..Default::default()
+        };
+        let m = spot_shadow_view_proj(&spot);
+        let on_axis = project(&m, [1.0, -3.0, 3.0]);
+        assert!(on_axis[0].abs() < 1e-5 && on_axis[1].abs() < 1e-5, "{:?}", on_axis);
+        assert!(on_axis[2] > 0.0 && on_axis[2] < 1.0);
+        // Depth grows with distance; behind the light and past the radius are outside.
+        assert!(project(&m, [1.0, -6.0, 3.0])[2] > on_axis[2]);
+        assert!(!inside(project(&m, [1.0, -9.0, 3.0])));
+        // Just inside and outside the outer cone, 5 units down.
+        let edge = 5.0 * 0.5f32.tan();
+        assert!(inside(project(&m, [1.0 + edge * 0.95, -3.0, 3.0])));
+        assert!(!inside(project(&m, [1.0 + edge * 1.05, -3.0, 3.0])));
+
+        let point = render_api::PointLight { position: [1.0, 2.0, 3.0], radius: 10.0, ..Default::default() };
+        let faces = point_shadow_view_projs(&point);
+        let dirs = [[1.0, 0.2, -0.3], [-1.0, 0.1, 0.4], [0.3, 1.0, 0.2], [-0.2, -1.0, 0.1], [0.1, 0.4, 1.0], [0.4, -0.3, -1.0]];
+        for (face, d) in dirs.iter().enumerate() {
+            let p = [1.0 + 3.0 * d[0], 2.0 + 3.0 * d[1], 3.0 + 3.0 * d[2]];
+            for (other, m) in faces.iter().enumerate() {
+                assert_eq!(inside(project(m, p)), face == other, "direction {:?}, face {}", d, other);
+            }
+        }
+    }
+
+    const SIZE: u32 = 8;
+
+    fn read_depth(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Vec<f32> {
+        let row = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (row * SIZE) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture { aspect: wgpu::TextureAspect::DepthOnly, ..texture.as_image_copy() },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(row), rows_per_image: None },
+            },
+            texture.size(),
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let bytes = readback.slice(..).get_mapped_range();
+        bytes
+            .chunks_exact(row as usize)
+            .flat_map(|r| bytemuck::cast_slice::<u8, f32>(&r[..(SIZE * 4) as usize]).to_vec())
+            .collect()
+    }
+
+    /// One vertex buffer drawn by both passes must land at the same depths. Skipped when no adapter is
+    /// available (e.g. headless CI) or it cannot copy depth textures.
+    #[test]
+    fn shadow_and_gbuffer_read_the_same_vertex_buffer() {
+        let Some(adapter) = test_support::adapter("shadow_and_gbuffer_read_the_same_vertex_buffer") else {
+            return;
+        };
+        if !adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::DEPTH_TEXTURE_AND_BUFFER_COPIES) {
+            eprintln!("shadow_and_gbuffer_read_the_same_vertex_buffer: no depth copies, skipped");
+            return;
+        }
+        let (device, queue) = test_support::device(&adapter);
+        let white = test_support::texture(&device, &queue, [255; 4]);
+        // Full-view quad sloping in z (depth 0.25 .. 0.75), with non-zero normals and uvs so a
+        // mismatched stride or offset would read them as positions.
+        #[rustfmt::skip]
+        let vertices: [[f32; 8]; 4] = [
+            [-1.0, -1.0, -0.5, 0.3, 0.4, 0.8, 0.25, 0.75], [1.0, -1.0, 0.5, 0.3, 0.4, 0.8, 0.5, 0.5],
+            [1.0, 1.0, 0.5, 0.3, 0.4, 0.8, 0.75, 0.25], [-1.0, 1.0, -0.5, 0.3, 0.4, 0.8, 1.0, 0.0],
+        ];
+        let mesh = test_support::mesh(&device, &vertices, &[0, 1, 2, 0, 2, 3], test_support::pbr_textures(white));
+        // Looking down -z: depth = 0.5 - 0.5 * z.
+        #[rustfmt::skip]
+        let view_proj = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 1.0];
+        let mut frame =
+            FrameResources::ensure_size(&device, None, SIZE, SIZE, true, SIZE, GBufferLayout::FULL).unwrap();
+        // Same as the frame's shadow map, plus COPY_SRC for the readback.
+        let shadow_map = frame.shadow_map.as_ref().unwrap();
+        frame.shadow_map = Some(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow_map"),
+            size: shadow_map.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: shadow_map.format(),
+            usage: shadow_map.usage() | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        }));
+        let meshes = [mesh];
+        let shadow_pass = ShadowPass::new(&device, SIZE).unwrap();
+        let gbuffer_pass = GBufferPass::new(&device, GBufferLayout::FULL, GBUFFER_DEPTH_FORMAT, wgpu::CompareFunction::LessEqual, None, Default::default(), &Default::default()).unwrap();
+        let clear = wgpu::LoadOp::Clear(1.0);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let shadow_draw = ShadowDrawOptions { light_view_proj: &view_proj, depth_load: clear };
+        shadow_pass.encode(&mut encoder, &device, &queue, &frame, &meshes, shadow_draw).unwrap();
+        let draw = GBufferDrawOptions { view_proj: &view_proj, depth_load: clear, ..Default::default() };
+        gbuffer_pass.encode(&mut encoder, &device, &queue, &frame, &meshes, draw).unwrap();
+        queue.submit([encoder.finish()]);
+        let shadow = read_depth(&device, &queue, frame.shadow_map.as_ref().unwrap());
+        let scene = read_depth(&device, &queue, &frame.depth);
+        assert!(scene.iter().all(|d| (0.25..=0.75).contains(d)), "{:?}", scene);
+        for (s, g) in shadow.iter().zip(&scene) {
+            assert!((s - g).abs() < 1e-4, "shadow {:?}\ngbuffer {:?}", shadow, scene);
+        }
     }
 }
@@ -0,0 +1,97 @@
+//! Fixtures shared by the GPU tests: the default adapter and device, skipping the test when there is
+//! none (e.g. headless CI), and a [`MeshDraw`] over small in-memory geometry.
+
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::gbuffer::{MeshDraw, PbrTextureViews};
+use crate::IDENTITY;
+
+/// The default adapter, or `None` after reporting `test` as skipped when there is none.
+pub fn adapter(test: &str) -> Option<wgpu::Adapter> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()));
+    if adapter.is_none() {
+        eprintln!("{}: no adapter, skipped", test);
+    }
+    adapter
+}
+
+/// A device with default features and limits on `adapter`.
+pub fn device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).expect("request_device")
+}
+
+/// A device with `features` on `adapter`, or `None` after reporting `test` as skipped when the adapter
+/// lacks them.
+pub fn device_with_features(adapter: &wgpu::Adapter, features: wgpu::Features, test: &str) -> Option<(wgpu::Device, wgpu::Queue)> {
+    if !adapter.features().contains(features) {
+        eprintln!("{}: adapter lacks {:?}, skipped", test, features - adapter.features());
+        return None;
+    }
+    let descriptor = wgpu::DeviceDescriptor { required_features: features, ..Default::default() };
+    Some(pollster::block_on(adapter.request_device(&descriptor, None)).expect("request_device"))
+}
+
+/// Whether `adapter` is the GL backend, reporting `test` as skipped if so (the light shaders
+/// `textureLoad` the depth buffer, which it cannot validate).
+pub fn skip_gl(adapter: &wgpu::Adapter, test: &str) -> bool {
+    let gl = adapter.get_info().backend == wgpu::Backend::Gl;
+    if gl {
+        eprintln!("{}: GL backend, skipped", test);
+    }
+    gl
+}
+
+/// A 1x1 Rgba8Unorm texture of `texel`.
+pub fn texture(device: &wgpu::Device, queue: &wgpu::Queue, texel: [u8; 4]) -> Arc<wgpu::TextureView> {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &texel,
+    );
+    Arc::new(texture.create_view(&Default::default()))
+}
+
+/// `view` as every PBR texture of a mesh.
+pub fn pbr_textures(view: Arc<wgpu::TextureView>) -> PbrTextureViews {
+    PbrTextureViews { base_color: view.clone(), normal: view.clone(), metallic_roughness: view.clone(), ao: view }
+}
+
+/// A PBR mesh of `vertices` (in `MESH_VERTEX_LAYOUT`) and `indices` with an identity transform and no
+/// bounds; override other fields with struct update syntax.
+pub fn mesh(device: &wgpu::Device, vertices: &[[f32; 8]], indices: &[u32], pbr_textures: PbrTextureViews) -> MeshDraw {
+    MeshDraw {
+        vertex_buf: Arc::new(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        })),
+        index_buf: Arc::new(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        })),
+        index_count: indices.len() as u32,
+        transform: IDENTITY,
+        pbr_textures,
+        mirrored: false,
+        double_sided: false,
+        bounds: None,
+        shader_variant: render_api::ShaderVariant::Pbr,
+        vertex_colors: None,
+        tangents: None,
+        emissive: [0.0; 3],
+    }
+}
@@ -0,0 +1,228 @@
+//! GPU pass timings from timestamp queries (`LumeliteConfig::debug_gpu_timings`). Timestamps are written
+//! between the passes of a frame, resolved after its first present and read back a frame or more
+//! later, so reading them never stalls the GPU.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+use render_api::{log, log_enabled, LogLevel};
+use wgpu::CommandEncoder;
+
+/// Timestamps written per frame, in order: frame start, then the end of the shadow, GBuffer, light and
+/// present passes.
+pub(crate) const TIMESTAMP_COUNT: u32 = 5;
+pub(crate) const SHADOW_END: u32 = 1;
+pub(crate) const GBUFFER_END: u32 = 2;
+pub(crate) const LIGHT_END: u32 = 3;
+const PRESENT_END: u32 = 4;
+
+/// Readback buffers in flight; a frame is dropped from the timings when all of them are still busy.
+const READBACK_SLOTS: usize = 3;
+
+/// State of a `map_async` in flight, set by its callback; shared with the cull stats readback.
+pub(crate) const MAP_PENDING: u8 = 0;
+pub(crate) const MAP_DONE: u8 = 1;
+pub(crate) const MAP_FAILED: u8 = 2;
+
+/// GPU time of each pass of one frame, in milliseconds. GPU culling counts toward `gbuffer_ms`; unlit
+/// meshes, fog, the grid and the post-process hook toward `light_ms`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GpuTimings {
+    pub shadow_ms: f32,
+    pub gbuffer_ms: f32,
+    pub light_ms: f32,
+    pub present_ms: f32,
+}
+
+impl GpuTimings {
+    /// Timings from the frame's timestamps in ticks, with `period` nanoseconds per tick
+    /// (`Queue::get_timestamp_period`). Out-of-order timestamps, which some drivers report across
+    /// passes, count as zero.
+    pub fn from_ticks(ticks: &[u64; TIMESTAMP_COUNT as usize], period: f32) -> Self {
+        let ms = |i: usize| ticks[i + 1].saturating_sub(ticks[i]) as f32 * period / 1_000_000.0;
+        Self { shadow_ms: ms(0), gbuffer_ms: ms(1), light_ms: ms(2), present_ms: ms(3) }
+    }
+
+    /// `(name, ms)` of every pass in frame order.
+    pub fn passes(&self) -> [(&'static str, f32); 4] {
+        [("SHADOW", self.shadow_ms), ("GBUFFER", self.gbuffer_ms), ("LIGHT", self.light_ms), ("PRESENT", self.present_ms)]
+    }
+
+    pub fn total_ms(&self) -> f32 {
+        self.shadow_ms + self.gbuffer_ms + self.light_ms + self.present_ms
+    }
+}
+
+enum Slot {
+    Free,
+    /// Timestamps of the frame being encoded go to this slot.
+    Recording,
+    /// Resolve and copy encoded; mapped by `frame_submitted` once the frame has been submitted.
+    Resolved,
+    Mapping(Arc<AtomicU8>),
+}
+
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buf: wgpu::Buffer,
+    readback: Vec<wgpu::Buffer>,
+    /// Behind a mutex because the present (`&self`) resolves the frame.
+    slots: Mutex<Vec<Slot>>,
+    period: f32,
+    latest: Option<GpuTimings>,
+    /// Frames read back so far; numbers the `latest` sample.
+    samples: u64,
+}
+
+impl GpuTimer {
+    /// Timestamps between passes need `Features::TIMESTAMP_QUERY | TIMESTAMP_QUERY_INSIDE_ENCODERS`,
+    /// which hosts must request in `DeviceDescriptor::required_features`.
+    pub fn is_supported(device: &wgpu::Device) -> bool {
+        device.features().contains(wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS)
+    }
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, String> {
+        if !Self::is_supported(device) {
+            return Err("GpuTimer: the device lacks TIMESTAMP_QUERY | TIMESTAMP_QUERY_INSIDE_ENCODERS".to_string());
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu_timer_queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: TIMESTAMP_COUNT,
+        });
+        let size = TIMESTAMP_COUNT as u64 * 8;
+        let resolve_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_timer_resolve"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = (0..READBACK_SLOTS)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("gpu_timer_readback"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        Ok(Self {
+            query_set,
+            resolve_buf,
+            readback,
+            slots: Mutex::new((0..READBACK_SLOTS).map(|_| Slot::Free).collect()),
+            period: queue.get_timestamp_period(),
+            latest: None,
+            samples: 0,
+        })
+    }
+
+    /// Timings of the most recent frame read back, if any.
+    pub fn latest(&self) -> Option<GpuTimings> {
+        self.latest
+    }
+
+    /// [`Self::latest`] with its sample number, which grows by one with every frame read back; lets
+    /// callers tell a new sample from one they have already seen.
+    pub fn latest_sample(&self) -> Option<(u64, GpuTimings)> {
+        self.latest.map(|timings| (self.samples, timings))
+    }
+
+    /// Collect finished readbacks and start timing a frame in a free slot.
+    pub(crate) fn begin_frame(&mut self, device: &wgpu::Device, encoder: &mut CommandEncoder) -> Result<(), String> {
+        let mut slots = self.slots.lock().map_err(|e| format!("timings: slots lock: {}", e))?;
+        for slot in slots.iter_mut() {
+            // The previous frame was never presented.
+            if matches!(slot, Slot::Recording) {
+                *slot = Slot::Free;
+            }
+        }
+        device.poll(wgpu::Maintain::Poll);
+        for (slot, buffer) in slots.iter_mut().zip(&self.readback) {
+            let Slot::Mapping(state) = slot else { continue };
+            match state.load(Ordering::Acquire) {
+                MAP_DONE => {
+                    {
+                        let data = buffer.slice(..).get_mapped_range();
+                        let ticks: &[u64] = bytemuck::cast_slice(&data);
+                        let timings = GpuTimings::from_ticks(ticks.try_into().unwrap(), self.period);
+                        if log_enabled(LogLevel::Debug, "lumelite::timings") {
+                            let passes: Vec<String> =
+                                timings.passes().iter().map(|(name, ms)| format!("{} {:.3} ms", name, ms)).collect();
+                            log(
+                                LogLevel::Debug,
+                                "lumelite::timings",
+                                format_args!("GPU {} (total {:.3} ms)", passes.join(", "), timings.total_ms()),
+                            );
+                        }
+                        self.latest = Some(timings);
+                        self.samples += 1;
+                    }
+                    buffer.unmap();
+                    *slot = Slot::Free;
+                }
+                MAP_FAILED => *slot = Slot::Free,
+                _ => {}
+            }
+        }
+        if let Some(slot) = slots.iter_mut().find(|s| matches!(s, Slot::Free)) {
+            *slot = Slot::Recording;
+            encoder.write_timestamp(&self.query_set, 0);
+        }
+        Ok(())
+    }
+
+    /// Mark the end of a pass (`SHADOW_END`, `GBUFFER_END`, `LIGHT_END`) of the frame being timed.
+    pub(crate) fn mark(&self, encoder: &mut CommandEncoder, index: u32) -> Result<(), String> {
+        let slots = self.slots.lock().map_err(|e| format!("timings: slots lock: {}", e))?;
+        if slots.iter().any(|s| matches!(s, Slot::Recording)) {
+            encoder.write_timestamp(&self.query_set, index);
+        }
+        Ok(())
+    }
+
+    /// Start mapping the timestamps queued by [`Self::end_frame`] since the last call. Call only once the
+    /// encoders holding them have been submitted.
+    pub(crate) fn frame_submitted(&self) -> Result<(), String> {
+        let mut slots = self.slots.lock().map_err(|e| format!("timings: slots lock: {}", e))?;
+        for (slot, buffer) in slots.iter_mut().zip(&self.readback) {
+            if matches!(slot, Slot::Resolved) {
+                let state = Arc::new(AtomicU8::new(MAP_PENDING));
+                let callback_state = Arc::clone(&state);
+                buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                    callback_state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+                });
+                *slot = Slot::Mapping(state);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the end of the present pass and queue the frame's timestamps for readback. Later presents
+    /// of the same frame (e.g. split screen) are not timed.
+    pub(crate) fn end_frame(&self, encoder: &mut CommandEncoder) -> Result<(), String> {
+        let mut slots = self.slots.lock().map_err(|e| format!("timings: slots lock: {}", e))?;
+        let Some(index) = slots.iter().position(|s| matches!(s, Slot::Recording)) else { return Ok(()) };
+        encoder.write_timestamp(&self.query_set, PRESENT_END);
+        encoder.resolve_query_set(&self.query_set, 0..TIMESTAMP_COUNT, &self.resolve_buf, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buf, 0, &self.readback[index], 0, self.resolve_buf.size());
+        slots[index] = Slot::Resolved;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timings_from_ticks() {
+        // 2 ns per tick: 1 ms = 500_000 ticks.
+        let ticks = [1_000, 501_000, 1_501_000, 1_251_000, 2_001_000];
+        let timings = GpuTimings::from_ticks(&ticks, 2.0);
+        assert_eq!(timings, GpuTimings { shadow_ms: 1.0, gbuffer_ms: 2.0, light_ms: 0.0, present_ms: 1.5 });
+        assert_eq!(timings.total_ms(), 4.5);
+        assert_eq!(timings.passes()[1], ("GBUFFER", 2.0));
+    }
+}
@@ -0,0 +1,457 @@
+//! Unlit pass: meshes with `ShaderVariant::Unlit` write their base color straight into the light buffer
+//! after the lit passes (UI in the world, emissive props, debug geometry). The GBuffer pass has already
+//! written their depth and the unlit shading model, so lights skip them and this pass only has to
+//! depth-test against the scene depth. Meshes of either variant with an emissive factor then add it
+//! on top, so untextured props can glow.
+
+use render_api::ShaderVariant;
+use wgpu::CommandEncoder;
+
+use crate::config::{AdapterCapabilities, MaterialSampling};
+use crate::culling::DRAW_ARGS_SIZE;
+use crate::gbuffer::{draw_instanced, mesh_runs, GBufferDrawOptions, MeshData, MeshDraw, GBUFFER_DEPTH_FORMAT, MESH_VERTEX_LAYOUT, VERTEX_COLOR_LAYOUT};
+use crate::resources::FrameResources;
+
+const UNLIT_SHADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/unlit.wgsl"));
+
+/// Clip plane that keeps everything (`dot(0, p) + 1 >= 0`).
+const NO_CLIP_PLANE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+pub struct UnlitPass {
+    /// Indexed by [`Self::pipeline_index`].
+    pipelines: [wgpu::RenderPipeline; 4],
+    /// Same as `pipelines` with `fs_emissive`, blended additively.
+    emissive_pipelines: [wgpu::RenderPipeline; 4],
+    mesh_data: MeshData,
+    bind_group_layout_0: wgpu::BindGroupLayout,
+    bind_group_layout_1: wgpu::BindGroupLayout,
+    view_proj_buf: wgpu::Buffer,
+    clip_plane_buf: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+}
+
+impl UnlitPass {
+    /// `cull_mode` is applied the way [`crate::GBufferPass::new`] applies it: to single-sided meshes
+    /// only, with clockwise front faces for mirrored ones. `capabilities` are those of the GBuffer pass.
+    pub fn new(
+        device: &wgpu::Device,
+        light_buffer_format: wgpu::TextureFormat,
+        depth_compare: wgpu::CompareFunction,
+        cull_mode: Option<wgpu::Face>,
+        material_sampling: MaterialSampling,
+        capabilities: &AdapterCapabilities,
+    ) -> Result<Self, String> {
+        let mesh_data = MeshData::new(device, capabilities);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("unlit_shader"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", mesh_data.wgsl(), UNLIT_SHADER).into()),
+        });
+        let uniform = |binding, visibility, size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: std::num::NonZeroU64::new(size),
+            },
+            count: None,
+        };
+        let bind_group_layout_0 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("unlit_bind_group_layout_0"),
+            entries: &[
+                uniform(0, wgpu::ShaderStages::VERTEX, 64),
+                mesh_data.layout_entry(),
+                uniform(2, wgpu::ShaderStages::FRAGMENT, 16),
+            ],
+        });
+        let bind_group_layout_1 = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("unlit_bind_group_layout_1"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("unlit_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout_0, &bind_group_layout_1],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label: &str, entry_point, blend, front_face, cull_mode| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs"),
+                buffers: &[MESH_VERTEX_LAYOUT, VERTEX_COLOR_LAYOUT],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some(entry_point),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: light_buffer_format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState { front_face, cull_mode, ..Default::default() },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: GBUFFER_DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let variant_pipelines = |label: &str, entry_point, blend| {
+            std::array::from_fn(|index| {
+                let (mirrored, double_sided) = (index & 1 != 0, index & 2 != 0);
+                let front_face = if mirrored { wgpu::FrontFace::Cw } else { wgpu::FrontFace::Ccw };
+                let mirrored_label = if mirrored { "_mirrored" } else { "" };
+                let double_sided_label = if double_sided { "_double_sided" } else { "" };
+                let label = format!("{}{}{}_pipeline", label, double_sided_label, mirrored_label);
+                create_pipeline(&label, entry_point, blend, front_face, if double_sided { None } else { cull_mode })
+            })
+        };
+        let pipelines = variant_pipelines("unlit", "fs", None);
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let emissive_pipelines =
+            variant_pipelines("unlit_emissive", "fs_emissive", Some(wgpu::BlendState { color: additive, alpha: additive }));
+        let uniform_buf = |label, size| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let sampler = device.create_sampler(&material_sampling.sampler_descriptor(Some("unlit_sampler")));
+        Ok(Self {
+            pipelines,
+            emissive_pipelines,
+            mesh_data,
+            bind_group_layout_0,
+            bind_group_layout_1,
+            view_proj_buf: uniform_buf("unlit_view_proj", 64),
+            clip_plane_buf: uniform_buf("unlit_clip_plane", 16),
+            sampler,
+        })
+    }
+
+    fn pipeline_index(mirrored: bool, double_sided: bool) -> usize {
+        mirrored as usize | (double_sided as usize) << 1
+    }
+
+    /// Bind group 0 with `mesh_data` from [`MeshData::create_buffer`].
+    fn bind_group_0(&self, device: &wgpu::Device, mesh_data: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("unlit_bind_group_0"),
+            layout: &self.bind_group_layout_0,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: self.view_proj_buf.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: mesh_data.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: self.clip_plane_buf.as_entire_binding() },
+            ],
+        })
+    }
+
+    /// Binds the base color (group 1), vertex streams and indices of `mesh`.
+    fn bind_mesh(&self, rp: &mut wgpu::RenderPass, device: &wgpu::Device, mesh: &MeshDraw) {
+        let bg1 = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("unlit_bind_group_1"),
+            layout: &self.bind_group_layout_1,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&mesh.pbr_textures.base_color),
+                },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+            ],
+        });
+        rp.set_bind_group(1, &bg1, &[]);
+        rp.set_vertex_buffer(0, mesh.vertex_buf.slice(..));
+        rp.set_vertex_buffer(1, mesh.color_stream());
+        rp.set_index_buffer(mesh.index_buf.slice(..), wgpu::IndexFormat::Uint32);
+    }
+
+    /// Draws the `ShaderVariant::Unlit` meshes of `meshes` onto the light buffer, replacing what the lit
+    /// passes wrote there, then adds the `emissive` of every mesh that has one. `draw` must be what the
+    /// GBuffer pass drew the same `meshes` with; its `view_proj`, `clip_plane`, `mirrored_view` and
+    /// `indirect` are used.
+    /// Records nothing when no mesh is unlit or emissive.
+    pub fn encode(
+        &self,
+        encoder: &mut CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &FrameResources,
+        meshes: &[MeshDraw],
+        draw: GBufferDrawOptions,
+    ) -> Result<(), String> {
+        let GBufferDrawOptions { view_proj, clip_plane, mirrored_view, indirect, .. } = draw;
+        let unlit = |m: &MeshDraw| m.shader_variant == ShaderVariant::Unlit;
+        let emissive = |m: &MeshDraw| m.emissive != [0.0; 3];
+        if !meshes.iter().any(|m| unlit(m) || emissive(m)) {
+            return Ok(());
+        }
+        if let Some(args) = indirect {
+            if args.size() < meshes.len() as u64 * DRAW_ARGS_SIZE {
+                return Err(format!("UnlitPass::encode: indirect buffer too small for {} meshes", meshes.len()));
+            }
+        }
+        queue.write_buffer(&self.view_proj_buf, 0, bytemuck::cast_slice(view_proj));
+        queue.write_buffer(&self.clip_plane_buf, 0, bytemuck::cast_slice(&clip_plane.unwrap_or(NO_CLIP_PLANE)));
+        let light_view = frame.light_buffer_view();
+        let depth_view = frame.depth_view();
+        let mut rp = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("unlit_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &light_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let draw_key = |mesh: &MeshDraw| {
+            let index = Self::pipeline_index(mesh.mirrored != mirrored_view, mesh.double_sided);
+            (unlit(mesh) || emissive(mesh)).then_some((index, unlit(mesh), emissive(mesh)))
+        };
+        // The unlit then the emissive pipeline, each when the mesh needs it.
+        let pipelines = |(index, unlit, emissive): (usize, bool, bool)| {
+            [(unlit, &self.pipelines[index]), (emissive, &self.emissive_pipelines[index])]
+                .into_iter()
+                .filter_map(|(draw, pipeline)| draw.then_some(pipeline))
+        };
+        match self.mesh_data {
+            MeshData::Instanced { multi_draw } => {
+                rp.set_bind_group(0, &self.bind_group_0(device, &MeshData::create_buffer(device, meshes)), &[]);
+                for (key, run) in mesh_runs(meshes, draw_key) {
+                    self.bind_mesh(&mut rp, device, &meshes[run.start]);
+                    for pipeline in pipelines(key) {
+                        rp.set_pipeline(pipeline);
+                        draw_instanced(&mut rp, meshes, run.clone(), indirect, multi_draw);
+                    }
+                }
+            }
+            MeshData::PerDraw => {
+                for (i, mesh) in meshes.iter().enumerate() {
+                    let Some(key) = draw_key(mesh) else { continue };
+                    let bg0 = self.bind_group_0(device, &MeshData::create_buffer(device, std::slice::from_ref(mesh)));
+                    rp.set_bind_group(0, &bg0, &[]);
+                    self.bind_mesh(&mut rp, device, mesh);
+                    for pipeline in pipelines(key) {
+                        rp.set_pipeline(pipeline);
+                        match indirect {
+                            Some(args) => rp.draw_indexed_indirect(args, i as u64 * DRAW_ARGS_SIZE),
+                            None => rp.draw_indexed(0..mesh.index_count, 0, 0..1),
+                        }
+                    }
+                }
+            }
+        }
+        drop(rp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gbuffer::{GBufferLayout, GBufferPass, GBUFFER_DEPTH_CLEAR};
+    use crate::test_support;
+
+    /// A red quad covering the view at height `z`, facing the camera unless `back_facing`.
+    fn quad(device: &wgpu::Device, queue: &wgpu::Queue, z: f32, back_facing: bool) -> MeshDraw {
+        let red = test_support::texture(device, queue, [255, 0, 0, 255]);
+        #[rustfmt::skip]
+        let vertices: [[f32; 8]; 4] = [
+            [-1.0, -1.0, z, 0.0, 0.0, 1.0, 0.0, 0.0], [1.0, -1.0, z, 0.0, 0.0, 1.0, 1.0, 0.0],
+            [1.0, 1.0, z, 0.0, 0.0, 1.0, 1.0, 1.0], [-1.0, 1.0, z, 0.0, 0.0, 1.0, 0.0, 1.0],
+        ];
+        let indices: &[u32] = if back_facing { &[0, 2, 1, 0, 3, 2] } else { &[0, 1, 2, 0, 2, 3] };
+        test_support::mesh(device, &vertices, indices, test_support::pbr_textures(red))
+    }
+
+    /// Center texel of the light buffer (Rgba16Float bits) after the GBuffer pass, a blue clear of the
+    /// light buffer and the unlit pass, for quads covering the view at the given heights, all emitting
+    /// `emissive`.
+    fn render_center_light(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        quads: &[(f32, ShaderVariant)],
+        emissive: [f32; 3],
+    ) -> [u16; 4] {
+        let meshes: Vec<MeshDraw> = quads
+            .iter()
+            .map(|&(z, shader_variant)| MeshDraw { shader_variant, emissive, ..quad(device, queue, z, false) })
+            .collect();
+        render_meshes_center_light(device, queue, &meshes, None)
+    }
+
+    /// Like [`render_center_light`] for arbitrary `meshes`, with both passes culling `cull_mode`.
+    fn render_meshes_center_light(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        meshes: &[MeshDraw],
+        cull_mode: Option<wgpu::Face>,
+    ) -> [u16; 4] {
+        let frame = FrameResources::ensure_size(device, None, 8, 8, false, 0, GBufferLayout::FULL).unwrap();
+        // Looking down -z: depth = 0.5 - 0.5 * z.
+        #[rustfmt::skip]
+        let view_proj = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 0.0, 0.5, 1.0];
+        let gbuffer_pass = GBufferPass::new(device, GBufferLayout::FULL, GBUFFER_DEPTH_FORMAT, wgpu::CompareFunction::LessEqual, cull_mode, MaterialSampling::default(), &Default::default()).unwrap();
+        let unlit_pass = UnlitPass::new(device, frame.light_buffer.format(), wgpu::CompareFunction::LessEqual, cull_mode, MaterialSampling::default(), &Default::default()).unwrap();
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let depth_load = wgpu::LoadOp::Clear(GBUFFER_DEPTH_CLEAR);
+        let draw = GBufferDrawOptions { view_proj: &view_proj, depth_load, ..Default::default() };
+        gbuffer_pass.encode(&mut encoder, device, queue, &frame, meshes, draw).unwrap();
+        let light_view = frame.light_buffer_view();
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &light_view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLUE), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        unlit_pass.encode(&mut encoder, device, queue, &frame, meshes, draw).unwrap();
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256 * 8,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            frame.light_buffer.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            wgpu::Extent3d { width: 8, height: 8, depth_or_array_layers: 1 },
+        );
+        queue.submit([encoder.finish()]);
+        readback.slice(..).map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback.slice(..).get_mapped_range();
+        let texel = 4 * 256 + 4 * 8;
+        std::array::from_fn(|c| u16::from_le_bytes([data[texel + c * 2], data[texel + c * 2 + 1]]))
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn unlit_meshes_write_base_color_unless_occluded() {
+        let Some(adapter) = test_support::adapter("unlit_meshes_write_base_color_unless_occluded") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        const ONE: u16 = 0x3C00;
+        assert_eq!(render_center_light(&device, &queue, &[(0.5, ShaderVariant::Unlit)], [0.0; 3]), [ONE, 0, 0, ONE]);
+        // A lit quad in front hides it; one behind does not.
+        let hidden = [(0.5, ShaderVariant::Unlit), (0.8, ShaderVariant::Pbr)];
+        assert_eq!(render_center_light(&device, &queue, &hidden, [0.0; 3]), [0, 0, ONE, ONE]);
+        let shown = [(0.5, ShaderVariant::Unlit), (0.2, ShaderVariant::Pbr)];
+        assert_eq!(render_center_light(&device, &queue, &shown, [0.0; 3]), [ONE, 0, 0, ONE]);
+        // Lit meshes alone: nothing recorded.
+        assert_eq!(render_center_light(&device, &queue, &[(0.5, ShaderVariant::Pbr)], [0.0; 3]), [0, 0, ONE, ONE]);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn emissive_factor_adds_to_the_light_buffer() {
+        let Some(adapter) = test_support::adapter("emissive_factor_adds_to_the_light_buffer") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        const ONE: u16 = 0x3C00;
+        const HALF: u16 = 0x3800;
+        const QUARTER: u16 = 0x3400;
+        // On top of what the lights left (here the blue clear), and of an unlit mesh's base color.
+        let lit = render_center_light(&device, &queue, &[(0.5, ShaderVariant::Pbr)], [0.5, 0.25, 0.0]);
+        assert_eq!(lit, [HALF, QUARTER, ONE, ONE]);
+        let unlit = render_center_light(&device, &queue, &[(0.5, ShaderVariant::Unlit)], [0.5, 0.0, 0.0]);
+        assert_eq!(unlit, [0x3E00, 0, 0, ONE]);
+        // Only the visible surface emits.
+        let behind = [(0.2, ShaderVariant::Pbr), (0.8, ShaderVariant::Pbr)];
+        assert_eq!(render_center_light(&device, &queue, &behind, [0.5, 0.0, 0.0]), [HALF, 0, ONE, ONE]);
+    }
+
+    /// Skipped when no adapter is available (e.g. headless CI).
+    #[test]
+    fn back_facing_unlit_quads_are_culled_like_lit_ones() {
+        let Some(adapter) = test_support::adapter("back_facing_unlit_quads_are_culled_like_lit_ones") else {
+            return;
+        };
+        let (device, queue) = test_support::device(&adapter);
+        const ONE: u16 = 0x3C00;
+        let back = MeshDraw { shader_variant: ShaderVariant::Unlit, ..quad(&device, &queue, 0.5, true) };
+        let back_face = Some(wgpu::Face::Back);
+        assert_eq!(render_meshes_center_light(&device, &queue, std::slice::from_ref(&back), back_face), [0, 0, ONE, ONE]);
+        assert_eq!(render_meshes_center_light(&device, &queue, std::slice::from_ref(&back), None), [ONE, 0, 0, ONE]);
+        // Double-sided meshes are never culled, and mirroring flips which side is the back.
+        let double_sided = MeshDraw { double_sided: true, ..back.clone() };
+        assert_eq!(render_meshes_center_light(&device, &queue, &[double_sided], back_face), [ONE, 0, 0, ONE]);
+        let mirrored = MeshDraw { mirrored: true, ..back.clone() };
+        assert_eq!(render_meshes_center_light(&device, &queue, &[mirrored], back_face), [ONE, 0, 0, ONE]);
+        // The emissive pipelines cull the same way.
+        let emissive = MeshDraw { shader_variant: ShaderVariant::Pbr, emissive: [0.5, 0.0, 0.0], ..back };
+        assert_eq!(render_meshes_center_light(&device, &queue, &[emissive], back_face), [0, 0, ONE, ONE]);
+    }
+
+    /// Skipped without an adapter or instanced draws.
+    #[test]
+    fn instanced_unlit_draws_read_each_meshes_data() {
+        let test = "instanced_unlit_draws_read_each_meshes_data";
+        let Some(adapter) = test_support::adapter(test) else {
+            return;
+        };
+        let features = wgpu::Features::INDIRECT_FIRST_INSTANCE;
+        let Some((device, queue)) = test_support::device_with_features(&adapter, features, test) else {
+            return;
+        };
+        if MeshData::new(&device, &Default::default()) == MeshData::PerDraw {
+            eprintln!("{}: no vertex storage or indirect instance_index, skipped", test);
+            return;
+        }
+        const ONE: u16 = 0x3C00;
+        const HALF: u16 = 0x3800;
+        // The unlit quad in front (instance 1) wins the depth test, and only its emissive is added.
+        let quads = [(0.2, ShaderVariant::Pbr), (0.5, ShaderVariant::Unlit)];
+        let meshes: Vec<MeshDraw> = quads
+            .iter()
+            .zip([[0.0, 0.0, 1.0], [0.0, 0.5, 0.0]])
+            .map(|(&(z, shader_variant), emissive)| MeshDraw { shader_variant, emissive, ..quad(&device, &queue, z, false) })
+            .collect();
+        assert_eq!(render_meshes_center_light(&device, &queue, &meshes, None), [ONE, HALF, 0, ONE]);
+    }
+}
@@ -10,6 +10,27 @@ pub trait RenderBackend: Send {
 
     /// Render one frame. Submits work internally; caller does not need to submit command buffers.
     fn render_frame(&mut self, view: &ExtractedView) -> Result<(), String>;
+
+    /// Internal resolution of the last frame, after any render scale; `None` before the first frame or
+    /// when the backend does not report it.
+    fn render_resolution(&self) -> Option<(u32, u32)> {
+        None
+    }
+}
+
+/// Swapchain state of a window backend, for UI layout and debug display.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapchainInfo {
+    /// Surface size in pixels, as last configured.
+    pub size: (u32, u32),
+    /// The backend's name for the surface pixel format, for display.
+    pub format: String,
+    /// The surface takes HDR (floating-point, unclamped) values.
+    pub hdr: bool,
+    /// Presentation waits for vertical blank.
+    pub vsync: bool,
+    /// Most frames queued ahead of presentation.
+    pub max_frame_latency: u32,
 }
 
 /// Extension for backends that can present to a window. Host passes raw handles (e.g. from winit);
@@ -23,4 +44,10 @@ pub trait RenderBackendWindow: RenderBackend + Send {
         raw_window_handle: RawWindowHandle,
         raw_display_handle: RawDisplayHandle,
     ) -> Result<(), String>;
+
+    /// Swapchain of the last presented frame (it follows `view.viewport_size`, so this reflects
+    /// resizes); `None` before the first frame or when the backend does not report it.
+    fn swapchain_info(&self) -> Option<SwapchainInfo> {
+        None
+    }
 }
@@ -0,0 +1,59 @@
+//! sRGB <-> linear conversion for CPU-side color math (e.g. authored light colors).
+//!
+//! Color space contract of the backends, end to end:
+//! 1. Authored colors and color textures (`ExtractedPbrMaterial::base_color`) are sRGB-encoded.
+//!    Lumelite uploads base color as `Rgba8UnormSrgb`, so the sampler decodes it to linear. Data
+//!    textures (normal, metallic/roughness, AO) are uploaded as `Rgba8Unorm` and never decoded.
+//! 2. All shading is linear: light, ambient, fog and emissive colors are linear values, and the light
+//!    buffer holds linear HDR radiance.
+//! 3. Present applies exposure and tone mapping to the linear light buffer and writes linear values.
+//!    Lumelite's default `swapchain_format` is `Rgba8UnormSrgb`, and its window backend presents
+//!    through the sRGB view of an SDR surface, so the GPU encodes to sRGB on store. HDR swapchains
+//!    stay linear.
+//!
+//! Host colors picked in an sRGB color picker go through [`srgb_to_linear_rgb`] before they are used
+//! as light colors.
+
+/// Decodes one sRGB-encoded channel in [0, 1] to linear (IEC 61966-2-1 piecewise curve).
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes one linear channel in [0, 1] to sRGB; inverse of [`srgb_to_linear`].
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// [`srgb_to_linear`] per channel.
+pub fn srgb_to_linear_rgb(rgb: [f32; 3]) -> [f32; 3] {
+    rgb.map(srgb_to_linear)
+}
+
+/// [`linear_to_srgb`] per channel.
+pub fn linear_to_srgb_rgb(rgb: [f32; 3]) -> [f32; 3] {
+    rgb.map(linear_to_srgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mid_gray_round_trips() {
+        // sRGB 0.5 is about 21.4% linear reflectance; linear 0.5 encodes to about 0.735.
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-5);
+        assert!((linear_to_srgb(0.5) - 0.735_357).abs() < 1e-5);
+        for c in [0.0, 0.002, 0.04045, 0.5, 128.0 / 255.0, 1.0] {
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-6, "{}", c);
+        }
+        assert_eq!(srgb_to_linear_rgb([0.0, 1.0, 0.5]), [0.0, 1.0, srgb_to_linear(0.5)]);
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::collections::HashMap;
 
-/// Vertex layout for mesh data. Lumelite only accepts PositionNormalUv.
+/// Vertex layout for mesh data. Lumelite renders PositionNormalUv and converts the other layouts on upload.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum VertexFormat {
     /// Position (12 bytes) + normal (12 bytes) = 24 bytes per vertex.
@@ -11,6 +11,24 @@ pub enum VertexFormat {
     /// Position (12) + normal (12) + uv (8) = 32 bytes per vertex. Default for Lumelite.
     #[default]
     PositionNormalUv,
+    /// Position (12) + normal (12) + tangent with handedness in w (16) + uv (8) = 48 bytes per vertex.
+    /// See [`crate::generate_tangents`] when the source mesh has no tangents.
+    PositionNormalTangentUv,
+    /// Position (12) + normal (12) + linear RGBA color (16) + uv (8) = 48 bytes per vertex. The color
+    /// multiplies the material's base color (e.g. glTF `COLOR_0`, baked vertex lighting).
+    PositionNormalColorUv,
+}
+
+impl VertexFormat {
+    /// Bytes per vertex.
+    pub fn stride(self) -> usize {
+        match self {
+            VertexFormat::PositionNormal => 24,
+            VertexFormat::PositionNormalUv => 32,
+            VertexFormat::PositionNormalTangentUv => 48,
+            VertexFormat::PositionNormalColorUv => 48,
+        }
+    }
 }
 
 /// CPU-side texture data for cross-backend transfer. RGBA8 row-major.
@@ -29,6 +47,43 @@ pub struct ExtractedPbrMaterial {
     /// R = metallic, G = roughness. Single RGBA texture.
     pub metallic_roughness: Option<PbrTextureData>,
     pub ao: Option<PbrTextureData>,
+    /// Render both sides (foliage, thin surfaces): no back-face culling, and back faces are shaded
+    /// with the normal flipped toward the viewer.
+    pub double_sided: bool,
+    /// Linear radiance the surface emits, added to the lit result regardless of lights; zero (the
+    /// default) emits nothing. Needs no texture, so untextured props can glow.
+    pub emissive_factor: [f32; 3],
+}
+
+/// Surface shader of an [`ExtractedMesh`]. Lumelite keeps one GBuffer pipeline set per variant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ShaderVariant {
+    /// Lit by every light, from the PBR material (base color, normal, metallic/roughness, AO).
+    #[default]
+    Pbr,
+    /// Shows the base color as is, unaffected by lights and ambient (signs, markers, debug geometry).
+    Unlit,
+}
+
+impl ShaderVariant {
+    /// Every variant, e.g. to build one pipeline per variant.
+    pub const ALL: [ShaderVariant; 2] = [ShaderVariant::Pbr, ShaderVariant::Unlit];
+}
+
+/// A coarser level of detail of an [`ExtractedMesh`], indexing the same vertex data.
+#[derive(Clone, Debug, Default)]
+pub struct MeshLod {
+    /// Index data (u32 indices) of this level.
+    pub index_data: Vec<u8>,
+    /// Used while the mesh's screen size (bounding sphere diameter over viewport height) is at most this.
+    pub screen_size: f32,
+}
+
+/// Bounding sphere (e.g. world-space bounds of an [`ExtractedMesh`]).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sphere {
+    pub center: [f32; 3],
+    pub radius: f32,
 }
 
 /// Per-mesh instance data extracted from the main world.
@@ -45,10 +100,24 @@ pub struct ExtractedMesh {
     pub transform: [f32; 16],
     /// Whether this instance is visible.
     pub visible: bool,
-    /// Vertex layout. Lumelite only accepts PositionNormalUv.
+    /// Vertex layout of vertex_data.
     pub vertex_format: VertexFormat,
     /// Optional PBR material. When None, Lumelite uses default (flat) material.
     pub material: Option<ExtractedPbrMaterial>,
+    /// Coarser LODs in decreasing `screen_size` order; `index_data` is LOD 0, drawn while the mesh is
+    /// larger on screen than every threshold. Empty (default) for a single LOD.
+    pub lods: Vec<MeshLod>,
+    /// Surface shader; `Pbr` by default. Only Lumelite honors it so far.
+    pub shader_variant: ShaderVariant,
+    /// World-space bounds for culling and LOD selection in place of those computed from `vertex_data`,
+    /// e.g. animation-aware bounds of a skinned mesh whose vertices move every frame. `None` (default)
+    /// computes them.
+    pub bounds_override: Option<Sphere>,
+    /// Host-maintained revision of the geometry (`vertex_data`, `index_data`, `vertex_format`, `lods`).
+    /// 0 (the default) means untracked: backends hash the geometry on every prepare and pick up in-place
+    /// edits. Any other value opts in to skipping that: the host bumps it whenever the geometry changes,
+    /// and backends only re-read an entity's geometry when it moves.
+    pub geometry_revision: u64,
 }
 
 impl Default for ExtractedMesh {
@@ -63,6 +132,10 @@ impl Default for ExtractedMesh {
             visible: true,
             vertex_format: VertexFormat::default(),
             material: None,
+            lods: Vec::new(),
+            shader_variant: ShaderVariant::Pbr,
+            bounds_override: None,
+            geometry_revision: 0,
         }
     }
 }
@@ -80,6 +153,9 @@ pub struct PointLight {
     pub color: [f32; 3],
     pub radius: f32,
     pub falloff_exponent: f32,
+    /// Importance when the backend hands out its shadow map budget; 0 (default) casts no shadow, higher
+    /// values are served first. The directional light always ranks above local lights.
+    pub shadow_priority: u32,
 }
 
 /// Spot light: position, direction (unit vector), color, radius, inner/outer angles (radians).
@@ -91,6 +167,8 @@ pub struct SpotLight {
     pub radius: f32,
     pub inner_angle: f32,
     pub outer_angle: f32,
+    /// See [`PointLight::shadow_priority`].
+    pub shadow_priority: u32,
 }
 
 /// Sky light (simplified): direction, color, intensity.
@@ -101,10 +179,23 @@ pub struct SkyLight {
     pub intensity: f32,
 }
 
+/// Tone mapping operator applied when the light buffer is presented.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapping {
+    #[default]
+    Reinhard,
+    /// No tone mapping (clamp).
+    None,
+}
+
 /// View/camera data for the current frame.
 #[derive(Clone, Debug)]
 pub struct ExtractedView {
     pub view_proj: [f32; 16],
+    /// World-space eye position; light shaders use it for the view vector (specular, Fresnel). The host
+    /// fills it from the same camera as `view_proj`; an orthographic view has its eye at infinity, so
+    /// pass a point behind the near plane along the view axis.
+    pub camera_pos: [f32; 3],
     pub viewport_size: (u32, u32),
     /// Optional: main directional light. If None, Lumelite uses a default.
     /// (direction: unit vector, color: RGB)
@@ -115,6 +206,12 @@ pub struct ExtractedView {
     pub spot_lights: Vec<SpotLight>,
     /// Optional sky light.
     pub sky_light: Option<SkyLight>,
+    /// Tone mapping for this view (e.g. one split-screen player or a minimap); `None` uses the
+    /// backend's configured one.
+    pub tone_mapping: Option<ToneMapping>,
+    /// Linear multiplier on scene color before tone mapping for this view; `None` uses the backend's
+    /// configured exposure.
+    pub exposure: Option<f32>,
 }
 
 impl Default for ExtractedView {
@@ -123,11 +220,14 @@ impl Default for ExtractedView {
             view_proj: [
                 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
             ],
+            camera_pos: [0.0, 0.0, 0.0],
             viewport_size: (800, 600),
             directional_light: None,
             point_lights: Vec::new(),
             spot_lights: Vec::new(),
             sky_light: None,
+            tone_mapping: None,
+            exposure: None,
         }
     }
 }
@@ -0,0 +1,74 @@
+//! Graphviz (DOT) dump of a render graph, shared by the Lume and Lumelite graphs so both debug views
+//! read the same way (render with e.g. `dot -Tpng graph.dot -o graph.png`).
+//!
+//! Nodes are boxes (`nN`), resources ellipses (`rN`, labeled with their kind). Resource edges are
+//! color-coded: blue = read (resource -> node), red = write (node -> resource), purple = read-write
+//! (both directions). Dependency edges are bold; orange ones also record a memory barrier.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// How a node uses a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DotAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Collects nodes, resources and edges, then writes them out with [`Self::finish`].
+#[derive(Debug, Default)]
+pub struct GraphDot {
+    node_count: usize,
+    resources: BTreeMap<usize, &'static str>,
+    accesses: Vec<String>,
+    edges: Vec<String>,
+}
+
+impl GraphDot {
+    /// Graph with nodes `0..node_count`.
+    pub fn new(node_count: usize) -> Self {
+        Self { node_count, ..Default::default() }
+    }
+
+    /// Registered resource with its kind (e.g. `"buffer"`). Resources that only appear in
+    /// [`Self::access`] are listed as `"unregistered"`.
+    pub fn resource(&mut self, resource: usize, kind: &'static str) {
+        self.resources.insert(resource, kind);
+    }
+
+    /// Resource edge of `node`, with an optional edge label (e.g. the layout a barrier hint asks for).
+    pub fn access(&mut self, node: usize, resource: usize, access: DotAccess, label: Option<&str>) {
+        self.resources.entry(resource).or_insert("unregistered");
+        let label = label.map(|l| format!(", label=\"{}\"", l)).unwrap_or_default();
+        self.accesses.push(match access {
+            DotAccess::Read => format!("r{} -> n{} [color=blue{}];", resource, node, label),
+            DotAccess::Write => format!("n{} -> r{} [color=red{}];", node, resource, label),
+            DotAccess::ReadWrite => format!("n{} -> r{} [color=purple, dir=both{}];", node, resource, label),
+        });
+    }
+
+    /// Dependency edge; `memory_barrier` marks edges that also record a full memory barrier.
+    pub fn edge(&mut self, before: usize, after: usize, memory_barrier: bool) {
+        self.edges.push(if memory_barrier {
+            format!("n{} -> n{} [style=bold, color=orange, label=\"memory barrier\"];", before, after)
+        } else {
+            format!("n{} -> n{} [style=bold];", before, after)
+        });
+    }
+
+    pub fn finish(self) -> String {
+        let mut out = String::from("digraph render_graph {\n    rankdir=LR;\n");
+        for i in 0..self.node_count {
+            let _ = writeln!(out, "    n{} [shape=box, label=\"node {}\"];", i, i);
+        }
+        for (r, kind) in &self.resources {
+            let _ = writeln!(out, "    r{} [shape=ellipse, label=\"resource {}\\n{}\"];", r, r, kind);
+        }
+        for line in self.accesses.iter().chain(&self.edges) {
+            let _ = writeln!(out, "    {}", line);
+        }
+        out.push_str("}\n");
+        out
+    }
+}
@@ -4,10 +4,20 @@
 
 mod extract;
 mod backend;
+mod color;
+mod graph_dot;
+mod logging;
+mod math;
+mod tangents;
 
 pub use extract::{
-    ExtractedMesh, ExtractedMeshes, ExtractedPbrMaterial, ExtractedView, PbrTextureData, PointLight,
-    SpotLight, SkyLight, VertexFormat,
+    ExtractedMesh, ExtractedMeshes, ExtractedPbrMaterial, ExtractedView, MeshLod, PbrTextureData, PointLight,
+    ShaderVariant, Sphere, SpotLight, SkyLight, ToneMapping, VertexFormat,
 };
-pub use backend::{RenderBackend, RenderBackendWindow};
+pub use backend::{RenderBackend, RenderBackendWindow, SwapchainInfo};
+pub use color::{linear_to_srgb, linear_to_srgb_rgb, srgb_to_linear, srgb_to_linear_rgb};
+pub use graph_dot::{DotAccess, GraphDot};
+pub use logging::{log, log_enabled, set_logger, LogLevel, LogRecord, Logger, StderrLogger};
+pub use math::{mat4_inverse, mat4_mul};
+pub use tangents::generate_tangents;
 pub use raw_window_handle::{RawDisplayHandle, RawWindowHandle};
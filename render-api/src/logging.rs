@@ -0,0 +1,165 @@
+//! Diagnostics hook shared by the backends: warnings, fallback decisions, resource recreations and
+//! pass timings go through [`log`] instead of printing, so a host can route them into its own logging
+//! system with [`set_logger`]. Without a logger, warnings and errors are printed to stderr as before
+//! and everything more verbose is dropped.
+//!
+//! Targets name the emitting component (`"lumelite"`, `"lume::graph"`, ...) for filtering.
+
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Severity of a diagnostic, most severe first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    /// Resource (re)creation and other one-off state changes.
+    Info,
+    /// Per-frame detail such as pass timings.
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        })
+    }
+}
+
+/// One diagnostic; the message is formatted lazily by the logger.
+#[derive(Clone, Copy, Debug)]
+pub struct LogRecord<'a> {
+    pub level: LogLevel,
+    pub target: &'a str,
+    pub message: fmt::Arguments<'a>,
+}
+
+/// Receiver for renderer diagnostics. Called from whichever thread emits them (render thread, upload
+/// workers), so implementations must be cheap and thread-safe.
+pub trait Logger: Send + Sync {
+    /// Whether records of `level` from `target` are wanted; emitters skip costly work (e.g. timing
+    /// queries) when this is false.
+    fn enabled(&self, level: LogLevel, target: &str) -> bool {
+        let _ = (level, target);
+        true
+    }
+
+    fn log(&self, record: &LogRecord);
+}
+
+/// The default when no logger is set: `target: message` on stderr for `max_level` and above.
+#[derive(Clone, Copy, Debug)]
+pub struct StderrLogger {
+    pub max_level: LogLevel,
+}
+
+impl Default for StderrLogger {
+    fn default() -> Self {
+        Self { max_level: LogLevel::Warn }
+    }
+}
+
+impl Logger for StderrLogger {
+    fn enabled(&self, level: LogLevel, _target: &str) -> bool {
+        level <= self.max_level
+    }
+
+    fn log(&self, record: &LogRecord) {
+        eprintln!("{}: {}", record.target, record.message);
+    }
+}
+
+static LOGGER: RwLock<Option<Arc<dyn Logger>>> = RwLock::new(None);
+
+/// Install the process-wide logger for both backends; `None` restores the stderr default.
+pub fn set_logger(logger: Option<Box<dyn Logger>>) {
+    *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = logger.map(Arc::from);
+}
+
+/// The installed logger, cloned out so the lock is released before it runs: a logger may itself call
+/// [`log`] or [`set_logger`].
+fn current_logger() -> Option<Arc<dyn Logger>> {
+    LOGGER.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Whether a record at `level` for `target` would be delivered.
+pub fn log_enabled(level: LogLevel, target: &str) -> bool {
+    match current_logger() {
+        Some(logger) => logger.enabled(level, target),
+        None => StderrLogger::default().enabled(level, target),
+    }
+}
+
+/// Emit a diagnostic, e.g. `log(LogLevel::Warn, "lumelite", format_args!("{} downscaled", label))`.
+pub fn log(level: LogLevel, target: &str, message: fmt::Arguments) {
+    let record = LogRecord { level, target, message };
+    let installed = current_logger();
+    let default = StderrLogger::default();
+    let logger: &dyn Logger = installed.as_deref().unwrap_or(&default);
+    if logger.enabled(level, target) {
+        logger.log(&record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// The logger is process-wide; tests that install one run one at a time.
+    static INSTALLED: Mutex<()> = Mutex::new(());
+
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Logger for Capture {
+        fn enabled(&self, level: LogLevel, _target: &str) -> bool {
+            level <= LogLevel::Info
+        }
+
+        fn log(&self, record: &LogRecord) {
+            self.0.lock().unwrap().push(format!("{} {}: {}", record.level, record.target, record.message));
+        }
+    }
+
+    #[test]
+    fn installed_logger_receives_enabled_records() {
+        let _installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        set_logger(Some(Box::new(Capture(Arc::clone(&lines)))));
+        log(LogLevel::Warn, "lumelite", format_args!("texture {} downscaled", 3));
+        log(LogLevel::Debug, "lumelite", format_args!("dropped"));
+        assert!(log_enabled(LogLevel::Info, "lume") && !log_enabled(LogLevel::Trace, "lume"));
+        set_logger(None);
+        assert!(!log_enabled(LogLevel::Info, "lume"));
+        assert_eq!(*lines.lock().unwrap(), vec!["warn lumelite: texture 3 downscaled".to_string()]);
+    }
+
+    /// Forwards every record to `target: "inner"` once, as a logger wrapping renderer code might.
+    struct Reentrant(Capture);
+
+    impl Logger for Reentrant {
+        fn log(&self, record: &LogRecord) {
+            self.0.log(record);
+            if record.target != "inner" {
+                log(record.level, "inner", format_args!("{}", record.message));
+                set_logger(None);
+            }
+        }
+    }
+
+    #[test]
+    fn logger_may_log_and_replace_itself() {
+        let _installed = INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        set_logger(Some(Box::new(Reentrant(Capture(Arc::clone(&lines))))));
+        log(LogLevel::Warn, "lume", format_args!("outer"));
+        assert_eq!(*lines.lock().unwrap(), vec!["warn lume: outer".to_string(), "warn inner: outer".to_string()]);
+        assert!(!log_enabled(LogLevel::Info, "lume"));
+    }
+}
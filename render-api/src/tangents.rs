@@ -0,0 +1,116 @@
+//! Tangent generation for normal mapping when the host mesh has no tangents.
+
+fn sub3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize3(v: [f32; 3]) -> Option<[f32; 3]> {
+    let len = dot3(v, v).sqrt();
+    if len > 1e-8 {
+        Some([v[0] / len, v[1] / len, v[2] / len])
+    } else {
+        None
+    }
+}
+
+/// Any unit vector perpendicular to `n`; used when UVs are degenerate.
+fn perpendicular(n: [f32; 3]) -> [f32; 3] {
+    let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    normalize3(cross3(axis, n)).unwrap_or([1.0, 0.0, 0.0])
+}
+
+/// Per-vertex tangents (Lengyel's method). Returns `[tx, ty, tz, w]` per vertex where `w` is the
+/// bitangent sign (+1 or -1): `bitangent = w * cross(normal, tangent)`.
+///
+/// `positions`, `normals` and `uvs` are per-vertex and must have the same length; `indices` is a
+/// triangle list. Triangles with out-of-range indices or degenerate UVs are skipped; vertices left
+/// without a tangent get an arbitrary unit vector perpendicular to their normal.
+pub fn generate_tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[u32],
+) -> Vec<[f32; 4]> {
+    let n = positions.len().min(normals.len()).min(uvs.len());
+    let mut tan1 = vec![[0.0f32; 3]; n];
+    let mut tan2 = vec![[0.0f32; 3]; n];
+    for tri in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+        if i0 >= n || i1 >= n || i2 >= n {
+            continue;
+        }
+        let e1 = sub3(positions[i1], positions[i0]);
+        let e2 = sub3(positions[i2], positions[i0]);
+        let (s1, t1) = (uvs[i1][0] - uvs[i0][0], uvs[i1][1] - uvs[i0][1]);
+        let (s2, t2) = (uvs[i2][0] - uvs[i0][0], uvs[i2][1] - uvs[i0][1]);
+        let det = s1 * t2 - s2 * t1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let r = 1.0 / det;
+        let sdir = [
+            (t2 * e1[0] - t1 * e2[0]) * r,
+            (t2 * e1[1] - t1 * e2[1]) * r,
+            (t2 * e1[2] - t1 * e2[2]) * r,
+        ];
+        let tdir = [
+            (s1 * e2[0] - s2 * e1[0]) * r,
+            (s1 * e2[1] - s2 * e1[1]) * r,
+            (s1 * e2[2] - s2 * e1[2]) * r,
+        ];
+        for &i in &[i0, i1, i2] {
+            for k in 0..3 {
+                tan1[i][k] += sdir[k];
+                tan2[i][k] += tdir[k];
+            }
+        }
+    }
+    (0..n)
+        .map(|i| {
+            let nrm = normals[i];
+            // Gram-Schmidt orthogonalize against the normal.
+            let d = dot3(nrm, tan1[i]);
+            let t = [
+                tan1[i][0] - nrm[0] * d,
+                tan1[i][1] - nrm[1] * d,
+                tan1[i][2] - nrm[2] * d,
+            ];
+            let t = normalize3(t).unwrap_or_else(|| perpendicular(nrm));
+            let w = if dot3(cross3(nrm, t), tan2[i]) < 0.0 { -1.0 } else { 1.0 };
+            [t[0], t[1], t[2], w]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_tangents_follow_u() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+        let normals = [[0.0, 0.0, 1.0]; 4];
+        let uvs = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let indices = [0u32, 1, 2, 0, 2, 3];
+        let tangents = generate_tangents(&positions, &normals, &uvs, &indices);
+        assert_eq!(tangents.len(), 4);
+        for t in tangents {
+            let len = (t[0] * t[0] + t[1] * t[1] + t[2] * t[2]).sqrt();
+            assert!((len - 1.0).abs() < 1e-5);
+            assert!((t[0] - 1.0).abs() < 1e-5 && t[1].abs() < 1e-5 && t[2].abs() < 1e-5);
+            assert_eq!(t[3], 1.0);
+        }
+    }
+}